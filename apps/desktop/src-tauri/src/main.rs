@@ -66,7 +66,7 @@ impl ShutdownManager {
 #[tokio::main]
 async fn main() {
 	dotenv().ok();
-	env_logger::init();
+	sdcore::init_logger();
 
 	let data_dir = path::data_dir()
 		.unwrap_or_else(|| PathBuf::from("./"))
@@ -1,26 +1,51 @@
-use sdcore::{ClientCommand, ClientQuery, CoreEvent, CoreResponse, Node, NodeController};
+use sdcore::{
+	api::{PreviewError, ShareError},
+	stream_archive_entry, stream_share_bundle, stream_tar, ClientCommand, ClientQuery,
+	CoreError, CoreEvent, CoreResponse, LibraryCommand, LibraryQuery, Node,
+	NodeController,
+};
 use std::{
 	collections::HashSet,
 	env,
-	path::Path,
+	net::ToSocketAddrs,
+	path::{Component, Path, PathBuf},
 	sync::{Arc, RwLock},
-	time::{Duration, Instant},
+	time::{Duration, Instant, UNIX_EPOCH},
 };
 
 use actix::{
 	Actor, ActorContext, Addr, AsyncContext, Context, ContextFutureSpawner, Handler,
 	Message, StreamHandler, WrapFuture,
 };
+use actix_files::NamedFile;
 use actix_web::{
-	get, http::StatusCode, web, App, Error, HttpRequest, HttpResponse, HttpServer,
-	Responder,
+	dev::{Service, ServiceRequest},
+	error::ErrorUnauthorized,
+	get,
+	http::{
+		header::{self, ETag, EntityTag, Header, HeaderValue, IfNoneMatch},
+		StatusCode,
+	},
+	post, web, App, Error, HttpRequest, HttpResponse, HttpServer, Responder,
 };
 use actix_web_actors::ws;
+use futures::future::{self, Either};
+use ring::constant_time;
 use serde::{Deserialize, Serialize};
 
 use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
 
 const DATA_DIR_ENV_VAR: &str = "DATA_DIR";
+// the address to listen on, eg: a LAN address to keep the server off other networks
+const BIND_ADDRESS_ENV_VAR: &str = "BIND_ADDRESS";
+const DEFAULT_BIND_ADDRESS: &str = "0.0.0.0:8080";
+// without tokens anyone reaching the server could use it, so it only listens locally then
+const LOCAL_BIND_ADDRESS: &str = "127.0.0.1:8080";
+// comma separated tokens, one of which every request must carry once any is set
+const AUTH_TOKENS_ENV_VAR: &str = "AUTH_TOKENS";
+// serves /metrics for Prometheus when set to "true"
+const METRICS_ENV_VAR: &str = "METRICS";
 
 #[derive(Serialize)]
 pub struct Event(CoreEvent);
@@ -245,32 +270,507 @@ async fn ws_handler(
 	)
 }
 
+#[derive(Deserialize)]
+struct ArchiveParams {
+	// a directory within the location, its root if unset
+	path: Option<String>,
+}
+
+// streams a directory of a location as a tar archive, built while it is downloaded
+#[get("/library/{library_id}/location/{location_id}/archive")]
+async fn archive_handler(
+	ids: web::Path<(Uuid, i32)>,
+	params: web::Query<ArchiveParams>,
+	controller: web::Data<NodeController>,
+) -> HttpResponse {
+	let (library_id, location_id) = ids.into_inner();
+	let path = Path::new(params.path.as_deref().unwrap_or_default());
+	// the archive can't reach outside of the location
+	if path
+		.components()
+		.any(|component| !matches!(component, Component::Normal(_)))
+	{
+		return HttpResponse::BadRequest().body("Invalid path");
+	}
+
+	let location = match controller
+		.query(ClientQuery::LibraryQuery {
+			library_id,
+			query: LibraryQuery::GetLocation { id: location_id },
+		})
+		.await
+	{
+		Ok(CoreResponse::GetLocation(location)) => location,
+		_ => return HttpResponse::NotFound().body("Location not found"),
+	};
+	let dir = match location.path {
+		Some(location_path) => location_path.join(path),
+		None => return HttpResponse::NotFound().body("Location not available"),
+	};
+	if !dir.is_dir() {
+		return HttpResponse::NotFound().body("Directory not found");
+	}
+
+	let name = dir
+		.file_name()
+		.map(|name| name.to_string_lossy().replace('"', ""))
+		.unwrap_or_else(|| "archive".to_string());
+	let chunks = futures::stream::unfold(stream_tar(dir), |mut rx| async move {
+		rx.recv()
+			.await
+			.map(|chunk| (chunk.map(web::Bytes::from), rx))
+	});
+
+	HttpResponse::Ok()
+		.content_type("application/x-tar")
+		.insert_header((
+			"Content-Disposition",
+			format!("attachment; filename=\"{}.tar\"", name),
+		))
+		.streaming(chunks)
+}
+
+#[derive(Deserialize)]
+struct ArchiveEntryParams {
+	// the path of the entry within the archive
+	path: String,
+}
+
+// streams a single entry of an archive, so it can be previewed without extracting the archive
+#[get("/library/{library_id}/file/{file_path_id}/archive-entry")]
+async fn archive_entry_handler(
+	ids: web::Path<(Uuid, i32)>,
+	params: web::Query<ArchiveEntryParams>,
+	controller: web::Data<NodeController>,
+) -> HttpResponse {
+	let (library_id, file_path_id) = ids.into_inner();
+	let path = params.path.trim_matches('/');
+	let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+
+	// the entry is looked up within its directory, which also gives its size
+	let contents = match controller
+		.query(ClientQuery::LibraryQuery {
+			library_id,
+			query: LibraryQuery::GetArchiveContents {
+				id: file_path_id,
+				path: parent.to_string(),
+			},
+		})
+		.await
+	{
+		Ok(CoreResponse::GetArchiveContents(contents)) => contents,
+		_ => return HttpResponse::NotFound().body("Archive not found"),
+	};
+	let entry = match contents
+		.entries
+		.into_iter()
+		.find(|entry| entry.name == name && !entry.is_dir)
+	{
+		Some(entry) => entry,
+		None => return HttpResponse::NotFound().body("Entry not found"),
+	};
+
+	let location = match controller
+		.query(ClientQuery::LibraryQuery {
+			library_id,
+			query: LibraryQuery::GetLocation {
+				id: contents.archive.location_id,
+			},
+		})
+		.await
+	{
+		Ok(CoreResponse::GetLocation(location)) => location,
+		_ => return HttpResponse::NotFound().body("Location not found"),
+	};
+	let source = match location.path {
+		Some(location_path) => location_path.join(&contents.archive.materialized_path),
+		None => return HttpResponse::NotFound().body("Location not available"),
+	};
+
+	let chunks = futures::stream::unfold(
+		stream_archive_entry(source, entry.path),
+		|mut rx| async move {
+			rx.recv()
+				.await
+				.map(|chunk| (chunk.map(web::Bytes::from), rx))
+		},
+	);
+
+	HttpResponse::Ok()
+		.content_type("application/octet-stream")
+		.insert_header(("Content-Length", entry.size_in_bytes.to_string()))
+		.insert_header((
+			"Content-Disposition",
+			format!("inline; filename=\"{}\"", entry.name.replace('"', "")),
+		))
+		.streaming(chunks)
+}
+
+// the websocket queries and commands as plain requests, for clients that can't hold a socket
+#[post("/api/query")]
+async fn query_handler(
+	query: web::Json<ClientQuery>,
+	controller: web::Data<NodeController>,
+) -> HttpResponse {
+	json_response(controller.query(query.into_inner()).await)
+}
+
+#[post("/api/command")]
+async fn command_handler(
+	command: web::Json<ClientCommand>,
+	controller: web::Data<NodeController>,
+) -> HttpResponse {
+	json_response(controller.command(command.into_inner()).await)
+}
+
+fn json_response(result: Result<CoreResponse, CoreError>) -> HttpResponse {
+	match result {
+		Ok(response) => HttpResponse::Ok().json(response),
+		Err(err) => HttpResponse::BadRequest()
+			.json(serde_json::json!({ "error": err.to_string() })),
+	}
+}
+
+// serves the content of a file, range requests included so media can be streamed
+#[get("/library/{library_id}/file/{file_path_id}/content")]
+async fn file_handler(
+	req: HttpRequest,
+	ids: web::Path<(Uuid, i32)>,
+	controller: web::Data<NodeController>,
+) -> HttpResponse {
+	let (library_id, file_path_id) = ids.into_inner();
+
+	let file_path = match controller
+		.query(ClientQuery::LibraryQuery {
+			library_id,
+			query: LibraryQuery::GetFilePath { id: file_path_id },
+		})
+		.await
+	{
+		Ok(CoreResponse::GetFilePath(file_path)) if !file_path.is_dir => file_path,
+		_ => return HttpResponse::NotFound().body("File not found"),
+	};
+
+	let location = match controller
+		.query(ClientQuery::LibraryQuery {
+			library_id,
+			query: LibraryQuery::GetLocation {
+				id: file_path.location_id,
+			},
+		})
+		.await
+	{
+		Ok(CoreResponse::GetLocation(location)) => location,
+		_ => return HttpResponse::NotFound().body("Location not found"),
+	};
+	let path = match location.path {
+		Some(location_path) => location_path.join(&file_path.materialized_path),
+		None => return HttpResponse::NotFound().body("Location not available"),
+	};
+
+	let file = match NamedFile::open_async(path).await {
+		Ok(file) => file,
+		Err(_) => return HttpResponse::NotFound().body("File not found"),
+	};
+
+	// the contents are identified by their cas id, the modification time covers the files
+	// changed since they were identified. Weak, as the cas id of large files is sampled.
+	let etag = file_path.file.as_ref().map(|content| {
+		let modified = file
+			.modified()
+			.and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+			.map_or(0, |modified| modified.as_secs());
+		EntityTag::new_weak(format!("{}-{}", content.cas_id, modified))
+	});
+	let etag = match etag {
+		Some(etag) => etag,
+		// not identified yet, actix-files tags it after its inode and modification time
+		None => return file.into_response(&req),
+	};
+
+	let not_modified = match IfNoneMatch::parse(&req) {
+		Ok(IfNoneMatch::Any) => true,
+		Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+		Err(_) => false,
+	};
+	if not_modified {
+		return HttpResponse::NotModified()
+			.insert_header(ETag(etag))
+			.finish();
+	}
+
+	// actix-files answers byte ranges and If-Modified-Since, streaming the file in chunks
+	// as the client reads them so seeking only reads what is played
+	let mut response = file.use_etag(false).into_response(&req);
+	if let Ok(value) = HeaderValue::from_str(&etag.to_string()) {
+		response.headers_mut().insert(header::ETAG, value);
+	}
+	response
+}
+
+// how long the browser is told to wait before asking again for a preview being written
+const PREVIEW_RETRY_AFTER_SECS: u64 = 2;
+
+// serves a version of a video the browser plays, remuxed or transcoded in the background on the
+// first request, which is answered to retry later until it is written
+#[get("/library/{library_id}/file/{file_path_id}/preview")]
+async fn preview_handler(
+	req: HttpRequest,
+	ids: web::Path<(Uuid, i32)>,
+	controller: web::Data<NodeController>,
+) -> HttpResponse {
+	let (library_id, file_path_id) = ids.into_inner();
+
+	let path = match controller
+		.query(ClientQuery::LibraryQuery {
+			library_id,
+			query: LibraryQuery::GetVideoPreview { file_path_id },
+		})
+		.await
+	{
+		Ok(CoreResponse::GetVideoPreview(Some(path))) => path,
+		Ok(CoreResponse::GetVideoPreview(None)) => {
+			return HttpResponse::Accepted()
+				.insert_header((
+					header::RETRY_AFTER,
+					PREVIEW_RETRY_AFTER_SECS.to_string(),
+				))
+				.finish()
+		},
+		Ok(_) => return HttpResponse::InternalServerError().finish(),
+		Err(CoreError::Preview(PreviewError::FilePathNotFound(_))) => {
+			return HttpResponse::NotFound().body("File not found")
+		},
+		Err(err) => {
+			return HttpResponse::BadRequest()
+				.json(serde_json::json!({ "error": err.to_string() }))
+		},
+	};
+
+	match NamedFile::open_async(path).await {
+		Ok(file) => file.into_response(&req),
+		Err(_) => HttpResponse::NotFound().body("Preview not found"),
+	}
+}
+
+// the password of a share is sent in a header, so it stays out of urls and access logs
+const SHARE_PASSWORD_HEADER: &str = "X-Share-Password";
+
+// serves the contents of a share, decrypted from its bundle. Links are public, the share itself
+// is checked instead of a token
+#[get("/share/{library_id}/{token}")]
+async fn share_handler(
+	req: HttpRequest,
+	ids: web::Path<(Uuid, String)>,
+	controller: web::Data<NodeController>,
+) -> HttpResponse {
+	let (library_id, token) = ids.into_inner();
+	let password = req
+		.headers()
+		.get(SHARE_PASSWORD_HEADER)
+		.and_then(|value| value.to_str().ok())
+		.map(str::to_string);
+
+	let bundle = match controller
+		.command(ClientCommand::LibraryCommand {
+			library_id,
+			command: LibraryCommand::ShareOpen { token, password },
+		})
+		.await
+	{
+		Ok(CoreResponse::ShareOpenResponse(bundle)) => bundle,
+		Err(CoreError::Share(ShareError::Expired)) => {
+			return HttpResponse::Gone().body("Share expired")
+		},
+		Err(CoreError::Share(
+			err @ (ShareError::PasswordRequired | ShareError::WrongPassword),
+		)) => return HttpResponse::Unauthorized().body(err.to_string()),
+		_ => return HttpResponse::NotFound().body("Share not found"),
+	};
+
+	let name = bundle.name.replace('"', "");
+	let (content_type, file_name) = match bundle.is_dir {
+		true => ("application/x-tar", format!("{}.tar", name)),
+		false => ("application/octet-stream", name),
+	};
+	let chunks = futures::stream::unfold(
+		stream_share_bundle(bundle.path, bundle.key),
+		|mut rx| async move {
+			rx.recv()
+				.await
+				.map(|chunk| (chunk.map(web::Bytes::from), rx))
+		},
+	);
+
+	HttpResponse::Ok()
+		.content_type(content_type)
+		.insert_header((
+			"Content-Disposition",
+			format!("attachment; filename=\"{}\"", file_name),
+		))
+		.streaming(chunks)
+}
+
+#[get("/metrics")]
+async fn metrics_handler(controller: web::Data<NodeController>) -> HttpResponse {
+	match controller.query(ClientQuery::GetMetrics).await {
+		Ok(CoreResponse::GetMetrics(metrics)) => HttpResponse::Ok()
+			.content_type("application/openmetrics-text; version=1.0.0; charset=utf-8")
+			.body(metrics.to_openmetrics()),
+		_ => HttpResponse::InternalServerError().body("Failed to read metrics"),
+	}
+}
+
+struct ThumbnailsDir(PathBuf);
+
+#[get("/thumbnail/{location_id}/{cas_id}")]
+async fn thumbnail_handler(
+	req: HttpRequest,
+	ids: web::Path<(i32, String)>,
+	thumbnails_dir: web::Data<ThumbnailsDir>,
+) -> HttpResponse {
+	let (location_id, cas_id) = ids.into_inner();
+	if !cas_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+		return HttpResponse::BadRequest().body("Invalid cas id");
+	}
+
+	let path = thumbnails_dir
+		.0
+		.join(location_id.to_string())
+		.join(cas_id)
+		.with_extension("webp");
+	match NamedFile::open_async(path).await {
+		Ok(file) => file.into_response(&req),
+		Err(_) => HttpResponse::NotFound().body("Thumbnail not found"),
+	}
+}
+
+#[derive(Deserialize)]
+struct TokenParams {
+	token: Option<String>,
+}
+
+// the token is read from the query too, as browsers can't set headers on websockets or media
+fn is_authorized(req: &ServiceRequest, tokens: &[String]) -> bool {
+	if tokens.is_empty()
+		|| matches!(req.path(), "/" | "/health")
+		|| req.path().starts_with("/share/")
+	{
+		return true;
+	}
+
+	req.headers()
+		.get("Authorization")
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+		.map(str::to_string)
+		.or_else(|| {
+			web::Query::<TokenParams>::from_query(req.query_string())
+				.ok()
+				.and_then(|params| params.into_inner().token)
+		})
+		.map_or(false, |token| {
+			// every token is compared, in constant time, so the time taken doesn't tell how much
+			// of one was guessed nor which one
+			tokens
+				.iter()
+				.filter(|valid| {
+					constant_time::verify_slices_are_equal(
+						valid.as_bytes(),
+						token.as_bytes(),
+					)
+					.is_ok()
+				})
+				.count() > 0
+		})
+}
+
+// whether only this machine can reach an address, which a server without tokens is limited to
+fn is_local(address: &str) -> bool {
+	address.to_socket_addrs().map_or(false, |mut addresses| {
+		addresses.all(|address| address.ip().is_loopback())
+	})
+}
+
 async fn not_found() -> impl Responder {
 	HttpResponse::build(StatusCode::OK).body("We're past the event horizon...")
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-	let (event_receiver, controller) = setup().await;
+	sdcore::init_logger();
+	let (event_receiver, controller, data_dir) = setup().await;
 
 	let server = web::Data::new(EventServer::listen(event_receiver));
+	let thumbnails_dir = web::Data::new(ThumbnailsDir(data_dir.join("thumbnails")));
+
+	let tokens: Arc<Vec<String>> = Arc::new(
+		env::var(AUTH_TOKENS_ENV_VAR)
+			.unwrap_or_default()
+			.split(',')
+			.map(str::trim)
+			.filter(|token| !token.is_empty())
+			.map(str::to_string)
+			.collect(),
+	);
+	let metrics = env::var(METRICS_ENV_VAR).map_or(false, |value| value == "true");
+	let bind_address = match env::var(BIND_ADDRESS_ENV_VAR) {
+		Ok(bind_address) => bind_address,
+		Err(_) if tokens.is_empty() => LOCAL_BIND_ADDRESS.to_string(),
+		Err(_) => DEFAULT_BIND_ADDRESS.to_string(),
+	};
+	if tokens.is_empty() && !is_local(&bind_address) {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidInput,
+			format!(
+				"Refusing to listen on {} without {} set, anyone reaching it could use the node",
+				bind_address, AUTH_TOKENS_ENV_VAR
+			),
+		));
+	}
 
-	println!("Listening http://localhost:8080");
+	println!("Listening http://{}", bind_address);
 	HttpServer::new(move || {
+		let tokens = tokens.clone();
 		App::new()
 			.app_data(controller.clone())
 			.app_data(server.clone())
+			.app_data(thumbnails_dir.clone())
+			.wrap_fn(move |req, srv| {
+				if is_authorized(&req, &tokens) {
+					Either::Left(srv.call(req))
+				} else {
+					Either::Right(future::err(ErrorUnauthorized("Invalid token")))
+				}
+			})
 			.service(index)
 			.service(healthcheck)
 			.service(ws_handler)
+			.service(query_handler)
+			.service(command_handler)
+			.service(file_handler)
+			.service(preview_handler)
+			.service(thumbnail_handler)
+			.service(archive_handler)
+			.service(archive_entry_handler)
+			.service(share_handler)
+			.configure(|cfg| {
+				if metrics {
+					cfg.service(metrics_handler);
+				}
+			})
 			.default_service(web::route().to(not_found))
 	})
-	.bind(("0.0.0.0", 8080))?
+	.bind(bind_address)?
 	.run()
 	.await
 }
 
-async fn setup() -> (mpsc::Receiver<CoreEvent>, web::Data<NodeController>) {
+async fn setup() -> (
+	mpsc::Receiver<CoreEvent>,
+	web::Data<NodeController>,
+	PathBuf,
+) {
 	let data_dir_path = match env::var(DATA_DIR_ENV_VAR) {
 		Ok(path) => Path::new(&path).to_path_buf(),
 		Err(_e) => {
@@ -288,9 +788,9 @@ async fn setup() -> (mpsc::Receiver<CoreEvent>, web::Data<NodeController>) {
 	};
 
 	let (controller, event_receiver, node, _shutdown_completion_rx) =
-		Node::new(data_dir_path).await;
+		Node::new(&data_dir_path).await;
 	let (_shutdown_tx, shutdown_rx) = oneshot::channel();
 	tokio::spawn(node.start(shutdown_rx));
 
-	(event_receiver, web::Data::new(controller))
+	(event_receiver, web::Data::new(controller), data_dir_path)
 }
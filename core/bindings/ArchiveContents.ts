@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ArchiveEntry } from "./ArchiveEntry";
+import type { FilePath } from "./FilePath";
+
+export interface ArchiveContents { archive: FilePath, entries: Array<ArchiveEntry>, }
\ No newline at end of file
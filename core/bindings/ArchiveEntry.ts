@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ArchiveEntry { path: string, name: string, is_dir: boolean, size_in_bytes: bigint, date_modified: string | null, }
\ No newline at end of file
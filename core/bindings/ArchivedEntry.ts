@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ArchivedEntry { path: string, archived_path: string, cas_id: string, size_in_bytes: bigint, date_archived: string, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AttributeKind } from "./AttributeKind";
+
+export interface AttributeLoss { source_path: string, target_path: string, kind: AttributeKind, name: string | null, error: string, }
\ No newline at end of file
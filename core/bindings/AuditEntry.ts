@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AuditOperation } from "./AuditOperation";
+import type { AuditedJob } from "./AuditedJob";
+import type { LibraryCommand } from "./LibraryCommand";
+
+export interface AuditEntry { id: number, node_id: number, operation: AuditOperation, command: LibraryCommand | null, job: AuditedJob | null, date_created: string, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AuditOperation } from "./AuditOperation";
+
+export interface AuditFilter { node_id: number | null, from: string | null, to: string | null, operations: Array<AuditOperation>, }
\ No newline at end of file
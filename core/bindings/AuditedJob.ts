@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type AuditedJob = { key: "RetentionExpiry", data: { policy_id: number, location_id: number | null, materialized_path: string, } } | { key: "TrashPurge", data: { location_id: number, original_path: string, } };
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ArchivedEntry } from "./ArchivedEntry";
+import type { ManifestEntry } from "./ManifestEntry";
+
+export interface BackupManifest { plan_id: string, library_id: string, date_created: string, entries: Array<ManifestEntry>, archived: Array<ArchivedEntry>, checksum: string, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupTarget } from "./BackupTarget";
+import type { DeletionPolicy } from "./DeletionPolicy";
+
+export interface BackupPlan { id: number, pub_id: string, name: string, location_id: number, target: BackupTarget, deletion_policy: DeletionPolicy, interval_hours: number | null, last_run_at: string | null, last_error: string | null, date_created: string, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ShareTarget } from "./ShareTarget";
+
+export type BackupTarget = { key: "Volume", data: { path: string, } } | { key: "Remote", data: { target: ShareTarget, } };
\ No newline at end of file
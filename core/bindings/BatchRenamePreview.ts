@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RenamedPath } from "./RenamedPath";
+
+export interface BatchRenamePreview { renames: Array<RenamedPath>, collisions: Array<number>, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BulkTagAction } from "./BulkTagAction";
+
+export interface BulkTagPreview { tag_id: number, action: BulkTagAction, changed_file_ids: Array<number>, unchanged: number, }
\ No newline at end of file
@@ -1,4 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Collation } from "./Collation";
 import type { LibraryCommand } from "./LibraryCommand";
+import type { ShareTarget } from "./ShareTarget";
+import type { ThumbnailProfile } from "./ThumbnailProfile";
 
-export type ClientCommand = { key: "CreateLibrary", params: { name: string, } } | { key: "EditLibrary", params: { id: string, name: string | null, description: string | null, } } | { key: "DeleteLibrary", params: { id: string, } } | { key: "LibraryCommand", params: { library_id: string, command: LibraryCommand, } };
\ No newline at end of file
+export type ClientCommand = { key: "CreateLibrary", params: { name: string, } } | { key: "EditLibrary", params: { id: string, name: string | null, description: string | null, object_chunk_hashing: boolean | null, secrets_scanning: boolean | null, trash_retention_days: number | null, maintenance_interval_days: number | null, index_batch_size: number | null, index_flush_interval_ms: number | null, collation: Collation | null, show_hidden_files: boolean | null, places: boolean | null, thumbnail_profiles: Array<ThumbnailProfile> | null, share_target: ShareTarget | null, } } | { key: "DeleteLibrary", params: { id: string, } } | { key: "LibraryCommand", params: { library_id: string, command: LibraryCommand, } };
\ No newline at end of file
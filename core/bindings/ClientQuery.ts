@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { LibraryQuery } from "./LibraryQuery";
 
-export type ClientQuery = { key: "GetLibraries" } | { key: "GetNode" } | { key: "GetVolumes" } | { key: "GetNodes" } | { key: "LibraryQuery", params: { library_id: string, query: LibraryQuery, } };
\ No newline at end of file
+export type ClientQuery = { key: "GetLibraries" } | { key: "GetNode" } | { key: "GetVolumes" } | { key: "GetNodes" } | { key: "GetUsage", params: { days: number, } } | { key: "LibraryQuery", params: { library_id: string, query: LibraryQuery, } } | { key: "GetMetrics" } | { key: "GetDegradedLibraries" };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ConflictResolution } from "./ConflictResolution";
+
+export interface ConflictOutcome { source_path: string, target_path: string, resolution: ConflictResolution, }
\ No newline at end of file
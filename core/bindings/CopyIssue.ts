@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CopyIssueKind } from "./CopyIssueKind";
+
+export interface CopyIssue { file_path_id: number, source_path: string, target_path: string, kinds: Array<CopyIssueKind>, sanitized_path: string | null, }
\ No newline at end of file
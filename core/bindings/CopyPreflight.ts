@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CopyIssue } from "./CopyIssue";
+
+export interface CopyPreflight { file_system: string | null, case_insensitive: boolean, entry_count: number, issues: Array<CopyIssue>, }
\ No newline at end of file
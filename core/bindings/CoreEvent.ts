@@ -1,5 +1,13 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AttributeLoss } from "./AttributeLoss";
 import type { ClientQuery } from "./ClientQuery";
+import type { ConflictOutcome } from "./ConflictOutcome";
 import type { CoreResource } from "./CoreResource";
+import type { DegradedLibrary } from "./DegradedLibrary";
+import type { FilePath } from "./FilePath";
+import type { MaintenanceReport } from "./MaintenanceReport";
+import type { Volume } from "./Volume";
+import type { VolumeHealth } from "./VolumeHealth";
+import type { WatchdogWarning } from "./WatchdogWarning";
 
-export type CoreEvent = { key: "InvalidateQuery", data: ClientQuery } | { key: "InvalidateQueryDebounced", data: ClientQuery } | { key: "InvalidateResource", data: CoreResource } | { key: "NewThumbnail", data: { cas_id: string, } } | { key: "Log", data: { message: string, } } | { key: "DatabaseDisconnected", data: { reason: string | null, } };
\ No newline at end of file
+export type CoreEvent = { key: "InvalidateQuery", data: ClientQuery } | { key: "InvalidateQueryDebounced", data: ClientQuery } | { key: "InvalidateResource", data: CoreResource } | { key: "NewThumbnail", data: { cas_id: string, } } | { key: "Log", data: { message: string, } } | { key: "DatabaseDisconnected", data: { reason: string | null, } } | { key: "VolumeConnected", data: Volume } | { key: "VolumeDisconnected", data: Volume } | { key: "VolumeHealthWarning", data: VolumeHealth } | { key: "SavedSearchChanged", data: { library_id: string, id: number, added: Array<FilePath>, removed: Array<number>, } } | { key: "VirtualFolderChanged", data: { library_id: string, id: number, } } | { key: "WatchdogWarning", data: WatchdogWarning } | { key: "FileConflict", data: { library_id: string, job_id: string, source_path: string, target_path: string, } } | { key: "FileConflictOutcomes", data: { library_id: string, job_id: string, outcomes: Array<ConflictOutcome>, } } | { key: "FileAttributeLosses", data: { library_id: string, job_id: string, losses: Array<AttributeLoss>, } } | { key: "DirectoryChanged", data: { library_id: string, directory_id: number, added: Array<FilePath>, removed: Array<number>, } } | { key: "LibraryMaintained", data: { library_id: string, report: MaintenanceReport, } } | { key: "LibraryDegraded", data: DegradedLibrary } | { key: "VideoPreviewReady", data: { library_id: string, file_path_id: number, path: string | null, } };
\ No newline at end of file
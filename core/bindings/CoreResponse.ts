@@ -1,12 +1,59 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ArchiveContents } from "./ArchiveContents";
+import type { ArchivePreview } from "./ArchivePreview";
+import type { AuditEntry } from "./AuditEntry";
+import type { BackupManifest } from "./BackupManifest";
+import type { BackupPlan } from "./BackupPlan";
+import type { BackupVerification } from "./BackupVerification";
+import type { BatchRenamePreview } from "./BatchRenamePreview";
+import type { BulkTagPreview } from "./BulkTagPreview";
+import type { CopyPreflight } from "./CopyPreflight";
+import type { CustomField } from "./CustomField";
+import type { CustomFieldOnFile } from "./CustomFieldOnFile";
+import type { DailyUsage } from "./DailyUsage";
+import type { DegradedLibrary } from "./DegradedLibrary";
+import type { DirectoryPage } from "./DirectoryPage";
 import type { DirectoryWithContents } from "./DirectoryWithContents";
+import type { DuplicateGroup } from "./DuplicateGroup";
+import type { EphemeralDirectory } from "./EphemeralDirectory";
+import type { File } from "./File";
+import type { FileLink } from "./FileLink";
+import type { FileMetadataResult } from "./FileMetadataResult";
+import type { FilePath } from "./FilePath";
+import type { FileSnapshot } from "./FileSnapshot";
+import type { FullTextSearchResult } from "./FullTextSearchResult";
+import type { HistoryEntry } from "./HistoryEntry";
+import type { ImportPreview } from "./ImportPreview";
+import type { JobHistoryPage } from "./JobHistoryPage";
+import type { JobLogLine } from "./JobLogLine";
 import type { JobReport } from "./JobReport";
 import type { LibraryConfigWrapped } from "./LibraryConfigWrapped";
+import type { LibraryViewState } from "./LibraryViewState";
 import type { LocationResource } from "./LocationResource";
+import type { MetricsSnapshot } from "./MetricsSnapshot";
+import type { NameRepairPreview } from "./NameRepairPreview";
 import type { NodeState } from "./NodeState";
+import type { Note } from "./Note";
+import type { NoteChange } from "./NoteChange";
+import type { Place } from "./Place";
+import type { QuickAccessFile } from "./QuickAccessFile";
+import type { RetentionExpiry } from "./RetentionExpiry";
+import type { RetentionPolicy } from "./RetentionPolicy";
+import type { SavedSearch } from "./SavedSearch";
+import type { SecureDeletePreview } from "./SecureDeletePreview";
+import type { Share } from "./Share";
+import type { ShareBundle } from "./ShareBundle";
+import type { SimilarImage } from "./SimilarImage";
 import type { Statistics } from "./Statistics";
+import type { StorageBreakdown } from "./StorageBreakdown";
+import type { StorageTreemap } from "./StorageTreemap";
 import type { Tag } from "./Tag";
 import type { TagWithFiles } from "./TagWithFiles";
+import type { ThumbstripLayout } from "./ThumbstripLayout";
+import type { TrashedEntry } from "./TrashedEntry";
+import type { VirtualFolder } from "./VirtualFolder";
+import type { VirtualFolderContents } from "./VirtualFolderContents";
 import type { Volume } from "./Volume";
+import type { VolumeHealth } from "./VolumeHealth";
 
-export type CoreResponse = { key: "Success", data: null } | { key: "Error", data: string } | { key: "GetLibraries", data: Array<LibraryConfigWrapped> } | { key: "GetVolumes", data: Array<Volume> } | { key: "TagCreateResponse", data: Tag } | { key: "GetTag", data: Tag | null } | { key: "GetTags", data: Array<Tag> } | { key: "GetLocation", data: LocationResource } | { key: "GetLocations", data: Array<LocationResource> } | { key: "GetExplorerDir", data: DirectoryWithContents } | { key: "GetNode", data: NodeState } | { key: "LocCreate", data: LocationResource } | { key: "OpenTag", data: Array<TagWithFiles> } | { key: "GetRunningJobs", data: Array<JobReport> } | { key: "GetJobHistory", data: Array<JobReport> } | { key: "GetLibraryStatistics", data: Statistics };
\ No newline at end of file
+export type CoreResponse = { key: "Success", data: null } | { key: "Error", data: string } | { key: "GetLibraries", data: Array<LibraryConfigWrapped> } | { key: "GetVolumes", data: Array<Volume> } | { key: "GetUsage", data: Array<DailyUsage> } | { key: "GetMetrics", data: MetricsSnapshot } | { key: "TagCreateResponse", data: Tag } | { key: "GetTag", data: Tag | null } | { key: "GetTags", data: Array<Tag> } | { key: "GetLocation", data: LocationResource } | { key: "GetLocations", data: Array<LocationResource> } | { key: "GetExplorerDir", data: DirectoryWithContents } | { key: "GetNode", data: NodeState } | { key: "LocCreate", data: LocationResource } | { key: "OpenTag", data: Array<TagWithFiles> } | { key: "GetRunningJobs", data: Array<JobReport> } | { key: "GetJobHistory", data: JobHistoryPage } | { key: "GetLibraryStatistics", data: Statistics } | { key: "GetVolumeHealth", data: Array<VolumeHealth> } | { key: "GetDuplicateGroups", data: Array<DuplicateGroup> } | { key: "GetSimilarImages", data: Array<SimilarImage> } | { key: "GetThumbstrip", data: ThumbstripLayout | null } | { key: "SearchFullText", data: Array<FullTextSearchResult> } | { key: "GetRetentionPolicies", data: Array<RetentionPolicy> } | { key: "GetRetentionPreview", data: Array<RetentionExpiry> } | { key: "GetSavedSearches", data: Array<SavedSearch> } | { key: "SavedSearchResults", data: Array<FilePath> } | { key: "GetFileLinks", data: Array<FileLink> } | { key: "GetLinkedFiles", data: Array<File> } | { key: "GetViewState", data: LibraryViewState } | { key: "GetVirtualFolders", data: Array<VirtualFolder> } | { key: "GetVirtualFolderContents", data: VirtualFolderContents } | { key: "GetBulkTagPreview", data: BulkTagPreview } | { key: "GetHistory", data: Array<HistoryEntry> } | { key: "GetTrash", data: Array<TrashedEntry> } | { key: "GetBatchRenamePreview", data: BatchRenamePreview } | { key: "GetCompressPreview", data: ArchivePreview } | { key: "GetExtractPreview", data: ArchivePreview } | { key: "GetArchiveContents", data: ArchiveContents } | { key: "FileBatchSetMetadata", data: FileMetadataResult } | { key: "GetFileVersions", data: Array<FileSnapshot> } | { key: "GetFilePath", data: FilePath } | { key: "GetAuditLog", data: Array<AuditEntry> } | { key: "GetJobLogs", data: Array<JobLogLine> } | { key: "GetPlaces", data: Array<Place> } | { key: "GetPhotosByPlace", data: Array<FilePath> } | { key: "GetVideoPreview", data: string | null } | { key: "GetExplorerPage", data: DirectoryPage } | { key: "GetStorageBreakdown", data: StorageBreakdown } | { key: "GetStorageTreemap", data: StorageTreemap } | { key: "GetRecentFiles", data: Array<QuickAccessFile> } | { key: "GetFrequentFiles", data: Array<QuickAccessFile> } | { key: "CustomFieldCreateResponse", data: CustomField } | { key: "GetCustomFields", data: Array<CustomField> } | { key: "GetFileCustomFields", data: Array<CustomFieldOnFile> } | { key: "NoteCreateResponse", data: Note } | { key: "NotesMergeResponse", data: number } | { key: "GetNotes", data: Array<Note> } | { key: "GetNoteChanges", data: Array<NoteChange> } | { key: "ShareCreateResponse", data: Share } | { key: "ShareOpenResponse", data: ShareBundle } | { key: "GetShares", data: Array<Share> } | { key: "GetSecureDeletePreview", data: SecureDeletePreview } | { key: "BackupPlanCreateResponse", data: BackupPlan } | { key: "GetBackupPlans", data: Array<BackupPlan> } | { key: "GetBackupManifest", data: BackupManifest } | { key: "VerifyBackup", data: BackupVerification } | { key: "GetImportPreview", data: ImportPreview } | { key: "GetNameRepairPreview", data: NameRepairPreview } | { key: "GetCopyPreflight", data: CopyPreflight } | { key: "GetDegradedLibraries", data: Array<DegradedLibrary> } | { key: "GetEphemeralDir", data: EphemeralDirectory };
\ No newline at end of file
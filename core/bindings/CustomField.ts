@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CustomFieldKind } from "./CustomFieldKind";
+
+export interface CustomField { id: number, pub_id: string, name: string, kind: CustomFieldKind, options: Array<string>, date_created: string, date_modified: string, }
\ No newline at end of file
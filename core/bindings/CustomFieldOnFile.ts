@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CustomFieldValue } from "./CustomFieldValue";
+
+export interface CustomFieldOnFile { field_id: number, file_id: number, value: CustomFieldValue, date_modified: string, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type CustomFieldValue = { key: "Text", data: string } | { key: "Number", data: number } | { key: "Date", data: string } | { key: "Enum", data: string } | { key: "Rating", data: number };
\ No newline at end of file
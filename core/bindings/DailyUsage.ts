@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { UsageCategory } from "./UsageCategory";
+
+export interface DailyUsage { date: string, library_id: string, category: UsageCategory, bytes: bigint, }
\ No newline at end of file
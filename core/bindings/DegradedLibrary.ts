@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface DegradedLibrary { id: string, name: string, problems: Array<string>, restored_from: string | null, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DirectorySortBy } from "./DirectorySortBy";
+
+export interface DirectorySort { by: DirectorySortBy, descending: boolean, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ExplorerLayout } from "./ExplorerLayout";
+
+export interface DirectoryViewState { scroll_anchor: number | null, layout: ExplorerLayout | null, date_modified: string, }
\ No newline at end of file
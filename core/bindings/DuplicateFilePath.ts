@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FilePath } from "./FilePath";
+import type { LocationResource } from "./LocationResource";
+
+export interface DuplicateFilePath { file_path: FilePath, location: LocationResource | null, full_path: string | null, }
\ No newline at end of file
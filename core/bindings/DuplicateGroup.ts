@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DuplicateFilePath } from "./DuplicateFilePath";
+import type { DuplicateKind } from "./DuplicateKind";
+
+export interface DuplicateGroup { id: number, kind: DuplicateKind, file_paths: Array<DuplicateFilePath>, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EphemeralEntry } from "./EphemeralEntry";
+
+export interface EphemeralDirectory { path: string, location_id: number | null, entries: Array<EphemeralEntry>, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface EphemeralEntry { path: string, name: string, extension: string | null, is_dir: boolean, is_symlink: boolean, size_in_bytes: bigint, hidden: boolean, date_created: string, date_modified: string, cas_id: string | null, has_thumbnail: boolean, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { StorageCategory } from "./StorageCategory";
+
+export interface ExtensionStorage { extension: string, category: StorageCategory, bytes: string, file_count: number, }
\ No newline at end of file
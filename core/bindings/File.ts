@@ -1,5 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { FileKind } from "./FileKind";
 import type { FilePath } from "./FilePath";
+import type { MediaData } from "./MediaData";
+import type { SecretKind } from "./SecretKind";
 
-export interface File { id: number, cas_id: string, integrity_checksum: string | null, size_in_bytes: string, kind: FileKind, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string, paths: Array<FilePath>, }
\ No newline at end of file
+export interface File { id: number, cas_id: string, integrity_checksum: string | null, size_in_bytes: string, kind: FileKind, hidden: boolean, favorite: boolean, important: boolean, legal_hold: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, metadata_version: number, secret_kind: SecretKind | null, date_created: string, date_modified: string, date_indexed: string, paths: Array<FilePath>, media_data: MediaData | null, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { File } from "./File";
+import type { FileLinkKind } from "./FileLinkKind";
+
+export interface FileLink { id: number, kind: FileLinkKind, file: File, date_created: string, }
\ No newline at end of file
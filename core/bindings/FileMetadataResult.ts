@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FileVersion } from "./FileVersion";
+
+export interface FileMetadataResult { applied: boolean, versions: Array<FileVersion>, conflicts: Array<number>, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface FileMetadataUpdate { note: string | null, favorite: boolean | null, important: boolean | null, hidden: boolean | null, }
\ No newline at end of file
@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { File } from "./File";
 
-export interface FilePath { id: number, is_dir: boolean, location_id: number, materialized_path: string, name: string, extension: string | null, file_id: number | null, parent_id: number | null, date_created: string, date_modified: string, date_indexed: string, file: File | null, }
\ No newline at end of file
+export interface FilePath { id: number, is_dir: boolean, location_id: number, materialized_path: string, name: string, extension: string | null, file_id: number | null, parent_id: number | null, retention_exempt: boolean, hidden: boolean, folder_size: string | null, folder_size_stale: boolean, is_symlink: boolean, link_target: string | null, broken_link: boolean, date_created: string, date_modified: string, date_indexed: string, file: File | null, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface FileSnapshot { id: number, file_path_id: number, materialized_path: string, size_in_bytes: string, date_modified: string, date_created: string, }
\ No newline at end of file
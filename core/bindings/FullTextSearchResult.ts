@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { File } from "./File";
+
+export interface FullTextSearchResult { file: File, snippet: string, }
\ No newline at end of file
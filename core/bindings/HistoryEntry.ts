@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Operation } from "./Operation";
+
+export interface HistoryEntry { id: number, operation: Operation, undone: boolean, date_created: string, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ImportSource } from "./ImportSource";
+import type { ImportedMedia } from "./ImportedMedia";
+
+export interface ImportPreview { source: ImportSource, media_count: number, total_bytes: bigint, duplicate_count: number, with_taken_time: number, with_location: number, albums: Array<string>, people: Array<string>, keywords: Array<string>, media: Array<ImportedMedia>, }
\ No newline at end of file
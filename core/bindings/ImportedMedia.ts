@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ImportedMedia { path: string, target_path: string, size_in_bytes: bigint, taken_at: string | null, latitude: number | null, longitude: number | null, albums: Array<string>, people: Array<string>, keywords: Array<string>, description: string | null, favorite: boolean, }
\ No newline at end of file
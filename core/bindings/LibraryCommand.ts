@@ -1,3 +1,27 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ArchiveFormat } from "./ArchiveFormat";
+import type { BackupTarget } from "./BackupTarget";
+import type { BulkTagAction } from "./BulkTagAction";
+import type { ConflictPolicy } from "./ConflictPolicy";
+import type { ConflictResolution } from "./ConflictResolution";
+import type { CustomFieldKind } from "./CustomFieldKind";
+import type { CustomFieldValue } from "./CustomFieldValue";
+import type { DeletionPolicy } from "./DeletionPolicy";
+import type { DuplicateResolution } from "./DuplicateResolution";
+import type { ExplorerLayout } from "./ExplorerLayout";
+import type { FileAccessKind } from "./FileAccessKind";
+import type { FileLinkKind } from "./FileLinkKind";
+import type { FileMetadataUpdate } from "./FileMetadataUpdate";
+import type { FileVersion } from "./FileVersion";
+import type { ImportSource } from "./ImportSource";
+import type { InvalidNamePolicy } from "./InvalidNamePolicy";
+import type { NoteChange } from "./NoteChange";
+import type { RenamePattern } from "./RenamePattern";
+import type { RetentionAction } from "./RetentionAction";
+import type { SearchFilter } from "./SearchFilter";
+import type { SearchSort } from "./SearchSort";
+import type { SymlinkPolicy } from "./SymlinkPolicy";
+import type { ThumbnailPolicy } from "./ThumbnailPolicy";
+import type { VersioningPolicy } from "./VersioningPolicy";
 
-export type LibraryCommand = { key: "FileReadMetaData", params: { id: number, } } | { key: "FileSetNote", params: { id: number, note: string | null, } } | { key: "FileSetFavorite", params: { id: number, favorite: boolean, } } | { key: "FileDelete", params: { id: number, } } | { key: "TagCreate", params: { name: string, color: string, } } | { key: "TagUpdate", params: { id: number, name: string | null, color: string | null, } } | { key: "TagAssign", params: { file_id: number, tag_id: number, } } | { key: "TagDelete", params: { id: number, } } | { key: "LocCreate", params: { path: string, } } | { key: "LocUpdate", params: { id: number, name: string | null, } } | { key: "LocDelete", params: { id: number, } } | { key: "LocFullRescan", params: { id: number, } } | { key: "LocQuickRescan", params: { id: number, } } | { key: "VolUnmount", params: { id: number, } } | { key: "GenerateThumbsForLocation", params: { id: number, path: string, } } | { key: "IdentifyUniqueFiles", params: { id: number, path: string, } };
\ No newline at end of file
+export type LibraryCommand = { key: "FileReadMetaData", params: { id: number, } } | { key: "FileSetNote", params: { id: number, note: string | null, } } | { key: "FileSetFavorite", params: { id: number, favorite: boolean, } } | { key: "FileSetLegalHold", params: { id: number, legal_hold: boolean, } } | { key: "FileSnapshotRestore", params: { id: number, } } | { key: "FileBatchSetMetadata", params: { files: Array<FileVersion>, update: FileMetadataUpdate, } } | { key: "FileDelete", params: { id: number, } } | { key: "FilePathTrash", params: { id: number, } } | { key: "TrashRestore", params: { id: number, } } | { key: "TrashEmpty" } | { key: "FilePathCopy", params: { ids: Array<number>, location_id: number, path: string, conflict_policy: ConflictPolicy, invalid_names: InvalidNamePolicy | null, alternate_streams: boolean | null, } } | { key: "FilePathMove", params: { ids: Array<number>, location_id: number, path: string, conflict_policy: ConflictPolicy, invalid_names: InvalidNamePolicy | null, alternate_streams: boolean | null, } } | { key: "ResolveFileConflict", params: { job_id: string, resolution: ConflictResolution, apply_to_all: boolean, } } | { key: "FilePathBatchRename", params: { ids: Array<number>, pattern: RenamePattern, } } | { key: "FilePathCompress", params: { ids: Array<number>, location_id: number, path: string, format: ArchiveFormat, } } | { key: "FilePathExtract", params: { id: number, path: string | null, password: string | null, } } | { key: "FileLinkCreate", params: { file_id: number, linked_file_id: number, kind: FileLinkKind, } } | { key: "FileLinkDelete", params: { id: number, } } | { key: "TagCreate", params: { name: string, color: string, } } | { key: "TagUpdate", params: { id: number, name: string | null, color: string | null, } } | { key: "TagAssign", params: { file_id: number, tag_id: number, } } | { key: "TagDelete", params: { id: number, } } | { key: "TagBulk", params: { tag_id: number, file_ids: Array<number>, action: BulkTagAction, } } | { key: "LocCreate", params: { path: string, } } | { key: "LocUpdate", params: { id: number, name: string | null, } } | { key: "LocDelete", params: { id: number, } } | { key: "LocFullRescan", params: { id: number, } } | { key: "LocQuickRescan", params: { id: number, } } | { key: "LocAddPathMapping", params: { id: number, path: string, } } | { key: "LocRemovePathMapping", params: { id: number, } } | { key: "LocSetThumbnailPolicy", params: { id: number, policy: ThumbnailPolicy | null, } } | { key: "LocSetVersioning", params: { id: number, policy: VersioningPolicy | null, } } | { key: "ViewStateOpened", params: { location_id: number, path: string, } } | { key: "ViewStateSetDirectory", params: { location_id: number, path: string, scroll_anchor: number | null, layout: ExplorerLayout | null, } } | { key: "VolUnmount", params: { id: number, } } | { key: "GenerateThumbsForLocation", params: { id: number, path: string, } } | { key: "ThumbnailsRequest", params: { file_path_ids: Array<number>, } } | { key: "IdentifyUniqueFiles", params: { id: number, path: string, } } | { key: "FindDuplicates", params: { similar_images_max_distance: number | null, } } | { key: "ResolveDuplicateGroup", params: { id: number, keep_file_path_id: number, resolution: DuplicateResolution, } } | { key: "RetentionPolicyCreate", params: { name: string, location_id: number | null, path: string | null, tag_id: number | null, max_age_days: number, action: RetentionAction, } } | { key: "RetentionPolicyDelete", params: { id: number, } } | { key: "FilePathSetRetentionExempt", params: { id: number, exempt: boolean, } } | { key: "EnforceRetentionPolicies" } | { key: "SavedSearchCreate", params: { name: string, filter: SearchFilter, sort: SearchSort, } } | { key: "SavedSearchUpdate", params: { id: number, name: string | null, filter: SearchFilter | null, sort: SearchSort | null, } } | { key: "SavedSearchDelete", params: { id: number, } } | { key: "SavedSearchSubscribe", params: { id: number, } } | { key: "SavedSearchUnsubscribe", params: { id: number, } } | { key: "VirtualFolderCreate", params: { name: string, filter: SearchFilter, sort: SearchSort, } } | { key: "VirtualFolderUpdate", params: { id: number, name: string | null, filter: SearchFilter | null, sort: SearchSort | null, } } | { key: "VirtualFolderDelete", params: { id: number, } } | { key: "Undo" } | { key: "Redo" } | { key: "DirectorySubscribe", params: { directory_id: number, } } | { key: "DirectoryUnsubscribe", params: { directory_id: number, } } | { key: "FileRecordAccess", params: { file_path_id: number, kind: FileAccessKind, } } | { key: "CustomFieldCreate", params: { name: string, kind: CustomFieldKind, options: Array<string>, } } | { key: "CustomFieldUpdate", params: { id: number, name: string | null, options: Array<string> | null, } } | { key: "CustomFieldDelete", params: { id: number, } } | { key: "FileSetCustomField", params: { file_id: number, field_id: number, value: CustomFieldValue | null, } } | { key: "NoteCreate", params: { file_id: number, body: string, } } | { key: "NoteUpdate", params: { id: number, body: string, } } | { key: "NoteDelete", params: { id: number, } } | { key: "NotesMerge", params: { changes: Array<NoteChange>, } } | { key: "ShareCreate", params: { file_path_id: number, expires_at: string, password: string | null, } } | { key: "ShareRevoke", params: { id: number, } } | { key: "ShareOpen", params: { token: string, password: string | null, } } | { key: "FilePathSecureDelete", params: { ids: Array<number>, passes: number | null, } } | { key: "BackupPlanCreate", params: { name: string, location_id: number, target: BackupTarget, deletion_policy: DeletionPolicy, interval_hours: number | null, } } | { key: "BackupPlanDelete", params: { id: number, } } | { key: "BackupPlanRun", params: { id: number, } } | { key: "MediaImport", params: { source: ImportSource, path: string, location_id: number, target_path: string | null, } } | { key: "LocRepairNames", params: { location_id: number, ids: Array<number> | null, } } | { key: "LocSetSymlinkPolicy", params: { id: number, policy: SymlinkPolicy, } } | { key: "LibraryMaintenanceRun" } | { key: "EphemeralCreateDir", params: { path: string, name: string, } } | { key: "EphemeralRename", params: { path: string, name: string, } } | { key: "EphemeralDelete", params: { paths: Array<string>, } } | { key: "EphemeralPromote", params: { path: string, } };
\ No newline at end of file
@@ -1,3 +1,6 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Collation } from "./Collation";
+import type { ShareTarget } from "./ShareTarget";
+import type { ThumbnailProfile } from "./ThumbnailProfile";
 
-export interface LibraryConfig { version: string | null, name: string, description: string, }
\ No newline at end of file
+export interface LibraryConfig { version: string | null, name: string, description: string, object_chunk_hashing: boolean, secrets_scanning: boolean, trash_retention_days: number | null, maintenance_interval_days: number | null, index_batch_size: number | null, index_flush_interval_ms: number | null, collation: Collation, show_hidden_files: boolean, places: boolean, thumbnail_profiles: Array<ThumbnailProfile>, share_target: ShareTarget | null, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface LibraryMetricsSnapshot { library_id: string | null, jobs_completed: bigint, jobs_failed: bigint, jobs_paused: bigint, queries: bigint, query_seconds: number, commands: bigint, command_seconds: number, indexed_rows: bigint, index_write_seconds: number, }
\ No newline at end of file
@@ -1,3 +1,10 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ArchiveFormat } from "./ArchiveFormat";
+import type { AuditFilter } from "./AuditFilter";
+import type { BulkTagAction } from "./BulkTagAction";
+import type { DirectorySort } from "./DirectorySort";
+import type { ImportSource } from "./ImportSource";
+import type { JobLogLevel } from "./JobLogLevel";
+import type { RenamePattern } from "./RenamePattern";
 
-export type LibraryQuery = { key: "GetJobHistory" } | { key: "GetLocations" } | { key: "GetLocation", params: { id: number, } } | { key: "GetRunningJobs" } | { key: "GetExplorerDir", params: { location_id: number, path: string, limit: number, } } | { key: "GetLibraryStatistics" } | { key: "GetTags" } | { key: "GetFilesTagged", params: { tag_id: number, } };
\ No newline at end of file
+export type LibraryQuery = { key: "GetJobHistory", params: { cursor: string | null, limit: number, } } | { key: "GetLocations" } | { key: "GetLocation", params: { id: number, } } | { key: "GetRunningJobs" } | { key: "GetExplorerDir", params: { location_id: number, path: string, limit: number, show_hidden: boolean | null, } } | { key: "GetLibraryStatistics" } | { key: "GetTags" } | { key: "GetFilesTagged", params: { tag_id: number, } } | { key: "GetBulkTagPreview", params: { tag_id: number, file_ids: Array<number>, action: BulkTagAction, } } | { key: "GetVolumeHealth" } | { key: "GetDuplicateGroups" } | { key: "GetSimilarImages", params: { file_id: number, max_distance: number, } } | { key: "GetThumbstrip", params: { location_id: number, cas_id: string, } } | { key: "SearchFullText", params: { query: string, limit: number, } } | { key: "GetRetentionPolicies" } | { key: "GetRetentionPreview", params: { days_ahead: number, } } | { key: "GetSavedSearches" } | { key: "GetSavedSearchResults", params: { id: number, } } | { key: "GetFileLinks", params: { file_id: number, } } | { key: "GetLinkedFiles", params: { file_id: number, } } | { key: "GetViewState" } | { key: "GetVirtualFolders" } | { key: "GetVirtualFolderContents", params: { id: number, cursor: string | null, limit: number, } } | { key: "GetHistory" } | { key: "GetTrash" } | { key: "GetBatchRenamePreview", params: { ids: Array<number>, pattern: RenamePattern, } } | { key: "GetCompressPreview", params: { ids: Array<number>, format: ArchiveFormat, } } | { key: "GetExtractPreview", params: { id: number, } } | { key: "GetArchiveContents", params: { id: number, path: string, } } | { key: "GetFileVersions", params: { file_path_id: number, } } | { key: "GetFilePath", params: { id: number, } } | { key: "GetAuditLog", params: { filter: AuditFilter, offset: bigint, limit: bigint, } } | { key: "GetJobLogs", params: { job_id: string, tail: bigint | null, level: JobLogLevel | null, } } | { key: "GetPlaces" } | { key: "GetPhotosByPlace", params: { place_id: number, } } | { key: "GetVideoPreview", params: { file_path_id: number, } } | { key: "GetExplorerPage", params: { location_id: number, path: string, sort: DirectorySort, cursor: string | null, limit: number, prefetch: number | null, show_hidden: boolean | null, } } | { key: "GetStorageBreakdown", params: { location_id: number | null, } } | { key: "GetStorageTreemap", params: { location_id: number, path: string, limit: number, } } | { key: "GetRecentFiles", params: { limit: number, } } | { key: "GetFrequentFiles", params: { limit: number, } } | { key: "GetCustomFields" } | { key: "GetFileCustomFields", params: { file_id: number, } } | { key: "GetNotes", params: { file_id: number, } } | { key: "GetNoteChanges", params: { since: string | null, } } | { key: "GetShares" } | { key: "GetSecureDeletePreview", params: { ids: Array<number>, passes: number | null, } } | { key: "GetBackupPlans" } | { key: "GetBackupManifest", params: { id: number, } } | { key: "VerifyBackup", params: { id: number, } } | { key: "GetImportPreview", params: { source: ImportSource, path: string, } } | { key: "GetNameRepairPreview", params: { location_id: number, } } | { key: "GetCopyPreflight", params: { ids: Array<number>, location_id: number, path: string, } } | { key: "GetEphemeralDir", params: { path: string, show_hidden: boolean | null, } };
\ No newline at end of file
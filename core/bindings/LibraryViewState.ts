@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DirectoryViewState } from "./DirectoryViewState";
+import type { ExplorerPath } from "./ExplorerPath";
+
+export interface LibraryViewState { last_opened: Array<ExplorerPath>, directories: Record<string, DirectoryViewState>, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface LocationPathMapping { node_id: number, path: string, date_created: string, }
\ No newline at end of file
@@ -1,4 +1,8 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { LibraryNode } from "./LibraryNode";
+import type { LocationPathMapping } from "./LocationPathMapping";
+import type { SymlinkPolicy } from "./SymlinkPolicy";
+import type { ThumbnailPolicy } from "./ThumbnailPolicy";
+import type { VersioningPolicy } from "./VersioningPolicy";
 
-export interface LocationResource { id: number, name: string | null, path: string | null, total_capacity: number | null, available_capacity: number | null, is_removable: boolean | null, node: LibraryNode | null, is_online: boolean, date_created: string, }
\ No newline at end of file
+export interface LocationResource { id: number, name: string | null, path: string | null, total_capacity: number | null, available_capacity: number | null, is_removable: boolean | null, node: LibraryNode | null, is_online: boolean, path_mappings: Array<LocationPathMapping>, thumbnail_policy: ThumbnailPolicy | null, symlink_policy: SymlinkPolicy, versioning: VersioningPolicy | null, date_created: string, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface LocationStorage { location_id: number, name: string | null, bytes: string, file_count: number, }
\ No newline at end of file
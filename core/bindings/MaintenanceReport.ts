@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface MaintenanceReport { orphaned_files: number, removed_thumbnails: number, removed_sync_events: number, reclaimed_bytes: bigint, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ManifestEntry { path: string, cas_id: string, size_in_bytes: bigint, date_modified: string, }
\ No newline at end of file
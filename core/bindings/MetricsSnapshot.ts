@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LibraryMetricsSnapshot } from "./LibraryMetricsSnapshot";
+
+export interface MetricsSnapshot { queued_jobs: number, running_jobs: number, libraries: Array<LibraryMetricsSnapshot>, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { NameIssue } from "./NameIssue";
+import type { RenamedPath } from "./RenamedPath";
+
+export interface NameRepair { materialized_path: string, issues: Array<NameIssue>, rename: RenamedPath | null, collision: boolean, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { NameRepair } from "./NameRepair";
+import type { NormalizationForm } from "./NormalizationForm";
+
+export interface NameRepairPreview { location_id: number, form: NormalizationForm, checked: number, repairs: Array<NameRepair>, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface Note { id: number, pub_id: string, file_id: number, body: string, preview: string, author: string, author_name: string, date_created: string, date_modified: string, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface NoteChange { pub_id: string, cas_id: string, body: string, author: string, author_name: string, modified_by: string, date_created: string, date_modified: string, date_deleted: string | null, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BulkTagAction } from "./BulkTagAction";
+import type { RenamedPath } from "./RenamedPath";
+
+export type Operation = { key: "Tag", data: { tag_id: number, file_ids: Array<number>, action: BulkTagAction, } } | { key: "Trash", data: { location_id: number, path: string, } } | { key: "Restore", data: { location_id: number, path: string, } } | { key: "Rename", data: { renames: Array<RenamedPath>, } };
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface Place { id: number, name: string | null, country_code: string | null, latitude: number, longitude: number, photo_count: number, date_created: string, }
\ No newline at end of file
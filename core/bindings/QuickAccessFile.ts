@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FilePath } from "./FilePath";
+
+export interface QuickAccessFile { file_path: FilePath, open_count: number, preview_count: number, last_accessed: string, score: number, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface RenamePattern { pattern: string, regex: string | null, counter_start: number | null, }
\ No newline at end of file
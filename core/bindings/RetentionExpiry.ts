@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FilePath } from "./FilePath";
+import type { RetentionAction } from "./RetentionAction";
+
+export interface RetentionExpiry { policy_id: number, action: RetentionAction, file_path: FilePath, expires_at: string, }
\ No newline at end of file
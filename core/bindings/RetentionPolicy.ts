@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RetentionAction } from "./RetentionAction";
+
+export interface RetentionPolicy { id: number, name: string, location_id: number | null, path: string | null, tag_id: number | null, max_age_days: number, action: RetentionAction, date_created: string, }
\ No newline at end of file
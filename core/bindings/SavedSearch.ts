@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SearchFilter } from "./SearchFilter";
+import type { SearchSort } from "./SearchSort";
+
+export interface SavedSearch { id: number, name: string, filter: SearchFilter, sort: SearchSort, date_created: string, date_modified: string, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FileKind } from "./FileKind";
+
+export interface SearchFilter { name: string | null, extensions: Array<string>, location_id: number | null, tag_id: number | null, kind: FileKind | null, favorite: boolean | null, min_size: bigint | null, max_size: bigint | null, created_after: string | null, created_before: string | null, show_hidden: boolean | null, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SearchSortBy } from "./SearchSortBy";
+
+export interface SearchSort { by: SearchSortBy, descending: boolean, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ShredCaveat } from "./ShredCaveat";
+
+export interface SecureDeletePreview { paths: Array<string>, file_count: number, directory_count: number, total_bytes: bigint, sidecars: Array<string>, version_count: number, files_with_copies: number, passes: number, caveats: Array<ShredCaveat>, }
\ No newline at end of file
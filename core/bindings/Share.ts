@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ShareStatus } from "./ShareStatus";
+
+export interface Share { id: number, pub_id: string, file_path_id: number | null, name: string, is_dir: boolean, is_remote: boolean, status: ShareStatus, protected: boolean, link: string | null, download_count: number, expires_at: string, date_created: string, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ShareTarget = { key: "Gateway", data: { base_url: string, } } | { key: "WebDav", data: { url: string, username: string | null, password: string | null, } } | { key: "S3", data: { endpoint: string, region: string, bucket: string, access_key_id: string, secret_access_key: string, } };
\ No newline at end of file
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CategoryStorage } from "./CategoryStorage";
+import type { ExtensionStorage } from "./ExtensionStorage";
+import type { LocationStorage } from "./LocationStorage";
+
+export interface StorageBreakdown { bytes: string, file_count: number, locations: Array<LocationStorage>, categories: Array<CategoryStorage>, extensions: Array<ExtensionStorage>, stale_location_ids: Array<number>, }
\ No newline at end of file
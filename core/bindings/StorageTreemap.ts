@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FilePath } from "./FilePath";
+import type { TreemapNode } from "./TreemapNode";
+
+export interface StorageTreemap { directory: FilePath, bytes: string, nodes: Array<TreemapNode>, rest_bytes: string, rest_count: number, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ThumbnailFormat } from "./ThumbnailFormat";
+
+export interface ThumbnailProfile { name: string, size: number, format: ThumbnailFormat, quality: number, }
\ No newline at end of file
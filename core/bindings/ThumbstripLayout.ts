@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ThumbstripLayout { path: string, frame_count: number, columns: number, rows: number, frame_width: number, frame_height: number, }
\ No newline at end of file
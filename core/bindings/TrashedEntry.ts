@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface TrashedEntry { id: number, location_id: number, original_path: string, is_dir: boolean, date_trashed: string, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface TreemapNode { file_path_id: number, name: string, extension: string | null, is_dir: boolean, bytes: string, }
\ No newline at end of file
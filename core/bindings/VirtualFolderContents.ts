@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FilePath } from "./FilePath";
+
+export interface VirtualFolderContents { items: Array<FilePath>, next_cursor: string | null, }
\ No newline at end of file
@@ -1,3 +1,5 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FileSystemCapabilities } from "./FileSystemCapabilities";
+import type { NetworkProtocol } from "./NetworkProtocol";
 
-export interface Volume { name: string, mount_point: string, total_capacity: bigint, available_capacity: bigint, is_removable: boolean, disk_type: string | null, file_system: string | null, is_root_filesystem: boolean, }
\ No newline at end of file
+export interface Volume { name: string, mount_point: string, total_capacity: bigint, available_capacity: bigint, is_removable: boolean, disk_type: string | null, file_system: string | null, is_root_filesystem: boolean, network_protocol: NetworkProtocol | null, remote_host: string | null, capabilities: FileSystemCapabilities, }
\ No newline at end of file
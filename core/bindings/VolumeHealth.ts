@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface VolumeHealth { device: string, model: string | null, serial_number: string | null, passed: boolean, reallocated_sectors: number | null, pending_sectors: number | null, uncorrectable_sectors: number | null, temperature: number | null, power_on_hours: number | null, date_captured: string, }
\ No newline at end of file
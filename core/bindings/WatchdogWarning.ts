@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { JobReport } from "./JobReport";
+
+export type WatchdogWarning = { key: "JobStalled", data: { library_id: string, job: JobReport, idle_seconds: bigint, queued_jobs: number, } } | { key: "EventLoopLagging", data: { lag_ms: bigint, } } | { key: "EventBacklog", data: { queued_events: number, } };
\ No newline at end of file
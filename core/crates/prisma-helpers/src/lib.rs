@@ -0,0 +1,241 @@
+//! Keyset pagination for the raw queries of the core.
+//!
+//! Reading a page by offset makes SQLite step over every row before it, which gets slow on the
+//! large tables of a library. A keyset page starts right after the last row of the previous
+//! one instead, found through the index of the sorted columns:
+//!
+//! ```
+//! use prisma_helpers::{Keyset, SortDirection};
+//!
+//! let keyset = Keyset::new(&["file_paths.date_created", "file_paths.id"], SortDirection::Desc);
+//! let (condition, values) = keyset.after(Some(vec!["2022-08-01", "42"]));
+//!
+//! assert_eq!(condition, "(file_paths.date_created, file_paths.id) < ({}, {})");
+//! assert_eq!(values, vec!["2022-08-01", "42"]);
+//! assert_eq!(keyset.order_by(), "file_paths.date_created DESC, file_paths.id DESC");
+//! ```
+//!
+//! Conditions use `{}` for their values, as the `raw!` queries of prisma-client-rust do, and are
+//! generic over the value type so this crate doesn't depend on the generated client.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+/// A cursor which doesn't decode to the key of the query it was given to.
+#[derive(Error, Debug)]
+#[error("Invalid cursor: {0}")]
+pub struct InvalidCursor(pub String);
+
+/// Encodes the key of the last row of a page, for clients to hand back as they are.
+pub fn encode_cursor<K: Serialize>(key: &K) -> String {
+	base64::encode_config(
+		serde_json::to_vec(key).unwrap_or_default(),
+		base64::URL_SAFE_NO_PAD,
+	)
+}
+
+pub fn decode_cursor<K: DeserializeOwned>(cursor: &str) -> Result<K, InvalidCursor> {
+	base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)
+		.ok()
+		.and_then(|bytes| serde_json::from_slice(&bytes).ok())
+		.ok_or_else(|| InvalidCursor(cursor.to_string()))
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub enum SortDirection {
+	Asc,
+	Desc,
+}
+
+impl SortDirection {
+	pub fn from_descending(descending: bool) -> Self {
+		match descending {
+			true => Self::Desc,
+			false => Self::Asc,
+		}
+	}
+
+	fn keyword(self) -> &'static str {
+		match self {
+			Self::Asc => "ASC",
+			Self::Desc => "DESC",
+		}
+	}
+
+	// what the rows after a key compare as
+	fn operator(self) -> &'static str {
+		match self {
+			Self::Asc => ">",
+			Self::Desc => "<",
+		}
+	}
+}
+
+/// The columns a query is sorted on, in the same direction. The last one has to be unique, an id
+/// or pub_id, so the order is total and no row is skipped or read twice between two pages. None
+/// of them can be null, as null never compares greater or less than a value.
+#[derive(Debug, Clone, Copy)]
+pub struct Keyset<'a> {
+	columns: &'a [&'a str],
+	direction: SortDirection,
+}
+
+impl<'a> Keyset<'a> {
+	pub fn new(columns: &'a [&'a str], direction: SortDirection) -> Self {
+		assert!(
+			!columns.is_empty(),
+			"a keyset needs at least its unique column"
+		);
+		Self { columns, direction }
+	}
+
+	/// The condition matching the rows after the one `key` holds the values of, a value per
+	/// column. Matches every row without a key, for the first page.
+	pub fn after<V>(&self, key: Option<Vec<V>>) -> (String, Vec<V>) {
+		let key = match key {
+			Some(key) => key,
+			None => return ("1 = 1".to_string(), vec![]),
+		};
+		assert_eq!(
+			key.len(),
+			self.columns.len(),
+			"a keyset needs a value per column"
+		);
+
+		// row values compare column by column, and SQLite walks the index of the columns for them
+		(
+			format!(
+				"({}) {} ({})",
+				self.columns.join(", "),
+				self.direction.operator(),
+				vec!["{}"; self.columns.len()].join(", ")
+			),
+			key,
+		)
+	}
+
+	/// The ORDER BY clause the condition pages through, without the keywords.
+	pub fn order_by(&self) -> String {
+		self.columns
+			.iter()
+			.map(|column| format!("{} {}", column, self.direction.keyword()))
+			.collect::<Vec<_>>()
+			.join(", ")
+	}
+}
+
+/// A page of rows, `next_cursor` resuming right after its last one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+	pub items: Vec<T>,
+	/// None once the last row was read.
+	pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+	/// The page of `rows` read with a limit of `limit + 1`, the extra row telling whether another
+	/// page follows. Its cursor is the key of the last row kept.
+	pub fn from_rows<K: Serialize>(mut rows: Vec<T>, limit: usize, key: impl Fn(&T) -> K) -> Self {
+		let next_cursor = match rows.len() > limit {
+			true => {
+				rows.truncate(limit);
+				rows.last().map(|row| encode_cursor(&key(row)))
+			}
+			false => None,
+		};
+
+		Self {
+			items: rows,
+			next_cursor,
+		}
+	}
+
+	pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+		Page {
+			items: self.items.into_iter().map(f).collect(),
+			next_cursor: self.next_cursor,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	struct Key {
+		date_created: String,
+		id: i32,
+	}
+
+	#[test]
+	fn cursor_round_trips() {
+		let key = Key {
+			date_created: "2022-08-01T00:00:00Z".to_string(),
+			id: 42,
+		};
+		let cursor = encode_cursor(&key);
+
+		assert!(cursor
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+		assert_eq!(decode_cursor::<Key>(&cursor).unwrap(), key);
+	}
+
+	#[test]
+	fn cursor_of_another_key_is_invalid() {
+		let cursor = encode_cursor(&(1, 2));
+
+		assert!(decode_cursor::<Key>(&cursor).is_err());
+		assert!(decode_cursor::<Key>("not a cursor!").is_err());
+		assert_eq!(
+			decode_cursor::<Key>("").unwrap_err().to_string(),
+			"Invalid cursor: "
+		);
+	}
+
+	#[test]
+	fn first_page_matches_every_row() {
+		let keyset = Keyset::new(&["name", "id"], SortDirection::Asc);
+		let (condition, values) = keyset.after::<i32>(None);
+
+		assert_eq!(condition, "1 = 1");
+		assert!(values.is_empty());
+	}
+
+	#[test]
+	fn ascending_keyset_reads_greater_rows() {
+		let keyset = Keyset::new(&["name", "id"], SortDirection::from_descending(false));
+		let (condition, values) = keyset.after(Some(vec!["b", "7"]));
+
+		assert_eq!(condition, "(name, id) > ({}, {})");
+		assert_eq!(values, vec!["b", "7"]);
+		assert_eq!(keyset.order_by(), "name ASC, id ASC");
+	}
+
+	#[test]
+	#[should_panic(expected = "a keyset needs a value per column")]
+	fn key_needs_a_value_per_column() {
+		Keyset::new(&["name", "id"], SortDirection::Asc).after(Some(vec![1]));
+	}
+
+	#[test]
+	fn page_with_an_extra_row_has_a_cursor_to_its_last_row() {
+		let page = Page::from_rows(vec![1, 2, 3], 2, |id| Key {
+			date_created: String::new(),
+			id: *id,
+		});
+
+		assert_eq!(page.items, vec![1, 2]);
+		let key = decode_cursor::<Key>(&page.next_cursor.unwrap()).unwrap();
+		assert_eq!(key.id, 2);
+	}
+
+	#[test]
+	fn last_page_has_no_cursor() {
+		let page = Page::from_rows(vec![1, 2], 2, |id| *id).map(|id| id * 10);
+
+		assert_eq!(page.items, vec![10, 20]);
+		assert!(page.next_cursor.is_none());
+	}
+}
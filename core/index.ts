@@ -1,30 +1,144 @@
+export * from './bindings/ArchiveContents';
+export * from './bindings/ArchiveEntry';
+export * from './bindings/ArchiveFormat';
+export * from './bindings/ArchivePreview';
+export * from './bindings/ArchivedEntry';
+export * from './bindings/AttributeKind';
+export * from './bindings/AttributeLoss';
+export * from './bindings/AuditEntry';
+export * from './bindings/AuditFilter';
+export * from './bindings/AuditOperation';
+export * from './bindings/AuditedJob';
+export * from './bindings/BackupManifest';
+export * from './bindings/BackupPlan';
+export * from './bindings/BackupTarget';
+export * from './bindings/BackupVerification';
+export * from './bindings/BatchRenamePreview';
+export * from './bindings/BulkTagAction';
+export * from './bindings/BulkTagPreview';
+export * from './bindings/CategoryStorage';
 export * from './bindings/Client';
 export * from './bindings/ClientCommand';
 export * from './bindings/ClientQuery';
 export * from './bindings/ClientState';
+export * from './bindings/Collation';
 export * from './bindings/ConfigMetadata';
+export * from './bindings/ConflictOutcome';
+export * from './bindings/ConflictPolicy';
+export * from './bindings/ConflictResolution';
+export * from './bindings/CopyIssue';
+export * from './bindings/CopyIssueKind';
+export * from './bindings/CopyPreflight';
 export * from './bindings/CoreEvent';
 export * from './bindings/CoreResource';
 export * from './bindings/CoreResponse';
+export * from './bindings/CustomField';
+export * from './bindings/CustomFieldKind';
+export * from './bindings/CustomFieldOnFile';
+export * from './bindings/CustomFieldValue';
+export * from './bindings/DailyUsage';
+export * from './bindings/DegradedLibrary';
+export * from './bindings/DeletionPolicy';
+export * from './bindings/DirectoryPage';
+export * from './bindings/DirectorySort';
+export * from './bindings/DirectorySortBy';
+export * from './bindings/DirectoryViewState';
 export * from './bindings/DirectoryWithContents';
+export * from './bindings/DuplicateFilePath';
+export * from './bindings/DuplicateGroup';
+export * from './bindings/DuplicateKind';
+export * from './bindings/DuplicateResolution';
 export * from './bindings/EncryptionAlgorithm';
+export * from './bindings/EphemeralDirectory';
+export * from './bindings/EphemeralEntry';
+export * from './bindings/ExplorerLayout';
+export * from './bindings/ExplorerPath';
+export * from './bindings/ExtensionStorage';
 export * from './bindings/File';
+export * from './bindings/FileAccessKind';
 export * from './bindings/FileKind';
+export * from './bindings/FileLink';
+export * from './bindings/FileLinkKind';
+export * from './bindings/FileMetadataResult';
+export * from './bindings/FileMetadataUpdate';
 export * from './bindings/FilePath';
+export * from './bindings/FileSnapshot';
+export * from './bindings/FileSystemCapabilities';
+export * from './bindings/FileVersion';
+export * from './bindings/FullTextSearchResult';
+export * from './bindings/HistoryEntry';
+export * from './bindings/ImportPreview';
+export * from './bindings/ImportSource';
+export * from './bindings/ImportedMedia';
+export * from './bindings/InvalidNamePolicy';
+export * from './bindings/JobHistoryPage';
+export * from './bindings/JobLogLevel';
+export * from './bindings/JobLogLine';
 export * from './bindings/JobReport';
 export * from './bindings/JobStatus';
 export * from './bindings/LibraryCommand';
 export * from './bindings/LibraryConfig';
 export * from './bindings/LibraryConfigWrapped';
+export * from './bindings/LibraryMetricsSnapshot';
 export * from './bindings/LibraryNode';
 export * from './bindings/LibraryQuery';
 export * from './bindings/LibraryState';
+export * from './bindings/LibraryViewState';
+export * from './bindings/LocationPathMapping';
 export * from './bindings/LocationResource';
+export * from './bindings/LocationStorage';
+export * from './bindings/MaintenanceReport';
+export * from './bindings/ManifestEntry';
+export * from './bindings/MediaData';
+export * from './bindings/MetricsSnapshot';
+export * from './bindings/NameIssue';
+export * from './bindings/NameRepair';
+export * from './bindings/NameRepairPreview';
+export * from './bindings/NetworkProtocol';
 export * from './bindings/NodeConfig';
 export * from './bindings/NodeState';
+export * from './bindings/NormalizationForm';
+export * from './bindings/Note';
+export * from './bindings/NoteChange';
+export * from './bindings/Operation';
+export * from './bindings/Place';
 export * from './bindings/Platform';
+export * from './bindings/QuickAccessFile';
+export * from './bindings/RenamePattern';
+export * from './bindings/RenamedPath';
+export * from './bindings/RetentionAction';
+export * from './bindings/RetentionExpiry';
+export * from './bindings/RetentionPolicy';
+export * from './bindings/SavedSearch';
+export * from './bindings/SearchFilter';
+export * from './bindings/SearchSort';
+export * from './bindings/SearchSortBy';
+export * from './bindings/SecretKind';
+export * from './bindings/SecureDeletePreview';
+export * from './bindings/Share';
+export * from './bindings/ShareBundle';
+export * from './bindings/ShareStatus';
+export * from './bindings/ShareTarget';
+export * from './bindings/ShredCaveat';
+export * from './bindings/SimilarImage';
 export * from './bindings/Statistics';
+export * from './bindings/StorageBreakdown';
+export * from './bindings/StorageCategory';
+export * from './bindings/StorageTreemap';
+export * from './bindings/SymlinkPolicy';
 export * from './bindings/Tag';
 export * from './bindings/TagOnFile';
 export * from './bindings/TagWithFiles';
+export * from './bindings/ThumbnailFormat';
+export * from './bindings/ThumbnailPolicy';
+export * from './bindings/ThumbnailProfile';
+export * from './bindings/ThumbstripLayout';
+export * from './bindings/TrashedEntry';
+export * from './bindings/TreemapNode';
+export * from './bindings/UsageCategory';
+export * from './bindings/VersioningPolicy';
+export * from './bindings/VirtualFolder';
+export * from './bindings/VirtualFolderContents';
 export * from './bindings/Volume';
+export * from './bindings/VolumeHealth';
+export * from './bindings/WatchdogWarning';
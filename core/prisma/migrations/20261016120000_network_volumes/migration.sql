@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "volumes" ADD COLUMN "network_protocol" INTEGER;
+ALTER TABLE "volumes" ADD COLUMN "remote_host" TEXT;
@@ -0,0 +1,18 @@
+-- CreateTable
+CREATE TABLE "volume_health" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "node_id" INTEGER NOT NULL,
+    "device" TEXT NOT NULL,
+    "model" TEXT,
+    "serial_number" TEXT,
+    "passed" BOOLEAN NOT NULL,
+    "reallocated_sectors" INTEGER,
+    "pending_sectors" INTEGER,
+    "uncorrectable_sectors" INTEGER,
+    "temperature" INTEGER,
+    "power_on_hours" INTEGER,
+    "date_captured" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE INDEX "volume_health_node_id_device_idx" ON "volume_health"("node_id", "device");
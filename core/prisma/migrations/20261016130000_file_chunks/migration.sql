@@ -0,0 +1,17 @@
+-- AlterTable
+ALTER TABLE "files" ADD COLUMN "chunk_root" TEXT;
+
+-- CreateTable
+CREATE TABLE "file_chunks" (
+    "file_id" INTEGER NOT NULL,
+    "sequence" INTEGER NOT NULL,
+    "byte_offset" BIGINT NOT NULL,
+    "size" INTEGER NOT NULL,
+    "hash" TEXT NOT NULL,
+
+    PRIMARY KEY ("file_id", "sequence"),
+    CONSTRAINT "file_chunks_file_id_fkey" FOREIGN KEY ("file_id") REFERENCES "files" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "file_chunks_hash_idx" ON "file_chunks"("hash");
@@ -0,0 +1,16 @@
+-- CreateTable
+CREATE TABLE "duplicate_groups" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "kind" INTEGER NOT NULL DEFAULT 0,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateTable
+CREATE TABLE "file_path_in_duplicate_group" (
+    "group_id" INTEGER NOT NULL,
+    "file_path_id" INTEGER NOT NULL,
+
+    PRIMARY KEY ("group_id", "file_path_id"),
+    CONSTRAINT "file_path_in_duplicate_group_group_id_fkey" FOREIGN KEY ("group_id") REFERENCES "duplicate_groups" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "file_path_in_duplicate_group_file_path_id_fkey" FOREIGN KEY ("file_path_id") REFERENCES "file_paths" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,7 @@
+-- AlterTable
+ALTER TABLE "media_data" ADD COLUMN "bitrate" INTEGER;
+ALTER TABLE "media_data" ADD COLUMN "title" TEXT;
+ALTER TABLE "media_data" ADD COLUMN "artist" TEXT;
+ALTER TABLE "media_data" ADD COLUMN "album" TEXT;
+ALTER TABLE "media_data" ADD COLUMN "track_number" INTEGER;
+ALTER TABLE "media_data" ADD COLUMN "has_album_art" BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "files" ADD COLUMN "legal_hold" BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+-- CreateTable
+-- full text index of the text extracted from documents, the rowid is the id of the file
+CREATE VIRTUAL TABLE "file_contents_fts" USING fts5("content", tokenize = 'porter unicode61');
@@ -0,0 +1,16 @@
+-- AlterTable
+ALTER TABLE "file_paths" ADD COLUMN "retention_exempt" BOOLEAN NOT NULL DEFAULT false;
+
+-- CreateTable
+CREATE TABLE "retention_policies" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "name" TEXT NOT NULL,
+    "location_id" INTEGER,
+    "path" TEXT,
+    "tag_id" INTEGER,
+    "max_age_days" INTEGER NOT NULL,
+    "action" INTEGER NOT NULL DEFAULT 0,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "retention_policies_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "locations" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "retention_policies_tag_id_fkey" FOREIGN KEY ("tag_id") REFERENCES "tags" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,13 @@
+-- CreateTable
+CREATE TABLE "location_path_mappings" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_id" INTEGER NOT NULL,
+    "node_id" INTEGER NOT NULL,
+    "path" TEXT NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "location_path_mappings_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "locations" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "location_path_mappings_node_id_fkey" FOREIGN KEY ("node_id") REFERENCES "nodes" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "location_path_mappings_location_id_node_id_key" ON "location_path_mappings"("location_id", "node_id");
@@ -0,0 +1,10 @@
+-- CreateTable
+CREATE TABLE "saved_searches" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "name" TEXT NOT NULL,
+    "filter" TEXT NOT NULL,
+    "sort_by" INTEGER NOT NULL DEFAULT 0,
+    "sort_descending" BOOLEAN NOT NULL DEFAULT false,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,16 @@
+-- CreateTable
+CREATE TABLE "file_links" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "from_file_id" INTEGER NOT NULL,
+    "to_file_id" INTEGER NOT NULL,
+    "kind" INTEGER NOT NULL DEFAULT 0,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "file_links_from_file_id_fkey" FOREIGN KEY ("from_file_id") REFERENCES "files" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "file_links_to_file_id_fkey" FOREIGN KEY ("to_file_id") REFERENCES "files" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "file_links_from_file_id_to_file_id_kind_key" ON "file_links"("from_file_id", "to_file_id", "kind");
+
+-- CreateIndex
+CREATE INDEX "file_links_to_file_id_idx" ON "file_links"("to_file_id");
@@ -0,0 +1,7 @@
+-- CreateTable
+CREATE TABLE "history" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "operation" TEXT NOT NULL,
+    "undone" BOOLEAN NOT NULL DEFAULT false,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
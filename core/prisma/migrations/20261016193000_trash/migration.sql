@@ -0,0 +1,14 @@
+-- CreateTable
+CREATE TABLE "trashed_entries" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_id" INTEGER NOT NULL,
+    "original_path" TEXT NOT NULL,
+    "trash_name" TEXT NOT NULL,
+    "is_dir" BOOLEAN NOT NULL DEFAULT false,
+    "file_paths" TEXT NOT NULL,
+    "date_trashed" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "trashed_entries_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "locations" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "trashed_entries_location_id_original_path_idx" ON "trashed_entries"("location_id", "original_path");
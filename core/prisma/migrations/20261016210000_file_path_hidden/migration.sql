@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "file_paths" ADD COLUMN "hidden" BOOLEAN NOT NULL DEFAULT false;
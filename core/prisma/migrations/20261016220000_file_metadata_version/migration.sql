@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "files" ADD COLUMN "metadata_version" INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,20 @@
+-- AlterTable
+ALTER TABLE "locations" ADD COLUMN "versioning_enabled" BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE "locations" ADD COLUMN "versions_max_count" INTEGER;
+ALTER TABLE "locations" ADD COLUMN "versions_quota" BIGINT;
+
+-- CreateTable
+CREATE TABLE "file_snapshots" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_id" INTEGER NOT NULL,
+    "file_path_id" INTEGER NOT NULL,
+    "materialized_path" TEXT NOT NULL,
+    "chunks" TEXT NOT NULL,
+    "size_in_bytes" TEXT NOT NULL,
+    "date_modified" DATETIME NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "file_snapshots_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "locations" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "file_snapshots_file_path_id_idx" ON "file_snapshots"("file_path_id");
@@ -0,0 +1,11 @@
+-- CreateTable
+CREATE TABLE "audit_log" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "node_id" INTEGER NOT NULL,
+    "operation" INTEGER NOT NULL,
+    "command" TEXT NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE INDEX "audit_log_date_created_idx" ON "audit_log"("date_created");
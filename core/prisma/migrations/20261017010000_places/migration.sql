@@ -0,0 +1,41 @@
+-- CreateTable
+CREATE TABLE "places" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "name" TEXT,
+    "country_code" TEXT,
+    "latitude" REAL NOT NULL,
+    "longitude" REAL NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- RedefineTables
+PRAGMA foreign_keys=OFF;
+CREATE TABLE "new_media_data" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pixel_width" INTEGER,
+    "pixel_height" INTEGER,
+    "longitude" REAL,
+    "latitude" REAL,
+    "fps" INTEGER,
+    "capture_device_make" TEXT,
+    "capture_device_model" TEXT,
+    "capture_device_software" TEXT,
+    "duration_seconds" INTEGER,
+    "codecs" TEXT,
+    "streams" INTEGER,
+    "bitrate" INTEGER,
+    "title" TEXT,
+    "artist" TEXT,
+    "album" TEXT,
+    "track_number" INTEGER,
+    "has_album_art" BOOLEAN NOT NULL DEFAULT false,
+    "place_id" INTEGER,
+    CONSTRAINT "media_data_id_fkey" FOREIGN KEY ("id") REFERENCES "files" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "media_data_place_id_fkey" FOREIGN KEY ("place_id") REFERENCES "places" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+INSERT INTO "new_media_data" ("album", "artist", "bitrate", "capture_device_make", "capture_device_model", "capture_device_software", "codecs", "duration_seconds", "fps", "has_album_art", "id", "latitude", "longitude", "pixel_height", "pixel_width", "streams", "title", "track_number") SELECT "album", "artist", "bitrate", "capture_device_make", "capture_device_model", "capture_device_software", "codecs", "duration_seconds", "fps", "has_album_art", "id", "latitude", "longitude", "pixel_height", "pixel_width", "streams", "title", "track_number" FROM "media_data";
+DROP TABLE "media_data";
+ALTER TABLE "new_media_data" RENAME TO "media_data";
+CREATE INDEX "media_data_place_id_idx" ON "media_data"("place_id");
+PRAGMA foreign_key_check;
+PRAGMA foreign_keys=ON;
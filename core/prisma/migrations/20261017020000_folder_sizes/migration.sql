@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "file_paths" ADD COLUMN "folder_size" TEXT;
+ALTER TABLE "file_paths" ADD COLUMN "folder_size_stale" BOOLEAN NOT NULL DEFAULT true;
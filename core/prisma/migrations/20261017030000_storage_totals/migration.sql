@@ -0,0 +1,15 @@
+-- AlterTable
+ALTER TABLE "locations" ADD COLUMN "storage_stale" BOOLEAN NOT NULL DEFAULT true;
+
+-- CreateTable
+CREATE TABLE "storage_totals" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_id" INTEGER NOT NULL,
+    "extension" TEXT NOT NULL,
+    "bytes" TEXT NOT NULL DEFAULT '0',
+    "file_count" INTEGER NOT NULL DEFAULT 0,
+    CONSTRAINT "storage_totals_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "locations" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "storage_totals_location_id_idx" ON "storage_totals"("location_id");
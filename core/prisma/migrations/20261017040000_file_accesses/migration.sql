@@ -0,0 +1,18 @@
+-- CreateTable
+CREATE TABLE "file_accesses" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "file_id" INTEGER NOT NULL,
+    "node_id" INTEGER NOT NULL,
+    "open_count" INTEGER NOT NULL DEFAULT 0,
+    "preview_count" INTEGER NOT NULL DEFAULT 0,
+    "score" REAL NOT NULL DEFAULT 0,
+    "date_accessed" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "file_accesses_file_id_fkey" FOREIGN KEY ("file_id") REFERENCES "files" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "file_accesses_node_id_fkey" FOREIGN KEY ("node_id") REFERENCES "nodes" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "file_accesses_file_id_node_id_key" ON "file_accesses"("file_id", "node_id");
+
+-- CreateIndex
+CREATE INDEX "file_accesses_date_accessed_idx" ON "file_accesses"("date_accessed");
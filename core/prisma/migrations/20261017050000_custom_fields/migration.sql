@@ -0,0 +1,25 @@
+-- CreateTable
+CREATE TABLE "custom_fields" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT NOT NULL,
+    "kind" INTEGER NOT NULL,
+    "options" TEXT,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateTable
+CREATE TABLE "custom_fields_on_files" (
+    "field_id" INTEGER NOT NULL,
+    "file_id" INTEGER NOT NULL,
+    "value" TEXT NOT NULL,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    PRIMARY KEY ("field_id", "file_id"),
+    CONSTRAINT "custom_fields_on_files_field_id_fkey" FOREIGN KEY ("field_id") REFERENCES "custom_fields" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "custom_fields_on_files_file_id_fkey" FOREIGN KEY ("file_id") REFERENCES "files" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "custom_fields_pub_id_key" ON "custom_fields"("pub_id");
@@ -0,0 +1,23 @@
+-- CreateTable
+CREATE TABLE "notes" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "file_id" INTEGER NOT NULL,
+    "body" TEXT NOT NULL,
+    "author" BLOB NOT NULL,
+    "author_name" TEXT NOT NULL,
+    "modified_by" BLOB NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_deleted" DATETIME,
+    CONSTRAINT "notes_file_id_fkey" FOREIGN KEY ("file_id") REFERENCES "files" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "notes_pub_id_key" ON "notes"("pub_id");
+
+-- CreateIndex
+CREATE INDEX "notes_file_id_idx" ON "notes"("file_id");
+
+-- CreateIndex
+CREATE INDEX "notes_date_modified_idx" ON "notes"("date_modified");
@@ -0,0 +1,28 @@
+-- CreateTable
+CREATE TABLE "shares" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "token" TEXT NOT NULL,
+    "file_path_id" INTEGER,
+    "name" TEXT NOT NULL,
+    "is_dir" BOOLEAN NOT NULL,
+    "target" TEXT NOT NULL,
+    "status" INTEGER NOT NULL DEFAULT 0,
+    "key" BLOB NOT NULL,
+    "salt" BLOB,
+    "protected" BOOLEAN NOT NULL DEFAULT false,
+    "link" TEXT,
+    "download_count" INTEGER NOT NULL DEFAULT 0,
+    "expires_at" DATETIME NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "shares_file_path_id_fkey" FOREIGN KEY ("file_path_id") REFERENCES "file_paths" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "shares_pub_id_key" ON "shares"("pub_id");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "shares_token_key" ON "shares"("token");
+
+-- CreateIndex
+CREATE INDEX "shares_status_expires_at_idx" ON "shares"("status", "expires_at");
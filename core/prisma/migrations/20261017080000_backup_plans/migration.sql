@@ -0,0 +1,17 @@
+-- CreateTable
+CREATE TABLE "backup_plans" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT NOT NULL,
+    "location_id" INTEGER NOT NULL,
+    "target" TEXT NOT NULL,
+    "deletion_policy" INTEGER NOT NULL DEFAULT 0,
+    "interval_hours" INTEGER,
+    "last_run_at" DATETIME,
+    "last_error" TEXT,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "backup_plans_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "locations" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "backup_plans_pub_id_key" ON "backup_plans"("pub_id");
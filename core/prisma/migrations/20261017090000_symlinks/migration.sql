@@ -0,0 +1,12 @@
+-- AlterTable
+ALTER TABLE "file_paths" ADD COLUMN "is_symlink" BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE "file_paths" ADD COLUMN "link_target" TEXT;
+ALTER TABLE "file_paths" ADD COLUMN "broken_link" BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE "file_paths" ADD COLUMN "inode" BIGINT;
+ALTER TABLE "file_paths" ADD COLUMN "device" BIGINT;
+
+-- AlterTable
+ALTER TABLE "locations" ADD COLUMN "symlink_policy" INTEGER NOT NULL DEFAULT 0;
+
+-- CreateIndex
+CREATE INDEX "file_paths_device_inode_idx" ON "file_paths"("device", "inode");
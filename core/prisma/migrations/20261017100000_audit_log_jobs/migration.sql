@@ -0,0 +1,16 @@
+-- RedefineTables
+PRAGMA foreign_keys=OFF;
+CREATE TABLE "new_audit_log" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "node_id" INTEGER NOT NULL,
+    "operation" INTEGER NOT NULL,
+    "command" TEXT,
+    "job" TEXT,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO "new_audit_log" ("command", "date_created", "id", "node_id", "operation") SELECT "command", "date_created", "id", "node_id", "operation" FROM "audit_log";
+DROP TABLE "audit_log";
+ALTER TABLE "new_audit_log" RENAME TO "audit_log";
+CREATE INDEX "audit_log_date_created_idx" ON "audit_log"("date_created");
+PRAGMA foreign_key_check;
+PRAGMA foreign_keys=ON;
@@ -0,0 +1,152 @@
+//! Embed the Spacedrive core in a Rust application.
+//!
+//! [`Spacedrive`] runs a node in the background of the current Tokio runtime, [`Client`]
+//! sends it the same queries and commands the desktop and web apps use, and
+//! [`Spacedrive::subscribe`] streams the events it emits.
+//!
+//! ```no_run
+//! use sd_sdk::{ClientQuery, CoreResponse, Spacedrive};
+//!
+//! # async fn run() -> sd_sdk::Result<()> {
+//! let node = Spacedrive::start("/var/lib/spacedrive").await;
+//!
+//! if let CoreResponse::GetLibraries(libraries) =
+//! 	node.client().query(ClientQuery::GetLibraries).await?
+//! {
+//! 	println!("{} libraries", libraries.len());
+//! }
+//!
+//! node.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Stability
+//!
+//! This crate follows semver. Everything exported here, including the types in [`types`],
+//! only changes incompatibly with a new major version. New variants may be added to the
+//! query, command, response and event enums in minor versions, so match them with a
+//! wildcard arm. The internal modules of the core are not reachable from here on purpose.
+
+use sdcore::{Node, NodeController};
+use std::path::Path;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use uuid::Uuid;
+
+pub use sdcore::{
+	init_logger, ClientCommand, ClientQuery, CoreError, CoreEvent, CoreResponse, LibraryCommand,
+	LibraryQuery,
+};
+
+/// The types carried by queries, commands, responses and events.
+pub mod types {
+	pub use sdcore::api::*;
+}
+
+pub type Result<T> = std::result::Result<T, CoreError>;
+
+// events are dropped for subscribers which fall further behind than this
+const EVENT_CAPACITY: usize = 1024;
+
+/// A running node, stopped with [`Spacedrive::shutdown`].
+pub struct Spacedrive {
+	client: Client,
+	events: broadcast::Sender<CoreEvent>,
+	shutdown_tx: oneshot::Sender<()>,
+	shutdown_completion_rx: oneshot::Receiver<()>,
+}
+
+impl Spacedrive {
+	/// Starts a node keeping its libraries and configuration in `data_dir`.
+	pub async fn start(data_dir: impl AsRef<Path>) -> Self {
+		Self::run(Node::new(data_dir).await)
+	}
+
+	/// Starts a node which keeps everything in memory and leaves nothing behind once it
+	/// is shut down.
+	pub async fn start_in_memory() -> Self {
+		Self::run(Node::new_in_memory().await)
+	}
+
+	fn run(
+		(controller, mut event_rx, node, shutdown_completion_rx): (
+			NodeController,
+			mpsc::Receiver<CoreEvent>,
+			Node,
+			oneshot::Receiver<()>,
+		),
+	) -> Self {
+		let (shutdown_tx, shutdown_rx) = oneshot::channel();
+		tokio::spawn(node.start(shutdown_rx));
+
+		let (events, _) = broadcast::channel(EVENT_CAPACITY);
+		let event_tx = events.clone();
+		tokio::spawn(async move {
+			while let Some(event) = event_rx.recv().await {
+				// no one is subscribed, which is fine
+				event_tx.send(event).ok();
+			}
+		});
+
+		Self {
+			client: Client { controller },
+			events,
+			shutdown_tx,
+			shutdown_completion_rx,
+		}
+	}
+
+	/// A client for the node, which can be cloned and sent to other tasks.
+	pub fn client(&self) -> Client {
+		self.client.clone()
+	}
+
+	/// Receives the events emitted by the node from now on.
+	pub fn subscribe(&self) -> broadcast::Receiver<CoreEvent> {
+		self.events.subscribe()
+	}
+
+	/// Pauses the running jobs so they resume on the next start, and waits for the node to
+	/// stop. Clients fail with [`CoreError::Query`] afterwards.
+	pub async fn shutdown(self) {
+		self.shutdown_tx.send(()).ok();
+		self.shutdown_completion_rx.await.ok();
+	}
+}
+
+/// Sends queries and commands to a node.
+#[derive(Clone)]
+pub struct Client {
+	controller: NodeController,
+}
+
+impl Client {
+	pub async fn query(&self, query: ClientQuery) -> Result<CoreResponse> {
+		self.controller.query(query).await
+	}
+
+	pub async fn command(&self, command: ClientCommand) -> Result<CoreResponse> {
+		self.controller.command(command).await
+	}
+
+	pub async fn library_query(
+		&self,
+		library_id: Uuid,
+		query: LibraryQuery,
+	) -> Result<CoreResponse> {
+		self.query(ClientQuery::LibraryQuery { library_id, query })
+			.await
+	}
+
+	pub async fn library_command(
+		&self,
+		library_id: Uuid,
+		command: LibraryCommand,
+	) -> Result<CoreResponse> {
+		self.command(ClientCommand::LibraryCommand {
+			library_id,
+			command,
+		})
+		.await
+	}
+}
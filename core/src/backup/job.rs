@@ -0,0 +1,322 @@
+use super::{
+	get_plan,
+	manifest::{read_manifest, write_manifest, ArchivedEntry, BackupManifest, ManifestEntry},
+	send_invalidate_query, BackupError, BackupPlan, DeletionPolicy, ARCHIVE_DIR_NAME,
+};
+use crate::{
+	file::cas::generate_cas_id,
+	job::{JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::LibraryContext,
+	prisma::{backup_plan, file_path},
+	sys::get_location,
+};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, HashSet},
+	path::PathBuf,
+};
+use tokio::fs;
+
+pub const MIRROR_BACKUP_JOB_NAME: &str = "mirror_backup";
+
+pub struct MirrorBackupJob {}
+
+// MirrorBackupJobInit copies the files of the location of the plan which changed since the
+// manifest of the target was written, comparing their cas ids and modification times, then
+// removes or archives the files the location no longer has.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MirrorBackupJobInit {
+	pub plan_id: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MirrorBackupJobData {
+	plan: BackupPlan,
+	location_path: PathBuf,
+	// what the target holds as the run goes, by path
+	entries: HashMap<String, ManifestEntry>,
+	archived: Vec<ArchivedEntry>,
+	// the WebDAV directories created by the run
+	collections: HashSet<String>,
+	date_started: DateTime<Utc>,
+	copied: usize,
+	bytes_copied: u64,
+	removed: usize,
+	failed: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum MirrorBackupJobStep {
+	Copy {
+		path: String,
+		date_modified: DateTime<Utc>,
+	},
+	Remove {
+		path: String,
+	},
+}
+
+async fn prepare(
+	ctx: &LibraryContext,
+	plan_id: i32,
+) -> Result<(MirrorBackupJobData, Vec<MirrorBackupJobStep>), BackupError> {
+	let plan = get_plan(ctx, plan_id).await?;
+	let location_path = get_location(ctx, plan.location_id)
+		.await?
+		.path
+		.ok_or(BackupError::LocationUnavailable(plan.location_id))?;
+
+	let previous = read_manifest(&plan.target).await?;
+	if let Some(previous) = &previous {
+		// removing what another plan backed up there would be a disaster
+		if previous.plan_id != plan.pub_id {
+			return Err(BackupError::TargetTaken);
+		}
+	}
+	let (entries, archived) = previous
+		.map(|previous| {
+			(
+				previous
+					.entries
+					.into_iter()
+					.map(|entry| (entry.path.clone(), entry))
+					.collect::<HashMap<_, _>>(),
+				previous.archived,
+			)
+		})
+		.unwrap_or_default();
+
+	let file_paths = ctx
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(plan.location_id)),
+			file_path::is_dir::equals(false),
+		])
+		.with(file_path::file::fetch())
+		.exec()
+		.await?;
+
+	let mut paths = HashSet::new();
+	let mut steps = vec![];
+	for file_path in file_paths {
+		let date_modified = file_path.date_modified.into();
+		let cas_id = file_path.file.flatten().map(|file| file.cas_id);
+		let unchanged = entries
+			.get(&file_path.materialized_path)
+			.map_or(false, |entry| {
+				entry.date_modified == date_modified
+					&& cas_id
+						.as_ref()
+						.map_or(true, |cas_id| *cas_id == entry.cas_id)
+			});
+		if !unchanged {
+			steps.push(MirrorBackupJobStep::Copy {
+				path: file_path.materialized_path.clone(),
+				date_modified,
+			});
+		}
+		paths.insert(file_path.materialized_path);
+	}
+	// after the copies, so a file moved within the location is on the target at all times
+	for path in entries.keys().filter(|path| !paths.contains(*path)) {
+		steps.push(MirrorBackupJobStep::Remove { path: path.clone() });
+	}
+
+	Ok((
+		MirrorBackupJobData {
+			plan,
+			location_path,
+			entries,
+			archived,
+			collections: HashSet::new(),
+			date_started: Utc::now(),
+			copied: 0,
+			bytes_copied: 0,
+			removed: 0,
+			failed: 0,
+		},
+		steps,
+	))
+}
+
+async fn set_last_error(ctx: &LibraryContext, plan_id: i32, last_error: Option<String>) {
+	let updated = ctx
+		.db
+		.backup_plan()
+		.find_unique(backup_plan::id::equals(plan_id))
+		.update(vec![backup_plan::last_error::set(last_error)])
+		.exec()
+		.await;
+	if let Err(e) = updated {
+		error!("Failed to update backup plan {}: {:#?}", plan_id, e);
+	}
+	send_invalidate_query(ctx).await;
+}
+
+async fn copy(data: &mut MirrorBackupJobData, path: &str) -> Result<(String, u64), BackupError> {
+	let source = data.location_path.join(path);
+	let size = fs::metadata(&source).await?.len();
+	data.plan
+		.target
+		.put(path, &source, &mut data.collections)
+		.await?;
+	Ok((generate_cas_id(source, size).await?, size))
+}
+
+async fn remove(data: &mut MirrorBackupJobData, path: &str) -> Result<(), BackupError> {
+	let entry = match data.entries.get(path) {
+		Some(entry) => entry.clone(),
+		None => return Ok(()),
+	};
+
+	match data.plan.deletion_policy {
+		DeletionPolicy::Mirror => data.plan.target.remove(path).await?,
+		// gone from the target already, there is nothing left to archive
+		DeletionPolicy::Archive if data.plan.target.size(path).await?.is_none() => {}
+		DeletionPolicy::Archive => {
+			let archived_path = format!(
+				"{}/{}/{}",
+				ARCHIVE_DIR_NAME,
+				data.date_started.format("%Y-%m-%d_%H%M%S"),
+				path
+			);
+			data.plan
+				.target
+				.rename(path, &archived_path, &mut data.collections)
+				.await?;
+			data.archived.push(ArchivedEntry {
+				path: entry.path,
+				archived_path,
+				cas_id: entry.cas_id,
+				size_in_bytes: entry.size_in_bytes,
+				date_archived: Utc::now(),
+			});
+		}
+	}
+
+	data.entries.remove(path);
+	Ok(())
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for MirrorBackupJob {
+	type Init = MirrorBackupJobInit;
+	type Data = MirrorBackupJobData;
+	type Step = MirrorBackupJobStep;
+
+	fn name(&self) -> &'static str {
+		MIRROR_BACKUP_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let (data, steps) = match prepare(&library_ctx, state.init.plan_id).await {
+			Ok(prepared) => prepared,
+			Err(e) => {
+				set_last_error(&library_ctx, state.init.plan_id, Some(e.to_string())).await;
+				return Err(e.into());
+			}
+		};
+
+		info!(
+			"Backing up location {} with {} changes",
+			data.plan.location_id,
+			steps.len()
+		);
+		ctx.progress(vec![JobReportUpdate::TaskCount(steps.len())]);
+
+		state.data = Some(data);
+		state.steps = steps.into_iter().collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let data = state.data.as_mut().unwrap();
+
+		match &state.steps[0] {
+			MirrorBackupJobStep::Copy {
+				path,
+				date_modified,
+			} => {
+				ctx.progress(vec![JobReportUpdate::Message(format!("Copying {}", path))]);
+				match copy(data, path).await {
+					Ok((cas_id, size)) => {
+						data.entries.insert(
+							path.clone(),
+							ManifestEntry {
+								path: path.clone(),
+								cas_id,
+								size_in_bytes: size,
+								date_modified: *date_modified,
+							},
+						);
+						data.copied += 1;
+						data.bytes_copied += size;
+					}
+					Err(e) => {
+						error!("Failed to back up {}: {:#?}", path, e);
+						data.failed += 1;
+					}
+				}
+			}
+			MirrorBackupJobStep::Remove { path } => match remove(data, path).await {
+				Ok(()) => data.removed += 1,
+				Err(e) => {
+					error!("Failed to remove {} from the backup: {:#?}", path, e);
+					data.failed += 1;
+				}
+			},
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let data = state.data.take().unwrap();
+
+		let manifest = BackupManifest::new(
+			data.plan.pub_id,
+			library_ctx.id,
+			data.entries.into_values().collect(),
+			data.archived,
+		);
+		if let Err(e) = write_manifest(&data.plan.target, &manifest).await {
+			set_last_error(&library_ctx, data.plan.id, Some(e.to_string())).await;
+			return Err(e.into());
+		}
+
+		info!(
+			"Backed up location {}: {} files copied ({} bytes), {} removed, {} failed",
+			data.plan.location_id, data.copied, data.bytes_copied, data.removed, data.failed
+		);
+		set_last_error(
+			&library_ctx,
+			data.plan.id,
+			(data.failed > 0).then(|| format!("{} files couldn't be backed up", data.failed)),
+		)
+		.await;
+
+		Ok(())
+	}
+}
@@ -0,0 +1,154 @@
+use super::{get_plan, BackupError, BackupTarget};
+use crate::{file::cas::generate_cas_id, library::LibraryContext};
+use chrono::{DateTime, Utc};
+use data_encoding::HEXLOWER;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use uuid::Uuid;
+
+// written at the root of the target by every run
+pub const MANIFEST_NAME: &str = ".spacedrive-backup.json";
+
+// A file of the location as it was backed up
+#[derive(Debug, Clone, Serialize, Deserialize, TS, PartialEq, Eq)]
+#[ts(export)]
+pub struct ManifestEntry {
+	// within the location, and the target
+	pub path: String,
+	// of the contents copied, which the library may not have identified yet
+	pub cas_id: String,
+	pub size_in_bytes: u64,
+	pub date_modified: DateTime<Utc>,
+}
+
+// A file removed from the location, kept in the archive directory of the target
+#[derive(Debug, Clone, Serialize, Deserialize, TS, PartialEq, Eq)]
+#[ts(export)]
+pub struct ArchivedEntry {
+	pub path: String,
+	pub archived_path: String,
+	pub cas_id: String,
+	pub size_in_bytes: u64,
+	pub date_archived: DateTime<Utc>,
+}
+
+// BackupManifest is the state of a backup as its latest run left it, stored with the backup so it
+// can be checked without the library
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BackupManifest {
+	pub plan_id: Uuid,
+	pub library_id: Uuid,
+	pub date_created: DateTime<Utc>,
+	// in the order of their paths
+	pub entries: Vec<ManifestEntry>,
+	pub archived: Vec<ArchivedEntry>,
+	// blake3 of the entries and archived entries, see BackupManifest::compute_checksum
+	pub checksum: String,
+}
+
+impl BackupManifest {
+	pub fn new(
+		plan_id: Uuid,
+		library_id: Uuid,
+		mut entries: Vec<ManifestEntry>,
+		archived: Vec<ArchivedEntry>,
+	) -> Self {
+		entries.sort_by(|a, b| a.path.cmp(&b.path));
+		let mut manifest = Self {
+			plan_id,
+			library_id,
+			date_created: Utc::now(),
+			entries,
+			archived,
+			checksum: String::new(),
+		};
+		manifest.checksum = manifest.compute_checksum();
+		manifest
+	}
+
+	fn compute_checksum(&self) -> String {
+		let data = serde_json::to_vec(&(&self.entries, &self.archived)).unwrap_or_default();
+		HEXLOWER.encode(blake3::hash(&data).as_bytes())
+	}
+}
+
+// What checking a backup against its manifest found
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BackupVerification {
+	pub date_created: DateTime<Utc>,
+	// the manifest wasn't changed since it was written
+	pub checksum_valid: bool,
+	pub checked: usize,
+	pub missing: Vec<String>,
+	// files of another size, or other contents on volume targets
+	pub mismatched: Vec<String>,
+}
+
+// a manifest which doesn't parse is as good as none, the next run backs everything up again
+pub(super) async fn read_manifest(
+	target: &BackupTarget,
+) -> Result<Option<BackupManifest>, BackupError> {
+	Ok(target
+		.get_bytes(MANIFEST_NAME)
+		.await?
+		.and_then(|data| serde_json::from_slice(&data).ok()))
+}
+
+pub(super) async fn write_manifest(
+	target: &BackupTarget,
+	manifest: &BackupManifest,
+) -> Result<(), BackupError> {
+	target
+		.put_bytes(MANIFEST_NAME, serde_json::to_vec_pretty(manifest)?)
+		.await
+}
+
+// verify_backup checks every file of the manifest is on the target with its size. The contents
+// of files on volumes are identified again and compared too, which remote targets would have to
+// download them for.
+pub async fn verify_backup(
+	ctx: &LibraryContext,
+	plan_id: i32,
+) -> Result<BackupVerification, BackupError> {
+	let plan = get_plan(ctx, plan_id).await?;
+	let manifest = read_manifest(&plan.target)
+		.await?
+		.ok_or(BackupError::ManifestNotFound)?;
+
+	let mut verification = BackupVerification {
+		date_created: Utc::now(),
+		checksum_valid: manifest.checksum == manifest.compute_checksum(),
+		checked: 0,
+		missing: vec![],
+		mismatched: vec![],
+	};
+
+	let files = manifest
+		.entries
+		.iter()
+		.map(|entry| (&entry.path, entry.size_in_bytes, &entry.cas_id))
+		.chain(
+			manifest
+				.archived
+				.iter()
+				.map(|entry| (&entry.archived_path, entry.size_in_bytes, &entry.cas_id)),
+		);
+	for (path, size, cas_id) in files {
+		verification.checked += 1;
+		match plan.target.size(path).await? {
+			None => verification.missing.push(path.clone()),
+			Some(found) if found != size => verification.mismatched.push(path.clone()),
+			Some(_) => {
+				if let BackupTarget::Volume { path: root } = &plan.target {
+					if &generate_cas_id(root.join(path), size).await? != cas_id {
+						verification.mismatched.push(path.clone());
+					}
+				}
+			}
+		}
+	}
+
+	Ok(verification)
+}
@@ -0,0 +1,395 @@
+use crate::{
+	job::Job,
+	library::{LibraryContext, LibraryManager},
+	prisma::{self, backup_plan, location},
+	share::{ShareError, ShareTarget},
+	sys::{get_location, SysError},
+	ClientQuery, CoreEvent, LibraryQuery,
+};
+use chrono::{DateTime, Duration, Utc};
+use filetime::FileTime;
+use int_enum::IntEnum;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashSet,
+	io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+use thiserror::Error;
+use tokio::fs;
+use ts_rs::TS;
+use uuid::Uuid;
+
+mod job;
+mod manifest;
+
+pub use job::{MirrorBackupJob, MirrorBackupJobInit, MIRROR_BACKUP_JOB_NAME};
+pub use manifest::{
+	verify_backup, ArchivedEntry, BackupManifest, BackupVerification, ManifestEntry,
+};
+
+// plans run every few hours at most, checking every few minutes starts them close enough to when
+// they are due
+const BACKUP_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+// where files removed from the location are kept on the target, by the date of the run
+pub const ARCHIVE_DIR_NAME: &str = ".spacedrive-archive";
+
+// Where a location is backed up to
+#[derive(Debug, Clone, Serialize, Deserialize, TS, PartialEq, Eq)]
+#[serde(tag = "key", content = "data")]
+#[ts(export)]
+pub enum BackupTarget {
+	// a directory of this node, usually on another volume
+	Volume { path: PathBuf },
+	// a WebDAV server or an S3 bucket, as shares are uploaded to
+	Remote { target: ShareTarget },
+}
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum DeletionPolicy {
+	// files removed from the location are removed from the backup
+	Mirror = 0,
+	// files removed from the location are moved to the archive directory of the target
+	Archive = 1,
+}
+
+// A location backed up to a target by the mirror backup job, on demand or every `interval_hours`
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BackupPlan {
+	pub id: i32,
+	pub pub_id: Uuid,
+	pub name: String,
+	pub location_id: i32,
+	pub target: BackupTarget,
+	pub deletion_policy: DeletionPolicy,
+	// only run on demand if unset
+	pub interval_hours: Option<i32>,
+	// when the latest run started
+	pub last_run_at: Option<DateTime<Utc>>,
+	// why the latest run failed, or how many files it couldn't back up
+	pub last_error: Option<String>,
+	pub date_created: DateTime<Utc>,
+}
+
+impl TryFrom<backup_plan::Data> for BackupPlan {
+	type Error = BackupError;
+
+	fn try_from(data: backup_plan::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: data.id,
+			pub_id: Uuid::from_slice(&data.pub_id).unwrap(),
+			name: data.name,
+			location_id: data.location_id,
+			target: serde_json::from_str(&data.target)?,
+			deletion_policy: DeletionPolicy::from_int(data.deletion_policy)
+				.unwrap_or(DeletionPolicy::Archive),
+			interval_hours: data.interval_hours,
+			last_run_at: data.last_run_at.map(Into::into),
+			last_error: data.last_error,
+			date_created: data.date_created.into(),
+		})
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+	#[error("Backup plan not found (id: {0})")]
+	PlanNotFound(i32),
+	#[error("Location is not available on this node (id: {0})")]
+	LocationUnavailable(i32),
+	#[error("Backups can't be stored on the gateway of this node")]
+	GatewayTarget,
+	#[error("A location can't be backed up within itself, or to a directory containing it")]
+	TargetInsideLocation,
+	#[error("The target holds the backup of another plan")]
+	TargetTaken,
+	#[error("The backup has no manifest yet")]
+	ManifestNotFound,
+	#[error("Backup target error: {0}")]
+	Target(#[from] ShareError),
+	#[error("I/O error: {0}")]
+	IOError(#[from] io::Error),
+	#[error("Invalid backup target or manifest: {0}")]
+	InvalidJson(#[from] serde_json::Error),
+	#[error("System error: {0}")]
+	SystemError(#[from] SysError),
+	#[error("Database error")]
+	DatabaseError(#[from] prisma::QueryError),
+}
+
+impl BackupTarget {
+	// copies a file of the location to the target as `object`, keeping its modification time on
+	// volumes. `collections` are the WebDAV directories already created by the run.
+	async fn put(
+		&self,
+		object: &str,
+		source: &Path,
+		collections: &mut HashSet<String>,
+	) -> Result<(), BackupError> {
+		match self {
+			Self::Volume { path } => {
+				let target = path.join(object);
+				if let Some(parent) = target.parent() {
+					fs::create_dir_all(parent).await?;
+				}
+				// copied next to the target first, so an interrupted copy never replaces a good one
+				let partial = target.with_file_name(format!(
+					"{}.sdpart",
+					target.file_name().unwrap_or_default().to_string_lossy()
+				));
+				fs::copy(source, &partial).await?;
+				let modified = FileTime::from_last_modification_time(&fs::metadata(source).await?);
+				filetime::set_file_mtime(&partial, modified)?;
+				fs::rename(&partial, &target).await?;
+			}
+			Self::Remote { target } => {
+				create_parent(target, object, collections).await?;
+				target.upload(object, source).await?;
+			}
+		}
+		Ok(())
+	}
+
+	async fn put_bytes(&self, object: &str, data: Vec<u8>) -> Result<(), BackupError> {
+		match self {
+			Self::Volume { path } => {
+				fs::create_dir_all(path).await?;
+				let partial = path.join(format!("{}.sdpart", object));
+				fs::write(&partial, data).await?;
+				fs::rename(&partial, path.join(object)).await?;
+			}
+			Self::Remote { target } => target.upload_bytes(object, data).await?,
+		}
+		Ok(())
+	}
+
+	async fn get_bytes(&self, object: &str) -> Result<Option<Vec<u8>>, BackupError> {
+		match self {
+			Self::Volume { path } => match fs::read(path.join(object)).await {
+				Ok(data) => Ok(Some(data)),
+				Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+				Err(e) => Err(e.into()),
+			},
+			Self::Remote { target } => Ok(target.download(object).await?),
+		}
+	}
+
+	// already gone from the target counts as removed
+	async fn remove(&self, object: &str) -> Result<(), BackupError> {
+		match self {
+			Self::Volume { path } => match fs::remove_file(path.join(object)).await {
+				Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+				_ => Ok(()),
+			},
+			Self::Remote { target } => Ok(target.delete(object).await?),
+		}
+	}
+
+	async fn rename(
+		&self,
+		from: &str,
+		to: &str,
+		collections: &mut HashSet<String>,
+	) -> Result<(), BackupError> {
+		match self {
+			Self::Volume { path } => {
+				let target = path.join(to);
+				if let Some(parent) = target.parent() {
+					fs::create_dir_all(parent).await?;
+				}
+				fs::rename(path.join(from), target).await?;
+			}
+			Self::Remote { target } => {
+				create_parent(target, to, collections).await?;
+				target.rename(from, to).await?;
+			}
+		}
+		Ok(())
+	}
+
+	// the size of a file on the target, none if it isn't there
+	async fn size(&self, object: &str) -> Result<Option<u64>, BackupError> {
+		match self {
+			Self::Volume { path } => match fs::metadata(path.join(object)).await {
+				Ok(metadata) => Ok(Some(metadata.len())),
+				Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+				Err(e) => Err(e.into()),
+			},
+			Self::Remote { target } => Ok(target.size(object).await?),
+		}
+	}
+}
+
+async fn create_parent(
+	target: &ShareTarget,
+	object: &str,
+	collections: &mut HashSet<String>,
+) -> Result<(), ShareError> {
+	if let Some((parent, _)) = object.rsplit_once('/') {
+		if !collections.contains(parent) {
+			target.create_collection(parent).await?;
+			collections.insert(parent.to_string());
+		}
+	}
+	Ok(())
+}
+
+async fn send_invalidate_query(ctx: &LibraryContext) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetBackupPlans,
+	}))
+	.await;
+}
+
+async fn get_plan(ctx: &LibraryContext, id: i32) -> Result<BackupPlan, BackupError> {
+	ctx.db
+		.backup_plan()
+		.find_unique(backup_plan::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(BackupError::PlanNotFound(id))?
+		.try_into()
+}
+
+pub async fn create_plan(
+	ctx: &LibraryContext,
+	name: String,
+	location_id: i32,
+	target: BackupTarget,
+	deletion_policy: DeletionPolicy,
+	interval_hours: Option<i32>,
+) -> Result<BackupPlan, BackupError> {
+	match &target {
+		BackupTarget::Remote { target } if !target.is_remote() => {
+			return Err(BackupError::GatewayTarget)
+		}
+		BackupTarget::Volume { path } => {
+			if let Some(location_path) = get_location(ctx, location_id).await?.path {
+				if path.starts_with(&location_path) || location_path.starts_with(path) {
+					return Err(BackupError::TargetInsideLocation);
+				}
+			}
+		}
+		_ => {}
+	}
+
+	let plan = ctx
+		.db
+		.backup_plan()
+		.create(
+			backup_plan::pub_id::set(Uuid::new_v4().as_bytes().to_vec()),
+			backup_plan::name::set(name),
+			backup_plan::location::link(location::id::equals(location_id)),
+			backup_plan::target::set(serde_json::to_string(&target)?),
+			vec![
+				backup_plan::deletion_policy::set(deletion_policy.int_value()),
+				backup_plan::interval_hours::set(interval_hours.map(|hours| hours.max(1))),
+			],
+		)
+		.exec()
+		.await?;
+
+	send_invalidate_query(ctx).await;
+
+	plan.try_into()
+}
+
+// the files already backed up stay on the target
+pub async fn delete_plan(ctx: &LibraryContext, id: i32) -> Result<(), BackupError> {
+	ctx.db
+		.backup_plan()
+		.find_unique(backup_plan::id::equals(id))
+		.delete()
+		.exec()
+		.await?
+		.ok_or(BackupError::PlanNotFound(id))?;
+
+	send_invalidate_query(ctx).await;
+
+	Ok(())
+}
+
+pub async fn get_plans(ctx: &LibraryContext) -> Result<Vec<BackupPlan>, BackupError> {
+	ctx.db
+		.backup_plan()
+		.find_many(vec![])
+		.exec()
+		.await?
+		.into_iter()
+		.map(TryInto::try_into)
+		.collect()
+}
+
+// the manifest the latest run left on the target
+pub async fn get_manifest(ctx: &LibraryContext, id: i32) -> Result<BackupManifest, BackupError> {
+	let plan = get_plan(ctx, id).await?;
+	manifest::read_manifest(&plan.target)
+		.await?
+		.ok_or(BackupError::ManifestNotFound)
+}
+
+// starts the job backing up the location of the plan now
+pub async fn run_plan(ctx: &LibraryContext, id: i32) -> Result<(), BackupError> {
+	let plan = get_plan(ctx, id).await?;
+	if get_location(ctx, plan.location_id).await?.path.is_none() {
+		return Err(BackupError::LocationUnavailable(plan.location_id));
+	}
+
+	ctx.db
+		.backup_plan()
+		.find_unique(backup_plan::id::equals(id))
+		.update(vec![backup_plan::last_run_at::set(Some(Utc::now().into()))])
+		.exec()
+		.await?;
+	ctx.spawn_job(Job::new(
+		MirrorBackupJobInit { plan_id: id },
+		Box::new(MirrorBackupJob {}),
+	))
+	.await;
+
+	send_invalidate_query(ctx).await;
+
+	Ok(())
+}
+
+// runs the plans of every library once they are due, skipping locations not on this node
+pub async fn watch_backups(library_manager: Arc<LibraryManager>) {
+	loop {
+		for ctx in library_manager.get_all_libraries_ctx().await {
+			let plans = match get_plans(&ctx).await {
+				Ok(plans) => plans,
+				Err(e) => {
+					error!("Failed to read backup plans: {:#?}", e);
+					continue;
+				}
+			};
+
+			for plan in plans {
+				let due = match (plan.interval_hours, plan.last_run_at) {
+					(None, _) => false,
+					(Some(_), None) => true,
+					(Some(hours), Some(last_run_at)) => {
+						last_run_at + Duration::hours(hours as i64) <= Utc::now()
+					}
+				};
+				let available = matches!(
+					get_location(&ctx, plan.location_id).await,
+					Ok(location) if location.path.is_some()
+				);
+				if due && available {
+					if let Err(e) = run_plan(&ctx, plan.id).await {
+						error!("Failed to start backup plan {}: {:#?}", plan.id, e);
+					}
+				}
+			}
+		}
+
+		tokio::time::sleep(BACKUP_CHECK_INTERVAL).await;
+	}
+}
@@ -0,0 +1,318 @@
+use crate::{
+	library::LibraryContext,
+	prisma::{self, custom_field, custom_field_on_file, file},
+	ClientQuery, CoreEvent, LibraryQuery,
+};
+use chrono::{DateTime, Utc};
+use int_enum::IntEnum;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ts_rs::TS;
+use uuid::Uuid;
+
+// ratings go from 1 to this many stars
+const MAX_RATING: u8 = 5;
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum CustomFieldKind {
+	Text = 0,
+	Number = 1,
+	Date = 2,
+	// one of the options of the field
+	Enum = 3,
+	Rating = 4,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CustomField {
+	pub id: i32,
+	pub pub_id: Uuid,
+	pub name: String,
+	pub kind: CustomFieldKind,
+	// empty unless the field is an enum
+	pub options: Vec<String>,
+	pub date_created: DateTime<Utc>,
+	pub date_modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS, PartialEq)]
+#[serde(tag = "key", content = "data")]
+#[ts(export)]
+pub enum CustomFieldValue {
+	Text(String),
+	Number(f64),
+	Date(DateTime<Utc>),
+	Enum(String),
+	Rating(u8),
+}
+
+impl CustomFieldValue {
+	fn kind(&self) -> CustomFieldKind {
+		match self {
+			Self::Text(_) => CustomFieldKind::Text,
+			Self::Number(_) => CustomFieldKind::Number,
+			Self::Date(_) => CustomFieldKind::Date,
+			Self::Enum(_) => CustomFieldKind::Enum,
+			Self::Rating(_) => CustomFieldKind::Rating,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CustomFieldOnFile {
+	pub field_id: i32,
+	pub file_id: i32,
+	pub value: CustomFieldValue,
+	pub date_modified: DateTime<Utc>,
+}
+
+#[derive(Error, Debug)]
+pub enum CustomFieldError {
+	#[error("Custom field not found (id: {0})")]
+	FieldNotFound(i32),
+	#[error("Unknown custom field kind: {0}")]
+	UnknownKind(i32),
+	#[error("An enum field needs at least one option")]
+	MissingOptions,
+	#[error("Value isn't a {0:?}")]
+	KindMismatch(CustomFieldKind),
+	#[error("Not an option of the field: {0}")]
+	InvalidOption(String),
+	#[error("Ratings go from 1 to 5, got {0}")]
+	InvalidRating(u8),
+	#[error("Invalid custom field value: {0}")]
+	InvalidValue(#[from] serde_json::Error),
+	#[error("Database error")]
+	DatabaseError(#[from] prisma::QueryError),
+}
+
+impl TryFrom<custom_field::Data> for CustomField {
+	type Error = CustomFieldError;
+
+	fn try_from(data: custom_field::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: data.id,
+			pub_id: Uuid::from_slice(&data.pub_id).unwrap(),
+			name: data.name,
+			kind: CustomFieldKind::from_int(data.kind)
+				.map_err(|_| CustomFieldError::UnknownKind(data.kind))?,
+			options: match data.options {
+				Some(options) => serde_json::from_str(&options)?,
+				None => vec![],
+			},
+			date_created: data.date_created.into(),
+			date_modified: data.date_modified.into(),
+		})
+	}
+}
+
+impl TryFrom<custom_field_on_file::Data> for CustomFieldOnFile {
+	type Error = CustomFieldError;
+
+	fn try_from(data: custom_field_on_file::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			field_id: data.field_id,
+			file_id: data.file_id,
+			value: serde_json::from_str(&data.value)?,
+			date_modified: data.date_modified.into(),
+		})
+	}
+}
+
+// options are trimmed, empty and repeated ones dropped
+fn clean_options(options: Vec<String>) -> Vec<String> {
+	let mut cleaned: Vec<String> = vec![];
+	for option in options {
+		let option = option.trim().to_string();
+		if !option.is_empty() && !cleaned.contains(&option) {
+			cleaned.push(option);
+		}
+	}
+	cleaned
+}
+
+async fn send_invalidate_fields(ctx: &LibraryContext) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetCustomFields,
+	}))
+	.await;
+}
+
+async fn get_field(ctx: &LibraryContext, id: i32) -> Result<CustomField, CustomFieldError> {
+	ctx.db
+		.custom_field()
+		.find_unique(custom_field::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(CustomFieldError::FieldNotFound(id))?
+		.try_into()
+}
+
+pub async fn create_field(
+	ctx: &LibraryContext,
+	name: String,
+	kind: CustomFieldKind,
+	options: Vec<String>,
+) -> Result<CustomField, CustomFieldError> {
+	let options = clean_options(options);
+	let options = match kind {
+		CustomFieldKind::Enum if options.is_empty() => {
+			return Err(CustomFieldError::MissingOptions)
+		}
+		CustomFieldKind::Enum => Some(serde_json::to_string(&options)?),
+		_ => None,
+	};
+
+	let field = ctx
+		.db
+		.custom_field()
+		.create(
+			custom_field::pub_id::set(Uuid::new_v4().as_bytes().to_vec()),
+			custom_field::name::set(name),
+			custom_field::kind::set(kind.int_value()),
+			vec![custom_field::options::set(options)],
+		)
+		.exec()
+		.await?;
+
+	send_invalidate_fields(ctx).await;
+
+	field.try_into()
+}
+
+// the kind of a field can't change, values already set would no longer match it. Values of
+// options removed from an enum field are kept, for the client to show as no longer valid.
+pub async fn update_field(
+	ctx: &LibraryContext,
+	id: i32,
+	name: Option<String>,
+	options: Option<Vec<String>>,
+) -> Result<(), CustomFieldError> {
+	let field = get_field(ctx, id).await?;
+
+	let mut params = vec![custom_field::date_modified::set(Utc::now().into())];
+	if let Some(name) = name {
+		params.push(custom_field::name::set(name));
+	}
+	if let (Some(options), CustomFieldKind::Enum) = (options, field.kind) {
+		let options = clean_options(options);
+		if options.is_empty() {
+			return Err(CustomFieldError::MissingOptions);
+		}
+		params.push(custom_field::options::set(Some(serde_json::to_string(
+			&options,
+		)?)));
+	}
+
+	ctx.db
+		.custom_field()
+		.find_unique(custom_field::id::equals(id))
+		.update(params)
+		.exec()
+		.await?;
+
+	send_invalidate_fields(ctx).await;
+
+	Ok(())
+}
+
+// the values of every file for the field go with it
+pub async fn delete_field(ctx: &LibraryContext, id: i32) -> Result<(), CustomFieldError> {
+	ctx.db
+		.custom_field()
+		.find_unique(custom_field::id::equals(id))
+		.delete()
+		.exec()
+		.await?
+		.ok_or(CustomFieldError::FieldNotFound(id))?;
+
+	send_invalidate_fields(ctx).await;
+
+	Ok(())
+}
+
+pub async fn get_fields(ctx: &LibraryContext) -> Result<Vec<CustomField>, CustomFieldError> {
+	ctx.db
+		.custom_field()
+		.find_many(vec![])
+		.exec()
+		.await?
+		.into_iter()
+		.map(TryInto::try_into)
+		.collect()
+}
+
+// set_value gives a file a value of the field, or clears it with `None`
+pub async fn set_value(
+	ctx: &LibraryContext,
+	file_id: i32,
+	field_id: i32,
+	value: Option<CustomFieldValue>,
+) -> Result<(), CustomFieldError> {
+	let field = get_field(ctx, field_id).await?;
+
+	if let Some(value) = &value {
+		if value.kind() != field.kind {
+			return Err(CustomFieldError::KindMismatch(field.kind));
+		}
+		match value {
+			CustomFieldValue::Enum(option) if !field.options.contains(option) => {
+				return Err(CustomFieldError::InvalidOption(option.clone()));
+			}
+			CustomFieldValue::Rating(rating) if *rating == 0 || *rating > MAX_RATING => {
+				return Err(CustomFieldError::InvalidRating(*rating));
+			}
+			_ => {}
+		}
+	}
+
+	ctx.db
+		.custom_field_on_file()
+		.find_many(vec![
+			custom_field_on_file::field_id::equals(field_id),
+			custom_field_on_file::file_id::equals(file_id),
+		])
+		.delete()
+		.exec()
+		.await?;
+
+	if let Some(value) = value {
+		ctx.db
+			.custom_field_on_file()
+			.create(
+				custom_field_on_file::field::link(custom_field::id::equals(field_id)),
+				custom_field_on_file::file::link(file::id::equals(file_id)),
+				custom_field_on_file::value::set(serde_json::to_string(&value)?),
+				vec![],
+			)
+			.exec()
+			.await?;
+	}
+
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetFileCustomFields { file_id },
+	}))
+	.await;
+
+	Ok(())
+}
+
+pub async fn get_file_values(
+	ctx: &LibraryContext,
+	file_id: i32,
+) -> Result<Vec<CustomFieldOnFile>, CustomFieldError> {
+	ctx.db
+		.custom_field_on_file()
+		.find_many(vec![custom_field_on_file::file_id::equals(file_id)])
+		.exec()
+		.await?
+		.into_iter()
+		.map(TryInto::try_into)
+		.collect()
+}
@@ -0,0 +1,286 @@
+use crate::{
+	encode::{encode_thumbnail, THUMBNAIL_CACHE_DIR_NAME},
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	node::UsageCategory,
+	prisma::{file, media_data},
+	sys::get_location,
+	CoreEvent,
+};
+use ffmpeg_next::{format, media, Dictionary};
+use log::{error, info};
+use prisma_client_rust::{prisma_models::PrismaValue, raw::Raw};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::{fs, task::block_in_place};
+
+pub const AUDIO_METADATA_JOB_NAME: &str = "audio_metadata_extractor";
+
+pub static AUDIO_EXTENSIONS: [&str; 8] = ["mp3", "flac", "ogg", "opus", "m4a", "aac", "wav", "wma"];
+
+// Tags and stream info of an audio file, as found in ID3, Vorbis comments, FLAC or MP4 atoms
+#[derive(Debug, Default)]
+pub struct AudioMetadata {
+	pub title: Option<String>,
+	pub artist: Option<String>,
+	pub album: Option<String>,
+	pub track_number: Option<i32>,
+	pub duration_seconds: Option<i32>,
+	pub codecs: Option<String>,
+	pub bitrate: Option<i32>,
+	pub streams: i32,
+	// encoded bytes of the embedded cover, usually a jpeg or png
+	pub album_art: Option<Vec<u8>>,
+}
+
+pub struct AudioMetadataJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AudioMetadataJobInit {
+	pub location_id: i32,
+	pub background: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AudioMetadataJobState {
+	location_path: PathBuf,
+	thumbnail_dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AudioMetadataJobStep {
+	file_id: i32,
+	cas_id: String,
+	materialized_path: String,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for AudioMetadataJob {
+	type Init = AudioMetadataJobInit;
+	type Data = AudioMetadataJobState;
+	type Step = AudioMetadataJobStep;
+
+	fn name(&self) -> &'static str {
+		AUDIO_METADATA_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let location = get_location(&library_ctx, state.init.location_id).await?;
+
+		let thumbnail_dir = library_ctx
+			.config()
+			.data_directory()
+			.join(THUMBNAIL_CACHE_DIR_NAME)
+			.join(location.id.to_string());
+		fs::create_dir_all(&thumbnail_dir).await?;
+
+		let extensions = AUDIO_EXTENSIONS
+			.iter()
+			.map(|ext| format!("'{}'", ext))
+			.collect::<Vec<_>>()
+			.join(", ");
+
+		// files that already have media data were extracted on a previous scan
+		let files = library_ctx
+			.db
+			._query_raw::<AudioMetadataJobStep>(Raw::new(
+				&format!(
+					"SELECT files.id AS file_id, files.cas_id AS cas_id,
+					MIN(file_paths.materialized_path) AS materialized_path
+					FROM file_paths INNER JOIN files ON files.id = file_paths.file_id
+					LEFT JOIN media_data ON media_data.id = files.id
+					WHERE file_paths.location_id = {{}} AND media_data.id IS NULL
+					AND LOWER(file_paths.extension) IN ({})
+					GROUP BY files.id",
+					extensions
+				),
+				vec![PrismaValue::Int(location.id as i64)],
+			))
+			.await?;
+
+		info!(
+			"Found {} audio files without metadata in location {}",
+			files.len(),
+			location.id
+		);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(files.len()),
+			JobReportUpdate::Message(format!("Reading metadata of {} audio files", files.len())),
+		]);
+
+		state.data = Some(AudioMetadataJobState {
+			location_path: location.path.unwrap_or_default(),
+			thumbnail_dir,
+		});
+		state.steps = files.into_iter().collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Reading metadata of {}",
+			step.materialized_path
+		))]);
+
+		let path = data.location_path.join(&step.materialized_path);
+		let metadata = match block_in_place(|| extract_audio_metadata(&path)) {
+			Ok(metadata) => metadata,
+			Err(e) => {
+				error!(
+					"Error reading audio metadata of {}: {:?}",
+					step.materialized_path, e
+				);
+				return Ok(());
+			}
+		};
+
+		let library_ctx = ctx.library_ctx();
+		library_ctx
+			.db
+			.media_data()
+			.create(
+				media_data::id::set(step.file_id),
+				vec![
+					media_data::title::set(metadata.title),
+					media_data::artist::set(metadata.artist),
+					media_data::album::set(metadata.album),
+					media_data::track_number::set(metadata.track_number),
+					media_data::duration_seconds::set(metadata.duration_seconds),
+					media_data::codecs::set(metadata.codecs),
+					media_data::bitrate::set(metadata.bitrate),
+					media_data::streams::set(Some(metadata.streams)),
+					media_data::has_album_art::set(metadata.album_art.is_some()),
+				],
+			)
+			.exec()
+			.await?;
+
+		// the cover becomes the thumbnail of the file, like the first frame of a video would
+		if let Some(album_art) = metadata.album_art {
+			let output_path = data.thumbnail_dir.join(&step.cas_id).with_extension("webp");
+
+			let webp = block_in_place(|| {
+				image::load_from_memory(&album_art)
+					.map_err(Into::into)
+					.and_then(|img| encode_thumbnail(&img))
+					.map_err(|e| error!("Error generating album art thumb {:?}", e))
+					.ok()
+			});
+
+			if let Some(webp) = webp {
+				fs::write(&output_path, &webp).await?;
+				library_ctx
+					.record_usage(UsageCategory::Thumbnails, webp.len() as u64)
+					.await;
+				library_ctx
+					.db
+					.file()
+					.find_unique(file::id::equals(step.file_id))
+					.update(vec![file::has_thumbnail::set(true)])
+					.exec()
+					.await?;
+
+				if !state.init.background {
+					library_ctx
+						.emit(CoreEvent::NewThumbnail {
+							cas_id: step.cas_id.clone(),
+						})
+						.await;
+				}
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		_ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		info!(
+			"Finished reading audio metadata for location {}",
+			state.init.location_id
+		);
+		Ok(())
+	}
+}
+
+pub fn extract_audio_metadata(path: impl AsRef<Path>) -> Result<AudioMetadata, ffmpeg_next::Error> {
+	ffmpeg_next::init()?;
+
+	let mut input = format::input(&path)?;
+
+	let audio_stream = input.streams().best(media::Type::Audio).map(|s| s.index());
+	let cover_stream = input
+		.streams()
+		.find(|s| {
+			s.disposition()
+				.contains(format::stream::Disposition::ATTACHED_PIC)
+		})
+		.map(|s| s.index());
+
+	// mp3 and flac store tags on the container, ogg and opus on the audio stream
+	let container_tags = input.metadata().to_owned();
+	let stream_tags = audio_stream
+		.and_then(|index| input.stream(index))
+		.map(|stream| stream.metadata().to_owned())
+		.unwrap_or_else(Dictionary::new);
+	let tag = |key: &str| {
+		container_tags
+			.get(key)
+			.or_else(|| stream_tags.get(key))
+			.map(|value| value.trim().to_string())
+			.filter(|value| !value.is_empty())
+	};
+
+	let mut metadata = AudioMetadata {
+		title: tag("title"),
+		artist: tag("artist").or_else(|| tag("album_artist")),
+		album: tag("album"),
+		// eg: "3" or "3/12"
+		track_number: tag("track").and_then(|track| track.split('/').next()?.parse().ok()),
+		duration_seconds: (input.duration() > 0)
+			.then(|| (input.duration() / i64::from(ffmpeg_next::ffi::AV_TIME_BASE)) as i32),
+		codecs: Some(
+			input
+				.streams()
+				.map(|stream| stream.parameters().id().name().to_string())
+				.collect::<Vec<_>>()
+				.join(","),
+		),
+		bitrate: (input.bit_rate() > 0).then(|| input.bit_rate() as i32),
+		streams: input.streams().count() as i32,
+		album_art: None,
+	};
+
+	// the attached picture is sent as the first packet of its stream
+	if let Some(cover_stream) = cover_stream {
+		metadata.album_art = input
+			.packets()
+			.find(|(stream, _)| stream.index() == cover_stream)
+			.and_then(|(_, packet)| packet.data().map(<[u8]>::to_vec));
+	}
+
+	Ok(metadata)
+}
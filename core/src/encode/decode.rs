@@ -0,0 +1,170 @@
+use crate::encode::{decode_raw, is_raw, IMAGE_EXTENSIONS, RAW_EXTENSIONS};
+use exif::{In, Tag};
+use image::DynamicImage;
+use std::{error::Error, fs::File, io::BufReader, path::Path};
+
+// decoded with libheif, when core is built with the `heif` feature
+pub static HEIF_EXTENSIONS: [&str; 2] = ["heic", "heif"];
+// decoded with libjxl, when core is built with the `jxl` feature
+pub static JXL_EXTENSIONS: [&str; 1] = ["jxl"];
+
+// the extensions of the formats needing a native library which this build of core decodes
+pub fn native_image_extensions() -> Vec<&'static str> {
+	#[allow(unused_mut)]
+	let mut extensions = vec![];
+	#[cfg(feature = "heif")]
+	extensions.extend(HEIF_EXTENSIONS);
+	#[cfg(feature = "jxl")]
+	extensions.extend(JXL_EXTENSIONS);
+	extensions
+}
+
+// whether the thumbnailer picks up images with this extension, the extensions of camera
+// formats being matched in any case
+pub fn is_thumbnailable_image(extension: &str) -> bool {
+	let lowercase = extension.to_lowercase();
+	IMAGE_EXTENSIONS.contains(&extension)
+		|| RAW_EXTENSIONS.contains(&lowercase.as_str())
+		|| native_image_extensions().contains(&lowercase.as_str())
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+	path.extension()
+		.map(|ext| ext.to_string_lossy().to_lowercase())
+		.map_or(false, |ext| extensions.contains(&ext.as_str()))
+}
+
+// opens any image the thumbnailer handles upright. Camera raws are developed, and HEIF and
+// JPEG XL go through their native libraries, which apply the orientation of the image
+// themselves.
+pub fn open_image(path: impl AsRef<Path>) -> Result<DynamicImage, Box<dyn Error>> {
+	let path = path.as_ref();
+
+	if is_raw(path) {
+		decode_raw(path)
+	} else if has_extension(path, &HEIF_EXTENSIONS) {
+		decode_heif(path)
+	} else if has_extension(path, &JXL_EXTENSIONS) {
+		decode_jxl(path)
+	} else {
+		let img = image::open(path)?;
+		Ok(match exif_orientation(path) {
+			Some(orientation) => apply_orientation(img, orientation),
+			None => img,
+		})
+	}
+}
+
+// the orientation tag cameras write instead of rotating the pixels, 1 being upright
+fn exif_orientation(path: &Path) -> Option<u32> {
+	let file = File::open(path).ok()?;
+	let exif = exif::Reader::new()
+		.read_from_container(&mut BufReader::new(file))
+		.ok()?;
+
+	exif.get_field(Tag::Orientation, In::PRIMARY)?
+		.value
+		.get_uint(0)
+}
+
+fn apply_orientation(img: DynamicImage, orientation: u32) -> DynamicImage {
+	match orientation {
+		2 => img.fliph(),
+		3 => img.rotate180(),
+		4 => img.flipv(),
+		// transposed
+		5 => img.rotate90().fliph(),
+		6 => img.rotate90(),
+		// transversed
+		7 => img.rotate270().fliph(),
+		8 => img.rotate270(),
+		_ => img,
+	}
+}
+
+#[cfg(feature = "heif")]
+fn decode_heif(path: &Path) -> Result<DynamicImage, Box<dyn Error>> {
+	use image::RgbImage;
+	use libheif_rs::{ColorSpace, HeifContext, RgbChroma};
+
+	let ctx = HeifContext::read_from_file(&path.to_string_lossy())?;
+	let handle = ctx.primary_image_handle()?;
+	// libheif applies the rotation and mirroring of the container, and brings the 10 and 12
+	// bit images of phones and cameras down to 8 bits per channel
+	let image = handle.decode(ColorSpace::Rgb(RgbChroma::Rgb), false)?;
+	let plane = image
+		.planes()
+		.interleaved
+		.ok_or("decoded HEIF image has no interleaved plane")?;
+
+	// rows are padded up to the stride
+	let row_length = plane.width as usize * 3;
+	let mut pixels = Vec::with_capacity(row_length * plane.height as usize);
+	for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+		pixels.extend_from_slice(&row[..row_length]);
+	}
+
+	if let Some(profile) = handle.color_profile_raw() {
+		pixels = icc_to_srgb(&profile.data, pixels)?;
+	}
+
+	Ok(DynamicImage::ImageRgb8(
+		RgbImage::from_raw(plane.width, plane.height, pixels)
+			.ok_or("decoded HEIF image has the wrong size")?,
+	))
+}
+
+#[cfg(not(feature = "heif"))]
+fn decode_heif(_path: &Path) -> Result<DynamicImage, Box<dyn Error>> {
+	Err("core was built without HEIF support".into())
+}
+
+// phones shoot in display p3, which looks washed out when shown as srgb
+#[cfg(feature = "heif")]
+fn icc_to_srgb(icc: &[u8], pixels: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+	use lcms2::{Intent, PixelFormat, Profile, Transform};
+
+	let transform = Transform::new(
+		&Profile::new_icc(icc)?,
+		PixelFormat::RGB_8,
+		&Profile::new_srgb(),
+		PixelFormat::RGB_8,
+		Intent::Perceptual,
+	)?;
+
+	let input = pixels
+		.chunks_exact(3)
+		.map(|pixel| [pixel[0], pixel[1], pixel[2]])
+		.collect::<Vec<[u8; 3]>>();
+	let mut output = vec![[0u8; 3]; input.len()];
+	transform.transform_pixels(&input, &mut output);
+
+	Ok(output.into_iter().flatten().collect())
+}
+
+#[cfg(feature = "jxl")]
+fn decode_jxl(path: &Path) -> Result<DynamicImage, Box<dyn Error>> {
+	use image::{GrayAlphaImage, GrayImage, RgbImage, RgbaImage};
+	use jpegxl_rs::decoder_builder;
+
+	let data = std::fs::read(path)?;
+	// libjxl applies the orientation, and converts the pixels to srgb for images it encoded
+	// itself
+	let decoder = decoder_builder().build()?;
+	let (metadata, pixels) = decoder.decode_with::<u8>(&data)?;
+	let (width, height) = (metadata.width, metadata.height);
+
+	let img = match (metadata.num_color_channels, metadata.has_alpha_channel) {
+		(1, false) => GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+		(1, true) => GrayAlphaImage::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8),
+		(_, false) => RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+		(_, true) => RgbaImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8),
+	};
+
+	Ok(img.ok_or("decoded JPEG XL image has the wrong size")?)
+}
+
+#[cfg(not(feature = "jxl"))]
+fn decode_jxl(_path: &Path) -> Result<DynamicImage, Box<dyn Error>> {
+	Err("core was built without JPEG XL support".into())
+}
@@ -0,0 +1,247 @@
+use crate::{
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	places,
+	prisma::media_data,
+	sys::get_location,
+};
+use exif::{In, Tag, Value};
+use log::{error, info};
+use prisma_client_rust::{prisma_models::PrismaValue, raw::Raw};
+use serde::{Deserialize, Serialize};
+use std::{
+	fs::File,
+	io::BufReader,
+	path::{Path, PathBuf},
+};
+use tokio::task::block_in_place;
+
+pub const IMAGE_METADATA_JOB_NAME: &str = "image_metadata_extractor";
+
+// the formats which can carry exif, kamadak-exif reads all of them, and raws built on tiff
+pub static EXIF_EXTENSIONS: [&str; 13] = [
+	"jpg", "jpeg", "tif", "tiff", "heic", "heif", "png", "webp", "cr2", "nef", "arw", "dng", "pef",
+];
+
+// What the camera recorded about a photo
+#[derive(Debug, Default)]
+pub struct ExifData {
+	pub pixel_width: Option<i32>,
+	pub pixel_height: Option<i32>,
+	// decimal degrees, negative south of the equator and west of Greenwich
+	pub latitude: Option<f64>,
+	pub longitude: Option<f64>,
+	pub make: Option<String>,
+	pub model: Option<String>,
+	pub software: Option<String>,
+}
+
+pub struct ImageMetadataJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ImageMetadataJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ImageMetadataJobState {
+	location_path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageMetadataJobStep {
+	file_id: i32,
+	materialized_path: String,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ImageMetadataJob {
+	type Init = ImageMetadataJobInit;
+	type Data = ImageMetadataJobState;
+	type Step = ImageMetadataJobStep;
+
+	fn name(&self) -> &'static str {
+		IMAGE_METADATA_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let location = get_location(&library_ctx, state.init.location_id).await?;
+
+		let extensions = EXIF_EXTENSIONS
+			.iter()
+			.map(|ext| format!("'{}'", ext))
+			.collect::<Vec<_>>()
+			.join(", ");
+
+		// files that already have media data were read on a previous scan
+		let files = library_ctx
+			.db
+			._query_raw::<ImageMetadataJobStep>(Raw::new(
+				&format!(
+					"SELECT files.id AS file_id,
+					MIN(file_paths.materialized_path) AS materialized_path
+					FROM file_paths INNER JOIN files ON files.id = file_paths.file_id
+					LEFT JOIN media_data ON media_data.id = files.id
+					WHERE file_paths.location_id = {{}} AND media_data.id IS NULL
+					AND LOWER(file_paths.extension) IN ({})
+					GROUP BY files.id",
+					extensions
+				),
+				vec![PrismaValue::Int(location.id as i64)],
+			))
+			.await?;
+
+		info!(
+			"Found {} images without metadata in location {}",
+			files.len(),
+			location.id
+		);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(files.len()),
+			JobReportUpdate::Message(format!("Reading metadata of {} images", files.len())),
+		]);
+
+		state.data = Some(ImageMetadataJobState {
+			location_path: location.path.unwrap_or_default(),
+		});
+		state.steps = files.into_iter().collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Reading metadata of {}",
+			step.materialized_path
+		))]);
+
+		let path = data.location_path.join(&step.materialized_path);
+		let metadata = match block_in_place(|| extract_exif(&path)) {
+			Ok(metadata) => metadata,
+			// the image is still recorded, so it isn't read again on every scan
+			Err(exif::Error::NotFound(_)) => ExifData::default(),
+			Err(e) => {
+				error!("Error reading exif of {}: {:?}", step.materialized_path, e);
+				return Ok(());
+			}
+		};
+
+		ctx.library_ctx()
+			.db
+			.media_data()
+			.create(
+				media_data::id::set(step.file_id),
+				vec![
+					media_data::pixel_width::set(metadata.pixel_width),
+					media_data::pixel_height::set(metadata.pixel_height),
+					media_data::latitude::set(metadata.latitude),
+					media_data::longitude::set(metadata.longitude),
+					media_data::capture_device_make::set(metadata.make),
+					media_data::capture_device_model::set(metadata.model),
+					media_data::capture_device_software::set(metadata.software),
+				],
+			)
+			.exec()
+			.await?;
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		info!(
+			"Finished reading image metadata for location {}",
+			state.init.location_id
+		);
+
+		// photos are grouped once all of them are read, the dataset is only loaded once then
+		let library_ctx = ctx.library_ctx();
+		if library_ctx.config.places {
+			if let Err(e) = places::assign_places(&library_ctx).await {
+				error!("Failed to group photos into places: {:#?}", e);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+pub fn extract_exif(path: impl AsRef<Path>) -> Result<ExifData, exif::Error> {
+	let file = File::open(path)?;
+	let exif = exif::Reader::new().read_from_container(&mut BufReader::new(file))?;
+
+	let uint = |tag| {
+		exif.get_field(tag, In::PRIMARY)
+			.and_then(|field| field.value.get_uint(0))
+			.map(|value| value as i32)
+	};
+	let ascii = |tag| match exif.get_field(tag, In::PRIMARY).map(|field| &field.value) {
+		Some(Value::Ascii(values)) => values
+			.first()
+			.map(|value| {
+				String::from_utf8_lossy(value)
+					.trim_matches(char::from(0))
+					.trim()
+					.to_string()
+			})
+			.filter(|value| !value.is_empty()),
+		_ => None,
+	};
+
+	Ok(ExifData {
+		pixel_width: uint(Tag::PixelXDimension).or_else(|| uint(Tag::ImageWidth)),
+		pixel_height: uint(Tag::PixelYDimension).or_else(|| uint(Tag::ImageLength)),
+		latitude: coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S'),
+		longitude: coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W'),
+		make: ascii(Tag::Make),
+		model: ascii(Tag::Model),
+		software: ascii(Tag::Software),
+	})
+}
+
+// gps coordinates are stored as degrees, minutes and seconds, with the hemisphere apart
+fn coordinate(exif: &exif::Exif, tag: Tag, ref_tag: Tag, negative_ref: u8) -> Option<f64> {
+	let degrees = match &exif.get_field(tag, In::PRIMARY)?.value {
+		Value::Rational(dms) if dms.len() == 3 => {
+			dms[0].to_f64() + dms[1].to_f64() / 60.0 + dms[2].to_f64() / 3600.0
+		}
+		_ => return None,
+	};
+	if !degrees.is_finite() {
+		return None;
+	}
+
+	let negative = match exif
+		.get_field(ref_tag, In::PRIMARY)
+		.map(|field| &field.value)
+	{
+		Some(Value::Ascii(values)) => {
+			values.first().and_then(|value| value.first()) == Some(&negative_ref)
+		}
+		_ => false,
+	};
+
+	Some(if negative { -degrees } else { degrees })
+}
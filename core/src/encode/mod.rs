@@ -1,5 +1,23 @@
+mod audio;
+mod decode;
+mod image_metadata;
 mod metadata;
+mod phash;
+mod preview;
+mod profiles;
+mod raw;
 mod thumb;
+mod thumb_requests;
+mod thumbstrip;
 
+pub use audio::*;
+pub use decode::*;
+pub use image_metadata::*;
 pub use metadata::*;
+pub use phash::*;
+pub use preview::*;
+pub use profiles::*;
+pub use raw::*;
 pub use thumb::*;
+pub use thumb_requests::*;
+pub use thumbstrip::*;
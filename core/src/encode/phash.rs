@@ -0,0 +1,32 @@
+use crate::encode::open_image;
+use image::imageops::FilterType;
+use std::{error::Error, path::Path};
+
+// the image is shrunk to 9x8, comparing each pixel with its right neighbour gives 64 bits
+static DHASH_WIDTH: u32 = 9;
+static DHASH_HEIGHT: u32 = 8;
+
+// perceptual_hash computes a difference hash (dHash) of an image, visually similar images produce
+// hashes with a small hamming distance regardless of resolution, compression or small edits
+pub fn perceptual_hash(path: impl AsRef<Path>) -> Result<u64, Box<dyn Error>> {
+	let img = open_image(path)?
+		.resize_exact(DHASH_WIDTH, DHASH_HEIGHT, FilterType::Triangle)
+		.into_luma8();
+
+	let mut hash = 0u64;
+	for y in 0..DHASH_HEIGHT {
+		for x in 0..DHASH_WIDTH - 1 {
+			hash <<= 1;
+			if img.get_pixel(x, y)[0] < img.get_pixel(x + 1, y)[0] {
+				hash |= 1;
+			}
+		}
+	}
+
+	Ok(hash)
+}
+
+// the amount of bits that differ between two perceptual hashes, 0 is visually identical
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+	(a ^ b).count_ones()
+}
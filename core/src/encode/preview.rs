@@ -0,0 +1,477 @@
+use crate::{
+	encode::VIDEO_EXTENSIONS,
+	library::LibraryContext,
+	node::UsageCategory,
+	prisma::{self, file_path},
+	sys::{get_location, SysError},
+	CoreEvent,
+};
+use ffmpeg_next::{
+	codec::{self, decoder, encoder},
+	format, frame, media, picture,
+	software::scaling::{self, Flags},
+	util::format::Pixel,
+	Dictionary, Packet, Rational,
+};
+use filetime::FileTime;
+use log::{error, info, warn};
+use std::{
+	collections::HashSet,
+	io,
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+use thiserror::Error;
+use tokio::{fs, task::spawn_blocking};
+use uuid::Uuid;
+
+pub static PREVIEW_CACHE_DIR_NAME: &str = "previews";
+// previews are evicted least recently played first once they take more than this
+const PREVIEW_CACHE_BUDGET: u64 = 4 * 1024 * 1024 * 1024;
+// fast enough for the player to not wait long on most machines, previews needn't be small
+const TRANSCODE_PRESET: &str = "veryfast";
+// containers the webview opens itself
+static PLAYABLE_CONTAINERS: [&str; 3] = ["mp4", "m4v", "webm"];
+
+#[derive(Error, Debug)]
+pub enum PreviewError {
+	#[error("File path not found (id: {0})")]
+	FilePathNotFound(i32),
+	#[error("File path is not a video (id: {0})")]
+	NotAVideo(i32),
+	#[error("File path is not identified yet (id: {0})")]
+	NotIdentified(i32),
+	#[error("Location of the file path is not available (id: {0})")]
+	LocationUnavailable(i32),
+	#[error("Failed to write preview: {0}")]
+	Ffmpeg(#[from] ffmpeg_next::Error),
+	#[error("I/O error: {0}")]
+	Io(#[from] io::Error),
+	#[error("System error: {0}")]
+	Sys(#[from] SysError),
+	#[error("Database error")]
+	DatabaseError(#[from] prisma::QueryError),
+}
+
+fn is_playable_video(id: codec::Id) -> bool {
+	match id {
+		codec::Id::H264 | codec::Id::VP8 | codec::Id::VP9 | codec::Id::AV1 => true,
+		// only the webview of macOS plays hevc
+		codec::Id::HEVC => cfg!(target_os = "macos"),
+		_ => false,
+	}
+}
+
+fn is_playable_audio(id: codec::Id) -> bool {
+	matches!(
+		id,
+		codec::Id::AAC | codec::Id::MP3 | codec::Id::OPUS | codec::Id::FLAC
+	)
+}
+
+/// PreviewRequests keeps the file paths of each library whose preview is being written, so one
+/// asked for again meanwhile is only written once.
+#[derive(Default)]
+pub struct PreviewRequests {
+	in_flight: Mutex<HashSet<(Uuid, i32)>>,
+}
+
+// get_video_preview returns the path of a version of the video the webview plays: the video
+// itself when it can already, or else a copy remuxed into fragmented mp4, with the video
+// transcoded to h264 if its codec can't be played either. Copies are cached after the cas id
+// of the video. A copy not written yet is written in the background, nothing is returned then
+// and VideoPreviewReady is emitted once it is written.
+pub async fn get_video_preview(
+	ctx: &LibraryContext,
+	file_path_id: i32,
+) -> Result<Option<PathBuf>, PreviewError> {
+	let file_path = ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.with(file_path::file::fetch())
+		.exec()
+		.await?
+		.ok_or(PreviewError::FilePathNotFound(file_path_id))?;
+
+	let extension = file_path
+		.extension
+		.clone()
+		.unwrap_or_default()
+		.to_lowercase();
+	if !VIDEO_EXTENSIONS.contains(&extension.as_str()) {
+		return Err(PreviewError::NotAVideo(file_path_id));
+	}
+	let cas_id = file_path
+		.file
+		.flatten()
+		.map(|file| file.cas_id)
+		.ok_or(PreviewError::NotIdentified(file_path_id))?;
+	let location_id = file_path
+		.location_id
+		.ok_or(PreviewError::LocationUnavailable(file_path_id))?;
+	let path = get_location(ctx, location_id)
+		.await?
+		.path
+		.ok_or(PreviewError::LocationUnavailable(file_path_id))?
+		.join(&file_path.materialized_path);
+
+	let cache_dir = ctx.config().data_directory().join(PREVIEW_CACHE_DIR_NAME);
+	let preview_path = cache_dir.join(&cas_id).with_extension("mp4");
+	if preview_path.exists() {
+		// the modification time of previews is when they were last played, for eviction
+		filetime::set_file_mtime(&preview_path, FileTime::now())?;
+		return Ok(Some(preview_path));
+	}
+
+	// only the headers of the video are read, which is quick enough to wait for
+	let playable_container = PLAYABLE_CONTAINERS.contains(&extension.as_str());
+	let probed_path = path.clone();
+	if !spawn_blocking(move || needs_preview(&probed_path, playable_container))
+		.await
+		.map_err(|e| io::Error::new(io::ErrorKind::Other, e))??
+	{
+		return Ok(Some(path));
+	}
+
+	let requests = ctx.preview_requests();
+	if !requests
+		.in_flight
+		.lock()
+		.unwrap()
+		.insert((ctx.id, file_path_id))
+	{
+		return Ok(None);
+	}
+
+	let ctx = ctx.clone();
+	tokio::spawn(async move {
+		let written =
+			match write_cached_preview(&ctx, path.clone(), &cache_dir, &preview_path).await {
+				Ok(()) => Some(preview_path),
+				Err(e) => {
+					error!("Failed to write preview of {:?}: {:#?}", path, e);
+					None
+				}
+			};
+		requests
+			.in_flight
+			.lock()
+			.unwrap()
+			.remove(&(ctx.id, file_path_id));
+
+		ctx.emit(CoreEvent::VideoPreviewReady {
+			library_id: ctx.id,
+			file_path_id,
+			path: written,
+		})
+		.await;
+	});
+
+	Ok(None)
+}
+
+async fn write_cached_preview(
+	ctx: &LibraryContext,
+	path: PathBuf,
+	cache_dir: &Path,
+	preview_path: &Path,
+) -> Result<(), PreviewError> {
+	// written aside first, so an interrupted preview is never served
+	fs::create_dir_all(cache_dir).await?;
+	let partial_path = cache_dir.join(format!("{}.part", Uuid::new_v4()));
+
+	info!("Writing preview of {:?}", path);
+	let written_path = partial_path.clone();
+	let written = spawn_blocking(move || write_preview(&path, &written_path))
+		.await
+		.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+	if let Err(e) = written {
+		fs::remove_file(&partial_path).await.ok();
+		return Err(e.into());
+	}
+	fs::rename(&partial_path, preview_path).await?;
+
+	ctx.record_usage(
+		UsageCategory::Previews,
+		fs::metadata(preview_path).await?.len(),
+	)
+	.await;
+	evict_previews(cache_dir, preview_path).await?;
+
+	Ok(())
+}
+
+fn needs_preview(path: &Path, playable_container: bool) -> Result<bool, ffmpeg_next::Error> {
+	if !playable_container {
+		return Ok(true);
+	}
+
+	ffmpeg_next::init()?;
+	let input = format::input(&path)?;
+	let unplayable = input.streams().any(|stream| {
+		let parameters = stream.parameters();
+		match parameters.medium() {
+			media::Type::Video => !is_playable_video(parameters.id()),
+			media::Type::Audio => !is_playable_audio(parameters.id()),
+			_ => false,
+		}
+	});
+
+	Ok(unplayable)
+}
+
+// removes the least recently played previews until the cache fits in its budget, keeping the
+// one just written whatever its size
+async fn evict_previews(cache_dir: &Path, keep: &Path) -> Result<(), io::Error> {
+	let mut previews = vec![];
+	let mut entries = fs::read_dir(cache_dir).await?;
+	while let Some(entry) = entries.next_entry().await? {
+		let path = entry.path();
+		if path.extension().map_or(false, |ext| ext == "mp4") {
+			let metadata = entry.metadata().await?;
+			previews.push((metadata.modified()?, metadata.len(), path));
+		}
+	}
+
+	let mut size = previews.iter().map(|(_, len, _)| len).sum::<u64>();
+	previews.sort_by_key(|(modified, _, _)| *modified);
+
+	for (_, len, path) in previews {
+		if size <= PREVIEW_CACHE_BUDGET {
+			break;
+		}
+		if path == keep {
+			continue;
+		}
+		info!("Evicting preview {:?}", path);
+		fs::remove_file(&path).await?;
+		size -= len;
+	}
+
+	Ok(())
+}
+
+enum StreamOutput {
+	Skip,
+	Copy { index: usize, time_base: Rational },
+	Transcode(Box<VideoTranscoder>),
+}
+
+// copies the playable streams into a fragmented mp4 and transcodes the video streams which
+// aren't, leaving out subtitles and audio which can't be played
+fn write_preview(input_path: &Path, output_path: &Path) -> Result<(), ffmpeg_next::Error> {
+	ffmpeg_next::init()?;
+
+	let mut input = format::input(&input_path)?;
+	let mut output = format::output_as(&output_path, "mp4")?;
+	let global_header = output
+		.format()
+		.flags()
+		.contains(format::Flags::GLOBAL_HEADER);
+
+	let mut outputs = vec![];
+	let mut index = 0;
+	for stream in input.streams() {
+		let parameters = stream.parameters();
+		let (medium, id) = (parameters.medium(), parameters.id());
+
+		let stream_output = match medium {
+			media::Type::Video if !is_playable_video(id) => StreamOutput::Transcode(Box::new(
+				VideoTranscoder::new(&stream, &mut output, index, global_header)?,
+			)),
+			media::Type::Video => {
+				add_copied_stream(&mut output, parameters)?;
+				StreamOutput::Copy {
+					index,
+					time_base: stream.time_base(),
+				}
+			}
+			media::Type::Audio if is_playable_audio(id) => {
+				add_copied_stream(&mut output, parameters)?;
+				StreamOutput::Copy {
+					index,
+					time_base: stream.time_base(),
+				}
+			}
+			media::Type::Audio => {
+				warn!(
+					"Leaving the {:?} audio of {:?} out of its preview",
+					id, input_path
+				);
+				StreamOutput::Skip
+			}
+			_ => StreamOutput::Skip,
+		};
+
+		if !matches!(stream_output, StreamOutput::Skip) {
+			index += 1;
+		}
+		outputs.push(stream_output);
+	}
+
+	let mut options = Dictionary::new();
+	// fragmented, so the player starts before reaching the end of the file
+	options.set("movflags", "frag_keyframe+empty_moov+default_base_moof");
+	output.write_header_with(options)?;
+
+	for (stream, mut packet) in input.packets() {
+		match &mut outputs[stream.index()] {
+			StreamOutput::Skip => {}
+			StreamOutput::Copy { index, time_base } => {
+				let output_time_base = output
+					.stream(*index)
+					.ok_or(ffmpeg_next::Error::StreamNotFound)?
+					.time_base();
+				packet.rescale_ts(*time_base, output_time_base);
+				packet.set_position(-1);
+				packet.set_stream(*index);
+				packet.write_interleaved(&mut output)?;
+			}
+			StreamOutput::Transcode(transcoder) => {
+				transcoder.decoder.send_packet(&packet)?;
+				transcoder.write_frames(&mut output)?;
+			}
+		}
+	}
+
+	for stream_output in &mut outputs {
+		if let StreamOutput::Transcode(transcoder) = stream_output {
+			transcoder.finish(&mut output)?;
+		}
+	}
+	output.write_trailer()?;
+
+	Ok(())
+}
+
+fn add_copied_stream(
+	output: &mut format::context::Output,
+	parameters: codec::Parameters,
+) -> Result<(), ffmpeg_next::Error> {
+	let mut stream = output.add_stream(encoder::find(codec::Id::None))?;
+	stream.set_parameters(parameters);
+	// the tag of the codec in the source container may not exist in mp4, left for the muxer
+	// to pick
+	unsafe {
+		(*stream.parameters().as_mut_ptr()).codec_tag = 0;
+	}
+	Ok(())
+}
+
+struct VideoTranscoder {
+	index: usize,
+	// of the source stream, which the encoder keeps
+	time_base: Rational,
+	decoder: decoder::Video,
+	encoder: encoder::video::Encoder,
+	// to the 8 bit 4:2:0 every h264 decoder plays, from 10 bit and other pixel formats
+	scaler: Option<scaling::Context>,
+}
+
+impl VideoTranscoder {
+	fn new(
+		stream: &format::stream::Stream,
+		output: &mut format::context::Output,
+		index: usize,
+		global_header: bool,
+	) -> Result<Self, ffmpeg_next::Error> {
+		let decoder = codec::context::Context::from_parameters(stream.parameters())?
+			.decoder()
+			.video()?;
+
+		let codec = encoder::find(codec::Id::H264).ok_or(ffmpeg_next::Error::EncoderNotFound)?;
+		let mut output_stream = output.add_stream(codec)?;
+		let mut encoder = codec::context::Context::from_parameters(output_stream.parameters())?
+			.encoder()
+			.video()?;
+		encoder.set_width(decoder.width());
+		encoder.set_height(decoder.height());
+		encoder.set_aspect_ratio(decoder.aspect_ratio());
+		encoder.set_format(Pixel::YUV420P);
+		encoder.set_frame_rate(decoder.frame_rate());
+		encoder.set_time_base(stream.time_base());
+		if global_header {
+			encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+		}
+
+		let mut options = Dictionary::new();
+		options.set("preset", TRANSCODE_PRESET);
+		let encoder = encoder.open_as_with(codec, options)?;
+		output_stream.set_parameters(&encoder);
+
+		let scaler = match decoder.format() {
+			Pixel::YUV420P => None,
+			format => Some(scaling::Context::get(
+				format,
+				decoder.width(),
+				decoder.height(),
+				Pixel::YUV420P,
+				decoder.width(),
+				decoder.height(),
+				Flags::BILINEAR,
+			)?),
+		};
+
+		Ok(Self {
+			index,
+			time_base: stream.time_base(),
+			decoder,
+			encoder,
+			scaler,
+		})
+	}
+
+	// encodes the frames decoded so far
+	fn write_frames(
+		&mut self,
+		output: &mut format::context::Output,
+	) -> Result<(), ffmpeg_next::Error> {
+		let mut decoded = frame::Video::empty();
+		while self.decoder.receive_frame(&mut decoded).is_ok() {
+			let mut frame = match &mut self.scaler {
+				Some(scaler) => {
+					let mut scaled = frame::Video::empty();
+					scaler.run(&decoded, &mut scaled)?;
+					scaled
+				}
+				None => decoded.clone(),
+			};
+			frame.set_pts(decoded.timestamp());
+			// lets the encoder place keyframes itself
+			frame.set_kind(picture::Type::None);
+
+			self.encoder.send_frame(&frame)?;
+			self.write_packets(output)?;
+		}
+
+		Ok(())
+	}
+
+	fn write_packets(
+		&mut self,
+		output: &mut format::context::Output,
+	) -> Result<(), ffmpeg_next::Error> {
+		let output_time_base = output
+			.stream(self.index)
+			.ok_or(ffmpeg_next::Error::StreamNotFound)?
+			.time_base();
+
+		let mut encoded = Packet::empty();
+		while self.encoder.receive_packet(&mut encoded).is_ok() {
+			encoded.set_stream(self.index);
+			encoded.rescale_ts(self.time_base, output_time_base);
+			encoded.write_interleaved(output)?;
+		}
+
+		Ok(())
+	}
+
+	// drains the frames held by the decoder and the encoder at the end of the stream
+	fn finish(&mut self, output: &mut format::context::Output) -> Result<(), ffmpeg_next::Error> {
+		self.decoder.send_eof()?;
+		self.write_frames(output)?;
+		self.encoder.send_eof()?;
+		self.write_packets(output)
+	}
+}
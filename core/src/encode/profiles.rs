@@ -0,0 +1,276 @@
+use crate::{
+	encode::{open_image, ThumbnailJob, ThumbnailJobInit, THUMBNAIL_CACHE_DIR_NAME},
+	job::{Job, JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	prisma::location,
+};
+use image::{imageops::FilterType, DynamicImage};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+	error::Error,
+	io::Cursor,
+	ops::Deref,
+	path::{Path, PathBuf},
+};
+use tokio::fs;
+use ts_rs::TS;
+use webp::Encoder;
+
+pub const THUMBNAIL_PROFILES_JOB_NAME: &str = "thumbnail_profiles";
+// the variants of every profile of a location are kept in this directory of its thumbnails
+static PROFILES_DIR_NAME: &str = "profiles";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, PartialEq, Eq)]
+#[ts(export)]
+pub enum ThumbnailFormat {
+	Webp,
+	Jpeg,
+	// only encoded when core is built with the `avif` feature
+	Avif,
+}
+
+impl ThumbnailFormat {
+	pub fn extension(&self) -> &'static str {
+		match self {
+			Self::Webp => "webp",
+			Self::Jpeg => "jpg",
+			Self::Avif => "avif",
+		}
+	}
+}
+
+/// ThumbnailProfile is an extra size and encoding of the thumbnail of every image, next to the default one, eg: a large variant for a detail view.
+#[derive(Debug, Clone, Serialize, Deserialize, TS, PartialEq, Eq)]
+#[ts(export)]
+pub struct ThumbnailProfile {
+	pub name: String,
+	/// size is the length of the longest side of the variant in pixels. Smaller images aren't upscaled.
+	pub size: u32,
+	pub format: ThumbnailFormat,
+	/// quality goes from 0 to 100.
+	pub quality: u8,
+}
+
+impl ThumbnailProfile {
+	/// dir_name names the directory of the variants after every setting of the profile, so changing one of them never serves variants made with the previous settings, eg: `detail@1024_avif_q60`.
+	pub fn dir_name(&self) -> String {
+		let name = self
+			.name
+			.chars()
+			.filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+			.collect::<String>();
+
+		format!(
+			"{}@{}_{}_q{}",
+			name,
+			self.size,
+			self.format.extension(),
+			self.quality.min(100)
+		)
+	}
+
+	/// path is where the variant of a file is written, `thumbnail_dir` being the thumbnail directory of its location.
+	pub fn path(&self, thumbnail_dir: impl AsRef<Path>, cas_id: &str) -> PathBuf {
+		thumbnail_dir
+			.as_ref()
+			.join(PROFILES_DIR_NAME)
+			.join(self.dir_name())
+			.join(cas_id)
+			.with_extension(self.format.extension())
+	}
+
+	pub fn encode(&self, img: &DynamicImage) -> Result<Vec<u8>, Box<dyn Error>> {
+		let img = if img.width() > self.size || img.height() > self.size {
+			img.resize(self.size, self.size, FilterType::Triangle)
+		} else {
+			img.clone()
+		};
+		let quality = self.quality.min(100);
+
+		let mut bytes = vec![];
+		match self.format {
+			ThumbnailFormat::Webp => {
+				// see encode_thumbnail for why this is copied out
+				let img = DynamicImage::ImageRgba8(img.to_rgba8());
+				bytes = Encoder::from_image(&img)?
+					.encode(quality as f32)
+					.deref()
+					.to_owned();
+			}
+			ThumbnailFormat::Jpeg => {
+				DynamicImage::ImageRgb8(img.to_rgb8()).write_to(
+					&mut Cursor::new(&mut bytes),
+					image::ImageOutputFormat::Jpeg(quality),
+				)?;
+			}
+			#[cfg(feature = "avif")]
+			ThumbnailFormat::Avif => {
+				use image::{codecs::avif::AvifEncoder, ImageEncoder};
+
+				let img = img.to_rgba8();
+				// 8 is a fast speed, the quality difference with slower ones is small at this size
+				AvifEncoder::new_with_speed_quality(&mut bytes, 8, quality).write_image(
+					img.as_raw(),
+					img.width(),
+					img.height(),
+					image::ColorType::Rgba8,
+				)?;
+			}
+			#[cfg(not(feature = "avif"))]
+			ThumbnailFormat::Avif => {
+				return Err("core was built without AVIF support".into());
+			}
+		}
+
+		Ok(bytes)
+	}
+}
+
+// writes the missing variants of an image, returning how many bytes were written
+pub(crate) async fn generate_variants(
+	path: impl AsRef<Path>,
+	thumbnail_dir: impl AsRef<Path>,
+	cas_id: &str,
+	profiles: &[ThumbnailProfile],
+) -> Result<u64, Box<dyn Error>> {
+	let missing = profiles
+		.iter()
+		.filter(|profile| !profile.path(&thumbnail_dir, cas_id).exists())
+		.collect::<Vec<_>>();
+	if missing.is_empty() {
+		return Ok(0);
+	}
+
+	// decoded once for every variant
+	let variants = tokio::task::block_in_place(|| -> Result<Vec<_>, Box<dyn Error>> {
+		let img = open_image(path)?;
+		missing
+			.iter()
+			.map(|profile| Ok((profile.path(&thumbnail_dir, cas_id), profile.encode(&img)?)))
+			.collect()
+	})?;
+
+	let mut written = 0;
+	for (variant_path, bytes) in variants {
+		if let Some(parent) = variant_path.parent() {
+			fs::create_dir_all(parent).await?;
+		}
+		fs::write(&variant_path, &bytes).await?;
+		written += bytes.len() as u64;
+	}
+
+	Ok(written)
+}
+
+pub struct ThumbnailProfilesJob {}
+
+// ThumbnailProfilesJobInit brings the variants of a library in line with its profiles, removing
+// those of profiles which changed or were removed and generating the missing ones
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ThumbnailProfilesJobInit {}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailProfilesJobStep {
+	location_id: i32,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ThumbnailProfilesJob {
+	type Init = ThumbnailProfilesJobInit;
+	type Data = ();
+	type Step = ThumbnailProfilesJobStep;
+
+	fn name(&self) -> &'static str {
+		THUMBNAIL_PROFILES_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let locations = ctx
+			.library_ctx()
+			.db
+			.location()
+			.find_many(vec![location::node_id::equals(Some(
+				ctx.library_ctx().node_local_id,
+			))])
+			.exec()
+			.await?;
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(locations.len())]);
+
+		state.steps = locations
+			.into_iter()
+			.map(|location| ThumbnailProfilesJobStep {
+				location_id: location.id,
+			})
+			.collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let step = &state.steps[0];
+		let library_ctx = ctx.library_ctx();
+
+		let profiles_dir = library_ctx
+			.config()
+			.data_directory()
+			.join(THUMBNAIL_CACHE_DIR_NAME)
+			.join(step.location_id.to_string())
+			.join(PROFILES_DIR_NAME);
+		let current = library_ctx
+			.config
+			.thumbnail_profiles
+			.iter()
+			.map(ThumbnailProfile::dir_name)
+			.collect::<Vec<_>>();
+
+		if profiles_dir.exists() {
+			let mut entries = fs::read_dir(&profiles_dir).await?;
+			while let Some(entry) = entries.next_entry().await? {
+				let dir_name = entry.file_name().to_string_lossy().to_string();
+				if !current.contains(&dir_name) {
+					info!("Removing outdated thumbnail variants {:?}", entry.path());
+					fs::remove_dir_all(entry.path()).await?;
+				}
+			}
+		}
+
+		// the thumbnailer only writes what is missing, which is the variants of new profiles
+		if !current.is_empty() {
+			library_ctx
+				.queue_job(Job::new(
+					ThumbnailJobInit {
+						location_id: step.location_id,
+						path: PathBuf::new(),
+						background: true,
+						shallow: false,
+					},
+					Box::new(ThumbnailJob {}),
+				))
+				.await;
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		_ctx: WorkerContext,
+		_state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		info!("Finished updating thumbnail variants");
+		Ok(())
+	}
+}
@@ -0,0 +1,60 @@
+use exif::{In, Tag};
+use image::{DynamicImage, RgbImage};
+use log::warn;
+use std::{error::Error, fs::File, io::BufReader, path::Path};
+
+// camera raw formats rawloader decodes, all but raf are tiff based so exif reads them too
+pub static RAW_EXTENSIONS: [&str; 8] = ["cr2", "nef", "arw", "dng", "orf", "rw2", "pef", "raf"];
+// raws are developed at this size at most, thumbnails and hashes only need a fraction of it
+static RAW_DEVELOP_SIZE: usize = 2048;
+
+pub fn is_raw(path: impl AsRef<Path>) -> bool {
+	path.as_ref()
+		.extension()
+		.map(|ext| ext.to_string_lossy().to_lowercase())
+		.map_or(false, |ext| RAW_EXTENSIONS.contains(&ext.as_str()))
+}
+
+// develops a raw with the color matrices of its camera into srgb, or falls back to the jpeg
+// preview most cameras embed when the camera or the compression isn't supported
+pub fn decode_raw(path: impl AsRef<Path>) -> Result<DynamicImage, Box<dyn Error>> {
+	let developed = imagepipe::simple_decode_8bit(&path, RAW_DEVELOP_SIZE, RAW_DEVELOP_SIZE);
+
+	match developed {
+		Ok(img) => Ok(DynamicImage::ImageRgb8(
+			RgbImage::from_raw(img.width as u32, img.height as u32, img.data)
+				.ok_or("developed raw has the wrong size")?,
+		)),
+		Err(e) => {
+			warn!(
+				"Failed to develop raw {:?}, using its preview: {}",
+				path.as_ref(),
+				e
+			);
+			embedded_preview(path)
+		}
+	}
+}
+
+// the jpeg the camera stored in the second ifd of the raw
+fn embedded_preview(path: impl AsRef<Path>) -> Result<DynamicImage, Box<dyn Error>> {
+	let file = File::open(path)?;
+	let exif = exif::Reader::new().read_from_container(&mut BufReader::new(file))?;
+
+	let offset = exif
+		.get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)
+		.and_then(|field| field.value.get_uint(0))
+		.ok_or("raw has no embedded preview")? as usize;
+	let length = exif
+		.get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)
+		.and_then(|field| field.value.get_uint(0))
+		.ok_or("raw has no embedded preview")? as usize;
+
+	// offsets are from the start of the tiff structure, which is the start of these files
+	let preview = exif
+		.buf()
+		.get(offset..offset + length)
+		.ok_or("embedded preview is out of bounds")?;
+
+	Ok(image::load_from_memory(preview)?)
+}
@@ -1,7 +1,12 @@
 use crate::{
+	encode::{
+		generate_thumbstrip, generate_variants, native_image_extensions, open_image,
+		perceptual_hash, thumbstrip_path, RAW_EXTENSIONS, VIDEO_EXTENSIONS,
+	},
 	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
 	library::LibraryContext,
-	prisma::file_path,
+	node::UsageCategory,
+	prisma::{file, file_path},
 	sys, CoreEvent,
 };
 use image::{self, imageops, DynamicImage, GenericImageView};
@@ -19,6 +24,7 @@ static THUMBNAIL_SIZE_FACTOR: f32 = 0.2;
 static THUMBNAIL_QUALITY: f32 = 30.0;
 pub static THUMBNAIL_CACHE_DIR_NAME: &str = "thumbnails";
 pub const THUMBNAIL_JOB_NAME: &str = "thumbnailer";
+pub static IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpeg", "jpg", "gif", "webp"];
 
 pub struct ThumbnailJob {}
 
@@ -27,6 +33,9 @@ pub struct ThumbnailJobInit {
 	pub location_id: i32,
 	pub path: PathBuf,
 	pub background: bool,
+	// only the files directly in `path`, not the ones below its subdirectories
+	#[serde(default)]
+	pub shallow: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -60,7 +69,7 @@ impl StatefulJob for ThumbnailJob {
 		let location = sys::get_location(&library_ctx, state.init.location_id).await?;
 
 		info!(
-			"Searching for images in location {} at path {:#?}",
+			"Searching for images and videos in location {} at path {:#?}",
 			location.id, state.init.path
 		);
 
@@ -69,20 +78,27 @@ impl StatefulJob for ThumbnailJob {
 		let root_path = location.path.unwrap();
 
 		// query database for all files in this location that need thumbnails
-		let image_files =
+		let mut media_files =
 			get_images(&library_ctx, state.init.location_id, &state.init.path).await?;
-		info!("Found {:?} files", image_files.len());
+		media_files
+			.extend(get_videos(&library_ctx, state.init.location_id, &state.init.path).await?);
+		if state.init.shallow {
+			media_files.retain(|file_path| {
+				Path::new(&file_path.materialized_path).parent() == Some(state.init.path.as_path())
+			});
+		}
+		info!("Found {:?} files", media_files.len());
 
 		ctx.progress(vec![
-			JobReportUpdate::TaskCount(image_files.len()),
-			JobReportUpdate::Message(format!("Preparing to process {} files", image_files.len())),
+			JobReportUpdate::TaskCount(media_files.len()),
+			JobReportUpdate::Message(format!("Preparing to process {} files", media_files.len())),
 		]);
 
 		state.data = Some(ThumbnailJobState {
 			thumbnail_dir,
 			root_path,
 		});
-		state.steps = image_files.into_iter().collect();
+		state.steps = media_files.into_iter().collect();
 
 		Ok(())
 	}
@@ -92,6 +108,13 @@ impl StatefulJob for ThumbnailJob {
 		ctx: WorkerContext,
 		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
 	) -> JobResult {
+		// the thumbnails clients are waiting for go first
+		let library_ctx = ctx.library_ctx();
+		library_ctx
+			.thumbnail_requests()
+			.wait_idle(library_ctx.id)
+			.await;
+
 		let step = &state.steps[0];
 		ctx.progress(vec![JobReportUpdate::Message(format!(
 			"Processing {}",
@@ -108,10 +131,15 @@ impl StatefulJob for ThumbnailJob {
 		trace!("image_file {:?}", step);
 
 		// get cas_id, if none found skip
-		let cas_id = match step.file() {
+		let (file_id, cas_id, has_perceptual_hash, has_thumbstrip) = match step.file() {
 			Ok(file) => {
 				if let Some(f) = file {
-					f.cas_id.clone()
+					(
+						f.id,
+						f.cas_id.clone(),
+						f.perceptual_hash.is_some(),
+						f.has_thumbstrip,
+					)
 				} else {
 					info!(
 						"skipping thumbnail generation for {}",
@@ -126,6 +154,46 @@ impl StatefulJob for ThumbnailJob {
 			}
 		};
 
+		let is_video = step.extension.as_ref().map_or(false, |ext| {
+			VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+		});
+
+		if is_video {
+			let output_path = thumbstrip_path(&data.thumbnail_dir, &cas_id);
+
+			if !has_thumbstrip || !output_path.exists() {
+				info!("Writing thumbstrip of {:?} to {:?}", path, output_path);
+
+				match generate_thumbstrip(&path, &output_path).await {
+					Ok(bytes) => {
+						ctx.library_ctx()
+							.record_usage(UsageCategory::Thumbstrips, bytes)
+							.await;
+						ctx.library_ctx()
+							.db
+							.file()
+							.find_unique(file::id::equals(file_id))
+							.update(vec![file::has_thumbstrip::set(true)])
+							.exec()
+							.await?;
+
+						if !state.init.background {
+							ctx.library_ctx()
+								.emit(CoreEvent::NewThumbnail { cas_id })
+								.await;
+						}
+					}
+					Err(e) => error!("Error generating thumbstrip {:?}", e),
+				}
+			}
+
+			ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+				state.step_number + 1,
+			)]);
+
+			return Ok(());
+		}
+
 		// Define and write the WebP-encoded file to a given path
 		let output_path = data.thumbnail_dir.join(&cas_id).with_extension("webp");
 
@@ -133,19 +201,57 @@ impl StatefulJob for ThumbnailJob {
 		if !output_path.exists() {
 			info!("Writing {:?} to {:?}", path, output_path);
 
-			if let Err(e) = generate_thumbnail(&path, &output_path).await {
-				error!("Error generating thumb {:?}", e);
+			match generate_thumbnail(&path, &output_path).await {
+				Ok(bytes) => {
+					ctx.library_ctx()
+						.record_usage(UsageCategory::Thumbnails, bytes)
+						.await
+				}
+				Err(e) => error!("Error generating thumb {:?}", e),
 			}
 
 			if !state.init.background {
 				ctx.library_ctx()
-					.emit(CoreEvent::NewThumbnail { cas_id })
+					.emit(CoreEvent::NewThumbnail {
+						cas_id: cas_id.clone(),
+					})
 					.await;
 			};
 		} else {
 			info!("Thumb exists, skipping... {}", output_path.display());
 		}
 
+		let profiles = &library_ctx.config.thumbnail_profiles;
+		match generate_variants(&path, &data.thumbnail_dir, &cas_id, profiles)
+			.await
+			.map_err(|e| e.to_string())
+		{
+			Ok(0) => {}
+			Ok(bytes) => {
+				library_ctx
+					.record_usage(UsageCategory::Thumbnails, bytes)
+					.await
+			}
+			Err(e) => error!("Error generating thumbnail variants {:?}", e),
+		}
+
+		if !has_perceptual_hash {
+			let hash = block_in_place(|| perceptual_hash(&path))
+				.map_err(|e| error!("Error generating perceptual hash {:?}", e))
+				.ok();
+
+			if let Some(hash) = hash {
+				ctx.library_ctx()
+					.db
+					.file()
+					.find_unique(file::id::equals(file_id))
+					// stored as the same 64 bits, sqlite has no unsigned integers
+					.update(vec![file::perceptual_hash::set(Some(hash as i64))])
+					.exec()
+					.await?;
+			}
+		}
+
 		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
 			state.step_number + 1,
 		)]);
@@ -171,52 +277,92 @@ impl StatefulJob for ThumbnailJob {
 	}
 }
 
+// returns the size of the written thumbnail
 pub async fn generate_thumbnail<P: AsRef<Path>>(
 	file_path: P,
 	output_path: P,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<u64, Box<dyn Error>> {
 	// Webp creation has blocking code
 	let webp = block_in_place(|| -> Result<Vec<u8>, Box<dyn Error>> {
 		// Using `image` crate, open the included .jpg file
-		let img = image::open(file_path)?;
-		let (w, h) = img.dimensions();
-		// Optionally, resize the existing photo and convert back into DynamicImage
-		let img = DynamicImage::ImageRgba8(imageops::resize(
-			&img,
-			(w as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			(h as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			imageops::FilterType::Triangle,
-		));
-		// Create the WebP encoder for the above image
-		let encoder = Encoder::from_image(&img)?;
-
-		// Encode the image at a specified quality 0-100
-
-		// Type WebPMemory is !Send, which makes the Future in this function !Send,
-		// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
-		// which implies on a unwanted clone...
-		Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
+		let img = open_image(file_path)?;
+		encode_thumbnail(&img)
 	})?;
 
 	fs::write(output_path, &webp).await?;
 
-	Ok(())
+	Ok(webp.len() as u64)
+}
+
+// resizes an already decoded image and encodes it as a WebP thumbnail
+pub fn encode_thumbnail(img: &DynamicImage) -> Result<Vec<u8>, Box<dyn Error>> {
+	let (w, h) = img.dimensions();
+	// Optionally, resize the existing photo and convert back into DynamicImage
+	let img = DynamicImage::ImageRgba8(imageops::resize(
+		img,
+		(w as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
+		(h as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
+		imageops::FilterType::Triangle,
+	));
+	// Create the WebP encoder for the above image
+	let encoder = Encoder::from_image(&img)?;
+
+	// Encode the image at a specified quality 0-100
+
+	// Type WebPMemory is !Send, which makes the Future in this function !Send,
+	// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
+	// which implies on a unwanted clone...
+	Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
 }
 
 pub async fn get_images(
 	ctx: &LibraryContext,
 	location_id: i32,
 	path: impl AsRef<Path>,
+) -> Result<Vec<file_path::Data>, std::io::Error> {
+	// cameras and phones name their photos in upper case
+	let camera_extensions = RAW_EXTENSIONS
+		.iter()
+		.copied()
+		.chain(native_image_extensions())
+		.flat_map(|ext| [ext.to_string(), ext.to_uppercase()]);
+
+	get_files_with_extensions(
+		ctx,
+		location_id,
+		path,
+		IMAGE_EXTENSIONS
+			.iter()
+			.map(|ext| ext.to_string())
+			.chain(camera_extensions)
+			.collect(),
+	)
+	.await
+}
+
+pub async fn get_videos(
+	ctx: &LibraryContext,
+	location_id: i32,
+	path: impl AsRef<Path>,
+) -> Result<Vec<file_path::Data>, std::io::Error> {
+	get_files_with_extensions(
+		ctx,
+		location_id,
+		path,
+		VIDEO_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+	)
+	.await
+}
+
+async fn get_files_with_extensions(
+	ctx: &LibraryContext,
+	location_id: i32,
+	path: impl AsRef<Path>,
+	extensions: Vec<String>,
 ) -> Result<Vec<file_path::Data>, std::io::Error> {
 	let mut params = vec![
 		file_path::location_id::equals(Some(location_id)),
-		file_path::extension::in_vec(vec![
-			"png".to_string(),
-			"jpeg".to_string(),
-			"jpg".to_string(),
-			"gif".to_string(),
-			"webp".to_string(),
-		]),
+		file_path::extension::in_vec(extensions),
 	];
 
 	let path_str = path.as_ref().to_string_lossy().to_string();
@@ -225,7 +371,7 @@ pub async fn get_images(
 		params.push(file_path::materialized_path::starts_with(path_str))
 	}
 
-	let image_files = ctx
+	let files = ctx
 		.db
 		.file_path()
 		.find_many(params)
@@ -234,5 +380,5 @@ pub async fn get_images(
 		.await
 		.unwrap();
 
-	Ok(image_files)
+	Ok(files)
 }
@@ -0,0 +1,200 @@
+use crate::{
+	encode::{
+		generate_thumbnail, generate_thumbstrip, is_thumbnailable_image, thumbstrip_path,
+		THUMBNAIL_CACHE_DIR_NAME, VIDEO_EXTENSIONS,
+	},
+	library::LibraryContext,
+	node::UsageCategory,
+	prisma::{file, file_path},
+	sys::get_location,
+	CoreError, CoreEvent,
+};
+use log::{error, info};
+use std::{
+	collections::{HashMap, HashSet},
+	path::PathBuf,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{sync::Semaphore, time::sleep};
+use uuid::Uuid;
+
+// thumbnails of a library generated at once for the files on screen, the database and the
+// disk are shared with everything else
+const ON_DEMAND_CONCURRENCY: usize = 4;
+
+struct Lane {
+	// cas ids being generated, so a file asked for twice is only generated once
+	in_flight: Mutex<HashSet<String>>,
+	permits: Semaphore,
+}
+
+/// ThumbnailRequests generates the thumbnails clients ask for right away, ahead of the thumbnailer jobs. Those wait for the requested thumbnails of their library before each file, so on-demand work takes the disk first.
+#[derive(Default)]
+pub struct ThumbnailRequests {
+	lanes: Mutex<HashMap<Uuid, Arc<Lane>>>,
+}
+
+impl ThumbnailRequests {
+	fn lane(&self, library_id: Uuid) -> Arc<Lane> {
+		self.lanes
+			.lock()
+			.unwrap()
+			.entry(library_id)
+			.or_insert_with(|| {
+				Arc::new(Lane {
+					in_flight: Mutex::new(HashSet::new()),
+					permits: Semaphore::new(ON_DEMAND_CONCURRENCY),
+				})
+			})
+			.clone()
+	}
+
+	/// wait_idle returns once no requested thumbnail of the library is left to generate.
+	pub(crate) async fn wait_idle(&self, library_id: Uuid) {
+		let lane = self.lane(library_id);
+		while !lane.in_flight.lock().unwrap().is_empty() {
+			sleep(Duration::from_millis(50)).await;
+		}
+	}
+}
+
+struct Request {
+	path: PathBuf,
+	output_path: PathBuf,
+	file_id: i32,
+	cas_id: String,
+	is_video: bool,
+}
+
+// generates the missing thumbnails and thumbstrips of the file paths in the background,
+// emitting NewThumbnail for each of them once written
+pub async fn request_thumbnails(
+	ctx: &LibraryContext,
+	file_path_ids: Vec<i32>,
+) -> Result<(), CoreError> {
+	let file_paths = ctx
+		.db
+		.file_path()
+		.find_many(vec![file_path::id::in_vec(file_path_ids)])
+		.with(file_path::file::fetch())
+		.exec()
+		.await?;
+
+	let mut location_paths = HashMap::new();
+	let mut requests = vec![];
+	for file_path in file_paths {
+		let file = match file_path.file.flatten() {
+			Some(file) => file,
+			// not identified yet, there is no cas id to name the thumbnail after
+			None => continue,
+		};
+		let location_id = match file_path.location_id {
+			Some(location_id) => location_id,
+			None => continue,
+		};
+		let extension = file_path.extension.unwrap_or_default().to_lowercase();
+		let is_video = VIDEO_EXTENSIONS.contains(&extension.as_str());
+		if !is_video && !is_thumbnailable_image(&extension) {
+			continue;
+		}
+
+		if !location_paths.contains_key(&location_id) {
+			let location = get_location(ctx, location_id).await?;
+			location_paths.insert(location_id, location.path.unwrap_or_default());
+		}
+
+		let thumbnail_dir = ctx
+			.config()
+			.data_directory()
+			.join(THUMBNAIL_CACHE_DIR_NAME)
+			.join(location_id.to_string());
+		let output_path = if is_video {
+			thumbstrip_path(&thumbnail_dir, &file.cas_id)
+		} else {
+			thumbnail_dir.join(&file.cas_id).with_extension("webp")
+		};
+		if output_path.exists() {
+			continue;
+		}
+
+		requests.push(Request {
+			path: location_paths[&location_id].join(&file_path.materialized_path),
+			output_path,
+			file_id: file.id,
+			cas_id: file.cas_id,
+			is_video,
+		});
+	}
+
+	let lane = ctx.thumbnail_requests().lane(ctx.id);
+	for request in requests {
+		// already requested, or the same contents at another path
+		if !lane
+			.in_flight
+			.lock()
+			.unwrap()
+			.insert(request.cas_id.clone())
+		{
+			continue;
+		}
+
+		let ctx = ctx.clone();
+		let lane = Arc::clone(&lane);
+		tokio::spawn(async move {
+			if let Ok(_permit) = lane.permits.acquire().await {
+				generate(&ctx, &request).await;
+			}
+			lane.in_flight.lock().unwrap().remove(&request.cas_id);
+		});
+	}
+
+	Ok(())
+}
+
+async fn generate(ctx: &LibraryContext, request: &Request) {
+	if let Some(parent) = request.output_path.parent() {
+		if let Err(e) = tokio::fs::create_dir_all(parent).await {
+			error!("Error creating thumbnail directory {:?}", e);
+			return;
+		}
+	}
+
+	info!("Writing requested thumbnail of {:?}", request.path);
+
+	// the errors of the encoders aren't Send, so they're logged before awaiting anything else
+	let written = if request.is_video {
+		generate_thumbstrip(&request.path, &request.output_path)
+			.await
+			.map_err(|e| error!("Error generating thumbstrip {:?}", e))
+	} else {
+		generate_thumbnail(&request.path, &request.output_path)
+			.await
+			.map_err(|e| error!("Error generating thumb {:?}", e))
+	};
+	let bytes = match written {
+		Ok(bytes) => bytes,
+		Err(()) => return,
+	};
+
+	if request.is_video {
+		ctx.record_usage(UsageCategory::Thumbstrips, bytes).await;
+		if let Err(e) = ctx
+			.db
+			.file()
+			.find_unique(file::id::equals(request.file_id))
+			.update(vec![file::has_thumbstrip::set(true)])
+			.exec()
+			.await
+		{
+			error!("Error recording thumbstrip {:?}", e);
+		}
+	} else {
+		ctx.record_usage(UsageCategory::Thumbnails, bytes).await;
+	}
+
+	ctx.emit(CoreEvent::NewThumbnail {
+		cas_id: request.cas_id.clone(),
+	})
+	.await;
+}
@@ -0,0 +1,161 @@
+use crate::{encode::THUMBNAIL_CACHE_DIR_NAME, library::LibraryContext};
+use ffmpeg_next::{
+	codec, format, frame, media,
+	software::scaling::{self, Flags},
+	util::format::Pixel,
+};
+use image::{DynamicImage, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::{
+	error::Error,
+	ops::Deref,
+	path::{Path, PathBuf},
+};
+use tokio::{fs, task::block_in_place};
+use ts_rs::TS;
+use webp::Encoder;
+
+// frames are evenly spread over the video and tiled left to right in a single row
+pub static THUMBSTRIP_FRAME_COUNT: u32 = 10;
+static THUMBSTRIP_FRAME_WIDTH: u32 = 160;
+static THUMBSTRIP_QUALITY: f32 = 30.0;
+
+pub static VIDEO_EXTENSIONS: [&str; 6] = ["mp4", "mov", "mkv", "webm", "avi", "m4v"];
+
+// ThumbstripLayout tells the explorer how to slice a scrub strip into frames on hover
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ThumbstripLayout {
+	pub path: PathBuf,
+	pub frame_count: u32,
+	pub columns: u32,
+	pub rows: u32,
+	pub frame_width: u32,
+	pub frame_height: u32,
+}
+
+// thumbstrips live next to the thumbnail of the same file
+pub fn thumbstrip_path(thumbnail_dir: impl AsRef<Path>, cas_id: &str) -> PathBuf {
+	thumbnail_dir
+		.as_ref()
+		.join(format!("{}_strip", cas_id))
+		.with_extension("webp")
+}
+
+// returns the size of the written thumbstrip
+pub async fn generate_thumbstrip<P: AsRef<Path>>(
+	file_path: P,
+	output_path: P,
+) -> Result<u64, Box<dyn Error>> {
+	// decoding with ffmpeg is blocking
+	let webp = block_in_place(|| -> Result<Vec<u8>, Box<dyn Error>> {
+		let strip = extract_frames(file_path.as_ref())?;
+		let encoder = Encoder::from_image(&DynamicImage::ImageRgb8(strip))?;
+		Ok(encoder.encode(THUMBSTRIP_QUALITY).deref().to_owned())
+	})?;
+
+	fs::write(output_path, &webp).await?;
+
+	Ok(webp.len() as u64)
+}
+
+// decodes one frame at the middle of each equal slice of the video, scaled down and tiled
+fn extract_frames(path: &Path) -> Result<RgbImage, ffmpeg_next::Error> {
+	ffmpeg_next::init()?;
+
+	let mut input = format::input(&path)?;
+	let (stream_index, parameters) = {
+		let stream = input
+			.streams()
+			.best(media::Type::Video)
+			.ok_or(ffmpeg_next::Error::StreamNotFound)?;
+		(stream.index(), stream.parameters())
+	};
+	let mut decoder = codec::context::Context::from_parameters(parameters)?
+		.decoder()
+		.video()?;
+
+	let frame_height = (THUMBSTRIP_FRAME_WIDTH * decoder.height() / decoder.width().max(1)).max(1);
+	let mut scaler = scaling::Context::get(
+		decoder.format(),
+		decoder.width(),
+		decoder.height(),
+		Pixel::RGB24,
+		THUMBSTRIP_FRAME_WIDTH,
+		frame_height,
+		Flags::BILINEAR,
+	)?;
+
+	let duration = input.duration().max(0);
+	let mut strip = RgbImage::new(
+		THUMBSTRIP_FRAME_WIDTH * THUMBSTRIP_FRAME_COUNT,
+		frame_height,
+	);
+
+	for i in 0..THUMBSTRIP_FRAME_COUNT as i64 {
+		let timestamp = duration * (2 * i + 1) / (2 * THUMBSTRIP_FRAME_COUNT as i64);
+		// seeking lands on the closest keyframe before the timestamp, which is close enough for a preview
+		input.seek(timestamp, ..timestamp)?;
+		decoder.flush();
+
+		let mut decoded = frame::Video::empty();
+		for (stream, packet) in input.packets() {
+			if stream.index() != stream_index {
+				continue;
+			}
+			decoder.send_packet(&packet)?;
+			if decoder.receive_frame(&mut decoded).is_ok() {
+				break;
+			}
+		}
+
+		// the video ended before this frame, leave the tile black
+		if decoded.width() == 0 {
+			continue;
+		}
+
+		let mut rgb = frame::Video::empty();
+		scaler.run(&decoded, &mut rgb)?;
+
+		let stride = rgb.stride(0);
+		let data = rgb.data(0);
+		for y in 0..frame_height {
+			for x in 0..THUMBSTRIP_FRAME_WIDTH {
+				let offset = y as usize * stride + x as usize * 3;
+				strip.put_pixel(
+					i as u32 * THUMBSTRIP_FRAME_WIDTH + x,
+					y,
+					image::Rgb([data[offset], data[offset + 1], data[offset + 2]]),
+				);
+			}
+		}
+	}
+
+	Ok(strip)
+}
+
+// returns the layout of the scrub strip of a video, if one was generated
+pub async fn get_thumbstrip_layout(
+	ctx: &LibraryContext,
+	location_id: i32,
+	cas_id: String,
+) -> Option<ThumbstripLayout> {
+	let path = thumbstrip_path(
+		ctx.config()
+			.data_directory()
+			.join(THUMBNAIL_CACHE_DIR_NAME)
+			.join(location_id.to_string()),
+		&cas_id,
+	);
+
+	let (width, height) = block_in_place(|| image::image_dimensions(&path)).ok()?;
+
+	Some(ThumbstripLayout {
+		path,
+		frame_count: THUMBSTRIP_FRAME_COUNT,
+		columns: THUMBSTRIP_FRAME_COUNT,
+		rows: 1,
+		frame_width: width / THUMBSTRIP_FRAME_COUNT,
+		frame_height: height,
+	})
+}
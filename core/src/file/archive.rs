@@ -0,0 +1,915 @@
+use crate::{
+	file::{
+		cas::{FileIdentifierJob, FileIdentifierJobInit},
+		copy::full_name,
+		indexer::index_entry,
+		trash::TRASH_DIR_NAME,
+		FileError, FilePath,
+	},
+	job::{JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::LibraryContext,
+	prisma::file_path,
+	sys::{get_location, LocationResource},
+	Job,
+};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	fs::{self, File},
+	io::{self, Read, Write},
+	path::{Path, PathBuf},
+};
+use tokio::{
+	sync::{broadcast, mpsc},
+	task::spawn_blocking,
+};
+use ts_rs::TS;
+use walkdir::WalkDir;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+// archives are streamed in chunks of this size, with at most CHUNKS_IN_FLIGHT of them buffered
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+// streams a directory as a tar archive built while it is read, so no archive is written to disk.
+// the archive stops being built once the receiver is dropped
+pub fn stream_tar(path: PathBuf) -> mpsc::Receiver<io::Result<Vec<u8>>> {
+	let (tx, rx) = mpsc::channel(CHUNKS_IN_FLIGHT);
+
+	tokio::task::spawn_blocking(move || {
+		let writer = ChunkWriter {
+			tx: tx.clone(),
+			buf: Vec::with_capacity(CHUNK_SIZE),
+		};
+		if let Err(e) = write_tar(&path, writer).and_then(|mut writer| writer.flush()) {
+			tx.blocking_send(Err(e)).ok();
+		}
+	});
+
+	rx
+}
+
+// writes a directory as a tar archive, giving the writer back once the archive is complete
+pub(crate) fn write_tar<W: Write>(path: &Path, writer: W) -> io::Result<W> {
+	// entries are archived below the name of the directory, as they would be when extracted
+	let root = PathBuf::from(path.file_name().unwrap_or_default());
+
+	let mut builder = tar::Builder::new(writer);
+	builder.follow_symlinks(false);
+
+	for entry in WalkDir::new(path)
+		.into_iter()
+		.filter_entry(|entry| entry.file_name() != TRASH_DIR_NAME)
+	{
+		let entry = entry?;
+		let name = root.join(entry.path().strip_prefix(path).unwrap_or(entry.path()));
+
+		match entry.file_type().is_dir() {
+			true => builder.append_dir(name, entry.path())?,
+			false => builder.append_path_with_name(entry.path(), name)?,
+		}
+	}
+
+	builder.into_inner()
+}
+
+struct ChunkWriter {
+	tx: mpsc::Sender<io::Result<Vec<u8>>>,
+	buf: Vec<u8>,
+}
+
+impl Write for ChunkWriter {
+	fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+		self.buf.extend_from_slice(data);
+		if self.buf.len() >= CHUNK_SIZE {
+			self.flush()?;
+		}
+
+		Ok(data.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		if self.buf.is_empty() {
+			return Ok(());
+		}
+
+		let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+		// blocks until the client has taken enough of the archive, which bounds the memory used
+		self.tx
+			.blocking_send(Ok(chunk))
+			.map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "archive stream closed"))
+	}
+}
+
+// streams a single entry of an archive, so it can be previewed without extracting the archive
+pub fn stream_archive_entry(source: PathBuf, path: String) -> mpsc::Receiver<io::Result<Vec<u8>>> {
+	let (tx, rx) = mpsc::channel(CHUNKS_IN_FLIGHT);
+
+	tokio::task::spawn_blocking(move || {
+		if let Err(e) = write_entry(&source, &path, tx.clone()) {
+			tx.blocking_send(Err(e)).ok();
+		}
+	});
+
+	rx
+}
+
+fn write_entry(source: &Path, path: &str, tx: mpsc::Sender<io::Result<Vec<u8>>>) -> io::Result<()> {
+	let format =
+		ArchiveFormat::from_name(&source.file_name().unwrap_or_default().to_string_lossy())
+			.ok_or_else(|| {
+				io::Error::new(io::ErrorKind::InvalidInput, "not a supported archive")
+			})?;
+	let not_found = || io::Error::new(io::ErrorKind::NotFound, "archive entry not found");
+	let mut writer = ChunkWriter {
+		tx,
+		buf: Vec::with_capacity(CHUNK_SIZE),
+	};
+
+	match format {
+		ArchiveFormat::Zip => {
+			let mut archive = ZipArchive::new(File::open(source)?)?;
+			let mut file = archive.by_name(path).map_err(|_| not_found())?;
+			io::copy(&mut file, &mut writer)?;
+		}
+		ArchiveFormat::Tar | ArchiveFormat::TarGz => {
+			let mut archive = tar::Archive::new(tar_reader(source, format)?);
+			// tar archives can only be read in order, up to the entry
+			let mut entry = archive
+				.entries()?
+				.filter_map(Result::ok)
+				.find(|entry| entry_path(entry).as_deref() == Some(path))
+				.ok_or_else(not_found)?;
+			io::copy(&mut entry, &mut writer)?;
+		}
+	}
+
+	writer.flush()
+}
+
+// An entry of an archive, listed like a file path of the directory the archive is browsed as
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ArchiveEntry {
+	// the path within the archive, without a trailing slash
+	pub path: String,
+	pub name: String,
+	pub is_dir: bool,
+	pub size_in_bytes: u64,
+	// unset for directories only implied by the paths of their entries
+	pub date_modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ArchiveContents {
+	pub archive: FilePath,
+	pub entries: Vec<ArchiveEntry>,
+}
+
+// lists the entries of a directory within an archive, its root when `path` is empty
+pub async fn get_archive_contents(
+	ctx: &LibraryContext,
+	file_path_id: i32,
+	path: String,
+) -> Result<ArchiveContents, FileError> {
+	let (_, source, format) = archive_source(ctx, file_path_id).await?;
+	let archive = ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.exec()
+		.await?
+		.ok_or(FileError::FilePathNotFound(file_path_id))?;
+
+	let prefix = match path.trim_matches('/') {
+		"" => String::new(),
+		path => format!("{}/", path),
+	};
+	let mut entries = spawn_blocking(move || {
+		let mut entries = HashMap::new();
+		let mut add = |path: &str, is_dir: bool, size_in_bytes, date_modified| {
+			let path = path.trim_end_matches('/');
+			let rest = match path.strip_prefix(&prefix) {
+				Some(rest) if !rest.is_empty() => rest,
+				_ => return,
+			};
+			match rest.split_once('/') {
+				// an entry deeper within the directory implies its child directory, which zip archives
+				// don't always list
+				Some((child, _)) => {
+					entries
+						.entry(child.to_string())
+						.or_insert_with(|| ArchiveEntry {
+							path: format!("{}{}", prefix, child),
+							name: child.to_string(),
+							is_dir: true,
+							size_in_bytes: 0,
+							date_modified: None,
+						});
+				}
+				None => {
+					entries.insert(
+						rest.to_string(),
+						ArchiveEntry {
+							path: path.to_string(),
+							name: rest.to_string(),
+							is_dir,
+							size_in_bytes,
+							date_modified,
+						},
+					);
+				}
+			}
+		};
+
+		match format {
+			ArchiveFormat::Zip => {
+				let mut archive = ZipArchive::new(File::open(&source)?)?;
+				for i in 0..archive.len() {
+					let file = archive.by_index_raw(i)?;
+					let modified = file.last_modified();
+					let date_modified = NaiveDate::from_ymd_opt(
+						modified.year() as i32,
+						modified.month() as u32,
+						modified.day() as u32,
+					)
+					.and_then(|date| {
+						date.and_hms_opt(
+							modified.hour() as u32,
+							modified.minute() as u32,
+							modified.second() as u32,
+						)
+					})
+					.map(|date| DateTime::<Utc>::from_utc(date, Utc));
+					add(file.name(), file.is_dir(), file.size(), date_modified);
+				}
+			}
+			ArchiveFormat::Tar | ArchiveFormat::TarGz => {
+				let mut archive = tar::Archive::new(tar_reader(&source, format)?);
+				for entry in archive.entries()? {
+					let entry = entry?;
+					let header = entry.header();
+					let path = match entry_path(&entry) {
+						Some(path) => path,
+						None => continue,
+					};
+					let date_modified = header
+						.mtime()
+						.ok()
+						.and_then(|mtime| Utc.timestamp_opt(mtime as i64, 0).single());
+					add(
+						&path,
+						header.entry_type().is_dir(),
+						header.size()?,
+						date_modified,
+					);
+				}
+			}
+		}
+
+		Ok::<_, io::Error>(entries.into_values().collect::<Vec<_>>())
+	})
+	.await
+	.map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+	let collation = ctx.config.collation;
+	entries.sort_by(|a, b| {
+		b.is_dir
+			.cmp(&a.is_dir)
+			.then_with(|| collation.compare(&a.name, &b.name))
+	});
+
+	Ok(ArchiveContents {
+		archive: archive.into(),
+		entries,
+	})
+}
+
+fn entry_path<R: Read>(entry: &tar::Entry<R>) -> Option<String> {
+	let path = entry.path().ok()?;
+	// "./" prefixes are common in tar archives, they aren't part of the path
+	Some(
+		path.to_string_lossy()
+			.trim_start_matches("./")
+			.trim_end_matches('/')
+			.to_string(),
+	)
+}
+
+pub const COMPRESS_JOB_NAME: &str = "file_compressor";
+pub const EXTRACT_JOB_NAME: &str = "archive_extractor";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum ArchiveFormat {
+	Zip,
+	Tar,
+	TarGz,
+}
+
+impl ArchiveFormat {
+	// the format of an archive from its name
+	pub fn from_name(name: &str) -> Option<Self> {
+		let name = name.to_lowercase();
+		if name.ends_with(".zip") {
+			Some(Self::Zip)
+		} else if name.ends_with(".tar") {
+			Some(Self::Tar)
+		} else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+			Some(Self::TarGz)
+		} else {
+			None
+		}
+	}
+}
+
+// What compressing or extracting would write
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ArchivePreview {
+	pub entry_count: usize,
+	// the size of the entries once extracted
+	pub total_bytes: u64,
+	// the size of the archive created, an upper bound for compressed formats as their ratio isn't known
+	// until they're written. unset when extracting
+	pub estimated_bytes: Option<u64>,
+}
+
+pub async fn get_compress_preview(
+	ctx: &LibraryContext,
+	file_path_ids: Vec<i32>,
+	format: ArchiveFormat,
+) -> Result<ArchivePreview, FileError> {
+	let entries = archive_entries(ctx, file_path_ids).await?;
+
+	let preview = spawn_blocking(move || {
+		let mut preview = ArchivePreview {
+			entry_count: 0,
+			total_bytes: 0,
+			estimated_bytes: Some(match format {
+				// the end of central directory record
+				ArchiveFormat::Zip => 22,
+				// the two empty blocks ending the archive
+				ArchiveFormat::Tar | ArchiveFormat::TarGz => 1024,
+			}),
+		};
+
+		for_each_entry(&entries, |path, name| {
+			let size = match path.is_dir() {
+				true => 0,
+				false => fs::metadata(path)?.len(),
+			};
+			preview.entry_count += 1;
+			preview.total_bytes += size;
+			preview.estimated_bytes = preview.estimated_bytes.map(|bytes| {
+				bytes
+					+ match format {
+						// a local header and a central directory header, each with the name
+						ArchiveFormat::Zip => 76 + 2 * name.len() as u64 + size,
+						// a header block, with the contents padded to blocks
+						ArchiveFormat::Tar | ArchiveFormat::TarGz => 512 + (size + 511) / 512 * 512,
+					}
+			});
+			Ok(())
+		})?;
+
+		Ok::<_, io::Error>(preview)
+	})
+	.await
+	.map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+	Ok(preview)
+}
+
+pub async fn get_extract_preview(
+	ctx: &LibraryContext,
+	file_path_id: i32,
+) -> Result<ArchivePreview, FileError> {
+	let (_, source, format) = archive_source(ctx, file_path_id).await?;
+
+	let preview = spawn_blocking(move || {
+		let mut preview = ArchivePreview {
+			entry_count: 0,
+			total_bytes: 0,
+			estimated_bytes: None,
+		};
+
+		match format {
+			ArchiveFormat::Zip => {
+				let mut archive = ZipArchive::new(File::open(&source)?)?;
+				for i in 0..archive.len() {
+					// read without decrypting, so encrypted archives can be previewed without their password
+					let file = archive.by_index_raw(i)?;
+					preview.entry_count += 1;
+					preview.total_bytes += file.size();
+				}
+			}
+			ArchiveFormat::Tar | ArchiveFormat::TarGz => {
+				let mut archive = tar::Archive::new(tar_reader(&source, format)?);
+				for entry in archive.entries()? {
+					preview.entry_count += 1;
+					preview.total_bytes += entry?.header().size()?;
+				}
+			}
+		}
+
+		Ok::<_, io::Error>(preview)
+	})
+	.await
+	.map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+	Ok(preview)
+}
+
+// the entries selected to be archived, with the name they are archived under
+async fn archive_entries(
+	ctx: &LibraryContext,
+	file_path_ids: Vec<i32>,
+) -> Result<Vec<(PathBuf, String)>, FileError> {
+	let mut entries = vec![];
+	for id in file_path_ids {
+		let file_path = ctx
+			.db
+			.file_path()
+			.find_unique(file_path::id::equals(id))
+			.exec()
+			.await?
+			.ok_or(FileError::FilePathNotFound(id))?;
+		let location_id = file_path.location_id.unwrap_or(0);
+		let location_path = get_location(ctx, location_id)
+			.await?
+			.path
+			.ok_or(FileError::LocationUnavailable(location_id))?;
+
+		entries.push((
+			location_path.join(&file_path.materialized_path),
+			full_name(&file_path),
+		));
+	}
+
+	Ok(entries)
+}
+
+// the archive a file path is, and where it is on disk
+async fn archive_source(
+	ctx: &LibraryContext,
+	file_path_id: i32,
+) -> Result<(LocationResource, PathBuf, ArchiveFormat), FileError> {
+	let file_path = ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.exec()
+		.await?
+		.ok_or(FileError::FilePathNotFound(file_path_id))?;
+	let format = ArchiveFormat::from_name(&full_name(&file_path))
+		.ok_or_else(|| FileError::UnsupportedArchive(full_name(&file_path)))?;
+
+	let location = get_location(ctx, file_path.location_id.unwrap_or(0)).await?;
+	let source = location
+		.path
+		.as_ref()
+		.ok_or(FileError::LocationUnavailable(location.id))?
+		.join(&file_path.materialized_path);
+
+	Ok((location, source, format))
+}
+
+// calls `f` with every file and directory below the entries, parents first
+fn for_each_entry(
+	entries: &[(PathBuf, String)],
+	mut f: impl FnMut(&Path, &str) -> io::Result<()>,
+) -> io::Result<()> {
+	for (path, name) in entries {
+		for entry in WalkDir::new(path)
+			.into_iter()
+			.filter_entry(|entry| entry.file_name() != TRASH_DIR_NAME)
+		{
+			let entry = entry?;
+			let relative = entry.path().strip_prefix(path).unwrap_or(entry.path());
+			// archives always use forward slashes
+			let name = Path::new(name)
+				.join(relative)
+				.to_string_lossy()
+				.replace('\\', "/");
+
+			f(entry.path(), &name)?;
+		}
+	}
+
+	Ok(())
+}
+
+fn tar_reader(source: &Path, format: ArchiveFormat) -> io::Result<Box<dyn Read>> {
+	let file = File::open(source)?;
+	Ok(match format {
+		ArchiveFormat::TarGz => Box::new(GzDecoder::new(file)),
+		_ => Box::new(file),
+	})
+}
+
+// reports the progress of an archive being written, and whether the job was asked to stop
+struct ArchiveProgress {
+	ctx: WorkerContext,
+	shutdown_rx: broadcast::Receiver<()>,
+	total_bytes: u64,
+	written_bytes: u64,
+	entries: usize,
+}
+
+impl ArchiveProgress {
+	fn new(ctx: WorkerContext, total_bytes: u64) -> Self {
+		Self {
+			shutdown_rx: ctx.shutdown_rx(),
+			ctx,
+			total_bytes,
+			written_bytes: 0,
+			entries: 0,
+		}
+	}
+
+	// called after each entry, stopping the archive between entries once the job is paused
+	fn entry_written(&mut self, bytes: u64) -> io::Result<()> {
+		self.entries += 1;
+		self.written_bytes += bytes;
+		self.ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(self.entries),
+			JobReportUpdate::Message(format!(
+				"{} of {} bytes",
+				self.written_bytes, self.total_bytes
+			)),
+		]);
+
+		match self.shutdown_rx.try_recv() {
+			Ok(()) | Err(broadcast::error::TryRecvError::Closed) => Err(io::Error::new(
+				io::ErrorKind::Interrupted,
+				"archive job stopped",
+			)),
+			Err(_) => Ok(()),
+		}
+	}
+}
+
+fn write_archive(
+	entries: &[(PathBuf, String)],
+	target: &Path,
+	format: ArchiveFormat,
+	progress: &mut ArchiveProgress,
+) -> io::Result<()> {
+	let file = File::create(target)?;
+
+	match format {
+		ArchiveFormat::Zip => {
+			let mut zip = ZipWriter::new(file);
+			for_each_entry(entries, |path, name| {
+				if path.is_dir() {
+					zip.add_directory(name, FileOptions::default())?;
+					return progress.entry_written(0);
+				}
+
+				let size = fs::metadata(path)?.len();
+				zip.start_file(
+					name,
+					FileOptions::default()
+						.compression_method(CompressionMethod::Deflated)
+						.large_file(size >= u32::MAX as u64),
+				)?;
+				io::copy(&mut File::open(path)?, &mut zip)?;
+				progress.entry_written(size)
+			})?;
+			zip.finish()?;
+		}
+		ArchiveFormat::Tar => {
+			let mut builder = tar::Builder::new(file);
+			append_tar(&mut builder, entries, progress)?;
+			builder.into_inner()?.flush()?;
+		}
+		ArchiveFormat::TarGz => {
+			let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+			append_tar(&mut builder, entries, progress)?;
+			builder.into_inner()?.finish()?;
+		}
+	}
+
+	Ok(())
+}
+
+fn append_tar<W: Write>(
+	builder: &mut tar::Builder<W>,
+	entries: &[(PathBuf, String)],
+	progress: &mut ArchiveProgress,
+) -> io::Result<()> {
+	builder.follow_symlinks(false);
+	for_each_entry(entries, |path, name| {
+		let size = match path.is_dir() {
+			true => {
+				builder.append_dir(name, path)?;
+				0
+			}
+			false => {
+				builder.append_path_with_name(path, name)?;
+				fs::metadata(path)?.len()
+			}
+		};
+		progress.entry_written(size)
+	})
+}
+
+fn extract_archive(
+	source: &Path,
+	target: &Path,
+	format: ArchiveFormat,
+	password: Option<&str>,
+	progress: &mut ArchiveProgress,
+) -> io::Result<()> {
+	fs::create_dir_all(target)?;
+
+	match format {
+		ArchiveFormat::Zip => {
+			let mut archive = ZipArchive::new(File::open(source)?)?;
+			for i in 0..archive.len() {
+				let mut file =
+					match password {
+						Some(password) => archive
+							.by_index_decrypt(i, password.as_bytes())?
+							.map_err(|_| {
+								io::Error::new(io::ErrorKind::PermissionDenied, "invalid password")
+							})?,
+						None => archive.by_index(i)?,
+					};
+				// entries reaching outside of the target are skipped
+				let path = match file.enclosed_name() {
+					Some(name) => target.join(name),
+					None => continue,
+				};
+
+				if file.is_dir() {
+					fs::create_dir_all(&path)?;
+				} else {
+					if let Some(parent) = path.parent() {
+						fs::create_dir_all(parent)?;
+					}
+					io::copy(&mut file, &mut File::create(&path)?)?;
+				}
+				progress.entry_written(file.size())?;
+			}
+		}
+		ArchiveFormat::Tar | ArchiveFormat::TarGz => {
+			let mut archive = tar::Archive::new(tar_reader(source, format)?);
+			for entry in archive.entries()? {
+				let mut entry = entry?;
+				let size = entry.header().size()?;
+				// refuses entries reaching outside of the target
+				entry.unpack_in(target)?;
+				progress.entry_written(size)?;
+			}
+		}
+	}
+
+	Ok(())
+}
+
+// the first path nothing exists at, numbering the name when needed, eg: "photos (2)"
+pub(crate) fn free_path(path: PathBuf) -> PathBuf {
+	if !path.exists() {
+		return path;
+	}
+
+	let stem = path
+		.file_stem()
+		.unwrap_or_default()
+		.to_string_lossy()
+		.to_string();
+	let extension = path
+		.extension()
+		.map(|extension| format!(".{}", extension.to_string_lossy()))
+		.unwrap_or_default();
+	(1..)
+		.map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+		.find(|path| !path.exists())
+		.unwrap_or(path)
+}
+
+// the archive or extracted directory is indexed right away, so it shows up without a rescan
+async fn register(ctx: &LibraryContext, location: &LocationResource, path: &Path) -> JobResult {
+	index_entry(ctx, location, path).await?;
+
+	let location_path = location.path.clone().unwrap_or_default();
+	ctx.queue_job(Job::new(
+		FileIdentifierJobInit {
+			location_id: location.id,
+			path: location_path,
+		},
+		Box::new(FileIdentifierJob {}),
+	))
+	.await;
+
+	Ok(())
+}
+
+pub struct CompressJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CompressJobInit {
+	pub file_path_ids: Vec<i32>,
+	pub location_id: i32,
+	// materialized path of the archive created
+	pub path: String,
+	pub format: ArchiveFormat,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for CompressJob {
+	type Init = CompressJobInit;
+	type Data = ArchivePreview;
+	// the archive is written in a single step, as a writer can't be kept across a pause.
+	// a paused job writes it again from the start
+	type Step = ();
+
+	fn name(&self) -> &'static str {
+		COMPRESS_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let preview = get_compress_preview(
+			&ctx.library_ctx(),
+			state.init.file_path_ids.clone(),
+			state.init.format,
+		)
+		.await?;
+
+		info!(
+			"Compressing {} entries ({} bytes) to {:?}",
+			preview.entry_count, preview.total_bytes, state.init.path
+		);
+		ctx.progress(vec![JobReportUpdate::TaskCount(preview.entry_count)]);
+		state.data = Some(preview);
+		state.steps = [()].into_iter().collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let location = get_location(&library_ctx, state.init.location_id).await?;
+		let target = location
+			.path
+			.as_ref()
+			.ok_or(FileError::LocationUnavailable(location.id))?
+			.join(&state.init.path);
+		let target = free_path(target);
+
+		let entries = archive_entries(&library_ctx, state.init.file_path_ids.clone()).await?;
+		let mut progress = ArchiveProgress::new(
+			ctx.clone(),
+			state.data.as_ref().map_or(0, |data| data.total_bytes),
+		);
+		let format = state.init.format;
+
+		let written_target = target.clone();
+		// the archive is cleaned up from the blocking task, as the step is dropped when the job is paused
+		spawn_blocking(move || {
+			write_archive(&entries, &written_target, format, &mut progress).map_err(|e| {
+				// a partial archive is of no use
+				fs::remove_file(&written_target).ok();
+				e
+			})
+		})
+		.await??;
+
+		register(&library_ctx, &location, &target).await
+	}
+
+	async fn finalize(
+		&self,
+		_ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		info!("Compressed {:?}", state.init.path);
+
+		Ok(())
+	}
+}
+
+pub struct ExtractJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExtractJobInit {
+	pub file_path_id: i32,
+	// materialized path of the directory extracted to, a directory named after the archive next to it if unset
+	pub path: Option<String>,
+	// not kept with the job state, so an encrypted archive can't be extracted once the job is resumed
+	#[serde(skip)]
+	pub password: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ExtractJob {
+	type Init = ExtractJobInit;
+	type Data = ArchivePreview;
+	type Step = ();
+
+	fn name(&self) -> &'static str {
+		EXTRACT_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let preview = get_extract_preview(&ctx.library_ctx(), state.init.file_path_id).await?;
+
+		info!(
+			"Extracting {} entries ({} bytes) of file path {}",
+			preview.entry_count, preview.total_bytes, state.init.file_path_id
+		);
+		ctx.progress(vec![JobReportUpdate::TaskCount(preview.entry_count)]);
+		state.data = Some(preview);
+		state.steps = [()].into_iter().collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let (location, source, format) =
+			archive_source(&library_ctx, state.init.file_path_id).await?;
+
+		let target = match &state.init.path {
+			Some(path) => location
+				.path
+				.as_ref()
+				.ok_or(FileError::LocationUnavailable(location.id))?
+				.join(path),
+			None => {
+				let name = source
+					.file_name()
+					.unwrap_or_default()
+					.to_string_lossy()
+					.to_lowercase();
+				let name = [".tar.gz", ".tgz", ".tar", ".zip"]
+					.iter()
+					.find_map(|extension| {
+						name.ends_with(extension).then(|| {
+							let name = source.file_name().unwrap_or_default().to_string_lossy();
+							name[..name.len() - extension.len()].to_string()
+						})
+					})
+					.unwrap_or(name);
+				source.with_file_name(name)
+			}
+		};
+		let target = free_path(target);
+
+		let mut progress = ArchiveProgress::new(
+			ctx.clone(),
+			state.data.as_ref().map_or(0, |data| data.total_bytes),
+		);
+		let password = state.init.password.clone();
+
+		let extracted_target = target.clone();
+		spawn_blocking(move || {
+			extract_archive(
+				&source,
+				&extracted_target,
+				format,
+				password.as_deref(),
+				&mut progress,
+			)
+			.map_err(|e| {
+				fs::remove_dir_all(&extracted_target).ok();
+				e
+			})
+		})
+		.await??;
+
+		register(&library_ctx, &location, &target).await
+	}
+
+	async fn finalize(
+		&self,
+		_ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		info!("Extracted file path {}", state.init.file_path_id);
+
+		Ok(())
+	}
+}
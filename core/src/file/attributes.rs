@@ -0,0 +1,367 @@
+use serde::{Deserialize, Serialize};
+use std::{io, path::Path};
+use ts_rs::TS;
+
+// the extended attributes Finder keeps the tags and flags of an entry in
+#[cfg(unix)]
+const FINDER_ATTRIBUTES: [&str; 2] = [
+	"com.apple.metadata:_kMDItemUserTags",
+	"com.apple.FinderInfo",
+];
+#[cfg(target_os = "macos")]
+const FINDER_TAGS_ATTRIBUTE: &str = "com.apple.metadata:_kMDItemUserTags";
+// access control lists as Linux exposes them, they are copied along the other attributes
+#[cfg(unix)]
+const ACL_ATTRIBUTES: [&str; 3] = [
+	"system.posix_acl_access",
+	"system.posix_acl_default",
+	"system.nfs4_acl",
+];
+// security labels are given by the policy of the target, not copied
+#[cfg(unix)]
+const SECURITY_NAMESPACE: &str = "security.";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum AttributeKind {
+	ExtendedAttribute,
+	// the tags, colors and flags Finder keeps in extended attributes
+	FinderInfo,
+	// the named streams of NTFS, Explorer keeps where a file was downloaded from in one
+	AlternateDataStream,
+	Acl,
+}
+
+#[cfg(unix)]
+impl AttributeKind {
+	fn of(name: &str) -> Self {
+		if FINDER_ATTRIBUTES.contains(&name) {
+			Self::FinderInfo
+		} else if ACL_ATTRIBUTES.contains(&name) {
+			Self::Acl
+		} else {
+			Self::ExtendedAttribute
+		}
+	}
+}
+
+// Something a copy or move couldn't write to the target along an entry, mostly as the volume
+// doesn't support it
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AttributeLoss {
+	pub source_path: String,
+	pub target_path: String,
+	pub kind: AttributeKind,
+	// the attribute or stream, unset for the ACL of macOS and Windows
+	pub name: Option<String>,
+	pub error: String,
+}
+
+// An attribute of an entry which couldn't be copied, see AttributeLoss
+pub(crate) struct LostAttribute {
+	pub kind: AttributeKind,
+	pub name: Option<String>,
+	pub error: io::Error,
+}
+
+// copies what a copy of the contents of `source` to `target` leaves behind, and returns what
+// couldn't be. macOS copies the attributes and ACL of files along their contents and Windows their
+// streams, those of directories are copied here
+#[cfg_attr(not(windows), allow(unused_variables))]
+pub(crate) fn preserve(source: &Path, target: &Path, streams: bool) -> Vec<LostAttribute> {
+	let mut lost = vec![];
+
+	#[cfg(target_os = "macos")]
+	if let Err(error) = macos::copy_acl(source, target) {
+		lost.push(LostAttribute {
+			kind: AttributeKind::Acl,
+			name: None,
+			error,
+		});
+	}
+	#[cfg(unix)]
+	lost.extend(copy_extended_attributes(source, target));
+
+	#[cfg(windows)]
+	{
+		if let Err(error) = windows::copy_dacl(source, target) {
+			lost.push(LostAttribute {
+				kind: AttributeKind::Acl,
+				name: None,
+				error,
+			});
+		}
+		if streams {
+			lost.extend(windows::copy_streams(source, target));
+		}
+	}
+
+	lost
+}
+
+#[cfg(unix)]
+fn copy_extended_attributes(source: &Path, target: &Path) -> Vec<LostAttribute> {
+	// nothing to copy from a volume without extended attributes
+	let names = match xattr::list(source) {
+		Ok(names) => names,
+		Err(_) => return vec![],
+	};
+
+	let mut lost = vec![];
+	for name in names {
+		let display_name = name.to_string_lossy().to_string();
+		if display_name.starts_with(SECURITY_NAMESPACE) {
+			continue;
+		}
+
+		let value = match xattr::get(source, &name) {
+			Ok(Some(value)) => value,
+			_ => continue,
+		};
+		// copied along the contents already
+		if xattr::get(target, &name).ok().flatten().as_ref() == Some(&value) {
+			continue;
+		}
+		if let Err(error) = xattr::set(target, &name, &value) {
+			lost.push(LostAttribute {
+				kind: AttributeKind::of(&display_name),
+				name: Some(display_name),
+				error,
+			});
+		}
+	}
+
+	lost
+}
+
+// The tags of an entry in Finder, with the index of their color
+#[cfg(target_os = "macos")]
+pub(crate) fn read_finder_tags(path: &Path) -> Vec<(String, Option<usize>)> {
+	// a property list of names, each followed by a line with its color, "Work\n4"
+	xattr::get(path, FINDER_TAGS_ATTRIBUTE)
+		.ok()
+		.flatten()
+		.and_then(|value| plist::from_bytes::<Vec<String>>(&value).ok())
+		.unwrap_or_default()
+		.into_iter()
+		.filter_map(|tag| {
+			let (name, color) = match tag.split_once('\n') {
+				Some((name, color)) => (name.to_string(), color.trim().parse().ok()),
+				None => (tag, None),
+			};
+			(!name.trim().is_empty()).then(|| (name, color))
+		})
+		.collect()
+}
+
+// other systems have no Finder tags
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn read_finder_tags(_path: &Path) -> Vec<(String, Option<usize>)> {
+	vec![]
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+	use std::{
+		ffi::CString,
+		io,
+		os::{
+			raw::{c_char, c_int, c_void},
+			unix::ffi::OsStrExt,
+		},
+		path::Path,
+		ptr,
+	};
+
+	const COPYFILE_ACL: u32 = 1 << 0;
+
+	extern "C" {
+		fn copyfile(
+			from: *const c_char,
+			to: *const c_char,
+			state: *mut c_void,
+			flags: u32,
+		) -> c_int;
+	}
+
+	// copies nothing when the source has no ACL
+	pub fn copy_acl(source: &Path, target: &Path) -> io::Result<()> {
+		let source = CString::new(source.as_os_str().as_bytes())?;
+		let target = CString::new(target.as_os_str().as_bytes())?;
+
+		match unsafe {
+			copyfile(
+				source.as_ptr(),
+				target.as_ptr(),
+				ptr::null_mut(),
+				COPYFILE_ACL,
+			)
+		} {
+			0 => Ok(()),
+			_ => Err(io::Error::last_os_error()),
+		}
+	}
+}
+
+#[cfg(windows)]
+mod windows {
+	use super::{AttributeKind, LostAttribute};
+	use std::{
+		ffi::{OsStr, OsString},
+		fs::File,
+		io,
+		os::windows::ffi::{OsStrExt, OsStringExt},
+		path::{Path, PathBuf},
+		ptr,
+	};
+	use windows_sys::Win32::{
+		Foundation::{ERROR_HANDLE_EOF, ERROR_SUCCESS, INVALID_HANDLE_VALUE},
+		Security::{
+			Authorization::{GetNamedSecurityInfoW, SetNamedSecurityInfoW, SE_FILE_OBJECT},
+			GetSecurityDescriptorControl, ACL, DACL_SECURITY_INFORMATION,
+			PROTECTED_DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, SE_DACL_PROTECTED,
+			UNPROTECTED_DACL_SECURITY_INFORMATION,
+		},
+		Storage::FileSystem::{
+			FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+			WIN32_FIND_STREAM_DATA,
+		},
+		System::Memory::LocalFree,
+	};
+
+	// the unnamed stream, which holds the contents
+	const DATA_STREAM: &str = "::$DATA";
+
+	fn wide(path: &OsStr) -> Vec<u16> {
+		path.encode_wide().chain(Some(0)).collect()
+	}
+
+	// the path of a named stream of an entry, "photo.jpg:Zone.Identifier"
+	fn stream_path(path: &Path, name: &str) -> PathBuf {
+		let mut path = path.as_os_str().to_os_string();
+		path.push(":");
+		path.push(name);
+		PathBuf::from(path)
+	}
+
+	// the explicit entries of the DACL are copied, the target inherits the others from its new
+	// parent unless the source doesn't inherit any
+	pub fn copy_dacl(source: &Path, target: &Path) -> io::Result<()> {
+		let source = wide(source.as_os_str());
+		let mut target = wide(target.as_os_str());
+
+		let mut dacl: *mut ACL = ptr::null_mut();
+		let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+		unsafe {
+			let result = GetNamedSecurityInfoW(
+				source.as_ptr(),
+				SE_FILE_OBJECT,
+				DACL_SECURITY_INFORMATION,
+				ptr::null_mut(),
+				ptr::null_mut(),
+				&mut dacl,
+				ptr::null_mut(),
+				&mut descriptor,
+			);
+			if result != ERROR_SUCCESS {
+				return Err(io::Error::from_raw_os_error(result as i32));
+			}
+
+			let (mut control, mut revision) = (0, 0);
+			GetSecurityDescriptorControl(descriptor, &mut control, &mut revision);
+			let inheritance = match control & SE_DACL_PROTECTED {
+				0 => UNPROTECTED_DACL_SECURITY_INFORMATION,
+				_ => PROTECTED_DACL_SECURITY_INFORMATION,
+			};
+
+			let result = SetNamedSecurityInfoW(
+				target.as_mut_ptr(),
+				SE_FILE_OBJECT,
+				DACL_SECURITY_INFORMATION | inheritance,
+				ptr::null_mut(),
+				ptr::null_mut(),
+				dacl,
+				ptr::null(),
+			);
+			LocalFree(descriptor as isize);
+			match result {
+				ERROR_SUCCESS => Ok(()),
+				result => Err(io::Error::from_raw_os_error(result as i32)),
+			}
+		}
+	}
+
+	// the named streams of an entry, without the unnamed one
+	fn streams(path: &Path) -> io::Result<Vec<String>> {
+		let path = wide(path.as_os_str());
+		let mut data: WIN32_FIND_STREAM_DATA = unsafe { std::mem::zeroed() };
+		let mut names = vec![];
+		unsafe {
+			let handle = FindFirstStreamW(
+				path.as_ptr(),
+				FindStreamInfoStandard,
+				&mut data as *mut _ as *mut _,
+				0,
+			);
+			if handle == INVALID_HANDLE_VALUE {
+				let error = io::Error::last_os_error();
+				return match error.raw_os_error() {
+					Some(code) if code == ERROR_HANDLE_EOF as i32 => Ok(names),
+					_ => Err(error),
+				};
+			}
+
+			loop {
+				let len = data
+					.cStreamName
+					.iter()
+					.position(|c| *c == 0)
+					.unwrap_or(data.cStreamName.len());
+				let name = OsString::from_wide(&data.cStreamName[..len])
+					.to_string_lossy()
+					.to_string();
+				// ":Zone.Identifier:$DATA"
+				if name != DATA_STREAM {
+					if let Some(name) = name
+						.strip_prefix(':')
+						.and_then(|name| name.strip_suffix(":$DATA"))
+					{
+						names.push(name.to_string());
+					}
+				}
+
+				if FindNextStreamW(handle, &mut data as *mut _ as *mut _) == 0 {
+					break;
+				}
+			}
+			FindClose(handle);
+		}
+
+		Ok(names)
+	}
+
+	pub fn copy_streams(source: &Path, target: &Path) -> Vec<LostAttribute> {
+		let existing = streams(target).unwrap_or_default();
+		let mut lost = vec![];
+		for name in streams(source).unwrap_or_default() {
+			if existing.contains(&name) {
+				continue;
+			}
+
+			let copied = File::open(stream_path(source, &name)).and_then(|mut reader| {
+				let mut writer = File::create(stream_path(target, &name))?;
+				io::copy(&mut reader, &mut writer)
+			});
+			if let Err(error) = copied {
+				lost.push(LostAttribute {
+					kind: AttributeKind::AlternateDataStream,
+					name: Some(name),
+					error,
+				});
+			}
+		}
+
+		lost
+	}
+}
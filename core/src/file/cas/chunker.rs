@@ -0,0 +1,212 @@
+use crate::{
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	prisma::file,
+	sys::get_location,
+};
+use fastcdc::v2020::StreamCDC;
+use log::info;
+use prisma_client_rust::{prisma_models::PrismaValue, raw, raw::Raw};
+use serde::{Deserialize, Serialize};
+use std::{
+	fs::File,
+	io::{self, BufReader},
+	path::{Path, PathBuf},
+};
+
+// content defined chunking boundaries, in bytes
+pub(crate) static CHUNK_MIN_SIZE: u32 = 16 * 1024;
+pub(crate) static CHUNK_AVG_SIZE: u32 = 64 * 1024;
+pub(crate) static CHUNK_MAX_SIZE: u32 = 256 * 1024;
+// sqlite limits the amount of bound variables per statement, 5 per row
+static CHUNK_INSERT_BATCH_SIZE: usize = 100;
+pub const CHUNK_HASHER_JOB_NAME: &str = "object_chunk_hasher";
+
+pub struct ChunkHasherJob {}
+
+// ChunkHasherJobInit reads the full contents of every identified file in a location, splitting it
+// into content defined chunks which are individually hashed, for block level dedup and partial sync
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ChunkHasherJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChunkHasherJobState {
+	location_path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChunkHasherJobStep {
+	file_id: i32,
+	materialized_path: String,
+}
+
+#[derive(Debug)]
+pub struct ChunkHash {
+	pub offset: u64,
+	pub size: usize,
+	pub hash: String,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ChunkHasherJob {
+	type Init = ChunkHasherJobInit;
+	type Data = ChunkHasherJobState;
+	type Step = ChunkHasherJobStep;
+
+	fn name(&self) -> &'static str {
+		CHUNK_HASHER_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let location = get_location(&library_ctx, state.init.location_id).await?;
+
+		// one path per file is enough, as every path of a file has the same content
+		let files = library_ctx
+			.db
+			._query_raw::<ChunkHasherJobStep>(raw!(
+				"SELECT files.id AS file_id, MIN(file_paths.materialized_path) AS materialized_path
+				FROM file_paths INNER JOIN files ON files.id = file_paths.file_id
+				WHERE file_paths.location_id = {} AND files.chunk_root IS NULL
+				GROUP BY files.id",
+				PrismaValue::Int(location.id as i64)
+			))
+			.await?;
+
+		info!(
+			"Found {} files without chunk hashes in location {}",
+			files.len(),
+			location.id
+		);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(files.len()),
+			JobReportUpdate::Message(format!("Preparing to hash {} files", files.len())),
+		]);
+
+		state.data = Some(ChunkHasherJobState {
+			location_path: location.path.unwrap_or_default(),
+		});
+		state.steps = files.into_iter().collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Hashing chunks of {}",
+			step.materialized_path
+		))]);
+
+		let path = data.location_path.join(&step.materialized_path);
+		let (chunks, root) = match tokio::task::spawn_blocking(move || hash_chunks(&path)).await? {
+			Ok(hashed) => hashed,
+			Err(e) => {
+				info!(
+					"Error hashing chunks of {}: {:#?}",
+					step.materialized_path, e
+				);
+				return Ok(());
+			}
+		};
+
+		let library_ctx = ctx.library_ctx();
+
+		// a previous run could have been interrupted mid file
+		library_ctx
+			.db
+			._execute_raw(raw!(
+				"DELETE FROM file_chunks WHERE file_id = {}",
+				PrismaValue::Int(step.file_id as i64)
+			))
+			.await?;
+
+		for (batch_index, batch) in chunks.chunks(CHUNK_INSERT_BATCH_SIZE).enumerate() {
+			let mut values = Vec::with_capacity(batch.len() * 5);
+			for (i, chunk) in batch.iter().enumerate() {
+				values.extend([
+					PrismaValue::Int(step.file_id as i64),
+					PrismaValue::Int((batch_index * CHUNK_INSERT_BATCH_SIZE + i) as i64),
+					PrismaValue::BigInt(chunk.offset as i64),
+					PrismaValue::Int(chunk.size as i64),
+					PrismaValue::String(chunk.hash.clone()),
+				]);
+			}
+
+			library_ctx
+				.db
+				._execute_raw(Raw::new(
+					&format!(
+						"INSERT INTO file_chunks (file_id, sequence, byte_offset, size, hash) VALUES {}",
+						vec!["({}, {}, {}, {}, {})"; batch.len()].join(", ")
+					),
+					values,
+				))
+				.await?;
+		}
+
+		library_ctx
+			.db
+			.file()
+			.find_unique(file::id::equals(step.file_id))
+			.update(vec![file::chunk_root::set(Some(root))])
+			.exec()
+			.await?;
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		_ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		info!(
+			"Finished hashing chunks for location {}",
+			state.init.location_id
+		);
+		Ok(())
+	}
+}
+
+// hash_chunks streams a file through FastCDC, returning the BLAKE3 hash of every chunk and a root
+// hash of the ordered chunk hashes, which identifies the full contents of the file
+pub fn hash_chunks(path: impl AsRef<Path>) -> Result<(Vec<ChunkHash>, String), io::Error> {
+	let file = BufReader::new(File::open(path)?);
+
+	let mut root = blake3::Hasher::new();
+	let mut chunks = Vec::new();
+
+	for chunk in StreamCDC::new(file, CHUNK_MIN_SIZE, CHUNK_AVG_SIZE, CHUNK_MAX_SIZE) {
+		let chunk = chunk.map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+		let hash = blake3::hash(&chunk.data);
+		root.update(hash.as_bytes());
+
+		chunks.push(ChunkHash {
+			offset: chunk.offset,
+			size: chunk.length,
+			hash: hash.to_hex().to_string(),
+		});
+	}
+
+	Ok((chunks, root.finalize().to_hex().to_string()))
+}
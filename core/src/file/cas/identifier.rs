@@ -271,7 +271,7 @@ pub async fn count_orphan_file_paths(
 ) -> Result<usize, FileError> {
 	let files_count = ctx.db
 		._query_raw::<CountRes>(raw!(
-			"SELECT COUNT(*) AS count FROM file_paths WHERE file_id IS NULL AND is_dir IS FALSE AND location_id = {}",
+			"SELECT COUNT(*) AS count FROM file_paths WHERE file_id IS NULL AND is_dir IS FALSE AND broken_link IS FALSE AND location_id = {}",
 			PrismaValue::Int(location_id)
 		))
 		.await?;
@@ -291,6 +291,8 @@ pub async fn get_orphan_file_paths(
 		.find_many(vec![
 			file_path::file_id::equals(None),
 			file_path::is_dir::equals(false),
+			// nothing to identify behind a broken link
+			file_path::broken_link::equals(false),
 		])
 		.order_by(file_path::id::order(Direction::Asc))
 		.cursor(file_path::id::cursor(cursor))
@@ -1,5 +1,7 @@
 mod checksum;
+mod chunker;
 mod identifier;
 
 pub use checksum::*;
+pub use chunker::*;
 pub use identifier::*;
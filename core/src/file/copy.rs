@@ -0,0 +1,1219 @@
+use crate::{
+	file::{
+		attributes::{self, AttributeLoss},
+		ensure_not_held,
+		fastcopy::{self, CopyMethod},
+		indexer::is_hidden_path,
+		preflight::{get_copy_preflight, plan_entry, InvalidNamePolicy, TargetNames, TargetRules},
+		sizes::{mark_folder_sizes_stale, FolderSizesJob, FolderSizesJobInit},
+		FileError,
+	},
+	job::{
+		JobError, JobReport, JobReportUpdate, JobResult, JobState, JobStatus, StatefulJob,
+		WorkerContext,
+	},
+	library::LibraryContext,
+	prisma::{file, file_path, job, location},
+	sys::{get_location, FileSystemCapabilities},
+	CoreEvent, Job,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	ffi::OsStr,
+	path::{Path, PathBuf},
+};
+use tokio::{fs, task::spawn_blocking};
+use ts_rs::TS;
+use uuid::Uuid;
+
+pub const FILE_COPY_JOB_NAME: &str = "file_copier";
+
+// What a copy or move does when something already exists at the target
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum ConflictPolicy {
+	Overwrite,
+	Skip,
+	// the entry is copied next to the existing one with a numbered name, eg: "photo (1).jpg"
+	KeepBoth,
+	// overwrites the existing entry only if the source was modified after it, skips it otherwise
+	OverwriteIfNewer,
+	// pauses the job on each conflict until the client answers with `ResolveFileConflict`
+	Ask,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum ConflictResolution {
+	Overwrite,
+	Skip,
+	KeepBoth,
+}
+
+// A conflict met by a copy or move and what was done about it
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ConflictOutcome {
+	pub source_path: String,
+	// where the entry ended up, or the existing entry if it was skipped
+	pub target_path: String,
+	pub resolution: ConflictResolution,
+}
+
+pub struct FileCopyJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileCopyJobInit {
+	pub file_path_ids: Vec<i32>,
+	pub target_location_id: i32,
+	// materialized path of the directory the entries are copied into, empty for the location root
+	pub target_path: String,
+	// a move removes the entries from where they were once copied
+	pub delete_source: bool,
+	pub conflict_policy: ConflictPolicy,
+	// for names the target volume can't take, see GetCopyPreflight
+	#[serde(default)]
+	pub invalid_names: InvalidNamePolicy,
+	// copies the alternate data streams of directories too on Windows, and reports those which
+	// couldn't be. Files keep theirs either way, as far as the target supports them
+	#[serde(default)]
+	pub alternate_streams: bool,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct FileCopyJobData {
+	// the answer to the conflict the job is paused on
+	pub answer: Option<ConflictResolution>,
+	// an answer given for every conflict left
+	pub answer_all: Option<ConflictResolution>,
+	pub outcomes: Vec<ConflictOutcome>,
+	// the naming rules of the target volume
+	#[serde(default)]
+	pub(crate) rules: Option<TargetRules>,
+	// entries renamed or left out for them
+	#[serde(default)]
+	pub renamed: usize,
+	#[serde(default)]
+	pub left_out: usize,
+	#[serde(default)]
+	pub attribute_losses: Vec<AttributeLoss>,
+	// files copied without writing their contents out, or only their data
+	#[serde(default)]
+	pub cloned: usize,
+	#[serde(default)]
+	pub sparse: usize,
+}
+
+impl FileCopyJobData {
+	fn add(&mut self, copied: CopiedContents) {
+		self.attribute_losses.extend(copied.losses);
+		self.cloned += copied.cloned;
+		self.sparse += copied.sparse;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileCopyJob {
+	type Init = FileCopyJobInit;
+	type Data = FileCopyJobData;
+	type Step = i32;
+
+	fn name(&self) -> &'static str {
+		FILE_COPY_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		target_parent_id(&library_ctx, &state.init).await?;
+		let target_root = location_path(&library_ctx, state.init.target_location_id).await?;
+
+		// the names are checked before anything is copied, rather than failing midway
+		if state.init.invalid_names == InvalidNamePolicy::Abort {
+			let preflight = get_copy_preflight(
+				&library_ctx,
+				state.init.file_path_ids.clone(),
+				state.init.target_location_id,
+				state.init.target_path.clone(),
+			)
+			.await?;
+			if !preflight.issues.is_empty() {
+				return Err(FileError::InvalidTargetNames(
+					preflight
+						.issues
+						.into_iter()
+						.map(|issue| issue.source_path)
+						.collect(),
+				)
+				.into());
+			}
+		}
+
+		info!(
+			"{} {} entries to {:?} of location {}",
+			if state.init.delete_source {
+				"Moving"
+			} else {
+				"Copying"
+			},
+			state.init.file_path_ids.len(),
+			state.init.target_path,
+			state.init.target_location_id
+		);
+
+		state.steps = state.init.file_path_ids.iter().copied().collect();
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+		state.data = Some(FileCopyJobData {
+			rules: Some(TargetRules::of(&target_root)),
+			..Default::default()
+		});
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+
+		let file_path = match library_ctx
+			.db
+			.file_path()
+			.find_unique(file_path::id::equals(state.steps[0]))
+			.exec()
+			.await?
+		{
+			Some(file_path) => file_path,
+			// removed since the job started
+			None => return Ok(()),
+		};
+
+		if state.init.delete_source {
+			if let Some(file_id) = file_path.file_id {
+				ensure_not_held(&library_ctx, file_id).await?;
+			}
+		}
+
+		let source_location_id = file_path.location_id.unwrap_or(0);
+		let source_root = location_path(&library_ctx, source_location_id).await?;
+		let target_root = location_path(&library_ctx, state.init.target_location_id).await?;
+		let rules = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state")
+			.rules
+			.get_or_insert_with(|| TargetRules::of(&target_root))
+			.clone();
+		let plan = plan_entry(
+			&rules,
+			&source_root,
+			&file_path,
+			&target_root,
+			&state.init.target_path,
+			state.init.invalid_names,
+			state.init.delete_source,
+		)
+		.await?;
+		if state.init.invalid_names == InvalidNamePolicy::Abort && !plan.issues.is_empty() {
+			return Err(FileError::InvalidTargetNames(
+				plan.issues
+					.into_iter()
+					.map(|issue| issue.source_path)
+					.collect(),
+			)
+			.into());
+		}
+		let names = plan.names;
+
+		let name = match names.name(&file_path.materialized_path, full_name(&file_path)) {
+			Some(name) => name,
+			None => {
+				let data = state
+					.data
+					.as_mut()
+					.expect("critical error: missing data on job state");
+				data.left_out += 1;
+				ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+					state.step_number + 1,
+				)]);
+				return Ok(());
+			}
+		};
+		let mut target_path = join_path(&state.init.target_path, &name);
+
+		let same_location = source_location_id == state.init.target_location_id;
+		if same_location && target_path == file_path.materialized_path {
+			// moving an entry to where it already is does nothing, copying it keeps both
+			if state.init.delete_source {
+				return Ok(());
+			}
+		} else if same_location
+			&& file_path.is_dir
+			&& target_path.starts_with(&format!("{}/", file_path.materialized_path))
+		{
+			return Err(FileError::CopyIntoItself(file_path.materialized_path).into());
+		}
+
+		let mut overwritten = false;
+		if fs::metadata(target_root.join(&target_path)).await.is_ok() {
+			let resolution = if target_path == file_path.materialized_path && same_location {
+				ConflictResolution::KeepBoth
+			} else {
+				match resolve(
+					&ctx,
+					state,
+					&source_root,
+					&target_root,
+					&file_path,
+					&target_path,
+				)
+				.await?
+				{
+					Some(resolution) => resolution,
+					None => {
+						return Err(JobError::Paused(rmp_serde::to_vec(&*state)?));
+					}
+				}
+			};
+
+			let resolution = match resolution {
+				ConflictResolution::Overwrite => {
+					match can_overwrite(&library_ctx, state.init.target_location_id, &target_path)
+						.await?
+					{
+						true => ConflictResolution::Overwrite,
+						false => ConflictResolution::Skip,
+					}
+				}
+				resolution => resolution,
+			};
+
+			let data = state
+				.data
+				.as_mut()
+				.expect("critical error: missing data on job state");
+			match resolution {
+				ConflictResolution::KeepBoth => {
+					target_path =
+						free_path(&target_root, &state.init.target_path, &file_path, &name).await;
+				}
+				ConflictResolution::Skip => {
+					data.outcomes.push(ConflictOutcome {
+						source_path: file_path.materialized_path,
+						target_path,
+						resolution,
+					});
+					ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+						state.step_number + 1,
+					)]);
+					return Ok(());
+				}
+				ConflictResolution::Overwrite => overwritten = true,
+			}
+			data.outcomes.push(ConflictOutcome {
+				source_path: file_path.materialized_path.clone(),
+				target_path: target_path.clone(),
+				resolution,
+			});
+		}
+
+		let source = source_root.join(&file_path.materialized_path);
+		let target = target_root.join(&target_path);
+		if let Some(parent) = target.parent() {
+			fs::create_dir_all(parent).await?;
+		}
+
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+		data.renamed += names.renamed();
+		data.left_out += names.left_out();
+
+		// an entry overwriting another is written aside, and only replaces it once complete
+		let written = match overwritten {
+			true => staging_path(&target),
+			false => target.clone(),
+		};
+		if state.init.delete_source {
+			// a rename fails across file systems, the entry is copied over then. So is one with
+			// contents to rename, which a rename would keep as they are
+			let moved = !names.changes_below(&file_path.materialized_path)
+				&& fs::rename(&source, &written).await.is_ok();
+			if moved {
+				if overwritten {
+					if let Err(e) = replace_target(&written, &target).await {
+						// the entry moved aside goes back to where it was
+						fs::rename(&written, &source).await.ok();
+						return Err(e.into());
+					}
+				}
+			} else {
+				let copied = copy_entry(
+					CopiedEntry {
+						source: source.clone(),
+						target: written.clone(),
+						source_path: file_path.materialized_path.clone(),
+						target_path: target_path.clone(),
+					},
+					&names,
+					state.init.alternate_streams,
+					rules.capabilities,
+				)
+				.await;
+				let copied = match overwritten {
+					true => place_staged(copied, &written, &target).await,
+					false => copied,
+				}?;
+				data.add(copied);
+				remove_entry(&source).await?;
+			}
+		} else {
+			let copied = copy_entry(
+				CopiedEntry {
+					source,
+					target: written.clone(),
+					source_path: file_path.materialized_path.clone(),
+					target_path: target_path.clone(),
+				},
+				&names,
+				state.init.alternate_streams,
+				rules.capabilities,
+			)
+			.await;
+			let copied = match overwritten {
+				true => place_staged(copied, &written, &target).await,
+				false => copied,
+			}?;
+			data.add(copied);
+		}
+
+		if overwritten {
+			remove_target_rows(&library_ctx, state.init.target_location_id, &target_path).await?;
+		}
+		let parent_id = target_parent_id(&library_ctx, &state.init).await?;
+		if state.init.delete_source {
+			move_rows(
+				&library_ctx,
+				&file_path,
+				state.init.target_location_id,
+				&target_path,
+				parent_id,
+				&names,
+			)
+			.await?;
+		} else {
+			copy_rows(
+				&library_ctx,
+				&file_path,
+				state.init.target_location_id,
+				&target_path,
+				parent_id,
+				&names,
+			)
+			.await?;
+		}
+
+		// a move changes the size of the directory it leaves too
+		let mut changed_dirs = parent_id.into_iter().collect::<Vec<_>>();
+		if state.init.delete_source {
+			changed_dirs.extend(file_path.parent_id);
+		}
+		mark_folder_sizes_stale(&library_ctx, changed_dirs).await?;
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let data = state.data.take().unwrap_or_default();
+		let outcomes = data.outcomes;
+		if data.renamed + data.left_out > 0 {
+			info!(
+				"{} entries renamed and {} left out for the target volume",
+				data.renamed, data.left_out
+			);
+		}
+		if data.cloned + data.sparse > 0 {
+			info!(
+				"{} files cloned and {} copied around their holes",
+				data.cloned, data.sparse
+			);
+		}
+
+		ctx.library_ctx()
+			.queue_job(Job::new(
+				FolderSizesJobInit::default(),
+				Box::new(FolderSizesJob {}),
+			))
+			.await;
+
+		if !outcomes.is_empty() {
+			let count = |resolution| {
+				outcomes
+					.iter()
+					.filter(|outcome| outcome.resolution == resolution)
+					.count()
+			};
+			ctx.progress(vec![JobReportUpdate::Message(format!(
+				"{} conflicts: {} overwritten, {} skipped, {} kept both",
+				outcomes.len(),
+				count(ConflictResolution::Overwrite),
+				count(ConflictResolution::Skip),
+				count(ConflictResolution::KeepBoth),
+			))]);
+
+			let library_ctx = ctx.library_ctx();
+			library_ctx
+				.emit(CoreEvent::FileConflictOutcomes {
+					library_id: library_ctx.id,
+					job_id: ctx.job_id(),
+					outcomes,
+				})
+				.await;
+		}
+
+		let losses = data.attribute_losses;
+		if !losses.is_empty() {
+			for loss in &losses {
+				warn!(
+					"Couldn't copy {:?} {:?} of {:?} to {:?}: {}",
+					loss.kind, loss.name, loss.source_path, loss.target_path, loss.error
+				);
+			}
+			ctx.progress(vec![JobReportUpdate::Message(format!(
+				"{} attributes couldn't be copied",
+				losses.len()
+			))]);
+
+			let library_ctx = ctx.library_ctx();
+			library_ctx
+				.emit(CoreEvent::FileAttributeLosses {
+					library_id: library_ctx.id,
+					job_id: ctx.job_id(),
+					losses,
+				})
+				.await;
+		}
+
+		Ok(())
+	}
+}
+
+// what to do about an existing target, or `None` if the job has to wait for the client to answer
+async fn resolve(
+	ctx: &WorkerContext,
+	state: &mut JobState<FileCopyJobInit, FileCopyJobData, i32>,
+	source_root: &Path,
+	target_root: &Path,
+	file_path: &file_path::Data,
+	target_path: &str,
+) -> Result<Option<ConflictResolution>, JobError> {
+	let data = state
+		.data
+		.as_mut()
+		.expect("critical error: missing data on job state");
+
+	Ok(match state.init.conflict_policy {
+		ConflictPolicy::Overwrite => Some(ConflictResolution::Overwrite),
+		ConflictPolicy::Skip => Some(ConflictResolution::Skip),
+		ConflictPolicy::KeepBoth => Some(ConflictResolution::KeepBoth),
+		ConflictPolicy::OverwriteIfNewer => {
+			let source = fs::metadata(source_root.join(&file_path.materialized_path))
+				.await?
+				.modified()?;
+			let target = fs::metadata(target_root.join(target_path))
+				.await?
+				.modified()?;
+			Some(match source > target {
+				true => ConflictResolution::Overwrite,
+				false => ConflictResolution::Skip,
+			})
+		}
+		ConflictPolicy::Ask => match data.answer.take().or(data.answer_all) {
+			Some(answer) => Some(answer),
+			None => {
+				let library_ctx = ctx.library_ctx();
+				library_ctx
+					.emit(CoreEvent::FileConflict {
+						library_id: library_ctx.id,
+						job_id: ctx.job_id(),
+						source_path: file_path.materialized_path.clone(),
+						target_path: target_path.to_string(),
+					})
+					.await;
+				None
+			}
+		},
+	})
+}
+
+// answers the conflict a copy or move is paused on and resumes it
+pub async fn resolve_conflict(
+	ctx: &LibraryContext,
+	job_id: Uuid,
+	resolution: ConflictResolution,
+	apply_to_all: bool,
+) -> Result<(), JobError> {
+	let mut report = ctx
+		.db
+		.job()
+		.find_unique(job::id::equals(job_id.as_bytes().to_vec()))
+		.exec()
+		.await?
+		.map(JobReport::from)
+		.filter(|report| report.name == FILE_COPY_JOB_NAME && report.status == JobStatus::Paused)
+		.ok_or(FileError::NoPendingConflict(job_id))?;
+
+	let mut state: JobState<FileCopyJobInit, FileCopyJobData, i32> = rmp_serde::from_slice(
+		report
+			.data
+			.as_ref()
+			.ok_or(FileError::NoPendingConflict(job_id))?,
+	)?;
+	if let Some(data) = state.data.as_mut() {
+		data.answer = Some(resolution);
+		if apply_to_all {
+			data.answer_all = Some(resolution);
+		}
+	}
+	report.data = Some(rmp_serde::to_vec(&state)?);
+
+	ctx.spawn_job(Job::resume(report, Box::new(FileCopyJob {}))?)
+		.await;
+
+	Ok(())
+}
+
+// whether the entry at the target can be overwritten, which it can't if a file there is under legal
+// hold
+async fn can_overwrite(
+	ctx: &LibraryContext,
+	location_id: i32,
+	path: &str,
+) -> Result<bool, FileError> {
+	let rows = ctx
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::equals(path.to_string()),
+		])
+		.exec()
+		.await?;
+
+	for row in &rows {
+		if let Some(file_id) = row.file_id {
+			if let Err(e) = ensure_not_held(ctx, file_id).await {
+				warn!("Not overwriting {:?}: {}", path, e);
+				return Ok(false);
+			}
+		}
+	}
+
+	Ok(true)
+}
+
+// removes the rows of an entry which was overwritten, and those below it
+async fn remove_target_rows(
+	ctx: &LibraryContext,
+	location_id: i32,
+	path: &str,
+) -> Result<(), FileError> {
+	ctx.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::starts_with(format!("{}/", path)),
+		])
+		.delete()
+		.exec()
+		.await?;
+	ctx.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::equals(path.to_string()),
+		])
+		.delete()
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+// a hidden sibling of the target an entry overwriting it is written to, so the target is left
+// whole until the entry is complete
+fn staging_path(target: &Path) -> PathBuf {
+	target.with_file_name(format!(".{}.part", Uuid::new_v4()))
+}
+
+// puts an entry written aside in place of the one it overwrites, which is only removed then. A
+// file replaces another in a single rename, a directory can't be renamed over so the entry it
+// replaces is moved aside until it is in place
+async fn replace_target(staged: &Path, target: &Path) -> Result<(), std::io::Error> {
+	let is_dir = |metadata: std::fs::Metadata| metadata.is_dir();
+	if !fs::metadata(staged).await.map_or(false, is_dir)
+		&& !fs::metadata(target).await.map_or(false, is_dir)
+	{
+		return fs::rename(staged, target).await;
+	}
+
+	let replaced = staging_path(target);
+	fs::rename(target, &replaced).await?;
+	if let Err(e) = fs::rename(staged, target).await {
+		fs::rename(&replaced, target).await.ok();
+		return Err(e);
+	}
+	remove_entry(&replaced).await
+}
+
+// puts a copy written aside in place of the entry it overwrites, or removes what was written of it
+// if either the copy or that failed
+async fn place_staged<T>(
+	copied: Result<T, std::io::Error>,
+	staged: &Path,
+	target: &Path,
+) -> Result<T, std::io::Error> {
+	let placed = match copied {
+		Ok(copied) => replace_target(staged, target).await.map(|_| copied),
+		Err(e) => Err(e),
+	};
+	if placed.is_err() {
+		remove_entry(staged).await.ok();
+	}
+	placed
+}
+
+async fn remove_entry(path: &Path) -> Result<(), std::io::Error> {
+	match fs::metadata(path).await?.is_dir() {
+		true => fs::remove_dir_all(path).await,
+		false => fs::remove_file(path).await,
+	}
+}
+
+// the rows below a directory, parents first
+async fn descendants(
+	ctx: &LibraryContext,
+	file_path: &file_path::Data,
+) -> Result<Vec<file_path::Data>, FileError> {
+	if !file_path.is_dir {
+		return Ok(vec![]);
+	}
+
+	let mut rows = ctx
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(file_path.location_id),
+			file_path::materialized_path::starts_with(format!("{}/", file_path.materialized_path)),
+		])
+		.exec()
+		.await?;
+	rows.sort_by_key(|row| row.materialized_path.matches('/').count());
+
+	Ok(rows)
+}
+
+// the rows keep their ids when moved, so their tags and history still apply
+async fn move_rows(
+	ctx: &LibraryContext,
+	file_path: &file_path::Data,
+	location_id: i32,
+	target_path: &str,
+	parent_id: Option<i32>,
+	names: &TargetNames,
+) -> Result<(), FileError> {
+	for row in descendants(ctx, file_path).await? {
+		// nothing is left out of a move, see plan_entry
+		let path = names
+			.rebase(
+				&row.materialized_path,
+				&file_path.materialized_path,
+				target_path,
+			)
+			.unwrap_or_default();
+		let (name, extension) = split_target_name(&row, &path);
+		ctx.db
+			.file_path()
+			.find_unique(file_path::id::equals(row.id))
+			.update(vec![
+				file_path::location::link(location::id::equals(location_id)),
+				file_path::materialized_path::set(path),
+				file_path::name::set(name),
+				file_path::extension::set(extension),
+			])
+			.exec()
+			.await?;
+	}
+
+	let (name, extension) = split_target_name(file_path, target_path);
+	ctx.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path.id))
+		.update(vec![
+			file_path::location::link(location::id::equals(location_id)),
+			file_path::materialized_path::set(target_path.to_string()),
+			file_path::name::set(name),
+			file_path::extension::set(extension),
+			file_path::parent_id::set(parent_id),
+		])
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+// copies share the file of their source, as they have the same contents
+async fn copy_rows(
+	ctx: &LibraryContext,
+	file_path: &file_path::Data,
+	location_id: i32,
+	target_path: &str,
+	parent_id: Option<i32>,
+	names: &TargetNames,
+) -> Result<(), FileError> {
+	let mut ids = HashMap::new();
+
+	let copy = create_row(
+		ctx,
+		file_path,
+		location_id,
+		target_path.to_string(),
+		parent_id,
+	)
+	.await?;
+	ids.insert(file_path.id, copy.id);
+
+	for row in descendants(ctx, file_path).await? {
+		let path = match names.rebase(
+			&row.materialized_path,
+			&file_path.materialized_path,
+			target_path,
+		) {
+			Some(path) => path,
+			// left out, with its contents
+			None => continue,
+		};
+		let parent_id = row.parent_id.and_then(|id| ids.get(&id).copied());
+		let copy = create_row(ctx, &row, location_id, path, parent_id).await?;
+		ids.insert(row.id, copy.id);
+	}
+
+	Ok(())
+}
+
+async fn create_row(
+	ctx: &LibraryContext,
+	row: &file_path::Data,
+	location_id: i32,
+	materialized_path: String,
+	parent_id: Option<i32>,
+) -> Result<file_path::Data, FileError> {
+	let (name, extension) = split_target_name(row, &materialized_path);
+	let mut params = vec![
+		file_path::is_dir::set(row.is_dir),
+		file_path::location::link(location::id::equals(location_id)),
+		file_path::extension::set(extension),
+		file_path::parent_id::set(parent_id),
+		file_path::hidden::set(is_hidden_path(&materialized_path)),
+	];
+	if let Some(file_id) = row.file_id {
+		params.push(file_path::file::link(file::id::equals(file_id)));
+	}
+
+	Ok(ctx
+		.db
+		.file_path()
+		.create(
+			file_path::materialized_path::set(materialized_path),
+			file_path::name::set(name),
+			params,
+		)
+		.exec()
+		.await?)
+}
+
+// An entry copied, with the materialized paths its attributes are reported by
+struct CopiedEntry {
+	source: PathBuf,
+	target: PathBuf,
+	source_path: String,
+	target_path: String,
+}
+
+impl CopiedEntry {
+	// an entry of this directory, copied as `target_name` or its own name
+	fn join(&self, name: &OsStr, target_name: Option<&str>) -> Self {
+		let name_lossy = name.to_string_lossy();
+		let target = match target_name {
+			Some(target_name) => self.target.join(target_name),
+			None => self.target.join(name),
+		};
+		Self {
+			source: self.source.join(name),
+			target,
+			source_path: format!("{}/{}", self.source_path, name_lossy),
+			target_path: format!(
+				"{}/{}",
+				self.target_path,
+				target_name.unwrap_or(&name_lossy)
+			),
+		}
+	}
+
+	// copies what the contents were copied without, see attributes::preserve
+	async fn preserve(&self, streams: bool) -> Vec<AttributeLoss> {
+		let (source, target) = (self.source.clone(), self.target.clone());
+		spawn_blocking(move || attributes::preserve(&source, &target, streams))
+			.await
+			.unwrap_or_default()
+			.into_iter()
+			.map(|lost| AttributeLoss {
+				source_path: self.source_path.clone(),
+				target_path: self.target_path.clone(),
+				kind: lost.kind,
+				name: lost.name,
+				error: lost.error.to_string(),
+			})
+			.collect()
+	}
+}
+
+// What copying an entry did beside writing its contents
+#[derive(Default)]
+struct CopiedContents {
+	// the attributes which couldn't be copied along
+	losses: Vec<AttributeLoss>,
+	cloned: usize,
+	sparse: usize,
+}
+
+impl CopiedContents {
+	// copies a file the cheapest way the target allows, see fastcopy::copy_file
+	async fn copy_file(
+		&mut self,
+		entry: &CopiedEntry,
+		streams: bool,
+		capabilities: FileSystemCapabilities,
+	) -> Result<(), std::io::Error> {
+		let (source, target) = (entry.source.clone(), entry.target.clone());
+		let method = spawn_blocking(move || fastcopy::copy_file(&source, &target, capabilities))
+			.await
+			.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))??;
+		match method {
+			CopyMethod::Cloned => self.cloned += 1,
+			CopyMethod::Sparse => self.sparse += 1,
+			CopyMethod::Streamed => {}
+		}
+		self.losses.extend(entry.preserve(streams).await);
+
+		Ok(())
+	}
+}
+
+// copies a file, or a directory with its contents, under the names given
+async fn copy_entry(
+	entry: CopiedEntry,
+	names: &TargetNames,
+	streams: bool,
+	capabilities: FileSystemCapabilities,
+) -> Result<CopiedContents, std::io::Error> {
+	let mut copied = CopiedContents::default();
+	if !fs::metadata(&entry.source).await?.is_dir() {
+		copied.copy_file(&entry, streams, capabilities).await?;
+		return Ok(copied);
+	}
+
+	let mut copied_dirs = vec![];
+	let mut dirs = vec![entry];
+	while let Some(dir) = dirs.pop() {
+		fs::create_dir_all(&dir.target).await?;
+
+		let mut entries = fs::read_dir(&dir.source).await?;
+		while let Some(child) = entries.next_entry().await? {
+			let name = child.file_name();
+			let path = format!("{}/{}", dir.source_path, name.to_string_lossy());
+			let child_entry = match names.get(&path) {
+				Some(Some(target_name)) => dir.join(&name, Some(target_name)),
+				Some(None) => continue,
+				None => dir.join(&name, None),
+			};
+			match child.file_type().await?.is_dir() {
+				true => dirs.push(child_entry),
+				false => {
+					copied
+						.copy_file(&child_entry, streams, capabilities)
+						.await?
+				}
+			}
+		}
+		copied_dirs.push(dir);
+	}
+
+	// once their contents are in, as an ACL could keep them from being written
+	for dir in copied_dirs.iter().rev() {
+		copied.losses.extend(dir.preserve(streams).await);
+	}
+
+	Ok(copied)
+}
+
+// the first numbered name nothing exists at, eg: "photo (2).jpg"
+async fn free_path(root: &Path, dir: &str, file_path: &file_path::Data, name: &str) -> String {
+	let (stem, extension) = split_target_name(file_path, name);
+	let mut n = 1;
+	loop {
+		let name = numbered_name(&stem, n, extension.as_deref(), file_path.is_dir);
+		let path = join_path(dir, &name);
+		if fs::metadata(root.join(&path)).await.is_err() {
+			return path;
+		}
+		n += 1;
+	}
+}
+
+async fn target_parent_id(
+	ctx: &LibraryContext,
+	init: &FileCopyJobInit,
+) -> Result<Option<i32>, FileError> {
+	if init.target_path.is_empty() {
+		return Ok(None);
+	}
+
+	let directory = ctx
+		.db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(Some(init.target_location_id)),
+			file_path::materialized_path::equals(init.target_path.clone()),
+			file_path::is_dir::equals(true),
+		])
+		.exec()
+		.await?
+		.ok_or_else(|| FileError::DirectoryNotFound(PathBuf::from(&init.target_path)))?;
+
+	Ok(Some(directory.id))
+}
+
+async fn location_path(ctx: &LibraryContext, location_id: i32) -> Result<PathBuf, FileError> {
+	get_location(ctx, location_id)
+		.await?
+		.path
+		.ok_or(FileError::LocationUnavailable(location_id))
+}
+
+pub(crate) fn full_name(file_path: &file_path::Data) -> String {
+	entry_name(
+		&file_path.name,
+		file_path.extension.as_deref(),
+		file_path.is_dir,
+	)
+}
+
+pub(crate) fn entry_name(name: &str, extension: Option<&str>, is_dir: bool) -> String {
+	match extension {
+		Some(extension) if !is_dir && !extension.is_empty() => format!("{}.{}", name, extension),
+		_ => name.to_string(),
+	}
+}
+
+// the name of an entry moved to `target_path`, without its extension
+pub(crate) fn target_name(file_path: &file_path::Data, target_path: &str) -> String {
+	name_stem(
+		target_path,
+		file_path.extension.as_deref(),
+		file_path.is_dir,
+	)
+}
+
+fn name_stem(path: &str, extension: Option<&str>, is_dir: bool) -> String {
+	let name = path.rsplit('/').next().unwrap_or(path);
+	match extension {
+		Some(extension) if !is_dir && !extension.is_empty() => name
+			.strip_suffix(&format!(".{}", extension))
+			.unwrap_or(name)
+			.to_string(),
+		_ => name.to_string(),
+	}
+}
+
+// the name and extension of a row written to `path`. A sanitized name can change its extension too
+fn split_target_name(row: &file_path::Data, path: &str) -> (String, Option<String>) {
+	split_name(path, row.extension.as_deref(), row.is_dir)
+}
+
+fn split_name(path: &str, extension: Option<&str>, is_dir: bool) -> (String, Option<String>) {
+	let name = path.rsplit('/').next().unwrap_or(path);
+	match extension {
+		Some(original)
+			if !is_dir && !original.is_empty() && !name.ends_with(&format!(".{}", original)) =>
+		{
+			match name.rsplit_once('.') {
+				Some((stem, extension)) if !stem.is_empty() => {
+					(stem.to_string(), Some(extension.to_string()))
+				}
+				_ => (name.to_string(), None),
+			}
+		}
+		_ => (
+			name_stem(path, extension, is_dir),
+			extension.map(str::to_string),
+		),
+	}
+}
+
+// the name of the `n`th copy kept next to an entry, eg: "photo (1).jpg"
+fn numbered_name(stem: &str, n: usize, extension: Option<&str>, is_dir: bool) -> String {
+	entry_name(&format!("{} ({})", stem, n), extension, is_dir)
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+	match dir.is_empty() {
+		true => name.to_string(),
+		false => format!("{}/{}", dir, name),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn numbers_copies_before_the_extension() {
+		let (stem, extension) = split_name("photos/photo.jpg", Some("jpg"), false);
+		assert_eq!(
+			(stem.as_str(), extension.as_deref()),
+			("photo", Some("jpg"))
+		);
+		assert_eq!(
+			numbered_name(&stem, 1, extension.as_deref(), false),
+			"photo (1).jpg"
+		);
+		assert_eq!(
+			numbered_name(&stem, 12, extension.as_deref(), false),
+			"photo (12).jpg"
+		);
+	}
+
+	#[test]
+	fn numbers_directories_after_their_whole_name() {
+		let (stem, extension) = split_name("albums/2022.summer", None, true);
+		assert_eq!(stem, "2022.summer");
+		assert_eq!(
+			numbered_name(&stem, 1, extension.as_deref(), true),
+			"2022.summer (1)"
+		);
+		// an extension set on a directory isn't split off either
+		assert_eq!(numbered_name("2022", 2, Some("summer"), true), "2022 (2)");
+	}
+
+	#[test]
+	fn numbers_names_without_an_extension() {
+		let (stem, extension) = split_name("Makefile", None, false);
+		assert_eq!(
+			numbered_name(&stem, 1, extension.as_deref(), false),
+			"Makefile (1)"
+		);
+
+		let (stem, extension) = split_name("notes", Some(""), false);
+		assert_eq!(
+			numbered_name(&stem, 3, extension.as_deref(), false),
+			"notes (3)"
+		);
+	}
+
+	#[test]
+	fn takes_the_extension_of_a_sanitized_name() {
+		// sanitizing "report.txt." for Windows leaves "report.txt_"
+		assert_eq!(
+			split_name("docs/report.txt_", Some("txt"), false),
+			("report".to_string(), Some("txt_".to_string()))
+		);
+		// a name of a dot and an extension keeps it whole
+		assert_eq!(
+			split_name(".env_", Some("env"), false),
+			(".env_".to_string(), None)
+		);
+	}
+
+	#[test]
+	fn joins_names_to_their_directory() {
+		assert_eq!(join_path("", "photo (1).jpg"), "photo (1).jpg");
+		assert_eq!(
+			join_path("photos/2022", "photo (1).jpg"),
+			"photos/2022/photo (1).jpg"
+		);
+	}
+
+	#[test]
+	fn names_entries_with_their_extension() {
+		assert_eq!(entry_name("photo", Some("jpg"), false), "photo.jpg");
+		assert_eq!(entry_name("photo", Some(""), false), "photo");
+		assert_eq!(entry_name("photos", Some("jpg"), true), "photos");
+		assert_eq!(name_stem("photos/photo.jpg", Some("jpg"), false), "photo");
+		assert_eq!(
+			name_stem("photos/photo.jpeg", Some("jpg"), false),
+			"photo.jpeg"
+		);
+	}
+
+	#[tokio::test]
+	async fn overwrites_a_file_in_a_single_rename() {
+		let dir = tempfile::tempdir().unwrap();
+		let target = dir.path().join("photo.jpg");
+		let staged = staging_path(&target);
+		std::fs::write(&target, "old").unwrap();
+		std::fs::write(&staged, "new").unwrap();
+
+		replace_target(&staged, &target).await.unwrap();
+		assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+		assert!(!staged.exists());
+	}
+
+	#[tokio::test]
+	async fn overwrites_a_directory_once_its_replacement_is_in_place() {
+		let dir = tempfile::tempdir().unwrap();
+		let target = dir.path().join("photos");
+		let staged = staging_path(&target);
+		std::fs::create_dir_all(target.join("2019")).unwrap();
+		std::fs::write(target.join("2019/old.jpg"), "old").unwrap();
+		std::fs::create_dir(&staged).unwrap();
+		std::fs::write(staged.join("new.jpg"), "new").unwrap();
+
+		replace_target(&staged, &target).await.unwrap();
+		assert!(target.join("new.jpg").exists());
+		assert!(!target.join("2019").exists());
+		// neither the staged entry nor the one replaced are left behind
+		assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+	}
+
+	#[tokio::test]
+	async fn keeps_the_target_when_the_copy_fails() {
+		let dir = tempfile::tempdir().unwrap();
+		let target = dir.path().join("photo.jpg");
+		let staged = staging_path(&target);
+		std::fs::write(&target, "old").unwrap();
+		// what a copy which failed part way left
+		std::fs::write(&staged, "ne").unwrap();
+
+		let copied = Err::<(), _>(std::io::Error::new(std::io::ErrorKind::Other, "disk full"));
+		assert!(place_staged(copied, &staged, &target).await.is_err());
+		assert_eq!(std::fs::read_to_string(&target).unwrap(), "old");
+		assert!(!staged.exists());
+	}
+}
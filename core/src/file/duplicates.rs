@@ -0,0 +1,497 @@
+use crate::{
+	encode::hamming_distance,
+	file::{ensure_not_held, sizes::invalidate_folder_sizes, File, FileError, FilePath},
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::LibraryContext,
+	prisma::{
+		self, duplicate_group, file, file_path, file_path_in_duplicate_group, tag, tag_on_file,
+	},
+	sys::{get_locations, LocationResource},
+	ClientQuery, CoreError, CoreEvent, CoreResponse, LibraryQuery,
+};
+use int_enum::IntEnum;
+use log::info;
+use prisma_client_rust::{prisma_models::PrismaValue, raw, raw::Raw};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
+use tokio::fs;
+use ts_rs::TS;
+
+static GROUPS_PER_STEP: usize = 100;
+pub const DUPLICATE_FINDER_JOB_NAME: &str = "find_duplicates";
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum DuplicateKind {
+	// every path in the group has the same cas_id
+	Exact = 0,
+	// the paths belong to different images with a close perceptual hash
+	Similar = 1,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(tag = "key", content = "params")]
+#[ts(export)]
+pub enum DuplicateResolution {
+	// delete every other path from disk and from the library
+	KeepOneDeleteRest,
+	// replace every other path with a hardlink to the kept path, only valid for exact duplicates
+	Hardlink,
+	// only assign a tag to the other paths, so they can be reviewed later
+	TagOnly { tag_id: i32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DuplicateFilePath {
+	pub file_path: FilePath,
+	pub location: Option<LocationResource>,
+	// the absolute path of this file on the node that owns the location
+	pub full_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DuplicateGroup {
+	pub id: i32,
+	pub kind: DuplicateKind,
+	pub file_paths: Vec<DuplicateFilePath>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SimilarImage {
+	pub file: File,
+	pub distance: u32,
+}
+
+pub struct DuplicateFinderJob {}
+
+// DuplicateFinderJobInit groups every file stored more than once in the library, and optionally
+// images whose perceptual hashes are at most `similar_images_max_distance` bits apart
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DuplicateFinderJobInit {
+	pub similar_images_max_distance: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DuplicateFinderJobState {
+	total_groups: usize,
+	completed_groups: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum DuplicateFinderJobStep {
+	// files with more than one path, the paths of each file become a group
+	Exact(Vec<i32>),
+	// visually similar image files, the paths of all of them become a single group
+	Similar(Vec<i32>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DuplicateCandidate {
+	file_id: i32,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for DuplicateFinderJob {
+	type Init = DuplicateFinderJobInit;
+	type Data = DuplicateFinderJobState;
+	type Step = DuplicateFinderJobStep;
+
+	fn name(&self) -> &'static str {
+		DUPLICATE_FINDER_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+
+		// results of a previous run are stale, every group is recomputed from scratch
+		library_ctx
+			.db
+			.file_path_in_duplicate_group()
+			.find_many(vec![])
+			.delete()
+			.exec()
+			.await?;
+		library_ctx
+			.db
+			.duplicate_group()
+			.find_many(vec![])
+			.delete()
+			.exec()
+			.await?;
+
+		// a file with more than one path is the same content stored more than once
+		let candidates = library_ctx
+			.db
+			._query_raw::<DuplicateCandidate>(raw!(
+				"SELECT file_id FROM file_paths WHERE file_id IS NOT NULL AND is_dir IS FALSE
+				GROUP BY file_id HAVING COUNT(*) > 1"
+			))
+			.await?;
+
+		info!("Found {} files stored more than once", candidates.len());
+
+		let file_ids = candidates
+			.into_iter()
+			.map(|candidate| candidate.file_id)
+			.collect::<Vec<_>>();
+
+		let similar_images = match state.init.similar_images_max_distance {
+			Some(max_distance) => {
+				cluster_similar_images(get_perceptual_hashes(&library_ctx).await?, max_distance)
+			}
+			None => vec![],
+		};
+
+		let total_groups = file_ids.len() + similar_images.len();
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(total_groups),
+			JobReportUpdate::Message(format!("Grouping {} duplicates", total_groups)),
+		]);
+
+		state.data = Some(DuplicateFinderJobState {
+			total_groups,
+			completed_groups: 0,
+		});
+		state.steps = file_ids
+			.chunks(GROUPS_PER_STEP)
+			.map(|chunk| DuplicateFinderJobStep::Exact(chunk.to_vec()))
+			.chain(
+				similar_images
+					.into_iter()
+					.map(DuplicateFinderJobStep::Similar),
+			)
+			.collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+
+		let completed = match &state.steps[0] {
+			DuplicateFinderJobStep::Exact(file_ids) => {
+				for file_id in file_ids {
+					create_group(&library_ctx, DuplicateKind::Exact, &[*file_id]).await?;
+				}
+				file_ids.len()
+			}
+			DuplicateFinderJobStep::Similar(file_ids) => {
+				create_group(&library_ctx, DuplicateKind::Similar, file_ids).await?;
+				1
+			}
+		};
+
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+		data.completed_groups += completed;
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			data.completed_groups,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+		info!("Found {} duplicate groups", data.total_groups);
+
+		let library_ctx = ctx.library_ctx();
+		library_ctx
+			.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+				library_id: library_ctx.id,
+				query: LibraryQuery::GetDuplicateGroups,
+			}))
+			.await;
+
+		Ok(())
+	}
+}
+
+// create_group stores every non directory path of the given files as one duplicate group
+async fn create_group(
+	ctx: &LibraryContext,
+	kind: DuplicateKind,
+	file_ids: &[i32],
+) -> Result<(), prisma::QueryError> {
+	let group = ctx
+		.db
+		.duplicate_group()
+		.create(vec![duplicate_group::kind::set(kind.int_value())])
+		.exec()
+		.await?;
+
+	let mut values = vec![PrismaValue::Int(group.id as i64)];
+	values.extend(file_ids.iter().map(|id| PrismaValue::Int(*id as i64)));
+
+	ctx.db
+		._execute_raw(Raw::new(
+			&format!(
+				"INSERT INTO file_path_in_duplicate_group (group_id, file_path_id)
+				SELECT {{}}, id FROM file_paths WHERE is_dir IS FALSE AND file_id IN ({})",
+				vec!["{}"; file_ids.len()].join(", ")
+			),
+			values,
+		))
+		.await?;
+
+	Ok(())
+}
+
+async fn get_perceptual_hashes(
+	ctx: &LibraryContext,
+) -> Result<Vec<(i32, u64)>, prisma::QueryError> {
+	Ok(ctx
+		.db
+		.file()
+		.find_many(vec![file::perceptual_hash::not(None)])
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file| Some((file.id, file.perceptual_hash? as u64)))
+		.collect())
+}
+
+// every image joins the first cluster whose first image is close enough, clusters of one are dropped
+fn cluster_similar_images(hashes: Vec<(i32, u64)>, max_distance: u32) -> Vec<Vec<i32>> {
+	let mut clusters: Vec<(u64, Vec<i32>)> = vec![];
+
+	for (file_id, hash) in hashes {
+		match clusters
+			.iter_mut()
+			.find(|(seed, _)| hamming_distance(*seed, hash) <= max_distance)
+		{
+			Some((_, file_ids)) => file_ids.push(file_id),
+			None => clusters.push((hash, vec![file_id])),
+		}
+	}
+
+	clusters
+		.into_iter()
+		.map(|(_, file_ids)| file_ids)
+		.filter(|file_ids| file_ids.len() > 1)
+		.collect()
+}
+
+// returns images with a perceptual hash at most `max_distance` bits away, closest first
+pub async fn get_similar_images(
+	ctx: &LibraryContext,
+	file_id: i32,
+	max_distance: u32,
+) -> Result<Vec<SimilarImage>, FileError> {
+	let hash = ctx
+		.db
+		.file()
+		.find_unique(file::id::equals(file_id))
+		.exec()
+		.await?
+		.and_then(|file| file.perceptual_hash)
+		.ok_or(FileError::PerceptualHashNotFound(file_id))? as u64;
+
+	let mut similar = ctx
+		.db
+		.file()
+		.find_many(vec![
+			file::perceptual_hash::not(None),
+			file::id::not(file_id),
+		])
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file| {
+			let distance = hamming_distance(hash, file.perceptual_hash? as u64);
+			(distance <= max_distance).then(|| SimilarImage {
+				file: file.into(),
+				distance,
+			})
+		})
+		.collect::<Vec<_>>();
+
+	similar.sort_by_key(|image| image.distance);
+
+	Ok(similar)
+}
+
+pub async fn get_duplicate_groups(ctx: &LibraryContext) -> Result<Vec<DuplicateGroup>, FileError> {
+	let locations = get_locations(ctx)
+		.await?
+		.into_iter()
+		.map(|location| (location.id, location))
+		.collect::<HashMap<_, _>>();
+
+	let groups = ctx
+		.db
+		.duplicate_group()
+		.find_many(vec![])
+		.with(duplicate_group::file_paths::fetch(vec![]))
+		.exec()
+		.await?;
+
+	let file_path_ids = groups
+		.iter()
+		.flat_map(|group| group.file_paths.iter().flatten())
+		.map(|member| member.file_path_id)
+		.collect::<Vec<_>>();
+
+	let mut file_paths = ctx
+		.db
+		.file_path()
+		.find_many(vec![file_path::id::in_vec(file_path_ids)])
+		.with(file_path::file::fetch())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| (file_path.id, FilePath::from(file_path)))
+		.collect::<HashMap<_, _>>();
+
+	Ok(groups
+		.into_iter()
+		.map(|group| DuplicateGroup {
+			id: group.id,
+			kind: DuplicateKind::from_int(group.kind).unwrap_or(DuplicateKind::Exact),
+			file_paths: group
+				.file_paths
+				.unwrap_or_default()
+				.into_iter()
+				.filter_map(|member| file_paths.remove(&member.file_path_id))
+				.map(|file_path| {
+					let location = locations.get(&file_path.location_id).cloned();
+					DuplicateFilePath {
+						full_path: location
+							.as_ref()
+							.and_then(|location| location.path.as_ref())
+							.map(|path| path.join(&file_path.materialized_path)),
+						location,
+						file_path,
+					}
+				})
+				.collect(),
+		})
+		.collect())
+}
+
+// resolve_duplicate_group applies the chosen resolution to every path of the group except `keep_file_path_id`
+pub async fn resolve_duplicate_group(
+	ctx: LibraryContext,
+	id: i32,
+	keep_file_path_id: i32,
+	resolution: DuplicateResolution,
+) -> Result<CoreResponse, CoreError> {
+	let group = get_duplicate_groups(&ctx)
+		.await?
+		.into_iter()
+		.find(|group| group.id == id)
+		.ok_or(FileError::DuplicateGroupNotFound(id))?;
+
+	let (kept, others): (Vec<_>, Vec<_>) = group
+		.file_paths
+		.into_iter()
+		.partition(|path| path.file_path.id == keep_file_path_id);
+
+	let kept = kept
+		.into_iter()
+		.next()
+		.ok_or(FileError::FilePathNotFound(keep_file_path_id))?;
+
+	// a held file can still be tagged, but never deleted or replaced
+	if !matches!(resolution, DuplicateResolution::TagOnly { .. }) {
+		for other in &others {
+			if let Some(file_id) = other.file_path.file_id {
+				ensure_not_held(&ctx, file_id).await?;
+			}
+		}
+	}
+
+	let mut changed_dirs = vec![];
+	for other in others {
+		match &resolution {
+			DuplicateResolution::KeepOneDeleteRest => {
+				if let Some(full_path) = &other.full_path {
+					fs::remove_file(full_path).await.map_err(FileError::from)?;
+				}
+				changed_dirs.extend(other.file_path.parent_id);
+				ctx.db
+					.file_path()
+					.find_unique(file_path::id::equals(other.file_path.id))
+					.delete()
+					.exec()
+					.await?;
+			}
+			DuplicateResolution::Hardlink => {
+				// similar images have different contents, linking them would lose data
+				if group.kind != DuplicateKind::Exact {
+					return Err(FileError::InvalidDuplicateResolution(id).into());
+				}
+				let (source, target) = match (&kept.full_path, &other.full_path) {
+					(Some(source), Some(target)) => (source, target),
+					_ => continue,
+				};
+				// link next to the target first, so a failed link (eg: across filesystems) keeps the original
+				let temp_target = target.with_extension("sdlink");
+				fs::hard_link(source, &temp_target)
+					.await
+					.map_err(FileError::from)?;
+				fs::rename(&temp_target, target)
+					.await
+					.map_err(FileError::from)?;
+			}
+			DuplicateResolution::TagOnly { tag_id } => {
+				if let Some(file_id) = other.file_path.file_id {
+					ctx.db
+						.tag_on_file()
+						.create(
+							tag_on_file::tag::link(tag::id::equals(*tag_id)),
+							tag_on_file::file::link(crate::prisma::file::id::equals(file_id)),
+							vec![],
+						)
+						.exec()
+						.await?;
+				}
+			}
+		}
+	}
+
+	invalidate_folder_sizes(&ctx, changed_dirs).await?;
+
+	ctx.db
+		.file_path_in_duplicate_group()
+		.find_many(vec![file_path_in_duplicate_group::group_id::equals(id)])
+		.delete()
+		.exec()
+		.await?;
+	ctx.db
+		.duplicate_group()
+		.find_unique(duplicate_group::id::equals(id))
+		.delete()
+		.exec()
+		.await?;
+
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetDuplicateGroups,
+	}))
+	.await;
+
+	Ok(CoreResponse::Success(()))
+}
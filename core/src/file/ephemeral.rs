@@ -0,0 +1,466 @@
+use crate::{
+	encode::{generate_thumbnail, is_thumbnailable_image, THUMBNAIL_CACHE_DIR_NAME},
+	file::{
+		cas::generate_cas_id,
+		indexer::{is_hidden_path, is_os_hidden},
+		trash::TRASH_DIR_NAME,
+		FileError,
+	},
+	library::LibraryContext,
+	node::UsageCategory,
+	sys::{self, LocationResource, DOTFILE_NAME},
+	ClientQuery, CoreEvent, LibraryQuery,
+};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	fs,
+	path::{Component, Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant, SystemTime},
+};
+use tokio::task::spawn_blocking;
+use ts_rs::TS;
+
+// thumbnails of entries browsed outside of locations, named after their cas id like those of a
+// location so they can be moved into one
+pub(crate) const EPHEMERAL_THUMBNAIL_DIR_NAME: &str = "ephemeral";
+// how long a thumbnail is kept once generated, in case the entry is browsed again
+pub(crate) const EPHEMERAL_THUMBNAIL_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+// a directory is listed again once it changed, or after this long as changes to the entries
+// within it don't change its own modification date
+const CACHE_TTL: Duration = Duration::from_secs(30);
+// the directory listed the longest ago is dropped past this many
+const MAX_CACHED_DIRS: usize = 256;
+
+// An entry of a directory read straight from the filesystem, without a location
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EphemeralEntry {
+	pub path: PathBuf,
+	pub name: String,
+	pub extension: Option<String>,
+	pub is_dir: bool,
+	pub is_symlink: bool,
+	pub size_in_bytes: u64,
+	pub hidden: bool,
+	pub date_created: DateTime<Utc>,
+	pub date_modified: DateTime<Utc>,
+	// only set for entries which get a thumbnail, it is at thumbnails/ephemeral/<cas_id>.webp
+	pub cas_id: Option<String>,
+	pub has_thumbnail: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EphemeralDirectory {
+	pub path: PathBuf,
+	// the location the directory is within, it is better browsed there
+	pub location_id: Option<i32>,
+	pub entries: Vec<EphemeralEntry>,
+}
+
+struct CachedDir {
+	modified: Option<SystemTime>,
+	listed_at: Instant,
+	entries: Arc<Vec<EphemeralEntry>>,
+}
+
+/// EphemeralCache keeps the latest listings of the directories browsed outside of locations, so going back and forth between them doesn't read them again. It is shared by the libraries of the node and lost when it stops.
+#[derive(Default)]
+pub struct EphemeralCache {
+	dirs: Mutex<HashMap<PathBuf, CachedDir>>,
+}
+
+impl EphemeralCache {
+	fn get(&self, path: &Path, modified: Option<SystemTime>) -> Option<Arc<Vec<EphemeralEntry>>> {
+		self.dirs
+			.lock()
+			.unwrap()
+			.get(path)
+			.filter(|dir| dir.modified == modified && dir.listed_at.elapsed() < CACHE_TTL)
+			.map(|dir| dir.entries.clone())
+	}
+
+	fn insert(
+		&self,
+		path: PathBuf,
+		modified: Option<SystemTime>,
+		entries: Arc<Vec<EphemeralEntry>>,
+	) {
+		let mut dirs = self.dirs.lock().unwrap();
+		if dirs.len() >= MAX_CACHED_DIRS && !dirs.contains_key(&path) {
+			let oldest = dirs
+				.iter()
+				.min_by_key(|(_, dir)| dir.listed_at)
+				.map(|(path, _)| path.clone());
+			if let Some(oldest) = oldest {
+				dirs.remove(&oldest);
+			}
+		}
+		dirs.insert(
+			path,
+			CachedDir {
+				modified,
+				listed_at: Instant::now(),
+				entries,
+			},
+		);
+	}
+
+	fn invalidate(&self, path: &Path) {
+		self.dirs.lock().unwrap().remove(path);
+	}
+
+	// takes out the listings of a directory and of those below it
+	fn take_within(&self, root: &Path) -> Vec<Arc<Vec<EphemeralEntry>>> {
+		let mut dirs = self.dirs.lock().unwrap();
+		let within = dirs
+			.keys()
+			.filter(|path| path.starts_with(root))
+			.cloned()
+			.collect::<Vec<_>>();
+		within
+			.iter()
+			.filter_map(|path| dirs.remove(path))
+			.map(|dir| dir.entries)
+			.collect()
+	}
+}
+
+// lists a directory which doesn't have to be in a location. the thumbnails of its images are
+// generated in the background, NewThumbnail is emitted for each of them
+pub async fn get_ephemeral_dir(
+	ctx: &LibraryContext,
+	path: PathBuf,
+	show_hidden: Option<bool>,
+) -> Result<EphemeralDirectory, FileError> {
+	let metadata = match tokio::fs::metadata(&path).await {
+		Ok(metadata) if metadata.is_dir() => metadata,
+		_ => return Err(FileError::DirectoryNotFound(path)),
+	};
+	let modified = metadata.modified().ok();
+
+	let cache = ctx.ephemeral_cache();
+	let entries = match cache.get(&path, modified) {
+		Some(entries) => entries,
+		None => {
+			let entries = Arc::new(read_dir(&path).await?);
+			cache.insert(path.clone(), modified, entries.clone());
+			generate_thumbnails(ctx, entries.clone());
+			entries
+		}
+	};
+
+	let show_hidden = show_hidden.unwrap_or(ctx.config.show_hidden_files);
+	let thumbnail_dir = thumbnail_dir(ctx);
+	let mut entries = entries
+		.iter()
+		.filter(|entry| show_hidden || !entry.hidden)
+		.cloned()
+		.map(|mut entry| {
+			// thumbnails are written after the directory was listed
+			entry.has_thumbnail = entry.cas_id.as_ref().map_or(false, |cas_id| {
+				thumbnail_dir.join(cas_id).with_extension("webp").exists()
+			});
+			entry
+		})
+		.collect::<Vec<_>>();
+
+	let collation = ctx.config.collation;
+	entries.sort_by(|a, b| {
+		collation.compare(&a.name, &b.name).then_with(|| {
+			collation.compare(
+				a.extension.as_deref().unwrap_or_default(),
+				b.extension.as_deref().unwrap_or_default(),
+			)
+		})
+	});
+
+	Ok(EphemeralDirectory {
+		location_id: location_of(ctx, &path).await?.map(|location| location.id),
+		path,
+		entries,
+	})
+}
+
+async fn read_dir(path: &Path) -> Result<Vec<EphemeralEntry>, FileError> {
+	let dir = path.to_path_buf();
+	let mut entries = spawn_blocking(move || -> Result<_, FileError> {
+		Ok(fs::read_dir(&dir)?
+			.filter_map(|entry| entry.ok())
+			.filter(|entry| {
+				entry.file_name() != DOTFILE_NAME && entry.file_name() != TRASH_DIR_NAME
+			})
+			.filter_map(|entry| match read_entry(&entry.path()) {
+				Ok(entry) => Some(entry),
+				Err(e) => {
+					error!("Error reading file {:?}: {}", entry.path(), e);
+					None
+				}
+			})
+			.collect::<Vec<_>>())
+	})
+	.await
+	.map_err(|e| FileError::IOError(e.into()))??;
+
+	// the cas id only reads a few samples of the file, the thumbnail is generated later
+	for entry in entries.iter_mut() {
+		let extension = entry.extension.as_deref().unwrap_or_default();
+		if entry.is_dir || !is_thumbnailable_image(extension) {
+			continue;
+		}
+		match generate_cas_id(entry.path.clone(), entry.size_in_bytes).await {
+			Ok(mut cas_id) => {
+				cas_id.truncate(16);
+				entry.cas_id = Some(cas_id);
+			}
+			Err(e) => error!("Error reading file {:?}: {}", entry.path, e),
+		}
+	}
+
+	Ok(entries)
+}
+
+// the entry at a path, with the metadata of what it links to if it is a symlink
+fn read_entry(path: &Path) -> Result<EphemeralEntry, std::io::Error> {
+	let link_metadata = fs::symlink_metadata(path)?;
+	let is_symlink = link_metadata.file_type().is_symlink();
+	let metadata = match is_symlink {
+		true => fs::metadata(path).unwrap_or(link_metadata),
+		false => link_metadata,
+	};
+
+	let is_dir = metadata.is_dir();
+	let file_name = path
+		.file_name()
+		.map(|name| name.to_string_lossy().to_string())
+		.unwrap_or_default();
+	// directories have no extension, so periods in their names aren't taken for one
+	let (name, extension) = match is_dir {
+		true => (file_name.clone(), None),
+		false => (
+			path.file_stem()
+				.map(|stem| stem.to_string_lossy().to_string())
+				.unwrap_or_default(),
+			path.extension()
+				.map(|extension| extension.to_string_lossy().to_lowercase()),
+		),
+	};
+
+	Ok(EphemeralEntry {
+		path: path.to_path_buf(),
+		hidden: is_hidden_path(&file_name) || is_os_hidden(&metadata),
+		name,
+		extension,
+		is_dir,
+		is_symlink,
+		size_in_bytes: if is_dir { 0 } else { metadata.len() },
+		date_created: metadata
+			.created()
+			.map(Into::into)
+			.unwrap_or_else(|_| Utc::now()),
+		date_modified: metadata
+			.modified()
+			.map(Into::into)
+			.unwrap_or_else(|_| Utc::now()),
+		cas_id: None,
+		has_thumbnail: false,
+	})
+}
+
+fn thumbnail_dir(ctx: &LibraryContext) -> PathBuf {
+	ctx.config()
+		.data_directory()
+		.join(THUMBNAIL_CACHE_DIR_NAME)
+		.join(EPHEMERAL_THUMBNAIL_DIR_NAME)
+}
+
+// generates the missing thumbnails of a listing one after the other, so browsing a large
+// directory doesn't take every core
+fn generate_thumbnails(ctx: &LibraryContext, entries: Arc<Vec<EphemeralEntry>>) {
+	let ctx = ctx.clone();
+	tokio::spawn(async move {
+		let thumbnail_dir = thumbnail_dir(&ctx);
+		if let Err(e) = tokio::fs::create_dir_all(&thumbnail_dir).await {
+			error!("Error creating thumbnail directory {:?}", e);
+			return;
+		}
+
+		for entry in entries.iter() {
+			let cas_id = match &entry.cas_id {
+				Some(cas_id) => cas_id,
+				None => continue,
+			};
+			let output_path = thumbnail_dir.join(cas_id).with_extension("webp");
+			if output_path.exists() {
+				continue;
+			}
+
+			// the errors of the encoders aren't Send, so they're logged before awaiting anything else
+			let written = generate_thumbnail(&entry.path, &output_path)
+				.await
+				.map_err(|e| error!("Error generating thumb {:?}", e));
+			if let Ok(bytes) = written {
+				ctx.record_usage(UsageCategory::Thumbnails, bytes).await;
+				ctx.emit(CoreEvent::NewThumbnail {
+					cas_id: cas_id.clone(),
+				})
+				.await;
+			}
+		}
+	});
+}
+
+// the location a path is within, if any
+async fn location_of(
+	ctx: &LibraryContext,
+	path: &Path,
+) -> Result<Option<LocationResource>, FileError> {
+	Ok(sys::get_locations(ctx)
+		.await?
+		.into_iter()
+		.filter(|location| {
+			location
+				.path
+				.as_ref()
+				.map_or(false, |location_path| path.starts_with(location_path))
+		})
+		.max_by_key(|location| location.path.as_ref().map(|path| path.as_os_str().len())))
+}
+
+// entries within a location are changed through it, so they stay indexed
+async fn ensure_outside_locations(ctx: &LibraryContext, path: &Path) -> Result<(), FileError> {
+	match location_of(ctx, path).await? {
+		Some(location) => Err(FileError::WithinLocation(path.to_path_buf(), location.id)),
+		None => Ok(()),
+	}
+}
+
+// a name has to be a single component, it can't point to another directory
+fn ensure_valid_name(name: &str) -> Result<(), FileError> {
+	let mut components = Path::new(name).components();
+	match (components.next(), components.next()) {
+		(Some(Component::Normal(_)), None) => Ok(()),
+		_ => Err(FileError::InvalidTargetNames(vec![name.to_string()])),
+	}
+}
+
+async fn changed(ctx: &LibraryContext, dir: &Path) {
+	ctx.ephemeral_cache().invalidate(dir);
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetEphemeralDir {
+			path: PathBuf::new(),
+			show_hidden: None,
+		},
+	}))
+	.await;
+}
+
+pub async fn create_ephemeral_dir(
+	ctx: &LibraryContext,
+	path: PathBuf,
+	name: String,
+) -> Result<(), FileError> {
+	ensure_valid_name(&name)?;
+	ensure_outside_locations(ctx, &path).await?;
+
+	let target = path.join(&name);
+	if target.exists() {
+		return Err(FileError::AlreadyExists(target));
+	}
+	tokio::fs::create_dir(&target).await?;
+
+	changed(ctx, &path).await;
+	Ok(())
+}
+
+pub async fn rename_ephemeral_entry(
+	ctx: &LibraryContext,
+	path: PathBuf,
+	name: String,
+) -> Result<(), FileError> {
+	ensure_valid_name(&name)?;
+	ensure_outside_locations(ctx, &path).await?;
+
+	let parent = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+	let target = parent.join(&name);
+	if target.exists() {
+		return Err(FileError::AlreadyExists(target));
+	}
+	tokio::fs::rename(&path, &target).await?;
+	info!("Renamed {:?} to {:?}", path, target);
+
+	ctx.ephemeral_cache().invalidate(&path);
+	changed(ctx, &parent).await;
+	Ok(())
+}
+
+pub async fn delete_ephemeral_entries(
+	ctx: &LibraryContext,
+	paths: Vec<PathBuf>,
+) -> Result<(), FileError> {
+	for path in &paths {
+		ensure_outside_locations(ctx, path).await?;
+	}
+
+	for path in paths {
+		let metadata = tokio::fs::symlink_metadata(&path).await?;
+		match metadata.is_dir() {
+			true => tokio::fs::remove_dir_all(&path).await?,
+			false => tokio::fs::remove_file(&path).await?,
+		}
+		info!("Deleted {:?}", path);
+
+		ctx.ephemeral_cache().invalidate(&path);
+		changed(ctx, path.parent().unwrap_or_else(|| Path::new(""))).await;
+	}
+
+	Ok(())
+}
+
+// adds a browsed directory as a location and indexes it. the thumbnails generated while
+// browsing it are moved to the location rather than generated again, the identifier gives its
+// files the cas ids they are named after
+pub async fn promote_ephemeral_dir(
+	ctx: &LibraryContext,
+	path: PathBuf,
+) -> Result<LocationResource, FileError> {
+	let location = sys::create_location(ctx, &path).await?;
+
+	let ephemeral_dir = thumbnail_dir(ctx);
+	let location_dir = ephemeral_dir
+		.parent()
+		.unwrap_or_else(|| Path::new(""))
+		.join(location.id.to_string());
+	let cas_ids = ctx
+		.ephemeral_cache()
+		.take_within(&path)
+		.iter()
+		.flat_map(|entries| entries.iter())
+		.filter_map(|entry| entry.cas_id.clone())
+		.collect::<Vec<_>>();
+	if !cas_ids.is_empty() {
+		tokio::fs::create_dir_all(&location_dir).await?;
+	}
+	for cas_id in cas_ids {
+		let thumbnail = ephemeral_dir.join(&cas_id).with_extension("webp");
+		if thumbnail.exists() {
+			if let Err(e) = tokio::fs::rename(
+				&thumbnail,
+				location_dir.join(&cas_id).with_extension("webp"),
+			)
+			.await
+			{
+				error!("Failed to move thumbnail {:?}: {:#?}", thumbnail, e);
+			}
+		}
+	}
+
+	sys::scan_location(ctx, location.id, &path).await;
+
+	Ok(location)
+}
@@ -0,0 +1,302 @@
+use crate::{
+	encode::request_thumbnails,
+	file::{explorer::prepare_thumbnails, FileError, FilePath},
+	library::LibraryContext,
+	prisma::{file, file_path},
+	sys::{get_location, ThumbnailPolicy},
+	util::collation::Collation,
+	CoreEvent,
+};
+use chrono::{DateTime, Utc};
+use log::error;
+use prisma_client_rust::{prisma_models::PrismaValue, raw::Raw};
+use prisma_helpers::{decode_cursor, encode_cursor};
+use serde::{Deserialize, Serialize};
+use std::{
+	cmp::Ordering,
+	collections::{HashMap, HashSet},
+	path::Path,
+};
+use ts_rs::TS;
+
+// pages are capped so a client can't ask for a whole directory at once
+const MAX_PAGE_SIZE: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, PartialEq, Eq)]
+#[ts(export)]
+pub enum DirectorySortBy {
+	// in the collation of the library
+	Name,
+	Size,
+	DateModified,
+	Kind,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DirectorySort {
+	pub by: DirectorySortBy,
+	pub descending: bool,
+}
+
+// A page of a directory, `next_cursor` resuming right after its last entry
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DirectoryPage {
+	pub directory: FilePath,
+	pub entries: Vec<FilePath>,
+	// of the whole directory, for clients to size a virtualized list
+	pub total: i32,
+	pub next_cursor: Option<String>,
+}
+
+// What entries are sorted on, read for every entry of the directory while only the entries of
+// the page are read whole. Cursors are one of these, encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EntryKey {
+	id: i32,
+	name: String,
+	extension: Option<String>,
+	date_modified: DateTime<Utc>,
+	// the folder size for directories, null for entries which aren't identified yet
+	size_in_bytes: Option<String>,
+	kind: Option<i32>,
+}
+
+impl EntryKey {
+	fn size(&self) -> u64 {
+		self.size_in_bytes
+			.as_deref()
+			.and_then(|size| size.parse().ok())
+			.unwrap_or(0)
+	}
+}
+
+impl DirectorySort {
+	// entries which compare equal on the sorted field are ordered by name then id, so the
+	// order is total and a cursor always falls at a single place
+	fn compare(&self, a: &EntryKey, b: &EntryKey, collation: Collation) -> Ordering {
+		let by_name = || {
+			collation.compare(&a.name, &b.name).then_with(|| {
+				collation.compare(
+					a.extension.as_deref().unwrap_or_default(),
+					b.extension.as_deref().unwrap_or_default(),
+				)
+			})
+		};
+
+		let ordering = match self.by {
+			DirectorySortBy::Name => by_name(),
+			DirectorySortBy::Size => a.size().cmp(&b.size()).then_with(by_name),
+			DirectorySortBy::DateModified => {
+				a.date_modified.cmp(&b.date_modified).then_with(by_name)
+			}
+			DirectorySortBy::Kind => a.kind.cmp(&b.kind).then_with(by_name),
+		}
+		.then_with(|| a.id.cmp(&b.id));
+
+		match self.descending {
+			true => ordering.reverse(),
+			false => ordering,
+		}
+	}
+}
+
+pub(crate) async fn find_directory(
+	ctx: &LibraryContext,
+	location_id: i32,
+	path: &Path,
+) -> Result<file_path::Data, FileError> {
+	ctx.db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::equals(path.to_string_lossy().to_string()),
+			file_path::is_dir::equals(true),
+		])
+		.exec()
+		.await?
+		.ok_or_else(|| FileError::DirectoryNotFound(path.to_path_buf()))
+}
+
+async fn load_keys(
+	ctx: &LibraryContext,
+	directory: &file_path::Data,
+	show_hidden: Option<bool>,
+) -> Result<Vec<EntryKey>, FileError> {
+	// everything within a hidden directory is hidden, so its contents are listed once it is opened
+	let hidden_filter = if !show_hidden.unwrap_or(ctx.config.show_hidden_files) && !directory.hidden
+	{
+		"AND file_paths.hidden = 0"
+	} else {
+		""
+	};
+
+	Ok(ctx
+		.db
+		._query_raw::<EntryKey>(Raw::new(
+			&format!(
+				"SELECT file_paths.id, file_paths.name, file_paths.extension,
+				file_paths.date_modified,
+				COALESCE(files.size_in_bytes, file_paths.folder_size) AS size_in_bytes, files.kind
+				FROM file_paths LEFT JOIN files ON files.id = file_paths.file_id
+				WHERE file_paths.parent_id = {{}} {}",
+				hidden_filter
+			),
+			vec![PrismaValue::Int(directory.id as i64)],
+		))
+		.await?)
+}
+
+async fn load_entries(ctx: &LibraryContext, ids: &[i32]) -> Result<Vec<FilePath>, FileError> {
+	let mut entries = ctx
+		.db
+		.file_path()
+		.find_many(vec![file_path::id::in_vec(ids.to_vec())])
+		.with(file_path::file::fetch().with(file::media_data::fetch()))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| (file_path.id, FilePath::from(file_path)))
+		.collect::<HashMap<_, _>>();
+
+	Ok(ids.iter().filter_map(|id| entries.remove(id)).collect())
+}
+
+// list_dir reads a directory a page at a time, sorted on the node so clients can virtualize
+// their list. The thumbnails of the `prefetch` entries following the page are requested
+// ahead, so they are ready once scrolled to.
+#[allow(clippy::too_many_arguments)]
+pub async fn list_dir(
+	ctx: &LibraryContext,
+	location_id: i32,
+	path: impl AsRef<Path>,
+	sort: DirectorySort,
+	cursor: Option<String>,
+	limit: u32,
+	prefetch: Option<u32>,
+	show_hidden: Option<bool>,
+) -> Result<DirectoryPage, FileError> {
+	let location = get_location(ctx, location_id).await?;
+	let directory = find_directory(ctx, location.id, path.as_ref()).await?;
+
+	let collation = ctx.config.collation;
+	let mut keys = load_keys(ctx, &directory, show_hidden).await?;
+	keys.sort_by(|a, b| sort.compare(a, b, collation));
+
+	// the entry the cursor points at may be gone, the page starts wherever it would be
+	let start = match cursor {
+		Some(cursor) => {
+			let cursor = decode_cursor::<EntryKey>(&cursor)
+				.map_err(|cursor| FileError::InvalidCursor(cursor.0))?;
+			keys.partition_point(|key| sort.compare(key, &cursor, collation) != Ordering::Greater)
+		}
+		None => 0,
+	};
+	let end = (start + limit.min(MAX_PAGE_SIZE) as usize).min(keys.len());
+
+	let page_ids = keys[start..end]
+		.iter()
+		.map(|key| key.id)
+		.collect::<Vec<_>>();
+	let mut entries = load_entries(ctx, &page_ids).await?;
+	prepare_thumbnails(ctx, &location, path.as_ref(), &mut entries).await;
+
+	let prefetch_end = (end + prefetch.unwrap_or(0) as usize).min(keys.len());
+	if prefetch_end > end && location.thumbnail_policy().await != ThumbnailPolicy::Never {
+		let prefetch_ids = keys[end..prefetch_end].iter().map(|key| key.id).collect();
+		if let Err(e) = request_thumbnails(ctx, prefetch_ids).await {
+			error!("Failed to prefetch thumbnails: {:#?}", e);
+		}
+	}
+
+	Ok(DirectoryPage {
+		directory: directory.into(),
+		entries,
+		total: keys.len() as i32,
+		// none once the last entry was sent
+		next_cursor: (end > 0 && end < keys.len()).then(|| encode_cursor(&keys[end - 1])),
+	})
+}
+
+// the entries of the directory are remembered, after which what is added or removed is sent
+// as DirectoryChanged events. Entries which change in place are not.
+pub async fn subscribe_dir(ctx: &LibraryContext, directory_id: i32) -> Result<(), FileError> {
+	let entries = directory_entry_ids(ctx, directory_id).await?;
+	ctx.saved_searches()
+		.directories
+		.lock()
+		.await
+		.insert((ctx.id, directory_id), entries);
+
+	Ok(())
+}
+
+pub async fn unsubscribe_dir(ctx: &LibraryContext, directory_id: i32) {
+	ctx.saved_searches()
+		.directories
+		.lock()
+		.await
+		.remove(&(ctx.id, directory_id));
+}
+
+async fn directory_entry_ids(
+	ctx: &LibraryContext,
+	directory_id: i32,
+) -> Result<HashSet<i32>, FileError> {
+	let directory = ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(directory_id))
+		.exec()
+		.await?
+		.ok_or(FileError::FilePathNotFound(directory_id))?;
+
+	Ok(load_keys(ctx, &directory, None)
+		.await?
+		.into_iter()
+		.map(|key| key.id)
+		.collect())
+}
+
+pub(crate) async fn refresh_directories(ctx: &LibraryContext) {
+	let subscriptions = ctx.saved_searches();
+	let mut directories = subscriptions.directories.lock().await;
+
+	for ((library_id, directory_id), previous) in directories.iter_mut() {
+		if *library_id != ctx.id {
+			continue;
+		}
+
+		let current = match directory_entry_ids(ctx, *directory_id).await {
+			Ok(current) => current,
+			Err(e) => {
+				error!("Failed to refresh directory {}: {:#?}", directory_id, e);
+				continue;
+			}
+		};
+
+		let added_ids = current.difference(previous).copied().collect::<Vec<_>>();
+		let removed = previous.difference(&current).copied().collect::<Vec<_>>();
+		*previous = current;
+
+		if added_ids.is_empty() && removed.is_empty() {
+			continue;
+		}
+		let added = match load_entries(ctx, &added_ids).await {
+			Ok(added) => added,
+			Err(e) => {
+				error!("Failed to refresh directory {}: {:#?}", directory_id, e);
+				continue;
+			}
+		};
+
+		ctx.emit(CoreEvent::DirectoryChanged {
+			library_id: ctx.id,
+			directory_id: *directory_id,
+			added,
+			removed,
+		})
+		.await;
+	}
+}
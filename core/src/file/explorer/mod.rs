@@ -1,3 +1,5 @@
+mod list;
 mod open;
 
+pub use list::*;
 pub use open::*;
@@ -1,10 +1,14 @@
 use crate::{
-	encode::THUMBNAIL_CACHE_DIR_NAME,
+	encode::{
+		is_thumbnailable_image, ThumbnailJob, ThumbnailJobInit, THUMBNAIL_CACHE_DIR_NAME,
+		VIDEO_EXTENSIONS,
+	},
 	file::{DirectoryWithContents, FileError, FilePath},
 	library::LibraryContext,
-	prisma::{file_path, tag, tag_on_file},
-	sys::get_location,
+	prisma::{file, file_path, tag, tag_on_file},
+	sys::{get_location, LocationResource, ThumbnailPolicy},
 	tag::{Tag, TagError, TagOnFile, TagWithFiles},
+	Job,
 };
 use log::info;
 use std::path::Path;
@@ -13,6 +17,7 @@ pub async fn open_dir(
 	ctx: &LibraryContext,
 	location_id: i32,
 	path: impl AsRef<Path>,
+	show_hidden: Option<bool>,
 ) -> Result<DirectoryWithContents, FileError> {
 	// get location
 	let location = get_location(ctx, location_id).await?;
@@ -33,21 +38,53 @@ pub async fn open_dir(
 
 	info!("DIRECTORY: {:?}", directory);
 
+	let mut params = vec![
+		file_path::location_id::equals(Some(location.id)),
+		file_path::parent_id::equals(Some(directory.id)),
+	];
+	// everything within a hidden directory is hidden, so its contents are listed once it is opened
+	if !show_hidden.unwrap_or(ctx.config.show_hidden_files) && !directory.hidden {
+		params.push(file_path::hidden::equals(false));
+	}
+
 	let mut file_paths: Vec<FilePath> = ctx
 		.db
 		.file_path()
-		.find_many(vec![
-			file_path::location_id::equals(Some(location.id)),
-			file_path::parent_id::equals(Some(directory.id)),
-		])
-		.with(file_path::file::fetch())
+		.find_many(params)
+		.with(file_path::file::fetch().with(file::media_data::fetch()))
 		.exec()
 		.await?
 		.into_iter()
 		.map(Into::into)
 		.collect();
 
-	for file_path in &mut file_paths {
+	let collation = ctx.config.collation;
+	file_paths.sort_by(|a, b| {
+		collation.compare(&a.name, &b.name).then_with(|| {
+			collation.compare(
+				a.extension.as_deref().unwrap_or_default(),
+				b.extension.as_deref().unwrap_or_default(),
+			)
+		})
+	});
+
+	prepare_thumbnails(ctx, &location, path.as_ref(), &mut file_paths).await;
+
+	Ok(DirectoryWithContents {
+		directory: directory.into(),
+		contents: file_paths,
+	})
+}
+
+// sets whether each file has a thumbnail, generating the missing ones of the directory at
+// `path` if its location only gets them once opened
+pub(crate) async fn prepare_thumbnails(
+	ctx: &LibraryContext,
+	location: &LocationResource,
+	path: &Path,
+	file_paths: &mut [FilePath],
+) {
+	for file_path in file_paths.iter_mut() {
 		if let Some(file) = &mut file_path.file {
 			let thumb_path = ctx
 				.config()
@@ -61,10 +98,26 @@ pub async fn open_dir(
 		}
 	}
 
-	Ok(DirectoryWithContents {
-		directory: directory.into(),
-		contents: file_paths,
-	})
+	// thumbnails of locations on slow storage are only generated for the directories opened
+	let missing_thumbnails = file_paths.iter().any(|file_path| {
+		let extension = file_path.extension.as_deref().unwrap_or_default();
+		file_path.file.as_ref().map_or(false, |file| {
+			(is_thumbnailable_image(extension) && !file.has_thumbnail)
+				|| (VIDEO_EXTENSIONS.contains(&extension) && !file.has_thumbstrip)
+		})
+	});
+	if missing_thumbnails && location.thumbnail_policy().await == ThumbnailPolicy::OnDemand {
+		ctx.spawn_job(Job::new(
+			ThumbnailJobInit {
+				location_id: location.id,
+				path: path.to_path_buf(),
+				background: false,
+				shallow: true,
+			},
+			Box::new(ThumbnailJob {}),
+		))
+		.await;
+	}
 }
 
 pub async fn open_tag(ctx: &LibraryContext, tag_id: i32) -> Result<TagWithFiles, TagError> {
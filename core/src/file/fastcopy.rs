@@ -0,0 +1,155 @@
+use crate::sys::FileSystemCapabilities;
+use std::{fs, io, path::Path};
+
+// How the contents of a file were copied
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum CopyMethod {
+	// sharing the blocks of the source, nothing is written until either is changed
+	Cloned,
+	// the data of the source written around its holes
+	Sparse,
+	Streamed,
+}
+
+// copies a file the cheapest way the target volume allows, falling back to writing the contents
+// out. permissions are copied along, the rest is left to attributes::preserve
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub(crate) fn copy_file(
+	source: &Path,
+	target: &Path,
+	capabilities: FileSystemCapabilities,
+) -> io::Result<CopyMethod> {
+	#[cfg(unix)]
+	{
+		if capabilities.clone
+			&& unix::same_file_system(source, target)
+			&& unix::clone_file(source, target).is_ok()
+		{
+			return Ok(CopyMethod::Cloned);
+		}
+		if capabilities.sparse_files
+			&& unix::is_sparse(source)
+			&& unix::copy_sparse(source, target).is_ok()
+		{
+			return Ok(CopyMethod::Sparse);
+		}
+	}
+	// cloning and sparse copies aren't done on Windows, where CopyFileEx copies the streams along
+	fs::copy(source, target)?;
+	Ok(CopyMethod::Streamed)
+}
+
+#[cfg(unix)]
+mod unix {
+	use std::{
+		fs::{self, File},
+		io::{self, Read, Seek, SeekFrom},
+		os::unix::{fs::MetadataExt, io::AsRawFd},
+		path::Path,
+	};
+
+	// the size of the blocks st_blocks counts, whatever the block size of the file system
+	const STAT_BLOCK_SIZE: u64 = 512;
+
+	// whether the directory a file is copied to is on the file system of the source, as clones
+	// can't span two
+	pub fn same_file_system(source: &Path, target: &Path) -> bool {
+		let target_dir = target.parent().unwrap_or(target);
+		match (fs::metadata(source), fs::metadata(target_dir)) {
+			(Ok(source), Ok(target_dir)) => source.dev() == target_dir.dev(),
+			_ => false,
+		}
+	}
+
+	// a file with fewer blocks than its size takes, from holes left unwritten
+	pub fn is_sparse(path: &Path) -> bool {
+		fs::metadata(path)
+			.map(|metadata| metadata.blocks() * STAT_BLOCK_SIZE < metadata.len())
+			.unwrap_or(false)
+	}
+
+	#[cfg(target_os = "macos")]
+	pub fn clone_file(source: &Path, target: &Path) -> io::Result<()> {
+		use std::{ffi::CString, os::unix::ffi::OsStrExt};
+		const CLONE_NOFOLLOW: u32 = 0x0001;
+
+		let source = CString::new(source.as_os_str().as_bytes())?;
+		let target = CString::new(target.as_os_str().as_bytes())?;
+		// fails when something exists at the target, which is copied over then
+		match unsafe { libc::clonefile(source.as_ptr(), target.as_ptr(), CLONE_NOFOLLOW) } {
+			0 => Ok(()),
+			_ => Err(io::Error::last_os_error()),
+		}
+	}
+
+	#[cfg(target_os = "linux")]
+	pub fn clone_file(source: &Path, target: &Path) -> io::Result<()> {
+		// _IOW(0x94, 9, int), shares the extents of the source with the target
+		const FICLONE: u64 = 0x4004_9409;
+
+		let source_file = File::open(source)?;
+		let target_file = File::create(target)?;
+		if unsafe {
+			libc::ioctl(
+				target_file.as_raw_fd(),
+				FICLONE as _,
+				source_file.as_raw_fd(),
+			)
+		} != 0
+		{
+			let error = io::Error::last_os_error();
+			drop(target_file);
+			let _ = fs::remove_file(target);
+			return Err(error);
+		}
+		fs::set_permissions(target, source_file.metadata()?.permissions())
+	}
+
+	#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+	pub fn clone_file(_source: &Path, _target: &Path) -> io::Result<()> {
+		Err(io::Error::new(
+			io::ErrorKind::Unsupported,
+			"cloning files isn't supported on this system",
+		))
+	}
+
+	// copies the data of the source region by region, seeking over its holes so the target keeps
+	// them. a file system without SEEK_DATA reports the whole file as data
+	pub fn copy_sparse(source: &Path, target: &Path) -> io::Result<()> {
+		let mut source_file = File::open(source)?;
+		let metadata = source_file.metadata()?;
+		let len = metadata.len();
+		let mut target_file = File::create(target)?;
+
+		let fd = source_file.as_raw_fd();
+		let mut offset = 0;
+		while offset < len {
+			let data = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+			if data < 0 {
+				let error = io::Error::last_os_error();
+				// no data past the offset, the rest of the file is a hole
+				if error.raw_os_error() == Some(libc::ENXIO) {
+					break;
+				}
+				return Err(error);
+			}
+			let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+			if hole < 0 {
+				return Err(io::Error::last_os_error());
+			}
+
+			let (data, hole) = (data as u64, hole as u64);
+			source_file.seek(SeekFrom::Start(data))?;
+			target_file.seek(SeekFrom::Start(data))?;
+			io::copy(
+				&mut Read::by_ref(&mut source_file).take(hole - data),
+				&mut target_file,
+			)?;
+			offset = hole;
+		}
+
+		// the holes at the end
+		target_file.set_len(len)?;
+		fs::set_permissions(target, metadata.permissions())
+	}
+}
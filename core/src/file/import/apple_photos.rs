@@ -0,0 +1,198 @@
+use super::{export::ExportFile, split_path, ImportedMedia};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use regex::Regex;
+use std::collections::HashMap;
+
+// the first of these set is when the photo was taken
+const DATE_PROPERTIES: [&str; 3] = [
+	"exif:DateTimeOriginal",
+	"photoshop:DateCreated",
+	"xmp:CreateDate",
+];
+const LATITUDE_PROPERTY: &str = "exif:GPSLatitude";
+const LONGITUDE_PROPERTY: &str = "exif:GPSLongitude";
+// the names of the faces of the photo
+const FACE_NAME_PROPERTY: &str = "mwg-rs:Name";
+// favorites are rated this, as Apple Photos has no rating of its own
+const RATING_PROPERTY: &str = "xmp:Rating";
+const FAVORITE_RATING: &str = "5";
+
+const KEYWORDS_LIST: &str = "dc:subject";
+const PEOPLE_LIST: &str = "Iptc4xmpExt:PersonInImage";
+const DESCRIPTION_LIST: &str = "dc:description";
+
+pub(super) fn is_sidecar(path: &str) -> bool {
+	path.to_lowercase().ends_with(".xmp")
+}
+
+// Photos are exported with a directory for every album, and the metadata of each of them in an XMP
+// sidecar, "IMG_0001.xmp" or "IMG_0001.HEIC.xmp". Live photos share theirs with their video.
+pub(super) fn read_media(
+	files: &[ExportFile],
+	sidecars: &HashMap<String, Vec<u8>>,
+) -> Vec<ImportedMedia> {
+	let sidecars = sidecars
+		.iter()
+		.map(|(path, data)| (path.to_lowercase(), String::from_utf8_lossy(data)))
+		.collect::<HashMap<_, _>>();
+	let reader = XmpReader::new();
+
+	files
+		.iter()
+		.filter(|file| super::is_media(&file.path))
+		.map(|file| {
+			let mut media = ImportedMedia::new(file);
+			let (dir, _) = split_path(&file.path);
+			let (_, album) = split_path(dir);
+			if !album.is_empty() {
+				media.albums.push(album.to_string());
+			}
+
+			let path = file.path.to_lowercase();
+			let stem = path
+				.rsplit_once('.')
+				.map_or(path.as_str(), |(stem, _)| stem);
+			if let Some(xmp) = sidecars
+				.get(&format!("{}.xmp", stem))
+				.or_else(|| sidecars.get(&format!("{}.xmp", path)))
+			{
+				reader.read(xmp, &mut media);
+			}
+
+			media
+		})
+		.collect()
+}
+
+// Reads the few properties of a sidecar the import needs, which can be written as elements or as
+// attributes of their description
+struct XmpReader {
+	values: HashMap<&'static str, Regex>,
+	lists: HashMap<&'static str, Regex>,
+	item: Regex,
+}
+
+impl XmpReader {
+	fn new() -> Self {
+		let values = DATE_PROPERTIES
+			.into_iter()
+			.chain([
+				LATITUDE_PROPERTY,
+				LONGITUDE_PROPERTY,
+				FACE_NAME_PROPERTY,
+				RATING_PROPERTY,
+			])
+			.map(|name| {
+				let pattern = format!(r#"<{0}>([^<]*)</{0}>|{0}="([^"]*)""#, regex::escape(name));
+				(name, Regex::new(&pattern).unwrap())
+			})
+			.collect();
+		let lists = [KEYWORDS_LIST, PEOPLE_LIST, DESCRIPTION_LIST]
+			.into_iter()
+			.map(|name| {
+				let pattern = format!(r"(?s)<{0}>(.*?)</{0}>", regex::escape(name));
+				(name, Regex::new(&pattern).unwrap())
+			})
+			.collect();
+
+		Self {
+			values,
+			lists,
+			item: Regex::new(r"(?s)<rdf:li[^>]*>(.*?)</rdf:li>").unwrap(),
+		}
+	}
+
+	fn values(&self, xmp: &str, name: &str) -> Vec<String> {
+		self.values[name]
+			.captures_iter(xmp)
+			.filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+			.map(|value| unescape(value.as_str().trim()))
+			.filter(|value| !value.is_empty())
+			.collect()
+	}
+
+	fn value(&self, xmp: &str, name: &str) -> Option<String> {
+		self.values(xmp, name).into_iter().next()
+	}
+
+	// the items of a bag, a sequence or an alternative
+	fn items(&self, xmp: &str, name: &str) -> Vec<String> {
+		self.lists[name]
+			.captures(xmp)
+			.map(|list| {
+				self.item
+					.captures_iter(&list[1])
+					.map(|item| unescape(item[1].trim()))
+					.filter(|item| !item.is_empty())
+					.collect()
+			})
+			.unwrap_or_default()
+	}
+
+	fn read(&self, xmp: &str, media: &mut ImportedMedia) {
+		media.taken_at = DATE_PROPERTIES
+			.iter()
+			.find_map(|name| self.value(xmp, name).and_then(|date| parse_date(&date)));
+
+		let latitude = self
+			.value(xmp, LATITUDE_PROPERTY)
+			.and_then(|value| parse_coordinate(&value));
+		let longitude = self
+			.value(xmp, LONGITUDE_PROPERTY)
+			.and_then(|value| parse_coordinate(&value));
+		if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+			media.latitude = Some(latitude);
+			media.longitude = Some(longitude);
+		}
+
+		media.keywords = self.items(xmp, KEYWORDS_LIST);
+		media.people = self.items(xmp, PEOPLE_LIST);
+		for name in self.values(xmp, FACE_NAME_PROPERTY) {
+			if !media.people.contains(&name) {
+				media.people.push(name);
+			}
+		}
+		media.description = self.items(xmp, DESCRIPTION_LIST).into_iter().next();
+		media.favorite = self.value(xmp, RATING_PROPERTY).as_deref() == Some(FAVORITE_RATING);
+	}
+}
+
+fn unescape(value: &str) -> String {
+	value
+		.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&#10;", "\n")
+		.replace("&amp;", "&")
+}
+
+// dates without an offset are the local time of the camera, they are read as UTC
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+	DateTime::parse_from_rfc3339(value)
+		.map(|date| date.with_timezone(&Utc))
+		.ok()
+		.or_else(|| {
+			["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"]
+				.iter()
+				.find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+				.map(|date| DateTime::from_utc(date, Utc))
+		})
+}
+
+// XMP writes coordinates as degrees and decimal minutes, "37,46.63N", or with seconds,
+// "37,46,37.8N"
+fn parse_coordinate(value: &str) -> Option<f64> {
+	let (number, sign) = match value.chars().last()? {
+		'N' | 'E' => (&value[..value.len() - 1], 1.0),
+		'S' | 'W' => (&value[..value.len() - 1], -1.0),
+		_ => (value, 1.0),
+	};
+
+	let mut coordinate = 0.0;
+	for (part, unit) in number.split(',').zip([1.0, 60.0, 3600.0]) {
+		coordinate += part.trim().parse::<f64>().ok()? / unit;
+	}
+
+	Some(sign * coordinate)
+}
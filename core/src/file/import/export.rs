@@ -0,0 +1,133 @@
+use crate::file::FileError;
+use std::{
+	collections::HashMap,
+	fs::{self, File},
+	io::{self, Read},
+	path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+// An export is read where it is, from the directory it is in or straight from its zip archive. A
+// Takeout split in several archives is imported from the directory they are all extracted to.
+#[derive(Debug, Clone)]
+pub(super) enum Export {
+	Dir(PathBuf),
+	Zip(PathBuf),
+}
+
+// A file of an export, its path within it with forward slashes
+#[derive(Debug, Clone)]
+pub(super) struct ExportFile {
+	pub path: String,
+	pub size: u64,
+}
+
+impl Export {
+	pub fn open(path: &Path) -> Result<Self, FileError> {
+		if path.is_dir() {
+			return Ok(Self::Dir(path.to_path_buf()));
+		}
+		if !path.is_file() {
+			return Err(FileError::FileNotFound(path.to_path_buf()));
+		}
+
+		let name = path.file_name().unwrap_or_default().to_string_lossy();
+		match name.to_lowercase().ends_with(".zip") {
+			true => Ok(Self::Zip(path.to_path_buf())),
+			false => Err(FileError::UnsupportedArchive(name.to_string())),
+		}
+	}
+
+	// every file of the export, in the order of their paths
+	pub fn files(&self) -> io::Result<Vec<ExportFile>> {
+		let mut files = vec![];
+		match self {
+			Self::Dir(root) => {
+				for entry in WalkDir::new(root) {
+					let entry = entry?;
+					if !entry.file_type().is_file() {
+						continue;
+					}
+					let path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+					files.push(ExportFile {
+						path: path
+							.components()
+							.map(|component| component.as_os_str().to_string_lossy())
+							.collect::<Vec<_>>()
+							.join("/"),
+						size: entry.metadata()?.len(),
+					});
+				}
+			}
+			Self::Zip(source) => {
+				let mut archive = ZipArchive::new(File::open(source)?)?;
+				for i in 0..archive.len() {
+					let file = archive.by_index_raw(i)?;
+					// entries escaping the archive are never read
+					if file.is_dir() || file.enclosed_name().is_none() {
+						continue;
+					}
+					files.push(ExportFile {
+						path: file.name().to_string(),
+						size: file.size(),
+					});
+				}
+			}
+		}
+		files.sort_by(|a, b| a.path.cmp(&b.path));
+
+		Ok(files)
+	}
+
+	// the contents of the files matching `filter`, the sidecars are all read in one pass
+	pub fn read_all(&self, filter: impl Fn(&str) -> bool) -> io::Result<HashMap<String, Vec<u8>>> {
+		let mut reader = self.reader()?;
+		let mut contents = HashMap::new();
+		for file in self.files()? {
+			if filter(&file.path) {
+				let mut data = Vec::with_capacity(file.size as usize);
+				reader.open(&file.path)?.read_to_end(&mut data)?;
+				contents.insert(file.path, data);
+			}
+		}
+
+		Ok(contents)
+	}
+
+	pub fn reader(&self) -> io::Result<ExportReader> {
+		Ok(match self {
+			Self::Dir(root) => ExportReader::Dir(root.clone()),
+			Self::Zip(source) => ExportReader::Zip(ZipArchive::new(File::open(source)?)?),
+		})
+	}
+}
+
+// An export opened once to read many of its files
+pub(super) enum ExportReader {
+	Dir(PathBuf),
+	Zip(ZipArchive<File>),
+}
+
+impl ExportReader {
+	pub fn open(&mut self, path: &str) -> io::Result<Box<dyn Read + '_>> {
+		Ok(match self {
+			Self::Dir(root) => Box::new(File::open(root.join(path))?),
+			Self::Zip(archive) => Box::new(archive.by_name(path)?),
+		})
+	}
+
+	// copies a file of the export to `target`, a partial copy is removed
+	pub fn copy(&mut self, path: &str, target: &Path) -> io::Result<u64> {
+		if let Some(parent) = target.parent() {
+			fs::create_dir_all(parent)?;
+		}
+
+		self.open(path)
+			.and_then(|mut reader| io::copy(&mut reader, &mut File::create(target)?))
+			.map_err(|e| {
+				fs::remove_file(target).ok();
+				e
+			})
+	}
+}
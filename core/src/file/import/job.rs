@@ -0,0 +1,506 @@
+use super::{export::Export, read_export, ImportSource, ImportedMedia};
+use crate::{
+	custom_field::{self, CustomFieldKind, CustomFieldValue},
+	encode::{ImageMetadataJob, ImageMetadataJobInit},
+	file::{
+		archive::free_path,
+		cas::{FileIdentifierJob, FileIdentifierJobInit},
+		indexer::index_entry,
+		send_invalidate_query, FileError,
+	},
+	job::{JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::LibraryContext,
+	places,
+	prisma::{file_path, media_data},
+	sys::get_location,
+	tag::{
+		bulk::{self, BulkTagAction},
+		find_or_create_tag,
+	},
+	ClientQuery, CoreEvent, Job, LibraryQuery,
+};
+use filetime::FileTime;
+use log::{error, info};
+use prisma_client_rust::{prisma_models::PrismaValue, raw::Raw};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
+use tokio::task::spawn_blocking;
+
+pub const MEDIA_IMPORT_JOB_NAME: &str = "media_importer";
+pub const IMPORT_METADATA_JOB_NAME: &str = "import_metadata";
+
+// media are copied, and their metadata applied, this many at a time. An archive is opened once
+// for each batch
+const BATCH_SIZE: usize = 100;
+// the color of the tags created for albums, people and keywords
+const IMPORTED_TAG_COLOR: &str = "#A717D9";
+const DATE_TAKEN_FIELD_NAME: &str = "Date taken";
+const DESCRIPTION_FIELD_NAME: &str = "Description";
+
+pub struct MediaImportJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MediaImportJobInit {
+	pub source: ImportSource,
+	// the zip archive or directory of the export, on this node
+	pub path: PathBuf,
+	pub location_id: i32,
+	// materialized path of the directory imported to, numbered if it exists already
+	pub target_path: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MediaImportJobData {
+	// the directory imported to, which no other file is in
+	target: PathBuf,
+	media: Vec<ImportedMedia>,
+	copied: usize,
+	failed: usize,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for MediaImportJob {
+	type Init = MediaImportJobInit;
+	type Data = MediaImportJobData;
+	// the indexes of a batch of media
+	type Step = Vec<usize>;
+
+	fn name(&self) -> &'static str {
+		MEDIA_IMPORT_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let location = get_location(&ctx.library_ctx(), state.init.location_id).await?;
+		let location_path = location
+			.path
+			.ok_or(FileError::LocationUnavailable(location.id))?;
+
+		let source = state.init.source;
+		let path = state.init.path.clone();
+		let (media, duplicate_count) = spawn_blocking(move || read_export(source, &path)).await??;
+
+		info!(
+			"Importing {} media from {:?}, {} copies left out",
+			media.len(),
+			state.init.path,
+			duplicate_count
+		);
+		ctx.progress(vec![JobReportUpdate::TaskCount(media.len())]);
+
+		state.steps = (0..media.len())
+			.collect::<Vec<_>>()
+			.chunks(BATCH_SIZE)
+			.map(|batch| batch.to_vec())
+			.collect();
+		state.data = Some(MediaImportJobData {
+			target: free_path(location_path.join(&state.init.target_path)),
+			media,
+			copied: 0,
+			failed: 0,
+		});
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let data = state.data.as_mut().unwrap();
+		let batch = state.steps[0]
+			.iter()
+			.map(|index| data.media[*index].clone())
+			.collect::<Vec<_>>();
+		let export = Export::open(&state.init.path)?;
+		let target = data.target.clone();
+
+		let (copied, failed) = spawn_blocking(move || {
+			let mut reader = export.reader()?;
+			let (mut copied, mut failed) = (0, 0);
+			for media in batch {
+				let media_target = target.join(&media.target_path);
+				// copied before the job was paused
+				if media_target.exists() {
+					continue;
+				}
+
+				match reader.copy(&media.path, &media_target) {
+					Ok(_) => {
+						if let Some(taken_at) = media.taken_at {
+							let mtime = FileTime::from_unix_time(taken_at.timestamp(), 0);
+							filetime::set_file_mtime(&media_target, mtime).ok();
+						}
+						copied += 1;
+					}
+					Err(e) => {
+						error!("Failed to import {}: {:#?}", media.path, e);
+						failed += 1;
+					}
+				}
+			}
+
+			Ok::<_, FileError>((copied, failed))
+		})
+		.await??;
+		data.copied += copied;
+		data.failed += failed;
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(data.copied + data.failed),
+			JobReportUpdate::Message(format!("Imported {} media", data.copied)),
+		]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let data = state.data.take().unwrap();
+		info!(
+			"Imported {} media from {:?} to {:?}, {} failed",
+			data.copied, state.init.path, data.target, data.failed
+		);
+		if !data.target.exists() {
+			return Ok(());
+		}
+
+		let location = get_location(&library_ctx, state.init.location_id).await?;
+		let location_path = location.path.clone().unwrap_or_default();
+		index_entry(&library_ctx, &location, &data.target).await?;
+
+		// the metadata is applied once the media are identified, and their exif data read, so
+		// the locations of the sidecars only fill in for photos the camera didn't locate
+		library_ctx
+			.queue_job(Job::new(
+				FileIdentifierJobInit {
+					location_id: location.id,
+					path: location_path.clone(),
+				},
+				Box::new(FileIdentifierJob {}),
+			))
+			.await;
+		library_ctx
+			.queue_job(Job::new(
+				ImageMetadataJobInit {
+					location_id: location.id,
+				},
+				Box::new(ImageMetadataJob {}),
+			))
+			.await;
+		library_ctx
+			.queue_job(Job::new(
+				ImportMetadataJobInit {
+					location_id: location.id,
+					target_path: data
+						.target
+						.strip_prefix(&location_path)
+						.unwrap_or(&data.target)
+						.to_string_lossy()
+						.to_string(),
+					media: data.media,
+				},
+				Box::new(ImportMetadataJob {}),
+			))
+			.await;
+
+		Ok(())
+	}
+}
+
+pub struct ImportMetadataJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ImportMetadataJobInit {
+	pub location_id: i32,
+	// materialized path of the directory the media were imported to
+	pub target_path: String,
+	pub media: Vec<ImportedMedia>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ImportMetadataJobData {
+	// the tag of every album, person and keyword, by name
+	tags: HashMap<String, i32>,
+	date_taken_field: Option<i32>,
+	description_field: Option<i32>,
+	applied: usize,
+	// media which weren't imported or identified
+	skipped: usize,
+	located: bool,
+}
+
+// the custom field named `name`, created unless there is one of the kind already
+async fn find_or_create_field(
+	ctx: &LibraryContext,
+	name: &str,
+	kind: CustomFieldKind,
+) -> Result<i32, custom_field::CustomFieldError> {
+	let existing = custom_field::get_fields(ctx)
+		.await?
+		.into_iter()
+		.find(|field| field.name == name && field.kind == kind);
+
+	Ok(match existing {
+		Some(field) => field.id,
+		None => {
+			custom_field::create_field(ctx, name.to_string(), kind, vec![])
+				.await?
+				.id
+		}
+	})
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ImportMetadataJob {
+	type Init = ImportMetadataJobInit;
+	type Data = ImportMetadataJobData;
+	type Step = Vec<usize>;
+
+	fn name(&self) -> &'static str {
+		IMPORT_METADATA_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+
+		let mut tags = HashMap::new();
+		for media in &state.init.media {
+			for name in media
+				.albums
+				.iter()
+				.chain(&media.people)
+				.chain(&media.keywords)
+			{
+				if !tags.contains_key(name) {
+					tags.insert(
+						name.clone(),
+						find_or_create_tag(&library_ctx, name, IMPORTED_TAG_COLOR).await?,
+					);
+				}
+			}
+		}
+		library_ctx
+			.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+				library_id: library_ctx.id,
+				query: LibraryQuery::GetTags,
+			}))
+			.await;
+
+		// the media are still tagged without the fields
+		let mut fields = vec![];
+		for (name, kind) in [
+			(DATE_TAKEN_FIELD_NAME, CustomFieldKind::Date),
+			(DESCRIPTION_FIELD_NAME, CustomFieldKind::Text),
+		] {
+			fields.push(match find_or_create_field(&library_ctx, name, kind).await {
+				Ok(field_id) => Some(field_id),
+				Err(e) => {
+					error!("Failed to create the {} field: {:#?}", name, e);
+					None
+				}
+			});
+		}
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.init.media.len())]);
+		state.steps = (0..state.init.media.len())
+			.collect::<Vec<_>>()
+			.chunks(BATCH_SIZE)
+			.map(|batch| batch.to_vec())
+			.collect();
+		state.data = Some(ImportMetadataJobData {
+			tags,
+			date_taken_field: fields[0],
+			description_field: fields[1],
+			applied: 0,
+			skipped: 0,
+			located: false,
+		});
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let data = state.data.as_mut().unwrap();
+		let batch = state.steps[0]
+			.iter()
+			.map(|index| &state.init.media[*index])
+			.map(|media| {
+				(
+					format!("{}/{}", state.init.target_path, media.target_path),
+					media,
+				)
+			})
+			.collect::<HashMap<_, _>>();
+
+		let file_ids = library_ctx
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(state.init.location_id)),
+				file_path::materialized_path::in_vec(batch.keys().cloned().collect()),
+			])
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| Some((file_path.materialized_path, file_path.file_id?)))
+			.collect::<HashMap<_, _>>();
+
+		let mut tagged: HashMap<i32, Vec<i32>> = HashMap::new();
+		let mut favorites = vec![];
+		let mut located = vec![];
+		for (path, media) in &batch {
+			let file_id = match file_ids.get(path) {
+				Some(file_id) => *file_id,
+				None => {
+					data.skipped += 1;
+					continue;
+				}
+			};
+
+			for name in media
+				.albums
+				.iter()
+				.chain(&media.people)
+				.chain(&media.keywords)
+			{
+				if let Some(tag_id) = data.tags.get(name) {
+					tagged.entry(*tag_id).or_default().push(file_id);
+				}
+			}
+			if media.favorite {
+				favorites.push(file_id);
+			}
+			if let (Some(latitude), Some(longitude)) = (media.latitude, media.longitude) {
+				located.push((file_id, latitude, longitude));
+			}
+
+			let values = [
+				(
+					data.date_taken_field,
+					media.taken_at.map(CustomFieldValue::Date),
+				),
+				(
+					data.description_field,
+					media.description.clone().map(CustomFieldValue::Text),
+				),
+			];
+			for (field_id, value) in values {
+				if let (Some(field_id), Some(value)) = (field_id, value) {
+					if let Err(e) =
+						custom_field::set_value(&library_ctx, file_id, field_id, Some(value)).await
+					{
+						error!("Failed to set a field of file {}: {:#?}", file_id, e);
+					}
+				}
+			}
+			data.applied += 1;
+		}
+
+		for (tag_id, file_ids) in tagged {
+			bulk::apply(&library_ctx, tag_id, &file_ids, BulkTagAction::Assign).await?;
+		}
+
+		if !favorites.is_empty() {
+			library_ctx
+				.db
+				._execute_raw(Raw::new(
+					&format!(
+						"UPDATE files SET favorite = 1, metadata_version = metadata_version + 1
+						WHERE id IN ({}) AND favorite = 0",
+						vec!["{}"; favorites.len()].join(", ")
+					),
+					favorites
+						.iter()
+						.map(|id| PrismaValue::Int(*id as i64))
+						.collect(),
+				))
+				.await?;
+		}
+
+		// the location the camera recorded is kept over the one of the sidecar
+		for (file_id, latitude, longitude) in located {
+			let existing = library_ctx
+				.db
+				.media_data()
+				.find_unique(media_data::id::equals(file_id))
+				.exec()
+				.await?;
+			match existing {
+				Some(media_data) if media_data.latitude.is_some() => continue,
+				Some(_) => {
+					library_ctx
+						.db
+						.media_data()
+						.find_unique(media_data::id::equals(file_id))
+						.update(vec![
+							media_data::latitude::set(Some(latitude)),
+							media_data::longitude::set(Some(longitude)),
+						])
+						.exec()
+						.await?;
+				}
+				None => {
+					library_ctx
+						.db
+						.media_data()
+						.create(
+							media_data::id::set(file_id),
+							vec![
+								media_data::latitude::set(Some(latitude)),
+								media_data::longitude::set(Some(longitude)),
+							],
+						)
+						.exec()
+						.await?;
+				}
+			}
+			data.located = true;
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			data.applied + data.skipped,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let data = state.data.as_ref().unwrap();
+		info!(
+			"Applied the metadata of {} imported media, {} skipped",
+			data.applied, data.skipped
+		);
+
+		send_invalidate_query(&library_ctx).await;
+		if data.located && library_ctx.config.places {
+			if let Err(e) = places::assign_places(&library_ctx).await {
+				error!("Failed to group photos into places: {:#?}", e);
+			}
+		}
+
+		Ok(())
+	}
+}
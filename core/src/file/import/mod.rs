@@ -0,0 +1,291 @@
+use crate::{
+	encode::{EXIF_EXTENSIONS, VIDEO_EXTENSIONS},
+	file::FileError,
+	library::LibraryContext,
+	sys::get_location,
+	Job,
+};
+use chrono::{DateTime, Datelike, Utc};
+use export::{Export, ExportFile};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{BTreeSet, HashMap, HashSet},
+	io,
+	path::{Path, PathBuf},
+};
+use tokio::task::spawn_blocking;
+use ts_rs::TS;
+
+mod apple_photos;
+mod export;
+mod job;
+mod takeout;
+
+pub use job::*;
+
+// the media listed by a preview, the counts are of all of them
+const PREVIEW_MEDIA_LIMIT: usize = 100;
+// other formats the photo libraries keep, on top of those with exif data and videos
+const OTHER_MEDIA_EXTENSIONS: [&str; 5] = ["gif", "bmp", "3gp", "mpg", "mts"];
+// imported media without a date taken are kept apart from the years
+const UNDATED_DIR_NAME: &str = "Undated";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum ImportSource {
+	// a Google Takeout archive of Google Photos, or the directory it is extracted to
+	GoogleTakeout,
+	// a directory of photos exported from Apple Photos with their XMP sidecars
+	ApplePhotos,
+}
+
+impl ImportSource {
+	// the directory imported to unless another is given
+	fn dir_name(&self) -> &'static str {
+		match self {
+			Self::GoogleTakeout => "Google Photos",
+			Self::ApplePhotos => "Apple Photos",
+		}
+	}
+}
+
+// A photo or video of an export, with what its sidecar tells about it
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ImportedMedia {
+	// within the export
+	pub path: String,
+	// within the directory imported to, in the directory of the year it was taken
+	pub target_path: String,
+	pub size_in_bytes: u64,
+	pub taken_at: Option<DateTime<Utc>>,
+	pub latitude: Option<f64>,
+	pub longitude: Option<f64>,
+	// the albums, people and keywords are imported as tags
+	pub albums: Vec<String>,
+	pub people: Vec<String>,
+	pub keywords: Vec<String>,
+	pub description: Option<String>,
+	pub favorite: bool,
+}
+
+impl ImportedMedia {
+	fn new(file: &ExportFile) -> Self {
+		Self {
+			path: file.path.clone(),
+			target_path: String::new(),
+			size_in_bytes: file.size,
+			taken_at: None,
+			latitude: None,
+			longitude: None,
+			albums: vec![],
+			people: vec![],
+			keywords: vec![],
+			description: None,
+			favorite: false,
+		}
+	}
+
+	// merges a copy of the same photo found elsewhere in the export
+	fn merge(&mut self, other: ImportedMedia) {
+		for (values, others) in [
+			(&mut self.albums, other.albums),
+			(&mut self.people, other.people),
+			(&mut self.keywords, other.keywords),
+		] {
+			for value in others {
+				if !values.contains(&value) {
+					values.push(value);
+				}
+			}
+		}
+		self.taken_at = self.taken_at.or(other.taken_at);
+		if self.latitude.is_none() {
+			self.latitude = other.latitude;
+			self.longitude = other.longitude;
+		}
+		self.description = self.description.take().or(other.description);
+		self.favorite |= other.favorite;
+	}
+}
+
+// What importing an export would add to the library, nothing is copied yet
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ImportPreview {
+	pub source: ImportSource,
+	pub media_count: usize,
+	pub total_bytes: u64,
+	// copies of media found in several albums of the export, imported once
+	pub duplicate_count: usize,
+	pub with_taken_time: usize,
+	pub with_location: usize,
+	// the tags the import would create or reuse, in order
+	pub albums: Vec<String>,
+	pub people: Vec<String>,
+	pub keywords: Vec<String>,
+	// the first 100 media
+	pub media: Vec<ImportedMedia>,
+}
+
+impl ImportPreview {
+	fn new(source: ImportSource, media: Vec<ImportedMedia>, duplicate_count: usize) -> Self {
+		let mut albums = BTreeSet::new();
+		let mut people = BTreeSet::new();
+		let mut keywords = BTreeSet::new();
+		for media in &media {
+			albums.extend(media.albums.iter().cloned());
+			people.extend(media.people.iter().cloned());
+			keywords.extend(media.keywords.iter().cloned());
+		}
+
+		Self {
+			source,
+			media_count: media.len(),
+			total_bytes: media.iter().map(|media| media.size_in_bytes).sum(),
+			duplicate_count,
+			with_taken_time: media
+				.iter()
+				.filter(|media| media.taken_at.is_some())
+				.count(),
+			with_location: media
+				.iter()
+				.filter(|media| media.latitude.is_some())
+				.count(),
+			albums: albums.into_iter().collect(),
+			people: people.into_iter().collect(),
+			keywords: keywords.into_iter().collect(),
+			media: media.into_iter().take(PREVIEW_MEDIA_LIMIT).collect(),
+		}
+	}
+}
+
+pub async fn get_import_preview(
+	source: ImportSource,
+	path: PathBuf,
+) -> Result<ImportPreview, FileError> {
+	let (media, duplicate_count) = read_export_blocking(source, path).await?;
+
+	Ok(ImportPreview::new(source, media, duplicate_count))
+}
+
+// imports an export into a new directory of the location, `target_path` or a directory named after
+// the source. The albums, people and keywords of the media are imported as tags once they are
+// identified, see ImportMetadataJob
+pub async fn import_media(
+	ctx: &LibraryContext,
+	source: ImportSource,
+	path: PathBuf,
+	location_id: i32,
+	target_path: Option<String>,
+) -> Result<(), FileError> {
+	get_location(ctx, location_id)
+		.await?
+		.path
+		.ok_or(FileError::LocationUnavailable(location_id))?;
+	Export::open(&path)?;
+
+	ctx.spawn_job(Job::new(
+		MediaImportJobInit {
+			source,
+			path,
+			location_id,
+			target_path: target_path.unwrap_or_else(|| source.dir_name().to_string()),
+		},
+		Box::new(MediaImportJob {}),
+	))
+	.await;
+
+	Ok(())
+}
+
+fn is_media(path: &str) -> bool {
+	let name = split_path(path).1;
+	if name.starts_with('.') {
+		return false;
+	}
+
+	let extension = match name.rsplit_once('.') {
+		Some((_, extension)) => extension.to_lowercase(),
+		None => return false,
+	};
+	EXIF_EXTENSIONS
+		.iter()
+		.chain(VIDEO_EXTENSIONS.iter())
+		.chain(OTHER_MEDIA_EXTENSIONS.iter())
+		.any(|media_extension| *media_extension == extension)
+}
+
+// the directory and name of a path of an export
+fn split_path(path: &str) -> (&str, &str) {
+	path.rsplit_once('/').unwrap_or(("", path))
+}
+
+async fn read_export_blocking(
+	source: ImportSource,
+	path: PathBuf,
+) -> Result<(Vec<ImportedMedia>, usize), FileError> {
+	spawn_blocking(move || read_export(source, &path))
+		.await
+		.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+}
+
+// the media of an export, each photo once, and how many copies were left out
+fn read_export(
+	source: ImportSource,
+	path: &Path,
+) -> Result<(Vec<ImportedMedia>, usize), FileError> {
+	let export = Export::open(path)?;
+	let files = export.files()?;
+	let media = match source {
+		ImportSource::GoogleTakeout => {
+			takeout::read_media(&files, &export.read_all(takeout::is_sidecar)?)
+		}
+		ImportSource::ApplePhotos => {
+			apple_photos::read_media(&files, &export.read_all(apple_photos::is_sidecar)?)
+		}
+	};
+
+	// copies are the same name and size, their albums all go to the one imported
+	let total = media.len();
+	let mut merged: Vec<ImportedMedia> = vec![];
+	let mut by_name = HashMap::new();
+	for media in media {
+		let key = (
+			split_path(&media.path).1.to_lowercase(),
+			media.size_in_bytes,
+		);
+		match by_name.get(&key) {
+			Some(index) => merged[*index].merge(media),
+			None => {
+				by_name.insert(key, merged.len());
+				merged.push(media);
+			}
+		}
+	}
+	let duplicate_count = total - merged.len();
+
+	let mut taken = HashSet::new();
+	for media in &mut merged {
+		let dir = media
+			.taken_at
+			.map_or(UNDATED_DIR_NAME.to_string(), |taken_at| {
+				taken_at.year().to_string()
+			});
+		let name = split_path(&media.path).1;
+		let (stem, extension) = match name.rsplit_once('.') {
+			Some((stem, extension)) => (stem, format!(".{}", extension)),
+			None => (name, String::new()),
+		};
+		// names are compared ignoring their case, which most volumes do
+		media.target_path = (0..)
+			.map(|n| match n {
+				0 => format!("{}/{}", dir, name),
+				n => format!("{}/{} ({}){}", dir, stem, n, extension),
+			})
+			.find(|target_path| taken.insert(target_path.to_lowercase()))
+			.unwrap_or_default();
+	}
+
+	Ok((merged, duplicate_count))
+}
@@ -0,0 +1,189 @@
+use super::{export::ExportFile, split_path, ImportedMedia};
+use chrono::{TimeZone, Utc};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Takeout truncates the names of sidecars to this many characters, before their ".json"
+const TRUNCATED_NAME_LEN: usize = 46;
+// the sidecar of an album directory, with the title of the album
+const ALBUM_METADATA_NAME: &str = "metadata.json";
+
+// The sidecar Takeout writes next to every photo, as "IMG_0001.jpg.json" or
+// "IMG_0001.jpg.supplemental-metadata.json" in newer exports
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Sidecar {
+	// the name the photo was uploaded with
+	title: Option<String>,
+	description: Option<String>,
+	photo_taken_time: Option<Timestamp>,
+	// the location set in Google Photos, over the one of the camera
+	geo_data: Option<GeoData>,
+	geo_data_exif: Option<GeoData>,
+	people: Vec<Person>,
+	favorited: bool,
+}
+
+#[derive(Deserialize)]
+struct Timestamp {
+	// seconds since the epoch, as a string
+	timestamp: String,
+}
+
+#[derive(Deserialize)]
+struct GeoData {
+	latitude: f64,
+	longitude: f64,
+}
+
+impl GeoData {
+	// Takeout writes 0,0 for photos without a location
+	fn coordinates(&self) -> Option<(f64, f64)> {
+		(self.latitude != 0.0 || self.longitude != 0.0).then(|| (self.latitude, self.longitude))
+	}
+}
+
+#[derive(Deserialize)]
+struct Person {
+	name: String,
+}
+
+#[derive(Deserialize)]
+struct AlbumMetadata {
+	title: Option<String>,
+}
+
+pub(super) fn is_sidecar(path: &str) -> bool {
+	path.to_lowercase().ends_with(".json")
+}
+
+// Every photo is in the directory of its year, "Photos from 2019", and in the directory of each
+// album it belongs to. The copies are merged later on, see ImportedMedia::merge.
+pub(super) fn read_media(
+	files: &[ExportFile],
+	sidecars: &HashMap<String, Vec<u8>>,
+) -> Vec<ImportedMedia> {
+	let mut by_dir: HashMap<&str, Vec<(&str, Sidecar)>> = HashMap::new();
+	let mut album_titles = HashMap::new();
+	for (path, data) in sidecars {
+		let (dir, name) = split_path(path);
+		if name == ALBUM_METADATA_NAME {
+			if let Ok(AlbumMetadata { title: Some(title) }) = serde_json::from_slice(data) {
+				album_titles.insert(dir, title);
+			}
+		} else if let Ok(sidecar) = serde_json::from_slice::<Sidecar>(data) {
+			by_dir
+				.entry(dir)
+				.or_default()
+				.push((&name[..name.len() - ".json".len()], sidecar));
+		}
+	}
+
+	let year_dir = Regex::new(r"^Photos from \d{4}$").unwrap();
+	let counter = Regex::new(r"^(.*)\((\d+)\)(\.[^.]*)?$").unwrap();
+
+	files
+		.iter()
+		.filter(|file| super::is_media(&file.path))
+		.map(|file| {
+			let (dir, name) = split_path(&file.path);
+			let dir_name = split_path(dir).1;
+
+			let mut media = ImportedMedia::new(file);
+			match album_titles.get(dir) {
+				Some(title) => media.albums.push(title.clone()),
+				None if dir_name.is_empty() || year_dir.is_match(dir_name) => {}
+				None => media.albums.push(dir_name.to_string()),
+			}
+
+			let sidecar = by_dir
+				.get(dir)
+				.and_then(|sidecars| find_sidecar(sidecars, name, &counter));
+			if let Some(sidecar) = sidecar {
+				media.taken_at = sidecar
+					.photo_taken_time
+					.as_ref()
+					.and_then(|time| time.timestamp.parse().ok())
+					.and_then(|seconds| Utc.timestamp_opt(seconds, 0).single());
+				if let Some((latitude, longitude)) = sidecar
+					.geo_data
+					.iter()
+					.chain(&sidecar.geo_data_exif)
+					.find_map(GeoData::coordinates)
+				{
+					media.latitude = Some(latitude);
+					media.longitude = Some(longitude);
+				}
+				media.people = sidecar
+					.people
+					.iter()
+					.map(|person| person.name.trim().to_string())
+					.filter(|name| !name.is_empty())
+					.collect();
+				media.description = sidecar
+					.description
+					.as_ref()
+					.map(|description| description.trim().to_string())
+					.filter(|description| !description.is_empty());
+				media.favorite = sidecar.favorited;
+			}
+
+			media
+		})
+		.collect()
+}
+
+// splits the "(1)" Takeout numbers copies of a name with, "IMG(1).jpg" for the photo and
+// "IMG.jpg(1)" for its sidecar
+fn split_counter<'a>(name: &'a str, counter: &Regex) -> (String, Option<&'a str>) {
+	match counter.captures(name) {
+		Some(captures) => (
+			format!(
+				"{}{}",
+				&captures[1],
+				captures.get(3).map_or("", |extension| extension.as_str())
+			),
+			captures.get(2).map(|n| n.as_str()),
+		),
+		None => (name.to_string(), None),
+	}
+}
+
+// the sidecars of a photo are named after it, unless their name was truncated. Edited photos share
+// the sidecar of their original, and the title of a sidecar is the last resort
+fn find_sidecar<'a>(
+	sidecars: &'a [(&str, Sidecar)],
+	name: &str,
+	counter: &Regex,
+) -> Option<&'a Sidecar> {
+	let (original, n) = split_counter(name, counter);
+	let original = original.replacen("-edited", "", 1);
+	let stem = original
+		.rsplit_once('.')
+		.map_or(original.as_str(), |(stem, _)| stem);
+	let supplemental = format!("{}.supplemental-metadata", original);
+
+	sidecars
+		.iter()
+		.filter_map(|(sidecar_name, sidecar)| {
+			let (sidecar_name, sidecar_n) = split_counter(sidecar_name, counter);
+			let matches = sidecar_n == n
+				&& (sidecar_name == stem
+					|| (supplemental.starts_with(&sidecar_name)
+						&& sidecar_name.len() >= original.len().min(TRUNCATED_NAME_LEN)));
+			matches.then(|| (sidecar_name.len(), sidecar))
+		})
+		.max_by_key(|(len, _)| *len)
+		.map(|(_, sidecar)| sidecar)
+		.or_else(|| {
+			n.is_none()
+				.then(|| {
+					sidecars
+						.iter()
+						.find(|(_, sidecar)| sidecar.title.as_deref() == Some(name))
+						.map(|(_, sidecar)| sidecar)
+				})
+				.flatten()
+		})
+}
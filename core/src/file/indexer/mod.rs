@@ -1,27 +1,34 @@
 use crate::{
+	file::FileError,
 	job::{JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
-	sys::{create_location, LocationResource},
+	library::LibraryContext,
+	prisma::{self, file_path},
+	sys::{create_location, LocationResource, SymlinkPolicy},
 };
 use chrono::{DateTime, Utc};
 use log::{error, info};
 use prisma_client_rust::{raw, raw::Raw, PrismaValue};
 use serde::{Deserialize, Serialize};
 use std::{
-	collections::HashMap,
 	ffi::OsStr,
+	fs::Metadata,
 	path::{Path, PathBuf},
 	time::Duration,
 };
-use tokio::{fs, time::Instant};
-use walkdir::{DirEntry, WalkDir};
+use tokio::{fs, sync::mpsc, time::Instant};
+
+mod walker;
+mod writer;
+
+use walker::walk;
+use writer::{write_paths, ScannedPath, WriteSettings, WriteStats};
 
-static BATCH_SIZE: usize = 100;
 pub const INDEXER_JOB_NAME: &str = "indexer";
 
 #[derive(Clone)]
 pub enum ScanProgress {
-	ChunkCount(usize),
-	SavedChunks(usize),
+	EntryCount(usize),
+	SavedEntries(usize),
 	Message(String),
 }
 
@@ -35,21 +42,21 @@ pub struct IndexerJobInit {
 #[derive(Serialize, Deserialize)]
 pub struct IndexerJobData {
 	location: LocationResource,
-	db_write_start: DateTime<Utc>,
-	scan_read_time: Duration,
+	// the highest id before the scan, its entries are numbered from the next one
+	first_file_id: i32,
+	scan_time: Duration,
 	total_paths: usize,
+	write_stats: WriteStats,
 }
 
-pub(crate) type IndexerJobStep = Vec<(PathBuf, i32, Option<i32>, bool)>;
-
 impl IndexerJobData {
 	fn on_scan_progress(ctx: WorkerContext, progress: Vec<ScanProgress>) {
 		ctx.progress(
 			progress
 				.iter()
 				.map(|p| match p.clone() {
-					ScanProgress::ChunkCount(c) => JobReportUpdate::TaskCount(c),
-					ScanProgress::SavedChunks(p) => JobReportUpdate::CompletedTaskCount(p),
+					ScanProgress::EntryCount(c) => JobReportUpdate::TaskCount(c),
+					ScanProgress::SavedEntries(p) => JobReportUpdate::CompletedTaskCount(p),
 					ScanProgress::Message(m) => JobReportUpdate::Message(m),
 				})
 				.collect(),
@@ -61,13 +68,13 @@ impl IndexerJobData {
 impl StatefulJob for IndexerJob {
 	type Init = IndexerJobInit;
 	type Data = IndexerJobData;
-	type Step = IndexerJobStep;
+	// the location is walked and written in one go, the walker waiting on the db when it gets ahead
+	type Step = ();
 
 	fn name(&self) -> &'static str {
 		INDEXER_JOB_NAME
 	}
 
-	// creates a vector of valid path buffers from a directory
 	async fn init(
 		&self,
 		ctx: WorkerContext,
@@ -97,107 +104,14 @@ impl StatefulJob for IndexerJob {
 			panic!("{:#?} is not a directory", state.init.path);
 		}
 
-		// spawn a dedicated thread to scan the directory for performance
-		let path = state.init.path.clone();
-		let inner_ctx = ctx.clone();
-		let (paths, scan_start) = tokio::task::spawn_blocking(move || {
-			// store every valid path discovered
-			let mut paths: Vec<(PathBuf, i32, Option<i32>, bool)> = Vec::new();
-			// store a hashmap of directories to their file ids for fast lookup
-			let mut dirs = HashMap::new();
-			// begin timer for logging purposes
-			let scan_start = Instant::now();
-
-			let mut next_file_id = first_file_id;
-			let mut get_id = || {
-				next_file_id += 1;
-				next_file_id
-			};
-			// walk through directory recursively
-			for entry in WalkDir::new(&path).into_iter().filter_entry(|dir| {
-				// check if entry is approved
-				!is_hidden(dir) && !is_app_bundle(dir) && !is_node_modules(dir) && !is_library(dir)
-			}) {
-				// extract directory entry or log and continue if failed
-				let entry = match entry {
-					Ok(entry) => entry,
-					Err(e) => {
-						error!("Error reading file {}", e);
-						continue;
-					}
-				};
-				let path = entry.path();
-
-				info!("Found filesystem path: {:?}", path);
-
-				let parent_path = path
-					.parent()
-					.unwrap_or_else(|| Path::new(""))
-					.to_str()
-					.unwrap_or("");
-				let parent_dir_id = dirs.get(&*parent_path);
-
-				let path_str = match path.as_os_str().to_str() {
-					Some(path_str) => path_str,
-					None => {
-						error!("Error reading file {}", &path.display());
-						continue;
-					}
-				};
-
-				IndexerJobData::on_scan_progress(
-					inner_ctx.clone(),
-					vec![
-						ScanProgress::Message(format!("Scanning {}", path_str)),
-						ScanProgress::ChunkCount(paths.len() / BATCH_SIZE),
-					],
-				);
-
-				let file_id = get_id();
-				let file_type = entry.file_type();
-				let is_dir = file_type.is_dir();
-
-				if is_dir || file_type.is_file() {
-					paths.push((path.to_owned(), file_id, parent_dir_id.cloned(), is_dir));
-				}
-
-				if is_dir {
-					let _path = match path.to_str() {
-						Some(path) => path.to_owned(),
-						None => continue,
-					};
-					dirs.insert(_path, file_id);
-				}
-			}
-			(paths, scan_start)
-		})
-		.await?;
-
 		state.data = Some(IndexerJobData {
 			location,
-			db_write_start: Utc::now(),
-			scan_read_time: scan_start.elapsed(),
-			total_paths: paths.len(),
+			first_file_id,
+			scan_time: Duration::ZERO,
+			total_paths: 0,
+			write_stats: WriteStats::default(),
 		});
-
-		state.steps = paths
-			.chunks(BATCH_SIZE)
-			.enumerate()
-			.map(|(i, chunk)| {
-				IndexerJobData::on_scan_progress(
-					ctx.clone(),
-					vec![
-						ScanProgress::SavedChunks(i as usize),
-						ScanProgress::Message(format!(
-							"Writing {} of {} to db",
-							i * chunk.len(),
-							paths.len(),
-						)),
-					],
-				);
-				chunk.to_vec()
-			})
-			.collect();
+		state.steps.push_back(());
 
 		Ok(())
 	}
@@ -207,42 +121,60 @@ impl StatefulJob for IndexerJob {
 		ctx: WorkerContext,
 		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
 	) -> JobResult {
-		// vector to store active models
-		let mut files = Vec::new();
-		let step = &state.steps[0];
-
 		let data = state
 			.data
-			.as_ref()
+			.as_mut()
 			.expect("critical error: missing data on job state");
+		let library_ctx = ctx.library_ctx();
 
-		for (file_path, file_id, parent_dir_id, is_dir) in step {
-			files.extend(
-				match prepare_values(file_path, *file_id, &data.location, parent_dir_id, *is_dir)
-					.await
-				{
-					Ok(values) => values.to_vec(),
-					Err(e) => {
-						error!("Error creating file model from path {:?}: {}", file_path, e);
-						continue;
-					}
+		// a scan cut short by a pause is started over, without what it wrote before
+		library_ctx
+			.db
+			._execute_raw(raw!(
+				"DELETE FROM file_paths WHERE location_id = {} AND id > {}",
+				PrismaValue::Int(data.location.id as i64),
+				PrismaValue::Int(data.first_file_id as i64)
+			))
+			.await?;
+
+		let settings = WriteSettings::of(&library_ctx.config);
+		let (tx, rx) = mpsc::channel(settings.channel_capacity());
+		let scan_start = Instant::now();
+
+		// the walker reads on threads of its own, the db is written meanwhile
+		let inner_ctx = ctx.clone();
+		let (total_paths, write_stats) = tokio::join!(
+			walk(
+				state.init.path.clone(),
+				data.location.symlink_policy == SymlinkPolicy::Follow,
+				data.first_file_id,
+				None,
+				tx,
+				move |found| {
+					IndexerJobData::on_scan_progress(
+						inner_ctx.clone(),
+						vec![ScanProgress::EntryCount(found)],
+					)
 				},
-			);
-		}
-
-		let raw = Raw::new(
-				&format!("
-		      		INSERT INTO file_paths (id, is_dir, location_id, materialized_path, name, extension, parent_id, date_created) 
-		      		VALUES {}
-		        ",
-						 vec!["({}, {}, {}, {}, {}, {}, {}, {})"; step.len()].join(", ")
-				),
-				files
-			);
-
-		let count = ctx.library_ctx().db._execute_raw(raw).await;
+			),
+			write_paths(&library_ctx, &data.location, settings, rx, |stats| {
+				IndexerJobData::on_scan_progress(
+					ctx.clone(),
+					vec![
+						ScanProgress::SavedEntries(stats.rows),
+						ScanProgress::Message(format!(
+							"Written {} entries to db, {:.0} per second",
+							stats.rows,
+							stats.rows_per_second()
+						)),
+					],
+				)
+			})
+		);
 
-		info!("Inserted {:?} records", count);
+		data.total_paths = total_paths;
+		data.scan_time = scan_start.elapsed();
+		data.write_stats = write_stats?;
 
 		Ok(())
 	}
@@ -257,17 +189,126 @@ impl StatefulJob for IndexerJob {
 			.as_ref()
 			.expect("critical error: missing data on job state");
 		info!(
-			"scan of {:?} completed in {:?}. {:?} files found. db write completed in {:?}",
+			"scan of {:?} completed in {:?}. {:?} files found. {} written in {} batches, {:?} spent writing ({:.0} rows per second)",
 			state.init.path,
-			data.scan_read_time,
+			data.scan_time,
 			data.total_paths,
-			Utc::now() - data.db_write_start,
+			data.write_stats.rows,
+			data.write_stats.batches,
+			data.write_stats.elapsed,
+			data.write_stats.rows_per_second(),
 		);
 
 		Ok(())
 	}
 }
 
+// writes a batch of scanned paths to the db in a single statement, returning how many were written
+async fn insert_paths(
+	ctx: &LibraryContext,
+	location: &LocationResource,
+	step: &[ScannedPath],
+) -> Result<usize, prisma::QueryError> {
+	// vector to store active models
+	let mut files = Vec::new();
+	let mut rows = 0;
+
+	for (file_path, file_id, parent_dir_id, is_dir) in step {
+		files.extend(
+			match prepare_values(file_path, *file_id, location, parent_dir_id, *is_dir).await {
+				Ok(values) => values.to_vec(),
+				Err(e) => {
+					error!("Error creating file model from path {:?}: {}", file_path, e);
+					continue;
+				}
+			},
+		);
+		rows += 1;
+	}
+
+	if rows == 0 {
+		return Ok(0);
+	}
+
+	let raw = Raw::new(
+			&format!("
+	      		INSERT INTO file_paths (id, is_dir, location_id, materialized_path, name, extension, parent_id, date_created, hidden, is_symlink, link_target, broken_link, inode, device) 
+	      		VALUES {}
+	        ",
+					 vec!["({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {})"; rows].join(", ")
+			),
+			files
+		);
+
+	let count = ctx.db._execute_raw(raw).await?;
+
+	info!("Inserted {:?} records", count);
+
+	Ok(count as usize)
+}
+
+// indexes an entry just written to a location, with its contents if it is a directory, without
+// scanning the whole location again. its files are identified by the next FileIdentifierJob
+pub(crate) async fn index_entry(
+	ctx: &LibraryContext,
+	location: &LocationResource,
+	path: &Path,
+) -> Result<(), FileError> {
+	let location_path = location
+		.path
+		.clone()
+		.ok_or(FileError::LocationUnavailable(location.id))?;
+	let parent = path
+		.parent()
+		.and_then(|parent| parent.strip_prefix(&location_path).ok())
+		.map(|parent| parent.to_string_lossy().to_string())
+		.unwrap_or_default();
+
+	let parent_id = match parent.is_empty() {
+		true => None,
+		false => ctx
+			.db
+			.file_path()
+			.find_first(vec![
+				file_path::location_id::equals(Some(location.id)),
+				file_path::materialized_path::equals(parent),
+				file_path::is_dir::equals(true),
+			])
+			.exec()
+			.await?
+			.map(|directory| directory.id),
+	};
+
+	#[derive(Deserialize, Serialize, Debug)]
+	struct QueryRes {
+		id: Option<i32>,
+	}
+	let last_file_id = ctx
+		.db
+		._query_raw::<QueryRes>(raw!("SELECT MAX(id) id FROM file_paths"))
+		.await?
+		.first()
+		.and_then(|row| row.id)
+		.unwrap_or(0);
+
+	let settings = WriteSettings::of(&ctx.config);
+	let (tx, rx) = mpsc::channel(settings.channel_capacity());
+	let (_, written) = tokio::join!(
+		walk(
+			path.to_path_buf(),
+			location.symlink_policy == SymlinkPolicy::Follow,
+			last_file_id,
+			parent_id,
+			tx,
+			|_| {},
+		),
+		write_paths(ctx, location, settings, rx, |_| {})
+	);
+	written?;
+
+	Ok(())
+}
+
 // // PathContext provides the indexer with instruction to handle particular directory structures and identify rich context.
 // pub struct PathContext {
 // 	// an app specific key "com.github.repo"
@@ -289,10 +330,31 @@ async fn prepare_values(
 	location: &LocationResource,
 	parent_id: &Option<i32>,
 	is_dir: bool,
-) -> Result<[PrismaValue; 8], std::io::Error> {
+) -> Result<[PrismaValue; 14], std::io::Error> {
 	let file_path = file_path.as_ref();
 
-	let metadata = fs::metadata(file_path).await?;
+	// a symlink is indexed with the metadata of what it links to, its own when broken
+	let link_metadata = fs::symlink_metadata(file_path).await?;
+	let is_symlink = link_metadata.file_type().is_symlink();
+	let (metadata, broken_link) = match is_symlink {
+		true => match fs::metadata(file_path).await {
+			Ok(metadata) => (metadata, false),
+			Err(_) => (link_metadata, true),
+		},
+		false => (link_metadata, false),
+	};
+	let link_target = match is_symlink {
+		true => fs::read_link(file_path)
+			.await
+			.ok()
+			.map(|target| target.to_string_lossy().to_string()),
+		false => None,
+	};
+	// directories can't be hardlinked, and a broken link has no contents
+	let (inode, device) = match inode_and_device(&metadata) {
+		Some((inode, device)) if !is_dir && !broken_link => (Some(inode), Some(device)),
+		_ => (None, None),
+	};
 	let location_path = location.path.as_ref().unwrap();
 	// let size = metadata.len();
 	let name;
@@ -313,6 +375,11 @@ async fn prepare_values(
 
 	let materialized_path = file_path.strip_prefix(location_path).unwrap();
 	let materialized_path_as_string = materialized_path.to_str().unwrap_or("").to_owned();
+	// materialized paths always use forward slashes, so nodes on different platforms can share a location
+	#[cfg(windows)]
+	let materialized_path_as_string = materialized_path_as_string.replace('\\', "/");
+
+	let hidden = is_hidden_path(&materialized_path_as_string) || is_os_hidden(&metadata);
 
 	let values = [
 		PrismaValue::Int(id as i64),
@@ -325,11 +392,37 @@ async fn prepare_values(
 			.map(|id| PrismaValue::Int(id as i64))
 			.unwrap_or(PrismaValue::Null),
 		PrismaValue::DateTime(date_created.into()),
+		PrismaValue::Boolean(hidden),
+		PrismaValue::Boolean(is_symlink),
+		link_target
+			.map(PrismaValue::String)
+			.unwrap_or(PrismaValue::Null),
+		PrismaValue::Boolean(broken_link),
+		inode
+			.map(|inode| PrismaValue::BigInt(inode as i64))
+			.unwrap_or(PrismaValue::Null),
+		device
+			.map(|device| PrismaValue::BigInt(device as i64))
+			.unwrap_or(PrismaValue::Null),
 	];
 
 	Ok(values)
 }
 
+// the contents of an entry, shared by its hardlinks
+#[cfg(unix)]
+fn inode_and_device(metadata: &Metadata) -> Option<(u64, u64)> {
+	use std::os::unix::fs::MetadataExt;
+
+	Some((metadata.ino(), metadata.dev()))
+}
+
+// the file index of Windows isn't exposed by std yet, hardlinks are counted as copies there
+#[cfg(not(unix))]
+fn inode_and_device(_metadata: &Metadata) -> Option<(u64, u64)> {
+	None
+}
+
 // extract name from OsStr returned by PathBuff
 fn extract_name(os_string: Option<&OsStr>) -> String {
 	os_string
@@ -339,46 +432,31 @@ fn extract_name(os_string: Option<&OsStr>) -> String {
 		.to_owned()
 }
 
-fn is_hidden(entry: &DirEntry) -> bool {
-	entry
-		.file_name()
-		.to_str()
-		.map(|s| s.starts_with('.'))
-		.unwrap_or(false)
+// whether the entry at a materialized path is a dotfile or within a dot directory
+pub(crate) fn is_hidden_path(materialized_path: &str) -> bool {
+	materialized_path
+		.split('/')
+		.any(|name| name.starts_with('.'))
 }
 
-fn is_library(entry: &DirEntry) -> bool {
-	entry
-		.path()
-		.to_str()
-		// make better this is shit
-		.map(|s| s.contains("/Library/"))
-		.unwrap_or(false)
-}
+#[cfg(windows)]
+pub(crate) fn is_os_hidden(metadata: &Metadata) -> bool {
+	use std::os::windows::fs::MetadataExt;
+	const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
 
-fn is_node_modules(entry: &DirEntry) -> bool {
-	entry
-		.file_name()
-		.to_str()
-		.map(|s| s.contains("node_modules"))
-		.unwrap_or(false)
+	metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
 }
 
-fn is_app_bundle(entry: &DirEntry) -> bool {
-	let is_dir = entry.metadata().unwrap().is_dir();
-	let contains_dot = entry
-		.file_name()
-		.to_str()
-		.map(|s| s.contains(".app") | s.contains(".bundle"))
-		.unwrap_or(false);
-
-	// let is_app_bundle = is_dir && contains_dot;
-	// if is_app_bundle {
-	//   let path_buff = entry.path();
-	//   let path = path_buff.to_str().unwrap();
+#[cfg(target_os = "macos")]
+pub(crate) fn is_os_hidden(metadata: &Metadata) -> bool {
+	use std::os::macos::fs::MetadataExt;
+	const UF_HIDDEN: u32 = 0x8000;
 
-	//   self::path(&path, );
-	// }
+	metadata.st_flags() & UF_HIDDEN != 0
+}
 
-	is_dir && contains_dot
+// other platforms only hide dotfiles
+#[cfg(not(any(windows, target_os = "macos")))]
+pub(crate) fn is_os_hidden(_metadata: &Metadata) -> bool {
+	false
 }
@@ -0,0 +1,621 @@
+use crate::{
+	file::trash::TRASH_DIR_NAME,
+	retention::ARCHIVE_DIR_NAME,
+	sys::{Volume, DOTFILE_NAME},
+};
+use log::error;
+use std::{
+	collections::{HashMap, VecDeque},
+	fs::{self, DirEntry},
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering},
+		Arc, Condvar, Mutex,
+	},
+	thread,
+	time::Duration,
+};
+use tokio::sync::mpsc;
+
+use super::writer::ScannedPath;
+
+// threads reading directories at once, however many cores there are
+const MAX_WALKERS: usize = 8;
+// directories read at once from a disk which seeks, more only make its head jump between them.
+// removable disks are counted as such, as plenty are spinning ones behind USB
+const SEEKING_DISK_READS: usize = 2;
+const NETWORK_READS: usize = 4;
+// how long a walker without directories to read waits before checking whether the walk is done
+const IDLE_WAIT: Duration = Duration::from_millis(10);
+// how many entries are found between two progress updates
+const PROGRESS_INTERVAL: usize = 1000;
+
+// A directory left to read
+struct PendingDir {
+	path: PathBuf,
+	id: i32,
+	// the real paths of the directories above it and of itself, for followed links not to walk
+	// in circles. empty when links aren't followed
+	ancestors: Vec<PathBuf>,
+}
+
+// An entry of a directory which passed the rules
+struct Scanned {
+	path: PathBuf,
+	is_dir: bool,
+	// the ancestors of the entry if it is a directory to read
+	walk: Option<Vec<PathBuf>>,
+}
+
+// walks a directory on a few threads of the blocking pool, numbering its entries from the id after
+// `last_id` and sending them to the writer, a directory always before its contents. stops early if
+// the writer is gone, and returns how many entries were found.
+//
+// a walker reads the directories it finds itself first and takes those of others once it runs
+// out, so a large subdirectory gets spread over every walker. the entries of a directory are
+// sorted before the rules are evaluated, so a location always indexes to the same entries
+pub(super) async fn walk(
+	root: PathBuf,
+	follow_links: bool,
+	last_id: i32,
+	root_parent_id: Option<i32>,
+	tx: mpsc::Sender<ScannedPath>,
+	on_found: impl Fn(usize) + Send + Sync + 'static,
+) -> usize {
+	let walk = match tokio::task::spawn_blocking(move || {
+		Walk::start(
+			&root,
+			follow_links,
+			last_id,
+			root_parent_id,
+			tx,
+			Box::new(on_found),
+		)
+	})
+	.await
+	{
+		Ok(Some(walk)) => Arc::new(walk),
+		Ok(None) => return 0,
+		Err(e) => {
+			error!("Failed to start walking: {:#?}", e);
+			return 0;
+		}
+	};
+
+	let walkers = (0..walk.queues.len())
+		.map(|index| {
+			let walk = walk.clone();
+			tokio::task::spawn_blocking(move || walk.run(index))
+		})
+		.collect::<Vec<_>>();
+	for walker in walkers {
+		if let Err(e) = walker.await {
+			error!("Walker failed: {:#?}", e);
+		}
+	}
+
+	let found = walk.found.load(Ordering::SeqCst);
+	(walk.on_found)(found);
+	found
+}
+
+struct Walk {
+	follow_links: bool,
+	// a queue of directories per walker. each takes from the back of its own, and from the front
+	// of the others' once it is empty
+	queues: Vec<Mutex<VecDeque<PendingDir>>>,
+	// directories queued or being read, the walk is done once there are none
+	pending: AtomicUsize,
+	idle: (Mutex<()>, Condvar),
+	next_id: AtomicI32,
+	found: AtomicUsize,
+	// the writer is gone, what's left is dropped
+	stopped: AtomicBool,
+	limits: VolumeLimits,
+	tx: mpsc::Sender<ScannedPath>,
+	on_found: Box<dyn Fn(usize) + Send + Sync>,
+}
+
+impl Walk {
+	// sends the root and queues it, none if it can't be walked
+	fn start(
+		root: &Path,
+		follow_links: bool,
+		last_id: i32,
+		root_parent_id: Option<i32>,
+		tx: mpsc::Sender<ScannedPath>,
+		on_found: Box<dyn Fn(usize) + Send + Sync>,
+	) -> Option<Self> {
+		// the root is walked even when it is a link
+		let is_dir = match fs::metadata(root) {
+			Ok(metadata) => metadata.is_dir(),
+			Err(e) => {
+				error!("Error reading file {:?}: {}", root, e);
+				return None;
+			}
+		};
+		if !included(root, is_dir) {
+			return None;
+		}
+
+		let walkers = thread::available_parallelism()
+			.map(|walkers| walkers.get())
+			.unwrap_or(1)
+			.min(MAX_WALKERS);
+		let walk = Self {
+			follow_links,
+			queues: (0..walkers).map(|_| Mutex::new(VecDeque::new())).collect(),
+			pending: AtomicUsize::new(0),
+			idle: (Mutex::new(()), Condvar::new()),
+			next_id: AtomicI32::new(last_id + 2),
+			found: AtomicUsize::new(0),
+			stopped: AtomicBool::new(false),
+			limits: VolumeLimits::new(walkers),
+			tx,
+			on_found,
+		};
+
+		let id = last_id + 1;
+		if !walk.send((root.to_path_buf(), id, root_parent_id, is_dir)) {
+			return None;
+		}
+		if is_dir {
+			let ancestors = match follow_links {
+				true => vec![fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf())],
+				false => vec![],
+			};
+			walk.push(
+				0,
+				PendingDir {
+					path: root.to_path_buf(),
+					id,
+					ancestors,
+				},
+			);
+		}
+
+		Some(walk)
+	}
+
+	fn run(&self, index: usize) {
+		while let Some(dir) = self.next_dir(index) {
+			if !self.stopped.load(Ordering::SeqCst) {
+				self.read(index, dir);
+			}
+			self.pending.fetch_sub(1, Ordering::SeqCst);
+			// the idle walkers check whether it was the last one
+			self.idle.1.notify_all();
+		}
+	}
+
+	fn next_dir(&self, index: usize) -> Option<PendingDir> {
+		let walkers = self.queues.len();
+		loop {
+			if let Some(dir) = self.queues[index].lock().unwrap().pop_back() {
+				return Some(dir);
+			}
+			for other in (1..walkers).map(|offset| (index + offset) % walkers) {
+				if let Some(dir) = self.queues[other].lock().unwrap().pop_front() {
+					return Some(dir);
+				}
+			}
+			if self.pending.load(Ordering::SeqCst) == 0 {
+				return None;
+			}
+
+			let idle = self.idle.0.lock().unwrap();
+			let _ = self.idle.1.wait_timeout(idle, IDLE_WAIT).unwrap();
+		}
+	}
+
+	fn push(&self, index: usize, dir: PendingDir) {
+		self.pending.fetch_add(1, Ordering::SeqCst);
+		self.queues[index].lock().unwrap().push_back(dir);
+		self.idle.1.notify_one();
+	}
+
+	// sends the entries of a directory which pass the rules and queues its subdirectories. the
+	// entries get consecutive ids, so their order is kept in the db
+	fn read(&self, index: usize, dir: PendingDir) {
+		let scanned = {
+			let _permit = self.limits.permit(&dir.path);
+
+			let mut entries = match fs::read_dir(&dir.path) {
+				Ok(entries) => entries
+					.filter_map(|entry| match entry {
+						Ok(entry) => Some(entry),
+						Err(e) => {
+							error!("Error reading file in {:?}: {}", dir.path, e);
+							None
+						}
+					})
+					.collect::<Vec<_>>(),
+				Err(e) => {
+					error!("Error reading directory {:?}: {}", dir.path, e);
+					return;
+				}
+			};
+			entries.sort_by_key(|entry| entry.file_name());
+			entries
+				.into_iter()
+				.filter_map(|entry| self.scan(&dir, entry))
+				.collect::<Vec<_>>()
+		};
+
+		let first_id = self
+			.next_id
+			.fetch_add(scanned.len() as i32, Ordering::SeqCst);
+		for (id, entry) in (first_id..).zip(scanned) {
+			if !self.send((entry.path.clone(), id, Some(dir.id), entry.is_dir)) {
+				return;
+			}
+			if let Some(ancestors) = entry.walk {
+				self.push(
+					index,
+					PendingDir {
+						path: entry.path,
+						id,
+						ancestors,
+					},
+				);
+			}
+		}
+	}
+
+	// a symlink is an entry of its own. when links are followed the directory it links to is
+	// walked, unless it is one the link is in
+	fn scan(&self, dir: &PendingDir, entry: DirEntry) -> Option<Scanned> {
+		let path = entry.path();
+		let file_type = match entry.file_type() {
+			Ok(file_type) => file_type,
+			Err(e) => {
+				error!("Error reading file {:?}: {}", path, e);
+				return None;
+			}
+		};
+
+		let (is_dir, real_path) = if file_type.is_symlink() {
+			match fs::metadata(&path) {
+				Ok(target) if target.is_dir() && self.follow_links => {
+					(true, fs::canonicalize(&path).ok())
+				}
+				Ok(target) => (target.is_dir(), None),
+				// a broken link
+				Err(_) => (false, None),
+			}
+		} else if file_type.is_dir() {
+			// a directory within a real one is as real
+			(
+				true,
+				dir.ancestors
+					.last()
+					.map(|real| real.join(entry.file_name())),
+			)
+		} else if file_type.is_file() {
+			(false, None)
+		} else {
+			return None;
+		};
+
+		if !included(&path, is_dir) {
+			return None;
+		}
+
+		let walk = match (self.follow_links, real_path) {
+			(false, _) if is_dir && !file_type.is_symlink() => Some(vec![]),
+			(true, Some(real_path)) if !dir.ancestors.contains(&real_path) => {
+				let mut ancestors = dir.ancestors.clone();
+				ancestors.push(real_path);
+				Some(ancestors)
+			}
+			_ => None,
+		};
+
+		Some(Scanned { path, is_dir, walk })
+	}
+
+	// false once the writer is gone. waits while the writer is a full channel behind
+	fn send(&self, entry: ScannedPath) -> bool {
+		if self.tx.blocking_send(entry).is_err() {
+			self.stopped.store(true, Ordering::SeqCst);
+			return false;
+		}
+
+		let found = self.found.fetch_add(1, Ordering::SeqCst) + 1;
+		if found % PROGRESS_INTERVAL == 0 {
+			(self.on_found)(found);
+		}
+		true
+	}
+}
+
+// How many directories of each volume are read at once, by the kind of disk it is on
+struct VolumeLimits {
+	// listed on the first read, a walk of a single file doesn't need them
+	volumes: Mutex<Option<Vec<Volume>>>,
+	walkers: usize,
+	limits: Mutex<HashMap<String, Arc<Limit>>>,
+}
+
+impl VolumeLimits {
+	fn new(walkers: usize) -> Self {
+		Self {
+			volumes: Mutex::new(None),
+			walkers,
+			limits: Mutex::new(HashMap::new()),
+		}
+	}
+
+	// waits until the volume the directory is on can take another read
+	fn permit(&self, dir: &Path) -> Permit {
+		let mut volumes = self.volumes.lock().unwrap();
+		// the volume mounted deepest above the directory, as in Volume::of_path
+		let volume = volumes
+			.get_or_insert_with(|| Volume::get_volumes().unwrap_or_default())
+			.iter()
+			.filter(|volume| dir.starts_with(&volume.mount_point))
+			.max_by_key(|volume| volume.mount_point.len());
+
+		let limit = self
+			.limits
+			.lock()
+			.unwrap()
+			.entry(
+				volume
+					.map(|volume| volume.mount_point.clone())
+					.unwrap_or_default(),
+			)
+			.or_insert_with(|| {
+				let reads = match volume.and_then(|volume| volume.disk_type.as_deref()) {
+					Some("HDD") | Some("Removable Disk") => SEEKING_DISK_READS,
+					Some("Network") => NETWORK_READS,
+					_ => self.walkers,
+				};
+				Arc::new(Limit {
+					available: Mutex::new(reads),
+					released: Condvar::new(),
+				})
+			})
+			.clone();
+		drop(volumes);
+
+		let mut available = limit.available.lock().unwrap();
+		while *available == 0 {
+			available = limit.released.wait(available).unwrap();
+		}
+		*available -= 1;
+		drop(available);
+
+		Permit(limit)
+	}
+}
+
+struct Limit {
+	available: Mutex<usize>,
+	released: Condvar,
+}
+
+// A read of a volume going on, another can start once it is dropped
+struct Permit(Arc<Limit>);
+
+impl Drop for Permit {
+	fn drop(&mut self) {
+		*self.0.available.lock().unwrap() += 1;
+		self.0.released.notify_one();
+	}
+}
+
+// the rules an entry has to pass to be indexed, from nothing but its own path and type. hidden
+// entries are indexed, and left out of listings unless asked for
+fn included(path: &Path, is_dir: bool) -> bool {
+	let name = path
+		.file_name()
+		.map(|name| name.to_string_lossy())
+		.unwrap_or_default();
+
+	!is_internal(&name)
+		&& !(is_dir && is_app_bundle(&name))
+		&& !is_node_modules(&name)
+		&& !is_library(path)
+}
+
+// entries spacedrive keeps within a location aren't indexed at all
+fn is_internal(name: &str) -> bool {
+	name == TRASH_DIR_NAME || name == ARCHIVE_DIR_NAME || name == DOTFILE_NAME
+}
+
+fn is_library(path: &Path) -> bool {
+	path.to_str()
+		// make better this is shit
+		.map(|s| s.contains("/Library/"))
+		.unwrap_or(false)
+}
+
+fn is_node_modules(name: &str) -> bool {
+	name.contains("node_modules")
+}
+
+fn is_app_bundle(name: &str) -> bool {
+	name.contains(".app") | name.contains(".bundle")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::mpsc as std_mpsc;
+
+	fn volume(mount_point: &str, disk_type: &str) -> Volume {
+		Volume {
+			name: mount_point.to_string(),
+			mount_point: mount_point.to_string(),
+			total_capacity: 0,
+			available_capacity: 0,
+			is_removable: false,
+			disk_type: Some(disk_type.to_string()),
+			file_system: None,
+			is_root_filesystem: mount_point == "/",
+			network_protocol: None,
+			remote_host: None,
+			capabilities: Default::default(),
+		}
+	}
+
+	fn limits(walkers: usize, volumes: Vec<Volume>) -> VolumeLimits {
+		VolumeLimits {
+			volumes: Mutex::new(Some(volumes)),
+			walkers,
+			limits: Mutex::new(HashMap::new()),
+		}
+	}
+
+	fn available(limits: &VolumeLimits, mount_point: &str) -> usize {
+		*limits.limits.lock().unwrap()[mount_point]
+			.available
+			.lock()
+			.unwrap()
+	}
+
+	#[test]
+	fn limits_reads_by_the_disk_of_the_volume() {
+		let limits = limits(
+			8,
+			vec![
+				volume("/", "SSD"),
+				volume("/mnt/hdd", "HDD"),
+				volume("/mnt/nas", "Network"),
+			],
+		);
+
+		let hdd = limits.permit(Path::new("/mnt/hdd/photos"));
+		let nas = limits.permit(Path::new("/mnt/nas/music"));
+		let ssd = limits.permit(Path::new("/home/user"));
+		assert_eq!(available(&limits, "/mnt/hdd"), SEEKING_DISK_READS - 1);
+		assert_eq!(available(&limits, "/mnt/nas"), NETWORK_READS - 1);
+		assert_eq!(available(&limits, "/"), 7);
+
+		drop((hdd, nas, ssd));
+		assert_eq!(available(&limits, "/mnt/hdd"), SEEKING_DISK_READS);
+		assert_eq!(available(&limits, "/mnt/nas"), NETWORK_READS);
+		assert_eq!(available(&limits, "/"), 8);
+	}
+
+	#[test]
+	fn waits_for_a_permit_to_be_released() {
+		let limits = Arc::new(limits(1, vec![]));
+		let permit = limits.permit(Path::new("/a"));
+
+		let (tx, rx) = std_mpsc::channel();
+		let waiting = {
+			let limits = limits.clone();
+			thread::spawn(move || {
+				let _permit = limits.permit(Path::new("/b"));
+				tx.send(()).unwrap();
+			})
+		};
+		assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+		drop(permit);
+		assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+		waiting.join().unwrap();
+		assert_eq!(available(&limits, ""), 1);
+	}
+
+	#[test]
+	fn leaves_out_internal_entries() {
+		assert!(included(Path::new("/photos/photo.jpg"), false));
+		assert!(included(Path::new("/photos/.hidden"), false));
+		assert!(!included(Path::new("/photos/.spacedrive"), false));
+		assert!(!included(Path::new("/code/node_modules"), true));
+		assert!(!included(Path::new("/Applications/Spacedrive.app"), true));
+		assert!(!included(Path::new("/Users/user/Library/Caches"), true));
+	}
+
+	async fn walk_all(root: &Path, follow_links: bool) -> Vec<ScannedPath> {
+		let (tx, mut rx) = mpsc::channel(100);
+		let found = walk(root.to_path_buf(), follow_links, 10, Some(1), tx, |_| {}).await;
+
+		let mut scanned = vec![];
+		while let Some(entry) = rx.recv().await {
+			scanned.push(entry);
+		}
+		assert_eq!(found, scanned.len());
+		scanned
+	}
+
+	#[tokio::test]
+	async fn walks_directories_before_their_contents() {
+		let root = tempfile::tempdir().unwrap();
+		for dir in ["a/b", "a/c", "d", "node_modules/e"] {
+			fs::create_dir_all(root.path().join(dir)).unwrap();
+		}
+		for file in ["a/1.txt", "a/b/2.txt", "a/c/3.txt", "d/4.txt", DOTFILE_NAME] {
+			fs::write(root.path().join(file), file).unwrap();
+		}
+
+		let scanned = walk_all(root.path(), false).await;
+		assert_eq!(scanned[0], (root.path().to_path_buf(), 11, Some(1), true));
+
+		let ids = scanned
+			.iter()
+			.map(|(path, id, _, _)| (path.clone(), *id))
+			.collect::<HashMap<_, _>>();
+		assert_eq!(ids.len(), scanned.len());
+		let mut found = scanned
+			.iter()
+			.map(|(path, _, _, _)| {
+				path.strip_prefix(root.path())
+					.unwrap()
+					.to_string_lossy()
+					.replace('\\', "/")
+			})
+			.collect::<Vec<_>>();
+		found.sort();
+		assert_eq!(
+			found,
+			[
+				"",
+				"a",
+				"a/1.txt",
+				"a/b",
+				"a/b/2.txt",
+				"a/c",
+				"a/c/3.txt",
+				"d",
+				"d/4.txt"
+			]
+		);
+
+		for (index, (path, id, parent_id, _)) in scanned.iter().enumerate().skip(1) {
+			let parent = path.parent().unwrap();
+			assert_eq!(*parent_id, Some(ids[parent]));
+			// the parent was sent before it
+			assert!(scanned[..index]
+				.iter()
+				.any(|(path, _, _, _)| path == parent));
+			assert!(*id > 11);
+		}
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn follows_links_without_walking_in_circles() {
+		let root = tempfile::tempdir().unwrap();
+		fs::create_dir_all(root.path().join("a")).unwrap();
+		fs::write(root.path().join("a/1.txt"), "1").unwrap();
+		std::os::unix::fs::symlink(root.path(), root.path().join("a/root")).unwrap();
+
+		let scanned = walk_all(root.path(), true).await;
+		let mut found = scanned
+			.iter()
+			.map(|(path, _, _, _)| path.strip_prefix(root.path()).unwrap().to_path_buf())
+			.collect::<Vec<_>>();
+		found.sort();
+		assert_eq!(
+			found,
+			["", "a", "a/1.txt", "a/root"]
+				.iter()
+				.map(PathBuf::from)
+				.collect::<Vec<_>>()
+		);
+	}
+}
@@ -0,0 +1,137 @@
+use crate::{
+	library::{LibraryConfig, LibraryContext},
+	prisma,
+	sys::LocationResource,
+};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time::Duration};
+use tokio::{
+	sync::mpsc,
+	time::{timeout_at, Instant},
+};
+
+use super::insert_paths;
+
+// rows written in a single statement when the library doesn't set its own
+const DEFAULT_BATCH_SIZE: usize = 1000;
+// SQLite binds at most 32766 values to a statement, and a row takes 14
+const MAX_BATCH_SIZE: usize = 2000;
+const DEFAULT_FLUSH_INTERVAL_MS: u32 = 1000;
+
+// a scanned entry, with the id it is inserted with and the id of its parent directory
+pub(crate) type ScannedPath = (PathBuf, i32, Option<i32>, bool);
+
+// How the scanned entries of a location are written to the db
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct WriteSettings {
+	pub batch_size: usize,
+	// how long an entry is buffered at most, so progress shows when the walker is slow
+	pub flush_interval: Duration,
+}
+
+impl WriteSettings {
+	pub fn of(config: &LibraryConfig) -> Self {
+		Self {
+			batch_size: config
+				.index_batch_size
+				.map(|size| (size as usize).clamp(1, MAX_BATCH_SIZE))
+				.unwrap_or(DEFAULT_BATCH_SIZE),
+			flush_interval: Duration::from_millis(
+				config
+					.index_flush_interval_ms
+					.unwrap_or(DEFAULT_FLUSH_INTERVAL_MS) as u64,
+			),
+		}
+	}
+
+	// entries the walker can get ahead of the db by, it waits for a batch to be written past that
+	pub fn channel_capacity(&self) -> usize {
+		self.batch_size * 2
+	}
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct WriteStats {
+	pub rows: usize,
+	pub batches: usize,
+	// the time spent writing, not waiting on the walker
+	pub elapsed: Duration,
+}
+
+impl WriteStats {
+	pub fn rows_per_second(&self) -> f64 {
+		match self.elapsed.as_secs_f64() {
+			seconds if seconds > 0.0 => self.rows as f64 / seconds,
+			_ => 0.0,
+		}
+	}
+}
+
+// writes the entries the walker sends in batches, each a single statement so SQLite commits
+// once per batch rather than once per entry. returns once the walker is done and the last batch
+// is written
+pub(crate) async fn write_paths(
+	ctx: &LibraryContext,
+	location: &LocationResource,
+	settings: WriteSettings,
+	mut rx: mpsc::Receiver<ScannedPath>,
+	mut on_flush: impl FnMut(&WriteStats),
+) -> Result<WriteStats, prisma::QueryError> {
+	let mut stats = WriteStats::default();
+	let mut batch = Vec::with_capacity(settings.batch_size);
+	let mut deadline = Instant::now();
+
+	loop {
+		let next = match batch.is_empty() {
+			// nothing buffered, so nothing to flush until the walker finds something
+			true => rx.recv().await,
+			false => match timeout_at(deadline, rx.recv()).await {
+				Ok(next) => next,
+				Err(_) => {
+					flush(ctx, location, &mut batch, &mut stats).await?;
+					on_flush(&stats);
+					continue;
+				}
+			},
+		};
+		let path = match next {
+			Some(path) => path,
+			None => break,
+		};
+
+		if batch.is_empty() {
+			deadline = Instant::now() + settings.flush_interval;
+		}
+		batch.push(path);
+		if batch.len() >= settings.batch_size {
+			flush(ctx, location, &mut batch, &mut stats).await?;
+			on_flush(&stats);
+		}
+	}
+
+	if !batch.is_empty() {
+		flush(ctx, location, &mut batch, &mut stats).await?;
+		on_flush(&stats);
+	}
+
+	Ok(stats)
+}
+
+async fn flush(
+	ctx: &LibraryContext,
+	location: &LocationResource,
+	batch: &mut Vec<ScannedPath>,
+	stats: &mut WriteStats,
+) -> Result<(), prisma::QueryError> {
+	let start = Instant::now();
+	let rows = insert_paths(ctx, location, batch).await?;
+	let elapsed = start.elapsed();
+	batch.clear();
+
+	stats.rows += rows;
+	stats.batches += 1;
+	stats.elapsed += elapsed;
+	ctx.metrics().record_index_write(ctx.id, rows, elapsed);
+
+	Ok(())
+}
@@ -0,0 +1,198 @@
+use crate::{
+	file::{File, FileError},
+	library::LibraryContext,
+	prisma::{file, file_link},
+	ClientQuery, CoreError, CoreEvent, CoreResponse, LibraryQuery,
+};
+use int_enum::IntEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use ts_rs::TS;
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum FileLinkKind {
+	Related = 0,
+	// eg: an edited jpeg derived from a raw file
+	DerivedFrom = 1,
+	SourceOf = 2,
+}
+
+impl FileLinkKind {
+	// the same link as seen from the other file
+	pub fn inverse(self) -> Self {
+		match self {
+			FileLinkKind::Related => FileLinkKind::Related,
+			FileLinkKind::DerivedFrom => FileLinkKind::SourceOf,
+			FileLinkKind::SourceOf => FileLinkKind::DerivedFrom,
+		}
+	}
+}
+
+// A link as seen from one of its files, `kind` reads as "this file is <kind> `file`"
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FileLink {
+	pub id: i32,
+	pub kind: FileLinkKind,
+	pub file: File,
+	pub date_created: chrono::DateTime<chrono::Utc>,
+}
+
+// links are between files rather than paths, so they follow an entry through moves and renames
+pub async fn create_link(
+	ctx: LibraryContext,
+	file_id: i32,
+	linked_file_id: i32,
+	kind: FileLinkKind,
+) -> Result<CoreResponse, CoreError> {
+	if file_id == linked_file_id {
+		return Err(FileError::InvalidFileLink(file_id).into());
+	}
+
+	// only one direction is stored, "a is source of b" is kept as "b is derived from a"
+	let (from_id, to_id, kind) = match kind {
+		FileLinkKind::SourceOf => (linked_file_id, file_id, FileLinkKind::DerivedFrom),
+		kind => (file_id, linked_file_id, kind),
+	};
+
+	let existing = ctx
+		.db
+		.file_link()
+		.find_first(vec![
+			file_link::from_file_id::equals(from_id),
+			file_link::to_file_id::equals(to_id),
+			file_link::kind::equals(kind.int_value()),
+		])
+		.exec()
+		.await?;
+
+	if existing.is_none() {
+		ctx.db
+			.file_link()
+			.create(
+				file_link::from_file::link(file::id::equals(from_id)),
+				file_link::to_file::link(file::id::equals(to_id)),
+				vec![file_link::kind::set(kind.int_value())],
+			)
+			.exec()
+			.await?;
+	}
+
+	send_invalidate_query(&ctx, file_id).await;
+	send_invalidate_query(&ctx, linked_file_id).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+pub async fn delete_link(ctx: LibraryContext, id: i32) -> Result<CoreResponse, CoreError> {
+	let link = ctx
+		.db
+		.file_link()
+		.find_unique(file_link::id::equals(id))
+		.delete()
+		.exec()
+		.await?
+		.ok_or(FileError::FileLinkNotFound(id))?;
+
+	send_invalidate_query(&ctx, link.from_file_id).await;
+	send_invalidate_query(&ctx, link.to_file_id).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+// returns the links of a file from both ends
+pub async fn get_links(ctx: &LibraryContext, file_id: i32) -> Result<Vec<FileLink>, FileError> {
+	let outgoing = ctx
+		.db
+		.file_link()
+		.find_many(vec![file_link::from_file_id::equals(file_id)])
+		.with(file_link::to_file::fetch())
+		.exec()
+		.await?;
+	let incoming = ctx
+		.db
+		.file_link()
+		.find_many(vec![file_link::to_file_id::equals(file_id)])
+		.with(file_link::from_file::fetch())
+		.exec()
+		.await?;
+
+	let outgoing = outgoing.into_iter().filter_map(|link| {
+		Some(FileLink {
+			id: link.id,
+			kind: FileLinkKind::from_int(link.kind).ok()?,
+			file: (*link.to_file?).into(),
+			date_created: link.date_created.into(),
+		})
+	});
+	let incoming = incoming.into_iter().filter_map(|link| {
+		Some(FileLink {
+			id: link.id,
+			kind: FileLinkKind::from_int(link.kind).ok()?.inverse(),
+			file: (*link.from_file?).into(),
+			date_created: link.date_created.into(),
+		})
+	});
+
+	Ok(outgoing.chain(incoming).collect())
+}
+
+// returns every file reachable through links of any kind, including the file itself, so a raw
+// file, its edits and the project using them can be browsed together
+pub async fn get_linked_group(ctx: &LibraryContext, file_id: i32) -> Result<Vec<File>, FileError> {
+	let mut group = HashSet::from([file_id]);
+	let mut pending = VecDeque::from([file_id]);
+
+	while let Some(id) = pending.pop_front() {
+		let links = ctx
+			.db
+			.file_link()
+			.find_many(vec![file_link::from_file_id::equals(id)])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|link| link.to_file_id)
+			.chain(
+				ctx.db
+					.file_link()
+					.find_many(vec![file_link::to_file_id::equals(id)])
+					.exec()
+					.await?
+					.into_iter()
+					.map(|link| link.from_file_id),
+			)
+			.collect::<Vec<_>>();
+
+		for linked_id in links {
+			if group.insert(linked_id) {
+				pending.push_back(linked_id);
+			}
+		}
+	}
+
+	Ok(ctx
+		.db
+		.file()
+		.find_many(vec![file::id::in_vec(group.into_iter().collect())])
+		.with(file::paths::fetch(vec![]))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|data| {
+			let paths = data.paths.clone().unwrap_or_default();
+			let mut file = File::from(data);
+			file.paths = paths.into_iter().map(Into::into).collect();
+			file
+		})
+		.collect())
+}
+
+async fn send_invalidate_query(ctx: &LibraryContext, file_id: i32) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetFileLinks { file_id },
+	}))
+	.await;
+}
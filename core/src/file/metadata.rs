@@ -0,0 +1,168 @@
+use crate::{
+	file::{ensure_not_held, send_invalidate_query, FileError},
+	library::LibraryContext,
+	prisma::{self, file},
+	search,
+};
+use log::info;
+use prisma_client_rust::{prisma_models::PrismaValue, raw::Raw};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ts_rs::TS;
+
+// The metadata version a client last read for a file, every metadata write increments it
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FileVersion {
+	pub id: i32,
+	pub version: i32,
+}
+
+// The fields to set on every file, unset fields are left as they are
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FileMetadataUpdate {
+	// an empty note removes it
+	pub note: Option<String>,
+	pub favorite: Option<bool>,
+	pub important: Option<bool>,
+	pub hidden: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FileMetadataResult {
+	// the update is applied to every file or to none of them
+	pub applied: bool,
+	// the current version of each file still in the library, so a client can read them again
+	pub versions: Vec<FileVersion>,
+	// files changed since the client read them, or removed from the library
+	pub conflicts: Vec<i32>,
+}
+
+// sets metadata on many files at once, as long as none of them changed since their version was read
+pub async fn set_metadata(
+	ctx: &LibraryContext,
+	files: Vec<FileVersion>,
+	update: FileMetadataUpdate,
+) -> Result<FileMetadataResult, FileError> {
+	let expected = files
+		.into_iter()
+		.map(|file| (file.id, file.version))
+		.collect::<HashMap<_, _>>();
+	for id in expected.keys() {
+		ensure_not_held(ctx, *id).await?;
+	}
+
+	let mut sets = vec![];
+	let mut values = vec![];
+	if let Some(note) = &update.note {
+		sets.push("note = {}");
+		values.push(match note.is_empty() {
+			true => PrismaValue::Null,
+			false => PrismaValue::String(note.clone()),
+		});
+	}
+	for (set, value) in [
+		("favorite = {}", update.favorite),
+		("important = {}", update.important),
+		("hidden = {}", update.hidden),
+	] {
+		if let Some(value) = value {
+			sets.push(set);
+			values.push(PrismaValue::Boolean(value));
+		}
+	}
+
+	let applied = match expected.is_empty() {
+		true => true,
+		false => apply(ctx, &expected, sets, values).await? == expected.len() as i64,
+	};
+
+	let versions = ctx
+		.db
+		.file()
+		.find_many(vec![file::id::in_vec(expected.keys().copied().collect())])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file| FileVersion {
+			id: file.id,
+			version: file.metadata_version,
+		})
+		.collect::<Vec<_>>();
+
+	let mut conflicts = vec![];
+	if !applied {
+		let current = versions
+			.iter()
+			.map(|file| (file.id, file.version))
+			.collect::<HashMap<_, _>>();
+		conflicts = expected
+			.iter()
+			.filter(|(id, version)| current.get(id) != Some(version))
+			.map(|(id, _)| *id)
+			.collect();
+		conflicts.sort_unstable();
+
+		info!(
+			"Rejected a metadata update of {} files, {} changed since they were read",
+			expected.len(),
+			conflicts.len()
+		);
+	} else if !expected.is_empty() {
+		send_invalidate_query(ctx).await;
+		search::refresh_subscriptions(ctx).await;
+	}
+
+	Ok(FileMetadataResult {
+		applied,
+		versions,
+		conflicts,
+	})
+}
+
+// a single statement, which only changes the files when all of them are still at their expected
+// version. returns the number of files changed
+async fn apply(
+	ctx: &LibraryContext,
+	expected: &HashMap<i32, i32>,
+	mut sets: Vec<&str>,
+	mut values: Vec<PrismaValue>,
+) -> Result<i64, prisma::QueryError> {
+	sets.push("metadata_version = metadata_version + 1");
+
+	values.extend(expected.keys().map(|id| PrismaValue::Int(*id as i64)));
+	for (id, version) in expected {
+		values.push(PrismaValue::Int(*id as i64));
+		values.push(PrismaValue::Int(*version as i64));
+	}
+
+	ctx.db
+		._execute_raw(Raw::new(
+			&format!(
+				"UPDATE files SET {} WHERE id IN ({}) AND (SELECT COUNT(*) FROM files WHERE {}) = {}",
+				sets.join(", "),
+				vec!["{}"; expected.len()].join(", "),
+				vec!["(id = {} AND metadata_version = {})"; expected.len()].join(" OR "),
+				expected.len()
+			),
+			values,
+		))
+		.await
+}
+
+// every other metadata write increments the version too, so batch updates see it
+pub(crate) async fn increment_version(
+	ctx: &LibraryContext,
+	id: i32,
+) -> Result<(), prisma::QueryError> {
+	ctx.db
+		._execute_raw(Raw::new(
+			"UPDATE files SET metadata_version = metadata_version + 1 WHERE id = {}",
+			vec![PrismaValue::Int(id as i64)],
+		))
+		.await?;
+
+	Ok(())
+}
@@ -1,20 +1,44 @@
 use crate::{
 	library::LibraryContext,
-	prisma::{self, file, file_path},
+	prisma::{self, file, file_path, media_data},
+	search,
 	sys::SysError,
 	ClientQuery, CoreError, CoreEvent, CoreResponse, LibraryQuery,
 };
 
 use chrono::{DateTime, Utc};
 use int_enum::IntEnum;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use thiserror::Error;
 use ts_rs::TS;
+use uuid::Uuid;
 
+pub mod archive;
+pub mod attributes;
 pub mod cas;
+pub mod copy;
+pub mod duplicates;
+pub mod ephemeral;
 pub mod explorer;
+pub mod fastcopy;
+pub mod import;
 pub mod indexer;
+pub mod links;
+pub mod metadata;
+pub mod names;
+pub mod notes;
+pub mod preflight;
+pub mod recents;
+pub mod rename;
+pub mod secrets;
+pub mod shred;
+pub mod sizes;
+pub mod storage;
+pub mod text;
+pub mod trash;
+pub mod versions;
 
 // A unique file
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
@@ -29,23 +53,53 @@ pub struct File {
 	pub hidden: bool,
 	pub favorite: bool,
 	pub important: bool,
+	pub legal_hold: bool,
 	pub has_thumbnail: bool,
 	pub has_thumbstrip: bool,
 	pub has_video_preview: bool,
 	// pub encryption: EncryptionAlgorithm,
 	pub ipfs_id: Option<String>,
 	pub note: Option<String>,
+	// incremented by every change to the metadata above, see metadata::set_metadata
+	pub metadata_version: i32,
+	// null until scanned by the secrets scanner
+	pub secret_kind: Option<secrets::SecretKind>,
 
 	pub date_created: DateTime<Utc>,
 	pub date_modified: DateTime<Utc>,
 	pub date_indexed: DateTime<Utc>,
 
 	pub paths: Vec<FilePath>,
-	// pub media_data: Option<MediaData>,
+	pub media_data: Option<MediaData>,
 	// pub tags: Vec<Tag>,
 	// pub label: Vec<Label>,
 }
 
+// Metadata extracted from the contents of an image, video or audio file
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MediaData {
+	pub pixel_width: Option<i32>,
+	pub pixel_height: Option<i32>,
+	pub longitude: Option<f64>,
+	pub latitude: Option<f64>,
+	pub fps: Option<i32>,
+	pub capture_device_make: Option<String>,
+	pub capture_device_model: Option<String>,
+	pub capture_device_software: Option<String>,
+	pub duration_seconds: Option<i32>,
+	pub codecs: Option<String>,
+	pub streams: Option<i32>,
+	pub bitrate: Option<i32>,
+	pub title: Option<String>,
+	pub artist: Option<String>,
+	pub album: Option<String>,
+	pub track_number: Option<i32>,
+	pub has_album_art: bool,
+	// set once a geotagged photo is grouped into a place
+	pub place_id: Option<i32>,
+}
+
 // A physical file path
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
@@ -58,6 +112,22 @@ pub struct FilePath {
 	pub extension: Option<String>,
 	pub file_id: Option<i32>,
 	pub parent_id: Option<i32>,
+	pub retention_exempt: bool,
+	// trashed entries were stored before paths could be hidden
+	#[serde(default)]
+	pub hidden: bool,
+	// the recursive size of directories, none until first computed
+	#[serde(default)]
+	pub folder_size: Option<String>,
+	#[serde(default)]
+	pub folder_size_stale: bool,
+	#[serde(default)]
+	pub is_symlink: bool,
+	// as the link was read, relative links are relative to its directory
+	#[serde(default)]
+	pub link_target: Option<String>,
+	#[serde(default)]
+	pub broken_link: bool,
 
 	pub date_created: DateTime<Utc>,
 	pub date_modified: DateTime<Utc>,
@@ -94,14 +164,20 @@ impl From<file::Data> for File {
 			hidden: data.hidden,
 			favorite: data.favorite,
 			important: data.important,
+			legal_hold: data.legal_hold,
 			has_thumbnail: data.has_thumbnail,
 			has_thumbstrip: data.has_thumbstrip,
 			has_video_preview: data.has_video_preview,
 			note: data.note,
+			metadata_version: data.metadata_version,
+			secret_kind: data
+				.secret_kind
+				.and_then(|kind| IntEnum::from_int(kind).ok()),
 			date_created: data.date_created.into(),
 			date_modified: data.date_modified.into(),
 			date_indexed: data.date_indexed.into(),
 			paths: vec![],
+			media_data: data.media_data.unwrap_or(None).map(Into::into),
 		}
 	}
 }
@@ -112,6 +188,37 @@ impl From<Box<file::Data>> for File {
 	}
 }
 
+impl From<media_data::Data> for MediaData {
+	fn from(data: media_data::Data) -> Self {
+		Self {
+			pixel_width: data.pixel_width,
+			pixel_height: data.pixel_height,
+			longitude: data.longitude,
+			latitude: data.latitude,
+			fps: data.fps,
+			capture_device_make: data.capture_device_make,
+			capture_device_model: data.capture_device_model,
+			capture_device_software: data.capture_device_software,
+			duration_seconds: data.duration_seconds,
+			codecs: data.codecs,
+			streams: data.streams,
+			bitrate: data.bitrate,
+			title: data.title,
+			artist: data.artist,
+			album: data.album,
+			track_number: data.track_number,
+			has_album_art: data.has_album_art,
+			place_id: data.place_id,
+		}
+	}
+}
+
+impl From<Box<media_data::Data>> for MediaData {
+	fn from(data: Box<media_data::Data>) -> Self {
+		Self::from(*data)
+	}
+}
+
 impl From<file_path::Data> for FilePath {
 	fn from(data: file_path::Data) -> Self {
 		Self {
@@ -120,6 +227,13 @@ impl From<file_path::Data> for FilePath {
 			materialized_path: data.materialized_path,
 			file_id: data.file_id,
 			parent_id: data.parent_id,
+			retention_exempt: data.retention_exempt,
+			hidden: data.hidden,
+			folder_size: data.folder_size,
+			folder_size_stale: data.folder_size_stale,
+			is_symlink: data.is_symlink,
+			link_target: data.link_target,
+			broken_link: data.broken_link,
 			location_id: data.location_id.unwrap_or(0),
 			date_indexed: data.date_indexed.into(),
 			name: data.name,
@@ -144,17 +258,109 @@ pub enum FileError {
 	DirectoryNotFound(PathBuf),
 	#[error("File not found (path: {0:?})")]
 	FileNotFound(PathBuf),
+	#[error("File path not found (id: {0})")]
+	FilePathNotFound(i32),
+	#[error("Duplicate group not found (id: {0})")]
+	DuplicateGroupNotFound(i32),
+	#[error("Only exact duplicates can be hardlinked (group id: {0})")]
+	InvalidDuplicateResolution(i32),
+	#[error("File has no perceptual hash (id: {0})")]
+	PerceptualHashNotFound(i32),
+	#[error("File is under legal hold (id: {0})")]
+	LegalHold(i32),
+	#[error("File link not found (id: {0})")]
+	FileLinkNotFound(i32),
+	#[error("A file can't be linked to itself (id: {0})")]
+	InvalidFileLink(i32),
+	#[error("Trashed entry not found (id: {0})")]
+	TrashedEntryNotFound(i32),
+	#[error("Can't restore, something already exists at {0:?}")]
+	RestoreConflict(PathBuf),
+	#[error("Location is not available on this node (id: {0})")]
+	LocationUnavailable(i32),
+	#[error("Can't copy a directory into itself (path: {0})")]
+	CopyIntoItself(String),
+	#[error("Job isn't waiting for a conflict to be resolved (id: {0})")]
+	NoPendingConflict(Uuid),
+	#[error("Invalid rename pattern: {0}")]
+	InvalidRenamePattern(String),
+	#[error("New names are taken or invalid (file path ids: {0:?})")]
+	RenameCollision(Vec<i32>),
+	#[error("File version not found (id: {0})")]
+	SnapshotNotFound(i32),
+	#[error("Invalid directory cursor: {0}")]
+	InvalidCursor(String),
+	#[error("Note not found (id: {0})")]
+	NoteNotFound(i32),
+	#[error("Not a supported archive: {0}")]
+	UnsupportedArchive(String),
+	#[error("Names can't be written to the target as they are (paths: {0:?})")]
+	InvalidTargetNames(Vec<String>),
+	#[error("Something already exists at {0:?}")]
+	AlreadyExists(PathBuf),
+	#[error(
+		"Path is within a location, change it through the location (path: {0:?}, location id: {1})"
+	)]
+	WithinLocation(PathBuf, i32),
+	#[error("Invalid trashed entry: {0}")]
+	InvalidTrashedEntry(#[from] serde_json::Error),
+	#[error("I/O error: {0}")]
+	IOError(#[from] std::io::Error),
 	#[error("Database error")]
 	DatabaseError(#[from] prisma::QueryError),
 	#[error("System error")]
 	SysError(#[from] SysError),
 }
 
+// returns an error if the file is under legal hold, must be checked before any action that deletes or modifies it
+pub async fn ensure_not_held(ctx: &LibraryContext, id: i32) -> Result<(), FileError> {
+	let held = ctx
+		.db
+		.file()
+		.find_unique(file::id::equals(id))
+		.exec()
+		.await?
+		.map_or(false, |file| file.legal_hold);
+
+	if held {
+		warn!("Blocked an action on file {} under legal hold", id);
+		return Err(FileError::LegalHold(id));
+	}
+
+	Ok(())
+}
+
+pub async fn set_legal_hold(
+	ctx: LibraryContext,
+	id: i32,
+	legal_hold: bool,
+) -> Result<CoreResponse, CoreError> {
+	ctx.db
+		.file()
+		.find_unique(file::id::equals(id))
+		.update(vec![file::legal_hold::set(legal_hold)])
+		.exec()
+		.await?;
+	metadata::increment_version(&ctx, id).await?;
+
+	info!(
+		"Legal hold {} on file {}",
+		if legal_hold { "placed" } else { "released" },
+		id
+	);
+
+	send_invalidate_query(&ctx).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
 pub async fn set_note(
 	ctx: LibraryContext,
 	id: i32,
 	note: Option<String>,
 ) -> Result<CoreResponse, CoreError> {
+	ensure_not_held(&ctx, id).await?;
+
 	let _response = ctx
 		.db
 		.file()
@@ -163,6 +369,7 @@ pub async fn set_note(
 		.exec()
 		.await
 		.unwrap();
+	metadata::increment_version(&ctx, id).await?;
 
 	send_invalidate_query(&ctx).await;
 
@@ -174,6 +381,8 @@ pub async fn favorite(
 	id: i32,
 	favorite: bool,
 ) -> Result<CoreResponse, CoreError> {
+	ensure_not_held(&ctx, id).await?;
+
 	let _response = ctx
 		.db
 		.file()
@@ -182,19 +391,34 @@ pub async fn favorite(
 		.exec()
 		.await
 		.unwrap();
+	metadata::increment_version(&ctx, id).await?;
 
 	send_invalidate_query(&ctx).await;
+	search::refresh_subscriptions(&ctx).await;
 
 	Ok(CoreResponse::Success(()))
 }
 
-async fn send_invalidate_query(ctx: &LibraryContext) {
+pub async fn get_file_path(ctx: &LibraryContext, id: i32) -> Result<FilePath, FileError> {
+	Ok(ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(id))
+		.with(file_path::file::fetch())
+		.exec()
+		.await?
+		.ok_or(FileError::FilePathNotFound(id))?
+		.into())
+}
+
+pub(crate) async fn send_invalidate_query(ctx: &LibraryContext) {
 	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
 		library_id: ctx.id,
 		query: LibraryQuery::GetExplorerDir {
 			limit: 0,
 			path: PathBuf::new(),
 			location_id: 0,
+			show_hidden: None,
 		},
 	}))
 	.await;
@@ -0,0 +1,244 @@
+use crate::{
+	file::{
+		copy::full_name,
+		rename::{is_taken, sibling_path, BatchRenameJob, BatchRenameJobInit, RenamedPath},
+		FileError,
+	},
+	library::LibraryContext,
+	prisma::file_path,
+	sys::{get_location, Volume},
+	Job,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use ts_rs::TS;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+// file systems which store names decomposed, whatever form they are given in
+const DECOMPOSING_FILE_SYSTEMS: [&str; 2] = ["hfs", "hfs+"];
+// names encoded twice are decoded as many times
+const MAX_MOJIBAKE_DEPTH: usize = 3;
+// the characters of Windows-1252 from 0x80 to 0x9F, the bytes it leaves undefined are read as the C1
+// controls of Latin-1
+const WINDOWS_1252: [char; 32] = [
+	'€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+	'\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+// The form names are normalized to. Names are composed (NFC) as Linux and Windows write them,
+// except on HFS+ volumes which decompose them (NFD) anyway. APFS keeps names as they are given and
+// finds them in either form, so names written by older macOS versions are composed there too.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum NormalizationForm {
+	Nfc,
+	Nfd,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum NameIssue {
+	// decomposed characters, as macOS writes them, on a volume names are composed on. Linux tells
+	// the two forms apart, so the same name can be in a directory twice
+	Decomposed,
+	// UTF-8 read as Windows-1252 or Latin-1 by another system, "cafÃ©" for "café"
+	Mojibake,
+	// bytes which aren't UTF-8, replaced when the entry was indexed. The entry can't be renamed
+	// from its name in the index, its encoding has to be converted on disk
+	InvalidEncoding,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NameRepair {
+	pub materialized_path: String,
+	pub issues: Vec<NameIssue>,
+	// unset when the name can't be repaired
+	pub rename: Option<RenamedPath>,
+	// the repaired name is taken, by another entry of the directory or another repair
+	pub collision: bool,
+}
+
+// What repairing the names of a location would rename, the entries whose names are fine are left
+// out
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NameRepairPreview {
+	pub location_id: i32,
+	pub form: NormalizationForm,
+	// the number of entries checked
+	pub checked: usize,
+	// parents before their contents
+	pub repairs: Vec<NameRepair>,
+}
+
+// the form names are stored in on the volume of the location, from the volume mounted closest to it
+fn normalization_form(location_path: &str) -> NormalizationForm {
+	let decomposing = Volume::get_volumes()
+		.unwrap_or_default()
+		.into_iter()
+		.filter(|volume| location_path.starts_with(&volume.mount_point))
+		.max_by_key(|volume| volume.mount_point.len())
+		.and_then(|volume| volume.file_system)
+		.map_or(false, |file_system| {
+			DECOMPOSING_FILE_SYSTEMS.contains(&file_system.to_lowercase().as_str())
+		});
+
+	match decomposing {
+		true => NormalizationForm::Nfd,
+		false => NormalizationForm::Nfc,
+	}
+}
+
+fn windows_1252_byte(c: char) -> Option<u8> {
+	match c as u32 {
+		0..=0xFF => Some(c as u8),
+		_ => WINDOWS_1252
+			.iter()
+			.position(|other| *other == c)
+			.map(|i| 0x80 + i as u8),
+	}
+}
+
+// the name the bytes of `name` make once read as UTF-8, when `name` is UTF-8 read as Windows-1252.
+// Names which really are Windows-1252 almost never make valid UTF-8, as their accented letters
+// aren't followed by the continuation bytes it needs
+fn decode_mojibake(name: &str) -> Option<String> {
+	if name.is_ascii() {
+		return None;
+	}
+
+	// mojibake written on macOS is decomposed, "Ã" as "A" and a combining tilde
+	let bytes = name
+		.nfc()
+		.map(windows_1252_byte)
+		.collect::<Option<Vec<_>>>()?;
+	String::from_utf8(bytes).ok()
+}
+
+// the issues of a name, and its repaired name unless it is fine or can't be repaired
+fn repair_name(name: &str, form: NormalizationForm) -> (Vec<NameIssue>, Option<String>) {
+	if name.contains(char::REPLACEMENT_CHARACTER) {
+		return (vec![NameIssue::InvalidEncoding], None);
+	}
+
+	let mut issues = vec![];
+	let mut repaired = name.to_string();
+	for _ in 0..MAX_MOJIBAKE_DEPTH {
+		match decode_mojibake(&repaired) {
+			Some(decoded) => repaired = decoded,
+			None => break,
+		}
+		if !issues.contains(&NameIssue::Mojibake) {
+			issues.push(NameIssue::Mojibake);
+		}
+	}
+
+	let repaired = match form {
+		NormalizationForm::Nfc => {
+			if !is_nfc(&repaired) {
+				issues.push(NameIssue::Decomposed);
+			}
+			repaired.nfc().collect::<String>()
+		}
+		NormalizationForm::Nfd => repaired.nfd().collect(),
+	};
+
+	let renamed = (repaired != name && !issues.is_empty()).then(|| repaired);
+	(issues, renamed)
+}
+
+pub async fn get_name_repair_preview(
+	ctx: &LibraryContext,
+	location_id: i32,
+) -> Result<NameRepairPreview, FileError> {
+	let location_path = get_location(ctx, location_id)
+		.await?
+		.path
+		.ok_or(FileError::LocationUnavailable(location_id))?;
+	let form = normalization_form(&location_path.to_string_lossy());
+
+	let mut file_paths = ctx
+		.db
+		.file_path()
+		.find_many(vec![file_path::location_id::equals(Some(location_id))])
+		.exec()
+		.await?;
+	// by depth, so directories are renamed before their contents
+	file_paths.sort_by_key(|file_path| file_path.materialized_path.matches('/').count());
+
+	let mut repairs = vec![];
+	let mut taken = HashSet::new();
+	for file_path in &file_paths {
+		let from = full_name(file_path);
+		let (issues, to) = repair_name(&from, form);
+		if issues.is_empty() {
+			continue;
+		}
+
+		let mut collision = false;
+		if let Some(to) = &to {
+			let path = sibling_path(&file_path.materialized_path, to);
+			collision = !taken.insert(path.clone())
+				|| is_taken(ctx, location_id, &file_path.materialized_path, &path).await?;
+		}
+		repairs.push(NameRepair {
+			materialized_path: file_path.materialized_path.clone(),
+			issues,
+			rename: to.map(|to| RenamedPath {
+				file_path_id: file_path.id,
+				from,
+				to,
+			}),
+			collision,
+		});
+	}
+
+	Ok(NameRepairPreview {
+		location_id,
+		form,
+		checked: file_paths.len(),
+		repairs,
+	})
+}
+
+// renames the entries of the preview given, or every one which can be repaired without a
+// collision, as a batch rename. Undoing it puts every name back
+pub async fn repair_names(
+	ctx: &LibraryContext,
+	location_id: i32,
+	file_path_ids: Option<Vec<i32>>,
+) -> Result<(), FileError> {
+	let preview = get_name_repair_preview(ctx, location_id).await?;
+
+	let mut collisions = vec![];
+	let mut renames = vec![];
+	for repair in preview.repairs {
+		let renamed = match repair.rename {
+			Some(renamed) => renamed,
+			None => continue,
+		};
+		let selected = file_path_ids
+			.as_ref()
+			.map_or(true, |ids| ids.contains(&renamed.file_path_id));
+		match (selected, repair.collision) {
+			(false, _) => {}
+			(true, true) if file_path_ids.is_some() => collisions.push(renamed.file_path_id),
+			(true, true) => {}
+			(true, false) => renames.push(renamed),
+		}
+	}
+	if !collisions.is_empty() {
+		return Err(FileError::RenameCollision(collisions));
+	}
+
+	if !renames.is_empty() {
+		ctx.spawn_job(Job::new(
+			BatchRenameJobInit { renames },
+			Box::new(BatchRenameJob {}),
+		))
+		.await;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,340 @@
+use crate::{
+	file::FileError,
+	library::LibraryContext,
+	prisma::{file, note},
+	ClientQuery, CoreEvent, LibraryQuery,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ts_rs::TS;
+use uuid::Uuid;
+
+// the length of the plain text previews of notes, in characters
+const PREVIEW_LENGTH: usize = 140;
+
+// A note on a file, as shown by this node
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Note {
+	pub id: i32,
+	pub pub_id: Uuid,
+	pub file_id: i32,
+	// markdown
+	pub body: String,
+	// the start of the body as plain text, for lists of notes
+	pub preview: String,
+	// the node the note was written on
+	pub author: Uuid,
+	pub author_name: String,
+	pub date_created: DateTime<Utc>,
+	pub date_modified: DateTime<Utc>,
+}
+
+impl From<note::Data> for Note {
+	fn from(data: note::Data) -> Self {
+		Self {
+			id: data.id,
+			pub_id: Uuid::from_slice(&data.pub_id).unwrap(),
+			file_id: data.file_id,
+			preview: preview(&data.body),
+			body: data.body,
+			author: Uuid::from_slice(&data.author).unwrap(),
+			author_name: data.author_name,
+			date_created: data.date_created.into(),
+			date_modified: data.date_modified.into(),
+		}
+	}
+}
+
+// NoteChange is a note as exchanged between the nodes of a library, deleted ones included. The
+// file is identified by its cas id, which unlike its id is the same on every node.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NoteChange {
+	pub pub_id: Uuid,
+	pub cas_id: String,
+	pub body: String,
+	pub author: Uuid,
+	pub author_name: String,
+	pub modified_by: Uuid,
+	pub date_created: DateTime<Utc>,
+	pub date_modified: DateTime<Utc>,
+	pub date_deleted: Option<DateTime<Utc>>,
+}
+
+// the first line with any text, without the markdown marking it up
+fn preview(body: &str) -> String {
+	let line = body
+		.lines()
+		.map(|line| {
+			line.trim().trim_start_matches(|c: char| {
+				matches!(c, '#' | '>' | '-' | '+') || c.is_whitespace()
+			})
+		})
+		.find(|line| !line.is_empty())
+		.unwrap_or_default();
+
+	let text = line
+		.chars()
+		.filter(|c| !matches!(c, '*' | '_' | '`' | '[' | ']'))
+		.collect::<String>();
+	match text.char_indices().nth(PREVIEW_LENGTH) {
+		Some((end, _)) => format!("{}…", text[..end].trim_end()),
+		None => text,
+	}
+}
+
+// a change wins over another made later, or at the same time by a node with a greater id
+fn wins(
+	date_modified: DateTime<Utc>,
+	modified_by: Uuid,
+	other_date_modified: DateTime<Utc>,
+	other_modified_by: Uuid,
+) -> bool {
+	(date_modified, modified_by) > (other_date_modified, other_modified_by)
+}
+
+async fn send_invalidate_notes(ctx: &LibraryContext, file_id: i32) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetNotes { file_id },
+	}))
+	.await;
+}
+
+// the note, unless it was deleted
+async fn get_note(ctx: &LibraryContext, id: i32) -> Result<note::Data, FileError> {
+	ctx.db
+		.note()
+		.find_first(vec![note::id::equals(id), note::date_deleted::equals(None)])
+		.exec()
+		.await?
+		.ok_or(FileError::NoteNotFound(id))
+}
+
+pub async fn create_note(
+	ctx: &LibraryContext,
+	file_id: i32,
+	body: String,
+) -> Result<Note, FileError> {
+	let node = ctx.config().get().await;
+
+	let note = ctx
+		.db
+		.note()
+		.create(
+			note::pub_id::set(Uuid::new_v4().as_bytes().to_vec()),
+			note::file::link(file::id::equals(file_id)),
+			note::body::set(body),
+			note::author::set(node.id.as_bytes().to_vec()),
+			note::author_name::set(node.name),
+			note::modified_by::set(node.id.as_bytes().to_vec()),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	send_invalidate_notes(ctx, file_id).await;
+
+	Ok(note.into())
+}
+
+pub async fn update_note(ctx: &LibraryContext, id: i32, body: String) -> Result<(), FileError> {
+	let note = get_note(ctx, id).await?;
+	let node = ctx.config().get().await;
+
+	ctx.db
+		.note()
+		.find_unique(note::id::equals(id))
+		.update(vec![
+			note::body::set(body),
+			note::modified_by::set(node.id.as_bytes().to_vec()),
+			note::date_modified::set(Utc::now().into()),
+		])
+		.exec()
+		.await?;
+
+	send_invalidate_notes(ctx, note.file_id).await;
+
+	Ok(())
+}
+
+// the note is emptied and kept as deleted, see NoteChange
+pub async fn delete_note(ctx: &LibraryContext, id: i32) -> Result<(), FileError> {
+	let note = get_note(ctx, id).await?;
+	let node = ctx.config().get().await;
+	let now = Utc::now();
+
+	ctx.db
+		.note()
+		.find_unique(note::id::equals(id))
+		.update(vec![
+			note::body::set(String::new()),
+			note::modified_by::set(node.id.as_bytes().to_vec()),
+			note::date_modified::set(now.into()),
+			note::date_deleted::set(Some(now.into())),
+		])
+		.exec()
+		.await?;
+
+	send_invalidate_notes(ctx, note.file_id).await;
+
+	Ok(())
+}
+
+// oldest first, as a conversation reads
+pub async fn get_notes(ctx: &LibraryContext, file_id: i32) -> Result<Vec<Note>, FileError> {
+	let mut notes = ctx
+		.db
+		.note()
+		.find_many(vec![
+			note::file_id::equals(file_id),
+			note::date_deleted::equals(None),
+		])
+		.exec()
+		.await?
+		.into_iter()
+		.map(Note::from)
+		.collect::<Vec<_>>();
+	notes.sort_by(|a, b| a.date_created.cmp(&b.date_created));
+
+	Ok(notes)
+}
+
+// get_note_changes returns the notes which changed after `since`, every note without it, for
+// another node of the library to merge
+pub async fn get_note_changes(
+	ctx: &LibraryContext,
+	since: Option<DateTime<Utc>>,
+) -> Result<Vec<NoteChange>, FileError> {
+	let notes = ctx
+		.db
+		.note()
+		.find_many(match since {
+			Some(since) => vec![note::date_modified::gt(since.into())],
+			None => vec![],
+		})
+		.with(note::file::fetch())
+		.exec()
+		.await?;
+
+	Ok(notes
+		.into_iter()
+		.filter_map(|note| {
+			Some(NoteChange {
+				pub_id: Uuid::from_slice(&note.pub_id).ok()?,
+				cas_id: note.file.as_ref()?.cas_id.clone(),
+				body: note.body,
+				author: Uuid::from_slice(&note.author).ok()?,
+				author_name: note.author_name,
+				modified_by: Uuid::from_slice(&note.modified_by).ok()?,
+				date_created: note.date_created.into(),
+				date_modified: note.date_modified.into(),
+				date_deleted: note.date_deleted.map(Into::into),
+			})
+		})
+		.collect())
+}
+
+// merge_notes applies the changes of another node, each note keeping whichever of its changes
+// wins, so nodes merging each other's changes in any order end up with the same notes. Notes of
+// files this node doesn't have are skipped, returning how many changes were applied.
+pub async fn merge_notes(
+	ctx: &LibraryContext,
+	changes: Vec<NoteChange>,
+) -> Result<usize, FileError> {
+	let files = ctx
+		.db
+		.file()
+		.find_many(vec![file::cas_id::in_vec(
+			changes.iter().map(|change| change.cas_id.clone()).collect(),
+		)])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file| (file.cas_id, file.id))
+		.collect::<HashMap<_, _>>();
+	let mut existing = ctx
+		.db
+		.note()
+		.find_many(vec![note::pub_id::in_vec(
+			changes
+				.iter()
+				.map(|change| change.pub_id.as_bytes().to_vec())
+				.collect(),
+		)])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|note| (note.pub_id.clone(), note))
+		.collect::<HashMap<_, _>>();
+
+	let mut applied = 0;
+	for change in changes {
+		let file_id = match files.get(&change.cas_id) {
+			Some(file_id) => *file_id,
+			None => continue,
+		};
+		let pub_id = change.pub_id.as_bytes().to_vec();
+
+		let local = existing.get(&pub_id).map(|local| {
+			(
+				local.id,
+				local.date_modified.into(),
+				Uuid::from_slice(&local.modified_by).unwrap_or_default(),
+			)
+		});
+		let note = match local {
+			Some((id, date_modified, modified_by)) => {
+				if !wins(
+					change.date_modified,
+					change.modified_by,
+					date_modified,
+					modified_by,
+				) {
+					continue;
+				}
+				ctx.db
+					.note()
+					.find_unique(note::id::equals(id))
+					.update(vec![
+						note::body::set(change.body),
+						note::modified_by::set(change.modified_by.as_bytes().to_vec()),
+						note::date_modified::set(change.date_modified.into()),
+						note::date_deleted::set(change.date_deleted.map(Into::into)),
+					])
+					.exec()
+					.await?
+			}
+			None => Some(
+				ctx.db
+					.note()
+					.create(
+						note::pub_id::set(pub_id.clone()),
+						note::file::link(file::id::equals(file_id)),
+						note::body::set(change.body),
+						note::author::set(change.author.as_bytes().to_vec()),
+						note::author_name::set(change.author_name),
+						note::modified_by::set(change.modified_by.as_bytes().to_vec()),
+						vec![
+							note::date_created::set(change.date_created.into()),
+							note::date_modified::set(change.date_modified.into()),
+							note::date_deleted::set(change.date_deleted.map(Into::into)),
+						],
+					)
+					.exec()
+					.await?,
+			),
+		};
+		// the same note may come more than once in a batch
+		if let Some(note) = note {
+			existing.insert(pub_id, note);
+		}
+
+		applied += 1;
+		send_invalidate_notes(ctx, file_id).await;
+	}
+
+	Ok(applied)
+}
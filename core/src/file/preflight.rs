@@ -0,0 +1,730 @@
+use crate::{
+	file::{copy::full_name, FileError},
+	library::LibraryContext,
+	prisma::file_path,
+	sys::{get_location, FileSystemCapabilities, Volume},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, HashSet},
+	path::{Path, PathBuf},
+};
+use tokio::task::spawn_blocking;
+use ts_rs::TS;
+use unicode_normalization::UnicodeNormalization;
+use walkdir::WalkDir;
+
+// file systems with the naming rules of Windows, wherever they are mounted
+const WINDOWS_FILE_SYSTEMS: [&str; 7] = ["ntfs", "exfat", "vfat", "fat", "fat16", "fat32", "msdos"];
+// formatted case-insensitive unless asked otherwise
+const MACOS_FILE_SYSTEMS: [&str; 3] = ["apfs", "hfs", "hfs+"];
+const ILLEGAL_CHARACTERS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+// with or without an extension, "CON.txt" is the console as well. COM1-9 and LPT1-9 too
+const RESERVED_NAMES: [&str; 4] = ["CON", "PRN", "AUX", "NUL"];
+// in UTF-16 units on Windows file systems, in bytes elsewhere
+const MAX_NAME_LEN: usize = 255;
+
+// What a copy or move does with entries which can't be written to the target as they are named
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum InvalidNamePolicy {
+	// nothing is copied as long as there are any, see GetCopyPreflight
+	Abort,
+	// their names are changed to valid ones, see CopyIssue::sanitized_path
+	Sanitize,
+	// they are left out, as are those no valid name fits when sanitizing. A move leaves out the
+	// whole entry they are in
+	Skip,
+}
+
+impl Default for InvalidNamePolicy {
+	fn default() -> Self {
+		Self::Abort
+	}
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum CopyIssueKind {
+	// another entry of the directory has the same name but for its case, they'd overwrite each other
+	CaseCollision,
+	// CON, NUL and the other device names of Windows
+	ReservedName,
+	// characters Windows forbids in names, or a trailing dot or space
+	IllegalCharacters,
+	NameTooLong,
+	PathTooLong,
+}
+
+// An entry which can't be written to the target as it is named
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CopyIssue {
+	// the entry copied it is in, or itself
+	pub file_path_id: i32,
+	pub source_path: String,
+	pub target_path: String,
+	pub kinds: Vec<CopyIssueKind>,
+	// where a sanitized copy would go, unset if no valid name fits
+	pub sanitized_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CopyPreflight {
+	pub file_system: Option<String>,
+	pub case_insensitive: bool,
+	// the entries checked, contents of directories included
+	pub entry_count: usize,
+	pub issues: Vec<CopyIssue>,
+}
+
+// The naming rules of the volume a copy is written to, and how files can be copied to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TargetRules {
+	pub file_system: Option<String>,
+	pub case_insensitive: bool,
+	pub windows_names: bool,
+	// the length of a whole path, the location root included
+	pub max_path_len: usize,
+	#[serde(default)]
+	pub capabilities: FileSystemCapabilities,
+}
+
+impl TargetRules {
+	// from the volume mounted closest to the target and the system of this node
+	pub fn of(target_root: &Path) -> Self {
+		let volume = Volume::of_path(target_root);
+		let capabilities = volume
+			.as_ref()
+			.map(|volume| volume.capabilities)
+			.unwrap_or_default();
+		let file_system = volume
+			.and_then(|volume| volume.file_system)
+			.map(|file_system| file_system.to_lowercase());
+		let is = |file_systems: &[&str]| {
+			file_system
+				.as_deref()
+				.map_or(false, |file_system| file_systems.contains(&file_system))
+		};
+
+		let windows_names = cfg!(windows) || is(&WINDOWS_FILE_SYSTEMS);
+		Self {
+			case_insensitive: windows_names || is(&MACOS_FILE_SYSTEMS),
+			windows_names,
+			max_path_len: if cfg!(windows) {
+				260
+			} else if cfg!(target_os = "macos") {
+				1024
+			} else {
+				4096
+			},
+			file_system,
+			capabilities,
+		}
+	}
+
+	fn name_len(&self, name: &str) -> usize {
+		match self.windows_names {
+			true => name.encode_utf16().count(),
+			false => name.len(),
+		}
+	}
+
+	fn path_len(&self, path: &Path) -> usize {
+		let path = path.to_string_lossy();
+		match cfg!(windows) {
+			true => path.encode_utf16().count(),
+			false => path.len(),
+		}
+	}
+
+	fn check_name(&self, name: &str) -> Vec<CopyIssueKind> {
+		let mut kinds = vec![];
+		if self.windows_names {
+			if name.chars().any(is_illegal) || name.ends_with(['.', ' ']) {
+				kinds.push(CopyIssueKind::IllegalCharacters);
+			}
+			if is_reserved(name) {
+				kinds.push(CopyIssueKind::ReservedName);
+			}
+		}
+		if self.name_len(name) > MAX_NAME_LEN {
+			kinds.push(CopyIssueKind::NameTooLong);
+		}
+		kinds
+	}
+
+	// the name compared to the others of its directory
+	fn fold(&self, name: &str) -> String {
+		match self.case_insensitive {
+			true => name.nfc().collect::<String>().to_lowercase(),
+			false => name.to_string(),
+		}
+	}
+
+	// a valid name close to `name` which fits in `max_len`
+	fn sanitize(&self, name: &str, max_len: usize) -> Option<String> {
+		let mut name = name.to_string();
+		if self.windows_names {
+			name = name
+				.chars()
+				.map(|c| if is_illegal(c) { '_' } else { c })
+				.collect::<String>()
+				.trim_end_matches(['.', ' '])
+				.to_string();
+			if name.is_empty() {
+				name = "_".to_string();
+			}
+			if is_reserved(&name) {
+				name = match name.split_once('.') {
+					Some((stem, rest)) => format!("{}_.{}", stem, rest),
+					None => format!("{}_", name),
+				};
+			}
+		}
+
+		self.truncate(&name, max_len)
+	}
+
+	// shortens the stem of a name, its extension is kept
+	fn truncate(&self, name: &str, max_len: usize) -> Option<String> {
+		if self.name_len(name) <= max_len {
+			return Some(name.to_string());
+		}
+
+		let (stem, extension) = split_extension(name);
+		let mut stem = stem.to_string();
+		while !stem.is_empty() && self.name_len(&stem) + self.name_len(&extension) > max_len {
+			stem.pop();
+		}
+		// a name of a dot and an extension would be hidden
+		(!stem.trim_end().is_empty()).then(|| format!("{}{}", stem.trim_end(), extension))
+	}
+}
+
+fn is_illegal(c: char) -> bool {
+	ILLEGAL_CHARACTERS.contains(&c) || (c as u32) < 0x20
+}
+
+fn is_reserved(name: &str) -> bool {
+	let stem = name
+		.split('.')
+		.next()
+		.unwrap_or(name)
+		.trim_end()
+		.to_uppercase();
+	RESERVED_NAMES.contains(&stem.as_str())
+		|| (stem.len() == 4
+			&& (stem.starts_with("COM") || stem.starts_with("LPT"))
+			&& matches!(stem.as_bytes()[3], b'1'..=b'9'))
+}
+
+fn split_extension(name: &str) -> (&str, String) {
+	match name.rsplit_once('.') {
+		Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+		_ => (name, String::new()),
+	}
+}
+
+// The names a copy writes in place of those of the source, by materialized path of the source.
+// Entries mapped to none are left out with their contents
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TargetNames(HashMap<String, Option<String>>);
+
+impl TargetNames {
+	// the name of an entry of the source at the target, none if it is left out
+	pub fn name(&self, source_path: &str, name: String) -> Option<String> {
+		match self.0.get(source_path) {
+			Some(renamed) => renamed.clone(),
+			None => Some(name),
+		}
+	}
+
+	pub fn get(&self, source_path: &str) -> Option<&Option<String>> {
+		self.0.get(source_path)
+	}
+
+	pub fn renamed(&self) -> usize {
+		self.0.values().filter(|name| name.is_some()).count()
+	}
+
+	pub fn left_out(&self) -> usize {
+		self.0.values().filter(|name| name.is_none()).count()
+	}
+
+	// whether anything below the entry at `source_path` is renamed or left out
+	pub fn changes_below(&self, source_path: &str) -> bool {
+		let prefix = format!("{}/", source_path);
+		self.0.keys().any(|path| path.starts_with(&prefix))
+	}
+
+	// the path at the target of an entry below `from`, copied to `to`, with the names of its
+	// parents changed. none if it or one of its parents is left out
+	pub fn rebase(&self, path: &str, from: &str, to: &str) -> Option<String> {
+		let mut source = from.to_string();
+		let mut target = to.to_string();
+		for component in path[from.len()..].split('/').filter(|c| !c.is_empty()) {
+			source = format!("{}/{}", source, component);
+			target = format!("{}/{}", target, self.name(&source, component.to_string())?);
+		}
+		Some(target)
+	}
+}
+
+// The issues of an entry copied, with what is done about them
+pub(crate) struct EntryPlan {
+	pub issues: Vec<CopyIssue>,
+	pub names: TargetNames,
+	pub entry_count: usize,
+}
+
+// walks an entry as it is on disk, so what isn't indexed yet is checked too. `target_dir` is the
+// materialized path of the directory it is copied into
+pub(crate) async fn plan_entry(
+	rules: &TargetRules,
+	source_root: &Path,
+	file_path: &file_path::Data,
+	target_root: &Path,
+	target_dir: &str,
+	policy: InvalidNamePolicy,
+	delete_source: bool,
+) -> Result<EntryPlan, FileError> {
+	let rules = rules.clone();
+	let source_root = source_root.to_path_buf();
+	let target_root = target_root.to_path_buf();
+	let target_dir = target_dir.to_string();
+	let (id, source_path, name) = (
+		file_path.id,
+		file_path.materialized_path.clone(),
+		full_name(file_path),
+	);
+
+	let plan = spawn_blocking(move || {
+		walk(
+			&rules,
+			&source_root,
+			(id, &source_path, name),
+			&target_root,
+			&target_dir,
+			policy,
+		)
+	})
+	.await
+	.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+
+	// a move with anything to leave out is skipped whole, so nothing is half moved
+	Ok(match delete_source && plan.names.left_out() > 0 {
+		true => EntryPlan {
+			names: TargetNames(HashMap::from([(file_path.materialized_path.clone(), None)])),
+			..plan
+		},
+		false => plan,
+	})
+}
+
+fn walk(
+	rules: &TargetRules,
+	source_root: &Path,
+	(file_path_id, source_path, name): (i32, &str, String),
+	target_root: &Path,
+	target_dir: &str,
+	policy: InvalidNamePolicy,
+) -> EntryPlan {
+	let mut plan = EntryPlan {
+		issues: vec![],
+		names: TargetNames::default(),
+		entry_count: 0,
+	};
+	// the target path of every directory, and the names given in it so far
+	let mut dirs = HashMap::from([(String::new(), target_dir.to_string())]);
+	let mut taken: HashMap<String, HashSet<String>> = HashMap::new();
+
+	let mut entries = WalkDir::new(source_root.join(source_path))
+		.sort_by(|a, b| a.file_name().cmp(b.file_name()))
+		.into_iter();
+	while let Some(entry) = entries.next() {
+		let entry = match entry {
+			Ok(entry) => entry,
+			Err(_) => continue,
+		};
+		plan.entry_count += 1;
+
+		let (path, name) = match entry.depth() {
+			0 => (source_path.to_string(), name.clone()),
+			_ => {
+				let name = entry.file_name().to_string_lossy().to_string();
+				let relative = entry
+					.path()
+					.strip_prefix(source_root)
+					.unwrap_or(entry.path())
+					.components()
+					.map(|component| component.as_os_str().to_string_lossy())
+					.collect::<Vec<_>>()
+					.join("/");
+				(relative, name)
+			}
+		};
+		let parent = match entry.depth() {
+			0 => String::new(),
+			_ => path
+				.rsplit_once('/')
+				.map_or("", |(parent, _)| parent)
+				.to_string(),
+		};
+		let target_parent = match dirs.get(&parent) {
+			Some(target_parent) => target_parent.clone(),
+			// the parent was left out
+			None => continue,
+		};
+		let join = |name: &str| match target_parent.is_empty() {
+			true => name.to_string(),
+			false => format!("{}/{}", target_parent, name),
+		};
+
+		let mut kinds = rules.check_name(&name);
+		// the entries copied are conflicts for the policy of the copy, only what they contain is
+		// checked against each other
+		let siblings = taken.entry(target_parent.clone()).or_default();
+		if entry.depth() > 0 && siblings.contains(&rules.fold(&name)) {
+			kinds.push(CopyIssueKind::CaseCollision);
+		}
+		let room = rules
+			.max_path_len
+			.saturating_sub(rules.path_len(&target_root.join(join(""))));
+		if rules.name_len(&name) > room {
+			kinds.push(CopyIssueKind::PathTooLong);
+		}
+
+		let target_name = match kinds.is_empty() {
+			true => Some(name.clone()),
+			false => {
+				let sanitized = rules
+					.sanitize(&name, room.min(MAX_NAME_LEN))
+					.and_then(|sanitized| free_name(rules, siblings, &sanitized, room));
+				plan.issues.push(CopyIssue {
+					file_path_id,
+					source_path: path.clone(),
+					target_path: join(&name),
+					kinds,
+					sanitized_path: sanitized.as_deref().map(join),
+				});
+				let target_name = match policy {
+					InvalidNamePolicy::Skip => None,
+					_ => sanitized,
+				};
+				plan.names.0.insert(path.clone(), target_name.clone());
+				target_name
+			}
+		};
+
+		match target_name {
+			Some(target_name) => {
+				siblings.insert(rules.fold(&target_name));
+				if entry.file_type().is_dir() {
+					dirs.insert(path, join(&target_name));
+				}
+			}
+			None if entry.file_type().is_dir() => entries.skip_current_dir(),
+			None => {}
+		}
+	}
+
+	plan
+}
+
+// numbers a name until it is free in its directory, eg: "photo (2).jpg"
+fn free_name(
+	rules: &TargetRules,
+	siblings: &HashSet<String>,
+	name: &str,
+	room: usize,
+) -> Option<String> {
+	if !siblings.contains(&rules.fold(name)) {
+		return Some(name.to_string());
+	}
+
+	let (stem, extension) = split_extension(name);
+	(1..)
+		.map(|n| {
+			let suffix = format!(" ({}){}", n, extension);
+			rules
+				.truncate(stem, room.min(MAX_NAME_LEN).saturating_sub(suffix.len()))
+				.map(|stem| format!("{}{}", stem, suffix))
+		})
+		.take(1000)
+		.flatten()
+		.find(|name| !siblings.contains(&rules.fold(name)))
+}
+
+// checks every entry of a copy or move against the naming rules of the target volume, before
+// anything is written
+pub async fn get_copy_preflight(
+	ctx: &LibraryContext,
+	file_path_ids: Vec<i32>,
+	target_location_id: i32,
+	target_path: String,
+) -> Result<CopyPreflight, FileError> {
+	let target_root = location_root(ctx, target_location_id).await?;
+	let rules = TargetRules::of(&target_root);
+
+	let mut preflight = CopyPreflight {
+		file_system: rules.file_system.clone(),
+		case_insensitive: rules.case_insensitive,
+		entry_count: 0,
+		issues: vec![],
+	};
+	for id in file_path_ids {
+		let file_path = ctx
+			.db
+			.file_path()
+			.find_unique(file_path::id::equals(id))
+			.exec()
+			.await?
+			.ok_or(FileError::FilePathNotFound(id))?;
+		let source_root = location_root(ctx, file_path.location_id.unwrap_or(0)).await?;
+
+		let plan = plan_entry(
+			&rules,
+			&source_root,
+			&file_path,
+			&target_root,
+			&target_path,
+			InvalidNamePolicy::Sanitize,
+			false,
+		)
+		.await?;
+		preflight.entry_count += plan.entry_count;
+		preflight.issues.extend(plan.issues);
+	}
+
+	Ok(preflight)
+}
+
+async fn location_root(ctx: &LibraryContext, location_id: i32) -> Result<PathBuf, FileError> {
+	get_location(ctx, location_id)
+		.await?
+		.path
+		.ok_or(FileError::LocationUnavailable(location_id))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+
+	fn windows_rules() -> TargetRules {
+		TargetRules {
+			file_system: Some("exfat".to_string()),
+			case_insensitive: true,
+			windows_names: true,
+			max_path_len: 4096,
+			capabilities: FileSystemCapabilities::default(),
+		}
+	}
+
+	fn posix_rules() -> TargetRules {
+		TargetRules {
+			file_system: Some("ext4".to_string()),
+			case_insensitive: false,
+			windows_names: false,
+			..windows_rules()
+		}
+	}
+
+	#[test]
+	fn reserves_device_names_with_any_extension() {
+		for name in [
+			"CON",
+			"con.txt",
+			"NUL .txt",
+			"Aux.tar.gz",
+			"COM1",
+			"lpt9.log",
+		] {
+			assert!(is_reserved(name), "{} is reserved", name);
+		}
+		for name in ["CONSOLE", "CON2", "COM0", "COM10", "prn_.txt", "photo.jpg"] {
+			assert!(!is_reserved(name), "{} isn't reserved", name);
+		}
+	}
+
+	#[test]
+	fn checks_names_against_the_rules_of_the_target() {
+		let windows = windows_rules();
+		assert_eq!(
+			windows.check_name("a:b.txt"),
+			vec![CopyIssueKind::IllegalCharacters]
+		);
+		assert_eq!(
+			windows.check_name("report."),
+			vec![CopyIssueKind::IllegalCharacters]
+		);
+		assert_eq!(windows.check_name("NUL"), vec![CopyIssueKind::ReservedName]);
+		assert_eq!(
+			windows.check_name(&"a".repeat(MAX_NAME_LEN + 1)),
+			vec![CopyIssueKind::NameTooLong]
+		);
+		assert!(windows.check_name("photo.jpg").is_empty());
+
+		let posix = posix_rules();
+		assert!(posix.check_name("a:b.txt").is_empty());
+		assert!(posix.check_name("NUL").is_empty());
+		// "é" is a single UTF-16 unit, but two bytes
+		assert!(windows.check_name(&"é".repeat(200)).is_empty());
+		assert_eq!(
+			posix.check_name(&"é".repeat(200)),
+			vec![CopyIssueKind::NameTooLong]
+		);
+	}
+
+	#[test]
+	fn folds_names_on_case_insensitive_targets() {
+		assert_eq!(windows_rules().fold("Photo.JPG"), "photo.jpg");
+		assert_eq!(posix_rules().fold("Photo.JPG"), "Photo.JPG");
+	}
+
+	#[test]
+	fn sanitizes_names_for_windows() {
+		let rules = windows_rules();
+		assert_eq!(
+			rules.sanitize("a<b>.txt", MAX_NAME_LEN).as_deref(),
+			Some("a_b_.txt")
+		);
+		assert_eq!(
+			rules.sanitize("report. ", MAX_NAME_LEN).as_deref(),
+			Some("report")
+		);
+		assert_eq!(rules.sanitize("...", MAX_NAME_LEN).as_deref(), Some("_"));
+		assert_eq!(
+			rules.sanitize("CON.txt", MAX_NAME_LEN).as_deref(),
+			Some("CON_.txt")
+		);
+		assert_eq!(rules.sanitize("AUX", MAX_NAME_LEN).as_deref(), Some("AUX_"));
+	}
+
+	#[test]
+	fn truncates_the_stem_and_keeps_the_extension() {
+		let rules = posix_rules();
+		assert_eq!(
+			rules.truncate("abcdefgh.txt", 8).as_deref(),
+			Some("abcd.txt")
+		);
+		assert_eq!(rules.truncate("abc.txt", 8).as_deref(), Some("abc.txt"));
+		// nothing of the stem would be left
+		assert_eq!(rules.truncate("a.verylongextension", 5), None);
+	}
+
+	#[test]
+	fn numbers_names_until_they_are_free() {
+		let rules = windows_rules();
+		let siblings = HashSet::from(["photo.jpg".to_string(), "photo (1).jpg".to_string()]);
+		assert_eq!(
+			free_name(&rules, &siblings, "Photo.JPG", MAX_NAME_LEN).as_deref(),
+			Some("Photo (2).JPG")
+		);
+		assert_eq!(
+			free_name(&rules, &siblings, "video.mp4", MAX_NAME_LEN).as_deref(),
+			Some("video.mp4")
+		);
+		// the stem is shortened for the number to fit
+		assert_eq!(
+			free_name(&rules, &siblings, "photo.jpg", 12).as_deref(),
+			Some("phot (1).jpg")
+		);
+	}
+
+	#[test]
+	fn rebases_paths_with_renamed_parents() {
+		let names = TargetNames(HashMap::from([
+			("src/a:b".to_string(), Some("a_b".to_string())),
+			("src/CON".to_string(), None),
+		]));
+		assert_eq!(
+			names
+				.rebase("src/a:b/notes.txt", "src", "backup/src")
+				.as_deref(),
+			Some("backup/src/a_b/notes.txt")
+		);
+		assert_eq!(names.rebase("src/CON/notes.txt", "src", "backup/src"), None);
+		assert_eq!(
+			names
+				.rebase("src/other/notes.txt", "src", "backup/src")
+				.as_deref(),
+			Some("backup/src/other/notes.txt")
+		);
+		assert!(names.changes_below("src"));
+		assert!(!names.changes_below("src/other"));
+		assert_eq!((names.renamed(), names.left_out()), (1, 1));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn plans_the_names_of_a_copy_to_windows() {
+		let source = tempfile::tempdir().unwrap();
+		let dir = source.path().join("src");
+		fs::create_dir(&dir).unwrap();
+		for name in ["CON.txt", "Photo.jpg", "a:b.txt", "photo.jpg", "video.mp4"] {
+			fs::write(dir.join(name), name).unwrap();
+		}
+
+		let plan = walk(
+			&windows_rules(),
+			source.path(),
+			(1, "src", "src".to_string()),
+			Path::new("/mnt/backup"),
+			"backup",
+			InvalidNamePolicy::Sanitize,
+		);
+		assert_eq!(plan.entry_count, 6);
+		let issues = plan
+			.issues
+			.iter()
+			.map(|issue| {
+				(
+					issue.source_path.as_str(),
+					issue.kinds.clone(),
+					issue.sanitized_path.as_deref(),
+				)
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(
+			issues,
+			vec![
+				(
+					"src/CON.txt",
+					vec![CopyIssueKind::ReservedName],
+					Some("backup/src/CON_.txt")
+				),
+				(
+					"src/a:b.txt",
+					vec![CopyIssueKind::IllegalCharacters],
+					Some("backup/src/a_b.txt")
+				),
+				(
+					"src/photo.jpg",
+					vec![CopyIssueKind::CaseCollision],
+					Some("backup/src/photo (1).jpg")
+				),
+			]
+		);
+		assert_eq!(plan.names.renamed(), 3);
+
+		let skipped = walk(
+			&windows_rules(),
+			source.path(),
+			(1, "src", "src".to_string()),
+			Path::new("/mnt/backup"),
+			"backup",
+			InvalidNamePolicy::Skip,
+		);
+		assert_eq!(skipped.names.left_out(), 3);
+		assert_eq!(
+			skipped
+				.names
+				.name("src/video.mp4", "video.mp4".to_string())
+				.as_deref(),
+			Some("video.mp4")
+		);
+	}
+}
@@ -0,0 +1,236 @@
+use crate::{
+	file::{FileError, FilePath},
+	library::LibraryContext,
+	prisma::{file, file_access, file_path, node},
+	ClientQuery, CoreEvent, LibraryQuery,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ts_rs::TS;
+
+// an access counts half as much after this many days
+const FRECENCY_HALF_LIFE_DAYS: f64 = 14.0;
+const PATHS_PER_QUERY: usize = 100;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, PartialEq, Eq)]
+#[ts(export)]
+pub enum FileAccessKind {
+	Open,
+	// eg: a quick look or the inspector, weighing less than opening the file
+	Preview,
+}
+
+impl FileAccessKind {
+	fn weight(&self) -> f64 {
+		match self {
+			Self::Open => 1.0,
+			Self::Preview => 0.5,
+		}
+	}
+}
+
+// A file for Quick Access, with its accesses summed over every node of the library
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct QuickAccessFile {
+	// one of the paths of the file, visible ones first
+	pub file_path: FilePath,
+	pub open_count: i32,
+	pub preview_count: i32,
+	pub last_accessed: DateTime<Utc>,
+	pub score: f64,
+}
+
+fn decay(score: f64, date_accessed: DateTime<Utc>, now: DateTime<Utc>) -> f64 {
+	let days = (now - date_accessed).num_seconds().max(0) as f64 / (24.0 * 60.0 * 60.0);
+	score * 0.5f64.powf(days / FRECENCY_HALF_LIFE_DAYS)
+}
+
+// record_access counts an open or a preview of a file on this node. Entries which aren't
+// identified yet aren't tracked, there is no file to count it against.
+pub async fn record_access(
+	ctx: &LibraryContext,
+	file_path_id: i32,
+	kind: FileAccessKind,
+) -> Result<(), FileError> {
+	let file_id = match ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.exec()
+		.await?
+		.ok_or(FileError::FilePathNotFound(file_path_id))?
+		.file_id
+	{
+		Some(file_id) => file_id,
+		None => return Ok(()),
+	};
+
+	let now = Utc::now();
+	let (opens, previews) = match kind {
+		FileAccessKind::Open => (1, 0),
+		FileAccessKind::Preview => (0, 1),
+	};
+
+	match ctx
+		.db
+		.file_access()
+		.find_unique(file_access::file_id_node_id(file_id, ctx.node_local_id))
+		.exec()
+		.await?
+	{
+		Some(access) => {
+			let score = decay(access.score, access.date_accessed.into(), now) + kind.weight();
+			ctx.db
+				.file_access()
+				.find_unique(file_access::id::equals(access.id))
+				.update(vec![
+					file_access::open_count::set(access.open_count + opens),
+					file_access::preview_count::set(access.preview_count + previews),
+					file_access::score::set(score),
+					file_access::date_accessed::set(now.into()),
+				])
+				.exec()
+				.await?;
+		}
+		None => {
+			ctx.db
+				.file_access()
+				.create(
+					file_access::file::link(file::id::equals(file_id)),
+					file_access::node::link(node::id::equals(ctx.node_local_id)),
+					vec![
+						file_access::open_count::set(opens),
+						file_access::preview_count::set(previews),
+						file_access::score::set(kind.weight()),
+						file_access::date_accessed::set(now.into()),
+					],
+				)
+				.exec()
+				.await?;
+		}
+	}
+
+	for query in [
+		LibraryQuery::GetRecentFiles { limit: 0 },
+		LibraryQuery::GetFrequentFiles { limit: 0 },
+	] {
+		ctx.emit(CoreEvent::InvalidateQueryDebounced(
+			ClientQuery::LibraryQuery {
+				library_id: ctx.id,
+				query,
+			},
+		))
+		.await;
+	}
+
+	Ok(())
+}
+
+struct Accesses {
+	file_id: i32,
+	open_count: i32,
+	preview_count: i32,
+	last_accessed: DateTime<Utc>,
+	score: f64,
+}
+
+// the accesses of every node summed per file, scores decayed to now
+async fn load_accesses(ctx: &LibraryContext) -> Result<Vec<Accesses>, FileError> {
+	let now = Utc::now();
+	let mut accesses = HashMap::<i32, Accesses>::new();
+
+	for row in ctx.db.file_access().find_many(vec![]).exec().await? {
+		let date_accessed = row.date_accessed.into();
+		let score = decay(row.score, date_accessed, now);
+		let file = accesses.entry(row.file_id).or_insert(Accesses {
+			file_id: row.file_id,
+			open_count: 0,
+			preview_count: 0,
+			last_accessed: date_accessed,
+			score: 0.0,
+		});
+		file.open_count += row.open_count;
+		file.preview_count += row.preview_count;
+		file.last_accessed = file.last_accessed.max(date_accessed);
+		file.score += score;
+	}
+
+	Ok(accesses.into_values().collect())
+}
+
+// files without any path left, eg: trashed, are skipped. paths are read a chunk of files at a
+// time, until there are enough of them
+async fn with_paths(
+	ctx: &LibraryContext,
+	accesses: Vec<Accesses>,
+	limit: u32,
+) -> Result<Vec<QuickAccessFile>, FileError> {
+	let mut files = Vec::new();
+	for chunk in accesses.chunks(PATHS_PER_QUERY) {
+		let file_ids = chunk.iter().map(|access| access.file_id).collect();
+		let mut paths = HashMap::<i32, file_path::Data>::new();
+		for file_path in ctx
+			.db
+			.file_path()
+			.find_many(vec![file_path::file_id::in_vec(file_ids)])
+			.with(file_path::file::fetch().with(file::media_data::fetch()))
+			.exec()
+			.await?
+		{
+			let file_id = match file_path.file_id {
+				Some(file_id) => file_id,
+				None => continue,
+			};
+			match paths.get(&file_id) {
+				Some(existing) if !existing.hidden || file_path.hidden => {}
+				_ => {
+					paths.insert(file_id, file_path);
+				}
+			}
+		}
+
+		for access in chunk {
+			if files.len() == limit as usize {
+				return Ok(files);
+			}
+			if let Some(file_path) = paths.remove(&access.file_id) {
+				files.push(QuickAccessFile {
+					file_path: file_path.into(),
+					open_count: access.open_count,
+					preview_count: access.preview_count,
+					last_accessed: access.last_accessed,
+					score: access.score,
+				});
+			}
+		}
+	}
+
+	Ok(files)
+}
+
+// get_recent_files returns the files accessed last on any node, the most recent first
+pub async fn get_recent_files(
+	ctx: &LibraryContext,
+	limit: u32,
+) -> Result<Vec<QuickAccessFile>, FileError> {
+	let mut accesses = load_accesses(ctx).await?;
+	accesses.sort_by(|a, b| b.last_accessed.cmp(&a.last_accessed));
+	with_paths(ctx, accesses, limit).await
+}
+
+// get_frequent_files returns the files with the highest frecency, counting how often they were
+// accessed with the recent accesses weighing more
+pub async fn get_frequent_files(
+	ctx: &LibraryContext,
+	limit: u32,
+) -> Result<Vec<QuickAccessFile>, FileError> {
+	let mut accesses = load_accesses(ctx).await?;
+	accesses.sort_by(|a, b| {
+		b.score
+			.partial_cmp(&a.score)
+			.unwrap_or(std::cmp::Ordering::Equal)
+	});
+	with_paths(ctx, accesses, limit).await
+}
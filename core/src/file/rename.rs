@@ -0,0 +1,423 @@
+use crate::{
+	file::{
+		copy::{entry_name, full_name, target_name},
+		FileError,
+	},
+	history::{self, Operation},
+	job::{JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::LibraryContext,
+	prisma::{file, file_path},
+	sys::get_location,
+	CoreError, CoreResponse, Job,
+};
+use chrono::{
+	format::{Item, StrftimeItems},
+	DateTime, Utc,
+};
+use log::{info, warn};
+use prisma_client_rust::{prisma_models::PrismaValue, raw::Raw};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, HashSet},
+	fs::Metadata,
+};
+use tokio::fs;
+use ts_rs::TS;
+
+pub const BATCH_RENAME_JOB_NAME: &str = "batch_renamer";
+
+// A pattern the new names of a batch rename are made from, the extension of files is kept.
+// Tokens are replaced in the pattern:
+// - `{name}` the current name, `{ext}` the extension
+// - `{n}` a counter in the order of the selection, `{n:3}` pads it to 3 digits
+// - `{date}` the date the file was created, `{date:%Y%m%d}` with a strftime format
+// - `{exif:make}`, `{exif:model}`, `{exif:software}`, `{exif:width}`, `{exif:height}`
+// - `{1}`, `{2}`... the capture groups of `regex` matched against the current name
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RenamePattern {
+	pub pattern: String,
+	pub regex: Option<String>,
+	pub counter_start: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RenamedPath {
+	pub file_path_id: i32,
+	// names on disk, with the extension
+	pub from: String,
+	pub to: String,
+}
+
+// What a batch rename would do, it is refused as long as there are collisions
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BatchRenamePreview {
+	// entries whose name wouldn't change are left out
+	pub renames: Vec<RenamedPath>,
+	// entries whose new name is taken, given to another entry of the batch, or isn't a valid name
+	pub collisions: Vec<i32>,
+}
+
+pub async fn get_batch_rename_preview(
+	ctx: &LibraryContext,
+	ids: Vec<i32>,
+	pattern: &RenamePattern,
+) -> Result<BatchRenamePreview, FileError> {
+	let regex = pattern
+		.regex
+		.as_deref()
+		.map(Regex::new)
+		.transpose()
+		.map_err(|e| FileError::InvalidRenamePattern(e.to_string()))?;
+
+	let mut rows = ctx
+		.db
+		.file_path()
+		.find_many(vec![file_path::id::in_vec(ids.clone())])
+		.with(file_path::file::fetch().with(file::media_data::fetch()))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|row| (row.id, row))
+		.collect::<HashMap<_, _>>();
+
+	let mut renames = vec![];
+	let mut counter = pattern.counter_start.unwrap_or(1);
+	for id in ids {
+		let row = rows.remove(&id).ok_or(FileError::FilePathNotFound(id))?;
+		let name = render(pattern, regex.as_ref(), &row, counter)?;
+		counter += 1;
+
+		let to = entry_name(&name, row.extension.as_deref(), row.is_dir);
+		let from = full_name(&row);
+		if to != from {
+			renames.push((
+				row,
+				RenamedPath {
+					file_path_id: id,
+					from,
+					to,
+				},
+			));
+		}
+	}
+
+	let mut collisions = vec![];
+	let mut taken = HashSet::new();
+	for (row, renamed) in &renames {
+		let location_id = row.location_id.unwrap_or(0);
+		let path = sibling_path(&row.materialized_path, &renamed.to);
+
+		let valid = !renamed.to.is_empty()
+			&& renamed.to != "."
+			&& renamed.to != ".."
+			&& !renamed.to.contains(['/', '\\']);
+		if !valid
+			|| !taken.insert((location_id, path.clone()))
+			|| is_taken(ctx, location_id, &row.materialized_path, &path).await?
+		{
+			collisions.push(renamed.file_path_id);
+		}
+	}
+
+	Ok(BatchRenamePreview {
+		renames: renames.into_iter().map(|(_, renamed)| renamed).collect(),
+		collisions,
+	})
+}
+
+pub async fn batch_rename(
+	ctx: LibraryContext,
+	ids: Vec<i32>,
+	pattern: RenamePattern,
+) -> Result<CoreResponse, CoreError> {
+	let preview = get_batch_rename_preview(&ctx, ids, &pattern).await?;
+	if !preview.collisions.is_empty() {
+		return Err(FileError::RenameCollision(preview.collisions).into());
+	}
+
+	ctx.spawn_job(Job::new(
+		BatchRenameJobInit {
+			renames: preview.renames,
+		},
+		Box::new(BatchRenameJob {}),
+	))
+	.await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+// renames every entry, or none of them if any changed since the renames were planned
+pub(crate) async fn rename_all(
+	ctx: &LibraryContext,
+	renames: &[RenamedPath],
+) -> Result<(), FileError> {
+	for renamed in renames {
+		check(ctx, renamed).await?;
+	}
+	for renamed in renames {
+		rename(ctx, renamed).await?;
+	}
+
+	Ok(())
+}
+
+// returns an error unless the entry still has its old name and nothing has the new one
+async fn check(ctx: &LibraryContext, renamed: &RenamedPath) -> Result<file_path::Data, FileError> {
+	let row = ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(renamed.file_path_id))
+		.exec()
+		.await?
+		.ok_or(FileError::FilePathNotFound(renamed.file_path_id))?;
+
+	let path = sibling_path(&row.materialized_path, &renamed.to);
+	if full_name(&row) != renamed.from
+		|| is_taken(
+			ctx,
+			row.location_id.unwrap_or(0),
+			&row.materialized_path,
+			&path,
+		)
+		.await?
+	{
+		return Err(FileError::RenameCollision(vec![renamed.file_path_id]));
+	}
+
+	Ok(row)
+}
+
+async fn rename(ctx: &LibraryContext, renamed: &RenamedPath) -> Result<(), FileError> {
+	let row = check(ctx, renamed).await?;
+	let location_id = row.location_id.unwrap_or(0);
+	let location_path = get_location(ctx, location_id)
+		.await?
+		.path
+		.ok_or(FileError::LocationUnavailable(location_id))?;
+
+	let old_path = row.materialized_path.clone();
+	let new_path = sibling_path(&old_path, &renamed.to);
+	fs::rename(location_path.join(&old_path), location_path.join(&new_path)).await?;
+
+	// a single statement renames the entry and moves what's below it, so it is applied entirely or not at all
+	ctx.db
+		._execute_raw(Raw::new(
+			"UPDATE file_paths SET
+				materialized_path = {} || substr(materialized_path, {}),
+				name = CASE WHEN id = {} THEN {} ELSE name END
+			WHERE location_id = {} AND (id = {} OR substr(materialized_path, 1, {}) = {})",
+			vec![
+				PrismaValue::String(new_path.clone()),
+				PrismaValue::Int(old_path.chars().count() as i64 + 1),
+				PrismaValue::Int(row.id as i64),
+				PrismaValue::String(target_name(&row, &new_path)),
+				PrismaValue::Int(location_id as i64),
+				PrismaValue::Int(row.id as i64),
+				PrismaValue::Int(old_path.chars().count() as i64 + 1),
+				PrismaValue::String(format!("{}/", old_path)),
+			],
+		))
+		.await?;
+
+	info!("Renamed {:?} to {:?}", old_path, new_path);
+
+	Ok(())
+}
+
+// whether the new path of an entry is taken by another one. Volumes which don't tell apart names
+// in another case or normalization form find the entry itself at its new path, which is free then
+pub(crate) async fn is_taken(
+	ctx: &LibraryContext,
+	location_id: i32,
+	old_path: &str,
+	new_path: &str,
+) -> Result<bool, FileError> {
+	let indexed = ctx
+		.db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::equals(new_path.to_string()),
+		])
+		.exec()
+		.await?
+		.is_some();
+
+	// entries which aren't indexed yet are taken into account too
+	let on_disk = match get_location(ctx, location_id).await?.path {
+		Some(location_path) => match fs::symlink_metadata(location_path.join(new_path)).await {
+			Ok(found) => match fs::symlink_metadata(location_path.join(old_path)).await {
+				Ok(entry) => !same_entry(&entry, &found),
+				Err(_) => true,
+			},
+			Err(_) => false,
+		},
+		None => false,
+	};
+
+	Ok(indexed || on_disk)
+}
+
+#[cfg(unix)]
+fn same_entry(a: &Metadata, b: &Metadata) -> bool {
+	use std::os::unix::fs::MetadataExt;
+	a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+// without inodes to compare, a name found on disk is always another entry
+#[cfg(not(unix))]
+fn same_entry(_: &Metadata, _: &Metadata) -> bool {
+	false
+}
+
+fn render(
+	pattern: &RenamePattern,
+	regex: Option<&Regex>,
+	row: &file_path::Data,
+	counter: i32,
+) -> Result<String, FileError> {
+	let invalid = || FileError::InvalidRenamePattern(pattern.pattern.clone());
+	let media_data = row
+		.file
+		.clone()
+		.flatten()
+		.and_then(|file| file.media_data.flatten());
+	let captures = regex.and_then(|regex| regex.captures(&row.name));
+
+	let mut name = String::new();
+	let mut rest = pattern.pattern.as_str();
+	while let Some(start) = rest.find('{') {
+		name.push_str(&rest[..start]);
+		let end = start + rest[start..].find('}').ok_or_else(invalid)?;
+		let (key, arg) = match rest[start + 1..end].split_once(':') {
+			Some((key, arg)) => (key, Some(arg)),
+			None => (&rest[start + 1..end], None),
+		};
+
+		let value = match (key, arg) {
+			("name", None) => row.name.clone(),
+			("ext", None) => row.extension.clone().unwrap_or_default(),
+			("n", width) => {
+				let width = width
+					.map(str::parse::<usize>)
+					.transpose()
+					.map_err(|_| invalid())?;
+				format!("{:0width$}", counter, width = width.unwrap_or(1))
+			}
+			("date", format) => {
+				let format = format.unwrap_or("%Y-%m-%d");
+				if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+					return Err(invalid());
+				}
+				DateTime::<Utc>::from(row.date_created)
+					.format(format)
+					.to_string()
+			}
+			("exif", Some(field)) => {
+				let media_data = media_data.as_ref();
+				match field {
+					"make" => media_data.and_then(|data| data.capture_device_make.clone()),
+					"model" => media_data.and_then(|data| data.capture_device_model.clone()),
+					"software" => media_data.and_then(|data| data.capture_device_software.clone()),
+					"width" => media_data.and_then(|data| data.pixel_width.map(|w| w.to_string())),
+					"height" => {
+						media_data.and_then(|data| data.pixel_height.map(|h| h.to_string()))
+					}
+					_ => return Err(invalid()),
+				}
+				.unwrap_or_default()
+			}
+			(group, None) if group.parse::<usize>().is_ok() => captures
+				.as_ref()
+				.and_then(|captures| captures.get(group.parse().unwrap_or_default()))
+				.map(|capture| capture.as_str().to_string())
+				.unwrap_or_default(),
+			_ => return Err(invalid()),
+		};
+		name.push_str(&value);
+		rest = &rest[end + 1..];
+	}
+	name.push_str(rest);
+
+	Ok(name)
+}
+
+pub(crate) fn sibling_path(materialized_path: &str, name: &str) -> String {
+	match materialized_path.rsplit_once('/') {
+		Some((parent, _)) => format!("{}/{}", parent, name),
+		None => name.to_string(),
+	}
+}
+
+pub struct BatchRenameJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BatchRenameJobInit {
+	pub renames: Vec<RenamedPath>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for BatchRenameJob {
+	type Init = BatchRenameJobInit;
+	// the renames applied, recorded in the history once the job completes
+	type Data = Vec<RenamedPath>;
+	type Step = RenamedPath;
+
+	fn name(&self) -> &'static str {
+		BATCH_RENAME_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		info!("Renaming {} entries", state.init.renames.len());
+
+		state.steps = state.init.renames.iter().cloned().collect();
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+		state.data = Some(vec![]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let renamed = &state.steps[0];
+		// entries changed since the preview are left as they are
+		match rename(&ctx.library_ctx(), renamed).await {
+			Ok(()) => state
+				.data
+				.as_mut()
+				.expect("critical error: missing data on job state")
+				.push(renamed.clone()),
+			Err(e) => warn!("Not renaming {:?}: {}", renamed.from, e),
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let renames = state.data.take().unwrap_or_default();
+		if !renames.is_empty() {
+			history::record(&ctx.library_ctx(), Operation::Rename { renames }).await;
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,674 @@
+use crate::{
+	encode::{thumbstrip_path, PREVIEW_CACHE_DIR_NAME, THUMBNAIL_CACHE_DIR_NAME},
+	file::{
+		copy::full_name,
+		send_invalidate_query,
+		sizes::{mark_folder_sizes_stale, FolderSizesJob, FolderSizesJobInit},
+		versions::{block_path, blocks_dir},
+		FileError,
+	},
+	job::{JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::LibraryContext,
+	prisma::{file, file_path, file_snapshot},
+	search,
+	sys::{get_location, Volume},
+	Job,
+};
+use log::{error, info, warn};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, HashSet},
+	fs::{self, OpenOptions},
+	io::{self, Seek, SeekFrom, Write},
+	path::{Path, PathBuf},
+};
+use tokio::task::spawn_blocking;
+use ts_rs::TS;
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+pub const SECURE_DELETE_JOB_NAME: &str = "secure_delete";
+// a single pass of random data is all modern drives need, more only take longer
+pub const DEFAULT_SHRED_PASSES: u8 = 1;
+pub const MAX_SHRED_PASSES: u8 = 7;
+const OVERWRITE_BUFFER_SIZE: usize = 1024 * 1024;
+// files next to an image holding its edits or metadata, eg: "photo.jpg.xmp" or "photo.xmp"
+const SIDECAR_EXTENSIONS: [&str; 2] = ["xmp", "aae"];
+const COPY_ON_WRITE_FILE_SYSTEMS: [&str; 5] = ["btrfs", "zfs", "apfs", "refs", "bcachefs"];
+
+// Why the overwritten contents of a file may still be recovered from the volume it is on
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum ShredCaveat {
+	// wear leveling writes over flash memory elsewhere than where the contents were, they stay
+	// until the drive erases those blocks on its own
+	SolidState,
+	// the new data is written to new blocks, the old ones are left as they were until reused and
+	// are kept by the snapshots of the volume
+	CopyOnWrite,
+	// the contents are overwritten by the server, which may keep snapshots or backups of its own
+	NetworkShare,
+	// the volume of the location wasn't found, nothing is known of how it stores data
+	UnknownVolume,
+}
+
+// SecureDeletePreview is what a secure delete would remove for good, for the client to confirm
+// with before running it
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SecureDeletePreview {
+	// the selected entries
+	pub paths: Vec<String>,
+	// with what is below the selected directories and the sidecars
+	pub file_count: usize,
+	pub directory_count: usize,
+	pub total_bytes: u64,
+	// the sidecars of the selected files, shredded with them
+	pub sidecars: Vec<String>,
+	// versions of the files kept by the library, shredded too
+	pub version_count: usize,
+	// files with copies elsewhere in the library, which are kept
+	pub files_with_copies: usize,
+	pub passes: u8,
+	pub caveats: Vec<ShredCaveat>,
+}
+
+// the entries a secure delete removes, deepest first
+struct Selection {
+	entries: Vec<file_path::Data>,
+	sidecars: Vec<String>,
+}
+
+// the selected entries, everything below the selected directories and the sidecars of the
+// selected files. Fails if any of their files is under legal hold.
+async fn select(ctx: &LibraryContext, file_path_ids: &[i32]) -> Result<Selection, FileError> {
+	let mut entries = HashMap::new();
+	let mut sidecars = vec![];
+
+	for id in file_path_ids {
+		let file_path = ctx
+			.db
+			.file_path()
+			.find_unique(file_path::id::equals(*id))
+			.with(file_path::file::fetch())
+			.exec()
+			.await?
+			.ok_or(FileError::FilePathNotFound(*id))?;
+
+		if file_path.is_dir {
+			let below = ctx
+				.db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(file_path.location_id),
+					file_path::materialized_path::starts_with(format!(
+						"{}/",
+						file_path.materialized_path
+					)),
+				])
+				.with(file_path::file::fetch())
+				.exec()
+				.await?;
+			entries.extend(below.into_iter().map(|entry| (entry.id, entry)));
+		} else {
+			let siblings = ctx
+				.db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(file_path.location_id),
+					file_path::parent_id::equals(file_path.parent_id),
+					file_path::name::in_vec(vec![file_path.name.clone(), full_name(&file_path)]),
+				])
+				.with(file_path::file::fetch())
+				.exec()
+				.await?;
+			for sibling in siblings {
+				let is_sidecar = sibling.id != file_path.id
+					&& !sibling.is_dir
+					&& sibling.extension.as_ref().map_or(false, |extension| {
+						SIDECAR_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+					});
+				if is_sidecar && !entries.contains_key(&sibling.id) {
+					sidecars.push(sibling.materialized_path.clone());
+					entries.insert(sibling.id, sibling);
+				}
+			}
+		}
+
+		entries.insert(file_path.id, file_path);
+	}
+
+	for entry in entries.values() {
+		if let Some(Some(file)) = &entry.file {
+			if file.legal_hold {
+				warn!("Blocked an action on file {} under legal hold", file.id);
+				return Err(FileError::LegalHold(file.id));
+			}
+		}
+	}
+
+	let mut entries = entries.into_values().collect::<Vec<_>>();
+	entries.sort_by_key(|entry| std::cmp::Reverse(entry.materialized_path.matches('/').count()));
+
+	Ok(Selection { entries, sidecars })
+}
+
+async fn location_paths(
+	ctx: &LibraryContext,
+	entries: &[file_path::Data],
+) -> Result<HashMap<i32, PathBuf>, FileError> {
+	let mut paths = HashMap::new();
+	for entry in entries {
+		let location_id = entry.location_id.unwrap_or(0);
+		if !paths.contains_key(&location_id) {
+			let path = get_location(ctx, location_id)
+				.await?
+				.path
+				.ok_or(FileError::LocationUnavailable(location_id))?;
+			paths.insert(location_id, path);
+		}
+	}
+	Ok(paths)
+}
+
+// the caveats of the volumes holding the locations, from the volume mounted closest to each
+fn caveats<'a>(location_paths: impl Iterator<Item = &'a PathBuf>) -> Vec<ShredCaveat> {
+	let volumes = Volume::get_volumes().unwrap_or_default();
+
+	let mut caveats = vec![];
+	for path in location_paths {
+		let volume = volumes
+			.iter()
+			.filter(|volume| path.starts_with(&volume.mount_point))
+			.max_by_key(|volume| volume.mount_point.len());
+
+		let found = match volume {
+			Some(volume) => {
+				let mut found = vec![];
+				if volume.disk_type.as_deref() == Some("SSD") {
+					found.push(ShredCaveat::SolidState);
+				}
+				if volume.file_system.as_ref().map_or(false, |file_system| {
+					COPY_ON_WRITE_FILE_SYSTEMS.contains(&file_system.to_lowercase().as_str())
+				}) {
+					found.push(ShredCaveat::CopyOnWrite);
+				}
+				if volume.network_protocol.is_some() {
+					found.push(ShredCaveat::NetworkShare);
+				}
+				found
+			}
+			None => vec![ShredCaveat::UnknownVolume],
+		};
+		for caveat in found {
+			if !caveats.contains(&caveat) {
+				caveats.push(caveat);
+			}
+		}
+	}
+
+	caveats
+}
+
+pub async fn get_secure_delete_preview(
+	ctx: &LibraryContext,
+	file_path_ids: Vec<i32>,
+	passes: Option<u8>,
+) -> Result<SecureDeletePreview, FileError> {
+	let Selection { entries, sidecars } = select(ctx, &file_path_ids).await?;
+	let location_paths = location_paths(ctx, &entries).await?;
+	let ids = entries.iter().map(|entry| entry.id).collect::<HashSet<_>>();
+
+	let files = entries
+		.iter()
+		.filter_map(|entry| entry.file.clone().flatten())
+		.map(|file| (file.id, file))
+		.collect::<HashMap<_, _>>();
+	let copies = ctx
+		.db
+		.file_path()
+		.find_many(vec![file_path::file_id::in_vec(
+			files.keys().copied().collect(),
+		)])
+		.exec()
+		.await?
+		.into_iter()
+		.filter(|copy| !ids.contains(&copy.id))
+		.filter_map(|copy| copy.file_id)
+		.collect::<HashSet<_>>();
+
+	let version_count = ctx
+		.db
+		.file_snapshot()
+		.find_many(vec![file_snapshot::file_path_id::in_vec(
+			ids.iter().copied().collect(),
+		)])
+		.exec()
+		.await?
+		.len();
+
+	Ok(SecureDeletePreview {
+		paths: entries
+			.iter()
+			.filter(|entry| file_path_ids.contains(&entry.id))
+			.map(|entry| entry.materialized_path.clone())
+			.collect(),
+		file_count: entries.iter().filter(|entry| !entry.is_dir).count(),
+		directory_count: entries.iter().filter(|entry| entry.is_dir).count(),
+		total_bytes: files
+			.values()
+			.map(|file| file.size_in_bytes.parse::<u64>().unwrap_or(0))
+			.sum(),
+		sidecars,
+		version_count,
+		files_with_copies: copies.len(),
+		passes: clamp_passes(passes),
+		caveats: caveats(location_paths.values()),
+	})
+}
+
+// checks the entries can be deleted before starting the job, which fails on the first one held
+pub async fn secure_delete(
+	ctx: &LibraryContext,
+	file_path_ids: Vec<i32>,
+	passes: Option<u8>,
+) -> Result<(), FileError> {
+	select(ctx, &file_path_ids).await?;
+
+	ctx.spawn_job(Job::new(
+		SecureDeleteJobInit {
+			file_path_ids,
+			passes: clamp_passes(passes),
+		},
+		Box::new(SecureDeleteJob {}),
+	))
+	.await;
+
+	Ok(())
+}
+
+fn clamp_passes(passes: Option<u8>) -> u8 {
+	passes
+		.unwrap_or(DEFAULT_SHRED_PASSES)
+		.clamp(1, MAX_SHRED_PASSES)
+}
+
+// shred overwrites the contents of a file with random data `passes` times, syncing every pass to
+// the disk, before emptying it, renaming it to a random name and removing it. A link is removed
+// without touching what it points to. Returns how many bytes were written.
+fn shred(path: &Path, passes: u8) -> io::Result<u64> {
+	let metadata = fs::symlink_metadata(path)?;
+	if !metadata.is_file() {
+		fs::remove_file(path)?;
+		return Ok(0);
+	}
+
+	let mut permissions = metadata.permissions();
+	if permissions.readonly() {
+		permissions.set_readonly(false);
+		fs::set_permissions(path, permissions)?;
+	}
+
+	let len = metadata.len();
+	let mut file = OpenOptions::new().write(true).open(path)?;
+	let random = SystemRandom::new();
+	let mut buf = vec![0; OVERWRITE_BUFFER_SIZE];
+	for _ in 0..passes {
+		file.seek(SeekFrom::Start(0))?;
+		let mut left = len;
+		while left > 0 {
+			let chunk = &mut buf[..left.min(OVERWRITE_BUFFER_SIZE as u64) as usize];
+			random.fill(chunk).map_err(|_| {
+				io::Error::new(
+					io::ErrorKind::Other,
+					"the system random number generator failed",
+				)
+			})?;
+			file.write_all(chunk)?;
+			left -= chunk.len() as u64;
+		}
+		file.sync_all()?;
+	}
+	file.set_len(0)?;
+	file.sync_all()?;
+	drop(file);
+
+	// so the name doesn't stay in the directory either, as far as the file system allows
+	let renamed = path.with_file_name(Uuid::new_v4().to_string());
+	fs::rename(path, &renamed)?;
+	fs::remove_file(renamed)?;
+
+	Ok(len * passes as u64)
+}
+
+// shreds what is left in a directory, the entries the library didn't index, without following
+// links out of it, then removes it
+fn shred_dir(path: &Path, passes: u8) -> io::Result<u64> {
+	let mut written = 0;
+	for entry in WalkDir::new(path).contents_first(true) {
+		let entry = entry?;
+		if entry.file_type().is_dir() {
+			fs::remove_dir(entry.path())?;
+		} else {
+			written += shred(entry.path(), passes)?;
+		}
+	}
+	Ok(written)
+}
+
+// already gone from disk counts as shredded
+fn ignore_not_found(result: io::Result<u64>) -> io::Result<u64> {
+	result.or_else(|e| match e.kind() {
+		io::ErrorKind::NotFound => Ok(0),
+		_ => Err(e),
+	})
+}
+
+pub struct SecureDeleteJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SecureDeleteJobInit {
+	pub file_path_ids: Vec<i32>,
+	pub passes: u8,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SecureDeleteJobData {
+	location_paths: HashMap<i32, PathBuf>,
+	bytes_written: u64,
+	failed: usize,
+	// the blocks of the versions removed, by location, shredded once no other version uses them
+	version_blocks: HashMap<i32, HashSet<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SecureDeleteJobStep {
+	file_path_id: i32,
+	location_id: i32,
+	materialized_path: String,
+	is_dir: bool,
+	file: Option<(i32, String)>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for SecureDeleteJob {
+	type Init = SecureDeleteJobInit;
+	type Data = SecureDeleteJobData;
+	type Step = SecureDeleteJobStep;
+
+	fn name(&self) -> &'static str {
+		SECURE_DELETE_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let Selection { entries, .. } = select(&library_ctx, &state.init.file_path_ids).await?;
+
+		mark_folder_sizes_stale(
+			&library_ctx,
+			entries.iter().filter_map(|entry| entry.parent_id).collect(),
+		)
+		.await?;
+
+		state.data = Some(SecureDeleteJobData {
+			location_paths: location_paths(&library_ctx, &entries).await?,
+			..Default::default()
+		});
+		state.steps = entries
+			.into_iter()
+			.map(|entry| SecureDeleteJobStep {
+				file_path_id: entry.id,
+				location_id: entry.location_id.unwrap_or(0),
+				is_dir: entry.is_dir,
+				file: entry.file.flatten().map(|file| (file.id, file.cas_id)),
+				materialized_path: entry.materialized_path,
+			})
+			.collect();
+
+		info!(
+			"Securely deleting {} entries with {} passes",
+			state.steps.len(),
+			state.init.passes
+		);
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let step = &state.steps[0];
+		let data = state.data.as_mut().unwrap();
+		let passes = state.init.passes;
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Shredding {}",
+			step.materialized_path
+		))]);
+
+		let path = data.location_paths[&step.location_id].join(&step.materialized_path);
+		let is_dir = step.is_dir;
+		let shredded = spawn_blocking(move || {
+			ignore_not_found(match is_dir {
+				true => shred_dir(&path, passes),
+				false => shred(&path, passes),
+			})
+		})
+		.await?;
+
+		match shredded {
+			Ok(written) => {
+				data.bytes_written += written;
+				if let Err(e) = forget(&library_ctx, step, passes, data).await {
+					error!(
+						"Failed to remove {} from the library: {:#?}",
+						step.materialized_path, e
+					);
+				}
+			}
+			// the entry stays in the library, as it is still on disk
+			Err(e) => {
+				error!("Failed to shred {}: {:#?}", step.materialized_path, e);
+				data.failed += 1;
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let data = state.data.take().unwrap_or_default();
+
+		for (location_id, hashes) in data.version_blocks {
+			if let Err(e) =
+				shred_version_blocks(&library_ctx, location_id, hashes, state.init.passes).await
+			{
+				error!(
+					"Failed to shred the versions of location {}: {:#?}",
+					location_id, e
+				);
+			}
+		}
+
+		library_ctx
+			.queue_job(Job::new(
+				FolderSizesJobInit::default(),
+				Box::new(FolderSizesJob {}),
+			))
+			.await;
+		send_invalidate_query(&library_ctx).await;
+		search::refresh_subscriptions(&library_ctx).await;
+
+		info!(
+			"Securely deleted entries with {} bytes overwritten, {} failed",
+			data.bytes_written, data.failed
+		);
+		if data.failed > 0 {
+			ctx.progress(vec![JobReportUpdate::Message(format!(
+				"{} entries couldn't be shredded and were kept",
+				data.failed
+			))]);
+		}
+
+		Ok(())
+	}
+}
+
+// removes a shredded entry from the library with its versions, and once no other path has its
+// file the thumbnails and previews of the file and the file itself
+async fn forget(
+	ctx: &LibraryContext,
+	step: &SecureDeleteJobStep,
+	passes: u8,
+	data: &mut SecureDeleteJobData,
+) -> Result<(), FileError> {
+	let snapshots = ctx
+		.db
+		.file_snapshot()
+		.find_many(vec![file_snapshot::file_path_id::equals(step.file_path_id)])
+		.exec()
+		.await?;
+	if !snapshots.is_empty() {
+		let blocks = data.version_blocks.entry(step.location_id).or_default();
+		for snapshot in &snapshots {
+			blocks.extend(
+				snapshot
+					.chunks
+					.split(',')
+					.filter(|hash| !hash.is_empty())
+					.map(String::from),
+			);
+		}
+		ctx.db
+			.file_snapshot()
+			.find_many(vec![file_snapshot::file_path_id::equals(step.file_path_id)])
+			.delete()
+			.exec()
+			.await?;
+	}
+
+	// entries below a directory left behind by a failed step are gone with it now
+	if step.is_dir {
+		ctx.db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(step.location_id)),
+				file_path::materialized_path::starts_with(format!("{}/", step.materialized_path)),
+			])
+			.delete()
+			.exec()
+			.await?;
+	}
+	ctx.db
+		.file_path()
+		.find_unique(file_path::id::equals(step.file_path_id))
+		.delete()
+		.exec()
+		.await?;
+
+	let (file_id, cas_id) = match &step.file {
+		Some(file) => file.clone(),
+		None => return Ok(()),
+	};
+	let paths = ctx
+		.db
+		.file_path()
+		.find_many(vec![file_path::file_id::equals(Some(file_id))])
+		.exec()
+		.await?;
+
+	let data_dir = ctx.config().data_directory();
+	let mut derived = vec![];
+	// thumbnails are generated per location
+	if !paths
+		.iter()
+		.any(|path| path.location_id == Some(step.location_id))
+	{
+		let thumbnail_dir = data_dir
+			.join(THUMBNAIL_CACHE_DIR_NAME)
+			.join(step.location_id.to_string());
+		derived.push(thumbnail_dir.join(&cas_id).with_extension("webp"));
+		derived.push(thumbstrip_path(&thumbnail_dir, &cas_id));
+		for profile in &ctx.config.thumbnail_profiles {
+			derived.push(profile.path(&thumbnail_dir, &cas_id));
+		}
+	}
+	if paths.is_empty() {
+		derived.push(
+			data_dir
+				.join(PREVIEW_CACHE_DIR_NAME)
+				.join(&cas_id)
+				.with_extension("mp4"),
+		);
+	}
+	spawn_blocking(move || {
+		for path in derived {
+			if let Err(e) = ignore_not_found(shred(&path, passes)) {
+				error!("Failed to shred {:?}: {:#?}", path, e);
+			}
+		}
+	})
+	.await
+	.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+	if paths.is_empty() {
+		ctx.db
+			.file()
+			.find_unique(file::id::equals(file_id))
+			.delete()
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
+
+// shreds the blocks of removed versions which no version left in the location uses
+async fn shred_version_blocks(
+	ctx: &LibraryContext,
+	location_id: i32,
+	mut hashes: HashSet<String>,
+	passes: u8,
+) -> Result<(), FileError> {
+	for snapshot in ctx
+		.db
+		.file_snapshot()
+		.find_many(vec![file_snapshot::location_id::equals(location_id)])
+		.exec()
+		.await?
+	{
+		for hash in snapshot.chunks.split(',') {
+			hashes.remove(hash);
+		}
+	}
+
+	let blocks_dir = blocks_dir(ctx, location_id);
+	spawn_blocking(move || {
+		for hash in hashes {
+			ignore_not_found(shred(&block_path(&blocks_dir, &hash), passes))?;
+		}
+		Ok::<_, io::Error>(())
+	})
+	.await
+	.map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+	Ok(())
+}
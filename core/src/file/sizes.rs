@@ -0,0 +1,301 @@
+use crate::{
+	file::storage::{StorageBreakdownJob, StorageBreakdownJobInit},
+	job::{Job, JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::LibraryContext,
+	prisma::{self, location},
+};
+use log::info;
+use prisma_client_rust::{prisma_models::PrismaValue, raw, raw::Raw};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+pub const FOLDER_SIZES_JOB_NAME: &str = "compute_folder_sizes";
+// sqlite limits the amount of bound variables per statement, 3 per directory
+static SIZE_UPDATE_BATCH_SIZE: usize = 200;
+
+pub struct FolderSizesJob {}
+
+// FolderSizesJobInit sums the sizes of the files below every directory of the locations with
+// stale folder sizes, from the database rather than the disk. Only the sizes which changed are
+// written.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct FolderSizesJobInit {
+	// summed even without stale directories, after a scan changed the sizes of its files
+	pub location_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FolderSizesJobStep {
+	location_id: i32,
+}
+
+#[derive(Deserialize)]
+struct SizedEntry {
+	id: i32,
+	parent_id: Option<i32>,
+	is_dir: i32,
+	// of the file for files, the cached folder size for directories
+	size: Option<String>,
+	// the contents of files, shared by their hardlinks
+	inode: Option<i64>,
+	device: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct StaleLocation {
+	location_id: i32,
+}
+
+// marks the directories and every directory above them stale, and starts the job computing
+// their sizes again. To be called with the parents of the entries added or removed.
+pub(crate) async fn invalidate_folder_sizes(
+	ctx: &LibraryContext,
+	directory_ids: Vec<i32>,
+) -> Result<(), prisma::QueryError> {
+	if directory_ids.is_empty() {
+		return Ok(());
+	}
+
+	mark_folder_sizes_stale(ctx, directory_ids).await?;
+	ctx.spawn_job(Job::new(
+		FolderSizesJobInit::default(),
+		Box::new(FolderSizesJob {}),
+	))
+	.await;
+
+	Ok(())
+}
+
+// marks without queueing the job, for jobs changing many entries to queue it once they're done
+pub(crate) async fn mark_folder_sizes_stale(
+	ctx: &LibraryContext,
+	directory_ids: Vec<i32>,
+) -> Result<(), prisma::QueryError> {
+	if directory_ids.is_empty() {
+		return Ok(());
+	}
+
+	ctx.db
+		._execute_raw(Raw::new(
+			&format!(
+				"WITH RECURSIVE ancestors(id) AS (
+					SELECT id FROM file_paths WHERE id IN ({})
+					UNION SELECT file_paths.parent_id FROM file_paths
+					INNER JOIN ancestors ON file_paths.id = ancestors.id
+					WHERE file_paths.parent_id IS NOT NULL
+				)
+				UPDATE file_paths SET folder_size_stale = 1 WHERE id IN (SELECT id FROM ancestors)",
+				vec!["{}"; directory_ids.len()].join(", ")
+			),
+			directory_ids
+				.into_iter()
+				.map(|id| PrismaValue::Int(id as i64))
+				.collect(),
+		))
+		.await?;
+
+	Ok(())
+}
+
+// sums the file sizes up the tree, every directory of the location getting a size even when empty
+fn sum_sizes(entries: &[SizedEntry]) -> HashMap<i32, u64> {
+	let parents = entries
+		.iter()
+		.map(|entry| (entry.id, entry.parent_id))
+		.collect::<HashMap<_, _>>();
+	let mut sizes = entries
+		.iter()
+		.filter(|entry| entry.is_dir != 0)
+		.map(|entry| (entry.id, 0))
+		.collect::<HashMap<_, _>>();
+
+	// hardlinked contents are counted once by each directory they are below
+	let mut links = HashMap::new();
+	for entry in entries.iter().filter(|entry| entry.is_dir == 0) {
+		if let (Some(inode), Some(device)) = (entry.inode, entry.device) {
+			*links.entry((inode, device)).or_insert(0) += 1;
+		}
+	}
+	let mut counted = HashSet::new();
+
+	for entry in entries.iter().filter(|entry| entry.is_dir == 0) {
+		let size = entry
+			.size
+			.as_deref()
+			.and_then(|size| size.parse::<u64>().ok())
+			.unwrap_or(0);
+		if size == 0 {
+			continue;
+		}
+
+		let hardlink = match (entry.inode, entry.device) {
+			(Some(inode), Some(device)) if links[&(inode, device)] > 1 => Some((inode, device)),
+			_ => None,
+		};
+
+		let mut parent = entry.parent_id;
+		// bounded, so a cycle left by an interrupted move can't hang the job
+		for _ in 0..entries.len() {
+			let id = match parent {
+				Some(id) => id,
+				None => break,
+			};
+			let first_link = match hardlink {
+				Some((inode, device)) => counted.insert((id, inode, device)),
+				None => true,
+			};
+			if let (true, Some(total)) = (first_link, sizes.get_mut(&id)) {
+				*total += size;
+			}
+			parent = parents.get(&id).copied().flatten();
+		}
+	}
+
+	sizes
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FolderSizesJob {
+	type Init = FolderSizesJobInit;
+	type Data = ();
+	type Step = FolderSizesJobStep;
+
+	fn name(&self) -> &'static str {
+		FOLDER_SIZES_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let locations = ctx
+			.library_ctx()
+			.db
+			._query_raw::<StaleLocation>(raw!(
+				"SELECT DISTINCT location_id FROM file_paths
+				WHERE is_dir = 1 AND folder_size_stale = 1 AND location_id IS NOT NULL"
+			))
+			.await?;
+
+		let mut location_ids = locations
+			.into_iter()
+			.map(|location| location.location_id)
+			.collect::<Vec<_>>();
+		if let Some(location_id) = state.init.location_id {
+			if !location_ids.contains(&location_id) {
+				location_ids.push(location_id);
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(location_ids.len())]);
+
+		state.steps = location_ids
+			.into_iter()
+			.map(|location_id| FolderSizesJobStep { location_id })
+			.collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let step = &state.steps[0];
+		let library_ctx = ctx.library_ctx();
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Computing folder sizes of location {}",
+			step.location_id
+		))]);
+
+		let entries = library_ctx
+			.db
+			._query_raw::<SizedEntry>(raw!(
+				"SELECT file_paths.id, file_paths.parent_id, CAST(file_paths.is_dir AS INTEGER) AS is_dir,
+				CASE WHEN file_paths.is_dir THEN file_paths.folder_size ELSE files.size_in_bytes END AS size,
+				file_paths.inode, file_paths.device
+				FROM file_paths LEFT JOIN files ON files.id = file_paths.file_id
+				WHERE file_paths.location_id = {}",
+				PrismaValue::Int(step.location_id as i64)
+			))
+			.await?;
+
+		let sizes = sum_sizes(&entries);
+		let changed = entries
+			.iter()
+			.filter(|entry| entry.is_dir != 0)
+			.filter_map(|entry| {
+				let size = sizes.get(&entry.id)?.to_string();
+				(entry.size.as_deref() != Some(size.as_str())).then(|| (entry.id, size))
+			})
+			.collect::<Vec<_>>();
+
+		for batch in changed.chunks(SIZE_UPDATE_BATCH_SIZE) {
+			let mut values = Vec::with_capacity(batch.len() * 3);
+			for (id, size) in batch {
+				values.push(PrismaValue::Int(*id as i64));
+				values.push(PrismaValue::String(size.clone()));
+			}
+			values.extend(batch.iter().map(|(id, _)| PrismaValue::Int(*id as i64)));
+
+			library_ctx
+				.db
+				._execute_raw(Raw::new(
+					&format!(
+						"UPDATE file_paths SET folder_size = CASE id {} END WHERE id IN ({})",
+						vec!["WHEN {} THEN {}"; batch.len()].join(" "),
+						vec!["{}"; batch.len()].join(", ")
+					),
+					values,
+				))
+				.await?;
+		}
+
+		library_ctx
+			.db
+			._execute_raw(raw!(
+				"UPDATE file_paths SET folder_size_stale = 0 WHERE location_id = {} AND is_dir = 1",
+				PrismaValue::Int(step.location_id as i64)
+			))
+			.await?;
+		// what changed below the directories changes the storage totals of the location too
+		library_ctx
+			.db
+			.location()
+			.find_unique(location::id::equals(step.location_id))
+			.update(vec![location::storage_stale::set(true)])
+			.exec()
+			.await?;
+
+		info!(
+			"Updated {} folder sizes of location {}",
+			changed.len(),
+			step.location_id
+		);
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		_state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		let library_ctx = ctx.library_ctx();
+		super::send_invalidate_query(&library_ctx).await;
+		library_ctx
+			.queue_job(Job::new(
+				StorageBreakdownJobInit {},
+				Box::new(StorageBreakdownJob {}),
+			))
+			.await;
+		Ok(())
+	}
+}
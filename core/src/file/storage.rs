@@ -0,0 +1,434 @@
+use crate::{
+	encode::{
+		AUDIO_EXTENSIONS, HEIF_EXTENSIONS, IMAGE_EXTENSIONS, JXL_EXTENSIONS, RAW_EXTENSIONS,
+		VIDEO_EXTENSIONS,
+	},
+	file::{
+		explorer::find_directory,
+		text::{DOCUMENT_EXTENSIONS, PLAINTEXT_EXTENSIONS},
+		FileError, FilePath,
+	},
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::LibraryContext,
+	prisma::{location, storage_total},
+	sys::get_location,
+	ClientQuery, CoreEvent, LibraryQuery,
+};
+use log::info;
+use prisma_client_rust::{prisma_models::PrismaValue, raw};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, HashSet},
+	path::Path,
+};
+use ts_rs::TS;
+
+pub const STORAGE_BREAKDOWN_JOB_NAME: &str = "storage_breakdown";
+// treemaps past this many nodes per level can't be drawn legibly anyway
+const MAX_TREEMAP_NODES: u32 = 500;
+
+static ARCHIVE_EXTENSIONS: [&str; 9] = ["zip", "tar", "gz", "tgz", "bz2", "xz", "7z", "rar", "zst"];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, PartialEq, Eq, Hash)]
+#[ts(export)]
+pub enum StorageCategory {
+	Images,
+	Videos,
+	Audio,
+	Documents,
+	Archives,
+	Other,
+}
+
+impl StorageCategory {
+	// `extension` is lowercased
+	pub fn of(extension: &str) -> Self {
+		let is_in = |extensions: &[&str]| extensions.contains(&extension);
+
+		if is_in(&IMAGE_EXTENSIONS)
+			|| is_in(&RAW_EXTENSIONS)
+			|| is_in(&HEIF_EXTENSIONS)
+			|| is_in(&JXL_EXTENSIONS)
+		{
+			Self::Images
+		} else if is_in(&VIDEO_EXTENSIONS) {
+			Self::Videos
+		} else if is_in(&AUDIO_EXTENSIONS) {
+			Self::Audio
+		} else if is_in(&PLAINTEXT_EXTENSIONS) || is_in(&DOCUMENT_EXTENSIONS) {
+			Self::Documents
+		} else if is_in(&ARCHIVE_EXTENSIONS) {
+			Self::Archives
+		} else {
+			Self::Other
+		}
+	}
+}
+
+// bytes are strings, as the sizes of the files they add up are
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LocationStorage {
+	pub location_id: i32,
+	pub name: Option<String>,
+	pub bytes: String,
+	pub file_count: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CategoryStorage {
+	pub category: StorageCategory,
+	pub bytes: String,
+	pub file_count: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ExtensionStorage {
+	// empty for files without one
+	pub extension: String,
+	pub category: StorageCategory,
+	pub bytes: String,
+	pub file_count: i32,
+}
+
+// Where the bytes of a library go, every list sorted from the largest. The totals of the
+// locations in `stale_location_ids` are those of before they last changed, until the storage
+// breakdown job catches up with them.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StorageBreakdown {
+	pub bytes: String,
+	pub file_count: i32,
+	pub locations: Vec<LocationStorage>,
+	pub categories: Vec<CategoryStorage>,
+	pub extensions: Vec<ExtensionStorage>,
+	pub stale_location_ids: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct TreemapNode {
+	pub file_path_id: i32,
+	pub name: String,
+	pub extension: Option<String>,
+	pub is_dir: bool,
+	pub bytes: String,
+}
+
+// The largest entries of a directory, directories sized by their cached folder size. What the
+// entries past them add up to is `rest_bytes`, for the treemap to draw as a single node.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StorageTreemap {
+	pub directory: FilePath,
+	pub bytes: String,
+	pub nodes: Vec<TreemapNode>,
+	pub rest_bytes: String,
+	pub rest_count: i32,
+}
+
+#[derive(Default)]
+struct Total {
+	bytes: u64,
+	file_count: i32,
+}
+
+impl Total {
+	fn add(&mut self, bytes: u64, file_count: i32) {
+		self.bytes += bytes;
+		self.file_count += file_count;
+	}
+}
+
+// largest first, ties in a stable order so the lists don't shuffle between refreshes
+fn sorted<K: Ord>(totals: HashMap<K, Total>) -> Vec<(K, Total)> {
+	let mut totals = totals.into_iter().collect::<Vec<_>>();
+	totals.sort_by(|(a_key, a), (b_key, b)| b.bytes.cmp(&a.bytes).then_with(|| a_key.cmp(b_key)));
+	totals
+}
+
+// get_storage_breakdown reads the cached totals of the library, or of a single location
+pub async fn get_storage_breakdown(
+	ctx: &LibraryContext,
+	location_id: Option<i32>,
+) -> Result<StorageBreakdown, FileError> {
+	let locations = ctx
+		.db
+		.location()
+		.find_many(match location_id {
+			Some(location_id) => vec![location::id::equals(location_id)],
+			None => vec![],
+		})
+		.exec()
+		.await?;
+	let rows = ctx
+		.db
+		.storage_total()
+		.find_many(vec![storage_total::location_id::in_vec(
+			locations.iter().map(|location| location.id).collect(),
+		)])
+		.exec()
+		.await?;
+
+	let mut total = Total::default();
+	let mut by_location = HashMap::<i32, Total>::new();
+	let mut by_extension = HashMap::<String, Total>::new();
+	for row in rows {
+		let bytes = row.bytes.parse::<u64>().unwrap_or(0);
+		total.add(bytes, row.file_count);
+		by_location
+			.entry(row.location_id)
+			.or_default()
+			.add(bytes, row.file_count);
+		by_extension
+			.entry(row.extension)
+			.or_default()
+			.add(bytes, row.file_count);
+	}
+
+	let mut by_category = HashMap::<StorageCategory, Total>::new();
+	for (extension, extension_total) in &by_extension {
+		by_category
+			.entry(StorageCategory::of(extension))
+			.or_default()
+			.add(extension_total.bytes, extension_total.file_count);
+	}
+	let mut categories = by_category.into_iter().collect::<Vec<_>>();
+	categories.sort_by(|(_, a), (_, b)| b.bytes.cmp(&a.bytes));
+
+	let names = locations
+		.iter()
+		.map(|location| (location.id, location.name.clone()))
+		.collect::<HashMap<_, _>>();
+
+	Ok(StorageBreakdown {
+		bytes: total.bytes.to_string(),
+		file_count: total.file_count,
+		locations: sorted(by_location)
+			.into_iter()
+			.map(|(location_id, total)| LocationStorage {
+				location_id,
+				name: names.get(&location_id).cloned().flatten(),
+				bytes: total.bytes.to_string(),
+				file_count: total.file_count,
+			})
+			.collect(),
+		categories: categories
+			.into_iter()
+			.map(|(category, total)| CategoryStorage {
+				category,
+				bytes: total.bytes.to_string(),
+				file_count: total.file_count,
+			})
+			.collect(),
+		extensions: sorted(by_extension)
+			.into_iter()
+			.map(|(extension, total)| ExtensionStorage {
+				category: StorageCategory::of(&extension),
+				extension,
+				bytes: total.bytes.to_string(),
+				file_count: total.file_count,
+			})
+			.collect(),
+		stale_location_ids: locations
+			.iter()
+			.filter(|location| location.storage_stale)
+			.map(|location| location.id)
+			.collect(),
+	})
+}
+
+#[derive(Deserialize)]
+struct TreemapRow {
+	id: i32,
+	name: String,
+	extension: Option<String>,
+	is_dir: i32,
+	size: Option<String>,
+	inode: Option<i64>,
+	device: Option<i64>,
+}
+
+// get_storage_treemap drills down into a directory of a location, the root with an empty path
+pub async fn get_storage_treemap(
+	ctx: &LibraryContext,
+	location_id: i32,
+	path: impl AsRef<Path>,
+	limit: u32,
+) -> Result<StorageTreemap, FileError> {
+	let location = get_location(ctx, location_id).await?;
+	let directory = find_directory(ctx, location.id, path.as_ref()).await?;
+
+	let rows = ctx
+		.db
+		._query_raw::<TreemapRow>(raw!(
+			"SELECT file_paths.id, file_paths.name, file_paths.extension,
+			CAST(file_paths.is_dir AS INTEGER) AS is_dir,
+			COALESCE(file_paths.folder_size, files.size_in_bytes) AS size,
+			file_paths.inode, file_paths.device
+			FROM file_paths LEFT JOIN files ON files.id = file_paths.file_id
+			WHERE file_paths.parent_id = {}
+			ORDER BY file_paths.id",
+			PrismaValue::Int(directory.id as i64)
+		))
+		.await?;
+
+	// hardlinks of the same contents within the directory are sized once
+	let mut links = HashSet::new();
+	let mut rows = rows
+		.into_iter()
+		.map(|row| {
+			let first_link = match (row.inode, row.device) {
+				(Some(inode), Some(device)) => links.insert((inode, device)),
+				_ => true,
+			};
+			let bytes = match first_link {
+				true => row
+					.size
+					.as_deref()
+					.and_then(|size| size.parse::<u64>().ok())
+					.unwrap_or(0),
+				false => 0,
+			};
+			(row, bytes)
+		})
+		.collect::<Vec<_>>();
+	rows.sort_by(|(a, a_bytes), (b, b_bytes)| b_bytes.cmp(a_bytes).then_with(|| a.id.cmp(&b.id)));
+
+	let bytes = rows.iter().map(|(_, bytes)| bytes).sum::<u64>();
+	let rest = rows.split_off((limit.min(MAX_TREEMAP_NODES) as usize).min(rows.len()));
+
+	Ok(StorageTreemap {
+		directory: directory.into(),
+		bytes: bytes.to_string(),
+		nodes: rows
+			.into_iter()
+			.map(|(row, bytes)| TreemapNode {
+				file_path_id: row.id,
+				name: row.name,
+				extension: row.extension,
+				is_dir: row.is_dir != 0,
+				bytes: bytes.to_string(),
+			})
+			.collect(),
+		rest_bytes: rest.iter().map(|(_, bytes)| bytes).sum::<u64>().to_string(),
+		rest_count: rest.len() as i32,
+	})
+}
+
+pub struct StorageBreakdownJob {}
+
+// StorageBreakdownJobInit sums the files of every location with stale storage totals by
+// extension. Locations are marked stale by the folder sizes job, which runs once their contents
+// change, so the locations which didn't are left as they are.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StorageBreakdownJobInit {}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StorageBreakdownJobStep {
+	location_id: i32,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for StorageBreakdownJob {
+	type Init = StorageBreakdownJobInit;
+	type Data = ();
+	type Step = StorageBreakdownJobStep;
+
+	fn name(&self) -> &'static str {
+		STORAGE_BREAKDOWN_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let locations = ctx
+			.library_ctx()
+			.db
+			.location()
+			.find_many(vec![location::storage_stale::equals(true)])
+			.exec()
+			.await?;
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(locations.len())]);
+
+		state.steps = locations
+			.into_iter()
+			.map(|location| StorageBreakdownJobStep {
+				location_id: location.id,
+			})
+			.collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let step = &state.steps[0];
+		let library_ctx = ctx.library_ctx();
+
+		// cleared first, so a change made while the totals are summed marks the location again
+		library_ctx
+			.db
+			.location()
+			.find_unique(location::id::equals(step.location_id))
+			.update(vec![location::storage_stale::set(false)])
+			.exec()
+			.await?;
+
+		library_ctx
+			.db
+			.storage_total()
+			.find_many(vec![storage_total::location_id::equals(step.location_id)])
+			.delete()
+			.exec()
+			.await?;
+		library_ctx
+			.db
+			._execute_raw(raw!(
+				"INSERT INTO storage_totals (location_id, extension, bytes, file_count)
+				SELECT file_paths.location_id, LOWER(COALESCE(file_paths.extension, '')),
+				CAST(SUM(CAST(COALESCE(files.size_in_bytes, '0') AS INTEGER)) AS TEXT), COUNT(*)
+				FROM file_paths LEFT JOIN files ON files.id = file_paths.file_id
+				WHERE file_paths.location_id = {} AND file_paths.is_dir = 0
+				AND (file_paths.inode IS NULL OR file_paths.id = (
+					SELECT MIN(links.id) FROM file_paths AS links
+					WHERE links.location_id = file_paths.location_id
+					AND links.inode = file_paths.inode AND links.device = file_paths.device
+				))
+				GROUP BY LOWER(COALESCE(file_paths.extension, ''))",
+				PrismaValue::Int(step.location_id as i64)
+			))
+			.await?;
+
+		info!("Updated storage totals of location {}", step.location_id);
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		_state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		let library_ctx = ctx.library_ctx();
+		library_ctx
+			.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+				library_id: library_ctx.id,
+				query: LibraryQuery::GetStorageBreakdown { location_id: None },
+			}))
+			.await;
+		Ok(())
+	}
+}
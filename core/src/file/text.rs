@@ -0,0 +1,308 @@
+use crate::{
+	file::{File, FileError},
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::LibraryContext,
+	prisma::file,
+	sys::get_location,
+};
+use log::{error, info};
+use prisma_client_rust::{prisma_models::PrismaValue, raw, raw::Raw};
+use serde::{Deserialize, Serialize};
+use std::{
+	fs,
+	io::{self, Read},
+	path::{Path, PathBuf},
+};
+use ts_rs::TS;
+
+// documents above this size are mostly images or scans, with little text worth indexing
+static TEXT_EXTRACTION_MAX_FILE_SIZE: i64 = 32 * 1024 * 1024;
+// only the start of very long documents is indexed
+static TEXT_EXTRACTION_MAX_CHARS: usize = 1024 * 1024;
+pub const TEXT_EXTRACTOR_JOB_NAME: &str = "text_extraction";
+
+pub(crate) static PLAINTEXT_EXTENSIONS: [&str; 11] = [
+	"txt", "md", "markdown", "csv", "log", "json", "xml", "html", "yaml", "yml", "toml",
+];
+pub(crate) static DOCUMENT_EXTENSIONS: [&str; 3] = ["pdf", "docx", "odt"];
+
+// A file matching a full text search, with the matching part of its text
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FullTextSearchResult {
+	pub file: File,
+	// the best matching fragment of text, with matches wrapped in <mark></mark>
+	pub snippet: String,
+}
+
+pub struct TextExtractorJob {}
+
+// TextExtractorJobInit pulls plain text out of documents in a location into the full text
+// search index, each file is its own step so an interrupted job resumes where it stopped
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TextExtractorJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TextExtractorJobState {
+	location_path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TextExtractorJobStep {
+	file_id: i32,
+	materialized_path: String,
+	extension: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct FullTextMatch {
+	file_id: i32,
+	snippet: String,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for TextExtractorJob {
+	type Init = TextExtractorJobInit;
+	type Data = TextExtractorJobState;
+	type Step = TextExtractorJobStep;
+
+	fn name(&self) -> &'static str {
+		TEXT_EXTRACTOR_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let location = get_location(&library_ctx, state.init.location_id).await?;
+
+		let extensions = PLAINTEXT_EXTENSIONS
+			.iter()
+			.chain(DOCUMENT_EXTENSIONS.iter())
+			.map(|ext| format!("'{}'", ext))
+			.collect::<Vec<_>>()
+			.join(", ");
+
+		// files already in the index were extracted by a previous run
+		let files = library_ctx
+			.db
+			._query_raw::<TextExtractorJobStep>(Raw::new(
+				&format!(
+					"SELECT files.id AS file_id, MIN(file_paths.materialized_path) AS materialized_path,
+					LOWER(file_paths.extension) AS extension
+					FROM file_paths INNER JOIN files ON files.id = file_paths.file_id
+					WHERE file_paths.location_id = {{}}
+					AND CAST(files.size_in_bytes AS INTEGER) <= {{}}
+					AND LOWER(file_paths.extension) IN ({})
+					AND files.id NOT IN (SELECT rowid FROM file_contents_fts)
+					GROUP BY files.id",
+					extensions
+				),
+				vec![
+					PrismaValue::Int(location.id as i64),
+					PrismaValue::Int(TEXT_EXTRACTION_MAX_FILE_SIZE),
+				],
+			))
+			.await?;
+
+		info!(
+			"Found {} documents to extract text from in location {}",
+			files.len(),
+			location.id
+		);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(files.len()),
+			JobReportUpdate::Message(format!("Extracting text from {} files", files.len())),
+		]);
+
+		state.data = Some(TextExtractorJobState {
+			location_path: location.path.unwrap_or_default(),
+		});
+		state.steps = files.into_iter().collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Extracting text from {}",
+			step.materialized_path
+		))]);
+
+		let path = data.location_path.join(&step.materialized_path);
+		let extension = step.extension.clone();
+		// document parsers can panic on malformed files, which only fails this step
+		let text = match tokio::task::spawn_blocking(move || extract_text(&path, &extension)).await
+		{
+			Ok(Ok(text)) => text,
+			Ok(Err(e)) => {
+				error!(
+					"Error extracting text from {}: {:?}",
+					step.materialized_path, e
+				);
+				String::new()
+			}
+			Err(e) => {
+				error!(
+					"Text extraction of {} panicked: {:?}",
+					step.materialized_path, e
+				);
+				String::new()
+			}
+		};
+
+		// an empty row still marks the file as extracted, so it isn't retried on every scan
+		let library_ctx = ctx.library_ctx();
+		library_ctx
+			.db
+			._execute_raw(raw!(
+				"DELETE FROM file_contents_fts WHERE rowid = {}",
+				PrismaValue::Int(step.file_id as i64)
+			))
+			.await?;
+		library_ctx
+			.db
+			._execute_raw(raw!(
+				"INSERT INTO file_contents_fts (rowid, content) VALUES ({}, {})",
+				PrismaValue::Int(step.file_id as i64),
+				PrismaValue::String(text)
+			))
+			.await?;
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		_ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		info!(
+			"Finished extracting text for location {}",
+			state.init.location_id
+		);
+		Ok(())
+	}
+}
+
+// extract_text returns the plain text contents of a document, `extension` must be lowercase
+pub fn extract_text(path: impl AsRef<Path>, extension: &str) -> Result<String, io::Error> {
+	let text = match extension {
+		"pdf" => pdf_extract::extract_text(path)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?,
+		"docx" => xml_text(&read_zip_entry(path, "word/document.xml")?, "w:p"),
+		"odt" => xml_text(&read_zip_entry(path, "content.xml")?, "text:p"),
+		_ => String::from_utf8_lossy(&fs::read(path)?).to_string(),
+	};
+
+	Ok(text.chars().take(TEXT_EXTRACTION_MAX_CHARS).collect())
+}
+
+fn read_zip_entry(path: impl AsRef<Path>, name: &str) -> Result<String, io::Error> {
+	let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+	let mut contents = String::new();
+	archive.by_name(name)?.read_to_string(&mut contents)?;
+	Ok(contents)
+}
+
+// strips every tag from an office document xml, ending each `paragraph_tag` with a new line
+fn xml_text(xml: &str, paragraph_tag: &str) -> String {
+	let paragraph_end = format!("</{}>", paragraph_tag);
+	let mut text = String::with_capacity(xml.len() / 4);
+
+	let mut rest = xml;
+	while let Some(start) = rest.find('<') {
+		text.push_str(&rest[..start]);
+		let end = rest[start..]
+			.find('>')
+			.map_or(rest.len(), |end| start + end + 1);
+		if rest[start..end] == paragraph_end {
+			text.push('\n');
+		}
+		rest = &rest[end..];
+	}
+	text.push_str(rest);
+
+	text.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&")
+}
+
+// search_full_text matches every word of `query` against the extracted text of all files
+pub async fn search_full_text(
+	ctx: &LibraryContext,
+	query: String,
+	limit: i32,
+) -> Result<Vec<FullTextSearchResult>, FileError> {
+	// quoting every word keeps fts5 operators in user input from being interpreted
+	let query = query
+		.split_whitespace()
+		.map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+		.collect::<Vec<_>>()
+		.join(" ");
+
+	if query.is_empty() {
+		return Ok(vec![]);
+	}
+
+	let matches = ctx
+		.db
+		._query_raw::<FullTextMatch>(raw!(
+			"SELECT rowid AS file_id,
+			snippet(file_contents_fts, 0, '<mark>', '</mark>', '…', 16) AS snippet
+			FROM file_contents_fts WHERE file_contents_fts MATCH {}
+			ORDER BY rank LIMIT {}",
+			PrismaValue::String(query),
+			PrismaValue::Int(limit as i64)
+		))
+		.await?;
+
+	let mut files = ctx
+		.db
+		.file()
+		.find_many(vec![file::id::in_vec(
+			matches.iter().map(|m| m.file_id).collect(),
+		)])
+		.with(file::paths::fetch(vec![]))
+		.exec()
+		.await?;
+
+	// keep the ranking of the index, files deleted since extraction are skipped
+	Ok(matches
+		.into_iter()
+		.filter_map(|m| {
+			let index = files.iter().position(|file| file.id == m.file_id)?;
+			let data = files.swap_remove(index);
+			let paths = data.paths.clone().unwrap_or_default();
+
+			let mut file = File::from(data);
+			file.paths = paths.into_iter().map(Into::into).collect();
+
+			Some(FullTextSearchResult {
+				file,
+				snippet: m.snippet,
+			})
+		})
+		.collect())
+}
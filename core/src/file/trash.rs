@@ -0,0 +1,508 @@
+use crate::{
+	file::{ensure_not_held, sizes::invalidate_folder_sizes, FileError, FilePath},
+	history::audit::{self, AuditOperation, AuditedJob},
+	job::{Job, JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::{LibraryContext, LibraryManager},
+	prisma::{file, file_path, location, trashed_entry},
+	search,
+	sys::get_location,
+	ClientQuery, CoreEvent, LibraryQuery,
+};
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+	io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+use tokio::{fs, task::spawn_blocking};
+use ts_rs::TS;
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+// the trash of a location lives at its root, it is hidden so the indexer skips it. See
+// LibraryContext::location_dir for in memory nodes
+pub const TRASH_DIR_NAME: &str = ".sd-trash";
+pub const TRASH_PURGE_JOB_NAME: &str = "trash_purger";
+// how long trashed entries are kept when the library doesn't configure it
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+const TRASH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(6 * 60 * 60);
+
+// An entry moved to the trash of its location, which can be restored to where it was
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct TrashedEntry {
+	pub id: i32,
+	pub location_id: i32,
+	// the materialized path the entry is restored to
+	pub original_path: String,
+	pub is_dir: bool,
+	pub date_trashed: DateTime<Utc>,
+}
+
+impl From<trashed_entry::Data> for TrashedEntry {
+	fn from(data: trashed_entry::Data) -> Self {
+		Self {
+			id: data.id,
+			location_id: data.location_id,
+			original_path: data.original_path,
+			is_dir: data.is_dir,
+			date_trashed: data.date_trashed.into(),
+		}
+	}
+}
+
+// moves an entry into the trash of its location, directories are trashed with their contents
+pub async fn trash_file_path(
+	ctx: &LibraryContext,
+	file_path_id: i32,
+) -> Result<TrashedEntry, FileError> {
+	let file_path = ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.exec()
+		.await?
+		.ok_or(FileError::FilePathNotFound(file_path_id))?;
+
+	if let Some(file_id) = file_path.file_id {
+		ensure_not_held(ctx, file_id).await?;
+	}
+
+	let location_id = file_path.location_id.unwrap_or(0);
+	let location_path = location_path(ctx, location_id).await?;
+	let trash_dir = ctx.location_dir(location_id, &location_path, TRASH_DIR_NAME);
+	fs::create_dir_all(&trash_dir).await?;
+
+	// the rows are kept with the entry, so restoring it brings back the same file paths
+	let mut rows = vec![file_path.clone()];
+	if file_path.is_dir {
+		rows.extend(
+			ctx.db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::materialized_path::starts_with(format!(
+						"{}/",
+						file_path.materialized_path
+					)),
+				])
+				.exec()
+				.await?,
+		);
+	}
+	let rows = rows.into_iter().map(FilePath::from).collect::<Vec<_>>();
+
+	// the entries of the trash are named uniquely, as the same path can be trashed many times
+	let trash_name = Uuid::new_v4().to_string();
+	let entry = ctx
+		.db
+		.trashed_entry()
+		.create(
+			trashed_entry::location::link(location::id::equals(location_id)),
+			trashed_entry::original_path::set(file_path.materialized_path.clone()),
+			trashed_entry::trash_name::set(trash_name.clone()),
+			trashed_entry::file_paths::set(serde_json::to_string(&rows)?),
+			vec![trashed_entry::is_dir::set(file_path.is_dir)],
+		)
+		.exec()
+		.await?;
+
+	// recorded first, so nothing is ever in the trash without the row to restore it from
+	if let Err(e) = move_entry(
+		&location_path.join(&file_path.materialized_path),
+		&trash_dir.join(&trash_name),
+	)
+	.await
+	{
+		ctx.db
+			.trashed_entry()
+			.find_unique(trashed_entry::id::equals(entry.id))
+			.delete()
+			.exec()
+			.await?;
+		return Err(e.into());
+	}
+
+	ctx.db
+		.file_path()
+		.find_many(vec![file_path::id::in_vec(
+			rows.iter().map(|row| row.id).collect(),
+		)])
+		.delete()
+		.exec()
+		.await?;
+	invalidate_folder_sizes(ctx, file_path.parent_id.into_iter().collect()).await?;
+
+	info!(
+		"Trashed {} of location {}",
+		file_path.materialized_path, location_id
+	);
+	changed(ctx).await;
+
+	Ok(entry.into())
+}
+
+// moves an entry back to where it was trashed from, unless something else is there now
+pub async fn restore(ctx: &LibraryContext, id: i32) -> Result<(), FileError> {
+	let entry = ctx
+		.db
+		.trashed_entry()
+		.find_unique(trashed_entry::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(FileError::TrashedEntryNotFound(id))?;
+
+	let location_path = location_path(ctx, entry.location_id).await?;
+	let original_path = location_path.join(&entry.original_path);
+	if fs::metadata(&original_path).await.is_ok() {
+		return Err(FileError::RestoreConflict(original_path));
+	}
+	if let Some(parent) = original_path.parent() {
+		fs::create_dir_all(parent).await?;
+	}
+
+	let trashed_path = ctx
+		.location_dir(entry.location_id, &location_path, TRASH_DIR_NAME)
+		.join(&entry.trash_name);
+	move_entry(&trashed_path, &original_path).await?;
+
+	let rows: Vec<FilePath> = serde_json::from_str(&entry.file_paths)?;
+	// the entry itself comes first, the directory it is restored to is the one to size again
+	let parent_ids = rows
+		.first()
+		.and_then(|row| row.parent_id)
+		.into_iter()
+		.collect();
+
+	// the row of the entry is removed last, and the entry goes back to the trash if its file paths
+	// can't be restored, so it is never out of the trash while its row is there
+	let mut restored = vec![];
+	if let Err(e) = restore_rows(ctx, &entry, rows, &mut restored).await {
+		ctx.db
+			.file_path()
+			.find_many(vec![file_path::id::in_vec(restored)])
+			.delete()
+			.exec()
+			.await
+			.ok();
+		move_entry(&original_path, &trashed_path).await?;
+		return Err(e);
+	}
+	invalidate_folder_sizes(ctx, parent_ids).await?;
+
+	info!(
+		"Restored {} of location {}",
+		entry.original_path, entry.location_id
+	);
+	changed(ctx).await;
+
+	Ok(())
+}
+
+// brings back the file paths of an entry moved out of the trash, adding their ids to `restored` as
+// they are, and removes its row
+async fn restore_rows(
+	ctx: &LibraryContext,
+	entry: &trashed_entry::Data,
+	rows: Vec<FilePath>,
+	restored: &mut Vec<i32>,
+) -> Result<(), FileError> {
+	for row in rows {
+		let mut params = vec![
+			file_path::id::set(row.id),
+			file_path::is_dir::set(row.is_dir),
+			file_path::location::link(location::id::equals(entry.location_id)),
+			file_path::extension::set(row.extension),
+			file_path::parent_id::set(row.parent_id),
+			file_path::retention_exempt::set(row.retention_exempt),
+			file_path::hidden::set(row.hidden),
+			file_path::date_created::set(row.date_created.into()),
+			file_path::date_modified::set(row.date_modified.into()),
+			file_path::date_indexed::set(row.date_indexed.into()),
+		];
+		// the file may have been removed from the library in the meantime, it's identified again then
+		if let Some(file_id) = row.file_id {
+			if ctx
+				.db
+				.file()
+				.find_unique(file::id::equals(file_id))
+				.exec()
+				.await?
+				.is_some()
+			{
+				params.push(file_path::file::link(file::id::equals(file_id)));
+			}
+		}
+
+		ctx.db
+			.file_path()
+			.create(
+				file_path::materialized_path::set(row.materialized_path),
+				file_path::name::set(row.name),
+				params,
+			)
+			.exec()
+			.await?;
+		restored.push(row.id);
+	}
+
+	ctx.db
+		.trashed_entry()
+		.find_unique(trashed_entry::id::equals(entry.id))
+		.delete()
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+// the latest entry trashed from a path, used to restore it by path
+pub async fn find_trashed(
+	ctx: &LibraryContext,
+	location_id: i32,
+	original_path: &str,
+) -> Result<Option<TrashedEntry>, FileError> {
+	Ok(ctx
+		.db
+		.trashed_entry()
+		.find_many(vec![
+			trashed_entry::location_id::equals(location_id),
+			trashed_entry::original_path::equals(original_path.to_string()),
+		])
+		.exec()
+		.await?
+		.into_iter()
+		.max_by_key(|entry| entry.id)
+		.map(Into::into))
+}
+
+pub async fn get_trash(ctx: &LibraryContext) -> Result<Vec<TrashedEntry>, FileError> {
+	Ok(ctx
+		.db
+		.trashed_entry()
+		.find_many(vec![])
+		.exec()
+		.await?
+		.into_iter()
+		.map(Into::into)
+		.collect())
+}
+
+// removes an entry from disk for good
+async fn purge(ctx: &LibraryContext, id: i32) -> Result<(), FileError> {
+	let entry = match ctx
+		.db
+		.trashed_entry()
+		.find_unique(trashed_entry::id::equals(id))
+		.exec()
+		.await?
+	{
+		Some(entry) => entry,
+		// restored since the purge started
+		None => return Ok(()),
+	};
+
+	let path = ctx
+		.location_dir(
+			entry.location_id,
+			&location_path(ctx, entry.location_id).await?,
+			TRASH_DIR_NAME,
+		)
+		.join(&entry.trash_name);
+	let removed = match entry.is_dir {
+		true => fs::remove_dir_all(&path).await,
+		false => fs::remove_file(&path).await,
+	};
+	// already gone from disk, only the entry is left to remove
+	removed.or_else(|e| match e.kind() {
+		io::ErrorKind::NotFound => Ok(()),
+		_ => Err(e),
+	})?;
+
+	ctx.db
+		.trashed_entry()
+		.find_unique(trashed_entry::id::equals(id))
+		.delete()
+		.exec()
+		.await?;
+	audit::record_job(
+		ctx,
+		AuditOperation::Delete,
+		AuditedJob::TrashPurge {
+			location_id: entry.location_id,
+			original_path: entry.original_path,
+		},
+	)
+	.await;
+
+	Ok(())
+}
+
+// moves an entry, copying it and removing it when that can't be done with a rename, as the trash of
+// an in memory node can be on another file system than its locations
+pub(crate) async fn move_entry(from: &Path, to: &Path) -> io::Result<()> {
+	if fs::rename(from, to).await.is_ok() {
+		return Ok(());
+	}
+
+	let (from, to) = (from.to_path_buf(), to.to_path_buf());
+	spawn_blocking(move || {
+		for entry in WalkDir::new(&from) {
+			let entry = entry?;
+			let target = match entry.path().strip_prefix(&from) {
+				Ok(path) if !path.as_os_str().is_empty() => to.join(path),
+				_ => to.clone(),
+			};
+			match entry.file_type().is_dir() {
+				true => std::fs::create_dir_all(&target)?,
+				false => {
+					std::fs::copy(entry.path(), &target)?;
+				}
+			}
+		}
+
+		match from.is_dir() {
+			true => std::fs::remove_dir_all(&from),
+			false => std::fs::remove_file(&from),
+		}
+	})
+	.await
+	.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+}
+
+async fn location_path(ctx: &LibraryContext, location_id: i32) -> Result<PathBuf, FileError> {
+	get_location(ctx, location_id)
+		.await?
+		.path
+		.ok_or(FileError::LocationUnavailable(location_id))
+}
+
+async fn changed(ctx: &LibraryContext) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetTrash,
+	}))
+	.await;
+
+	search::refresh_subscriptions(ctx).await;
+}
+
+fn retention_days(ctx: &LibraryContext) -> u32 {
+	ctx.config
+		.trash_retention_days
+		.unwrap_or(DEFAULT_TRASH_RETENTION_DAYS)
+}
+
+pub struct TrashPurgeJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrashPurgeJobInit {
+	// entries trashed more than this many days ago are purged, or all of them if unset
+	pub older_than_days: Option<u32>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for TrashPurgeJob {
+	type Init = TrashPurgeJobInit;
+	type Data = ();
+	type Step = i32;
+
+	fn name(&self) -> &'static str {
+		TRASH_PURGE_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let mut params = vec![];
+		if let Some(days) = state.init.older_than_days {
+			params.push(trashed_entry::date_trashed::lt(
+				(Utc::now() - Duration::days(days as i64)).into(),
+			));
+		}
+
+		state.steps = ctx
+			.library_ctx()
+			.db
+			.trashed_entry()
+			.find_many(params)
+			.exec()
+			.await?
+			.into_iter()
+			.map(|entry| entry.id)
+			.collect();
+
+		info!("Purging {} trashed entries", state.steps.len());
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+		state.data = Some(());
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		if let Err(e) = purge(&ctx.library_ctx(), state.steps[0]).await {
+			error!("Failed to purge trashed entry {}: {:#?}", state.steps[0], e);
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		_state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		changed(&ctx.library_ctx()).await;
+
+		Ok(())
+	}
+}
+
+// purges the trash of every library once its entries are older than the library keeps them
+pub async fn watch_trash(library_manager: Arc<LibraryManager>) {
+	loop {
+		for ctx in library_manager.get_all_libraries_ctx().await {
+			let days = retention_days(&ctx);
+			// a retention of 0 days keeps entries until the trash is emptied
+			if days == 0 {
+				continue;
+			}
+
+			match ctx
+				.db
+				.trashed_entry()
+				.find_first(vec![trashed_entry::date_trashed::lt(
+					(Utc::now() - Duration::days(days as i64)).into(),
+				)])
+				.exec()
+				.await
+			{
+				Ok(None) => {}
+				Ok(Some(_)) => {
+					ctx.spawn_job(Job::new(
+						TrashPurgeJobInit {
+							older_than_days: Some(days),
+						},
+						Box::new(TrashPurgeJob {}),
+					))
+					.await;
+				}
+				Err(e) => error!("Failed to read the trash: {:#?}", e),
+			}
+		}
+
+		tokio::time::sleep(TRASH_CHECK_INTERVAL).await;
+	}
+}
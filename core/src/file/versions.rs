@@ -0,0 +1,554 @@
+use crate::{
+	file::{
+		cas::{CHUNK_AVG_SIZE, CHUNK_MAX_SIZE, CHUNK_MIN_SIZE},
+		ensure_not_held, FileError,
+	},
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::{LibraryContext, LibraryManager},
+	prisma::{file_path, file_snapshot, location},
+	sys::{get_location, LocationResource},
+	Job,
+};
+use chrono::{DateTime, Utc};
+use fastcdc::v2020::StreamCDC;
+use log::{error, info};
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, HashSet},
+	fs::{self, File},
+	io::{self, BufReader, Write},
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+use tokio::task::spawn_blocking;
+use ts_rs::TS;
+use walkdir::WalkDir;
+
+pub static VERSIONS_DIR_NAME: &str = "versions";
+pub const SNAPSHOT_JOB_NAME: &str = "file_snapshotter";
+const VERSION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// How many versions of the files of a location are kept, once versioning is enabled on it
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Default)]
+#[ts(export)]
+pub struct VersioningPolicy {
+	// older versions of a file are pruned past this many, unlimited if unset
+	pub max_versions: Option<i32>,
+	// the oldest versions are pruned once the blocks of the location take more than this, unlimited
+	// if unset. the latest version of each file is always kept
+	pub quota_bytes: Option<i64>,
+}
+
+// The contents of a file at some point, stored as content addressed blocks so unchanged parts
+// of a file are only stored once
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FileSnapshot {
+	pub id: i32,
+	pub file_path_id: i32,
+	// where the file was when it was snapshotted
+	pub materialized_path: String,
+	pub size_in_bytes: String,
+	// when the file was modified on disk
+	pub date_modified: DateTime<Utc>,
+	pub date_created: DateTime<Utc>,
+}
+
+impl From<file_snapshot::Data> for FileSnapshot {
+	fn from(data: file_snapshot::Data) -> Self {
+		Self {
+			id: data.id,
+			file_path_id: data.file_path_id,
+			materialized_path: data.materialized_path,
+			size_in_bytes: data.size_in_bytes,
+			date_modified: data.date_modified.into(),
+			date_created: data.date_created.into(),
+		}
+	}
+}
+
+// the versions of a file path, newest first
+pub async fn get_versions(
+	ctx: &LibraryContext,
+	file_path_id: i32,
+) -> Result<Vec<FileSnapshot>, FileError> {
+	Ok(ctx
+		.db
+		.file_snapshot()
+		.find_many(vec![file_snapshot::file_path_id::equals(file_path_id)])
+		.order_by(file_snapshot::id::order(Direction::Desc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(Into::into)
+		.collect())
+}
+
+// writes a version of a file back over it. the current contents are snapshotted first, so a restore
+// can itself be undone
+pub async fn restore_snapshot(ctx: &LibraryContext, id: i32) -> Result<(), FileError> {
+	let snapshot = ctx
+		.db
+		.file_snapshot()
+		.find_unique(file_snapshot::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(FileError::SnapshotNotFound(id))?;
+	let location = get_location(ctx, snapshot.location_id).await?;
+	let location_path = location
+		.path
+		.clone()
+		.ok_or(FileError::LocationUnavailable(location.id))?;
+
+	// the file is restored to where it is now, or to where it was if it is gone
+	let file_path = ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(snapshot.file_path_id))
+		.exec()
+		.await?;
+	if let Some(file_path) = &file_path {
+		if let Some(file_id) = file_path.file_id {
+			ensure_not_held(ctx, file_id).await?;
+		}
+		take_snapshot(ctx, &location, file_path).await?;
+	}
+	let target = location_path.join(
+		file_path
+			.map(|file_path| file_path.materialized_path)
+			.unwrap_or_else(|| snapshot.materialized_path.clone()),
+	);
+
+	let blocks_dir = blocks_dir(ctx, location.id);
+	let chunks = snapshot.chunks.clone();
+	spawn_blocking(move || write_blocks(&blocks_dir, &chunks, &target))
+		.await
+		.map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+	info!(
+		"Restored {} of location {} to its version {}",
+		snapshot.materialized_path, location.id, id
+	);
+
+	Ok(())
+}
+
+pub(crate) fn blocks_dir(ctx: &LibraryContext, location_id: i32) -> PathBuf {
+	ctx.config()
+		.data_directory()
+		.join(VERSIONS_DIR_NAME)
+		.join(ctx.id.to_string())
+		.join(location_id.to_string())
+}
+
+pub(crate) fn block_path(blocks_dir: &Path, hash: &str) -> PathBuf {
+	blocks_dir.join(&hash[..2]).join(hash)
+}
+
+// splits a file into content defined chunks, storing the ones not stored yet. returns the hashes of
+// its chunks in order and its size
+fn store_blocks(blocks_dir: &Path, path: &Path) -> io::Result<(Vec<String>, u64)> {
+	let file = BufReader::new(File::open(path)?);
+	let mut hashes = vec![];
+	let mut size = 0;
+
+	for chunk in StreamCDC::new(file, CHUNK_MIN_SIZE, CHUNK_AVG_SIZE, CHUNK_MAX_SIZE) {
+		let chunk = chunk.map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+		let hash = blake3::hash(&chunk.data).to_hex().to_string();
+		size += chunk.length as u64;
+
+		let block = block_path(blocks_dir, &hash);
+		if !block.exists() {
+			fs::create_dir_all(block.parent().unwrap_or(blocks_dir))?;
+			// written aside first, so a block is never left half written
+			let partial = block.with_extension("partial");
+			fs::write(&partial, &chunk.data)?;
+			fs::rename(&partial, &block)?;
+		}
+
+		hashes.push(hash);
+	}
+
+	Ok((hashes, size))
+}
+
+// joins the blocks of a snapshot back into a file, replacing the target once it is complete
+fn write_blocks(blocks_dir: &Path, chunks: &str, target: &Path) -> io::Result<()> {
+	let partial = target.with_file_name(format!(
+		".{}.sd-restore",
+		target.file_name().unwrap_or_default().to_string_lossy()
+	));
+	if let Some(parent) = target.parent() {
+		fs::create_dir_all(parent)?;
+	}
+
+	let result = (|| {
+		let mut file = File::create(&partial)?;
+		for hash in chunks.split(',').filter(|hash| !hash.is_empty()) {
+			file.write_all(&fs::read(block_path(blocks_dir, hash))?)?;
+		}
+		file.sync_all()?;
+		fs::rename(&partial, target)
+	})();
+	if result.is_err() {
+		fs::remove_file(&partial).ok();
+	}
+
+	result
+}
+
+// when the file was last modified on disk, if it can be read
+fn modified(path: &Path) -> Option<DateTime<Utc>> {
+	fs::metadata(path)
+		.and_then(|metadata| metadata.modified())
+		.ok()
+		.map(Into::into)
+}
+
+// snapshots a file unless its latest version is already up to date. returns whether a version was
+// taken
+async fn take_snapshot(
+	ctx: &LibraryContext,
+	location: &LocationResource,
+	file_path: &file_path::Data,
+) -> Result<bool, FileError> {
+	let path = location
+		.path
+		.as_ref()
+		.ok_or(FileError::LocationUnavailable(location.id))?
+		.join(&file_path.materialized_path);
+	let date_modified = match modified(&path) {
+		Some(date_modified) => date_modified,
+		// removed since, nothing to snapshot
+		None => return Ok(false),
+	};
+
+	let latest = ctx
+		.db
+		.file_snapshot()
+		.find_many(vec![file_snapshot::file_path_id::equals(file_path.id)])
+		.order_by(file_snapshot::id::order(Direction::Desc))
+		.take(1)
+		.exec()
+		.await?;
+	if let Some(latest) = latest.first() {
+		if DateTime::<Utc>::from(latest.date_modified) >= date_modified {
+			return Ok(false);
+		}
+	}
+
+	let blocks_dir = blocks_dir(ctx, location.id);
+	let (hashes, size) = spawn_blocking(move || store_blocks(&blocks_dir, &path))
+		.await
+		.map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+	ctx.db
+		.file_snapshot()
+		.create(
+			file_snapshot::location::link(location::id::equals(location.id)),
+			file_snapshot::file_path_id::set(file_path.id),
+			file_snapshot::materialized_path::set(file_path.materialized_path.clone()),
+			file_snapshot::chunks::set(hashes.join(",")),
+			file_snapshot::size_in_bytes::set(size.to_string()),
+			file_snapshot::date_modified::set(date_modified.into()),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	Ok(true)
+}
+
+// the files of a location modified since their latest version, or without any version yet
+async fn changed_file_paths(
+	ctx: &LibraryContext,
+	location: &LocationResource,
+) -> Result<Vec<i32>, FileError> {
+	let location_path = location
+		.path
+		.clone()
+		.ok_or(FileError::LocationUnavailable(location.id))?;
+
+	let mut latest = HashMap::new();
+	for snapshot in ctx
+		.db
+		.file_snapshot()
+		.find_many(vec![file_snapshot::location_id::equals(location.id)])
+		.exec()
+		.await?
+	{
+		let date_modified = DateTime::<Utc>::from(snapshot.date_modified);
+		let entry = latest.entry(snapshot.file_path_id).or_insert(date_modified);
+		if *entry < date_modified {
+			*entry = date_modified;
+		}
+	}
+
+	let file_paths = ctx
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location.id)),
+			file_path::is_dir::equals(false),
+		])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| (file_path.id, file_path.materialized_path))
+		.collect::<Vec<_>>();
+
+	Ok(spawn_blocking(move || {
+		file_paths
+			.into_iter()
+			.filter(|(id, materialized_path)| {
+				match (
+					modified(&location_path.join(materialized_path)),
+					latest.get(id),
+				) {
+					(Some(date_modified), Some(latest)) => date_modified > *latest,
+					(Some(_), None) => true,
+					(None, _) => false,
+				}
+			})
+			.map(|(id, _)| id)
+			.collect()
+	})
+	.await
+	.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?)
+}
+
+// removes the versions past the policy of a location, then the blocks no version uses anymore
+async fn prune(
+	ctx: &LibraryContext,
+	location_id: i32,
+	policy: VersioningPolicy,
+) -> Result<(), FileError> {
+	let snapshots = ctx
+		.db
+		.file_snapshot()
+		.find_many(vec![file_snapshot::location_id::equals(location_id)])
+		.order_by(file_snapshot::id::order(Direction::Asc))
+		.exec()
+		.await?;
+
+	let mut pruned = HashSet::new();
+	let mut counts = HashMap::new();
+	let mut latest = HashSet::new();
+	for snapshot in snapshots.iter().rev() {
+		let count = counts.entry(snapshot.file_path_id).or_insert(0);
+		*count += 1;
+		if *count == 1 {
+			latest.insert(snapshot.id);
+		}
+		if matches!(policy.max_versions, Some(max) if *count > max.max(1)) {
+			pruned.insert(snapshot.id);
+		}
+	}
+
+	let mut references = HashMap::<&str, usize>::new();
+	for snapshot in snapshots.iter().filter(|s| !pruned.contains(&s.id)) {
+		for hash in snapshot.chunks.split(',').filter(|hash| !hash.is_empty()) {
+			*references.entry(hash).or_default() += 1;
+		}
+	}
+
+	let blocks_dir = blocks_dir(ctx, location_id);
+	if let Some(quota) = policy.quota_bytes {
+		let block_size = |hash: &str| {
+			fs::metadata(block_path(&blocks_dir, hash)).map_or(0, |metadata| metadata.len())
+		};
+		let mut used: u64 = references.keys().map(|hash| block_size(hash)).sum();
+
+		// oldest first, keeping the latest version of every file
+		for snapshot in &snapshots {
+			if used <= quota.max(0) as u64 {
+				break;
+			}
+			if pruned.contains(&snapshot.id) || latest.contains(&snapshot.id) {
+				continue;
+			}
+
+			pruned.insert(snapshot.id);
+			for hash in snapshot.chunks.split(',').filter(|hash| !hash.is_empty()) {
+				if let Some(count) = references.get_mut(hash) {
+					*count -= 1;
+					if *count == 0 {
+						used -= block_size(hash);
+					}
+				}
+			}
+		}
+	}
+
+	if pruned.is_empty() {
+		return Ok(());
+	}
+
+	ctx.db
+		.file_snapshot()
+		.find_many(vec![file_snapshot::id::in_vec(
+			pruned.iter().copied().collect(),
+		)])
+		.delete()
+		.exec()
+		.await?;
+
+	let referenced = references
+		.into_iter()
+		.filter(|(_, count)| *count > 0)
+		.map(|(hash, _)| hash.to_string())
+		.collect::<HashSet<_>>();
+	spawn_blocking(move || {
+		for entry in WalkDir::new(&blocks_dir)
+			.into_iter()
+			.filter_map(Result::ok)
+			.filter(|entry| entry.file_type().is_file())
+		{
+			if !referenced.contains(&*entry.file_name().to_string_lossy()) {
+				fs::remove_file(entry.path()).ok();
+			}
+		}
+	})
+	.await
+	.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+	info!(
+		"Pruned {} versions of location {}",
+		pruned.len(),
+		location_id
+	);
+
+	Ok(())
+}
+
+pub struct SnapshotJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SnapshotJobInit {
+	pub location_id: i32,
+	pub file_path_ids: Vec<i32>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for SnapshotJob {
+	type Init = SnapshotJobInit;
+	type Data = ();
+	type Step = i32;
+
+	fn name(&self) -> &'static str {
+		SNAPSHOT_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		state.steps = state.init.file_path_ids.iter().copied().collect();
+
+		info!(
+			"Snapshotting {} files of location {}",
+			state.steps.len(),
+			state.init.location_id
+		);
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+		state.data = Some(());
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let location = get_location(&library_ctx, state.init.location_id).await?;
+
+		if let Some(file_path) = library_ctx
+			.db
+			.file_path()
+			.find_unique(file_path::id::equals(state.steps[0]))
+			.exec()
+			.await?
+		{
+			// a file which can't be read is snapshotted on the next change instead
+			if let Err(e) = take_snapshot(&library_ctx, &location, &file_path).await {
+				error!(
+					"Failed to snapshot {}: {:#?}",
+					file_path.materialized_path, e
+				);
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		let library_ctx = ctx.library_ctx();
+		let location = get_location(&library_ctx, state.init.location_id).await?;
+		if let Some(policy) = location.versioning {
+			prune(&library_ctx, location.id, policy).await?;
+		}
+
+		Ok(())
+	}
+}
+
+// snapshots the files of versioned locations once they change
+pub async fn watch_versioned_locations(library_manager: Arc<LibraryManager>) {
+	loop {
+		for ctx in library_manager.get_all_libraries_ctx().await {
+			let locations = match ctx
+				.db
+				.location()
+				.find_many(vec![location::versioning_enabled::equals(true)])
+				.exec()
+				.await
+			{
+				Ok(locations) => locations,
+				Err(e) => {
+					error!("Failed to read versioned locations: {:#?}", e);
+					continue;
+				}
+			};
+
+			for location in locations {
+				let location = match get_location(&ctx, location.id).await {
+					Ok(location) if location.path.is_some() && location.is_online => location,
+					_ => continue,
+				};
+
+				match changed_file_paths(&ctx, &location).await {
+					Ok(file_path_ids) if !file_path_ids.is_empty() => {
+						ctx.spawn_job(Job::new(
+							SnapshotJobInit {
+								location_id: location.id,
+								file_path_ids,
+							},
+							Box::new(SnapshotJob {}),
+						))
+						.await;
+					}
+					Ok(_) => {}
+					Err(e) => error!(
+						"Failed to look for changes in location {}: {:#?}",
+						location.id, e
+					),
+				}
+			}
+		}
+
+		tokio::time::sleep(VERSION_CHECK_INTERVAL).await;
+	}
+}
@@ -0,0 +1,232 @@
+use crate::{
+	history::HistoryError, library::LibraryContext, prisma::audit_entry, ClientQuery, CoreEvent,
+	LibraryCommand, LibraryQuery,
+};
+use chrono::{DateTime, Utc};
+use int_enum::IntEnum;
+use log::error;
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+// What an audited command or job did to the entries of the library
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum AuditOperation {
+	Delete = 0,
+	Trash = 1,
+	Restore = 2,
+	Move = 3,
+	Rename = 4,
+	Tag = 5,
+	// undoing or redoing the latest operation of the history
+	Revert = 6,
+	// overwritten on disk before being deleted, see file::shred
+	SecureDelete = 7,
+	// written by a newer version, with an operation this one doesn't know
+	Unknown = -1,
+}
+
+impl AuditOperation {
+	// the commands which delete, move, rename or retag entries, the others aren't audited
+	fn of(command: &LibraryCommand) -> Option<Self> {
+		Some(match command {
+			LibraryCommand::FileDelete { .. }
+			| LibraryCommand::TrashEmpty
+			| LibraryCommand::TagDelete { .. }
+			| LibraryCommand::CustomFieldDelete { .. }
+			| LibraryCommand::NoteDelete { .. }
+			| LibraryCommand::LocDelete { .. }
+			| LibraryCommand::ResolveDuplicateGroup { .. }
+			| LibraryCommand::EphemeralDelete { .. } => Self::Delete,
+			LibraryCommand::FilePathTrash { .. } => Self::Trash,
+			LibraryCommand::TrashRestore { .. } | LibraryCommand::FileSnapshotRestore { .. } => {
+				Self::Restore
+			}
+			LibraryCommand::FilePathMove { .. } => Self::Move,
+			LibraryCommand::FilePathBatchRename { .. }
+			| LibraryCommand::LocRepairNames { .. }
+			| LibraryCommand::EphemeralRename { .. } => Self::Rename,
+			LibraryCommand::TagAssign { .. } | LibraryCommand::TagBulk { .. } => Self::Tag,
+			LibraryCommand::Undo | LibraryCommand::Redo => Self::Revert,
+			LibraryCommand::FilePathSecureDelete { .. } => Self::SecureDelete,
+			_ => return None,
+		})
+	}
+}
+
+// A deletion a job ran on its own, rather than for a command
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(tag = "key", content = "data")]
+#[ts(export)]
+pub enum AuditedJob {
+	// an entry which reached the maximum age of a retention policy
+	RetentionExpiry {
+		policy_id: i32,
+		location_id: Option<i32>,
+		materialized_path: String,
+	},
+	// an entry removed from the trash for good, once it was kept for long enough or the trash was
+	// emptied
+	TrashPurge {
+		location_id: i32,
+		original_path: String,
+	},
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AuditEntry {
+	pub id: i32,
+	// the local id of the node which ran the command
+	pub node_id: i32,
+	pub operation: AuditOperation,
+	// unset for deletions run by jobs, and for commands this version doesn't know, when the entry
+	// was written by a newer one
+	pub command: Option<LibraryCommand>,
+	// set for deletions run by jobs
+	pub job: Option<AuditedJob>,
+	pub date_created: DateTime<Utc>,
+}
+
+impl From<audit_entry::Data> for AuditEntry {
+	fn from(data: audit_entry::Data) -> Self {
+		Self {
+			id: data.id,
+			node_id: data.node_id,
+			operation: AuditOperation::from_int(data.operation).unwrap_or(AuditOperation::Unknown),
+			command: data
+				.command
+				.and_then(|command| serde_json::from_str(&command).ok()),
+			job: data.job.and_then(|job| serde_json::from_str(&job).ok()),
+			date_created: data.date_created.into(),
+		}
+	}
+}
+
+// Narrows the audit log down, every field left unset matches all entries
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AuditFilter {
+	pub node_id: Option<i32>,
+	pub from: Option<DateTime<Utc>>,
+	pub to: Option<DateTime<Utc>>,
+	// empty for every operation
+	#[serde(default)]
+	pub operations: Vec<AuditOperation>,
+}
+
+// An audited command which runs, written to the audit log once it succeeded
+pub(crate) struct PendingAuditEntry {
+	ctx: LibraryContext,
+	operation: AuditOperation,
+	command: String,
+}
+
+pub(crate) fn pending(ctx: &LibraryContext, command: &LibraryCommand) -> Option<PendingAuditEntry> {
+	let operation = AuditOperation::of(command)?;
+
+	match serde_json::to_string(command) {
+		Ok(command) => Some(PendingAuditEntry {
+			ctx: ctx.clone(),
+			operation,
+			command,
+		}),
+		Err(e) => {
+			error!("Failed to encode command for the audit log: {:#?}", e);
+			None
+		}
+	}
+}
+
+impl PendingAuditEntry {
+	pub(crate) async fn record(self) {
+		write(
+			&self.ctx,
+			self.operation,
+			vec![audit_entry::command::set(Some(self.command))],
+		)
+		.await;
+	}
+}
+
+// writes a deletion a job ran to the audit log, the job carries on if it can't be written
+pub(crate) async fn record_job(ctx: &LibraryContext, operation: AuditOperation, job: AuditedJob) {
+	match serde_json::to_string(&job) {
+		Ok(job) => write(ctx, operation, vec![audit_entry::job::set(Some(job))]).await,
+		Err(e) => error!("Failed to encode job for the audit log: {:#?}", e),
+	}
+}
+
+async fn write(
+	ctx: &LibraryContext,
+	operation: AuditOperation,
+	params: Vec<audit_entry::SetParam>,
+) {
+	if let Err(e) = ctx
+		.db
+		.audit_entry()
+		.create(
+			audit_entry::node_id::set(ctx.node_local_id),
+			audit_entry::operation::set(operation.int_value()),
+			params,
+		)
+		.exec()
+		.await
+	{
+		error!("Failed to write to the audit log: {:#?}", e);
+		return;
+	}
+
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetAuditLog {
+			filter: AuditFilter::default(),
+			offset: 0,
+			limit: 0,
+		},
+	}))
+	.await;
+}
+
+// newest first
+pub async fn get_audit_log(
+	ctx: &LibraryContext,
+	filter: AuditFilter,
+	offset: u64,
+	limit: u64,
+) -> Result<Vec<AuditEntry>, HistoryError> {
+	let mut params = vec![];
+	if let Some(node_id) = filter.node_id {
+		params.push(audit_entry::node_id::equals(node_id));
+	}
+	if let Some(from) = filter.from {
+		params.push(audit_entry::date_created::gte(from.into()));
+	}
+	if let Some(to) = filter.to {
+		params.push(audit_entry::date_created::lt(to.into()));
+	}
+	if !filter.operations.is_empty() {
+		params.push(audit_entry::operation::in_vec(
+			filter
+				.operations
+				.iter()
+				.map(|operation| operation.int_value())
+				.collect(),
+		));
+	}
+
+	Ok(ctx
+		.db
+		.audit_entry()
+		.find_many(params)
+		.order_by(audit_entry::id::order(Direction::Desc))
+		.skip(offset as i64)
+		.take(limit as i64)
+		.exec()
+		.await?
+		.into_iter()
+		.map(Into::into)
+		.collect())
+}
@@ -0,0 +1,296 @@
+use crate::{
+	file::{
+		rename::{self, RenamedPath},
+		trash, FileError,
+	},
+	library::LibraryContext,
+	prisma::{self, file_path, history_entry},
+	tag::{
+		bulk::{self, BulkTagAction},
+		TagError,
+	},
+	ClientQuery, CoreError, CoreEvent, CoreResponse, LibraryQuery,
+};
+use log::error;
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ts_rs::TS;
+
+pub mod audit;
+
+// the number of operations which can be undone, older ones are forgotten
+const MAX_HISTORY: usize = 100;
+
+// An operation recorded in the history of a library, holding what is needed to revert it
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(tag = "key", content = "data")]
+#[ts(export)]
+pub enum Operation {
+	// only the files which actually changed, so reverting leaves the others untouched
+	Tag {
+		tag_id: i32,
+		file_ids: Vec<i32>,
+		action: BulkTagAction,
+	},
+	// entries are identified by path, as they get new ids when trashed and restored again
+	Trash {
+		location_id: i32,
+		path: String,
+	},
+	Restore {
+		location_id: i32,
+		path: String,
+	},
+	// in the order they were applied
+	Rename {
+		renames: Vec<RenamedPath>,
+	},
+}
+
+impl Operation {
+	fn inverse(&self) -> Self {
+		match self {
+			Operation::Tag {
+				tag_id,
+				file_ids,
+				action,
+			} => Operation::Tag {
+				tag_id: *tag_id,
+				file_ids: file_ids.clone(),
+				action: match action {
+					BulkTagAction::Assign => BulkTagAction::Remove,
+					BulkTagAction::Remove => BulkTagAction::Assign,
+				},
+			},
+			Operation::Trash { location_id, path } => Operation::Restore {
+				location_id: *location_id,
+				path: path.clone(),
+			},
+			Operation::Restore { location_id, path } => Operation::Trash {
+				location_id: *location_id,
+				path: path.clone(),
+			},
+			Operation::Rename { renames } => Operation::Rename {
+				renames: renames
+					.iter()
+					.rev()
+					.map(|renamed| RenamedPath {
+						file_path_id: renamed.file_path_id,
+						from: renamed.to.clone(),
+						to: renamed.from.clone(),
+					})
+					.collect(),
+			},
+		}
+	}
+
+	// applies the operation, or nothing at all if any of its files changed since it was recorded
+	async fn apply(&self, ctx: &LibraryContext) -> Result<(), HistoryError> {
+		match self {
+			Operation::Tag {
+				tag_id,
+				file_ids,
+				action,
+			} => {
+				let preview =
+					bulk::get_bulk_tag_preview(ctx, *tag_id, file_ids.clone(), *action).await?;
+				if preview.changed_file_ids.len() != file_ids.len() {
+					return Err(HistoryError::Conflict(
+						file_ids
+							.iter()
+							.filter(|id| !preview.changed_file_ids.contains(id))
+							.copied()
+							.collect(),
+					));
+				}
+
+				bulk::apply(ctx, *tag_id, file_ids, *action).await?;
+			}
+			Operation::Trash { location_id, path } => {
+				let file_path = ctx
+					.db
+					.file_path()
+					.find_first(vec![
+						file_path::location_id::equals(Some(*location_id)),
+						file_path::materialized_path::equals(path.clone()),
+					])
+					.exec()
+					.await?
+					.ok_or_else(|| HistoryError::PathConflict(path.clone()))?;
+
+				trash::trash_file_path(ctx, file_path.id).await?;
+			}
+			Operation::Restore { location_id, path } => {
+				let entry = trash::find_trashed(ctx, *location_id, path)
+					.await?
+					.ok_or_else(|| HistoryError::PathConflict(path.clone()))?;
+
+				trash::restore(ctx, entry.id).await?;
+			}
+			Operation::Rename { renames } => rename::rename_all(ctx, renames).await?,
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct HistoryEntry {
+	pub id: i32,
+	pub operation: Operation,
+	// undone entries are the ones which can be redone
+	pub undone: bool,
+	pub date_created: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<history_entry::Data> for HistoryEntry {
+	type Error = HistoryError;
+
+	fn try_from(data: history_entry::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: data.id,
+			operation: serde_json::from_str(&data.operation)?,
+			undone: data.undone,
+			date_created: data.date_created.into(),
+		})
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+	#[error("There is nothing to undo")]
+	NothingToUndo,
+	#[error("There is nothing to redo")]
+	NothingToRedo,
+	#[error("Files changed since the operation, refusing to revert it (file ids: {0:?})")]
+	Conflict(Vec<i32>),
+	#[error("Entry changed since the operation, refusing to revert it (path: {0})")]
+	PathConflict(String),
+	#[error("Invalid operation in history: {0}")]
+	InvalidOperation(#[from] serde_json::Error),
+	#[error("Tag error: {0}")]
+	TagError(#[from] TagError),
+	#[error("File error: {0}")]
+	FileError(#[from] FileError),
+	#[error("Database error")]
+	DatabaseError(#[from] prisma::QueryError),
+}
+
+// records an operation which just happened, dropping the operations which were undone before it
+pub async fn record(ctx: &LibraryContext, operation: Operation) {
+	if let Err(e) = try_record(ctx, operation).await {
+		error!("Failed to record operation in history: {:#?}", e);
+	}
+
+	send_invalidate_query(ctx).await;
+}
+
+async fn try_record(ctx: &LibraryContext, operation: Operation) -> Result<(), HistoryError> {
+	ctx.db
+		.history_entry()
+		.find_many(vec![history_entry::undone::equals(true)])
+		.delete()
+		.exec()
+		.await?;
+
+	ctx.db
+		.history_entry()
+		.create(
+			history_entry::operation::set(serde_json::to_string(&operation)?),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	let forgotten = ctx
+		.db
+		.history_entry()
+		.find_many(vec![])
+		.order_by(history_entry::id::order(Direction::Desc))
+		.skip(MAX_HISTORY as i64)
+		.exec()
+		.await?
+		.into_iter()
+		.map(|entry| entry.id)
+		.collect::<Vec<_>>();
+	if !forgotten.is_empty() {
+		ctx.db
+			.history_entry()
+			.find_many(vec![history_entry::id::in_vec(forgotten)])
+			.delete()
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
+
+pub async fn undo(ctx: LibraryContext) -> Result<CoreResponse, CoreError> {
+	let entry: HistoryEntry = ctx
+		.db
+		.history_entry()
+		.find_first(vec![history_entry::undone::equals(false)])
+		.order_by(history_entry::id::order(Direction::Desc))
+		.exec()
+		.await?
+		.ok_or(HistoryError::NothingToUndo)?
+		.try_into()?;
+
+	entry.operation.inverse().apply(&ctx).await?;
+	set_undone(&ctx, entry.id, true).await?;
+
+	Ok(CoreResponse::Success(()))
+}
+
+pub async fn redo(ctx: LibraryContext) -> Result<CoreResponse, CoreError> {
+	let entry: HistoryEntry = ctx
+		.db
+		.history_entry()
+		.find_first(vec![history_entry::undone::equals(true)])
+		.order_by(history_entry::id::order(Direction::Asc))
+		.exec()
+		.await?
+		.ok_or(HistoryError::NothingToRedo)?
+		.try_into()?;
+
+	entry.operation.apply(&ctx).await?;
+	set_undone(&ctx, entry.id, false).await?;
+
+	Ok(CoreResponse::Success(()))
+}
+
+// newest first
+pub async fn get_history(ctx: &LibraryContext) -> Result<Vec<HistoryEntry>, HistoryError> {
+	ctx.db
+		.history_entry()
+		.find_many(vec![])
+		.order_by(history_entry::id::order(Direction::Desc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(TryInto::try_into)
+		.collect()
+}
+
+async fn set_undone(ctx: &LibraryContext, id: i32, undone: bool) -> Result<(), HistoryError> {
+	ctx.db
+		.history_entry()
+		.find_unique(history_entry::id::equals(id))
+		.update(vec![history_entry::undone::set(undone)])
+		.exec()
+		.await?;
+
+	send_invalidate_query(ctx).await;
+
+	Ok(())
+}
+
+async fn send_invalidate_query(ctx: &LibraryContext) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetHistory,
+	}))
+	.await;
+}
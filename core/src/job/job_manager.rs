@@ -1,21 +1,49 @@
 use crate::{
-	encode::THUMBNAIL_JOB_NAME,
+	backup::{MirrorBackupJob, MIRROR_BACKUP_JOB_NAME},
+	encode::{
+		AudioMetadataJob, ImageMetadataJob, ThumbnailProfilesJob, AUDIO_METADATA_JOB_NAME,
+		IMAGE_METADATA_JOB_NAME, THUMBNAIL_JOB_NAME, THUMBNAIL_PROFILES_JOB_NAME,
+	},
 	file::{
-		cas::IDENTIFIER_JOB_NAME,
+		archive::{CompressJob, ExtractJob, COMPRESS_JOB_NAME, EXTRACT_JOB_NAME},
+		cas::{ChunkHasherJob, CHUNK_HASHER_JOB_NAME, IDENTIFIER_JOB_NAME},
+		copy::{FileCopyJob, FILE_COPY_JOB_NAME},
+		duplicates::{DuplicateFinderJob, DUPLICATE_FINDER_JOB_NAME},
+		import::{
+			ImportMetadataJob, MediaImportJob, IMPORT_METADATA_JOB_NAME, MEDIA_IMPORT_JOB_NAME,
+		},
 		indexer::{IndexerJob, INDEXER_JOB_NAME},
+		rename::{BatchRenameJob, BATCH_RENAME_JOB_NAME},
+		secrets::{SecretsScannerJob, SECRETS_SCANNER_JOB_NAME},
+		shred::{SecureDeleteJob, SECURE_DELETE_JOB_NAME},
+		sizes::{FolderSizesJob, FOLDER_SIZES_JOB_NAME},
+		storage::{StorageBreakdownJob, STORAGE_BREAKDOWN_JOB_NAME},
+		text::{TextExtractorJob, TEXT_EXTRACTOR_JOB_NAME},
+		trash::{TrashPurgeJob, TRASH_PURGE_JOB_NAME},
+		versions::{SnapshotJob, SNAPSHOT_JOB_NAME},
 	},
-	job::{worker::Worker, DynJob, JobError},
-	library::LibraryContext,
+	job::{logs, worker::Worker, DynJob, JobError},
+	library::{LibraryContext, LibraryMaintenanceJob, LIBRARY_MAINTENANCE_JOB_NAME},
 	prisma::{job, node},
+	retention::{RetentionJob, RETENTION_JOB_NAME},
+	share::{ShareJob, SHARE_JOB_NAME},
+	tag::{
+		bulk::{BulkTagJob, BULK_TAG_JOB_NAME},
+		finder::{FinderTagsJob, FINDER_TAGS_JOB_NAME},
+	},
 	FileIdentifierJob, Job, ThumbnailJob,
 };
+use chrono::{DateTime, Utc};
 use int_enum::IntEnum;
 use log::{error, info};
+use prisma_client_rust::{prisma_models::PrismaValue, raw::Raw};
+use prisma_helpers::{decode_cursor, Keyset, Page, SortDirection};
 use serde::{Deserialize, Serialize};
 use std::{
 	collections::{HashMap, VecDeque},
 	fmt::Debug,
 	fmt::{Display, Formatter},
+	path::PathBuf,
 	sync::Arc,
 	time::Duration,
 };
@@ -28,6 +56,9 @@ use uuid::Uuid;
 
 // db is single threaded, nerd
 const MAX_WORKERS: usize = 1;
+const MAX_HISTORY_PAGE_SIZE: u32 = 500;
+// the first page of the job history clients load, the one invalidated whenever a job stops
+pub const JOB_HISTORY_PAGE_SIZE: u32 = 100;
 
 pub enum JobManagerEvent {
 	IngestJob(LibraryContext, Box<dyn DynJob>),
@@ -120,6 +151,27 @@ impl JobManager {
 		ret
 	}
 
+	// returns the running jobs which just stalled, with their library and how long they have been idle
+	pub async fn find_stalled(&self, threshold: Duration) -> Vec<(Uuid, JobReport, Duration)> {
+		let mut ret = vec![];
+
+		for worker in self.running_workers.read().await.values() {
+			let mut worker = worker.lock().await;
+			if let Some((library_id, idle)) = worker.check_stalled(threshold) {
+				ret.push((library_id, worker.report(), idle));
+			}
+		}
+		ret
+	}
+
+	pub async fn queued_count(&self) -> usize {
+		self.job_queue.read().await.len()
+	}
+
+	pub async fn running_count(&self) -> usize {
+		self.running_workers.read().await.len()
+	}
+
 	// pub async fn queue_pending_job(ctx: &LibraryContext) -> Result<(), JobError> {
 	// 	let _next_job = ctx
 	//      .db
@@ -131,15 +183,86 @@ impl JobManager {
 	// 	Ok(())
 	// }
 
-	pub async fn get_history(ctx: &LibraryContext) -> Result<Vec<JobReport>, JobError> {
-		let jobs = ctx
+	// the jobs which aren't running, newest first, a page at a time
+	pub async fn get_history(
+		ctx: &LibraryContext,
+		cursor: Option<String>,
+		limit: u32,
+	) -> Result<JobHistoryPage, JobError> {
+		#[derive(Deserialize)]
+		struct JobRow {
+			// hex encoded, as raw queries don't return bytes
+			id: String,
+			date_created: DateTime<Utc>,
+		}
+
+		let keyset = Keyset::new(&["date_created", "id"], SortDirection::Desc);
+		let key = match cursor {
+			Some(cursor) => {
+				let key = decode_cursor::<JobKey>(&cursor)?;
+				Some(vec![
+					PrismaValue::DateTime(key.date_created.into()),
+					PrismaValue::Bytes(key.id.as_bytes().to_vec()),
+				])
+			}
+			None => None,
+		};
+		let (after, mut values) = keyset.after(key);
+		let limit = limit.min(MAX_HISTORY_PAGE_SIZE);
+		values.insert(0, PrismaValue::Int(JobStatus::Running.int_value() as i64));
+		values.push(PrismaValue::Int(limit as i64 + 1));
+
+		let rows = ctx
+			.db
+			._query_raw::<JobRow>(Raw::new(
+				&format!(
+					"SELECT hex(id) AS id, date_created FROM jobs
+					WHERE status != {{}} AND {}
+					ORDER BY {} LIMIT {{}}",
+					after,
+					keyset.order_by()
+				),
+				values,
+			))
+			.await?
+			.into_iter()
+			.filter_map(|row| {
+				Some(JobKey {
+					id: Uuid::parse_str(&row.id).ok()?,
+					date_created: row.date_created,
+				})
+			})
+			.collect::<Vec<_>>();
+		let page = Page::from_rows(rows, limit as usize, |key| key.clone());
+
+		let mut jobs = ctx
 			.db
 			.job()
-			.find_many(vec![job::status::not(JobStatus::Running.int_value())])
+			.find_many(vec![job::id::in_vec(
+				page.items
+					.iter()
+					.map(|key| key.id.as_bytes().to_vec())
+					.collect(),
+			)])
 			.exec()
-			.await?;
-
-		Ok(jobs.into_iter().map(Into::into).collect())
+			.await?
+			.into_iter()
+			.map(|data| {
+				let mut report = JobReport::from(data);
+				let log_path = logs::log_path(ctx, report.id);
+				report.log_path = log_path.exists().then(|| log_path);
+				(report.id, report)
+			})
+			.collect::<HashMap<_, _>>();
+
+		Ok(JobHistoryPage {
+			jobs: page
+				.items
+				.iter()
+				.filter_map(|key| jobs.remove(&key.id))
+				.collect(),
+			next_cursor: page.next_cursor,
+		})
 	}
 
 	pub fn shutdown_tx(&self) -> Arc<broadcast::Sender<()>> {
@@ -182,6 +305,24 @@ impl JobManager {
 						.ingest(ctx, Job::resume(paused_job, Box::new(ThumbnailJob {}))?)
 						.await;
 				}
+				AUDIO_METADATA_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(AudioMetadataJob {}))?)
+						.await;
+				}
+				THUMBNAIL_PROFILES_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(
+							ctx,
+							Job::resume(paused_job, Box::new(ThumbnailProfilesJob {}))?,
+						)
+						.await;
+				}
+				IMAGE_METADATA_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(ImageMetadataJob {}))?)
+						.await;
+				}
 				INDEXER_JOB_NAME => {
 					Arc::clone(&self)
 						.ingest(ctx, Job::resume(paused_job, Box::new(IndexerJob {}))?)
@@ -195,6 +336,126 @@ impl JobManager {
 						)
 						.await;
 				}
+				CHUNK_HASHER_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(ChunkHasherJob {}))?)
+						.await;
+				}
+				DUPLICATE_FINDER_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(
+							ctx,
+							Job::resume(paused_job, Box::new(DuplicateFinderJob {}))?,
+						)
+						.await;
+				}
+				SECRETS_SCANNER_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(
+							ctx,
+							Job::resume(paused_job, Box::new(SecretsScannerJob {}))?,
+						)
+						.await;
+				}
+				TEXT_EXTRACTOR_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(TextExtractorJob {}))?)
+						.await;
+				}
+				RETENTION_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(RetentionJob {}))?)
+						.await;
+				}
+				BULK_TAG_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(BulkTagJob {}))?)
+						.await;
+				}
+				TRASH_PURGE_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(TrashPurgeJob {}))?)
+						.await;
+				}
+				FILE_COPY_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(FileCopyJob {}))?)
+						.await;
+				}
+				BATCH_RENAME_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(BatchRenameJob {}))?)
+						.await;
+				}
+				COMPRESS_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(CompressJob {}))?)
+						.await;
+				}
+				EXTRACT_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(ExtractJob {}))?)
+						.await;
+				}
+				SNAPSHOT_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(SnapshotJob {}))?)
+						.await;
+				}
+				FOLDER_SIZES_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(FolderSizesJob {}))?)
+						.await;
+				}
+				STORAGE_BREAKDOWN_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(
+							ctx,
+							Job::resume(paused_job, Box::new(StorageBreakdownJob {}))?,
+						)
+						.await;
+				}
+				SHARE_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(ShareJob {}))?)
+						.await;
+				}
+				SECURE_DELETE_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(SecureDeleteJob {}))?)
+						.await;
+				}
+				MIRROR_BACKUP_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(MirrorBackupJob {}))?)
+						.await;
+				}
+				MEDIA_IMPORT_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(MediaImportJob {}))?)
+						.await;
+				}
+				IMPORT_METADATA_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(
+							ctx,
+							Job::resume(paused_job, Box::new(ImportMetadataJob {}))?,
+						)
+						.await;
+				}
+				FINDER_TAGS_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(ctx, Job::resume(paused_job, Box::new(FinderTagsJob {}))?)
+						.await;
+				}
+				LIBRARY_MAINTENANCE_JOB_NAME => {
+					Arc::clone(&self)
+						.ingest(
+							ctx,
+							Job::resume(paused_job, Box::new(LibraryMaintenanceJob {}))?,
+						)
+						.await;
+				}
 				_ => {
 					error!(
 						"Unknown job type: {}, id: {}",
@@ -217,6 +478,20 @@ pub enum JobReportUpdate {
 	SecondsElapsed(u64),
 }
 
+// What a page of the job history resumes after
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JobKey {
+	date_created: DateTime<Utc>,
+	id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct JobHistoryPage {
+	pub jobs: Vec<JobReport>,
+	pub next_cursor: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, TS, Clone)]
 #[ts(export)]
 pub struct JobReport {
@@ -234,9 +509,15 @@ pub struct JobReport {
 	pub completed_task_count: i32,
 
 	pub message: String,
+	// set by the watchdog while a running job makes no progress
+	#[ts(type = "string | null")]
+	pub stalled_since: Option<chrono::DateTime<chrono::Utc>>,
 	// pub percentage_complete: f64,
 	#[ts(type = "string")]
 	pub seconds_elapsed: i32,
+	// what the job logged, see GetJobLogs
+	#[ts(type = "string | null")]
+	pub log_path: Option<PathBuf>,
 }
 
 impl Display for JobReport {
@@ -263,7 +544,9 @@ impl From<job::Data> for JobReport {
 			date_modified: data.date_modified.into(),
 			data: data.data,
 			message: String::new(),
+			stalled_since: None,
 			seconds_elapsed: data.seconds_elapsed,
+			log_path: None,
 		}
 	}
 }
@@ -281,7 +564,9 @@ impl JobReport {
 			data: None,
 			completed_task_count: 0,
 			message: String::new(),
+			stalled_since: None,
 			seconds_elapsed: 0,
+			log_path: None,
 		}
 	}
 
@@ -0,0 +1,255 @@
+use crate::{job::JobError, library::LibraryContext};
+use chrono::{DateTime, Utc};
+use log::{error, Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::{
+	fs::{self, File, OpenOptions},
+	future::Future,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime},
+};
+use ts_rs::TS;
+use uuid::Uuid;
+
+// the log of a job is rotated once it grows past this, keeping the previous one
+const MAX_JOB_LOG_BYTES: u64 = 10 * 1024 * 1024;
+// logs of jobs which haven't run for this long are removed when the node starts
+const JOB_LOG_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// the failures to write the log of a job are logged with this target, and aren't written to the
+// log of the job themselves
+const JOB_LOG_FAILURE_TARGET: &str = "sd_core::job::logs::failure";
+
+tokio::task_local! {
+	// the log of the job running on the current task
+	static JOB_LOG: Option<Arc<JobLogFile>>;
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, PartialEq, Eq, PartialOrd, Ord)]
+#[ts(export)]
+pub enum JobLogLevel {
+	Error,
+	Warn,
+	Info,
+	Debug,
+	Trace,
+}
+
+impl From<Level> for JobLogLevel {
+	fn from(level: Level) -> Self {
+		match level {
+			Level::Error => Self::Error,
+			Level::Warn => Self::Warn,
+			Level::Info => Self::Info,
+			Level::Debug => Self::Debug,
+			Level::Trace => Self::Trace,
+		}
+	}
+}
+
+// A record logged while a job was running, the logs of jobs are json lines of these
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct JobLogLine {
+	pub date: DateTime<Utc>,
+	pub level: JobLogLevel,
+	// the module which logged the record
+	pub target: String,
+	pub message: String,
+}
+
+struct JobLogFile {
+	path: PathBuf,
+	// the open log, and how many bytes it holds
+	file: Mutex<(File, u64)>,
+}
+
+impl JobLogFile {
+	fn open(path: PathBuf) -> io::Result<Self> {
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		let file = OpenOptions::new().create(true).append(true).open(&path)?;
+		let len = file.metadata()?.len();
+
+		Ok(Self {
+			path,
+			file: Mutex::new((file, len)),
+		})
+	}
+
+	fn write(&self, record: &Record) -> io::Result<()> {
+		let mut line = serde_json::to_vec(&JobLogLine {
+			date: Utc::now(),
+			level: record.level().into(),
+			target: record.target().to_string(),
+			message: record.args().to_string(),
+		})?;
+		line.push(b'\n');
+
+		let mut file = self.file.lock().unwrap();
+		if file.1 + line.len() as u64 > MAX_JOB_LOG_BYTES {
+			fs::rename(&self.path, rotated_path(&self.path))?;
+			*file = (
+				OpenOptions::new()
+					.create(true)
+					.append(true)
+					.open(&self.path)?,
+				0,
+			);
+		}
+		file.0.write_all(&line)?;
+		file.1 += line.len() as u64;
+
+		Ok(())
+	}
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+	path.with_extension("log.1")
+}
+
+pub(crate) fn log_path(ctx: &LibraryContext, job_id: Uuid) -> PathBuf {
+	ctx.config()
+		.data_directory()
+		.join("logs")
+		.join(ctx.id.to_string())
+		.join(job_id.to_string())
+		.with_extension("log")
+}
+
+// runs a job, writing what it logs from its task to its log. Records logged from blocking
+// threads it spawns aren't captured.
+pub(crate) async fn scope<F: Future>(path: PathBuf, job: F) -> F::Output {
+	let log = match JobLogFile::open(path) {
+		Ok(log) => Some(Arc::new(log)),
+		Err(e) => {
+			error!("Failed to open job log: {:#?}", e);
+			None
+		}
+	};
+
+	JOB_LOG.scope(log, job).await
+}
+
+fn in_job() -> bool {
+	JOB_LOG.try_with(Option::is_some).unwrap_or(false)
+}
+
+// Forwards records to another logger, and also writes those logged by running jobs to their log
+struct JobLogger<L: Log> {
+	inner: L,
+}
+
+impl<L: Log> Log for JobLogger<L> {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		self.inner.enabled(metadata) || (metadata.level() <= Level::Info && in_job())
+	}
+
+	fn log(&self, record: &Record) {
+		if self.inner.enabled(record.metadata()) {
+			self.inner.log(record);
+		}
+
+		if record.level() <= Level::Info && record.target() != JOB_LOG_FAILURE_TARGET {
+			JOB_LOG
+				.try_with(|log| {
+					if let Some(log) = log {
+						if let Err(e) = log.write(record) {
+							error!(
+								target: JOB_LOG_FAILURE_TARGET,
+								"Failed to write job log: {:?}", e
+							);
+						}
+					}
+				})
+				.ok();
+		}
+	}
+
+	fn flush(&self) {
+		self.inner.flush();
+	}
+}
+
+/// init_logger installs env_logger, configured from `RUST_LOG`, as the logger of the process. What jobs log at the info level and above is also kept in a log per job, see GetJobLogs.
+pub fn init_logger() {
+	let inner = env_logger::Builder::from_default_env().build();
+	let max_level = inner.filter().max(LevelFilter::Info);
+
+	if log::set_boxed_logger(Box::new(JobLogger { inner })).is_ok() {
+		log::set_max_level(max_level);
+	}
+}
+
+// the last `tail` lines of the log of a job, those at `level` or more severe
+pub async fn get_job_logs(
+	ctx: &LibraryContext,
+	job_id: Uuid,
+	tail: Option<u64>,
+	level: Option<JobLogLevel>,
+) -> Result<Vec<JobLogLine>, JobError> {
+	let path = log_path(ctx, job_id);
+	let level = level.unwrap_or(JobLogLevel::Trace);
+
+	let mut lines = vec![];
+	for path in [rotated_path(&path), path] {
+		let contents = match tokio::fs::read_to_string(&path).await {
+			Ok(contents) => contents,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(e.into()),
+		};
+
+		lines.extend(
+			contents
+				.lines()
+				// the last line is partial while the job writes it
+				.filter_map(|line| serde_json::from_str::<JobLogLine>(line).ok())
+				.filter(|line| line.level <= level),
+		);
+	}
+
+	if let Some(tail) = tail {
+		let skip = lines.len().saturating_sub(tail as usize);
+		lines.drain(..skip);
+	}
+
+	Ok(lines)
+}
+
+// removes the logs of jobs which last ran long ago, with the libraries left without logs
+pub async fn prune_logs(data_dir: PathBuf) {
+	let pruned = tokio::task::spawn_blocking(move || -> io::Result<()> {
+		let logs_dir = data_dir.join("logs");
+		if !logs_dir.exists() {
+			return Ok(());
+		}
+
+		for library_dir in fs::read_dir(logs_dir)? {
+			let library_dir = library_dir?.path();
+			for log in fs::read_dir(&library_dir)? {
+				let log = log?;
+				let modified = log.metadata()?.modified()?;
+				if SystemTime::now()
+					.duration_since(modified)
+					.map_or(false, |age| age > JOB_LOG_MAX_AGE)
+				{
+					fs::remove_file(log.path())?;
+				}
+			}
+			// only removed once empty
+			fs::remove_dir(&library_dir).ok();
+		}
+
+		Ok(())
+	})
+	.await;
+
+	match pruned {
+		Ok(Err(e)) => error!("Failed to prune job logs: {:#?}", e),
+		Err(e) => error!("Failed to prune job logs: {:#?}", e),
+		Ok(Ok(())) => {}
+	}
+}
@@ -1,4 +1,6 @@
-use crate::{file::FileError, prisma, sys::SysError};
+use crate::{
+	backup::BackupError, file::FileError, prisma, share::ShareError, sys::SysError, tag::TagError,
+};
 use rmp_serde::{decode::Error as DecodeError, encode::Error as EncodeError};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{collections::VecDeque, fmt::Debug};
@@ -6,9 +8,13 @@ use thiserror::Error;
 use uuid::Uuid;
 
 mod job_manager;
+mod logs;
+mod watchdog;
 mod worker;
 
 pub use job_manager::*;
+pub use logs::*;
+pub use watchdog::*;
 pub use worker::*;
 
 #[derive(Error, Debug)]
@@ -23,10 +29,18 @@ pub enum JobError {
 	JoinError(#[from] tokio::task::JoinError),
 	#[error("File error: {0}")]
 	FileError(#[from] FileError),
+	#[error("Tag error: {0}")]
+	TagError(#[from] TagError),
+	#[error("Share error: {0}")]
+	ShareError(#[from] ShareError),
+	#[error("Backup error: {0}")]
+	BackupError(#[from] BackupError),
 	#[error("Job state encode error: {0}")]
 	StateEncode(#[from] EncodeError),
 	#[error("Job state decode error: {0}")]
 	StateDecode(#[from] DecodeError),
+	#[error("{0}")]
+	InvalidCursor(#[from] prisma_helpers::InvalidCursor),
 	#[error("Tried to resume a job with unknown name: job <name='{1}', uuid='{0}'>")]
 	UnknownJobName(Uuid, String),
 	#[error(
@@ -166,6 +180,7 @@ where
 				) => {
 					step_result?;
 					self.state.steps.pop_front();
+					ctx.heartbeat();
 				}
 				_ = &mut shutdown_rx_fut => {
 					return Err(
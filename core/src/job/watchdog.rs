@@ -0,0 +1,75 @@
+use crate::{
+	job::{JobManager, JobReport},
+	CoreEvent, NodeContext, EVENT_CHANNEL_CAPACITY,
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::time::{sleep_until, Instant};
+use ts_rs::TS;
+use uuid::Uuid;
+
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(30);
+// a job making no progress for this long is reported as stalled
+const JOB_STALL_THRESHOLD: Duration = Duration::from_secs(5 * 60);
+// the watchdog waking up this late means the runtime is blocked
+const EVENT_LOOP_LAG_THRESHOLD: Duration = Duration::from_secs(1);
+// events queued up to this share of the channel means clients aren't keeping up
+const EVENT_BACKLOG_THRESHOLD: usize = EVENT_CHANNEL_CAPACITY * 3 / 4;
+
+// Something the watchdog found wrong, so clients can show it rather than spinning forever
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(tag = "key", content = "data")]
+#[ts(export)]
+pub enum WatchdogWarning {
+	JobStalled {
+		library_id: Uuid,
+		// the report holds the task counts and the last message of the job
+		job: JobReport,
+		idle_seconds: u64,
+		// jobs waiting behind the stalled one
+		queued_jobs: usize,
+	},
+	EventLoopLagging {
+		lag_ms: u64,
+	},
+	EventBacklog {
+		queued_events: usize,
+	},
+}
+
+pub async fn watch_liveness(jobs: Arc<JobManager>, node_ctx: NodeContext) {
+	loop {
+		let scheduled = Instant::now() + WATCHDOG_INTERVAL;
+		sleep_until(scheduled).await;
+
+		let mut warnings = vec![];
+
+		let lag = Instant::now().saturating_duration_since(scheduled);
+		if lag > EVENT_LOOP_LAG_THRESHOLD {
+			warnings.push(WatchdogWarning::EventLoopLagging {
+				lag_ms: lag.as_millis() as u64,
+			});
+		}
+
+		let queued_events = EVENT_CHANNEL_CAPACITY - node_ctx.event_sender.capacity();
+		if queued_events > EVENT_BACKLOG_THRESHOLD {
+			warnings.push(WatchdogWarning::EventBacklog { queued_events });
+		}
+
+		let queued_jobs = jobs.queued_count().await;
+		for (library_id, job, idle) in jobs.find_stalled(JOB_STALL_THRESHOLD).await {
+			warnings.push(WatchdogWarning::JobStalled {
+				library_id,
+				job,
+				idle_seconds: idle.as_secs(),
+				queued_jobs,
+			});
+		}
+
+		for warning in warnings {
+			warn!("Watchdog: {:?}", warning);
+			node_ctx.emit(CoreEvent::WatchdogWarning(warning)).await;
+		}
+	}
+}
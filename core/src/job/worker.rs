@@ -1,7 +1,7 @@
 use crate::{
-	job::{DynJob, JobError, JobManager, JobReportUpdate, JobStatus},
+	job::{logs, DynJob, JobError, JobManager, JobReportUpdate, JobStatus, JOB_HISTORY_PAGE_SIZE},
 	library::LibraryContext,
-	ClientQuery, CoreEvent, JobReport, LibraryQuery,
+	search, ClientQuery, CoreEvent, JobReport, LibraryQuery,
 };
 use log::{error, info, warn};
 use std::{sync::Arc, time::Duration};
@@ -13,11 +13,14 @@ use tokio::{
 	},
 	time::{interval_at, Instant},
 };
+use uuid::Uuid;
 
 // used to update the worker state from inside the worker thread
 #[derive(Debug)]
 pub enum WorkerEvent {
 	Progressed(Vec<JobReportUpdate>),
+	// a step finished, even if the job doesn't report its progress
+	Heartbeat,
 	Completed,
 	Failed,
 	Paused(Vec<u8>),
@@ -25,6 +28,7 @@ pub enum WorkerEvent {
 
 #[derive(Clone)]
 pub struct WorkerContext {
+	job_id: Uuid,
 	library_ctx: LibraryContext,
 	events_tx: UnboundedSender<WorkerEvent>,
 	shutdown_tx: Arc<broadcast::Sender<()>>,
@@ -37,10 +41,20 @@ impl WorkerContext {
 			.expect("critical error: failed to send worker worker progress event updates");
 	}
 
+	pub fn heartbeat(&self) {
+		self.events_tx
+			.send(WorkerEvent::Heartbeat)
+			.expect("critical error: failed to send worker heartbeat event");
+	}
+
 	pub fn library_ctx(&self) -> LibraryContext {
 		self.library_ctx.clone()
 	}
 
+	pub fn job_id(&self) -> Uuid {
+		self.job_id
+	}
+
 	pub fn shutdown_rx(&self) -> broadcast::Receiver<()> {
 		self.shutdown_tx.subscribe()
 	}
@@ -51,6 +65,9 @@ impl WorkerContext {
 pub struct Worker {
 	job: Option<Box<dyn DynJob>>,
 	report: JobReport,
+	library_id: Option<Uuid>,
+	// when the job last reported progress, used by the watchdog to find stalled jobs
+	last_progress: Instant,
 	worker_events_tx: UnboundedSender<WorkerEvent>,
 	worker_events_rx: Option<UnboundedReceiver<WorkerEvent>>,
 }
@@ -62,6 +79,8 @@ impl Worker {
 		Self {
 			job: Some(job),
 			report,
+			library_id: None,
+			last_progress: Instant::now(),
 			worker_events_tx,
 			worker_events_rx: Some(worker_events_rx),
 		}
@@ -70,6 +89,21 @@ impl Worker {
 	pub fn report(&self) -> JobReport {
 		self.report.clone()
 	}
+
+	// marks the job as stalled if it made no progress for longer than `threshold`, returning its
+	// library and how long it has been idle the first time it is found stalled
+	pub(crate) fn check_stalled(&mut self, threshold: Duration) -> Option<(Uuid, Duration)> {
+		let idle = self.last_progress.elapsed();
+		if self.report.status != JobStatus::Running
+			|| self.report.stalled_since.is_some()
+			|| idle < threshold
+		{
+			return None;
+		}
+
+		self.report.stalled_since = Some(chrono::Utc::now());
+		self.library_id.map(|library_id| (library_id, idle))
+	}
 	// spawns a thread and extracts channel sender to communicate with it
 	pub async fn spawn(
 		job_manager: Arc<JobManager>,
@@ -92,6 +126,10 @@ impl Worker {
 		let job_id = worker.report.id;
 		let old_status = worker.report.status;
 		worker.report.status = JobStatus::Running;
+		worker.library_id = Some(ctx.id);
+		worker.last_progress = Instant::now();
+		let log_path = logs::log_path(&ctx, job_id);
+		worker.report.log_path = Some(log_path.clone());
 		if matches!(old_status, JobStatus::Queued) {
 			worker.report.create(&ctx).await.unwrap_or(());
 		}
@@ -108,6 +146,7 @@ impl Worker {
 		// spawn task to handle running the job
 		tokio::spawn(async move {
 			let worker_ctx = WorkerContext {
+				job_id,
 				library_ctx,
 				events_tx: worker_events_tx,
 				shutdown_tx: job_manager.shutdown_tx(),
@@ -133,7 +172,7 @@ impl Worker {
 				}
 			});
 
-			if let Err(e) = job.run(worker_ctx.clone()).await {
+			if let Err(e) = logs::scope(log_path, job.run(worker_ctx.clone())).await {
 				if let JobError::Paused(state) = e {
 					worker_ctx
 						.events_tx
@@ -173,6 +212,10 @@ impl Worker {
 						continue;
 					};
 					for change in changes {
+						if !matches!(change, JobReportUpdate::SecondsElapsed(_)) {
+							worker.last_progress = Instant::now();
+							worker.report.stalled_since = None;
+						}
 						match change {
 							JobReportUpdate::TaskCount(task_count) => {
 								worker.report.task_count = task_count as i32;
@@ -196,6 +239,16 @@ impl Worker {
 					))
 					.await;
 				}
+				WorkerEvent::Heartbeat => {
+					worker.last_progress = Instant::now();
+					if worker.report.stalled_since.take().is_some() {
+						ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+							library_id: ctx.id,
+							query: LibraryQuery::GetRunningJobs,
+						}))
+						.await;
+					}
+				}
 				WorkerEvent::Completed => {
 					worker.report.status = JobStatus::Completed;
 					worker.report.data = None;
@@ -204,6 +257,7 @@ impl Worker {
 						.update(&ctx)
 						.await
 						.expect("critical error: failed to update job report");
+					ctx.metrics().record_job(ctx.id, worker.report.status);
 
 					ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
 						library_id: ctx.id,
@@ -211,13 +265,12 @@ impl Worker {
 					}))
 					.await;
 
-					ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
-						library_id: ctx.id,
-						query: LibraryQuery::GetJobHistory,
-					}))
-					.await;
+					invalidate_job_history(&ctx).await;
 					info!("{}", worker.report);
 
+					// jobs are what add, remove and update entries in bulk
+					search::refresh_subscriptions(&ctx).await;
+
 					break;
 				}
 				WorkerEvent::Failed => {
@@ -228,12 +281,9 @@ impl Worker {
 						.update(&ctx)
 						.await
 						.expect("critical error: failed to update job report");
+					ctx.metrics().record_job(ctx.id, worker.report.status);
 
-					ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
-						library_id: ctx.id,
-						query: LibraryQuery::GetJobHistory,
-					}))
-					.await;
+					invalidate_job_history(&ctx).await;
 					warn!("{}", worker.report);
 
 					break;
@@ -246,13 +296,10 @@ impl Worker {
 						.update(&ctx)
 						.await
 						.expect("critical error: failed to update job report");
+					ctx.metrics().record_job(ctx.id, worker.report.status);
 					info!("{}", worker.report);
 
-					ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
-						library_id: ctx.id,
-						query: LibraryQuery::GetJobHistory,
-					}))
-					.await;
+					invalidate_job_history(&ctx).await;
 
 					break;
 				}
@@ -260,3 +307,15 @@ impl Worker {
 		}
 	}
 }
+
+// clients key queries by their params, so the history is invalidated with those it is loaded with
+async fn invalidate_job_history(ctx: &LibraryContext) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetJobHistory {
+			cursor: None,
+			limit: JOB_HISTORY_PAGE_SIZE,
+		},
+	}))
+	.await;
+}
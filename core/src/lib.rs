@@ -1,18 +1,24 @@
 use crate::{
 	encode::{ThumbnailJob, ThumbnailJobInit},
-	file::cas::{FileIdentifierJob, FileIdentifierJobInit},
+	file::{
+		cas::{FileIdentifierJob, FileIdentifierJobInit},
+		duplicates::{self, DuplicateFinderJob, DuplicateFinderJobInit},
+	},
 	job::{Job, JobManager, JobReport},
-	library::{LibraryConfig, LibraryConfigWrapped, LibraryManager},
-	node::{NodeConfig, NodeConfigManager},
+	library::{EditLibraryArgs, LibraryConfig, LibraryConfigWrapped, LibraryManager},
+	node::{Metrics, NodeConfig, NodeConfigManager, UsageManager, ViewStateManager},
 	prisma::file as prisma_file,
 	prisma::location,
+	retention::{RetentionJob, RetentionJobInit},
 	tag::{Tag, TagWithFiles},
 };
+use int_enum::IntEnum;
 use log::{error, info};
 use serde::{Deserialize, Serialize};
 use std::{
 	path::{Path, PathBuf},
 	sync::Arc,
+	time::Instant,
 };
 use thiserror::Error;
 use tokio::{
@@ -25,16 +31,103 @@ use tokio::{
 use ts_rs::TS;
 use uuid::Uuid;
 
+mod backup;
+mod custom_field;
 mod encode;
 mod file;
+mod history;
 mod job;
 mod library;
 mod node;
+mod places;
 mod prisma;
+mod retention;
+mod search;
+mod share;
 mod sys;
 mod tag;
 mod util;
 
+// used by the server to stream directories to web clients
+pub use file::archive::{stream_archive_entry, stream_tar};
+pub use share::stream_share_bundle;
+// apps install it so jobs keep what they log
+pub use job::init_logger;
+
+// the types carried by the commands, queries, responses and events of the node, for crates
+// embedding core. The modules stay private so their internals can change between releases.
+pub mod api {
+	pub use crate::backup::{
+		ArchivedEntry, BackupError, BackupManifest, BackupPlan, BackupTarget, BackupVerification,
+		DeletionPolicy, ManifestEntry,
+	};
+	pub use crate::custom_field::{
+		CustomField, CustomFieldError, CustomFieldKind, CustomFieldOnFile, CustomFieldValue,
+	};
+	pub use crate::encode::{PreviewError, ThumbnailFormat, ThumbnailProfile, ThumbstripLayout};
+	pub use crate::file::{
+		archive::{ArchiveContents, ArchiveEntry, ArchiveFormat, ArchivePreview},
+		attributes::{AttributeKind, AttributeLoss},
+		copy::{ConflictOutcome, ConflictPolicy, ConflictResolution},
+		duplicates::{
+			DuplicateFilePath, DuplicateGroup, DuplicateKind, DuplicateResolution, SimilarImage,
+		},
+		ephemeral::{EphemeralDirectory, EphemeralEntry},
+		explorer::{DirectoryPage, DirectorySort, DirectorySortBy},
+		import::{ImportPreview, ImportSource, ImportedMedia},
+		links::{FileLink, FileLinkKind},
+		metadata::{FileMetadataResult, FileMetadataUpdate, FileVersion},
+		names::{NameIssue, NameRepair, NameRepairPreview, NormalizationForm},
+		notes::{Note, NoteChange},
+		preflight::{CopyIssue, CopyIssueKind, CopyPreflight, InvalidNamePolicy},
+		recents::{FileAccessKind, QuickAccessFile},
+		rename::{BatchRenamePreview, RenamePattern, RenamedPath},
+		secrets::SecretKind,
+		shred::{SecureDeletePreview, ShredCaveat},
+		storage::{
+			CategoryStorage, ExtensionStorage, LocationStorage, StorageBreakdown, StorageCategory,
+			StorageTreemap, TreemapNode,
+		},
+		text::FullTextSearchResult,
+		trash::TrashedEntry,
+		versions::{FileSnapshot, VersioningPolicy},
+		DirectoryWithContents, File, FileError, FileKind, FilePath, MediaData,
+	};
+	pub use crate::history::{
+		audit::{AuditEntry, AuditFilter, AuditOperation, AuditedJob},
+		HistoryEntry, HistoryError, Operation,
+	};
+	pub use crate::job::{
+		JobError, JobHistoryPage, JobLogLevel, JobLogLine, JobReport, JobStatus, WatchdogWarning,
+	};
+	pub use crate::library::{
+		DegradedLibrary, LibraryConfig, LibraryConfigWrapped, LibraryError, MaintenanceReport,
+		Statistics,
+	};
+	pub use crate::node::{
+		ConfigMetadata, DailyUsage, DirectoryViewState, ExplorerLayout, ExplorerPath,
+		LibraryMetricsSnapshot, LibraryNode, LibraryViewState, MetricsSnapshot, NodeConfig,
+		NodeConfigError, Platform, UsageCategory,
+	};
+	pub use crate::places::{Place, PlacesError};
+	pub use crate::retention::{RetentionAction, RetentionError, RetentionExpiry, RetentionPolicy};
+	pub use crate::search::{
+		folders::{VirtualFolder, VirtualFolderContents, VirtualFolderError},
+		SavedSearch, SavedSearchError, SearchFilter, SearchSort, SearchSortBy,
+	};
+	pub use crate::share::{Share, ShareBundle, ShareError, ShareStatus, ShareTarget};
+	pub use crate::sys::{
+		FileSystemCapabilities, LocationPathMapping, LocationResource, NetworkProtocol,
+		SymlinkPolicy, SysError, ThumbnailPolicy, Volume, VolumeHealth,
+	};
+	pub use crate::tag::{
+		bulk::{BulkTagAction, BulkTagPreview},
+		Tag, TagError, TagOnFile, TagWithFiles,
+	};
+	pub use crate::util::collation::Collation;
+	pub use crate::{CoreResource, NodeState};
+}
+
 // a wrapper around external input with a returning sender channel for core to respond
 #[derive(Debug)]
 pub struct ReturnableMessage<D, R = Result<CoreResponse, CoreError>> {
@@ -43,6 +136,7 @@ pub struct ReturnableMessage<D, R = Result<CoreResponse, CoreError>> {
 }
 
 // core controller is passed to the client to communicate with the core which runs in a dedicated thread
+#[derive(Clone)]
 pub struct NodeController {
 	query_sender: UnboundedSender<ReturnableMessage<ClientQuery>>,
 	command_sender: UnboundedSender<ReturnableMessage<ClientCommand>>,
@@ -71,15 +165,28 @@ impl NodeController {
 			})
 			.unwrap_or(());
 
-		recv.await.unwrap()
+		recv.await.unwrap_or(Err(CoreError::Query))
 	}
 }
 
+// the number of events buffered for clients before emitting waits
+pub(crate) const EVENT_CHANNEL_CAPACITY: usize = 100;
+
 #[derive(Clone)]
 pub struct NodeContext {
 	pub event_sender: mpsc::Sender<CoreEvent>,
 	pub config: Arc<NodeConfigManager>,
+	pub view_state: Arc<ViewStateManager>,
+	pub usage: Arc<UsageManager>,
 	pub jobs: Arc<JobManager>,
+	pub saved_searches: Arc<search::SavedSearchSubscriptions>,
+	pub metrics: Arc<Metrics>,
+	pub thumbnail_requests: Arc<encode::ThumbnailRequests>,
+	pub preview_requests: Arc<encode::PreviewRequests>,
+	pub ephemeral_cache: Arc<file::ephemeral::EphemeralCache>,
+	pub loaded_libraries: Arc<library::LoadedLibraries>,
+	// an in memory node writes nothing of its own to the folders of its locations
+	pub in_memory: bool,
 }
 
 impl NodeContext {
@@ -92,8 +199,16 @@ impl NodeContext {
 
 pub struct Node {
 	config: Arc<NodeConfigManager>,
+	view_state: Arc<ViewStateManager>,
+	usage: Arc<UsageManager>,
 	library_manager: Arc<LibraryManager>,
 	jobs: Arc<JobManager>,
+	saved_searches: Arc<search::SavedSearchSubscriptions>,
+	metrics: Arc<Metrics>,
+	thumbnail_requests: Arc<encode::ThumbnailRequests>,
+	preview_requests: Arc<encode::PreviewRequests>,
+	ephemeral_cache: Arc<file::ephemeral::EphemeralCache>,
+	loaded_libraries: Arc<library::LoadedLibraries>,
 
 	// global messaging channels
 	query_channel: (
@@ -106,6 +221,8 @@ pub struct Node {
 	),
 	event_sender: mpsc::Sender<CoreEvent>,
 	shutdown_completion_tx: oneshot::Sender<()>,
+	// set for in memory nodes, the temporary directory removed on shutdown
+	ephemeral_dir: Option<PathBuf>,
 }
 
 impl Node {
@@ -118,23 +235,104 @@ impl Node {
 		Node,
 		oneshot::Receiver<()>,
 	) {
-		let data_dir = data_dir.as_ref();
+		Self::build(data_dir.as_ref(), false).await
+	}
+
+	// create a node which keeps its libraries in memory and its files in a temporary directory,
+	// for tests, demos and trying Spacedrive out. Nothing is left behind once it is shut down.
+	pub async fn new_in_memory() -> (
+		NodeController,
+		mpsc::Receiver<CoreEvent>,
+		Node,
+		oneshot::Receiver<()>,
+	) {
+		let data_dir = std::env::temp_dir().join(format!("spacedrive-{}", Uuid::new_v4()));
+		Self::build(&data_dir, true).await
+	}
+
+	async fn build(
+		data_dir: &Path,
+		in_memory: bool,
+	) -> (
+		NodeController,
+		mpsc::Receiver<CoreEvent>,
+		Node,
+		oneshot::Receiver<()>,
+	) {
 		fs::create_dir_all(data_dir).await.unwrap();
 
-		let (event_sender, event_recv) = mpsc::channel(100);
+		let (event_sender, event_recv) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
 		let config = NodeConfigManager::new(data_dir.to_owned()).await.unwrap();
+		let view_state = ViewStateManager::new(data_dir.to_owned()).await.unwrap();
+		let usage = UsageManager::new(data_dir.to_owned()).await.unwrap();
 
 		let (shutdown_completion_tx, shutdown_completion_rx) = oneshot::channel();
 
 		let jobs = JobManager::new();
+		let saved_searches = Arc::new(search::SavedSearchSubscriptions::default());
+		let metrics = Arc::new(Metrics::default());
+		let thumbnail_requests = Arc::new(encode::ThumbnailRequests::default());
+		let preview_requests = Arc::new(encode::PreviewRequests::default());
+		let ephemeral_cache = Arc::new(file::ephemeral::EphemeralCache::default());
+		let loaded_libraries = Arc::new(library::LoadedLibraries::default());
 		let node_ctx = NodeContext {
 			event_sender: event_sender.clone(),
 			config: config.clone(),
+			view_state: view_state.clone(),
+			usage: usage.clone(),
 			jobs: jobs.clone(),
+			saved_searches: saved_searches.clone(),
+			metrics: metrics.clone(),
+			thumbnail_requests: thumbnail_requests.clone(),
+			preview_requests: preview_requests.clone(),
+			ephemeral_cache: ephemeral_cache.clone(),
+			loaded_libraries: loaded_libraries.clone(),
+			in_memory,
 		};
-		let library_manager = LibraryManager::new(data_dir.join("libraries"), node_ctx)
-			.await
-			.unwrap();
+		let library_manager =
+			LibraryManager::new(data_dir.join("libraries"), node_ctx.clone(), in_memory)
+				.await
+				.unwrap();
+
+		// Keep locations stored on network shares in sync with the reachability of the share
+		tokio::spawn(sys::watch_network_shares(
+			Arc::clone(&library_manager),
+			node_ctx.clone(),
+		));
+
+		// Sample the S.M.A.R.T. health of attached disks so the user is warned before a drive dies
+		tokio::spawn(sys::watch_volume_health(
+			Arc::clone(&library_manager),
+			node_ctx.clone(),
+		));
+
+		// Purge the entries which stayed in the trash for longer than their library keeps them
+		tokio::spawn(file::trash::watch_trash(Arc::clone(&library_manager)));
+
+		// Report stalled jobs and an unresponsive runtime instead of leaving clients spinning
+		tokio::spawn(job::watch_liveness(Arc::clone(&jobs), node_ctx));
+
+		// Expire the entries covered by retention policies once they reach their maximum age
+		tokio::spawn(retention::watch_retention_policies(Arc::clone(
+			&library_manager,
+		)));
+
+		// Remove the bundles of shares once their links expire
+		tokio::spawn(share::watch_shares(Arc::clone(&library_manager)));
+
+		// Run the backup plans of every library once they are due
+		tokio::spawn(backup::watch_backups(Arc::clone(&library_manager)));
+
+		// Snapshot the files of versioned locations whenever they change
+		tokio::spawn(file::versions::watch_versioned_locations(Arc::clone(
+			&library_manager,
+		)));
+
+		// Prune orphaned data and compact the database of every library once a week
+		tokio::spawn(library::watch_maintenance(Arc::clone(&library_manager)));
+
+		// Remove the logs of jobs which haven't run for a long time
+		tokio::spawn(job::prune_logs(data_dir.to_owned()));
 
 		// Trying to resume possible paused jobs
 		let inner_library_manager = Arc::clone(&library_manager);
@@ -144,17 +342,33 @@ impl Node {
 				if let Err(e) = Arc::clone(&inner_jobs).resume_jobs(&library_ctx).await {
 					error!("Failed to resume jobs for library. {:#?}", e);
 				}
+				// catches up with what changed while the node was off, eg: after an upgrade
+				library_ctx
+					.spawn_job(Job::new(
+						file::sizes::FolderSizesJobInit::default(),
+						Box::new(file::sizes::FolderSizesJob {}),
+					))
+					.await;
 			}
 		});
 
 		let node = Node {
 			config,
+			view_state,
+			usage,
 			library_manager,
 			query_channel: unbounded_channel(),
 			command_channel: unbounded_channel(),
 			jobs,
+			saved_searches,
+			metrics,
+			thumbnail_requests,
+			preview_requests,
+			ephemeral_cache,
+			loaded_libraries,
 			event_sender,
 			shutdown_completion_tx,
+			ephemeral_dir: in_memory.then(|| data_dir.to_owned()),
 		};
 
 		(
@@ -172,7 +386,16 @@ impl Node {
 		NodeContext {
 			event_sender: self.event_sender.clone(),
 			config: Arc::clone(&self.config),
+			view_state: Arc::clone(&self.view_state),
+			usage: Arc::clone(&self.usage),
 			jobs: Arc::clone(&self.jobs),
+			saved_searches: Arc::clone(&self.saved_searches),
+			metrics: Arc::clone(&self.metrics),
+			thumbnail_requests: Arc::clone(&self.thumbnail_requests),
+			preview_requests: Arc::clone(&self.preview_requests),
+			ephemeral_cache: Arc::clone(&self.ephemeral_cache),
+			loaded_libraries: Arc::clone(&self.loaded_libraries),
+			in_memory: self.ephemeral_dir.is_some(),
 		}
 	}
 
@@ -181,11 +404,23 @@ impl Node {
 			// listen on global messaging channels for incoming messages
 			tokio::select! {
 				Some(msg) = self.query_channel.1.recv() => {
+					let library_id = match &msg.data {
+						ClientQuery::LibraryQuery { library_id, .. } => Some(*library_id),
+						_ => None,
+					};
+					let started = Instant::now();
 					let res = self.exec_query(msg.data).await;
+					self.metrics.record_query(library_id, started.elapsed());
 					msg.return_sender.send(res).unwrap_or(());
 				}
 				Some(msg) = self.command_channel.1.recv() => {
+					let library_id = match &msg.data {
+						ClientCommand::LibraryCommand { library_id, .. } => Some(*library_id),
+						_ => None,
+					};
+					let started = Instant::now();
 					let res = self.exec_command(msg.data).await;
+					self.metrics.record_command(library_id, started.elapsed());
 					msg.return_sender.send(res).unwrap_or(());
 				}
 
@@ -203,7 +438,17 @@ impl Node {
 	}
 
 	pub async fn shutdown(&self) {
-		self.jobs.pause().await
+		self.jobs.pause().await;
+
+		if let Some(ephemeral_dir) = &self.ephemeral_dir {
+			if let Err(e) = fs::remove_dir_all(ephemeral_dir).await {
+				error!(
+					"Failed to remove the data of in memory node {}: {:#?}",
+					ephemeral_dir.display(),
+					e
+				);
+			}
+		}
 	}
 
 	async fn exec_command(&mut self, cmd: ClientCommand) -> Result<CoreResponse, CoreError> {
@@ -222,9 +467,37 @@ impl Node {
 				id,
 				name,
 				description,
+				object_chunk_hashing,
+				secrets_scanning,
+				trash_retention_days,
+				maintenance_interval_days,
+				index_batch_size,
+				index_flush_interval_ms,
+				collation,
+				show_hidden_files,
+				places,
+				thumbnail_profiles,
+				share_target,
 			} => {
 				self.library_manager
-					.edit(id, name, description)
+					.edit(
+						id,
+						EditLibraryArgs {
+							name,
+							description,
+							object_chunk_hashing,
+							secrets_scanning,
+							trash_retention_days,
+							maintenance_interval_days,
+							index_batch_size,
+							index_flush_interval_ms,
+							collation,
+							show_hidden_files,
+							places,
+							thumbnail_profiles,
+							share_target,
+						},
+					)
 					.await
 					.unwrap();
 				CoreResponse::Success(())
@@ -237,8 +510,14 @@ impl Node {
 				library_id,
 				command,
 			} => {
-				let ctx = self.library_manager.get_ctx(library_id).await.unwrap();
-				match command {
+				let ctx = self
+					.library_manager
+					.get_ctx(library_id)
+					.await
+					.ok_or(library::LibraryError::LibraryNotFound)?;
+				// destructive commands are written to the audit log once they succeed
+				let audit = history::audit::pending(&ctx, &command);
+				let response = match command {
 					// CRUD for locations
 					LibraryCommand::LocCreate { path } => {
 						let loc = sys::new_location_and_scan(&ctx, &path).await?;
@@ -264,6 +543,65 @@ impl Node {
 						CoreResponse::Success(())
 					}
 					LibraryCommand::LocQuickRescan { id: _ } => todo!(),
+					LibraryCommand::LocAddPathMapping { id, path } => {
+						CoreResponse::LocCreate(sys::add_path_mapping(&ctx, id, &path).await?)
+					}
+					LibraryCommand::LocRemovePathMapping { id } => {
+						sys::remove_path_mapping(&ctx, id).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::LocSetThumbnailPolicy { id, policy } => {
+						ctx.db
+							.location()
+							.find_unique(location::id::equals(id))
+							.update(vec![location::thumbnail_policy::set(
+								policy.map(|policy| policy.int_value()),
+							)])
+							.exec()
+							.await?;
+
+						CoreResponse::Success(())
+					}
+					LibraryCommand::LocSetVersioning { id, policy } => {
+						ctx.db
+							.location()
+							.find_unique(location::id::equals(id))
+							.update(vec![
+								location::versioning_enabled::set(policy.is_some()),
+								location::versions_max_count::set(
+									policy.and_then(|policy| policy.max_versions),
+								),
+								location::versions_quota::set(
+									policy.and_then(|policy| policy.quota_bytes),
+								),
+							])
+							.exec()
+							.await?;
+
+						CoreResponse::Success(())
+					}
+					LibraryCommand::ViewStateOpened { location_id, path } => {
+						ctx.view_state()
+							.opened(ctx.id, node::ExplorerPath { location_id, path })
+							.await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::ViewStateSetDirectory {
+						location_id,
+						path,
+						scroll_anchor,
+						layout,
+					} => {
+						ctx.view_state()
+							.set_directory(
+								ctx.id,
+								node::ExplorerPath { location_id, path },
+								scroll_anchor,
+								layout,
+							)
+							.await?;
+						CoreResponse::Success(())
+					}
 					// CRUD for files
 					LibraryCommand::FileReadMetaData { id: _ } => todo!(),
 					LibraryCommand::FileSetNote { id, note } => {
@@ -273,16 +611,152 @@ impl Node {
 						file::favorite(ctx, id, favorite).await?
 					}
 					// ClientCommand::FileEncrypt { id: _, algorithm: _ } => todo!(),
+					LibraryCommand::FileSetLegalHold { id, legal_hold } => {
+						file::set_legal_hold(ctx, id, legal_hold).await?
+					}
+					LibraryCommand::FileSnapshotRestore { id } => {
+						file::versions::restore_snapshot(&ctx, id).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::FileBatchSetMetadata { files, update } => {
+						CoreResponse::FileBatchSetMetadata(
+							file::metadata::set_metadata(&ctx, files, update).await?,
+						)
+					}
 					LibraryCommand::FileDelete { id } => {
+						file::ensure_not_held(&ctx, id).await?;
 						ctx.db
 							.file()
 							.find_unique(prisma_file::id::equals(id))
 							.delete()
 							.exec()
 							.await?;
+						search::refresh_subscriptions(&ctx).await;
 
 						CoreResponse::Success(())
 					}
+					LibraryCommand::FilePathTrash { id } => {
+						let entry = file::trash::trash_file_path(&ctx, id).await?;
+						history::record(
+							&ctx,
+							history::Operation::Trash {
+								location_id: entry.location_id,
+								path: entry.original_path,
+							},
+						)
+						.await;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::TrashRestore { id } => {
+						file::trash::restore(&ctx, id).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::TrashEmpty => {
+						ctx.spawn_job(Job::new(
+							file::trash::TrashPurgeJobInit {
+								older_than_days: None,
+							},
+							Box::new(file::trash::TrashPurgeJob {}),
+						))
+						.await;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::FilePathCopy {
+						ids,
+						location_id,
+						path,
+						conflict_policy,
+						invalid_names,
+						alternate_streams,
+					} => {
+						ctx.spawn_job(Job::new(
+							file::copy::FileCopyJobInit {
+								file_path_ids: ids,
+								target_location_id: location_id,
+								target_path: path,
+								delete_source: false,
+								conflict_policy,
+								invalid_names: invalid_names.unwrap_or_default(),
+								alternate_streams: alternate_streams.unwrap_or(false),
+							},
+							Box::new(file::copy::FileCopyJob {}),
+						))
+						.await;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::FilePathMove {
+						ids,
+						location_id,
+						path,
+						conflict_policy,
+						invalid_names,
+						alternate_streams,
+					} => {
+						ctx.spawn_job(Job::new(
+							file::copy::FileCopyJobInit {
+								file_path_ids: ids,
+								target_location_id: location_id,
+								target_path: path,
+								delete_source: true,
+								conflict_policy,
+								invalid_names: invalid_names.unwrap_or_default(),
+								alternate_streams: alternate_streams.unwrap_or(false),
+							},
+							Box::new(file::copy::FileCopyJob {}),
+						))
+						.await;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::ResolveFileConflict {
+						job_id,
+						resolution,
+						apply_to_all,
+					} => {
+						file::copy::resolve_conflict(&ctx, job_id, resolution, apply_to_all)
+							.await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::FilePathBatchRename { ids, pattern } => {
+						file::rename::batch_rename(ctx, ids, pattern).await?
+					}
+					LibraryCommand::FilePathCompress {
+						ids,
+						location_id,
+						path,
+						format,
+					} => {
+						ctx.spawn_job(Job::new(
+							file::archive::CompressJobInit {
+								file_path_ids: ids,
+								location_id,
+								path,
+								format,
+							},
+							Box::new(file::archive::CompressJob {}),
+						))
+						.await;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::FilePathExtract { id, path, password } => {
+						ctx.spawn_job(Job::new(
+							file::archive::ExtractJobInit {
+								file_path_id: id,
+								path,
+								password,
+							},
+							Box::new(file::archive::ExtractJob {}),
+						))
+						.await;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::FileLinkCreate {
+						file_id,
+						linked_file_id,
+						kind,
+					} => file::links::create_link(ctx, file_id, linked_file_id, kind).await?,
+					LibraryCommand::FileLinkDelete { id } => {
+						file::links::delete_link(ctx, id).await?
+					}
 					// CRUD for tags
 					LibraryCommand::TagCreate { name, color } => {
 						tag::create_tag(ctx, name, color).await?
@@ -291,6 +765,11 @@ impl Node {
 						tag::tag_assign(ctx, file_id, tag_id).await?
 					}
 					LibraryCommand::TagDelete { id } => tag::tag_delete(ctx, id).await?,
+					LibraryCommand::TagBulk {
+						tag_id,
+						file_ids,
+						action,
+					} => tag::bulk::bulk_tag(ctx, tag_id, file_ids, action).await?,
 					LibraryCommand::TagUpdate { id, name, color } => {
 						tag::update_tag(ctx, id, name, color).await?
 					}
@@ -302,12 +781,17 @@ impl Node {
 								location_id: id,
 								path,
 								background: false, // fix
+								shallow: false,
 							},
 							Box::new(ThumbnailJob {}),
 						))
 						.await;
 						CoreResponse::Success(())
 					}
+					LibraryCommand::ThumbnailsRequest { file_path_ids } => {
+						encode::request_thumbnails(&ctx, file_path_ids).await?;
+						CoreResponse::Success(())
+					}
 					LibraryCommand::IdentifyUniqueFiles { id, path } => {
 						ctx.spawn_job(Job::new(
 							FileIdentifierJobInit {
@@ -319,7 +803,241 @@ impl Node {
 						.await;
 						CoreResponse::Success(())
 					}
+					LibraryCommand::FindDuplicates {
+						similar_images_max_distance,
+					} => {
+						ctx.spawn_job(Job::new(
+							DuplicateFinderJobInit {
+								similar_images_max_distance,
+							},
+							Box::new(DuplicateFinderJob {}),
+						))
+						.await;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::ResolveDuplicateGroup {
+						id,
+						keep_file_path_id,
+						resolution,
+					} => {
+						duplicates::resolve_duplicate_group(ctx, id, keep_file_path_id, resolution)
+							.await?
+					}
+					// Retention
+					LibraryCommand::RetentionPolicyCreate {
+						name,
+						location_id,
+						path,
+						tag_id,
+						max_age_days,
+						action,
+					} => {
+						retention::create_policy(
+							ctx,
+							name,
+							location_id,
+							path,
+							tag_id,
+							max_age_days,
+							action,
+						)
+						.await?
+					}
+					LibraryCommand::RetentionPolicyDelete { id } => {
+						retention::delete_policy(ctx, id).await?
+					}
+					LibraryCommand::FilePathSetRetentionExempt { id, exempt } => {
+						retention::set_exempt(ctx, id, exempt).await?
+					}
+					LibraryCommand::EnforceRetentionPolicies => {
+						ctx.spawn_job(Job::new(RetentionJobInit {}, Box::new(RetentionJob {})))
+							.await;
+						CoreResponse::Success(())
+					}
+					// Saved searches
+					LibraryCommand::SavedSearchCreate { name, filter, sort } => {
+						search::create_saved_search(ctx, name, filter, sort).await?
+					}
+					LibraryCommand::SavedSearchUpdate {
+						id,
+						name,
+						filter,
+						sort,
+					} => search::update_saved_search(ctx, id, name, filter, sort).await?,
+					LibraryCommand::SavedSearchDelete { id } => {
+						search::delete_saved_search(ctx, id).await?
+					}
+					LibraryCommand::SavedSearchSubscribe { id } => {
+						CoreResponse::SavedSearchResults(search::subscribe(&ctx, id).await?)
+					}
+					LibraryCommand::SavedSearchUnsubscribe { id } => {
+						search::unsubscribe(&ctx, id).await;
+						CoreResponse::Success(())
+					}
+					// Virtual folders
+					LibraryCommand::VirtualFolderCreate { name, filter, sort } => {
+						search::folders::create_virtual_folder(ctx, name, filter, sort).await?
+					}
+					LibraryCommand::VirtualFolderUpdate {
+						id,
+						name,
+						filter,
+						sort,
+					} => search::folders::update_virtual_folder(ctx, id, name, filter, sort).await?,
+					LibraryCommand::VirtualFolderDelete { id } => {
+						search::folders::delete_virtual_folder(ctx, id).await?
+					}
+					// History
+					LibraryCommand::Undo => history::undo(ctx).await?,
+					LibraryCommand::Redo => history::redo(ctx).await?,
+					LibraryCommand::DirectorySubscribe { directory_id } => {
+						file::explorer::subscribe_dir(&ctx, directory_id).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::DirectoryUnsubscribe { directory_id } => {
+						file::explorer::unsubscribe_dir(&ctx, directory_id).await;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::FileRecordAccess { file_path_id, kind } => {
+						file::recents::record_access(&ctx, file_path_id, kind).await?;
+						CoreResponse::Success(())
+					}
+					// Custom fields
+					LibraryCommand::CustomFieldCreate {
+						name,
+						kind,
+						options,
+					} => CoreResponse::CustomFieldCreateResponse(
+						custom_field::create_field(&ctx, name, kind, options).await?,
+					),
+					LibraryCommand::CustomFieldUpdate { id, name, options } => {
+						custom_field::update_field(&ctx, id, name, options).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::CustomFieldDelete { id } => {
+						custom_field::delete_field(&ctx, id).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::FileSetCustomField {
+						file_id,
+						field_id,
+						value,
+					} => {
+						custom_field::set_value(&ctx, file_id, field_id, value).await?;
+						CoreResponse::Success(())
+					}
+					// Notes
+					LibraryCommand::NoteCreate { file_id, body } => {
+						CoreResponse::NoteCreateResponse(
+							file::notes::create_note(&ctx, file_id, body).await?,
+						)
+					}
+					LibraryCommand::NoteUpdate { id, body } => {
+						file::notes::update_note(&ctx, id, body).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::NoteDelete { id } => {
+						file::notes::delete_note(&ctx, id).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::NotesMerge { changes } => CoreResponse::NotesMergeResponse(
+						file::notes::merge_notes(&ctx, changes).await?,
+					),
+					// Shares
+					LibraryCommand::ShareCreate {
+						file_path_id,
+						expires_at,
+						password,
+					} => CoreResponse::ShareCreateResponse(
+						share::create_share(&ctx, file_path_id, expires_at, password).await?,
+					),
+					LibraryCommand::ShareRevoke { id } => {
+						share::revoke_share(&ctx, id).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::ShareOpen { token, password } => {
+						CoreResponse::ShareOpenResponse(
+							share::open_share(&ctx, token, password).await?,
+						)
+					}
+					LibraryCommand::FilePathSecureDelete { ids, passes } => {
+						file::shred::secure_delete(&ctx, ids, passes).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::BackupPlanCreate {
+						name,
+						location_id,
+						target,
+						deletion_policy,
+						interval_hours,
+					} => CoreResponse::BackupPlanCreateResponse(
+						backup::create_plan(
+							&ctx,
+							name,
+							location_id,
+							target,
+							deletion_policy,
+							interval_hours,
+						)
+						.await?,
+					),
+					LibraryCommand::BackupPlanDelete { id } => {
+						backup::delete_plan(&ctx, id).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::BackupPlanRun { id } => {
+						backup::run_plan(&ctx, id).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::MediaImport {
+						source,
+						path,
+						location_id,
+						target_path,
+					} => {
+						file::import::import_media(&ctx, source, path, location_id, target_path)
+							.await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::LocRepairNames { location_id, ids } => {
+						file::names::repair_names(&ctx, location_id, ids).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::LocSetSymlinkPolicy { id, policy } => {
+						ctx.db
+							.location()
+							.find_unique(location::id::equals(id))
+							.update(vec![location::symlink_policy::set(policy.int_value())])
+							.exec()
+							.await?;
+
+						CoreResponse::Success(())
+					}
+					LibraryCommand::LibraryMaintenanceRun => {
+						library::run_maintenance(&ctx).await;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::EphemeralCreateDir { path, name } => {
+						file::ephemeral::create_ephemeral_dir(&ctx, path, name).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::EphemeralRename { path, name } => {
+						file::ephemeral::rename_ephemeral_entry(&ctx, path, name).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::EphemeralDelete { paths } => {
+						file::ephemeral::delete_ephemeral_entries(&ctx, paths).await?;
+						CoreResponse::Success(())
+					}
+					LibraryCommand::EphemeralPromote { path } => CoreResponse::LocCreate(
+						file::ephemeral::promote_ephemeral_dir(&ctx, path).await?,
+					),
+				};
+
+				if let Some(audit) = audit {
+					audit.record().await;
 				}
+				response
 			}
 		})
 	}
@@ -336,6 +1054,14 @@ impl Node {
 			}),
 			ClientQuery::GetNodes => todo!(),
 			ClientQuery::GetVolumes => CoreResponse::GetVolumes(sys::Volume::get_volumes()?),
+			ClientQuery::GetUsage { days } => CoreResponse::GetUsage(self.usage.get(days).await),
+			ClientQuery::GetMetrics => CoreResponse::GetMetrics(self.metrics.snapshot(
+				self.jobs.queued_count().await,
+				self.jobs.running_count().await,
+			)),
+			ClientQuery::GetDegradedLibraries => CoreResponse::GetDegradedLibraries(
+				self.library_manager.get_degraded_libraries().await,
+			),
 			ClientQuery::LibraryQuery { library_id, query } => {
 				let ctx = match self.library_manager.get_ctx(library_id).await {
 					Some(ctx) => ctx,
@@ -360,12 +1086,20 @@ impl Node {
 						location_id,
 						path,
 						limit: _,
+						show_hidden,
 					} => CoreResponse::GetExplorerDir(Box::new(
-						file::explorer::open_dir(&ctx, location_id, path).await?,
+						file::explorer::open_dir(&ctx, location_id, path, show_hidden).await?,
 					)),
-					LibraryQuery::GetJobHistory => {
-						CoreResponse::GetJobHistory(JobManager::get_history(&ctx).await?)
-					}
+					LibraryQuery::GetJobHistory { cursor, limit } => CoreResponse::GetJobHistory(
+						JobManager::get_history(&ctx, cursor, limit).await?,
+					),
+					LibraryQuery::GetJobLogs {
+						job_id,
+						tail,
+						level,
+					} => CoreResponse::GetJobLogs(
+						job::get_job_logs(&ctx, job_id, tail, level).await?,
+					),
 					LibraryQuery::GetLibraryStatistics => CoreResponse::GetLibraryStatistics(
 						library::Statistics::calculate(&ctx).await?,
 					),
@@ -373,6 +1107,208 @@ impl Node {
 					LibraryQuery::GetFilesTagged { tag_id } => {
 						tag::get_files_for_tag(ctx, tag_id).await?
 					}
+					LibraryQuery::GetBulkTagPreview {
+						tag_id,
+						file_ids,
+						action,
+					} => CoreResponse::GetBulkTagPreview(
+						tag::bulk::get_bulk_tag_preview(&ctx, tag_id, file_ids, action).await?,
+					),
+					LibraryQuery::GetVolumeHealth => {
+						CoreResponse::GetVolumeHealth(sys::get_volume_health(&ctx).await?)
+					}
+					LibraryQuery::GetDuplicateGroups => CoreResponse::GetDuplicateGroups(
+						duplicates::get_duplicate_groups(&ctx).await?,
+					),
+					LibraryQuery::GetSimilarImages {
+						file_id,
+						max_distance,
+					} => CoreResponse::GetSimilarImages(
+						duplicates::get_similar_images(&ctx, file_id, max_distance).await?,
+					),
+					LibraryQuery::GetThumbstrip {
+						location_id,
+						cas_id,
+					} => CoreResponse::GetThumbstrip(
+						encode::get_thumbstrip_layout(&ctx, location_id, cas_id).await,
+					),
+					LibraryQuery::SearchFullText { query, limit } => CoreResponse::SearchFullText(
+						file::text::search_full_text(&ctx, query, limit).await?,
+					),
+					LibraryQuery::GetRetentionPolicies => {
+						CoreResponse::GetRetentionPolicies(retention::get_policies(&ctx).await?)
+					}
+					LibraryQuery::GetRetentionPreview { days_ahead } => {
+						CoreResponse::GetRetentionPreview(
+							retention::get_expiring(&ctx, days_ahead).await?,
+						)
+					}
+					LibraryQuery::GetSavedSearches => {
+						CoreResponse::GetSavedSearches(search::get_saved_searches(&ctx).await?)
+					}
+					LibraryQuery::GetSavedSearchResults { id } => {
+						CoreResponse::SavedSearchResults(search::run_saved_search(&ctx, id).await?)
+					}
+					LibraryQuery::GetFileLinks { file_id } => {
+						CoreResponse::GetFileLinks(file::links::get_links(&ctx, file_id).await?)
+					}
+					LibraryQuery::GetLinkedFiles { file_id } => CoreResponse::GetLinkedFiles(
+						file::links::get_linked_group(&ctx, file_id).await?,
+					),
+					LibraryQuery::GetViewState => {
+						CoreResponse::GetViewState(ctx.view_state().get(ctx.id).await)
+					}
+					LibraryQuery::GetVirtualFolders => CoreResponse::GetVirtualFolders(
+						search::folders::get_virtual_folders(&ctx).await?,
+					),
+					LibraryQuery::GetVirtualFolderContents { id, cursor, limit } => {
+						CoreResponse::GetVirtualFolderContents(
+							search::folders::get_virtual_folder_contents(&ctx, id, cursor, limit)
+								.await?,
+						)
+					}
+					LibraryQuery::GetHistory => {
+						CoreResponse::GetHistory(history::get_history(&ctx).await?)
+					}
+					LibraryQuery::GetTrash => {
+						CoreResponse::GetTrash(file::trash::get_trash(&ctx).await?)
+					}
+					LibraryQuery::GetBatchRenamePreview { ids, pattern } => {
+						CoreResponse::GetBatchRenamePreview(
+							file::rename::get_batch_rename_preview(&ctx, ids, &pattern).await?,
+						)
+					}
+					LibraryQuery::GetCompressPreview { ids, format } => {
+						CoreResponse::GetCompressPreview(
+							file::archive::get_compress_preview(&ctx, ids, format).await?,
+						)
+					}
+					LibraryQuery::GetExtractPreview { id } => CoreResponse::GetExtractPreview(
+						file::archive::get_extract_preview(&ctx, id).await?,
+					),
+					LibraryQuery::GetFileVersions { file_path_id } => {
+						CoreResponse::GetFileVersions(
+							file::versions::get_versions(&ctx, file_path_id).await?,
+						)
+					}
+					LibraryQuery::GetArchiveContents { id, path } => {
+						CoreResponse::GetArchiveContents(
+							file::archive::get_archive_contents(&ctx, id, path).await?,
+						)
+					}
+					LibraryQuery::GetAuditLog {
+						filter,
+						offset,
+						limit,
+					} => CoreResponse::GetAuditLog(
+						history::audit::get_audit_log(&ctx, filter, offset, limit).await?,
+					),
+					LibraryQuery::GetFilePath { id } => {
+						CoreResponse::GetFilePath(file::get_file_path(&ctx, id).await?)
+					}
+					LibraryQuery::GetPlaces => {
+						CoreResponse::GetPlaces(places::get_places(&ctx).await?)
+					}
+					LibraryQuery::GetPhotosByPlace { place_id } => CoreResponse::GetPhotosByPlace(
+						places::get_photos_by_place(&ctx, place_id).await?,
+					),
+					LibraryQuery::GetVideoPreview { file_path_id } => {
+						CoreResponse::GetVideoPreview(
+							encode::get_video_preview(&ctx, file_path_id).await?,
+						)
+					}
+					LibraryQuery::GetExplorerPage {
+						location_id,
+						path,
+						sort,
+						cursor,
+						limit,
+						prefetch,
+						show_hidden,
+					} => CoreResponse::GetExplorerPage(Box::new(
+						file::explorer::list_dir(
+							&ctx,
+							location_id,
+							path,
+							sort,
+							cursor,
+							limit,
+							prefetch,
+							show_hidden,
+						)
+						.await?,
+					)),
+					LibraryQuery::GetStorageBreakdown { location_id } => {
+						CoreResponse::GetStorageBreakdown(
+							file::storage::get_storage_breakdown(&ctx, location_id).await?,
+						)
+					}
+					LibraryQuery::GetStorageTreemap {
+						location_id,
+						path,
+						limit,
+					} => CoreResponse::GetStorageTreemap(
+						file::storage::get_storage_treemap(&ctx, location_id, path, limit).await?,
+					),
+					LibraryQuery::GetRecentFiles { limit } => CoreResponse::GetRecentFiles(
+						file::recents::get_recent_files(&ctx, limit).await?,
+					),
+					LibraryQuery::GetFrequentFiles { limit } => CoreResponse::GetFrequentFiles(
+						file::recents::get_frequent_files(&ctx, limit).await?,
+					),
+					LibraryQuery::GetCustomFields => {
+						CoreResponse::GetCustomFields(custom_field::get_fields(&ctx).await?)
+					}
+					LibraryQuery::GetFileCustomFields { file_id } => {
+						CoreResponse::GetFileCustomFields(
+							custom_field::get_file_values(&ctx, file_id).await?,
+						)
+					}
+					LibraryQuery::GetNotes { file_id } => {
+						CoreResponse::GetNotes(file::notes::get_notes(&ctx, file_id).await?)
+					}
+					LibraryQuery::GetNoteChanges { since } => CoreResponse::GetNoteChanges(
+						file::notes::get_note_changes(&ctx, since).await?,
+					),
+					LibraryQuery::GetShares => {
+						CoreResponse::GetShares(share::get_shares(&ctx).await?)
+					}
+					LibraryQuery::GetSecureDeletePreview { ids, passes } => {
+						CoreResponse::GetSecureDeletePreview(
+							file::shred::get_secure_delete_preview(&ctx, ids, passes).await?,
+						)
+					}
+					LibraryQuery::GetBackupPlans => {
+						CoreResponse::GetBackupPlans(backup::get_plans(&ctx).await?)
+					}
+					LibraryQuery::GetBackupManifest { id } => {
+						CoreResponse::GetBackupManifest(backup::get_manifest(&ctx, id).await?)
+					}
+					LibraryQuery::VerifyBackup { id } => {
+						CoreResponse::VerifyBackup(backup::verify_backup(&ctx, id).await?)
+					}
+					LibraryQuery::GetImportPreview { source, path } => {
+						CoreResponse::GetImportPreview(
+							file::import::get_import_preview(source, path).await?,
+						)
+					}
+					LibraryQuery::GetNameRepairPreview { location_id } => {
+						CoreResponse::GetNameRepairPreview(
+							file::names::get_name_repair_preview(&ctx, location_id).await?,
+						)
+					}
+					LibraryQuery::GetCopyPreflight {
+						ids,
+						location_id,
+						path,
+					} => CoreResponse::GetCopyPreflight(
+						file::preflight::get_copy_preflight(&ctx, ids, location_id, path).await?,
+					),
+					LibraryQuery::GetEphemeralDir { path, show_hidden } => {
+						CoreResponse::GetEphemeralDir(
+							file::ephemeral::get_ephemeral_dir(&ctx, path, show_hidden).await?,
+						)
+					}
 				}
 			}
 		})
@@ -392,6 +1328,17 @@ pub enum ClientCommand {
 		id: Uuid,
 		name: Option<String>,
 		description: Option<String>,
+		object_chunk_hashing: Option<bool>,
+		secrets_scanning: Option<bool>,
+		trash_retention_days: Option<u32>,
+		maintenance_interval_days: Option<u32>,
+		index_batch_size: Option<u32>,
+		index_flush_interval_ms: Option<u32>,
+		collation: Option<util::collation::Collation>,
+		show_hidden_files: Option<bool>,
+		places: Option<bool>,
+		thumbnail_profiles: Option<Vec<encode::ThumbnailProfile>>,
+		share_target: Option<share::ShareTarget>,
 	},
 	DeleteLibrary {
 		id: Uuid,
@@ -420,9 +1367,84 @@ pub enum LibraryCommand {
 		favorite: bool,
 	},
 	// FileEncrypt { id: i32, algorithm: EncryptionAlgorithm },
+	FileSetLegalHold {
+		id: i32,
+		legal_hold: bool,
+	},
+	// writes a version of a file back over it, see GetFileVersions
+	FileSnapshotRestore {
+		id: i32,
+	},
+	// sets metadata on every file, unless one of them changed since its version was read
+	FileBatchSetMetadata {
+		files: Vec<file::metadata::FileVersion>,
+		update: file::metadata::FileMetadataUpdate,
+	},
 	FileDelete {
 		id: i32,
 	},
+	// moves the entry to the trash of its location, from where it can be restored
+	FilePathTrash {
+		id: i32,
+	},
+	TrashRestore {
+		id: i32,
+	},
+	TrashEmpty,
+	FilePathCopy {
+		ids: Vec<i32>,
+		location_id: i32,
+		// materialized path of the target directory, empty for the location root
+		path: String,
+		conflict_policy: file::copy::ConflictPolicy,
+		// nothing is copied while names the target can't take are left, unless set
+		invalid_names: Option<file::preflight::InvalidNamePolicy>,
+		// see FileCopyJobInit, off unless set
+		alternate_streams: Option<bool>,
+	},
+	FilePathMove {
+		ids: Vec<i32>,
+		location_id: i32,
+		path: String,
+		conflict_policy: file::copy::ConflictPolicy,
+		invalid_names: Option<file::preflight::InvalidNamePolicy>,
+		alternate_streams: Option<bool>,
+	},
+	// answers the conflict a copy or move with the `Ask` policy is paused on
+	ResolveFileConflict {
+		job_id: Uuid,
+		resolution: file::copy::ConflictResolution,
+		apply_to_all: bool,
+	},
+	// renames entries from a pattern, see GetBatchRenamePreview
+	FilePathBatchRename {
+		ids: Vec<i32>,
+		pattern: file::rename::RenamePattern,
+	},
+	// archives entries into a new archive, see GetCompressPreview
+	FilePathCompress {
+		ids: Vec<i32>,
+		location_id: i32,
+		// materialized path of the archive created
+		path: String,
+		format: file::archive::ArchiveFormat,
+	},
+	// extracts an archive next to it, or to `path` when set
+	FilePathExtract {
+		id: i32,
+		path: Option<String>,
+		// for encrypted zip archives
+		password: Option<String>,
+	},
+	// `kind` reads as "file_id is <kind> linked_file_id"
+	FileLinkCreate {
+		file_id: i32,
+		linked_file_id: i32,
+		kind: file::links::FileLinkKind,
+	},
+	FileLinkDelete {
+		id: i32,
+	},
 	// Tags
 	TagCreate {
 		name: String,
@@ -440,6 +1462,12 @@ pub enum LibraryCommand {
 	TagDelete {
 		id: i32,
 	},
+	// assigns or removes a tag on many files at once, see GetBulkTagPreview
+	TagBulk {
+		tag_id: i32,
+		file_ids: Vec<i32>,
+		action: tag::bulk::BulkTagAction,
+	},
 	// Locations
 	LocCreate {
 		path: PathBuf,
@@ -457,6 +1485,35 @@ pub enum LibraryCommand {
 	LocQuickRescan {
 		id: i32,
 	},
+	// the location is mounted at `path` on this node
+	LocAddPathMapping {
+		id: i32,
+		path: PathBuf,
+	},
+	LocRemovePathMapping {
+		id: i32,
+	},
+	// `None` picks a policy from the volume the location is on
+	LocSetThumbnailPolicy {
+		id: i32,
+		policy: Option<sys::ThumbnailPolicy>,
+	},
+	// `None` stops versioning the files of the location, keeping the versions taken so far
+	LocSetVersioning {
+		id: i32,
+		policy: Option<file::versions::VersioningPolicy>,
+	},
+	// Explorer view state of this node
+	ViewStateOpened {
+		location_id: i32,
+		path: String,
+	},
+	ViewStateSetDirectory {
+		location_id: i32,
+		path: String,
+		scroll_anchor: Option<i32>,
+		layout: Option<node::ExplorerLayout>,
+	},
 	// System
 	VolUnmount {
 		id: i32,
@@ -465,11 +1522,207 @@ pub enum LibraryCommand {
 		id: i32,
 		path: PathBuf,
 	},
+	// thumbnails of the files on screen, generated ahead of the thumbnailer jobs
+	ThumbnailsRequest {
+		file_path_ids: Vec<i32>,
+	},
 	// PurgeDatabase,
 	IdentifyUniqueFiles {
 		id: i32,
 		path: PathBuf,
 	},
+	// Duplicates
+	FindDuplicates {
+		similar_images_max_distance: Option<u32>,
+	},
+	ResolveDuplicateGroup {
+		id: i32,
+		keep_file_path_id: i32,
+		resolution: duplicates::DuplicateResolution,
+	},
+	// Retention
+	RetentionPolicyCreate {
+		name: String,
+		location_id: Option<i32>,
+		path: Option<String>,
+		tag_id: Option<i32>,
+		max_age_days: i32,
+		action: retention::RetentionAction,
+	},
+	RetentionPolicyDelete {
+		id: i32,
+	},
+	FilePathSetRetentionExempt {
+		id: i32,
+		exempt: bool,
+	},
+	EnforceRetentionPolicies,
+	// Saved searches
+	SavedSearchCreate {
+		name: String,
+		filter: search::SearchFilter,
+		sort: search::SearchSort,
+	},
+	SavedSearchUpdate {
+		id: i32,
+		name: Option<String>,
+		filter: Option<search::SearchFilter>,
+		sort: Option<search::SearchSort>,
+	},
+	SavedSearchDelete {
+		id: i32,
+	},
+	// results are sent as SavedSearchChanged events until unsubscribed
+	SavedSearchSubscribe {
+		id: i32,
+	},
+	SavedSearchUnsubscribe {
+		id: i32,
+	},
+	// Virtual folders
+	VirtualFolderCreate {
+		name: String,
+		filter: search::SearchFilter,
+		sort: search::SearchSort,
+	},
+	VirtualFolderUpdate {
+		id: i32,
+		name: Option<String>,
+		filter: Option<search::SearchFilter>,
+		sort: Option<search::SearchSort>,
+	},
+	VirtualFolderDelete {
+		id: i32,
+	},
+	// History, reverts or reapplies the latest operation
+	Undo,
+	Redo,
+	// what is added to or removed from the directory is sent as DirectoryChanged events until
+	// unsubscribed
+	DirectorySubscribe {
+		directory_id: i32,
+	},
+	DirectoryUnsubscribe {
+		directory_id: i32,
+	},
+	// counts an open or preview of a file on this node, for Quick Access
+	FileRecordAccess {
+		file_path_id: i32,
+		kind: file::recents::FileAccessKind,
+	},
+	// Custom fields, `options` only apply to enum fields
+	CustomFieldCreate {
+		name: String,
+		kind: custom_field::CustomFieldKind,
+		options: Vec<String>,
+	},
+	CustomFieldUpdate {
+		id: i32,
+		name: Option<String>,
+		options: Option<Vec<String>>,
+	},
+	CustomFieldDelete {
+		id: i32,
+	},
+	// `None` clears the value of the field
+	FileSetCustomField {
+		file_id: i32,
+		field_id: i32,
+		value: Option<custom_field::CustomFieldValue>,
+	},
+	// Notes, `body` is markdown
+	NoteCreate {
+		file_id: i32,
+		body: String,
+	},
+	NoteUpdate {
+		id: i32,
+		body: String,
+	},
+	NoteDelete {
+		id: i32,
+	},
+	// applies the changes of another node of the library, see GetNoteChanges
+	NotesMerge {
+		changes: Vec<file::notes::NoteChange>,
+	},
+	// Shares, protected by `password` when set
+	ShareCreate {
+		file_path_id: i32,
+		expires_at: chrono::DateTime<chrono::Utc>,
+		password: Option<String>,
+	},
+	ShareRevoke {
+		id: i32,
+	},
+	// checks a link of the gateway and counts the download, for the http server to serve the share
+	ShareOpen {
+		token: String,
+		password: Option<String>,
+	},
+	// overwrites the entries on disk before removing them for good, with their versions,
+	// sidecars and thumbnails. See GetSecureDeletePreview for what it removes
+	FilePathSecureDelete {
+		ids: Vec<i32>,
+		// 1 unless set, at most 7
+		passes: Option<u8>,
+	},
+	// Backup plans, run every `interval_hours` or only with BackupPlanRun if unset
+	BackupPlanCreate {
+		name: String,
+		location_id: i32,
+		target: backup::BackupTarget,
+		deletion_policy: backup::DeletionPolicy,
+		interval_hours: Option<i32>,
+	},
+	// the files already backed up stay on the target
+	BackupPlanDelete {
+		id: i32,
+	},
+	BackupPlanRun {
+		id: i32,
+	},
+	// copies the media of a Google Takeout or Apple Photos export to a new directory of the
+	// location, then tags them with their albums and people. See GetImportPreview
+	MediaImport {
+		source: file::import::ImportSource,
+		// the zip archive or directory of the export, on this node
+		path: PathBuf,
+		location_id: i32,
+		// materialized path of the directory imported to, named after the source if unset
+		target_path: Option<String>,
+	},
+	// renames the entries of the location with mojibake or decomposed names, those of `ids` or all
+	// of them. See GetNameRepairPreview, the renames can be undone
+	LocRepairNames {
+		location_id: i32,
+		ids: Option<Vec<i32>>,
+	},
+	// applies from the next scan of the location
+	LocSetSymlinkPolicy {
+		id: i32,
+		policy: sys::SymlinkPolicy,
+	},
+	// prunes orphaned data and compacts the database now rather than when it is due
+	LibraryMaintenanceRun,
+	// change entries browsed outside of locations, see GetEphemeralDir. Those within a location
+	// are changed through it
+	EphemeralCreateDir {
+		path: PathBuf,
+		name: String,
+	},
+	EphemeralRename {
+		path: PathBuf,
+		name: String,
+	},
+	// deletes right away, there is no trash outside of locations
+	EphemeralDelete {
+		paths: Vec<PathBuf>,
+	},
+	// adds a browsed directory as a location, keeping the thumbnails generated while browsing it
+	EphemeralPromote {
+		path: PathBuf,
+	},
 }
 
 /// is a query destined for the core
@@ -481,10 +1734,18 @@ pub enum ClientQuery {
 	GetNode,
 	GetVolumes,
 	GetNodes,
+	// disk usage of this node over the last `days` days
+	GetUsage {
+		days: u32,
+	},
 	LibraryQuery {
 		library_id: Uuid,
 		query: LibraryQuery,
 	},
+	// counters of the jobs, queries and commands run since the node started
+	GetMetrics,
+	// the libraries whose database failed its integrity check when the node started
+	GetDegradedLibraries,
 }
 
 /// is a query destined for a specific library which is loaded into the core.
@@ -492,7 +1753,11 @@ pub enum ClientQuery {
 #[serde(tag = "key", content = "params")]
 #[ts(export)]
 pub enum LibraryQuery {
-	GetJobHistory,
+	// newest first, starting after `cursor` when set
+	GetJobHistory {
+		cursor: Option<String>,
+		limit: u32,
+	},
 	GetLocations,
 	GetLocation {
 		id: i32,
@@ -502,12 +1767,179 @@ pub enum LibraryQuery {
 		location_id: i32,
 		path: PathBuf,
 		limit: i32,
+		// overrides whether the library lists hidden entries
+		show_hidden: Option<bool>,
 	},
 	GetLibraryStatistics,
 	GetTags,
 	GetFilesTagged {
 		tag_id: i32,
 	},
+	GetBulkTagPreview {
+		tag_id: i32,
+		file_ids: Vec<i32>,
+		action: tag::bulk::BulkTagAction,
+	},
+	GetVolumeHealth,
+	GetDuplicateGroups,
+	GetSimilarImages {
+		file_id: i32,
+		max_distance: u32,
+	},
+	GetThumbstrip {
+		location_id: i32,
+		cas_id: String,
+	},
+	SearchFullText {
+		query: String,
+		limit: i32,
+	},
+	GetRetentionPolicies,
+	// entries that expire within `days_ahead` days, oldest first
+	GetRetentionPreview {
+		days_ahead: i32,
+	},
+	GetSavedSearches,
+	GetSavedSearchResults {
+		id: i32,
+	},
+	GetFileLinks {
+		file_id: i32,
+	},
+	GetLinkedFiles {
+		file_id: i32,
+	},
+	GetViewState,
+	GetVirtualFolders,
+	// starting after `cursor` when set
+	GetVirtualFolderContents {
+		id: i32,
+		cursor: Option<String>,
+		limit: u32,
+	},
+	GetHistory,
+	GetTrash,
+	GetBatchRenamePreview {
+		ids: Vec<i32>,
+		pattern: file::rename::RenamePattern,
+	},
+	GetCompressPreview {
+		ids: Vec<i32>,
+		format: file::archive::ArchiveFormat,
+	},
+	GetExtractPreview {
+		id: i32,
+	},
+	// lists an archive like a directory, `path` being a directory within it
+	GetArchiveContents {
+		id: i32,
+		path: String,
+	},
+	// the versions of a file path of a versioned location, newest first
+	GetFileVersions {
+		file_path_id: i32,
+	},
+	GetFilePath {
+		id: i32,
+	},
+	// destructive commands run on the library, newest first
+	GetAuditLog {
+		filter: history::audit::AuditFilter,
+		offset: u64,
+		limit: u64,
+	},
+	// the last `tail` lines logged by a job, at `level` or more severe
+	GetJobLogs {
+		job_id: Uuid,
+		tail: Option<u64>,
+		level: Option<job::JobLogLevel>,
+	},
+	// places with photos, the most photographed first
+	GetPlaces,
+	GetPhotosByPlace {
+		place_id: i32,
+	},
+	// a path to a version of the video the webview plays, unset while it is written on the first
+	// request, see VideoPreviewReady
+	GetVideoPreview {
+		file_path_id: i32,
+	},
+	// a page of a directory sorted on the node, starting after `cursor` when set
+	GetExplorerPage {
+		location_id: i32,
+		path: PathBuf,
+		sort: file::explorer::DirectorySort,
+		cursor: Option<String>,
+		limit: u32,
+		// entries past the page to request the thumbnails of
+		prefetch: Option<u32>,
+		// overrides whether the library lists hidden entries
+		show_hidden: Option<bool>,
+	},
+	// bytes and file counts by location, category and extension, of a single location when set
+	GetStorageBreakdown {
+		location_id: Option<i32>,
+	},
+	// the `limit` largest entries of a directory, the root of the location with an empty path
+	GetStorageTreemap {
+		location_id: i32,
+		path: PathBuf,
+		limit: u32,
+	},
+	// files accessed last on any node of the library, see FileRecordAccess
+	GetRecentFiles {
+		limit: u32,
+	},
+	// files accessed the most, recent accesses weighing more
+	GetFrequentFiles {
+		limit: u32,
+	},
+	GetCustomFields,
+	GetFileCustomFields {
+		file_id: i32,
+	},
+	GetNotes {
+		file_id: i32,
+	},
+	// the notes which changed after `since` for another node to merge, deleted ones included
+	GetNoteChanges {
+		since: Option<chrono::DateTime<chrono::Utc>>,
+	},
+	GetShares,
+	GetSecureDeletePreview {
+		ids: Vec<i32>,
+		passes: Option<u8>,
+	},
+	GetBackupPlans,
+	// the manifest the latest run of the plan wrote to its target
+	GetBackupManifest {
+		id: i32,
+	},
+	// checks the files of the manifest are all on the target
+	VerifyBackup {
+		id: i32,
+	},
+	// reads an export without copying anything
+	GetImportPreview {
+		source: file::import::ImportSource,
+		path: PathBuf,
+	},
+	GetNameRepairPreview {
+		location_id: i32,
+	},
+	// checks the names of what a copy or move would write against the rules of the target volume:
+	// case collisions, reserved names, illegal characters and lengths
+	GetCopyPreflight {
+		ids: Vec<i32>,
+		location_id: i32,
+		path: String,
+	},
+	// lists any directory of this node, without adding it as a location
+	GetEphemeralDir {
+		path: PathBuf,
+		// overrides whether the library lists hidden entries
+		show_hidden: Option<bool>,
+	},
 }
 
 // represents an event this library can emit
@@ -519,9 +1951,68 @@ pub enum CoreEvent {
 	InvalidateQuery(ClientQuery),
 	InvalidateQueryDebounced(ClientQuery),
 	InvalidateResource(CoreResource),
-	NewThumbnail { cas_id: String },
-	Log { message: String },
-	DatabaseDisconnected { reason: Option<String> },
+	NewThumbnail {
+		cas_id: String,
+	},
+	Log {
+		message: String,
+	},
+	DatabaseDisconnected {
+		reason: Option<String>,
+	},
+	VolumeConnected(sys::Volume),
+	VolumeDisconnected(sys::Volume),
+	VolumeHealthWarning(sys::VolumeHealth),
+	SavedSearchChanged {
+		library_id: Uuid,
+		id: i32,
+		added: Vec<file::FilePath>,
+		removed: Vec<i32>,
+	},
+	// the members of a virtual folder changed, its contents should be fetched again
+	VirtualFolderChanged {
+		library_id: Uuid,
+		id: i32,
+	},
+	WatchdogWarning(job::WatchdogWarning),
+	// a copy or move is paused until the client answers with `ResolveFileConflict`
+	FileConflict {
+		library_id: Uuid,
+		job_id: Uuid,
+		source_path: String,
+		target_path: String,
+	},
+	FileConflictOutcomes {
+		library_id: Uuid,
+		job_id: Uuid,
+		outcomes: Vec<file::copy::ConflictOutcome>,
+	},
+	// what a copy or move couldn't write to the target along the contents of its entries
+	FileAttributeLosses {
+		library_id: Uuid,
+		job_id: Uuid,
+		losses: Vec<file::attributes::AttributeLoss>,
+	},
+	// entries were added to or removed from a subscribed directory
+	DirectoryChanged {
+		library_id: Uuid,
+		directory_id: i32,
+		added: Vec<file::FilePath>,
+		removed: Vec<i32>,
+	},
+	// a maintenance of the library finished, with what it removed
+	LibraryMaintained {
+		library_id: Uuid,
+		report: library::MaintenanceReport,
+	},
+	// the database of a library was found corrupted when the node started, see GetDegradedLibraries
+	LibraryDegraded(library::DegradedLibrary),
+	// the preview of a video asked for with GetVideoPreview was written, unset if it failed
+	VideoPreviewReady {
+		library_id: Uuid,
+		file_path_id: i32,
+		path: Option<PathBuf>,
+	},
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -540,6 +2031,8 @@ pub enum CoreResponse {
 	Error(String),
 	GetLibraries(Vec<LibraryConfigWrapped>),
 	GetVolumes(Vec<sys::Volume>),
+	GetUsage(Vec<node::DailyUsage>),
+	GetMetrics(node::MetricsSnapshot),
 	TagCreateResponse(Tag),
 	GetTag(Option<Tag>),
 	GetTags(Vec<Tag>),
@@ -550,8 +2043,63 @@ pub enum CoreResponse {
 	LocCreate(sys::LocationResource),
 	OpenTag(Vec<TagWithFiles>),
 	GetRunningJobs(Vec<JobReport>),
-	GetJobHistory(Vec<JobReport>),
+	GetJobHistory(job::JobHistoryPage),
 	GetLibraryStatistics(library::Statistics),
+	GetVolumeHealth(Vec<sys::VolumeHealth>),
+	GetDuplicateGroups(Vec<duplicates::DuplicateGroup>),
+	GetSimilarImages(Vec<duplicates::SimilarImage>),
+	GetThumbstrip(Option<encode::ThumbstripLayout>),
+	SearchFullText(Vec<file::text::FullTextSearchResult>),
+	GetRetentionPolicies(Vec<retention::RetentionPolicy>),
+	GetRetentionPreview(Vec<retention::RetentionExpiry>),
+	GetSavedSearches(Vec<search::SavedSearch>),
+	SavedSearchResults(Vec<file::FilePath>),
+	GetFileLinks(Vec<file::links::FileLink>),
+	GetLinkedFiles(Vec<file::File>),
+	GetViewState(node::LibraryViewState),
+	GetVirtualFolders(Vec<search::folders::VirtualFolder>),
+	GetVirtualFolderContents(search::folders::VirtualFolderContents),
+	GetBulkTagPreview(tag::bulk::BulkTagPreview),
+	GetHistory(Vec<history::HistoryEntry>),
+	GetTrash(Vec<file::trash::TrashedEntry>),
+	GetBatchRenamePreview(file::rename::BatchRenamePreview),
+	GetCompressPreview(file::archive::ArchivePreview),
+	GetExtractPreview(file::archive::ArchivePreview),
+	GetArchiveContents(file::archive::ArchiveContents),
+	FileBatchSetMetadata(file::metadata::FileMetadataResult),
+	GetFileVersions(Vec<file::versions::FileSnapshot>),
+	GetFilePath(file::FilePath),
+	GetAuditLog(Vec<history::audit::AuditEntry>),
+	GetJobLogs(Vec<job::JobLogLine>),
+	GetPlaces(Vec<places::Place>),
+	GetPhotosByPlace(Vec<file::FilePath>),
+	GetVideoPreview(Option<PathBuf>),
+	GetExplorerPage(Box<file::explorer::DirectoryPage>),
+	GetStorageBreakdown(file::storage::StorageBreakdown),
+	GetStorageTreemap(file::storage::StorageTreemap),
+	GetRecentFiles(Vec<file::recents::QuickAccessFile>),
+	GetFrequentFiles(Vec<file::recents::QuickAccessFile>),
+	CustomFieldCreateResponse(custom_field::CustomField),
+	GetCustomFields(Vec<custom_field::CustomField>),
+	GetFileCustomFields(Vec<custom_field::CustomFieldOnFile>),
+	NoteCreateResponse(file::notes::Note),
+	// the number of changes applied
+	NotesMergeResponse(usize),
+	GetNotes(Vec<file::notes::Note>),
+	GetNoteChanges(Vec<file::notes::NoteChange>),
+	ShareCreateResponse(share::Share),
+	ShareOpenResponse(share::ShareBundle),
+	GetShares(Vec<share::Share>),
+	GetSecureDeletePreview(file::shred::SecureDeletePreview),
+	BackupPlanCreateResponse(backup::BackupPlan),
+	GetBackupPlans(Vec<backup::BackupPlan>),
+	GetBackupManifest(backup::BackupManifest),
+	VerifyBackup(backup::BackupVerification),
+	GetImportPreview(file::import::ImportPreview),
+	GetNameRepairPreview(file::names::NameRepairPreview),
+	GetCopyPreflight(file::preflight::CopyPreflight),
+	GetDegradedLibraries(Vec<library::DegradedLibrary>),
+	GetEphemeralDir(file::ephemeral::EphemeralDirectory),
 }
 
 #[derive(Error, Debug)]
@@ -568,6 +2116,28 @@ pub enum CoreError {
 	Database(#[from] prisma::QueryError),
 	#[error("Library error: {0}")]
 	Library(#[from] library::LibraryError),
+	#[error("Retention error: {0}")]
+	Retention(#[from] retention::RetentionError),
+	#[error("Saved search error: {0}")]
+	SavedSearch(#[from] search::SavedSearchError),
+	#[error("Node config error: {0}")]
+	NodeConfig(#[from] node::NodeConfigError),
+	#[error("Virtual folder error: {0}")]
+	VirtualFolder(#[from] search::folders::VirtualFolderError),
+	#[error("Tag error: {0}")]
+	Tag(#[from] tag::TagError),
+	#[error("History error: {0}")]
+	History(#[from] history::HistoryError),
+	#[error("Places error: {0}")]
+	Places(#[from] places::PlacesError),
+	#[error("Preview error: {0}")]
+	Preview(#[from] encode::PreviewError),
+	#[error("Custom field error: {0}")]
+	CustomField(#[from] custom_field::CustomFieldError),
+	#[error("Share error: {0}")]
+	Share(#[from] share::ShareError),
+	#[error("Backup error: {0}")]
+	Backup(#[from] backup::BackupError),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, TS)]
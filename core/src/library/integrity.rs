@@ -0,0 +1,336 @@
+use crate::{
+	job::JobError,
+	prisma::{self, new_client_with_url},
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use log::{error, info};
+use prisma_client_rust::{prisma_models::PrismaValue, raw};
+use serde::{Deserialize, Serialize};
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use super::LibraryContext;
+
+// snapshots of each library are kept in a directory of their own next to the databases
+const SNAPSHOTS_DIR_NAME: &str = "snapshots";
+const SNAPSHOT_DATE_FORMAT: &str = "%Y%m%d%H%M%S";
+// the newest ones are kept, in case the latest snapshot was taken of a database already corrupted
+const MAX_SNAPSHOTS: usize = 3;
+
+// A library whose database failed its integrity check when the node started
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DegradedLibrary {
+	pub id: Uuid,
+	pub name: String,
+	// what the check found, or why the database couldn't be opened
+	pub problems: Vec<String>,
+	// when the snapshot the database was restored from was taken, what changed since is lost.
+	// Unset when no snapshot could be restored, the library isn't loaded then
+	pub restored_from: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct IntegrityCheckRow {
+	integrity_check: String,
+}
+
+#[derive(Deserialize)]
+struct DatabaseFile {
+	file: String,
+}
+
+// the problems SQLite finds in the database, none if it is sound. A database which can't be
+// opened, as when its write-ahead log is corrupted, is reported as such
+pub(super) async fn check_integrity(db_path: &Path) -> Vec<String> {
+	let db = match new_client_with_url(&format!("file:{}", db_path.to_string_lossy())).await {
+		Ok(db) => db,
+		Err(e) => return vec![format!("The database can't be opened: {}", e)],
+	};
+
+	let mut problems = match db
+		._query_raw::<IntegrityCheckRow>(raw!("PRAGMA integrity_check"))
+		.await
+	{
+		Ok(rows) => rows
+			.into_iter()
+			.map(|row| row.integrity_check)
+			.filter(|row| row != "ok")
+			.collect(),
+		Err(e) => vec![format!("The database can't be read: {}", e)],
+	};
+
+	// the pages of the log are only read once written back to the database
+	if problems.is_empty() && wal_path(db_path).exists() {
+		if let Err(e) = db
+			._query_raw::<serde_json::Value>(raw!("PRAGMA wal_checkpoint(TRUNCATE)"))
+			.await
+		{
+			problems.push(format!("The write-ahead log can't be checkpointed: {}", e));
+		}
+	}
+
+	problems
+}
+
+fn wal_path(db_path: &Path) -> PathBuf {
+	PathBuf::from(format!("{}-wal", db_path.to_string_lossy()))
+}
+
+fn snapshots_dir(libraries_dir: &Path, id: Uuid) -> PathBuf {
+	libraries_dir.join(SNAPSHOTS_DIR_NAME).join(id.to_string())
+}
+
+// the snapshots of the library with when they were taken, newest first
+fn snapshots(libraries_dir: &Path, id: Uuid) -> Vec<(PathBuf, DateTime<Utc>)> {
+	let mut snapshots = fs::read_dir(snapshots_dir(libraries_dir, id))
+		.into_iter()
+		.flatten()
+		.filter_map(|entry| entry.ok())
+		.filter_map(|entry| {
+			let path = entry.path();
+			let date = NaiveDateTime::parse_from_str(
+				&path.file_stem()?.to_string_lossy(),
+				SNAPSHOT_DATE_FORMAT,
+			)
+			.ok()?;
+			Some((path, DateTime::from_utc(date, Utc)))
+		})
+		.collect::<Vec<_>>();
+	snapshots.sort_by(|(_, a), (_, b)| b.cmp(a));
+	snapshots
+}
+
+// replaces the database of the library with the newest snapshot which passes the integrity check,
+// and returns when it was taken. The corrupted database is kept aside rather than removed, for
+// what it still holds to be salvaged by hand.
+pub(super) async fn restore_snapshot(
+	libraries_dir: &Path,
+	id: Uuid,
+	db_path: &Path,
+) -> Option<DateTime<Utc>> {
+	for (snapshot, date) in snapshots(libraries_dir, id) {
+		if !check_integrity(&snapshot).await.is_empty() {
+			error!("Snapshot {:?} of library {} is corrupted too", snapshot, id);
+			continue;
+		}
+
+		let aside = format!(
+			"{}.corrupted-{}",
+			db_path.to_string_lossy(),
+			Utc::now().format(SNAPSHOT_DATE_FORMAT)
+		);
+		let restored = fs::rename(db_path, &aside).and_then(|_| {
+			// the log and shared memory of the corrupted database would be applied to the snapshot
+			for suffix in ["-wal", "-shm"] {
+				let path = format!("{}{}", db_path.to_string_lossy(), suffix);
+				if Path::new(&path).exists() {
+					fs::rename(&path, format!("{}{}", aside, suffix))?;
+				}
+			}
+			fs::copy(&snapshot, db_path)
+		});
+
+		match restored {
+			Ok(_) => {
+				info!("Restored library {} from its snapshot of {}", id, date);
+				return Some(date);
+			}
+			Err(e) => {
+				error!(
+					"Failed to restore library {} from {:?}: {:#?}",
+					id, snapshot, e
+				);
+				return None;
+			}
+		}
+	}
+
+	None
+}
+
+// writes a consistent copy of the database of the library next to it, for the library to be
+// restored from if its database gets corrupted. Libraries kept in memory aren't snapshotted.
+pub(super) async fn snapshot_database(ctx: &LibraryContext) -> Result<(), JobError> {
+	let db_path = match database_file(ctx).await? {
+		Some(db_path) => db_path,
+		None => return Ok(()),
+	};
+	let libraries_dir = db_path.parent().unwrap_or_else(|| Path::new(""));
+	let dir = snapshots_dir(libraries_dir, ctx.id);
+	fs::create_dir_all(&dir)?;
+
+	let snapshot = dir
+		.join(Utc::now().format(SNAPSHOT_DATE_FORMAT).to_string())
+		.with_extension("db");
+	// unlike copying the file, a snapshot taken this way can't see a write half done
+	ctx.db
+		._execute_raw(raw!(
+			"VACUUM INTO {}",
+			PrismaValue::String(snapshot.to_string_lossy().to_string())
+		))
+		.await?;
+
+	prune_snapshots(libraries_dir, ctx.id);
+
+	Ok(())
+}
+
+// removes the snapshots of the library but the newest ones
+fn prune_snapshots(libraries_dir: &Path, id: Uuid) {
+	for (old, _) in snapshots(libraries_dir, id).into_iter().skip(MAX_SNAPSHOTS) {
+		if let Err(e) = fs::remove_file(&old) {
+			error!("Failed to remove snapshot {:?}: {:#?}", old, e);
+		}
+	}
+}
+
+// the file of the database of the library, none if it is kept in memory
+async fn database_file(ctx: &LibraryContext) -> Result<Option<PathBuf>, prisma::QueryError> {
+	Ok(ctx
+		.db
+		._query_raw::<DatabaseFile>(raw!(
+			"SELECT file FROM pragma_database_list WHERE name = 'main'"
+		))
+		.await?
+		.into_iter()
+		.next()
+		.filter(|row| !row.file.is_empty())
+		.map(|row| PathBuf::from(row.file)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn take_snapshots(libraries_dir: &Path, id: Uuid, names: &[&str]) -> PathBuf {
+		let dir = snapshots_dir(libraries_dir, id);
+		fs::create_dir_all(&dir).unwrap();
+		for name in names {
+			fs::write(dir.join(name), "").unwrap();
+		}
+		dir
+	}
+
+	fn names(snapshots: &[(PathBuf, DateTime<Utc>)]) -> Vec<String> {
+		snapshots
+			.iter()
+			.map(|(path, _)| path.file_name().unwrap().to_string_lossy().to_string())
+			.collect()
+	}
+
+	#[test]
+	fn lists_snapshots_newest_first() {
+		let libraries_dir = tempfile::tempdir().unwrap();
+		let id = Uuid::new_v4();
+		assert!(snapshots(libraries_dir.path(), id).is_empty());
+
+		take_snapshots(
+			libraries_dir.path(),
+			id,
+			&[
+				"20261001120000.db",
+				"20261015080000.db",
+				"20260930235959.db",
+				// left by something else, it isn't a snapshot
+				"notes.txt",
+				"2026-10-16.db",
+			],
+		);
+		let snapshots = snapshots(libraries_dir.path(), id);
+		assert_eq!(
+			names(&snapshots),
+			[
+				"20261015080000.db",
+				"20261001120000.db",
+				"20260930235959.db"
+			]
+		);
+		assert_eq!(
+			snapshots[0].1,
+			DateTime::<Utc>::from_utc(
+				NaiveDateTime::parse_from_str("2026-10-15 08:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+				Utc
+			)
+		);
+	}
+
+	#[test]
+	fn keeps_the_newest_snapshots() {
+		let libraries_dir = tempfile::tempdir().unwrap();
+		let (id, other) = (Uuid::new_v4(), Uuid::new_v4());
+		let dir = take_snapshots(
+			libraries_dir.path(),
+			id,
+			&[
+				"20261012000000.db",
+				"20261016000000.db",
+				"20261013000000.db",
+				"20261015000000.db",
+				"20261014000000.db",
+				"notes.txt",
+			],
+		);
+		take_snapshots(libraries_dir.path(), other, &["20261001000000.db"]);
+
+		prune_snapshots(libraries_dir.path(), id);
+		assert_eq!(
+			names(&snapshots(libraries_dir.path(), id)),
+			[
+				"20261016000000.db",
+				"20261015000000.db",
+				"20261014000000.db"
+			]
+		);
+		// only snapshots are removed, and only those of the library
+		assert!(dir.join("notes.txt").exists());
+		assert_eq!(snapshots(libraries_dir.path(), other).len(), 1);
+	}
+
+	#[tokio::test]
+	async fn restores_the_newest_sound_snapshot() {
+		let libraries_dir = tempfile::tempdir().unwrap();
+		let id = Uuid::new_v4();
+		let dir = take_snapshots(libraries_dir.path(), id, &["20261014000000.db"]);
+		// newer, but corrupted
+		fs::write(dir.join("20261015000000.db"), "not a database").unwrap();
+
+		let db_path = libraries_dir.path().join(format!("{}.db", id));
+		fs::write(&db_path, "corrupted").unwrap();
+		fs::write(wal_path(&db_path), "corrupted log").unwrap();
+
+		let restored = restore_snapshot(libraries_dir.path(), id, &db_path).await;
+		assert_eq!(
+			restored.map(|date| date.format(SNAPSHOT_DATE_FORMAT).to_string()),
+			Some("20261014000000".to_string())
+		);
+		assert_eq!(fs::read(&db_path).unwrap(), b"");
+		// the corrupted database is kept aside with its log, which isn't applied to the snapshot
+		assert!(!wal_path(&db_path).exists());
+		let aside = fs::read_dir(libraries_dir.path())
+			.unwrap()
+			.filter_map(|entry| entry.ok())
+			.map(|entry| entry.file_name().to_string_lossy().to_string())
+			.filter(|name| name.contains(".corrupted-"))
+			.count();
+		assert_eq!(aside, 2);
+	}
+
+	#[tokio::test]
+	async fn restores_nothing_without_snapshots() {
+		let libraries_dir = tempfile::tempdir().unwrap();
+		let id = Uuid::new_v4();
+		let db_path = libraries_dir.path().join(format!("{}.db", id));
+		fs::write(&db_path, "corrupted").unwrap();
+
+		assert_eq!(
+			restore_snapshot(libraries_dir.path(), id, &db_path).await,
+			None
+		);
+		assert_eq!(fs::read(&db_path).unwrap(), b"corrupted");
+	}
+}
@@ -9,7 +9,9 @@ use std::io::Write;
 use ts_rs::TS;
 use uuid::Uuid;
 
-use crate::node::ConfigMetadata;
+use crate::{
+	encode::ThumbnailProfile, node::ConfigMetadata, share::ShareTarget, util::collation::Collation,
+};
 
 use super::LibraryManagerError;
 
@@ -23,6 +25,39 @@ pub struct LibraryConfig {
 	pub name: String,
 	/// description is a user set description of the library. This is used in the UI and is set by the user.
 	pub description: String,
+	/// object_chunk_hashing enables splitting the full contents of every file into content defined chunks which are individually hashed. This reads every byte of every file so it is disabled by default.
+	#[serde(default)]
+	pub object_chunk_hashing: bool,
+	/// secrets_scanning enables flagging files which contain credentials such as private keys, so they can be kept out of sharing.
+	#[serde(default)]
+	pub secrets_scanning: bool,
+	/// trash_retention_days is how long trashed entries are kept before being purged, 30 days when unset. 0 keeps them until the trash is emptied.
+	#[serde(default)]
+	pub trash_retention_days: Option<u32>,
+	/// maintenance_interval_days is how often orphaned data is removed from the library and its database compacted, weekly when unset. 0 only does it when asked to.
+	#[serde(default)]
+	pub maintenance_interval_days: Option<u32>,
+	/// index_batch_size is how many entries the indexer writes to the database in a single statement, 1000 when unset. Larger batches index faster and hold the database longer.
+	#[serde(default)]
+	pub index_batch_size: Option<u32>,
+	/// index_flush_interval_ms is how long the indexer holds entries before writing them when it scans slower than it writes, a second when unset.
+	#[serde(default)]
+	pub index_flush_interval_ms: Option<u32>,
+	/// collation is how names are ordered when listing entries, natural and case insensitive by default.
+	#[serde(default)]
+	pub collation: Collation,
+	/// show_hidden_files lists dotfiles and entries hidden by the OS, unless a query says otherwise. They're also counted in the statistics then.
+	#[serde(default)]
+	pub show_hidden_files: bool,
+	/// places groups geotagged photos into places named after the closest city. Naming them needs the geonames dataset, which is downloaded once the first time.
+	#[serde(default)]
+	pub places: bool,
+	/// thumbnail_profiles are extra variants of the thumbnail of every image, generated next to the default one.
+	#[serde(default)]
+	pub thumbnail_profiles: Vec<ThumbnailProfile>,
+	/// share_target is where the bundles of shared files are served from, either the HTTP gateway of this node or a WebDAV or S3 server. Nothing can be shared until it is set.
+	#[serde(default)]
+	pub share_target: Option<ShareTarget>,
 }
 
 impl LibraryConfig {
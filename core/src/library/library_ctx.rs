@@ -1,8 +1,23 @@
-use crate::{job::DynJob, node::NodeConfigManager, prisma::PrismaClient, CoreEvent, NodeContext};
-use std::sync::Arc;
+use crate::{
+	encode::{PreviewRequests, ThumbnailRequests},
+	file::ephemeral::EphemeralCache,
+	job::DynJob,
+	node::{Metrics, NodeConfigManager, UsageCategory, ViewStateManager},
+	prisma::PrismaClient,
+	search::SavedSearchSubscriptions,
+	CoreEvent, NodeContext,
+};
+use log::error;
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+};
 use uuid::Uuid;
 
-use super::LibraryConfig;
+use super::{LibraryConfig, LoadedLibraries};
+
+// where in memory nodes keep the directories of their locations, see LibraryContext::location_dir
+const LOCATION_DIRS_DIR_NAME: &str = "locations";
 
 /// LibraryContext holds context for a library which can be passed around the application.
 #[derive(Clone)]
@@ -41,4 +56,68 @@ impl LibraryContext {
 	pub(crate) fn config(&self) -> Arc<NodeConfigManager> {
 		self.node_context.config.clone()
 	}
+
+	pub(crate) fn view_state(&self) -> Arc<ViewStateManager> {
+		self.node_context.view_state.clone()
+	}
+
+	/// record_usage adds bytes written to disk on behalf of this library to the usage of the node.
+	pub(crate) async fn record_usage(&self, category: UsageCategory, bytes: u64) {
+		if let Err(e) = self
+			.node_context
+			.usage
+			.record(self.id, category, bytes)
+			.await
+		{
+			error!("Failed to record disk usage: {:#?}", e);
+		}
+	}
+
+	pub(crate) fn saved_searches(&self) -> Arc<SavedSearchSubscriptions> {
+		self.node_context.saved_searches.clone()
+	}
+
+	pub(crate) fn metrics(&self) -> Arc<Metrics> {
+		self.node_context.metrics.clone()
+	}
+
+	pub(crate) fn thumbnail_requests(&self) -> Arc<ThumbnailRequests> {
+		self.node_context.thumbnail_requests.clone()
+	}
+
+	pub(crate) fn preview_requests(&self) -> Arc<PreviewRequests> {
+		self.node_context.preview_requests.clone()
+	}
+
+	pub(crate) fn ephemeral_cache(&self) -> Arc<EphemeralCache> {
+		self.node_context.ephemeral_cache.clone()
+	}
+
+	pub(crate) fn loaded_libraries(&self) -> Arc<LoadedLibraries> {
+		self.node_context.loaded_libraries.clone()
+	}
+
+	/// in_memory is set when the node leaves nothing behind once shut down, in the folders of its locations either.
+	pub(crate) fn in_memory(&self) -> bool {
+		self.node_context.in_memory
+	}
+
+	/// location_dir returns the directory named `name` where a location keeps the entries moved out of it, eg: its trash. It is at the root of the location, or under the data directory for an in memory node.
+	pub(crate) fn location_dir(
+		&self,
+		location_id: i32,
+		location_path: &Path,
+		name: &str,
+	) -> PathBuf {
+		match self.in_memory() {
+			true => self
+				.config()
+				.data_directory()
+				.join(LOCATION_DIRS_DIR_NAME)
+				.join(self.id.to_string())
+				.join(location_id.to_string())
+				.join(name),
+			false => location_path.join(name),
+		}
+	}
 }
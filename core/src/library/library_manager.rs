@@ -1,22 +1,33 @@
 use std::{
+	collections::HashMap,
 	env, fs, io,
 	path::{Path, PathBuf},
 	str::FromStr,
 	sync::Arc,
 };
 
+use log::error;
 use thiserror::Error;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use crate::{
+	encode::{ThumbnailProfile, ThumbnailProfilesJob, ThumbnailProfilesJobInit},
+	job::Job,
 	node::Platform,
-	prisma::{self, node},
-	util::db::load_and_migrate,
+	prisma::{self, node, PrismaClient},
+	share::ShareTarget,
+	util::{
+		collation::Collation,
+		db::{load_and_migrate, MigrationError},
+	},
 	ClientQuery, CoreEvent, NodeContext,
 };
 
-use super::{LibraryConfig, LibraryConfigWrapped, LibraryContext};
+use super::{
+	integrity::{check_integrity, restore_snapshot},
+	DegradedLibrary, LibraryConfig, LibraryConfigWrapped, LibraryContext,
+};
 
 /// LibraryManager is a singleton that manages all libraries for a node.
 pub struct LibraryManager {
@@ -24,8 +35,50 @@ pub struct LibraryManager {
 	libraries_dir: PathBuf,
 	/// libraries holds the list of libraries which are currently loaded into the node.
 	libraries: RwLock<Vec<LibraryContext>>,
+	/// degraded holds the libraries whose database was found corrupted when they were loaded.
+	degraded: RwLock<Vec<DegradedLibrary>>,
 	/// node_context holds the context for the node which this library manager is running on.
 	node_context: NodeContext,
+	/// in_memory is set when the databases of the libraries are kept in memory and discarded on shutdown.
+	in_memory: bool,
+}
+
+/// LoadedLibraries holds the databases of the libraries loaded into the node, for the data which is
+/// kept per node rather than per library, like thumbnails, to only be removed once no library
+/// refers to it anymore.
+#[derive(Default)]
+pub struct LoadedLibraries(RwLock<HashMap<Uuid, Arc<PrismaClient>>>);
+
+impl LoadedLibraries {
+	async fn insert(&self, id: Uuid, db: Arc<PrismaClient>) {
+		self.0.write().await.insert(id, db);
+	}
+
+	async fn remove(&self, id: Uuid) {
+		self.0.write().await.remove(&id);
+	}
+
+	pub(crate) async fn dbs(&self) -> Vec<Arc<PrismaClient>> {
+		self.0.read().await.values().cloned().collect()
+	}
+}
+
+/// EditLibraryArgs holds the settings of a library to change, those left unset are kept as they are.
+#[derive(Debug, Default)]
+pub struct EditLibraryArgs {
+	pub name: Option<String>,
+	pub description: Option<String>,
+	pub object_chunk_hashing: Option<bool>,
+	pub secrets_scanning: Option<bool>,
+	pub trash_retention_days: Option<u32>,
+	pub maintenance_interval_days: Option<u32>,
+	pub index_batch_size: Option<u32>,
+	pub index_flush_interval_ms: Option<u32>,
+	pub collation: Option<Collation>,
+	pub show_hidden_files: Option<bool>,
+	pub places: Option<bool>,
+	pub thumbnail_profiles: Option<Vec<ThumbnailProfile>>,
+	pub share_target: Option<ShareTarget>,
 }
 
 #[derive(Error, Debug)]
@@ -42,16 +95,20 @@ pub enum LibraryManagerError {
 	Migration(String),
 	#[error("failed to parse uuid")]
 	Uuid(#[from] uuid::Error),
+	#[error("error opening or migrating the database")]
+	DatabaseMigration(#[from] MigrationError),
 }
 
 impl LibraryManager {
 	pub(crate) async fn new(
 		libraries_dir: PathBuf,
 		node_context: NodeContext,
+		in_memory: bool,
 	) -> Result<Arc<Self>, LibraryManagerError> {
 		fs::create_dir_all(&libraries_dir)?;
 
 		let mut libraries = Vec::new();
+		let mut degraded = Vec::new();
 		for entry in fs::read_dir(&libraries_dir)?
 			.into_iter()
 			.filter_map(|entry| entry.ok())
@@ -85,17 +142,66 @@ impl LibraryManager {
 			}
 
 			let config = LibraryConfig::read(config_path).await?;
-			libraries.push(Self::load(library_id, &db_path, config, node_context.clone()).await?);
+
+			// a library whose database is corrupted is restored from its latest sound snapshot, or
+			// left unloaded, instead of failing on its first query
+			let mut problems = check_integrity(&db_path).await;
+			let mut restored_from = match problems.is_empty() {
+				true => None,
+				false => {
+					error!(
+						"Database of library {} failed its integrity check: {:#?}",
+						library_id, problems
+					);
+					restore_snapshot(&libraries_dir, library_id, &db_path).await
+				}
+			};
+
+			let loaded = match problems.is_empty() || restored_from.is_some() {
+				true => Self::load(
+					library_id,
+					&format!("file:{}", db_path.to_string_lossy()),
+					config.clone(),
+					node_context.clone(),
+				)
+				.await
+				.map_err(|e| e.to_string()),
+				false => Err("No sound snapshot to restore the database from".to_string()),
+			};
+			match loaded {
+				Ok(library) => libraries.push(library),
+				Err(e) => {
+					error!("Failed to load library {}: {}", library_id, e);
+					problems.push(e);
+					restored_from = None;
+				}
+			}
+			if !problems.is_empty() {
+				degraded.push(DegradedLibrary {
+					id: library_id,
+					name: config.name,
+					problems,
+					restored_from,
+				});
+			}
 		}
 
 		let this = Arc::new(Self {
 			libraries: RwLock::new(libraries),
+			degraded: RwLock::new(degraded),
 			libraries_dir,
 			node_context,
+			in_memory,
 		});
 
+		for library in this.degraded.read().await.iter() {
+			this.node_context
+				.emit(CoreEvent::LibraryDegraded(library.clone()))
+				.await;
+		}
+
 		// TODO: Remove this before merging PR -> Currently it exists to make the app usable
-		if this.libraries.read().await.len() == 0 {
+		if this.libraries.read().await.len() == 0 && this.degraded.read().await.is_empty() {
 			this.create(LibraryConfig {
 				name: "My Default Library".into(),
 				..Default::default()
@@ -115,13 +221,18 @@ impl LibraryManager {
 		)
 		.await?;
 
-		let library = Self::load(
-			id,
-			self.libraries_dir.join(format!("{id}.db")),
-			config,
-			self.node_context.clone(),
-		)
-		.await?;
+		// a single connection, as every connection to `:memory:` opens a database of its own
+		let db_url = match self.in_memory {
+			true => "file::memory:?connection_limit=1".to_string(),
+			false => format!(
+				"file:{}",
+				self.libraries_dir
+					.join(format!("{id}.db"))
+					.to_string_lossy()
+			),
+		};
+
+		let library = Self::load(id, &db_url, config, self.node_context.clone()).await?;
 
 		self.libraries.write().await.push(library);
 
@@ -144,6 +255,10 @@ impl LibraryManager {
 			.collect()
 	}
 
+	pub(crate) async fn get_degraded_libraries(&self) -> Vec<DegradedLibrary> {
+		self.degraded.read().await.clone()
+	}
+
 	pub(crate) async fn get_all_libraries_ctx(&self) -> Vec<LibraryContext> {
 		self.libraries.read().await.clone()
 	}
@@ -151,8 +266,7 @@ impl LibraryManager {
 	pub(crate) async fn edit(
 		&self,
 		id: Uuid,
-		name: Option<String>,
-		description: Option<String>,
+		args: EditLibraryArgs,
 	) -> Result<(), LibraryManagerError> {
 		// check library is valid
 		let mut libraries = self.libraries.write().await;
@@ -162,12 +276,48 @@ impl LibraryManager {
 			.ok_or(LibraryManagerError::LibraryNotFound)?;
 
 		// update the library
-		if let Some(name) = name {
+		if let Some(name) = args.name {
 			library.config.name = name;
 		}
-		if let Some(description) = description {
+		if let Some(description) = args.description {
 			library.config.description = description;
 		}
+		if let Some(object_chunk_hashing) = args.object_chunk_hashing {
+			library.config.object_chunk_hashing = object_chunk_hashing;
+		}
+		if let Some(secrets_scanning) = args.secrets_scanning {
+			library.config.secrets_scanning = secrets_scanning;
+		}
+		if let Some(trash_retention_days) = args.trash_retention_days {
+			library.config.trash_retention_days = Some(trash_retention_days);
+		}
+		if let Some(maintenance_interval_days) = args.maintenance_interval_days {
+			library.config.maintenance_interval_days = Some(maintenance_interval_days);
+		}
+		if let Some(index_batch_size) = args.index_batch_size {
+			library.config.index_batch_size = Some(index_batch_size);
+		}
+		if let Some(index_flush_interval_ms) = args.index_flush_interval_ms {
+			library.config.index_flush_interval_ms = Some(index_flush_interval_ms);
+		}
+		if let Some(collation) = args.collation {
+			library.config.collation = collation;
+		}
+		if let Some(show_hidden_files) = args.show_hidden_files {
+			library.config.show_hidden_files = show_hidden_files;
+		}
+		if let Some(places) = args.places {
+			library.config.places = places;
+		}
+		let profiles_changed = args.thumbnail_profiles.as_ref().map_or(false, |profiles| {
+			*profiles != library.config.thumbnail_profiles
+		});
+		if let Some(thumbnail_profiles) = args.thumbnail_profiles {
+			library.config.thumbnail_profiles = thumbnail_profiles;
+		}
+		if let Some(share_target) = args.share_target {
+			library.config.share_target = Some(share_target);
+		}
 
 		LibraryConfig::save(
 			Path::new(&self.libraries_dir).join(format!("{id}.sdlibrary")),
@@ -175,6 +325,15 @@ impl LibraryManager {
 		)
 		.await?;
 
+		if profiles_changed {
+			library
+				.spawn_job(Job::new(
+					ThumbnailProfilesJobInit {},
+					Box::new(ThumbnailProfilesJob {}),
+				))
+				.await;
+		}
+
 		self.node_context
 			.emit(CoreEvent::InvalidateQuery(ClientQuery::GetLibraries))
 			.await;
@@ -189,10 +348,20 @@ impl LibraryManager {
 			.find(|l| l.id == id)
 			.ok_or(LibraryManagerError::LibraryNotFound)?;
 
-		fs::remove_file(Path::new(&self.libraries_dir).join(format!("{}.db", library.id)))?;
+		if !self.in_memory {
+			fs::remove_file(Path::new(&self.libraries_dir).join(format!("{}.db", library.id)))?;
+		}
 		fs::remove_file(Path::new(&self.libraries_dir).join(format!("{}.sdlibrary", library.id)))?;
 
 		libraries.retain(|l| l.id != id);
+		self.node_context.loaded_libraries.remove(id).await;
+
+		if let Err(e) = self.node_context.view_state.forget(id).await {
+			error!(
+				"Failed to remove the view state of library {}: {:#?}",
+				id, e
+			);
+		}
 
 		self.node_context
 			.emit(CoreEvent::InvalidateQuery(ClientQuery::GetLibraries))
@@ -210,18 +379,14 @@ impl LibraryManager {
 			.map(Clone::clone)
 	}
 
-	/// load the library from a given path
+	/// load the library from a given database url
 	pub(crate) async fn load(
 		id: Uuid,
-		db_path: impl AsRef<Path>,
+		db_url: &str,
 		config: LibraryConfig,
 		node_context: NodeContext,
 	) -> Result<LibraryContext, LibraryManagerError> {
-		let db = Arc::new(
-			load_and_migrate(&format!("file:{}", db_path.as_ref().to_string_lossy()))
-				.await
-				.unwrap(),
-		);
+		let db = Arc::new(load_and_migrate(db_url).await?);
 
 		let node_config = node_context.config.get().await;
 
@@ -248,6 +413,11 @@ impl LibraryManager {
 			.exec()
 			.await?;
 
+		node_context
+			.loaded_libraries
+			.insert(id, Arc::clone(&db))
+			.await;
+
 		Ok(LibraryContext {
 			id,
 			config,
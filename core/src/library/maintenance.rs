@@ -0,0 +1,471 @@
+use crate::{
+	encode::{PREVIEW_CACHE_DIR_NAME, THUMBNAIL_CACHE_DIR_NAME},
+	file::ephemeral::{EPHEMERAL_THUMBNAIL_DIR_NAME, EPHEMERAL_THUMBNAIL_RETENTION},
+	job::{
+		Job, JobError, JobReportUpdate, JobResult, JobState, JobStatus, StatefulJob, WorkerContext,
+	},
+	prisma::{self, file, job},
+	CoreEvent,
+};
+use chrono::{Duration, Utc};
+use int_enum::IntEnum;
+use log::{error, info};
+use prisma_client_rust::{prisma_models::PrismaValue, raw};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashSet,
+	fs, io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+use tokio::task::spawn_blocking;
+use ts_rs::TS;
+use walkdir::WalkDir;
+
+use super::{integrity::snapshot_database, LibraryContext, LibraryManager};
+
+pub const LIBRARY_MAINTENANCE_JOB_NAME: &str = "library_maintenance";
+// how often libraries are checked for a maintenance being due
+const MAINTENANCE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(6 * 60 * 60);
+const DEFAULT_MAINTENANCE_INTERVAL_DAYS: u32 = 7;
+// the sync log keeps this many of its newest events, the older ones were sent to the other nodes
+// long ago
+const SYNC_LOG_RETENTION: i64 = 100_000;
+// SQLite takes at most 999 values a query
+const DELETE_BATCH_SIZE: usize = 500;
+
+// What a maintenance of a library removed, sent with LibraryMaintained once it is done
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MaintenanceReport {
+	// files no entry points to anymore, with their media data, chunks and extracted text
+	pub orphaned_files: usize,
+	// thumbnails and previews of files which aren't in their location anymore
+	pub removed_thumbnails: usize,
+	pub removed_sync_events: usize,
+	// by the database and the removed thumbnails
+	pub reclaimed_bytes: u64,
+}
+
+// Keeps the database of a library small and fast: removes what nothing refers to anymore, then
+// has SQLite refresh its statistics and rewrite the database without its free pages. The database
+// is snapshotted last, for the library to be restored if it gets corrupted. Runs every
+// `maintenance_interval_days` or on LibraryMaintenanceRun.
+pub struct LibraryMaintenanceJob {}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct LibraryMaintenanceJobInit {}
+
+#[derive(Serialize, Deserialize)]
+pub struct LibraryMaintenanceJobData {
+	// the size of the database before anything was removed
+	database_size: u64,
+	report: MaintenanceReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum MaintenanceStep {
+	PruneOrphanedFiles,
+	PruneThumbnails,
+	CompactSyncLog,
+	// refreshes the statistics the query planner picks indexes with
+	Analyze,
+	// rewrites the database without its free pages, after the others as it gives back what they
+	// freed
+	Vacuum,
+	// a copy of the database to restore the library from if it gets corrupted
+	Snapshot,
+}
+
+#[derive(Deserialize)]
+struct OrphanedFile {
+	id: i32,
+	cas_id: String,
+}
+
+#[derive(Deserialize)]
+struct CasId {
+	cas_id: String,
+}
+
+#[derive(Deserialize)]
+struct DatabaseSize {
+	size: i64,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for LibraryMaintenanceJob {
+	type Init = LibraryMaintenanceJobInit;
+	type Data = LibraryMaintenanceJobData;
+	type Step = MaintenanceStep;
+
+	fn name(&self) -> &'static str {
+		LIBRARY_MAINTENANCE_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+
+		state.steps = [
+			MaintenanceStep::PruneOrphanedFiles,
+			MaintenanceStep::PruneThumbnails,
+			MaintenanceStep::CompactSyncLog,
+			MaintenanceStep::Analyze,
+			MaintenanceStep::Vacuum,
+			MaintenanceStep::Snapshot,
+		]
+		.into_iter()
+		.collect();
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		state.data = Some(LibraryMaintenanceJobData {
+			database_size: database_size(&library_ctx).await?,
+			report: MaintenanceReport::default(),
+		});
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let data = state.data.as_mut().unwrap();
+
+		match state.steps[0] {
+			MaintenanceStep::PruneOrphanedFiles => {
+				ctx.progress(vec![JobReportUpdate::Message(
+					"Removing orphaned files".to_string(),
+				)]);
+				prune_orphaned_files(&library_ctx, &mut data.report).await?;
+			}
+			MaintenanceStep::PruneThumbnails => {
+				ctx.progress(vec![JobReportUpdate::Message(
+					"Removing orphaned thumbnails".to_string(),
+				)]);
+				prune_thumbnails(&library_ctx, &mut data.report).await?;
+			}
+			MaintenanceStep::CompactSyncLog => {
+				data.report.removed_sync_events = library_ctx
+					.db
+					._execute_raw(raw!(
+						"DELETE FROM sync_events WHERE id <= (SELECT MAX(id) FROM sync_events) - {}",
+						PrismaValue::Int(SYNC_LOG_RETENTION)
+					))
+					.await? as usize;
+			}
+			MaintenanceStep::Analyze => {
+				ctx.progress(vec![JobReportUpdate::Message(
+					"Analyzing the database".to_string(),
+				)]);
+				library_ctx.db._execute_raw(raw!("ANALYZE")).await?;
+			}
+			MaintenanceStep::Vacuum => {
+				ctx.progress(vec![JobReportUpdate::Message(
+					"Compacting the database".to_string(),
+				)]);
+				library_ctx.db._execute_raw(raw!("VACUUM")).await?;
+
+				let size = database_size(&library_ctx).await?;
+				data.report.reclaimed_bytes += data.database_size.saturating_sub(size);
+			}
+			MaintenanceStep::Snapshot => {
+				ctx.progress(vec![JobReportUpdate::Message(
+					"Taking a snapshot of the database".to_string(),
+				)]);
+				snapshot_database(&library_ctx).await?;
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let report = state.data.as_ref().unwrap().report.clone();
+		info!(
+			"Maintained library {}: removed {} orphaned files, {} thumbnails and {} sync events, reclaimed {} bytes",
+			library_ctx.id,
+			report.orphaned_files,
+			report.removed_thumbnails,
+			report.removed_sync_events,
+			report.reclaimed_bytes
+		);
+
+		library_ctx
+			.emit(CoreEvent::LibraryMaintained {
+				library_id: library_ctx.id,
+				report,
+			})
+			.await;
+
+		Ok(())
+	}
+}
+
+async fn database_size(ctx: &LibraryContext) -> Result<u64, prisma::QueryError> {
+	Ok(ctx
+		.db
+		._query_raw::<DatabaseSize>(raw!(
+			"SELECT page_count * page_size AS size FROM pragma_page_count(), pragma_page_size()"
+		))
+		.await?
+		.first()
+		.map_or(0, |row| row.size as u64))
+}
+
+// removes the files no entry points to anymore, and the previews generated for them. Files the
+// user put anything on are kept, as they may still restore their entries from the trash: the held,
+// favorite or important ones, those with a note, and those which were tagged, labeled, put in an
+// album or a space, commented, or given notes or custom field values
+async fn prune_orphaned_files(
+	ctx: &LibraryContext,
+	report: &mut MaintenanceReport,
+) -> Result<(), prisma::QueryError> {
+	let orphans = ctx
+		.db
+		._query_raw::<OrphanedFile>(raw!(
+			"SELECT id, cas_id FROM files
+			WHERE legal_hold = 0 AND favorite = 0 AND important = 0
+			AND (note IS NULL OR note = '')
+			AND NOT EXISTS (SELECT 1 FROM file_paths WHERE file_paths.file_id = files.id)
+			AND NOT EXISTS (SELECT 1 FROM tags_on_file WHERE tags_on_file.file_id = files.id)
+			AND NOT EXISTS (SELECT 1 FROM label_on_file WHERE label_on_file.file_id = files.id)
+			AND NOT EXISTS (SELECT 1 FROM files_in_albums WHERE files_in_albums.file_id = files.id)
+			AND NOT EXISTS (SELECT 1 FROM file_in_space WHERE file_in_space.file_id = files.id)
+			AND NOT EXISTS (SELECT 1 FROM comments WHERE comments.file_id = files.id)
+			AND NOT EXISTS (SELECT 1 FROM notes WHERE notes.file_id = files.id)
+			AND NOT EXISTS (
+				SELECT 1 FROM custom_fields_on_files WHERE custom_fields_on_files.file_id = files.id
+			)"
+		))
+		.await?;
+
+	// the media data, chunks, links and accesses of a file are deleted with it by their foreign keys,
+	// everything the user put on a file kept it above
+	for batch in orphans.chunks(DELETE_BATCH_SIZE) {
+		ctx.db
+			.file()
+			.find_many(vec![file::id::in_vec(
+				batch.iter().map(|orphan| orphan.id).collect(),
+			)])
+			.delete()
+			.exec()
+			.await?;
+	}
+	// the extracted text isn't a relation prisma knows of
+	ctx.db
+		._execute_raw(raw!(
+			"DELETE FROM file_contents_fts WHERE rowid NOT IN (SELECT id FROM files)"
+		))
+		.await?;
+
+	// previews are kept per node, another library may still have a file of the same contents
+	let mut kept = HashSet::new();
+	for db in ctx.loaded_libraries().dbs().await {
+		for batch in orphans.chunks(DELETE_BATCH_SIZE) {
+			kept.extend(
+				db.file()
+					.find_many(vec![file::cas_id::in_vec(
+						batch.iter().map(|orphan| orphan.cas_id.clone()).collect(),
+					)])
+					.exec()
+					.await?
+					.into_iter()
+					.map(|file| file.cas_id),
+			);
+		}
+	}
+
+	let preview_dir = ctx.config().data_directory().join(PREVIEW_CACHE_DIR_NAME);
+	let previews = orphans
+		.iter()
+		.filter(|orphan| !kept.contains(&orphan.cas_id))
+		.map(|orphan| preview_dir.join(&orphan.cas_id).with_extension("mp4"))
+		.collect::<Vec<_>>();
+	let (count, bytes) =
+		spawn_blocking(move || remove_files(previews.iter().map(PathBuf::as_path)))
+			.await
+			.unwrap_or_default();
+
+	report.orphaned_files = orphans.len();
+	report.removed_thumbnails += count;
+	report.reclaimed_bytes += bytes;
+
+	Ok(())
+}
+
+// removes the thumbnails of the locations of the library whose file isn't in the location
+// anymore. Directories of locations which aren't in the library are left alone, they may be of
+// another library. Location ids are only unique within a library while thumbnails are kept per
+// node, so a thumbnail is only removed once no loaded library has its file in a location of that
+// id.
+async fn prune_thumbnails(
+	ctx: &LibraryContext,
+	report: &mut MaintenanceReport,
+) -> Result<(), JobError> {
+	let thumbnail_dir = ctx.config().data_directory().join(THUMBNAIL_CACHE_DIR_NAME);
+	let locations = ctx.db.location().find_many(vec![]).exec().await?;
+
+	for location in locations {
+		let location_dir = thumbnail_dir.join(location.id.to_string());
+		if !location_dir.exists() {
+			continue;
+		}
+
+		let cas_ids = location_cas_ids(ctx, location.id).await?;
+		let (count, bytes) =
+			spawn_blocking(move || prune_thumbnail_dir(&location_dir, &cas_ids)).await?;
+		report.removed_thumbnails += count;
+		report.reclaimed_bytes += bytes;
+	}
+
+	// the thumbnails of entries browsed outside of locations belong to no library
+	let ephemeral_dir = thumbnail_dir.join(EPHEMERAL_THUMBNAIL_DIR_NAME);
+	let (count, bytes) = spawn_blocking(move || prune_expired_thumbnails(&ephemeral_dir)).await?;
+	report.removed_thumbnails += count;
+	report.reclaimed_bytes += bytes;
+
+	Ok(())
+}
+
+async fn location_cas_ids(
+	ctx: &LibraryContext,
+	location_id: i32,
+) -> Result<HashSet<String>, prisma::QueryError> {
+	let mut cas_ids = HashSet::new();
+	for db in ctx.loaded_libraries().dbs().await {
+		cas_ids.extend(
+			db._query_raw::<CasId>(raw!(
+				"SELECT DISTINCT files.cas_id FROM file_paths
+				JOIN files ON files.id = file_paths.file_id
+				WHERE file_paths.location_id = {}",
+				PrismaValue::Int(location_id as i64)
+			))
+			.await?
+			.into_iter()
+			.map(|row| row.cas_id),
+		);
+	}
+	Ok(cas_ids)
+}
+
+fn prune_expired_thumbnails(dir: &Path) -> (usize, u64) {
+	let expired = fs::read_dir(dir)
+		.into_iter()
+		.flatten()
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| {
+			entry
+				.metadata()
+				.and_then(|metadata| metadata.modified())
+				.ok()
+				.and_then(|modified| modified.elapsed().ok())
+				.map_or(false, |age| age > EPHEMERAL_THUMBNAIL_RETENTION)
+		})
+		.map(|entry| entry.path())
+		.collect::<Vec<_>>();
+
+	remove_files(expired.iter().map(PathBuf::as_path))
+}
+
+// thumbnails are named after the cas id of their file, with the variants of the thumbnail
+// profiles in subdirectories and thumbstrips suffixed
+fn prune_thumbnail_dir(dir: &Path, cas_ids: &HashSet<String>) -> (usize, u64) {
+	let stale = WalkDir::new(dir)
+		.into_iter()
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.file_type().is_file())
+		.map(|entry| entry.into_path())
+		.filter(|path| {
+			let stem = path
+				.file_stem()
+				.map(|stem| stem.to_string_lossy().to_string())
+				.unwrap_or_default();
+			let cas_id = stem.strip_suffix("_strip").unwrap_or(&stem);
+			!cas_ids.contains(cas_id)
+		})
+		.collect::<Vec<_>>();
+
+	remove_files(stale.iter().map(PathBuf::as_path))
+}
+
+// removes the files, returning how many there were and their size
+fn remove_files<'a>(paths: impl Iterator<Item = &'a Path>) -> (usize, u64) {
+	let mut removed = (0, 0);
+	for path in paths {
+		let size = match fs::metadata(path) {
+			Ok(metadata) => metadata.len(),
+			Err(_) => continue,
+		};
+		match fs::remove_file(path) {
+			Ok(()) => {
+				removed.0 += 1;
+				removed.1 += size;
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => error!("Failed to remove {:?}: {:#?}", path, e),
+		}
+	}
+	removed
+}
+
+fn interval_days(ctx: &LibraryContext) -> u32 {
+	ctx.config
+		.maintenance_interval_days
+		.unwrap_or(DEFAULT_MAINTENANCE_INTERVAL_DAYS)
+}
+
+pub async fn run_maintenance(ctx: &LibraryContext) {
+	ctx.spawn_job(Job::new(
+		LibraryMaintenanceJobInit::default(),
+		Box::new(LibraryMaintenanceJob {}),
+	))
+	.await;
+}
+
+// periodically maintains every library which wasn't within its interval, a run which failed or
+// was canceled is tried again at the next check
+pub async fn watch_maintenance(library_manager: Arc<LibraryManager>) {
+	loop {
+		for ctx in library_manager.get_all_libraries_ctx().await {
+			let days = interval_days(&ctx);
+			if days == 0 {
+				continue;
+			}
+
+			match ctx
+				.db
+				.job()
+				.find_first(vec![
+					job::name::equals(LIBRARY_MAINTENANCE_JOB_NAME.to_string()),
+					job::status::not(JobStatus::Failed.int_value()),
+					job::status::not(JobStatus::Canceled.int_value()),
+					job::date_created::gt((Utc::now() - Duration::days(days as i64)).into()),
+				])
+				.exec()
+				.await
+			{
+				Ok(Some(_)) => {}
+				Ok(None) => run_maintenance(&ctx).await,
+				Err(e) => error!("Failed to read the maintenance runs: {:#?}", e),
+			}
+		}
+
+		tokio::time::sleep(MAINTENANCE_CHECK_INTERVAL).await;
+	}
+}
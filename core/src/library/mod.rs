@@ -1,14 +1,18 @@
 use crate::{prisma, sys::SysError};
 use thiserror::Error;
 
+mod integrity;
 mod library_config;
 mod library_ctx;
 mod library_manager;
+mod maintenance;
 mod statistics;
 
+pub use integrity::DegradedLibrary;
 pub use library_config::*;
 pub use library_ctx::*;
 pub use library_manager::*;
+pub use maintenance::*;
 pub use statistics::*;
 
 #[derive(Error, Debug)]
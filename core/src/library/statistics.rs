@@ -1,5 +1,6 @@
 use crate::{prisma::statistics::*, sys::Volume};
 use fs_extra::dir::get_size;
+use prisma_client_rust::{prisma_models::PrismaValue, raw};
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 use ts_rs::TS;
@@ -74,7 +75,23 @@ impl Statistics {
 
 		let thumbnail_folder_size = get_size(ctx.config().data_directory().join("thumbnails"));
 
+		// hidden files are only counted when the library lists them
+		#[derive(Deserialize)]
+		struct FileCount {
+			count: i32,
+		}
+		let total_file_count = ctx
+			.db
+			._query_raw::<FileCount>(raw!(
+				"SELECT COUNT(*) AS count FROM file_paths WHERE is_dir = false AND (hidden = false OR {})",
+				PrismaValue::Boolean(ctx.config.show_hidden_files)
+			))
+			.await?
+			.first()
+			.map_or(0, |row| row.count);
+
 		let statistics = Statistics {
+			total_file_count,
 			library_db_size: library_db_size.to_string(),
 			total_bytes_free: available_capacity.to_string(),
 			total_bytes_capacity: total_capacity.to_string(),
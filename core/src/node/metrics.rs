@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	fmt::{Display, Write},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, RwLock,
+	},
+	time::Duration,
+};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::job::JobStatus;
+
+/// Timings counts operations and the total time they took.
+#[derive(Default)]
+struct Timings {
+	count: AtomicU64,
+	micros: AtomicU64,
+}
+
+impl Timings {
+	fn record(&self, elapsed: Duration) {
+		self.count.fetch_add(1, Ordering::Relaxed);
+		self.micros
+			.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+	}
+
+	fn seconds(&self) -> f64 {
+		self.micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+	}
+}
+
+#[derive(Default)]
+struct LibraryMetrics {
+	jobs_completed: AtomicU64,
+	jobs_failed: AtomicU64,
+	jobs_paused: AtomicU64,
+	queries: Timings,
+	commands: Timings,
+	indexed_rows: AtomicU64,
+	// a batch of rows is a single write
+	index_writes: Timings,
+}
+
+/// Metrics holds the counters of this node since it started. They are plain atomics, so recording is cheap enough for every query and command.
+#[derive(Default)]
+pub struct Metrics {
+	// the queries and commands of the node itself are kept under None
+	libraries: RwLock<HashMap<Option<Uuid>, Arc<LibraryMetrics>>>,
+}
+
+impl Metrics {
+	fn library(&self, library_id: Option<Uuid>) -> Arc<LibraryMetrics> {
+		if let Some(metrics) = self.libraries.read().unwrap().get(&library_id) {
+			return metrics.clone();
+		}
+
+		self.libraries
+			.write()
+			.unwrap()
+			.entry(library_id)
+			.or_default()
+			.clone()
+	}
+
+	pub(crate) fn record_query(&self, library_id: Option<Uuid>, elapsed: Duration) {
+		self.library(library_id).queries.record(elapsed);
+	}
+
+	pub(crate) fn record_command(&self, library_id: Option<Uuid>, elapsed: Duration) {
+		self.library(library_id).commands.record(elapsed);
+	}
+
+	pub(crate) fn record_index_write(&self, library_id: Uuid, rows: usize, elapsed: Duration) {
+		let metrics = self.library(Some(library_id));
+		metrics
+			.indexed_rows
+			.fetch_add(rows as u64, Ordering::Relaxed);
+		metrics.index_writes.record(elapsed);
+	}
+
+	/// record_job counts a job of a library which stopped running, by the status it ended with.
+	pub(crate) fn record_job(&self, library_id: Uuid, status: JobStatus) {
+		let metrics = self.library(Some(library_id));
+		let counter = match status {
+			JobStatus::Completed => &metrics.jobs_completed,
+			JobStatus::Failed => &metrics.jobs_failed,
+			JobStatus::Paused => &metrics.jobs_paused,
+			_ => return,
+		};
+		counter.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub(crate) fn snapshot(&self, queued_jobs: usize, running_jobs: usize) -> MetricsSnapshot {
+		let mut libraries = self
+			.libraries
+			.read()
+			.unwrap()
+			.iter()
+			.map(|(library_id, metrics)| LibraryMetricsSnapshot {
+				library_id: *library_id,
+				jobs_completed: metrics.jobs_completed.load(Ordering::Relaxed),
+				jobs_failed: metrics.jobs_failed.load(Ordering::Relaxed),
+				jobs_paused: metrics.jobs_paused.load(Ordering::Relaxed),
+				queries: metrics.queries.count.load(Ordering::Relaxed),
+				query_seconds: metrics.queries.seconds(),
+				commands: metrics.commands.count.load(Ordering::Relaxed),
+				command_seconds: metrics.commands.seconds(),
+				indexed_rows: metrics.indexed_rows.load(Ordering::Relaxed),
+				index_write_seconds: metrics.index_writes.seconds(),
+			})
+			.collect::<Vec<_>>();
+		libraries.sort_by_key(|library| library.library_id);
+
+		MetricsSnapshot {
+			queued_jobs,
+			running_jobs,
+			libraries,
+		}
+	}
+}
+
+/// MetricsSnapshot is the state of the counters of the node at the time it was taken.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MetricsSnapshot {
+	pub queued_jobs: usize,
+	pub running_jobs: usize,
+	pub libraries: Vec<LibraryMetricsSnapshot>,
+}
+
+/// LibraryMetricsSnapshot holds the counters of a library, or of the node itself when library_id is unset.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LibraryMetricsSnapshot {
+	pub library_id: Option<Uuid>,
+	pub jobs_completed: u64,
+	pub jobs_failed: u64,
+	pub jobs_paused: u64,
+	pub queries: u64,
+	pub query_seconds: f64,
+	pub commands: u64,
+	pub command_seconds: f64,
+	pub indexed_rows: u64,
+	pub index_write_seconds: f64,
+}
+
+impl MetricsSnapshot {
+	/// to_openmetrics renders the snapshot in the OpenMetrics text format, for Prometheus to scrape.
+	pub fn to_openmetrics(&self) -> String {
+		let mut out = String::new();
+
+		writeln!(out, "# TYPE sd_job_queue_depth gauge").ok();
+		writeln!(out, "sd_job_queue_depth {}", self.queued_jobs).ok();
+		writeln!(out, "# TYPE sd_jobs_running gauge").ok();
+		writeln!(out, "sd_jobs_running {}", self.running_jobs).ok();
+
+		writeln!(out, "# TYPE sd_jobs counter").ok();
+		for library in &self.libraries {
+			if let Some(library_id) = library.library_id {
+				for (status, count) in [
+					("completed", library.jobs_completed),
+					("failed", library.jobs_failed),
+					("paused", library.jobs_paused),
+				] {
+					writeln!(
+						out,
+						"sd_jobs_total{{library=\"{}\",status=\"{}\"}} {}",
+						library_id, status, count
+					)
+					.ok();
+				}
+			}
+		}
+
+		counter(&mut out, "sd_queries", &self.libraries, |l| l.queries);
+		counter(&mut out, "sd_query_seconds", &self.libraries, |l| {
+			l.query_seconds
+		});
+		counter(&mut out, "sd_commands", &self.libraries, |l| l.commands);
+		counter(&mut out, "sd_command_seconds", &self.libraries, |l| {
+			l.command_seconds
+		});
+		counter(&mut out, "sd_indexed_rows", &self.libraries, |l| {
+			l.indexed_rows
+		});
+		counter(&mut out, "sd_index_write_seconds", &self.libraries, |l| {
+			l.index_write_seconds
+		});
+
+		out.push_str("# EOF\n");
+		out
+	}
+}
+
+// the queries and commands of the node itself have no library label
+fn counter<T: Display>(
+	out: &mut String,
+	name: &str,
+	libraries: &[LibraryMetricsSnapshot],
+	value: impl Fn(&LibraryMetricsSnapshot) -> T,
+) {
+	writeln!(out, "# TYPE {} counter", name).ok();
+	for library in libraries {
+		let labels = library
+			.library_id
+			.map(|library_id| format!("{{library=\"{}\"}}", library_id))
+			.unwrap_or_default();
+		writeln!(out, "{}_total{} {}", name, labels, value(library)).ok();
+	}
+}
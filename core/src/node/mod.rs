@@ -5,8 +5,14 @@ use ts_rs::TS;
 use uuid::Uuid;
 
 mod config;
+mod metrics;
+mod usage;
+mod view_state;
 use crate::prisma::node;
 pub use config::*;
+pub use metrics::*;
+pub use usage::*;
+pub use view_state::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
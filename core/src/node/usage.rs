@@ -0,0 +1,106 @@
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+	fs::File,
+	io::{BufReader, Write},
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+use tokio::sync::RwLock;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use super::NodeConfigError;
+
+/// USAGE_CONFIG_NAME is the name of the file which stores the usage rollups of this node
+pub const USAGE_CONFIG_NAME: &str = "usage.sdconfig";
+/// MAX_DAYS is the number of days of usage kept, older rollups are dropped.
+const MAX_DAYS: i64 = 90;
+
+/// UsageCategory is what Spacedrive itself wrote to disk.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, TS, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum UsageCategory {
+	Thumbnails,
+	Thumbstrips,
+	Previews,
+}
+
+/// DailyUsage is the number of bytes written for a category of a library during a day (UTC).
+#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct DailyUsage {
+	pub date: NaiveDate,
+	pub library_id: Uuid,
+	pub category: UsageCategory,
+	pub bytes: u64,
+}
+
+/// UsageManager keeps daily rollups of the disk usage of this node and persists them next to the node config, as every device tracks its own usage.
+pub struct UsageManager(RwLock<Vec<DailyUsage>>, PathBuf);
+
+impl UsageManager {
+	pub(crate) async fn new(data_path: PathBuf) -> Result<Arc<Self>, NodeConfigError> {
+		Ok(Arc::new(Self(
+			RwLock::new(Self::read(&data_path)?),
+			data_path,
+		)))
+	}
+
+	/// record adds bytes written for a library to the rollup of the current day.
+	pub(crate) async fn record(
+		&self,
+		library_id: Uuid,
+		category: UsageCategory,
+		bytes: u64,
+	) -> Result<(), NodeConfigError> {
+		let today = Utc::now().naive_utc().date();
+		let mut usage = self.0.write().await;
+
+		match usage.iter_mut().find(|day| {
+			day.date == today && day.library_id == library_id && day.category == category
+		}) {
+			Some(day) => day.bytes += bytes,
+			None => usage.push(DailyUsage {
+				date: today,
+				library_id,
+				category,
+				bytes,
+			}),
+		}
+
+		let oldest = today - Duration::days(MAX_DAYS);
+		usage.retain(|day| day.date > oldest);
+
+		Self::save(&self.1, &usage)
+	}
+
+	/// get returns the rollups of the last `days` days, today included.
+	pub(crate) async fn get(&self, days: u32) -> Vec<DailyUsage> {
+		let since = Utc::now().naive_utc().date() - Duration::days(days as i64);
+
+		self.0
+			.read()
+			.await
+			.iter()
+			.filter(|day| day.date > since)
+			.cloned()
+			.collect()
+	}
+
+	fn read(base_path: &Path) -> Result<Vec<DailyUsage>, NodeConfigError> {
+		let path = base_path.join(USAGE_CONFIG_NAME);
+
+		match path.exists() {
+			true => Ok(serde_json::from_reader(BufReader::new(File::open(&path)?))?),
+			false => Ok(Vec::new()),
+		}
+	}
+
+	fn save(base_path: &Path, usage: &[DailyUsage]) -> Result<(), NodeConfigError> {
+		let path = base_path.join(USAGE_CONFIG_NAME);
+		File::create(path)?.write_all(serde_json::to_string(usage)?.as_bytes())?;
+		Ok(())
+	}
+}
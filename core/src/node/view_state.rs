@@ -0,0 +1,159 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	fs::File,
+	io::{BufReader, Write},
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+use tokio::sync::RwLock;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use super::NodeConfigError;
+
+/// VIEW_STATE_CONFIG_NAME is the name of the file which stores the explorer view state of this node
+pub const VIEW_STATE_CONFIG_NAME: &str = "view_state.sdconfig";
+/// MAX_LAST_OPENED is the number of recently opened directories remembered per library.
+const MAX_LAST_OPENED: usize = 20;
+/// MAX_DIRECTORIES is the number of directories whose view state is remembered per library, the least recently changed are forgotten first.
+const MAX_DIRECTORIES: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, TS, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum ExplorerLayout {
+	Grid,
+	List,
+}
+
+/// ExplorerPath identifies a directory within a location.
+#[derive(Debug, Serialize, Deserialize, Clone, TS, PartialEq, Eq)]
+#[ts(export)]
+pub struct ExplorerPath {
+	pub location_id: i32,
+	pub path: String,
+}
+
+/// DirectoryViewState is how a directory was last displayed on this node.
+#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct DirectoryViewState {
+	/// scroll_anchor is the id of the file path at the top of the view, which survives items being added above it unlike a pixel offset.
+	pub scroll_anchor: Option<i32>,
+	pub layout: Option<ExplorerLayout>,
+	pub date_modified: DateTime<Utc>,
+}
+
+/// LibraryViewState is the explorer state of a library on this node, so clients can restore where the user left off.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, TS)]
+#[ts(export)]
+pub struct LibraryViewState {
+	/// last_opened holds the most recently opened directories, newest first.
+	pub last_opened: Vec<ExplorerPath>,
+	/// directories is keyed by "<location_id>:<path>".
+	pub directories: HashMap<String, DirectoryViewState>,
+}
+
+/// ViewStateManager holds the view state of every library on this node and persists it next to the node config. It is not stored in the library database as it is specific to each device.
+pub struct ViewStateManager(RwLock<HashMap<Uuid, LibraryViewState>>, PathBuf);
+
+impl ViewStateManager {
+	pub(crate) async fn new(data_path: PathBuf) -> Result<Arc<Self>, NodeConfigError> {
+		Ok(Arc::new(Self(
+			RwLock::new(Self::read(&data_path)?),
+			data_path,
+		)))
+	}
+
+	/// get returns the view state of a library, which is empty if the library was never opened on this node.
+	pub(crate) async fn get(&self, library_id: Uuid) -> LibraryViewState {
+		self.0
+			.read()
+			.await
+			.get(&library_id)
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	/// opened moves a directory to the front of the recently opened directories of a library.
+	pub(crate) async fn opened(
+		&self,
+		library_id: Uuid,
+		path: ExplorerPath,
+	) -> Result<(), NodeConfigError> {
+		let mut states = self.0.write().await;
+		let state = states.entry(library_id).or_default();
+
+		state.last_opened.retain(|opened| *opened != path);
+		state.last_opened.insert(0, path);
+		state.last_opened.truncate(MAX_LAST_OPENED);
+
+		Self::save(&self.1, &states)
+	}
+
+	/// set_directory updates the view state of a directory, fields left as `None` are kept as they were.
+	pub(crate) async fn set_directory(
+		&self,
+		library_id: Uuid,
+		path: ExplorerPath,
+		scroll_anchor: Option<i32>,
+		layout: Option<ExplorerLayout>,
+	) -> Result<(), NodeConfigError> {
+		let mut states = self.0.write().await;
+		let state = states.entry(library_id).or_default();
+
+		let directory = state
+			.directories
+			.entry(format!("{}:{}", path.location_id, path.path))
+			.or_insert(DirectoryViewState {
+				scroll_anchor: None,
+				layout: None,
+				date_modified: Utc::now(),
+			});
+		directory.scroll_anchor = scroll_anchor.or(directory.scroll_anchor);
+		directory.layout = layout.or(directory.layout);
+		directory.date_modified = Utc::now();
+
+		if state.directories.len() > MAX_DIRECTORIES {
+			if let Some(oldest) = state
+				.directories
+				.iter()
+				.min_by_key(|(_, directory)| directory.date_modified)
+				.map(|(key, _)| key.clone())
+			{
+				state.directories.remove(&oldest);
+			}
+		}
+
+		Self::save(&self.1, &states)
+	}
+
+	/// forget removes the view state of a library, used when the library is deleted.
+	pub(crate) async fn forget(&self, library_id: Uuid) -> Result<(), NodeConfigError> {
+		let mut states = self.0.write().await;
+		if states.remove(&library_id).is_some() {
+			Self::save(&self.1, &states)?;
+		}
+		Ok(())
+	}
+
+	fn read(base_path: &Path) -> Result<HashMap<Uuid, LibraryViewState>, NodeConfigError> {
+		let path = base_path.join(VIEW_STATE_CONFIG_NAME);
+
+		match path.exists() {
+			true => Ok(serde_json::from_reader(BufReader::new(File::open(&path)?))?),
+			false => Ok(HashMap::new()),
+		}
+	}
+
+	fn save(
+		base_path: &Path,
+		states: &HashMap<Uuid, LibraryViewState>,
+	) -> Result<(), NodeConfigError> {
+		let path = base_path.join(VIEW_STATE_CONFIG_NAME);
+		File::create(path)?.write_all(serde_json::to_string(states)?.as_bytes())?;
+		Ok(())
+	}
+}
@@ -0,0 +1,380 @@
+use crate::{
+	file::FilePath,
+	library::LibraryContext,
+	prisma::{self, file_path, place},
+	ClientQuery, CoreEvent, LibraryQuery,
+};
+use chrono::{DateTime, Utc};
+use log::info;
+use prisma_client_rust::{prisma_models::PrismaValue, raw, raw::Raw};
+use serde::{Deserialize, Serialize};
+use std::{
+	cmp::Ordering,
+	collections::HashMap,
+	io::{self, Cursor, Read},
+	path::{Path, PathBuf},
+};
+use thiserror::Error;
+use tokio::{fs, task::block_in_place};
+use ts_rs::TS;
+
+// every city above 15000 inhabitants, about 25000 of them
+const GEONAMES_URL: &str = "https://download.geonames.org/export/dump/cities15000.zip";
+const GEONAMES_FILE_NAME: &str = "cities15000.txt";
+const GEONAMES_DIR_NAME: &str = "geonames";
+// a photo taken this close to a place belongs to it
+const PLACE_RADIUS_KM: f64 = 2.0;
+// places further than this from every city in the dataset are left unnamed
+const MAX_CITY_DISTANCE_KM: f64 = 50.0;
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+// Where photos were taken, named after the closest city
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Place {
+	pub id: i32,
+	pub name: Option<String>,
+	// ISO 3166 alpha-2, eg: "FR"
+	pub country_code: Option<String>,
+	// the center of the photos of the place
+	pub latitude: f64,
+	pub longitude: f64,
+	pub photo_count: i32,
+	pub date_created: DateTime<Utc>,
+}
+
+#[derive(Error, Debug)]
+pub enum PlacesError {
+	#[error("Place not found (id: {0})")]
+	PlaceNotFound(i32),
+	#[error("Failed to download the geonames dataset: {0}")]
+	Download(#[from] reqwest::Error),
+	#[error("Failed to unpack the geonames dataset: {0}")]
+	Archive(#[from] zip::result::ZipError),
+	#[error("I/O error: {0}")]
+	Io(#[from] io::Error),
+	#[error("Database error")]
+	DatabaseError(#[from] prisma::QueryError),
+}
+
+struct City {
+	name: String,
+	country_code: String,
+	latitude: f64,
+	longitude: f64,
+}
+
+impl City {
+	// a line of the geonames dump, tab separated
+	fn parse(line: &str) -> Option<Self> {
+		let columns = line.split('\t').collect::<Vec<_>>();
+		Some(Self {
+			name: columns.get(1)?.to_string(),
+			latitude: columns.get(4)?.parse().ok()?,
+			longitude: columns.get(5)?.parse().ok()?,
+			country_code: columns.get(8)?.to_string(),
+		})
+	}
+}
+
+#[derive(Deserialize)]
+struct GeotaggedPhoto {
+	id: i32,
+	latitude: f64,
+	longitude: f64,
+}
+
+#[derive(Deserialize)]
+struct PlacePhotoCount {
+	place_id: i32,
+	count: i32,
+}
+
+// A place with the photos joining it
+struct Cluster {
+	place_id: Option<i32>,
+	latitude: f64,
+	longitude: f64,
+	count: i32,
+	photos: Vec<i32>,
+}
+
+impl Cluster {
+	// moves the center towards the photo, weighted by how many photos are already in
+	fn add(&mut self, photo: &GeotaggedPhoto) {
+		let count = self.count as f64;
+		self.latitude = (self.latitude * count + photo.latitude) / (count + 1.0);
+		self.longitude = (self.longitude * count + photo.longitude) / (count + 1.0);
+		self.count += 1;
+		self.photos.push(photo.id);
+	}
+}
+
+// great circle distance between two coordinates in decimal degrees
+fn distance_km(lat_a: f64, lon_a: f64, lat_b: f64, lon_b: f64) -> f64 {
+	let (lat_a, lat_b) = (lat_a.to_radians(), lat_b.to_radians());
+	let d_lat = lat_b - lat_a;
+	let d_lon = (lon_b - lon_a).to_radians();
+
+	let a = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+	2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+fn dataset_path(data_dir: &Path) -> PathBuf {
+	data_dir.join(GEONAMES_DIR_NAME).join(GEONAMES_FILE_NAME)
+}
+
+// the dataset is downloaded the first time it is needed, or can be put there beforehand
+// for nodes without network access
+async fn load_cities(data_dir: &Path) -> Result<Vec<City>, PlacesError> {
+	let path = dataset_path(data_dir);
+	if !path.exists() {
+		download_cities(&path).await?;
+	}
+
+	let contents = fs::read_to_string(&path).await?;
+	Ok(contents.lines().filter_map(City::parse).collect())
+}
+
+async fn download_cities(path: &Path) -> Result<(), PlacesError> {
+	info!("Downloading the geonames dataset to {:?}", path);
+
+	let archive = reqwest::get(GEONAMES_URL)
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	let contents = block_in_place(|| -> Result<Vec<u8>, PlacesError> {
+		let mut archive = zip::ZipArchive::new(Cursor::new(archive))?;
+		let mut contents = vec![];
+		archive
+			.by_name(GEONAMES_FILE_NAME)?
+			.read_to_end(&mut contents)?;
+		Ok(contents)
+	})?;
+
+	// written aside first, so an interrupted download isn't mistaken for the dataset
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent).await?;
+	}
+	let partial_path = path.with_extension("part");
+	fs::write(&partial_path, contents).await?;
+	fs::rename(&partial_path, path).await?;
+
+	Ok(())
+}
+
+async fn get_photo_counts(ctx: &LibraryContext) -> Result<HashMap<i32, i32>, prisma::QueryError> {
+	Ok(ctx
+		.db
+		._query_raw::<PlacePhotoCount>(raw!(
+			"SELECT place_id, COUNT(*) AS count FROM media_data
+			WHERE place_id IS NOT NULL GROUP BY place_id"
+		))
+		.await?
+		.into_iter()
+		.map(|count| (count.place_id, count.count))
+		.collect())
+}
+
+// adds the geotagged photos which aren't in a place yet to the closest place within
+// PLACE_RADIUS_KM, or to new places named after the closest city. Places keep their id and
+// name as photos join them, so clients can hold onto them.
+pub(crate) async fn assign_places(ctx: &LibraryContext) -> Result<(), PlacesError> {
+	let photos = ctx
+		.db
+		._query_raw::<GeotaggedPhoto>(raw!(
+			"SELECT id, latitude, longitude FROM media_data
+			WHERE place_id IS NULL AND latitude IS NOT NULL AND longitude IS NOT NULL"
+		))
+		.await?;
+	if photos.is_empty() {
+		return Ok(());
+	}
+
+	let photo_counts = get_photo_counts(ctx).await?;
+	let mut clusters = ctx
+		.db
+		.place()
+		.find_many(vec![])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|place| Cluster {
+			place_id: Some(place.id),
+			latitude: place.latitude,
+			longitude: place.longitude,
+			count: photo_counts.get(&place.id).copied().unwrap_or(0),
+			photos: vec![],
+		})
+		.collect::<Vec<_>>();
+
+	for photo in &photos {
+		let closest = clusters
+			.iter_mut()
+			.map(|cluster| {
+				let distance = distance_km(
+					cluster.latitude,
+					cluster.longitude,
+					photo.latitude,
+					photo.longitude,
+				);
+				(cluster, distance)
+			})
+			.filter(|(_, distance)| *distance <= PLACE_RADIUS_KM)
+			.min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+		match closest {
+			Some((cluster, _)) => cluster.add(photo),
+			None => clusters.push(Cluster {
+				place_id: None,
+				latitude: photo.latitude,
+				longitude: photo.longitude,
+				count: 1,
+				photos: vec![photo.id],
+			}),
+		}
+	}
+
+	// the dataset is only needed to name new places
+	let cities = if clusters.iter().any(|cluster| cluster.place_id.is_none()) {
+		load_cities(&ctx.config().data_directory()).await?
+	} else {
+		vec![]
+	};
+
+	for cluster in clusters
+		.into_iter()
+		.filter(|cluster| !cluster.photos.is_empty())
+	{
+		let place_id = match cluster.place_id {
+			Some(place_id) => {
+				ctx.db
+					.place()
+					.find_unique(place::id::equals(place_id))
+					.update(vec![
+						place::latitude::set(cluster.latitude),
+						place::longitude::set(cluster.longitude),
+					])
+					.exec()
+					.await?;
+				place_id
+			}
+			None => {
+				let city = cities
+					.iter()
+					.map(|city| {
+						let distance = distance_km(
+							city.latitude,
+							city.longitude,
+							cluster.latitude,
+							cluster.longitude,
+						);
+						(city, distance)
+					})
+					.filter(|(_, distance)| *distance <= MAX_CITY_DISTANCE_KM)
+					.min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+					.map(|(city, _)| city);
+
+				ctx.db
+					.place()
+					.create(
+						place::latitude::set(cluster.latitude),
+						place::longitude::set(cluster.longitude),
+						vec![
+							place::name::set(city.map(|city| city.name.clone())),
+							place::country_code::set(city.map(|city| city.country_code.clone())),
+						],
+					)
+					.exec()
+					.await?
+					.id
+			}
+		};
+
+		let mut values = vec![PrismaValue::Int(place_id as i64)];
+		values.extend(cluster.photos.iter().map(|id| PrismaValue::Int(*id as i64)));
+		ctx.db
+			._execute_raw(Raw::new(
+				&format!(
+					"UPDATE media_data SET place_id = {{}} WHERE id IN ({})",
+					vec!["{}"; cluster.photos.len()].join(", ")
+				),
+				values,
+			))
+			.await?;
+	}
+
+	info!("Grouped {} photos into places", photos.len());
+
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetPlaces,
+	}))
+	.await;
+
+	Ok(())
+}
+
+// places with photos left, the most photographed first
+pub async fn get_places(ctx: &LibraryContext) -> Result<Vec<Place>, PlacesError> {
+	let photo_counts = get_photo_counts(ctx).await?;
+
+	let mut places = ctx
+		.db
+		.place()
+		.find_many(vec![])
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|place| {
+			let photo_count = *photo_counts.get(&place.id)?;
+			Some(Place {
+				id: place.id,
+				name: place.name,
+				country_code: place.country_code,
+				latitude: place.latitude,
+				longitude: place.longitude,
+				photo_count,
+				date_created: place.date_created.into(),
+			})
+		})
+		.collect::<Vec<_>>();
+	places.sort_by(|a, b| b.photo_count.cmp(&a.photo_count));
+
+	Ok(places)
+}
+
+pub async fn get_photos_by_place(
+	ctx: &LibraryContext,
+	place_id: i32,
+) -> Result<Vec<FilePath>, PlacesError> {
+	let place = ctx
+		.db
+		.place()
+		.find_unique(place::id::equals(place_id))
+		.with(place::media_data::fetch(vec![]))
+		.exec()
+		.await?
+		.ok_or(PlacesError::PlaceNotFound(place_id))?;
+
+	// media data shares the id of its file
+	let file_ids = place
+		.media_data
+		.unwrap_or_default()
+		.into_iter()
+		.map(|media_data| media_data.id)
+		.collect();
+
+	Ok(ctx
+		.db
+		.file_path()
+		.find_many(vec![file_path::file_id::in_vec(file_ids)])
+		.with(file_path::file::fetch().with(prisma::file::media_data::fetch()))
+		.exec()
+		.await?
+		.into_iter()
+		.map(Into::into)
+		.collect())
+}
@@ -0,0 +1,499 @@
+use crate::{
+	file::{
+		archive::free_path,
+		ensure_not_held,
+		sizes::{mark_folder_sizes_stale, FolderSizesJob, FolderSizesJobInit},
+		trash::{move_entry, trash_file_path},
+		FilePath,
+	},
+	history::audit::{self, AuditOperation, AuditedJob},
+	job::{Job, JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::{LibraryContext, LibraryManager},
+	prisma::{self, file_path, location, retention_policy, tag, tag_on_file},
+	sys::get_location,
+	ClientQuery, CoreError, CoreEvent, CoreResponse, LibraryQuery,
+};
+use chrono::{DateTime, Duration, Utc};
+use int_enum::IntEnum;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+use thiserror::Error;
+use tokio::fs;
+use ts_rs::TS;
+
+// expirations are measured in days, so checking a few times a day is plenty
+const RETENTION_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(6 * 60 * 60);
+pub const RETENTION_JOB_NAME: &str = "retention_enforcer";
+// archived entries are kept at the root of their location, it is skipped by the indexer so they
+// don't expire again. See LibraryContext::location_dir for in memory nodes
+pub const ARCHIVE_DIR_NAME: &str = ".sd-archive";
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum RetentionAction {
+	// moves the file to the trash of its location, it can be restored until the trash is purged
+	Trash = 0,
+	// moves the file to the archive directory of its location, below the same path
+	Archive = 1,
+}
+
+// A rule expiring the entries of a folder or a tag once they reach a certain age
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RetentionPolicy {
+	pub id: i32,
+	pub name: String,
+	pub location_id: Option<i32>,
+	pub path: Option<String>,
+	pub tag_id: Option<i32>,
+	pub max_age_days: i32,
+	pub action: RetentionAction,
+	pub date_created: DateTime<Utc>,
+}
+
+impl From<retention_policy::Data> for RetentionPolicy {
+	fn from(data: retention_policy::Data) -> Self {
+		Self {
+			id: data.id,
+			name: data.name,
+			location_id: data.location_id,
+			path: data.path,
+			tag_id: data.tag_id,
+			max_age_days: data.max_age_days,
+			action: RetentionAction::from_int(data.action).unwrap_or(RetentionAction::Trash),
+			date_created: data.date_created.into(),
+		}
+	}
+}
+
+// An entry that a policy will expire, or already has
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RetentionExpiry {
+	pub policy_id: i32,
+	pub action: RetentionAction,
+	pub file_path: FilePath,
+	pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Error, Debug)]
+pub enum RetentionError {
+	#[error("Retention policy not found (id: {0})")]
+	PolicyNotFound(i32),
+	#[error("A retention policy needs either a location or a tag")]
+	MissingScope,
+	#[error("A retention policy needs a maximum age of at least a day (max_age_days: {0})")]
+	InvalidMaxAge(i32),
+	#[error("Database error")]
+	DatabaseError(#[from] prisma::QueryError),
+}
+
+pub async fn create_policy(
+	ctx: LibraryContext,
+	name: String,
+	location_id: Option<i32>,
+	path: Option<String>,
+	tag_id: Option<i32>,
+	max_age_days: i32,
+	action: RetentionAction,
+) -> Result<CoreResponse, CoreError> {
+	if location_id.is_none() && tag_id.is_none() {
+		return Err(RetentionError::MissingScope.into());
+	}
+	if max_age_days <= 0 {
+		return Err(RetentionError::InvalidMaxAge(max_age_days).into());
+	}
+
+	let mut params = vec![
+		retention_policy::path::set(path),
+		retention_policy::action::set(action.int_value()),
+	];
+	if let Some(location_id) = location_id {
+		params.push(retention_policy::location::link(location::id::equals(
+			location_id,
+		)));
+	}
+	if let Some(tag_id) = tag_id {
+		params.push(retention_policy::tag::link(tag::id::equals(tag_id)));
+	}
+
+	ctx.db
+		.retention_policy()
+		.create(
+			retention_policy::name::set(name),
+			retention_policy::max_age_days::set(max_age_days),
+			params,
+		)
+		.exec()
+		.await?;
+
+	send_invalidate_query(&ctx).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+pub async fn delete_policy(ctx: LibraryContext, id: i32) -> Result<CoreResponse, CoreError> {
+	ctx.db
+		.retention_policy()
+		.find_unique(retention_policy::id::equals(id))
+		.delete()
+		.exec()
+		.await?
+		.ok_or(RetentionError::PolicyNotFound(id))?;
+
+	send_invalidate_query(&ctx).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+// an exempt entry is never expired by any policy
+pub async fn set_exempt(
+	ctx: LibraryContext,
+	file_path_id: i32,
+	exempt: bool,
+) -> Result<CoreResponse, CoreError> {
+	ctx.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.update(vec![file_path::retention_exempt::set(exempt)])
+		.exec()
+		.await?;
+
+	send_invalidate_query(&ctx).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+pub async fn get_policies(
+	ctx: &LibraryContext,
+) -> Result<Vec<RetentionPolicy>, prisma::QueryError> {
+	Ok(ctx
+		.db
+		.retention_policy()
+		.find_many(vec![])
+		.exec()
+		.await?
+		.into_iter()
+		.map(Into::into)
+		.collect())
+}
+
+// returns every entry expiring within `days_ahead` days, including ones that are already expired
+pub async fn get_expiring(
+	ctx: &LibraryContext,
+	days_ahead: i32,
+) -> Result<Vec<RetentionExpiry>, prisma::QueryError> {
+	let mut expiring = vec![];
+
+	for policy in get_policies(ctx).await? {
+		let max_age = Duration::days(policy.max_age_days as i64);
+		let created_before = Utc::now() - max_age + Duration::days(days_ahead as i64);
+
+		let mut params = vec![
+			file_path::is_dir::equals(false),
+			file_path::retention_exempt::equals(false),
+			file_path::date_created::lt(created_before.into()),
+		];
+		if let Some(location_id) = policy.location_id {
+			params.push(file_path::location_id::equals(Some(location_id)));
+		}
+		if let Some(path) = &policy.path {
+			params.push(file_path::materialized_path::starts_with(path.clone()));
+		}
+		if let Some(tag_id) = policy.tag_id {
+			let file_ids = ctx
+				.db
+				.tag_on_file()
+				.find_many(vec![tag_on_file::tag_id::equals(tag_id)])
+				.exec()
+				.await?
+				.into_iter()
+				.map(|tag_on_file| tag_on_file.file_id)
+				.collect();
+			params.push(file_path::file_id::in_vec(file_ids));
+		}
+
+		expiring.extend(
+			ctx.db
+				.file_path()
+				.find_many(params)
+				.exec()
+				.await?
+				.into_iter()
+				.filter(|data| match &policy.path {
+					Some(path) => covers(path, &data.materialized_path),
+					None => true,
+				})
+				.map(|data| {
+					let file_path = FilePath::from(data);
+					RetentionExpiry {
+						policy_id: policy.id,
+						action: policy.action,
+						expires_at: file_path.date_created + max_age,
+						file_path,
+					}
+				}),
+		);
+	}
+
+	// a path covered by several policies expires with the earliest of them
+	expiring.sort_by_key(|expiry| expiry.expires_at);
+	let mut seen = HashSet::new();
+	expiring.retain(|expiry| seen.insert(expiry.file_path.id));
+
+	Ok(expiring)
+}
+
+// whether a policy on a path covers an entry, which is the path itself or within it, but not a
+// sibling which only starts with the same name
+fn covers(path: &str, materialized_path: &str) -> bool {
+	let path = path.trim_end_matches('/');
+	path.is_empty()
+		|| materialized_path == path
+		|| materialized_path.starts_with(&format!("{}/", path))
+}
+
+async fn send_invalidate_query(ctx: &LibraryContext) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetRetentionPolicies,
+	}))
+	.await;
+}
+
+// moves an entry below the archive directory of its location, numbering its name if an entry
+// archived earlier from the same path is there already
+async fn archive(
+	ctx: &LibraryContext,
+	location_id: i32,
+	location_path: &Path,
+	materialized_path: &str,
+) -> io::Result<()> {
+	let archived_path = free_path(
+		ctx.location_dir(location_id, location_path, ARCHIVE_DIR_NAME)
+			.join(materialized_path),
+	);
+	if let Some(parent) = archived_path.parent() {
+		fs::create_dir_all(parent).await?;
+	}
+
+	move_entry(&location_path.join(materialized_path), &archived_path).await
+}
+
+pub struct RetentionJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RetentionJobInit {}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RetentionJobStep {
+	policy_id: i32,
+	action: RetentionAction,
+	file_path_id: i32,
+	file_id: Option<i32>,
+	location_id: i32,
+	materialized_path: String,
+	// unset when the location is offline, its entries expire once it is back
+	location_path: Option<PathBuf>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for RetentionJob {
+	type Init = RetentionJobInit;
+	type Data = ();
+	type Step = RetentionJobStep;
+
+	fn name(&self) -> &'static str {
+		RETENTION_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+
+		let mut location_paths = HashMap::new();
+		let mut steps = vec![];
+		for expiry in get_expiring(&library_ctx, 0).await? {
+			let location_id = expiry.file_path.location_id;
+			if !location_paths.contains_key(&location_id) {
+				let location = get_location(&library_ctx, location_id).await?;
+				location_paths.insert(location_id, location.path);
+			}
+
+			steps.push(RetentionJobStep {
+				policy_id: expiry.policy_id,
+				action: expiry.action,
+				file_path_id: expiry.file_path.id,
+				file_id: expiry.file_path.file_id,
+				location_id,
+				materialized_path: expiry.file_path.materialized_path,
+				location_path: location_paths[&location_id].clone(),
+			});
+		}
+
+		info!("Found {} expired entries", steps.len());
+		ctx.progress(vec![JobReportUpdate::TaskCount(steps.len())]);
+
+		state.data = Some(());
+		state.steps = steps.into_iter().collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let step = &state.steps[0];
+		let library_ctx = ctx.library_ctx();
+
+		if let Some(file_id) = step.file_id {
+			if ensure_not_held(&library_ctx, file_id).await.is_err() {
+				info!(
+					"Skipping expired entry {} under legal hold",
+					step.file_path_id
+				);
+				return Ok(());
+			}
+		}
+
+		let operation = match step.action {
+			RetentionAction::Trash => {
+				info!(
+					"Trashing {} expired by retention policy {}",
+					step.materialized_path, step.policy_id
+				);
+				if let Err(e) = trash_file_path(&library_ctx, step.file_path_id).await {
+					error!("Failed to trash {}: {:#?}", step.materialized_path, e);
+					return Ok(());
+				}
+				AuditOperation::Trash
+			}
+			RetentionAction::Archive => {
+				let location_path = match &step.location_path {
+					Some(location_path) => location_path,
+					None => {
+						error!(
+							"Failed to archive {}: its location is unavailable",
+							step.materialized_path
+						);
+						return Ok(());
+					}
+				};
+				info!(
+					"Archiving {} expired by retention policy {}",
+					step.materialized_path, step.policy_id
+				);
+				if let Err(e) = archive(
+					&library_ctx,
+					step.location_id,
+					location_path,
+					&step.materialized_path,
+				)
+				.await
+				{
+					error!("Failed to archive {}: {:#?}", step.materialized_path, e);
+					return Ok(());
+				}
+
+				let archived = library_ctx
+					.db
+					.file_path()
+					.find_unique(file_path::id::equals(step.file_path_id))
+					.delete()
+					.exec()
+					.await?;
+				mark_folder_sizes_stale(&library_ctx, archived.parent_id.into_iter().collect())
+					.await?;
+				AuditOperation::Move
+			}
+		};
+		audit::record_job(
+			&library_ctx,
+			operation,
+			AuditedJob::RetentionExpiry {
+				policy_id: step.policy_id,
+				location_id: Some(step.location_id),
+				materialized_path: step.materialized_path.clone(),
+			},
+		)
+		.await;
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		_state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		let library_ctx = ctx.library_ctx();
+		library_ctx
+			.queue_job(Job::new(
+				FolderSizesJobInit::default(),
+				Box::new(FolderSizesJob {}),
+			))
+			.await;
+		library_ctx
+			.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+				library_id: library_ctx.id,
+				query: LibraryQuery::GetRetentionPreview { days_ahead: 0 },
+			}))
+			.await;
+
+		Ok(())
+	}
+}
+
+// periodically expires entries in every library, a job is only spawned when something expired
+pub async fn watch_retention_policies(library_manager: Arc<LibraryManager>) {
+	loop {
+		for ctx in library_manager.get_all_libraries_ctx().await {
+			match get_expiring(&ctx, 0).await {
+				Ok(expired) if !expired.is_empty() => {
+					ctx.spawn_job(Job::new(RetentionJobInit {}, Box::new(RetentionJob {})))
+						.await;
+				}
+				Ok(_) => {}
+				Err(e) => error!("Failed to read retention policies: {:#?}", e),
+			}
+		}
+
+		tokio::time::sleep(RETENTION_CHECK_INTERVAL).await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn covers_the_path_and_what_is_within_it() {
+		assert!(covers("photos", "photos"));
+		assert!(covers("photos", "photos/2019/beach.jpg"));
+		assert!(covers("photos/", "photos/beach.jpg"));
+		assert!(covers("", "notes.md"));
+	}
+
+	#[test]
+	fn doesnt_cover_siblings_sharing_a_prefix() {
+		assert!(!covers("photos", "photos-old/beach.jpg"));
+		assert!(!covers("photos", "photosynthesis.md"));
+		assert!(!covers("photos/2019", "photos/beach.jpg"));
+	}
+}
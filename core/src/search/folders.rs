@@ -0,0 +1,247 @@
+use super::{evaluate_page, matching_keys, ResultKey, SearchFilter, SearchSort, SearchSortBy};
+use crate::{
+	file::FilePath,
+	library::LibraryContext,
+	prisma::{self, virtual_folder},
+	ClientQuery, CoreError, CoreEvent, CoreResponse, LibraryQuery,
+};
+use int_enum::IntEnum;
+use log::error;
+use prisma_helpers::{decode_cursor, InvalidCursor};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+use ts_rs::TS;
+
+// pages are capped so a client can't ask for a whole folder at once
+const MAX_PAGE_SIZE: u32 = 1000;
+
+// A folder listing every file matching its filter, eg: all raw photos from 2023 larger than 20MB
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct VirtualFolder {
+	pub id: i32,
+	pub name: String,
+	pub filter: SearchFilter,
+	pub sort: SearchSort,
+	pub date_created: chrono::DateTime<chrono::Utc>,
+	pub date_modified: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct VirtualFolderContents {
+	pub items: Vec<FilePath>,
+	// none once the last item was sent
+	pub next_cursor: Option<String>,
+}
+
+impl TryFrom<virtual_folder::Data> for VirtualFolder {
+	type Error = VirtualFolderError;
+
+	fn try_from(data: virtual_folder::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: data.id,
+			name: data.name,
+			filter: serde_json::from_str(&data.filter)?,
+			sort: SearchSort {
+				by: SearchSortBy::from_int(data.sort_by).unwrap_or(SearchSortBy::Name),
+				descending: data.sort_descending,
+			},
+			date_created: data.date_created.into(),
+			date_modified: data.date_modified.into(),
+		})
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum VirtualFolderError {
+	#[error("Virtual folder not found (id: {0})")]
+	VirtualFolderNotFound(i32),
+	#[error("Invalid virtual folder filter: {0}")]
+	InvalidFilter(#[from] serde_json::Error),
+	#[error("{0}")]
+	InvalidCursor(#[from] InvalidCursor),
+	#[error("Database error")]
+	DatabaseError(#[from] prisma::QueryError),
+}
+
+pub async fn create_virtual_folder(
+	ctx: LibraryContext,
+	name: String,
+	filter: SearchFilter,
+	sort: SearchSort,
+) -> Result<CoreResponse, CoreError> {
+	ctx.db
+		.virtual_folder()
+		.create(
+			virtual_folder::name::set(name),
+			virtual_folder::filter::set(
+				serde_json::to_string(&filter).map_err(VirtualFolderError::from)?,
+			),
+			vec![
+				virtual_folder::sort_by::set(sort.by.int_value()),
+				virtual_folder::sort_descending::set(sort.descending),
+			],
+		)
+		.exec()
+		.await?;
+
+	send_invalidate_query(&ctx).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+pub async fn update_virtual_folder(
+	ctx: LibraryContext,
+	id: i32,
+	name: Option<String>,
+	filter: Option<SearchFilter>,
+	sort: Option<SearchSort>,
+) -> Result<CoreResponse, CoreError> {
+	let mut params = vec![virtual_folder::date_modified::set(
+		chrono::Utc::now().into(),
+	)];
+	if let Some(name) = name {
+		params.push(virtual_folder::name::set(name));
+	}
+	if let Some(filter) = filter {
+		params.push(virtual_folder::filter::set(
+			serde_json::to_string(&filter).map_err(VirtualFolderError::from)?,
+		));
+	}
+	if let Some(sort) = sort {
+		params.push(virtual_folder::sort_by::set(sort.by.int_value()));
+		params.push(virtual_folder::sort_descending::set(sort.descending));
+	}
+
+	ctx.db
+		.virtual_folder()
+		.find_unique(virtual_folder::id::equals(id))
+		.update(params)
+		.exec()
+		.await?
+		.ok_or(VirtualFolderError::VirtualFolderNotFound(id))?;
+
+	send_invalidate_query(&ctx).await;
+	refresh_virtual_folders(&ctx).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+pub async fn delete_virtual_folder(
+	ctx: LibraryContext,
+	id: i32,
+) -> Result<CoreResponse, CoreError> {
+	ctx.db
+		.virtual_folder()
+		.find_unique(virtual_folder::id::equals(id))
+		.delete()
+		.exec()
+		.await?
+		.ok_or(VirtualFolderError::VirtualFolderNotFound(id))?;
+
+	ctx.saved_searches()
+		.virtual_folders
+		.lock()
+		.await
+		.remove(&(ctx.id, id));
+
+	send_invalidate_query(&ctx).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+pub async fn get_virtual_folders(
+	ctx: &LibraryContext,
+) -> Result<Vec<VirtualFolder>, VirtualFolderError> {
+	ctx.db
+		.virtual_folder()
+		.find_many(vec![])
+		.exec()
+		.await?
+		.into_iter()
+		.map(TryInto::try_into)
+		.collect()
+}
+
+pub async fn get_virtual_folder_contents(
+	ctx: &LibraryContext,
+	id: i32,
+	cursor: Option<String>,
+	limit: u32,
+) -> Result<VirtualFolderContents, VirtualFolderError> {
+	let folder: VirtualFolder = ctx
+		.db
+		.virtual_folder()
+		.find_unique(virtual_folder::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(VirtualFolderError::VirtualFolderNotFound(id))?
+		.try_into()?;
+
+	let after = match cursor {
+		Some(cursor) => Some(decode_cursor::<ResultKey>(&cursor)?),
+		None => None,
+	};
+	let page = evaluate_page(
+		ctx,
+		&folder.filter,
+		folder.sort,
+		after,
+		limit.min(MAX_PAGE_SIZE) as usize,
+	)
+	.await?;
+
+	Ok(VirtualFolderContents {
+		items: page.items,
+		next_cursor: page.next_cursor,
+	})
+}
+
+// evaluates every virtual folder of the library again and emits VirtualFolderChanged for those
+// whose members changed since the last evaluation
+pub(super) async fn refresh_virtual_folders(ctx: &LibraryContext) {
+	let folders = match get_virtual_folders(ctx).await {
+		Ok(folders) => folders,
+		Err(e) => {
+			error!("Failed to read virtual folders: {:#?}", e);
+			return;
+		}
+	};
+
+	let subscriptions = ctx.saved_searches();
+	let mut members = subscriptions.virtual_folders.lock().await;
+
+	for folder in folders {
+		// only the ids are compared, the file paths aren't read
+		let current = match matching_keys(ctx, &folder.filter, folder.sort, None, None).await {
+			Ok(keys) => keys.into_iter().map(|key| key.id).collect::<HashSet<_>>(),
+			Err(e) => {
+				error!("Failed to refresh virtual folder {}: {:#?}", folder.id, e);
+				continue;
+			}
+		};
+
+		// without a previous evaluation to compare to, clients refetch to be safe
+		let changed = members
+			.insert((ctx.id, folder.id), current.clone())
+			.map_or(true, |previous| previous != current);
+
+		if changed {
+			ctx.emit(CoreEvent::VirtualFolderChanged {
+				library_id: ctx.id,
+				id: folder.id,
+			})
+			.await;
+		}
+	}
+}
+
+async fn send_invalidate_query(ctx: &LibraryContext) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetVirtualFolders,
+	}))
+	.await;
+}
@@ -0,0 +1,529 @@
+use crate::{
+	file::{FileKind, FilePath},
+	library::LibraryContext,
+	prisma::{self, file_path, saved_search},
+	util::collation::Collation,
+	ClientQuery, CoreError, CoreEvent, CoreResponse, LibraryQuery,
+};
+use chrono::{DateTime, Utc};
+use int_enum::IntEnum;
+use log::error;
+use prisma_client_rust::{prisma_models::PrismaValue, raw::Raw};
+use prisma_helpers::{Keyset, Page, SortDirection};
+use serde::{Deserialize, Serialize};
+use std::{
+	cmp::Ordering,
+	collections::{HashMap, HashSet},
+};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use ts_rs::TS;
+use uuid::Uuid;
+
+pub mod folders;
+
+// Every set field must match, an empty filter matches every file
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SearchFilter {
+	// part of the name, without the extension
+	pub name: Option<String>,
+	// matches any of the extensions
+	#[serde(default)]
+	pub extensions: Vec<String>,
+	pub location_id: Option<i32>,
+	pub tag_id: Option<i32>,
+	pub kind: Option<FileKind>,
+	pub favorite: Option<bool>,
+	// in bytes, inclusive
+	pub min_size: Option<i64>,
+	pub max_size: Option<i64>,
+	// when the file was created on disk
+	pub created_after: Option<DateTime<Utc>>,
+	pub created_before: Option<DateTime<Utc>>,
+	// overrides whether the library lists hidden entries
+	#[serde(default)]
+	pub show_hidden: Option<bool>,
+}
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum SearchSortBy {
+	Name = 0,
+	DateCreated = 1,
+	DateModified = 2,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SearchSort {
+	pub by: SearchSortBy,
+	pub descending: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SavedSearch {
+	pub id: i32,
+	pub name: String,
+	pub filter: SearchFilter,
+	pub sort: SearchSort,
+	pub date_created: chrono::DateTime<chrono::Utc>,
+	pub date_modified: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<saved_search::Data> for SavedSearch {
+	type Error = SavedSearchError;
+
+	fn try_from(data: saved_search::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: data.id,
+			name: data.name,
+			filter: serde_json::from_str(&data.filter)?,
+			sort: SearchSort {
+				by: SearchSortBy::from_int(data.sort_by).unwrap_or(SearchSortBy::Name),
+				descending: data.sort_descending,
+			},
+			date_created: data.date_created.into(),
+			date_modified: data.date_modified.into(),
+		})
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum SavedSearchError {
+	#[error("Saved search not found (id: {0})")]
+	SavedSearchNotFound(i32),
+	#[error("Invalid saved search filter: {0}")]
+	InvalidFilter(#[from] serde_json::Error),
+	#[error("Database error")]
+	DatabaseError(#[from] prisma::QueryError),
+}
+
+// SavedSearchSubscriptions remembers the results last sent for each subscribed search, so only
+// what changed is pushed to clients when the search is evaluated again
+#[derive(Default)]
+pub struct SavedSearchSubscriptions {
+	results: Mutex<HashMap<(Uuid, i32), HashSet<i32>>>,
+	// members of every virtual folder, to tell clients when a listing is stale
+	virtual_folders: Mutex<HashMap<(Uuid, i32), HashSet<i32>>>,
+	// entries of every subscribed directory, by the id of its file path
+	pub(crate) directories: Mutex<HashMap<(Uuid, i32), HashSet<i32>>>,
+}
+
+pub async fn create_saved_search(
+	ctx: LibraryContext,
+	name: String,
+	filter: SearchFilter,
+	sort: SearchSort,
+) -> Result<CoreResponse, CoreError> {
+	ctx.db
+		.saved_search()
+		.create(
+			saved_search::name::set(name),
+			saved_search::filter::set(
+				serde_json::to_string(&filter).map_err(SavedSearchError::from)?,
+			),
+			vec![
+				saved_search::sort_by::set(sort.by.int_value()),
+				saved_search::sort_descending::set(sort.descending),
+			],
+		)
+		.exec()
+		.await?;
+
+	send_invalidate_query(&ctx).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+pub async fn update_saved_search(
+	ctx: LibraryContext,
+	id: i32,
+	name: Option<String>,
+	filter: Option<SearchFilter>,
+	sort: Option<SearchSort>,
+) -> Result<CoreResponse, CoreError> {
+	let mut params = vec![saved_search::date_modified::set(chrono::Utc::now().into())];
+	if let Some(name) = name {
+		params.push(saved_search::name::set(name));
+	}
+	if let Some(filter) = filter {
+		params.push(saved_search::filter::set(
+			serde_json::to_string(&filter).map_err(SavedSearchError::from)?,
+		));
+	}
+	if let Some(sort) = sort {
+		params.push(saved_search::sort_by::set(sort.by.int_value()));
+		params.push(saved_search::sort_descending::set(sort.descending));
+	}
+
+	ctx.db
+		.saved_search()
+		.find_unique(saved_search::id::equals(id))
+		.update(params)
+		.exec()
+		.await?
+		.ok_or(SavedSearchError::SavedSearchNotFound(id))?;
+
+	send_invalidate_query(&ctx).await;
+	refresh_subscriptions(&ctx).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+pub async fn delete_saved_search(ctx: LibraryContext, id: i32) -> Result<CoreResponse, CoreError> {
+	ctx.db
+		.saved_search()
+		.find_unique(saved_search::id::equals(id))
+		.delete()
+		.exec()
+		.await?
+		.ok_or(SavedSearchError::SavedSearchNotFound(id))?;
+
+	ctx.saved_searches()
+		.results
+		.lock()
+		.await
+		.remove(&(ctx.id, id));
+
+	send_invalidate_query(&ctx).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+pub async fn get_saved_searches(
+	ctx: &LibraryContext,
+) -> Result<Vec<SavedSearch>, SavedSearchError> {
+	ctx.db
+		.saved_search()
+		.find_many(vec![])
+		.exec()
+		.await?
+		.into_iter()
+		.map(TryInto::try_into)
+		.collect()
+}
+
+async fn get_saved_search(ctx: &LibraryContext, id: i32) -> Result<SavedSearch, SavedSearchError> {
+	ctx.db
+		.saved_search()
+		.find_unique(saved_search::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(SavedSearchError::SavedSearchNotFound(id))?
+		.try_into()
+}
+
+pub async fn run_saved_search(
+	ctx: &LibraryContext,
+	id: i32,
+) -> Result<Vec<FilePath>, SavedSearchError> {
+	let search = get_saved_search(ctx, id).await?;
+	Ok(evaluate(ctx, &search.filter, search.sort).await?)
+}
+
+// returns the current results, after which only changes are sent as SavedSearchChanged events
+pub async fn subscribe(ctx: &LibraryContext, id: i32) -> Result<Vec<FilePath>, SavedSearchError> {
+	let file_paths = run_saved_search(ctx, id).await?;
+
+	ctx.saved_searches().results.lock().await.insert(
+		(ctx.id, id),
+		file_paths.iter().map(|file_path| file_path.id).collect(),
+	);
+
+	Ok(file_paths)
+}
+
+pub async fn unsubscribe(ctx: &LibraryContext, id: i32) {
+	ctx.saved_searches()
+		.results
+		.lock()
+		.await
+		.remove(&(ctx.id, id));
+}
+
+// evaluates every subscribed search, virtual folder and directory of the library again, to be
+// called after entries, tags or file metadata changed
+pub async fn refresh_subscriptions(ctx: &LibraryContext) {
+	folders::refresh_virtual_folders(ctx).await;
+	crate::file::explorer::refresh_directories(ctx).await;
+
+	let subscriptions = ctx.saved_searches();
+	let mut results = subscriptions.results.lock().await;
+
+	for ((library_id, id), previous) in results.iter_mut() {
+		if *library_id != ctx.id {
+			continue;
+		}
+
+		let file_paths = match run_saved_search(ctx, *id).await {
+			Ok(file_paths) => file_paths,
+			Err(e) => {
+				error!("Failed to refresh saved search {}: {:#?}", id, e);
+				continue;
+			}
+		};
+
+		let current = file_paths
+			.iter()
+			.map(|file_path| file_path.id)
+			.collect::<HashSet<_>>();
+		let added = file_paths
+			.into_iter()
+			.filter(|file_path| !previous.contains(&file_path.id))
+			.collect::<Vec<_>>();
+		let removed = previous.difference(&current).copied().collect::<Vec<_>>();
+
+		*previous = current;
+
+		if !added.is_empty() || !removed.is_empty() {
+			ctx.emit(CoreEvent::SavedSearchChanged {
+				library_id: ctx.id,
+				id: *id,
+				added,
+				removed,
+			})
+			.await;
+		}
+	}
+}
+
+// What results are sorted on, read for every match while only the results of a page are read
+// whole. Cursors are one of these, encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct ResultKey {
+	pub id: i32,
+	name: String,
+	date_created: DateTime<Utc>,
+	date_modified: DateTime<Utc>,
+}
+
+impl SearchSort {
+	fn column(&self) -> &'static str {
+		match self.by {
+			SearchSortBy::Name => "file_paths.name",
+			SearchSortBy::DateCreated => "file_paths.date_created",
+			SearchSortBy::DateModified => "file_paths.date_modified",
+		}
+	}
+
+	// the values of the keyset of the sort, the id breaking ties
+	fn key_values(&self, key: &ResultKey) -> Vec<PrismaValue> {
+		vec![
+			match self.by {
+				SearchSortBy::Name => PrismaValue::String(key.name.clone()),
+				SearchSortBy::DateCreated => PrismaValue::DateTime(key.date_created.into()),
+				SearchSortBy::DateModified => PrismaValue::DateTime(key.date_modified.into()),
+			},
+			PrismaValue::Int(key.id as i64),
+		]
+	}
+
+	fn compare(&self, a: &ResultKey, b: &ResultKey, collation: Collation) -> Ordering {
+		let ordering = collation
+			.compare(&a.name, &b.name)
+			.then_with(|| a.id.cmp(&b.id));
+		match self.descending {
+			true => ordering.reverse(),
+			false => ordering,
+		}
+	}
+}
+
+// the condition on `file_paths LEFT JOIN files` matching the filter, with its values
+fn filter_condition(ctx: &LibraryContext, filter: &SearchFilter) -> (String, Vec<PrismaValue>) {
+	let mut conditions = vec!["file_paths.is_dir = 0".to_string()];
+	let mut values = vec![];
+
+	if !filter.show_hidden.unwrap_or(ctx.config.show_hidden_files) {
+		conditions.push("file_paths.hidden = 0".to_string());
+	}
+	if let Some(name) = &filter.name {
+		conditions.push("file_paths.name LIKE '%' || {} || '%' ESCAPE '\\'".to_string());
+		values.push(PrismaValue::String(
+			name.replace('\\', "\\\\")
+				.replace('%', "\\%")
+				.replace('_', "\\_"),
+		));
+	}
+	if !filter.extensions.is_empty() {
+		conditions.push(format!(
+			"file_paths.extension IN ({})",
+			vec!["{}"; filter.extensions.len()].join(", ")
+		));
+		values.extend(
+			filter
+				.extensions
+				.iter()
+				.map(|extension| PrismaValue::String(extension.to_lowercase())),
+		);
+	}
+	if let Some(created_after) = filter.created_after {
+		conditions.push("file_paths.date_created >= {}".to_string());
+		values.push(PrismaValue::DateTime(created_after.into()));
+	}
+	if let Some(created_before) = filter.created_before {
+		conditions.push("file_paths.date_created <= {}".to_string());
+		values.push(PrismaValue::DateTime(created_before.into()));
+	}
+	if let Some(location_id) = filter.location_id {
+		conditions.push("file_paths.location_id = {}".to_string());
+		values.push(PrismaValue::Int(location_id as i64));
+	}
+	if let Some(kind) = filter.kind {
+		conditions.push("files.kind = {}".to_string());
+		values.push(PrismaValue::Int(kind.int_value() as i64));
+	}
+	if let Some(favorite) = filter.favorite {
+		conditions.push("files.favorite = {}".to_string());
+		values.push(PrismaValue::Boolean(favorite));
+	}
+	if let Some(tag_id) = filter.tag_id {
+		conditions.push(
+			"EXISTS (SELECT 1 FROM tags_on_file
+			WHERE tags_on_file.file_id = files.id AND tags_on_file.tag_id = {})"
+				.to_string(),
+		);
+		values.push(PrismaValue::Int(tag_id as i64));
+	}
+	// sizes are stored as strings
+	if filter.min_size.is_some() || filter.max_size.is_some() {
+		conditions.push("CAST(files.size_in_bytes AS INTEGER) BETWEEN {} AND {}".to_string());
+		values.push(PrismaValue::Int(filter.min_size.unwrap_or(0)));
+		values.push(PrismaValue::Int(filter.max_size.unwrap_or(i64::MAX)));
+	}
+
+	(conditions.join(" AND "), values)
+}
+
+// the keys of the matches of `filter` in order, starting after `after`
+pub(super) async fn matching_keys(
+	ctx: &LibraryContext,
+	filter: &SearchFilter,
+	sort: SearchSort,
+	after: Option<&ResultKey>,
+	limit: Option<usize>,
+) -> Result<Vec<ResultKey>, prisma::QueryError> {
+	let (condition, mut values) = filter_condition(ctx, filter);
+	let select = |condition: String, order_by: String, limit: &str| {
+		format!(
+			"SELECT file_paths.id, file_paths.name, file_paths.date_created,
+			file_paths.date_modified
+			FROM file_paths LEFT JOIN files ON files.id = file_paths.file_id
+			WHERE {} ORDER BY {} {}",
+			condition, order_by, limit
+		)
+	};
+
+	// names are ordered by the collation of the library, which SQLite doesn't know of, so every
+	// match is read and sorted here
+	let collation = ctx.config.collation;
+	if sort.by == SearchSortBy::Name && collation != Collation::Binary {
+		let mut keys = ctx
+			.db
+			._query_raw::<ResultKey>(Raw::new(
+				&select(condition, "file_paths.id".to_string(), ""),
+				values,
+			))
+			.await?;
+		keys.sort_by(|a, b| sort.compare(a, b, collation));
+
+		let start = after.map_or(0, |after| {
+			keys.partition_point(|key| sort.compare(key, after, collation) != Ordering::Greater)
+		});
+		keys.drain(..start);
+		keys.truncate(limit.unwrap_or(usize::MAX));
+		return Ok(keys);
+	}
+
+	let columns = [sort.column(), "file_paths.id"];
+	let keyset = Keyset::new(&columns, SortDirection::from_descending(sort.descending));
+	let (after, after_values) = keyset.after(after.map(|after| sort.key_values(after)));
+	values.extend(after_values);
+	let limit = match limit {
+		Some(limit) => {
+			values.push(PrismaValue::Int(limit as i64));
+			"LIMIT {}"
+		}
+		None => "",
+	};
+
+	ctx.db
+		._query_raw::<ResultKey>(Raw::new(
+			&select(
+				format!("{} AND {}", condition, after),
+				keyset.order_by(),
+				limit,
+			),
+			values,
+		))
+		.await
+}
+
+// the file paths of `ids`, in their order
+async fn load_results(
+	ctx: &LibraryContext,
+	ids: &[i32],
+) -> Result<Vec<FilePath>, prisma::QueryError> {
+	let mut file_paths = HashMap::with_capacity(ids.len());
+	// SQLite takes at most 999 values a query
+	for chunk in ids.chunks(500) {
+		file_paths.extend(
+			ctx.db
+				.file_path()
+				.find_many(vec![file_path::id::in_vec(chunk.to_vec())])
+				.with(file_path::file::fetch())
+				.exec()
+				.await?
+				.into_iter()
+				.map(|file_path| (file_path.id, FilePath::from(file_path))),
+		);
+	}
+
+	Ok(ids.iter().filter_map(|id| file_paths.remove(id)).collect())
+}
+
+// evaluate returns every file path matching `filter`
+pub(crate) async fn evaluate(
+	ctx: &LibraryContext,
+	filter: &SearchFilter,
+	sort: SearchSort,
+) -> Result<Vec<FilePath>, prisma::QueryError> {
+	let ids = matching_keys(ctx, filter, sort, None, None)
+		.await?
+		.into_iter()
+		.map(|key| key.id)
+		.collect::<Vec<_>>();
+
+	load_results(ctx, &ids).await
+}
+
+// the page of the matches of `filter` following `after`, read through the index of the sorted
+// column rather than stepping over every match before it
+pub(super) async fn evaluate_page(
+	ctx: &LibraryContext,
+	filter: &SearchFilter,
+	sort: SearchSort,
+	after: Option<ResultKey>,
+	limit: usize,
+) -> Result<Page<FilePath>, prisma::QueryError> {
+	// one extra key tells whether there is another page
+	let keys = matching_keys(ctx, filter, sort, after.as_ref(), Some(limit + 1)).await?;
+	let page = Page::from_rows(keys, limit, |key| key.clone());
+
+	let ids = page.items.iter().map(|key| key.id).collect::<Vec<_>>();
+	Ok(Page {
+		items: load_results(ctx, &ids).await?,
+		next_cursor: page.next_cursor,
+	})
+}
+
+async fn send_invalidate_query(ctx: &LibraryContext) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetSavedSearches,
+	}))
+	.await;
+}
@@ -0,0 +1,253 @@
+use ring::{
+	aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305},
+	pbkdf2,
+	rand::{SecureRandom, SystemRandom},
+};
+use std::{
+	fs::File,
+	io::{self, BufReader, Read, Write},
+	num::NonZeroU32,
+	path::PathBuf,
+};
+use tokio::sync::mpsc;
+
+// A bundle is a header followed by the contents of the share sealed in chunks, so a bundle of any
+// size is encrypted and decrypted as it is streamed. The contents are the file itself, or a tar
+// archive of a folder.
+const MAGIC: &[u8; 8] = b"SDSHARE1";
+const CHUNK_SIZE: usize = 1024 * 1024;
+// set on the length of the final chunk, so a truncated bundle fails to open
+const LAST_CHUNK_FLAG: u32 = 1 << 31;
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+const STREAM_CHUNKS_IN_FLIGHT: usize = 4;
+pub const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+pub fn random_bytes<const N: usize>() -> [u8; N] {
+	let mut bytes = [0; N];
+	SystemRandom::new()
+		.fill(&mut bytes)
+		.expect("the system random number generator failed");
+	bytes
+}
+
+// the key of a password protected bundle, its salt is stored in the header
+pub fn derive_key(password: &str, salt: &[u8]) -> [u8; KEY_LEN] {
+	let mut key = [0; KEY_LEN];
+	pbkdf2::derive(
+		pbkdf2::PBKDF2_HMAC_SHA256,
+		NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+		salt,
+		password.as_bytes(),
+		&mut key,
+	);
+	key
+}
+
+fn open_key(key: &[u8]) -> io::Result<LessSafeKey> {
+	UnboundKey::new(&CHACHA20_POLY1305, key)
+		.map(LessSafeKey::new)
+		.map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid bundle key"))
+}
+
+// every chunk is sealed with a nonce of its index, and whether it is the final one
+fn nonce(index: u64, last: bool) -> Nonce {
+	let mut nonce = [0; aead::NONCE_LEN];
+	nonce[3..11].copy_from_slice(&index.to_be_bytes());
+	nonce[11] = last as u8;
+	Nonce::assume_unique_for_key(nonce)
+}
+
+pub struct BundleHeader {
+	// the contents are a tar archive
+	pub is_dir: bool,
+	// set when the key is derived from a password
+	pub salt: Option<[u8; SALT_LEN]>,
+}
+
+impl BundleHeader {
+	fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+		writer.write_all(MAGIC)?;
+		writer.write_all(&[self.is_dir as u8, self.salt.is_some() as u8])?;
+		writer.write_all(&self.salt.unwrap_or_default())
+	}
+
+	pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+		let mut magic = [0; MAGIC.len()];
+		reader.read_exact(&mut magic)?;
+		if &magic != MAGIC {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"not a share bundle",
+			));
+		}
+
+		let mut flags = [0; 2];
+		reader.read_exact(&mut flags)?;
+		let mut salt = [0; SALT_LEN];
+		reader.read_exact(&mut salt)?;
+
+		Ok(Self {
+			is_dir: flags[0] != 0,
+			salt: (flags[1] != 0).then(|| salt),
+		})
+	}
+}
+
+pub struct BundleWriter<W: Write> {
+	inner: W,
+	key: LessSafeKey,
+	buf: Vec<u8>,
+	index: u64,
+}
+
+impl<W: Write> BundleWriter<W> {
+	pub fn new(mut inner: W, key: &[u8], header: BundleHeader) -> io::Result<Self> {
+		header.write(&mut inner)?;
+		Ok(Self {
+			inner,
+			key: open_key(key)?,
+			buf: Vec::with_capacity(CHUNK_SIZE),
+			index: 0,
+		})
+	}
+
+	fn seal(&mut self, last: bool) -> io::Result<()> {
+		let rest = self.buf.split_off(self.buf.len().min(CHUNK_SIZE));
+		let mut chunk = std::mem::replace(&mut self.buf, rest);
+		self.key
+			.seal_in_place_append_tag(nonce(self.index, last), Aad::empty(), &mut chunk)
+			.map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to seal bundle chunk"))?;
+
+		let len = chunk.len() as u32 | if last { LAST_CHUNK_FLAG } else { 0 };
+		self.inner.write_all(&len.to_be_bytes())?;
+		self.inner.write_all(&chunk)?;
+		self.index += 1;
+
+		Ok(())
+	}
+
+	// seals what is left as the final chunk, which is empty when the contents are
+	pub fn finish(mut self) -> io::Result<W> {
+		while self.buf.len() > CHUNK_SIZE {
+			self.seal(false)?;
+		}
+		self.seal(true)?;
+		self.inner.flush()?;
+		Ok(self.inner)
+	}
+}
+
+impl<W: Write> Write for BundleWriter<W> {
+	fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+		self.buf.extend_from_slice(data);
+		// a full chunk is only sealed once more follows, it could be the final one
+		while self.buf.len() > CHUNK_SIZE {
+			self.seal(false)?;
+		}
+		Ok(data.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+pub struct BundleReader<R: Read> {
+	inner: R,
+	key: LessSafeKey,
+	buf: Vec<u8>,
+	pos: usize,
+	index: u64,
+	done: bool,
+}
+
+impl<R: Read> BundleReader<R> {
+	// to be given the reader past the header
+	pub fn new(inner: R, key: &[u8]) -> io::Result<Self> {
+		Ok(Self {
+			inner,
+			key: open_key(key)?,
+			buf: vec![],
+			pos: 0,
+			index: 0,
+			done: false,
+		})
+	}
+
+	fn open_next(&mut self) -> io::Result<()> {
+		let mut len = [0; 4];
+		self.inner.read_exact(&mut len)?;
+		let len = u32::from_be_bytes(len);
+		let last = len & LAST_CHUNK_FLAG != 0;
+		let len = (len & !LAST_CHUNK_FLAG) as usize;
+		if len > CHUNK_SIZE + CHACHA20_POLY1305.tag_len() {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"bundle chunk too large",
+			));
+		}
+
+		self.buf.resize(len, 0);
+		self.inner.read_exact(&mut self.buf)?;
+		let plaintext_len = self
+			.key
+			.open_in_place(nonce(self.index, last), Aad::empty(), &mut self.buf)
+			.map_err(|_| {
+				io::Error::new(io::ErrorKind::InvalidData, "wrong key or corrupted bundle")
+			})?
+			.len();
+		self.buf.truncate(plaintext_len);
+		self.pos = 0;
+		self.index += 1;
+		self.done = last;
+
+		Ok(())
+	}
+}
+
+impl<R: Read> Read for BundleReader<R> {
+	fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+		while self.pos == self.buf.len() {
+			if self.done {
+				return Ok(0);
+			}
+			self.open_next()?;
+		}
+
+		let len = out.len().min(self.buf.len() - self.pos);
+		out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+		self.pos += len;
+		Ok(len)
+	}
+}
+
+// streams the decrypted contents of a bundle, which stops being read once the receiver is dropped
+pub fn stream_share_bundle(path: PathBuf, key: Vec<u8>) -> mpsc::Receiver<io::Result<Vec<u8>>> {
+	let (tx, rx) = mpsc::channel(STREAM_CHUNKS_IN_FLIGHT);
+
+	tokio::task::spawn_blocking(move || {
+		let read = || -> io::Result<()> {
+			let mut file = BufReader::new(File::open(&path)?);
+			BundleHeader::read(&mut file)?;
+			let mut reader = BundleReader::new(file, &key)?;
+			loop {
+				let mut chunk = vec![0; STREAM_CHUNK_SIZE];
+				let len = reader.read(&mut chunk)?;
+				if len == 0 {
+					return Ok(());
+				}
+				chunk.truncate(len);
+				if tx.blocking_send(Ok(chunk)).is_err() {
+					return Ok(());
+				}
+			}
+		};
+		if let Err(e) = read() {
+			tx.blocking_send(Err(e)).ok();
+		}
+	});
+
+	rx
+}
@@ -0,0 +1,619 @@
+use crate::{
+	file::{archive::write_tar, copy::full_name},
+	job::{Job, JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::{LibraryContext, LibraryManager},
+	prisma::{self, file, file_path, share},
+	sys::{get_location, SysError},
+	ClientQuery, CoreEvent, LibraryQuery,
+};
+use bundle::{derive_key, random_bytes, BundleHeader, BundleWriter, KEY_LEN, SALT_LEN};
+use chrono::{DateTime, Duration, Utc};
+use int_enum::IntEnum;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+	fs::{self, File},
+	io::{self, BufWriter},
+	path::PathBuf,
+	sync::Arc,
+};
+use thiserror::Error;
+use ts_rs::TS;
+use uuid::Uuid;
+
+mod bundle;
+mod target;
+
+pub use bundle::stream_share_bundle;
+pub use target::ShareTarget;
+
+pub const SHARE_JOB_NAME: &str = "share_publisher";
+const SHARES_DIR_NAME: &str = "shares";
+// links expire to the minute, close enough for expirations set in hours or days
+const SHARE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum ShareStatus {
+	// the bundle is being built or uploaded
+	Pending = 0,
+	Active = 1,
+	Failed = 2,
+	Revoked = 3,
+	Expired = 4,
+}
+
+// A link to a file or a folder of the library. The contents are encrypted into a bundle when the
+// share is created, so the link keeps giving them as they were then.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Share {
+	pub id: i32,
+	pub pub_id: Uuid,
+	// unset once the shared entry is removed from the library, the link still works until it expires
+	pub file_path_id: Option<i32>,
+	pub name: String,
+	pub is_dir: bool,
+	// uploaded to a WebDAV or S3 target rather than served by this node
+	pub is_remote: bool,
+	pub status: ShareStatus,
+	pub protected: bool,
+	// set once the share is active
+	pub link: Option<String>,
+	// only counted for shares served by this node
+	pub download_count: i32,
+	pub expires_at: DateTime<Utc>,
+	pub date_created: DateTime<Utc>,
+}
+
+// What the gateway needs to serve a share, once its link was checked
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ShareBundle {
+	pub name: String,
+	pub is_dir: bool,
+	pub path: PathBuf,
+	// only handed to the gateway within the node, never sent to clients
+	#[serde(skip)]
+	#[ts(skip)]
+	pub key: Vec<u8>,
+}
+
+#[derive(Error, Debug)]
+pub enum ShareError {
+	#[error("No share target is set for the library")]
+	NoTarget,
+	#[error("Invalid share target url: {0}")]
+	InvalidTarget(String),
+	#[error("Share not found (id: {0})")]
+	NotFound(i32),
+	#[error("File path not found (id: {0})")]
+	FilePathNotFound(i32),
+	#[error("Share link not found")]
+	LinkNotFound,
+	#[error("Share link expired")]
+	Expired,
+	#[error("The expiration of a share must be in the future")]
+	InvalidExpiry,
+	#[error("Links of this share target expire within {0} days")]
+	ExpiryTooFar(i64),
+	#[error("A password is required to open this share")]
+	PasswordRequired,
+	#[error("Wrong password")]
+	WrongPassword,
+	#[error("Files containing credentials can't be shared")]
+	ContainsSecrets,
+	#[error("The location of the shared entry is unavailable")]
+	LocationUnavailable,
+	#[error("Share target responded with {0}: {1}")]
+	TargetResponse(u16, String),
+	#[error("Share target request failed: {0}")]
+	Request(#[from] reqwest::Error),
+	#[error("I/O error: {0}")]
+	IOError(#[from] io::Error),
+	#[error("Invalid share target: {0}")]
+	InvalidTargetJson(#[from] serde_json::Error),
+	#[error("System error: {0}")]
+	SystemError(#[from] SysError),
+	#[error("Database error")]
+	DatabaseError(#[from] prisma::QueryError),
+}
+
+impl TryFrom<share::Data> for Share {
+	type Error = ShareError;
+
+	fn try_from(data: share::Data) -> Result<Self, Self::Error> {
+		let target: ShareTarget = serde_json::from_str(&data.target)?;
+		Ok(Self {
+			id: data.id,
+			pub_id: Uuid::from_slice(&data.pub_id).unwrap(),
+			file_path_id: data.file_path_id,
+			name: data.name,
+			is_dir: data.is_dir,
+			is_remote: target.is_remote(),
+			status: ShareStatus::from_int(data.status).unwrap_or(ShareStatus::Failed),
+			protected: data.protected,
+			link: data.link,
+			download_count: data.download_count,
+			expires_at: data.expires_at.into(),
+			date_created: data.date_created.into(),
+		})
+	}
+}
+
+fn bundle_path(ctx: &LibraryContext, pub_id: &[u8]) -> PathBuf {
+	ctx.config()
+		.data_directory()
+		.join(SHARES_DIR_NAME)
+		.join(ctx.id.to_string())
+		.join(Uuid::from_slice(pub_id).unwrap_or_default().to_string())
+		.with_extension("sdshare")
+}
+
+fn object_name(token: &str) -> String {
+	format!("{}.sdshare", token)
+}
+
+async fn send_invalidate_shares(ctx: &LibraryContext) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetShares,
+	}))
+	.await;
+}
+
+async fn set_status(
+	ctx: &LibraryContext,
+	id: i32,
+	status: ShareStatus,
+) -> Result<(), prisma::QueryError> {
+	ctx.db
+		.share()
+		.find_unique(share::id::equals(id))
+		.update(vec![share::status::set(status.int_value())])
+		.exec()
+		.await?;
+	Ok(())
+}
+
+// create_share starts building the bundle of an entry, to be served by or uploaded to the share
+// target of the library. Entries with credentials found by the secrets scanner can't be shared.
+pub async fn create_share(
+	ctx: &LibraryContext,
+	file_path_id: i32,
+	expires_at: DateTime<Utc>,
+	password: Option<String>,
+) -> Result<Share, ShareError> {
+	let target = ctx
+		.config
+		.share_target
+		.clone()
+		.ok_or(ShareError::NoTarget)?;
+	let now = Utc::now();
+	if expires_at <= now {
+		return Err(ShareError::InvalidExpiry);
+	}
+	if matches!(target, ShareTarget::S3 { .. })
+		&& expires_at > now + Duration::days(target::S3_MAX_LINK_DAYS)
+	{
+		return Err(ShareError::ExpiryTooFar(target::S3_MAX_LINK_DAYS));
+	}
+
+	let file_path = ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.with(file_path::file::fetch())
+		.exec()
+		.await?
+		.ok_or(ShareError::FilePathNotFound(file_path_id))?;
+
+	let contains_secrets = match file_path.is_dir {
+		true => ctx
+			.db
+			.file_path()
+			.find_first(vec![
+				file_path::location_id::equals(file_path.location_id),
+				file_path::materialized_path::starts_with(format!(
+					"{}/",
+					file_path.materialized_path
+				)),
+				file_path::file::is(vec![
+					file::secret_kind::not(None),
+					file::secret_kind::not(Some(0)),
+				]),
+			])
+			.exec()
+			.await?
+			.is_some(),
+		false => file_path
+			.file
+			.as_ref()
+			.and_then(|file| file.as_ref())
+			.and_then(|file| file.secret_kind)
+			.map_or(false, |secret_kind| secret_kind != 0),
+	};
+	if contains_secrets {
+		return Err(ShareError::ContainsSecrets);
+	}
+
+	let (key, salt) = match password.filter(|password| !password.is_empty()) {
+		Some(password) => {
+			let salt = random_bytes::<SALT_LEN>();
+			(derive_key(&password, &salt).to_vec(), Some(salt.to_vec()))
+		}
+		None => (random_bytes::<KEY_LEN>().to_vec(), None),
+	};
+
+	let share = ctx
+		.db
+		.share()
+		.create(
+			share::pub_id::set(Uuid::new_v4().as_bytes().to_vec()),
+			share::token::set(base64::encode_config(
+				random_bytes::<16>(),
+				base64::URL_SAFE_NO_PAD,
+			)),
+			share::name::set(full_name(&file_path)),
+			share::is_dir::set(file_path.is_dir),
+			share::target::set(serde_json::to_string(&target)?),
+			share::key::set(key),
+			share::expires_at::set(expires_at.into()),
+			vec![
+				share::file_path::link(file_path::id::equals(file_path_id)),
+				share::protected::set(salt.is_some()),
+				share::salt::set(salt),
+			],
+		)
+		.exec()
+		.await?;
+
+	ctx.spawn_job(Job::new(
+		ShareJobInit { share_id: share.id },
+		Box::new(ShareJob {}),
+	))
+	.await;
+	send_invalidate_shares(ctx).await;
+
+	share.try_into()
+}
+
+// removes the bundle of a share from wherever it is, so its link stops working
+async fn discard(ctx: &LibraryContext, share: &share::Data) -> Result<(), ShareError> {
+	let target: ShareTarget = serde_json::from_str(&share.target)?;
+	if target.is_remote() && share.status == ShareStatus::Active.int_value() {
+		target.delete(&object_name(&share.token)).await?;
+	}
+
+	match tokio::fs::remove_file(bundle_path(ctx, &share.pub_id)).await {
+		Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+		_ => Ok(()),
+	}
+}
+
+// a share stays active until its bundle could be removed from the target, so the user can try
+// again rather than believe a link dead which still works
+pub async fn revoke_share(ctx: &LibraryContext, id: i32) -> Result<(), ShareError> {
+	let share = ctx
+		.db
+		.share()
+		.find_unique(share::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(ShareError::NotFound(id))?;
+	if matches!(
+		ShareStatus::from_int(share.status),
+		Ok(ShareStatus::Revoked) | Ok(ShareStatus::Expired)
+	) {
+		return Ok(());
+	}
+
+	discard(ctx, &share).await?;
+	set_status(ctx, id, ShareStatus::Revoked).await?;
+	send_invalidate_shares(ctx).await;
+
+	Ok(())
+}
+
+// the newest first
+pub async fn get_shares(ctx: &LibraryContext) -> Result<Vec<Share>, ShareError> {
+	let mut shares = ctx
+		.db
+		.share()
+		.find_many(vec![])
+		.exec()
+		.await?
+		.into_iter()
+		.map(TryInto::try_into)
+		.collect::<Result<Vec<Share>, _>>()?;
+	shares.sort_by(|a, b| b.date_created.cmp(&a.date_created));
+
+	Ok(shares)
+}
+
+// open_share checks a link served by the gateway of this node, counting a download
+pub async fn open_share(
+	ctx: &LibraryContext,
+	token: String,
+	password: Option<String>,
+) -> Result<ShareBundle, ShareError> {
+	let share = ctx
+		.db
+		.share()
+		.find_unique(share::token::equals(token))
+		.exec()
+		.await?
+		.ok_or(ShareError::LinkNotFound)?;
+
+	let target: ShareTarget = serde_json::from_str(&share.target)?;
+	if target.is_remote() {
+		return Err(ShareError::LinkNotFound);
+	}
+	match ShareStatus::from_int(share.status) {
+		Ok(ShareStatus::Active) => {}
+		Ok(ShareStatus::Expired) => return Err(ShareError::Expired),
+		_ => return Err(ShareError::LinkNotFound),
+	}
+	// the share may not have been expired yet
+	if DateTime::<Utc>::from(share.expires_at) <= Utc::now() {
+		return Err(ShareError::Expired);
+	}
+
+	if share.protected {
+		let password = password.ok_or(ShareError::PasswordRequired)?;
+		let key = derive_key(&password, share.salt.as_deref().unwrap_or_default());
+		ring::constant_time::verify_slices_are_equal(&key, &share.key)
+			.map_err(|_| ShareError::WrongPassword)?;
+	}
+
+	ctx.db
+		.share()
+		.find_unique(share::id::equals(share.id))
+		.update(vec![share::download_count::increment(1)])
+		.exec()
+		.await?;
+	send_invalidate_shares(ctx).await;
+
+	Ok(ShareBundle {
+		path: bundle_path(ctx, &share.pub_id),
+		name: share.name,
+		is_dir: share.is_dir,
+		key: share.key,
+	})
+}
+
+async fn expire_shares(ctx: &LibraryContext) -> Result<(), ShareError> {
+	let expired = ctx
+		.db
+		.share()
+		.find_many(vec![
+			share::status::in_vec(vec![
+				ShareStatus::Pending.int_value(),
+				ShareStatus::Active.int_value(),
+			]),
+			share::expires_at::lt(Utc::now().into()),
+		])
+		.exec()
+		.await?;
+	if expired.is_empty() {
+		return Ok(());
+	}
+
+	for share in expired {
+		// tried again at the next check
+		if let Err(e) = discard(ctx, &share).await {
+			error!(
+				"Failed to remove the bundle of share {}: {:#?}",
+				share.id, e
+			);
+			continue;
+		}
+		set_status(ctx, share.id, ShareStatus::Expired).await?;
+		info!("Share {} expired", share.id);
+	}
+	send_invalidate_shares(ctx).await;
+
+	Ok(())
+}
+
+pub async fn watch_shares(library_manager: Arc<LibraryManager>) {
+	loop {
+		for ctx in library_manager.get_all_libraries_ctx().await {
+			if let Err(e) = expire_shares(&ctx).await {
+				error!("Failed to expire shares: {:#?}", e);
+			}
+		}
+
+		tokio::time::sleep(SHARE_CHECK_INTERVAL).await;
+	}
+}
+
+pub struct ShareJob {}
+
+// ShareJobInit encrypts the shared entry into a bundle, uploading it when the share target is
+// remote. The share is active once it's done, unless revoked in the meantime.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ShareJobInit {
+	pub share_id: i32,
+}
+
+async fn publish(ctx: &LibraryContext, share_id: i32) -> Result<(), ShareError> {
+	let share = ctx
+		.db
+		.share()
+		.find_unique(share::id::equals(share_id))
+		.exec()
+		.await?
+		.ok_or(ShareError::NotFound(share_id))?;
+	if share.status != ShareStatus::Pending.int_value() {
+		return Ok(());
+	}
+
+	let file_path_id = share
+		.file_path_id
+		.ok_or(ShareError::FilePathNotFound(share_id))?;
+	let file_path = ctx
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.exec()
+		.await?
+		.ok_or(ShareError::FilePathNotFound(file_path_id))?;
+	let location = get_location(ctx, file_path.location_id.unwrap_or_default()).await?;
+	let source = location
+		.path
+		.ok_or(ShareError::LocationUnavailable)?
+		.join(&file_path.materialized_path);
+
+	let path = bundle_path(ctx, &share.pub_id);
+	let header = BundleHeader {
+		is_dir: share.is_dir,
+		salt: share.salt.as_deref().and_then(|salt| salt.try_into().ok()),
+	};
+	let (bundle, key, is_dir) = (path.clone(), share.key.clone(), share.is_dir);
+	tokio::task::spawn_blocking(move || -> io::Result<()> {
+		if let Some(parent) = bundle.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		let mut writer = BundleWriter::new(BufWriter::new(File::create(&bundle)?), &key, header)?;
+		match is_dir {
+			true => writer = write_tar(&source, writer)?,
+			false => {
+				io::copy(&mut File::open(&source)?, &mut writer)?;
+			}
+		}
+		writer.finish()?;
+		Ok(())
+	})
+	.await
+	.map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+	let target: ShareTarget = serde_json::from_str(&share.target)?;
+	let expires_at = share.expires_at.into();
+	let link = match target.is_remote() {
+		true => {
+			let object = object_name(&share.token);
+			target.upload(&object, &path).await?;
+			tokio::fs::remove_file(&path).await?;
+
+			let link = target.link(&object, expires_at)?;
+			match share.protected {
+				true => link,
+				// the key never reaches the target, browsers keep the fragment to themselves
+				false => format!(
+					"{}#{}",
+					link,
+					base64::encode_config(&share.key, base64::URL_SAFE_NO_PAD)
+				),
+			}
+		}
+		false => target.link(&format!("share/{}/{}", ctx.id, share.token), expires_at)?,
+	};
+
+	let activated = ctx
+		.db
+		.share()
+		.find_many(vec![
+			share::id::equals(share_id),
+			share::status::equals(ShareStatus::Pending.int_value()),
+		])
+		.update(vec![
+			share::status::set(ShareStatus::Active.int_value()),
+			share::link::set(Some(link)),
+		])
+		.exec()
+		.await?;
+	// revoked or expired while the bundle was built
+	if activated == 0 {
+		let mut share = share;
+		share.status = ShareStatus::Active.int_value();
+		discard(ctx, &share).await?;
+	}
+
+	Ok(())
+}
+
+// a share which couldn't be published is left failed, without what was built of its bundle
+async fn fail(ctx: &LibraryContext, share_id: i32) -> Result<(), ShareError> {
+	let share = match ctx
+		.db
+		.share()
+		.find_unique(share::id::equals(share_id))
+		.exec()
+		.await?
+	{
+		Some(share) => share,
+		None => return Ok(()),
+	};
+	tokio::fs::remove_file(bundle_path(ctx, &share.pub_id))
+		.await
+		.ok();
+
+	ctx.db
+		.share()
+		.find_many(vec![
+			share::id::equals(share_id),
+			share::status::equals(ShareStatus::Pending.int_value()),
+		])
+		.update(vec![share::status::set(ShareStatus::Failed.int_value())])
+		.exec()
+		.await?;
+	send_invalidate_shares(ctx).await;
+
+	Ok(())
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ShareJob {
+	type Init = ShareJobInit;
+	type Data = ();
+	type Step = ();
+
+	fn name(&self) -> &'static str {
+		SHARE_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		ctx.progress(vec![JobReportUpdate::TaskCount(1)]);
+		state.steps = [()].into_iter().collect();
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let share_id = state.init.share_id;
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Publishing share {}",
+			share_id
+		))]);
+
+		if let Err(e) = publish(&library_ctx, share_id).await {
+			fail(&library_ctx, share_id).await?;
+			return Err(e.into());
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(1)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		info!("Published share {}", state.init.share_id);
+		send_invalidate_shares(&ctx.library_ctx()).await;
+		Ok(())
+	}
+}
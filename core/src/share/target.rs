@@ -0,0 +1,375 @@
+use super::ShareError;
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::HEXLOWER;
+use reqwest::{Client, RequestBuilder, Response, Url};
+use ring::{digest, hmac};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use ts_rs::TS;
+
+// the longest a presigned S3 link stays valid
+pub const S3_MAX_LINK_DAYS: i64 = 7;
+
+// Where the bundles of the shares of a library are served from
+#[derive(Debug, Clone, Serialize, Deserialize, TS, PartialEq, Eq)]
+#[serde(tag = "key", content = "data")]
+#[ts(export)]
+pub enum ShareTarget {
+	// served by the http server of this node, at its address as the recipients reach it
+	Gateway {
+		base_url: String,
+	},
+	// uploaded to a directory of a WebDAV server
+	WebDav {
+		url: String,
+		username: Option<String>,
+		password: Option<String>,
+	},
+	// uploaded to a bucket of an S3 compatible storage, and shared through a presigned link
+	S3 {
+		endpoint: String,
+		region: String,
+		bucket: String,
+		access_key_id: String,
+		secret_access_key: String,
+	},
+}
+
+impl ShareTarget {
+	pub fn is_remote(&self) -> bool {
+		!matches!(self, Self::Gateway { .. })
+	}
+
+	fn object_url(&self, object: &str) -> Result<Url, ShareError> {
+		let object = encode_object(object);
+		let url = match self {
+			Self::Gateway { base_url } => format!("{}/{}", base_url.trim_end_matches('/'), object),
+			Self::WebDav { url, .. } => format!("{}/{}", url.trim_end_matches('/'), object),
+			Self::S3 {
+				endpoint, bucket, ..
+			} => format!("{}/{}/{}", endpoint.trim_end_matches('/'), bucket, object),
+		};
+		Url::parse(&url).map_err(|_| ShareError::InvalidTarget(url))
+	}
+
+	// the link to the bundle uploaded as `object`, valid until `expires_at` for S3
+	pub fn link(&self, object: &str, expires_at: DateTime<Utc>) -> Result<String, ShareError> {
+		let url = self.object_url(object)?;
+		Ok(match self {
+			Self::S3 {
+				region,
+				access_key_id,
+				secret_access_key,
+				..
+			} => presign_s3(
+				&url,
+				region,
+				access_key_id,
+				secret_access_key,
+				Utc::now(),
+				expires_at,
+			),
+			_ => url.to_string(),
+		})
+	}
+
+	pub async fn upload(&self, object: &str, path: &Path) -> Result<(), ShareError> {
+		let file = tokio::fs::File::open(path).await?;
+		let len = file.metadata().await?.len();
+		let request = self
+			.request(reqwest::Method::PUT, object)?
+			.header(reqwest::header::CONTENT_LENGTH, len)
+			.body(file);
+
+		check(request.send().await?).await
+	}
+
+	pub async fn upload_bytes(&self, object: &str, data: Vec<u8>) -> Result<(), ShareError> {
+		let request = self
+			.request(reqwest::Method::PUT, object)?
+			.header(reqwest::header::CONTENT_LENGTH, data.len())
+			.body(data);
+
+		check(request.send().await?).await
+	}
+
+	// a bundle already gone from the target counts as deleted
+	pub async fn delete(&self, object: &str) -> Result<(), ShareError> {
+		let response = self
+			.request(reqwest::Method::DELETE, object)?
+			.send()
+			.await?;
+		if response.status() == reqwest::StatusCode::NOT_FOUND {
+			return Ok(());
+		}
+		check(response).await
+	}
+
+	// the contents of an uploaded object, none if it isn't there
+	pub async fn download(&self, object: &str) -> Result<Option<Vec<u8>>, ShareError> {
+		let response = self.request(reqwest::Method::GET, object)?.send().await?;
+		if response.status() == reqwest::StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+		if !response.status().is_success() {
+			return check(response).await.map(|_| None);
+		}
+		Ok(Some(response.bytes().await?.to_vec()))
+	}
+
+	// the size of an uploaded object, none if it isn't there
+	pub async fn size(&self, object: &str) -> Result<Option<u64>, ShareError> {
+		let response = self.request(reqwest::Method::HEAD, object)?.send().await?;
+		if response.status() == reqwest::StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+		let size = response.content_length();
+		check(response).await?;
+		Ok(size)
+	}
+
+	// creates the directories of a WebDAV target up to `path`, which S3 doesn't have
+	pub async fn create_collection(&self, path: &str) -> Result<(), ShareError> {
+		if !matches!(self, Self::WebDav { .. }) {
+			return Ok(());
+		}
+
+		let mut collection = String::new();
+		for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+			collection = format!("{}{}/", collection, segment);
+			let response = self
+				.request(reqwest::Method::from_bytes(b"MKCOL").unwrap(), &collection)?
+				.send()
+				.await?;
+			// 405 when the collection already exists
+			if response.status() != reqwest::StatusCode::METHOD_NOT_ALLOWED {
+				check(response).await?;
+			}
+		}
+
+		Ok(())
+	}
+
+	// moves an uploaded object on the target, the parent collection of `to` must exist
+	pub async fn rename(&self, from: &str, to: &str) -> Result<(), ShareError> {
+		match self {
+			Self::WebDav { .. } => {
+				let response = self
+					.request(reqwest::Method::from_bytes(b"MOVE").unwrap(), from)?
+					.header("Destination", self.object_url(to)?.to_string())
+					.header("Overwrite", "T")
+					.send()
+					.await?;
+				check(response).await
+			}
+			// S3 can only copy an object, and remove the original once copied
+			Self::S3 { bucket, .. } => {
+				let source = format!("/{}/{}", bucket, encode_object(from));
+				let response = self
+					.request_with(reqwest::Method::PUT, to, &[("x-amz-copy-source", &source)])?
+					.send()
+					.await?;
+				check(response).await?;
+				self.delete(from).await
+			}
+			Self::Gateway { .. } => unreachable!("bundles of the gateway stay on this node"),
+		}
+	}
+
+	fn request(&self, method: reqwest::Method, object: &str) -> Result<RequestBuilder, ShareError> {
+		self.request_with(method, object, &[])
+	}
+
+	// `amz_headers` are only sent to S3 targets, where they are signed with the request
+	fn request_with(
+		&self,
+		method: reqwest::Method,
+		object: &str,
+		amz_headers: &[(&str, &str)],
+	) -> Result<RequestBuilder, ShareError> {
+		let url = self.object_url(object)?;
+		let request = Client::new().request(method.clone(), url.clone());
+		Ok(match self {
+			Self::Gateway { .. } => unreachable!("bundles of the gateway stay on this node"),
+			Self::WebDav {
+				username, password, ..
+			} => match username {
+				Some(username) => request.basic_auth(username, password.as_ref()),
+				None => request,
+			},
+			Self::S3 {
+				region,
+				access_key_id,
+				secret_access_key,
+				..
+			} => sign_s3(
+				request,
+				method.as_str(),
+				&url,
+				region,
+				access_key_id,
+				secret_access_key,
+				amz_headers,
+				Utc::now(),
+			),
+		})
+	}
+}
+
+async fn check(response: Response) -> Result<(), ShareError> {
+	let status = response.status();
+	if status.is_success() {
+		return Ok(());
+	}
+	let body = response.text().await.unwrap_or_default();
+	Err(ShareError::TargetResponse(status.as_u16(), body))
+}
+
+// percent encodes every segment of an object name as S3 signatures expect them, leaving the
+// slashes between them
+fn encode_object(object: &str) -> String {
+	object
+		.split('/')
+		.map(|segment| {
+			segment
+				.bytes()
+				.map(|byte| match byte {
+					b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+						(byte as char).to_string()
+					}
+					_ => format!("%{:02X}", byte),
+				})
+				.collect::<String>()
+		})
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+	HEXLOWER.encode(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+	hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+		.as_ref()
+		.to_vec()
+}
+
+fn host(url: &Url) -> String {
+	match url.port() {
+		Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+		None => url.host_str().unwrap_or_default().to_string(),
+	}
+}
+
+// the scope and signature of an AWS signature version 4
+fn signature_v4(
+	canonical_request: &str,
+	region: &str,
+	secret_access_key: &str,
+	now: DateTime<Utc>,
+) -> (String, String) {
+	let date = now.format("%Y%m%d").to_string();
+	let scope = format!("{}/{}/s3/aws4_request", date, region);
+	let string_to_sign = format!(
+		"AWS4-HMAC-SHA256\n{}\n{}\n{}",
+		now.format("%Y%m%dT%H%M%SZ"),
+		scope,
+		sha256_hex(canonical_request.as_bytes())
+	);
+
+	let mut key = hmac_sha256(format!("AWS4{}", secret_access_key).as_bytes(), &date);
+	for part in [region, "s3", "aws4_request"] {
+		key = hmac_sha256(&key, part);
+	}
+
+	(scope, HEXLOWER.encode(&hmac_sha256(&key, &string_to_sign)))
+}
+
+// signs a request with headers, leaving the body unsigned so it can be streamed
+#[allow(clippy::too_many_arguments)]
+fn sign_s3(
+	mut request: RequestBuilder,
+	method: &str,
+	url: &Url,
+	region: &str,
+	access_key_id: &str,
+	secret_access_key: &str,
+	amz_headers: &[(&str, &str)],
+	now: DateTime<Utc>,
+) -> RequestBuilder {
+	let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+	let host = host(url);
+	let mut headers = vec![
+		("host", host.as_str()),
+		("x-amz-content-sha256", "UNSIGNED-PAYLOAD"),
+		("x-amz-date", timestamp.as_str()),
+	];
+	headers.extend_from_slice(amz_headers);
+	// canonical headers are in the order of their names
+	headers.sort_by_key(|(name, _)| *name);
+
+	let signed_headers = headers
+		.iter()
+		.map(|(name, _)| *name)
+		.collect::<Vec<_>>()
+		.join(";");
+	let canonical_headers = headers
+		.iter()
+		.map(|(name, value)| format!("{}:{}\n", name, value))
+		.collect::<String>();
+	let canonical_request = format!(
+		"{}\n{}\n\n{}\n{}\nUNSIGNED-PAYLOAD",
+		method,
+		url.path(),
+		canonical_headers,
+		signed_headers
+	);
+	let (scope, signature) = signature_v4(&canonical_request, region, secret_access_key, now);
+
+	for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+		request = request.header(*name, *value);
+	}
+	request.header(
+		reqwest::header::AUTHORIZATION,
+		format!(
+			"AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+			access_key_id, scope, signed_headers, signature
+		),
+	)
+}
+
+// a link anyone can download the object with until it expires, at most S3_MAX_LINK_DAYS from now
+fn presign_s3(
+	url: &Url,
+	region: &str,
+	access_key_id: &str,
+	secret_access_key: &str,
+	now: DateTime<Utc>,
+	expires_at: DateTime<Utc>,
+) -> String {
+	let expires_in = (expires_at - now)
+		.min(Duration::days(S3_MAX_LINK_DAYS))
+		.num_seconds()
+		.max(1);
+	let scope = format!("{}/{}/s3/aws4_request", now.format("%Y%m%d"), region);
+	// already in the order of their names, as the canonical request needs them
+	let query = format!(
+		"X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential={}%2F{}&X-Amz-Date={}&X-Amz-Expires={}&X-Amz-SignedHeaders=host",
+		access_key_id,
+		scope.replace('/', "%2F"),
+		now.format("%Y%m%dT%H%M%SZ"),
+		expires_in
+	);
+	let canonical_request = format!(
+		"GET\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD",
+		url.path(),
+		query,
+		host(url)
+	);
+	let (_, signature) = signature_v4(&canonical_request, region, secret_access_key, now);
+
+	let mut url = url.clone();
+	url.set_query(Some(&format!("{}&X-Amz-Signature={}", query, signature)));
+	url.to_string()
+}
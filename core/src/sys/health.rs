@@ -0,0 +1,463 @@
+use crate::{
+	library::{LibraryContext, LibraryManager},
+	prisma::{location, volume_health},
+	CoreEvent, NodeContext,
+};
+use chrono::{DateTime, Utc};
+use log::{debug, error, warn};
+use prisma_client_rust::{prisma_models::PrismaValue, raw, Direction};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+	collections::HashSet,
+	fs,
+	path::{Path, PathBuf},
+	process::Command,
+	sync::Arc,
+	time::Duration,
+};
+use tokio::task::spawn_blocking;
+use ts_rs::TS;
+
+use super::SysError;
+
+// S.M.A.R.T. attributes change slowly, polling more often only grows the sample history
+const VOLUME_HEALTH_POLL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// older samples are removed, the history only has to show how a disk got worse lately
+const VOLUME_HEALTH_RETENTION_DAYS: i64 = 90;
+
+// ATA attribute ids we care about, see: https://en.wikipedia.org/wiki/S.M.A.R.T.#Known_ATA_S.M.A.R.T._attributes
+const ATA_REALLOCATED_SECTORS: i64 = 5;
+const ATA_CURRENT_PENDING_SECTORS: i64 = 197;
+const ATA_OFFLINE_UNCORRECTABLE: i64 = 198;
+
+// A single S.M.A.R.T. health sample of a physical disk
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct VolumeHealth {
+	pub device: String,
+	pub model: Option<String>,
+	pub serial_number: Option<String>,
+	// the overall self-assessment reported by the drive
+	pub passed: bool,
+	pub reallocated_sectors: Option<i32>,
+	pub pending_sectors: Option<i32>,
+	pub uncorrectable_sectors: Option<i32>,
+	pub temperature: Option<i32>,
+	pub power_on_hours: Option<i32>,
+	#[ts(type = "string")]
+	pub date_captured: DateTime<Utc>,
+}
+
+impl From<volume_health::Data> for VolumeHealth {
+	fn from(data: volume_health::Data) -> Self {
+		Self {
+			device: data.device,
+			model: data.model,
+			serial_number: data.serial_number,
+			passed: data.passed,
+			reallocated_sectors: data.reallocated_sectors,
+			pending_sectors: data.pending_sectors,
+			uncorrectable_sectors: data.uncorrectable_sectors,
+			temperature: data.temperature,
+			power_on_hours: data.power_on_hours,
+			date_captured: data.date_captured.into(),
+		}
+	}
+}
+
+impl VolumeHealth {
+	// a drive is considered failing if it fails its self-assessment or has sectors it can't read back
+	pub fn is_failing(&self) -> bool {
+		!self.passed
+			|| self.pending_sectors.unwrap_or(0) > 0
+			|| self.uncorrectable_sectors.unwrap_or(0) > 0
+	}
+
+	// polls the physical disks smartctl can see which are among `disks`, see backing_disks.
+	// Returns nothing if smartmontools isn't installed.
+	pub fn get_volume_health(disks: &HashSet<String>) -> Vec<VolumeHealth> {
+		let scan = match Command::new("smartctl").args(["--scan", "--json"]).output() {
+			Ok(output) => output,
+			Err(e) => {
+				debug!("smartctl is not available, skipping volume health: {}", e);
+				return vec![];
+			}
+		};
+
+		let scan: Value = match serde_json::from_slice(&scan.stdout) {
+			Ok(scan) => scan,
+			Err(e) => {
+				error!("Failed to parse smartctl scan output: {:#?}", e);
+				return vec![];
+			}
+		};
+
+		scan["devices"]
+			.as_array()
+			.map(Vec::as_slice)
+			.unwrap_or_default()
+			.iter()
+			.filter_map(|device| {
+				let name = device["name"]
+					.as_str()
+					.filter(|name| disks.contains(*name))?;
+				let device_type = device["type"].as_str().unwrap_or("auto");
+
+				// smartctl uses the bits of the exit code to report disk problems, so only stdout matters
+				let output = Command::new("smartctl")
+					.args(["--json", "-a", "-d", device_type, name])
+					.output()
+					.map_err(|e| error!("Failed to run smartctl on '{}': {:#?}", name, e))
+					.ok()?;
+
+				serde_json::from_slice::<Value>(&output.stdout)
+					.map_err(|e| error!("Failed to parse smartctl output of '{}': {:#?}", name, e))
+					.ok()
+					.and_then(|report| Self::from_smartctl_report(name, &report))
+			})
+			.collect()
+	}
+
+	fn from_smartctl_report(device: &str, report: &Value) -> Option<Self> {
+		// disks that don't support S.M.A.R.T. (eg: most usb enclosures) have no status at all
+		let passed = report["smart_status"]["passed"].as_bool()?;
+
+		let ata_attribute = |id: i64| {
+			report["ata_smart_attributes"]["table"]
+				.as_array()?
+				.iter()
+				.find(|attribute| attribute["id"].as_i64() == Some(id))
+				.and_then(|attribute| attribute["raw"]["value"].as_i64())
+				.map(|value| value as i32)
+		};
+		let nvme_log = &report["nvme_smart_health_information_log"];
+
+		Some(Self {
+			device: device.to_string(),
+			model: report["model_name"].as_str().map(str::to_string),
+			serial_number: report["serial_number"].as_str().map(str::to_string),
+			passed,
+			reallocated_sectors: ata_attribute(ATA_REALLOCATED_SECTORS),
+			pending_sectors: ata_attribute(ATA_CURRENT_PENDING_SECTORS),
+			uncorrectable_sectors: ata_attribute(ATA_OFFLINE_UNCORRECTABLE)
+				.or_else(|| nvme_log["media_errors"].as_i64().map(|v| v as i32)),
+			temperature: report["temperature"]["current"].as_i64().map(|v| v as i32),
+			power_on_hours: report["power_on_time"]["hours"].as_i64().map(|v| v as i32),
+			date_captured: Utc::now(),
+		})
+	}
+
+	pub async fn save(&self, ctx: &LibraryContext) -> Result<(), SysError> {
+		ctx.db
+			.volume_health()
+			.create(
+				volume_health::node_id::set(ctx.node_local_id),
+				volume_health::device::set(self.device.clone()),
+				volume_health::passed::set(self.passed),
+				vec![
+					volume_health::model::set(self.model.clone()),
+					volume_health::serial_number::set(self.serial_number.clone()),
+					volume_health::reallocated_sectors::set(self.reallocated_sectors),
+					volume_health::pending_sectors::set(self.pending_sectors),
+					volume_health::uncorrectable_sectors::set(self.uncorrectable_sectors),
+					volume_health::temperature::set(self.temperature),
+					volume_health::power_on_hours::set(self.power_on_hours),
+					volume_health::date_captured::set(self.date_captured.into()),
+				],
+			)
+			.exec()
+			.await?;
+
+		Ok(())
+	}
+}
+
+#[derive(Deserialize)]
+struct LatestSample {
+	id: i32,
+}
+
+// returns the most recent health sample of every disk attached to this node
+pub async fn get_volume_health(ctx: &LibraryContext) -> Result<Vec<VolumeHealth>, SysError> {
+	// samples are written as they are captured, so the latest of a disk has its highest id
+	let latest = ctx
+		.db
+		._query_raw::<LatestSample>(raw!(
+			"SELECT MAX(id) AS id FROM volume_health WHERE node_id = {} GROUP BY device",
+			PrismaValue::Int(ctx.node_local_id as i64)
+		))
+		.await?;
+
+	Ok(ctx
+		.db
+		.volume_health()
+		.find_many(vec![volume_health::id::in_vec(
+			latest.into_iter().map(|sample| sample.id).collect(),
+		)])
+		.order_by(volume_health::device::order(Direction::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(Into::into)
+		.collect())
+}
+
+// the paths of the locations of the library stored on this node
+async fn location_paths(ctx: &LibraryContext) -> Result<Vec<PathBuf>, SysError> {
+	Ok(ctx
+		.db
+		.location()
+		.find_many(vec![location::node_id::equals(Some(ctx.node_local_id))])
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|location| location.local_path.map(PathBuf::from))
+		.collect())
+}
+
+async fn prune_samples(ctx: &LibraryContext) -> Result<(), SysError> {
+	ctx.db
+		.volume_health()
+		.find_many(vec![
+			volume_health::node_id::equals(ctx.node_local_id),
+			volume_health::date_captured::lt(
+				(Utc::now() - chrono::Duration::days(VOLUME_HEALTH_RETENTION_DAYS)).into(),
+			),
+		])
+		.delete()
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+// the physical disks the paths are stored on, named as smartctl names them, eg: "/dev/sda".
+// Network shares and disks which can't be told apart are left out.
+fn backing_disks(paths: &[PathBuf]) -> HashSet<String> {
+	if cfg!(target_os = "linux") {
+		let mounts = fs::read_to_string("/proc/mounts").unwrap_or_else(|e| {
+			error!("Failed to read /proc/mounts: {:#?}", e);
+			String::new()
+		});
+		paths
+			.iter()
+			.filter_map(|path| linux_mount_source(&mounts, path))
+			.flat_map(|source| {
+				fs::canonicalize(source)
+					.ok()
+					.and_then(|device| Some(device.file_name()?.to_string_lossy().to_string()))
+					.map(|name| linux_disks(&name))
+					.unwrap_or_default()
+			})
+			.collect()
+	} else if cfg!(target_os = "macos") {
+		paths.iter().filter_map(|path| macos_disk(path)).collect()
+	} else if cfg!(target_os = "windows") {
+		paths.iter().filter_map(|path| windows_disk(path)).collect()
+	} else {
+		HashSet::new()
+	}
+}
+
+// the device mounted deepest above the path, eg: "/dev/sda1"
+fn linux_mount_source<'a>(mounts: &'a str, path: &Path) -> Option<&'a str> {
+	mounts
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split_whitespace();
+			Some((fields.next()?, fields.next()?))
+		})
+		.filter(|(source, _)| source.starts_with("/dev/"))
+		// spaces in mount points are escaped as \040
+		.map(|(source, mount_point)| (source, mount_point.replace("\\040", " ")))
+		.filter(|(_, mount_point)| path.starts_with(mount_point))
+		.max_by_key(|(_, mount_point)| mount_point.len())
+		.map(|(source, _)| source)
+}
+
+// a partition resolves to its disk, and a device mapper or md device to the disks under it
+fn linux_disks(name: &str) -> Vec<String> {
+	let block = Path::new("/sys/class/block").join(name);
+
+	let slaves = fs::read_dir(block.join("slaves"))
+		.into_iter()
+		.flatten()
+		.filter_map(|entry| entry.ok())
+		.map(|entry| entry.file_name().to_string_lossy().to_string())
+		.collect::<Vec<_>>();
+	if !slaves.is_empty() {
+		return slaves.iter().flat_map(|slave| linux_disks(slave)).collect();
+	}
+
+	if block.join("partition").exists() {
+		// the directory of a partition is within the one of its disk
+		return fs::canonicalize(&block)
+			.ok()
+			.and_then(|path| Some(path.parent()?.file_name()?.to_string_lossy().to_string()))
+			.map(|disk| linux_disks(&disk))
+			.unwrap_or_default();
+	}
+
+	let mut disks = vec![format!("/dev/{}", name)];
+	// smartctl names nvme disks after their controller, "nvme0n1" is "nvme0"
+	if let Some((controller, _)) = name.rsplit_once('n').filter(|_| name.starts_with("nvme")) {
+		disks.push(format!("/dev/{}", controller));
+	}
+	disks
+}
+
+// apfs volumes are on a synthesized disk, the physical one is the disk of its physical store
+fn macos_disk(path: &Path) -> Option<String> {
+	// diskutil only knows of devices and mount points, df gives the device a path is on
+	let df = Command::new("df").arg("-P").arg(path).output().ok()?;
+	let device = String::from_utf8_lossy(&df.stdout)
+		.lines()
+		.nth(1)?
+		.split_whitespace()
+		.next()
+		.filter(|device| device.starts_with("/dev/"))?
+		.to_string();
+
+	let output = Command::new("diskutil")
+		.args(["info", &device])
+		.output()
+		.ok()?;
+	let info = String::from_utf8_lossy(&output.stdout);
+	let field = |name: &str| {
+		info.lines()
+			.find_map(|line| line.trim().strip_prefix(name))
+			.map(|value| value.trim().to_string())
+	};
+
+	let partition = field("APFS Physical Store:").or_else(|| field("Part of Whole:"))?;
+	// "disk0s2" is the second partition of "disk0"
+	let disk = partition
+		.strip_prefix("disk")?
+		.split('s')
+		.next()
+		.filter(|number| !number.is_empty())?;
+	Some(format!("/dev/disk{}", disk))
+}
+
+// smartctl names the physical drives of windows "/dev/sda", "/dev/sdb" and so on
+fn windows_disk(path: &Path) -> Option<String> {
+	let letter = path
+		.to_str()?
+		.chars()
+		.next()
+		.filter(|letter| letter.is_ascii_alphabetic())?;
+	let output = Command::new("powershell")
+		.args([
+			"-NoProfile",
+			"-Command",
+			&format!("(Get-Partition -DriveLetter {}).DiskNumber", letter),
+		])
+		.output()
+		.ok()?;
+	let number = String::from_utf8_lossy(&output.stdout)
+		.trim()
+		.parse::<u8>()
+		.ok()
+		.filter(|number| *number < 26)?;
+
+	Some(format!("/dev/sd{}", (b'a' + number) as char))
+}
+
+// watch_volume_health periodically samples the health of the disks backing locations into the
+// libraries with locations on them, and warns the user once when a disk starts to report failing
+// attributes
+pub async fn watch_volume_health(library_manager: Arc<LibraryManager>, node_ctx: NodeContext) {
+	let mut failing_devices = HashSet::new();
+
+	loop {
+		let mut libraries = vec![];
+		for ctx in library_manager.get_all_libraries_ctx().await {
+			if let Err(e) = prune_samples(&ctx).await {
+				error!("Failed to prune volume health samples: {:#?}", e);
+			}
+
+			let paths = match location_paths(&ctx).await {
+				Ok(paths) => paths,
+				Err(e) => {
+					error!("Failed to read locations: {:#?}", e);
+					continue;
+				}
+			};
+			let disks = spawn_blocking(move || backing_disks(&paths))
+				.await
+				.unwrap_or_default();
+			libraries.push((ctx, disks));
+		}
+
+		let disks = libraries
+			.iter()
+			.flat_map(|(_, disks)| disks.iter().cloned())
+			.collect::<HashSet<_>>();
+		let samples = match disks.is_empty() {
+			true => vec![],
+			false => spawn_blocking(move || VolumeHealth::get_volume_health(&disks))
+				.await
+				.unwrap_or_default(),
+		};
+
+		for sample in samples {
+			for (ctx, disks) in &libraries {
+				if !disks.contains(&sample.device) {
+					continue;
+				}
+				if let Err(e) = sample.save(ctx).await {
+					error!("Failed to save health of '{}': {:#?}", sample.device, e);
+				}
+			}
+
+			if sample.is_failing() {
+				if failing_devices.insert(sample.device.clone()) {
+					warn!(
+						"Disk '{}' reports failing health: {:?}",
+						sample.device, sample
+					);
+					node_ctx.emit(CoreEvent::VolumeHealthWarning(sample)).await;
+				}
+			} else {
+				failing_devices.remove(&sample.device);
+			}
+		}
+
+		tokio::time::sleep(VOLUME_HEALTH_POLL_INTERVAL).await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MOUNTS: &str = "/dev/sda2 / ext4 rw,relatime 0 0
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+/dev/sdb1 /mnt/media ext4 rw,relatime 0 0
+/dev/sdc1 /mnt/media/old\\040photos xfs rw,relatime 0 0
+//nas/share /mnt/nas cifs rw,relatime 0 0
+";
+
+	#[test]
+	fn path_is_on_the_device_mounted_deepest_above_it() {
+		let source = |path: &str| linux_mount_source(MOUNTS, Path::new(path));
+
+		assert_eq!(source("/home/user"), Some("/dev/sda2"));
+		assert_eq!(source("/mnt/media/videos"), Some("/dev/sdb1"));
+		assert_eq!(source("/mnt/media/old photos/2019"), Some("/dev/sdc1"));
+		assert_eq!(source("/mnt/media2"), Some("/dev/sda2"));
+	}
+
+	#[test]
+	fn network_shares_have_no_device() {
+		assert_eq!(
+			linux_mount_source(MOUNTS, Path::new("/mnt/nas/photos")),
+			Some("/dev/sda2")
+		);
+		assert_eq!(
+			linux_mount_source("//nas/share /mnt/nas cifs rw 0 0", Path::new("/mnt/nas")),
+			None
+		);
+	}
+}
@@ -1,15 +1,22 @@
-use super::SysError;
+use super::{get_network_shares, SysError};
 use crate::{
+	encode::{AudioMetadataJob, AudioMetadataJobInit, ImageMetadataJob, ImageMetadataJobInit},
 	file::{
-		cas::FileIdentifierJob,
+		cas::{ChunkHasherJob, ChunkHasherJobInit, FileIdentifierJob},
 		indexer::{IndexerJob, IndexerJobInit},
+		secrets::{SecretsScannerJob, SecretsScannerJobInit},
+		sizes::{FolderSizesJob, FolderSizesJobInit},
+		text::{TextExtractorJob, TextExtractorJobInit},
+		versions::VersioningPolicy,
 	},
 	library::LibraryContext,
 	node::LibraryNode,
-	prisma::{file_path, location},
+	prisma::{file_path, location, location_path_mapping, node},
+	tag::finder::{FinderTagsJob, FinderTagsJobInit},
 	ClientQuery, CoreEvent, FileIdentifierJobInit, Job, LibraryQuery, ThumbnailJob,
 	ThumbnailJobInit,
 };
+use int_enum::IntEnum;
 use log::info;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -20,6 +27,7 @@ use thiserror::Error;
 use tokio::{
 	fs::{metadata, File},
 	io::{self, AsyncWriteExt},
+	task::spawn_blocking,
 };
 use ts_rs::TS;
 use uuid::Uuid;
@@ -35,10 +43,101 @@ pub struct LocationResource {
 	pub is_removable: Option<bool>,
 	pub node: Option<LibraryNode>,
 	pub is_online: bool,
+	// paths of this location on other nodes
+	pub path_mappings: Vec<LocationPathMapping>,
+	// unset to pick one from the volume the location is on
+	pub thumbnail_policy: Option<ThumbnailPolicy>,
+	#[serde(default)]
+	pub symlink_policy: SymlinkPolicy,
+	// unset when the files of the location aren't versioned
+	pub versioning: Option<VersioningPolicy>,
 	#[ts(type = "string")]
 	pub date_created: chrono::DateTime<chrono::Utc>,
 }
 
+// When the thumbnails of a location are generated
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum ThumbnailPolicy {
+	// for every file, once the location is indexed
+	Always = 0,
+	// for the files of a directory once it is opened, so indexing doesn't read every file over the network
+	OnDemand = 1,
+	// only when asked to with `GenerateThumbsForLocation`
+	Never = 2,
+}
+
+// Whether the indexer goes through the symlinked directories of a location. Links are always
+// indexed themselves, and symlinked files are identified through the link
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum SymlinkPolicy {
+	Skip = 0,
+	// links back to a directory they are in are skipped, so a cycle doesn't index forever
+	Follow = 1,
+}
+
+impl Default for SymlinkPolicy {
+	fn default() -> Self {
+		Self::Skip
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LocationPathMapping {
+	pub node_id: i32,
+	pub path: PathBuf,
+	#[ts(type = "string")]
+	pub date_created: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<location_path_mapping::Data> for LocationPathMapping {
+	fn from(data: location_path_mapping::Data) -> Self {
+		LocationPathMapping {
+			node_id: data.node_id,
+			path: PathBuf::from(data.path),
+			date_created: data.date_created.into(),
+		}
+	}
+}
+
+impl LocationResource {
+	// a location shared between nodes is usually mounted somewhere else on this one
+	fn resolve_path(mut self, node_id: i32) -> Self {
+		if let Some(mapping) = self.path_mappings.iter().find(|m| m.node_id == node_id) {
+			self.path = Some(mapping.path.clone());
+		}
+		self
+	}
+
+	// the policy set for the location, otherwise network volumes generate thumbnails on demand
+	pub async fn thumbnail_policy(&self) -> ThumbnailPolicy {
+		if let Some(policy) = self.thumbnail_policy {
+			return policy;
+		}
+
+		let path = match &self.path {
+			Some(path) => path.clone(),
+			None => return ThumbnailPolicy::OnDemand,
+		};
+		let on_network = spawn_blocking(move || {
+			get_network_shares()
+				.iter()
+				.any(|share| path.starts_with(&share.mount_point))
+		})
+		.await
+		.unwrap_or(false);
+
+		match on_network {
+			true => ThumbnailPolicy::OnDemand,
+			false => ThumbnailPolicy::Always,
+		}
+	}
+}
+
 impl From<location::Data> for LocationResource {
 	fn from(data: location::Data) -> Self {
 		LocationResource {
@@ -50,6 +149,20 @@ impl From<location::Data> for LocationResource {
 			is_removable: data.is_removable,
 			node: data.node.unwrap_or(None).map(Into::into),
 			is_online: data.is_online,
+			path_mappings: data
+				.path_mappings
+				.unwrap_or_default()
+				.into_iter()
+				.map(Into::into)
+				.collect(),
+			thumbnail_policy: data
+				.thumbnail_policy
+				.and_then(|policy| ThumbnailPolicy::from_int(policy).ok()),
+			symlink_policy: SymlinkPolicy::from_int(data.symlink_policy).unwrap_or_default(),
+			versioning: data.versioning_enabled.then(|| VersioningPolicy {
+				max_versions: data.versions_max_count,
+				quota_bytes: data.versions_quota,
+			}),
 			date_created: data.date_created.into(),
 		}
 	}
@@ -61,7 +174,7 @@ pub struct DotSpacedrive {
 	pub library_uuid: Uuid,
 }
 
-static DOTFILE_NAME: &str = ".spacedrive";
+pub(crate) static DOTFILE_NAME: &str = ".spacedrive";
 
 // checks to see if a location is:
 // - accessible on from the local filesystem
@@ -84,9 +197,10 @@ pub async fn get_location(
 	ctx.db
 		.location()
 		.find_unique(location::id::equals(location_id))
+		.with(location::path_mappings::fetch(vec![]))
 		.exec()
 		.await?
-		.map(Into::into)
+		.map(|location| LocationResource::from(location).resolve_path(ctx.node_local_id))
 		.ok_or_else(|| LocationError::IdNotFound(location_id).into())
 }
 
@@ -107,14 +221,74 @@ pub async fn scan_location(ctx: &LibraryContext, location_id: i32, path: impl As
 		Box::new(FileIdentifierJob {}),
 	))
 	.await;
+	// tags are on files, so the files have to be identified first
+	if cfg!(target_os = "macos") {
+		ctx.queue_job(Job::new(
+			FinderTagsJobInit { location_id },
+			Box::new(FinderTagsJob {}),
+		))
+		.await;
+	}
+	// sizes are summed from those of the files, known once they're identified
+	ctx.queue_job(Job::new(
+		FolderSizesJobInit {
+			location_id: Some(location_id),
+		},
+		Box::new(FolderSizesJob {}),
+	))
+	.await;
+
+	if ctx.config.object_chunk_hashing {
+		ctx.queue_job(Job::new(
+			ChunkHasherJobInit { location_id },
+			Box::new(ChunkHasherJob {}),
+		))
+		.await;
+	}
+
+	if ctx.config.secrets_scanning {
+		ctx.queue_job(Job::new(
+			SecretsScannerJobInit { location_id },
+			Box::new(SecretsScannerJob {}),
+		))
+		.await;
+	}
+
+	let thumbnail_policy = match get_location(ctx, location_id).await {
+		Ok(location) => location.thumbnail_policy().await,
+		Err(_) => ThumbnailPolicy::Always,
+	};
+	if thumbnail_policy == ThumbnailPolicy::Always {
+		ctx.queue_job(Job::new(
+			ThumbnailJobInit {
+				location_id,
+				path: path_buf,
+				background: true,
+				shallow: false,
+			},
+			Box::new(ThumbnailJob {}),
+		))
+		.await;
+	}
+
+	ctx.queue_job(Job::new(
+		TextExtractorJobInit { location_id },
+		Box::new(TextExtractorJob {}),
+	))
+	.await;
 
 	ctx.queue_job(Job::new(
-		ThumbnailJobInit {
+		AudioMetadataJobInit {
 			location_id,
-			path: path_buf,
 			background: true,
 		},
-		Box::new(ThumbnailJob {}),
+		Box::new(AudioMetadataJob {}),
+	))
+	.await;
+
+	ctx.queue_job(Job::new(
+		ImageMetadataJobInit { location_id },
+		Box::new(ImageMetadataJob {}),
 	))
 	.await;
 }
@@ -136,11 +310,15 @@ pub async fn get_locations(ctx: &LibraryContext) -> Result<Vec<LocationResource>
 		.location()
 		.find_many(vec![])
 		.with(location::node::fetch())
+		.with(location::path_mappings::fetch(vec![]))
 		.exec()
 		.await?;
 
 	// turn locations into LocationResource
-	Ok(locations.into_iter().map(LocationResource::from).collect())
+	Ok(locations
+		.into_iter()
+		.map(|location| LocationResource::from(location).resolve_path(ctx.node_local_id))
+		.collect())
 }
 
 pub async fn create_location(
@@ -165,17 +343,33 @@ pub async fn create_location(
 
 	let path_string = path.to_string_lossy().to_string();
 
-	// check if location already exists
-	let location_resource = if let Some(location) = ctx
+	// check if location already exists, either added here or mapped from another node
+	let existing_location = ctx
 		.db
 		.location()
 		.find_first(vec![location::local_path::equals(Some(
 			path_string.clone(),
 		))])
+		.with(location::path_mappings::fetch(vec![]))
 		.exec()
-		.await?
-	{
-		location.into()
+		.await?;
+	let existing_location = match existing_location {
+		Some(location) => Some(location),
+		None => {
+			ctx.db
+				.location()
+				.find_first(vec![location::path_mappings::some(vec![
+					location_path_mapping::node_id::equals(ctx.node_local_id),
+					location_path_mapping::path::equals(path_string.clone()),
+				])])
+				.with(location::path_mappings::fetch(vec![]))
+				.exec()
+				.await?
+		}
+	};
+
+	let location_resource = if let Some(location) = existing_location {
+		LocationResource::from(location).resolve_path(ctx.node_local_id)
 	} else {
 		info!(
 			"Location does not exist, creating new location for '{}'",
@@ -202,23 +396,26 @@ pub async fn create_location(
 
 		info!("Created location: {:?}", location);
 
-		// write a file called .spacedrive to path containing the location id in JSON format
-		let mut dotfile = File::create(path.with_file_name(DOTFILE_NAME))
-			.await
-			.map_err(|e| LocationError::DotfileWriteFailure(e, path.to_owned()))?;
-
-		let data = DotSpacedrive {
-			location_uuid: uuid,
-			library_uuid: ctx.id,
-		};
-
-		let json_bytes = serde_json::to_vec(&data)
-			.map_err(|e| LocationError::DotfileSerializeFailure(e, path.to_owned()))?;
-
-		dotfile
-			.write_all(&json_bytes)
-			.await
-			.map_err(|e| LocationError::DotfileWriteFailure(e, path.to_owned()))?;
+		// write a file called .spacedrive to path containing the location id in JSON format, unless
+		// the node is in memory
+		if !ctx.in_memory() {
+			let mut dotfile = File::create(path.with_file_name(DOTFILE_NAME))
+				.await
+				.map_err(|e| LocationError::DotfileWriteFailure(e, path.to_owned()))?;
+
+			let data = DotSpacedrive {
+				location_uuid: uuid,
+				library_uuid: ctx.id,
+			};
+
+			let json_bytes = serde_json::to_vec(&data)
+				.map_err(|e| LocationError::DotfileSerializeFailure(e, path.to_owned()))?;
+
+			dotfile
+				.write_all(&json_bytes)
+				.await
+				.map_err(|e| LocationError::DotfileWriteFailure(e, path.to_owned()))?;
+		}
 
 		// ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::GetLocations))
 		// 	.await;
@@ -255,6 +452,75 @@ pub async fn delete_location(ctx: &LibraryContext, location_id: i32) -> Result<(
 	Ok(())
 }
 
+// declares `path` on this node to be the same location, so it is not indexed a second time
+pub async fn add_path_mapping(
+	ctx: &LibraryContext,
+	location_id: i32,
+	path: impl AsRef<Path>,
+) -> Result<LocationResource, SysError> {
+	let path = path.as_ref();
+
+	if !path.exists() {
+		return Err(LocationError::PathNotFound(path.to_owned()).into());
+	}
+
+	// a node has a single mapping per location
+	ctx.db
+		.location_path_mapping()
+		.find_many(vec![
+			location_path_mapping::location_id::equals(location_id),
+			location_path_mapping::node_id::equals(ctx.node_local_id),
+		])
+		.delete()
+		.exec()
+		.await?;
+
+	ctx.db
+		.location_path_mapping()
+		.create(
+			location_path_mapping::location::link(location::id::equals(location_id)),
+			location_path_mapping::node::link(node::id::equals(ctx.node_local_id)),
+			location_path_mapping::path::set(path.to_string_lossy().to_string()),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	info!(
+		"Mapped location {} to '{}' on this node",
+		location_id,
+		path.display()
+	);
+
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetLocations,
+	}))
+	.await;
+
+	get_location(ctx, location_id).await
+}
+
+pub async fn remove_path_mapping(ctx: &LibraryContext, location_id: i32) -> Result<(), SysError> {
+	ctx.db
+		.location_path_mapping()
+		.find_many(vec![
+			location_path_mapping::location_id::equals(location_id),
+			location_path_mapping::node_id::equals(ctx.node_local_id),
+		])
+		.delete()
+		.exec()
+		.await?;
+
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetLocations,
+	}))
+	.await;
+
+	Ok(())
+}
+
 #[derive(Error, Debug)]
 pub enum LocationError {
 	#[error("Failed to create location (uuid {uuid:?})")]
@@ -1,7 +1,11 @@
+mod health;
 mod locations;
+mod shares;
 mod volumes;
 
+pub use health::*;
 pub use locations::*;
+pub use shares::*;
 pub use volumes::*;
 
 use thiserror::Error;
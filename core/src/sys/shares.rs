@@ -0,0 +1,367 @@
+use crate::{
+	library::LibraryManager, prisma::location, sys::Volume, ClientQuery, CoreEvent, LibraryQuery,
+	NodeContext,
+};
+use int_enum::IntEnum;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	fs,
+	path::{Path, PathBuf},
+	process::Command,
+	sync::Arc,
+	time::Duration,
+};
+use tokio::{
+	task::{spawn_blocking, JoinHandle},
+	time::timeout,
+};
+use ts_rs::TS;
+
+// how often mounted network shares are checked for reachability
+const NETWORK_SHARE_POLL_INTERVAL: Duration = Duration::from_secs(15);
+// a stalled SMB/NFS mount can block a stat call for minutes, so we give up early
+const NETWORK_SHARE_REACHABILITY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq, IntEnum)]
+#[ts(export)]
+pub enum NetworkProtocol {
+	Smb = 0,
+	Nfs = 1,
+	WebDav = 2,
+}
+
+impl NetworkProtocol {
+	// maps a filesystem type as reported by the os mount table to a network protocol
+	fn from_fs_type(fs_type: &str) -> Option<Self> {
+		match fs_type.to_lowercase().as_str() {
+			"cifs" | "smb" | "smb2" | "smb3" | "smbfs" => Some(Self::Smb),
+			"nfs" | "nfs4" => Some(Self::Nfs),
+			"davfs" | "fuse.davfs2" | "webdav" => Some(Self::WebDav),
+			_ => None,
+		}
+	}
+}
+
+// A network filesystem mounted on this node
+#[derive(Debug, Clone)]
+pub struct NetworkShare {
+	pub protocol: NetworkProtocol,
+	pub remote_host: String,
+	// the remote source as shown by the os, eg: "//nas/media" or "nas:/export/media"
+	pub source: String,
+	pub mount_point: String,
+	pub file_system: String,
+}
+
+// The reachability checks of the mounted shares. A check of a hung mount stays blocked in its
+// read, holding a thread of the blocking pool, until the os gives up on the mount. So a share is
+// only checked again once its last check returned, a check still blocked meaning it's unreachable.
+#[derive(Default)]
+struct ReachabilityProbes(HashMap<String, JoinHandle<bool>>);
+
+impl ReachabilityProbes {
+	// checks that the share still responds, without blocking the caller on a dead mount
+	async fn is_reachable(&mut self, share: &NetworkShare) -> bool {
+		let probe = self.0.entry(share.mount_point.clone()).or_insert_with(|| {
+			let mount_point = PathBuf::from(&share.mount_point);
+			spawn_blocking(move || fs::read_dir(mount_point).is_ok())
+		});
+
+		let checked = timeout(NETWORK_SHARE_REACHABILITY_TIMEOUT, probe).await;
+		match checked {
+			Ok(reachable) => {
+				self.0.remove(&share.mount_point);
+				reachable.unwrap_or(false)
+			}
+			// kept for the next poll rather than starting another check
+			Err(_) => false,
+		}
+	}
+
+	// forgets the checks of shares which were unmounted, a blocked one is left to return on its own
+	fn retain(&mut self, mount_points: &HashMap<String, bool>) {
+		self.0
+			.retain(|mount_point, _| mount_points.contains_key(mount_point));
+	}
+}
+
+// lists all SMB/NFS/WebDAV shares currently mounted on this node
+pub fn get_network_shares() -> Vec<NetworkShare> {
+	if cfg!(target_os = "linux") {
+		fs::read_to_string("/proc/mounts")
+			.map(|mounts| parse_proc_mounts(&mounts))
+			.unwrap_or_else(|e| {
+				error!("Failed to read /proc/mounts: {:#?}", e);
+				vec![]
+			})
+	} else if cfg!(target_os = "macos") {
+		match Command::new("mount").output() {
+			Ok(output) => parse_macos_mount(&String::from_utf8_lossy(&output.stdout)),
+			Err(e) => {
+				error!("Failed to execute mount: {:#?}", e);
+				vec![]
+			}
+		}
+	} else if cfg!(target_os = "windows") {
+		match Command::new("cmd")
+			.args([
+				"/C",
+				"wmic logicaldisk where DriveType=4 get DeviceID,ProviderName /format:csv",
+			])
+			.output()
+		{
+			Ok(output) => parse_windows_wmic(&String::from_utf8_lossy(&output.stdout)),
+			Err(e) => {
+				error!("Failed to execute wmic: {:#?}", e);
+				vec![]
+			}
+		}
+	} else {
+		vec![]
+	}
+}
+
+// "//nas/media" -> "nas", "nas:/export" -> "nas", "https://nas/dav" -> "nas", "\\nas\media" -> "nas"
+fn extract_remote_host(source: &str) -> String {
+	// drop the url scheme used by WebDAV mounts
+	let source = source
+		.split_once("://")
+		.map(|(_, rest)| rest)
+		.unwrap_or(source);
+
+	let host = source
+		.trim_start_matches(|c| c == '/' || c == '\\')
+		.split(|c| c == '/' || c == '\\' || c == ':')
+		.next()
+		.unwrap_or_default();
+
+	// strip credentials, eg: "user@nas" and the windows WebDAV over SSL marker, eg: "nas@SSL"
+	match host.split_once('@') {
+		Some((host, marker)) if marker.eq_ignore_ascii_case("ssl") => host,
+		Some((_, host)) => host,
+		None => host,
+	}
+	.to_string()
+}
+
+// /proc/mounts encodes whitespace in paths as octal escapes
+fn unescape_mount_path(path: &str) -> String {
+	path.replace("\\040", " ")
+		.replace("\\011", "\t")
+		.replace("\\012", "\n")
+		.replace("\\134", "\\")
+}
+
+// each line looks like: "//nas/media /mnt/media cifs rw,relatime 0 0"
+fn parse_proc_mounts(mounts: &str) -> Vec<NetworkShare> {
+	mounts
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split_whitespace();
+			let source = unescape_mount_path(fields.next()?);
+			let mount_point = unescape_mount_path(fields.next()?);
+			let file_system = fields.next()?.to_string();
+
+			NetworkProtocol::from_fs_type(&file_system).map(|protocol| NetworkShare {
+				protocol,
+				remote_host: extract_remote_host(&source),
+				source,
+				mount_point,
+				file_system,
+			})
+		})
+		.collect()
+}
+
+// each line looks like: "//user@nas/media on /Volumes/media (smbfs, nodev, nosuid, mounted by user)"
+fn parse_macos_mount(mounts: &str) -> Vec<NetworkShare> {
+	mounts
+		.lines()
+		.filter_map(|line| {
+			let (source, rest) = line.split_once(" on ")?;
+			let (mount_point, options) = rest.rsplit_once(" (")?;
+			let file_system = options
+				.trim_end_matches(')')
+				.split(',')
+				.next()?
+				.trim()
+				.to_string();
+
+			NetworkProtocol::from_fs_type(&file_system).map(|protocol| NetworkShare {
+				protocol,
+				remote_host: extract_remote_host(source),
+				source: source.to_string(),
+				mount_point: mount_point.to_string(),
+				file_system,
+			})
+		})
+		.collect()
+}
+
+// csv output looks like: "Node,DeviceID,ProviderName\r\nDESKTOP,Z:,\\nas\media"
+fn parse_windows_wmic(output: &str) -> Vec<NetworkShare> {
+	output
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty())
+		.skip(1)
+		.filter_map(|line| {
+			let mut fields = line.split(',').skip(1);
+			let device_id = fields.next()?.trim();
+			let provider_name = fields.next()?.trim();
+			if provider_name.is_empty() {
+				return None;
+			}
+
+			// mapped WebDAV folders show up as "\\nas@SSL\DavWWWRoot\folder"
+			let protocol = if provider_name.contains("DavWWWRoot") || provider_name.contains("@SSL")
+			{
+				NetworkProtocol::WebDav
+			} else {
+				NetworkProtocol::Smb
+			};
+
+			Some(NetworkShare {
+				protocol,
+				remote_host: extract_remote_host(provider_name),
+				source: provider_name.to_string(),
+				mount_point: format!("{}\\", device_id),
+				file_system: match protocol {
+					NetworkProtocol::WebDav => "webdav".to_string(),
+					_ => "smb".to_string(),
+				},
+			})
+		})
+		.collect()
+}
+
+// watch_network_shares polls the mounted network shares and keeps the `is_online` state of
+// locations stored on them up to date, so that an unreachable share doesn't look like deleted files
+pub async fn watch_network_shares(library_manager: Arc<LibraryManager>, node_ctx: NodeContext) {
+	// mount point -> whether the share was reachable on the last poll
+	let mut known_shares: HashMap<String, bool> = HashMap::new();
+	let mut probes = ReachabilityProbes::default();
+
+	loop {
+		let shares = spawn_blocking(get_network_shares).await.unwrap_or_default();
+
+		let mut observed = HashMap::with_capacity(shares.len());
+		for share in shares {
+			let reachable = probes.is_reachable(&share).await;
+			let previous = known_shares.get(&share.mount_point).copied();
+			observed.insert(share.mount_point.clone(), reachable);
+
+			if previous == Some(reachable) {
+				continue;
+			}
+
+			let volume = Volume::from(share);
+			if reachable {
+				info!("Network volume connected: {}", volume.mount_point);
+				if previous.is_some() {
+					node_ctx
+						.emit(CoreEvent::VolumeConnected(volume.clone()))
+						.await;
+				}
+			} else {
+				info!("Network volume disconnected: {}", volume.mount_point);
+				node_ctx
+					.emit(CoreEvent::VolumeDisconnected(volume.clone()))
+					.await;
+			}
+			set_locations_online(&library_manager, &volume.mount_point, reachable).await;
+		}
+
+		// shares that were unmounted entirely since the last poll
+		for (mount_point, was_reachable) in known_shares.drain() {
+			if observed.contains_key(&mount_point) {
+				continue;
+			}
+			info!("Network volume unmounted: {}", mount_point);
+			if was_reachable {
+				node_ctx
+					.emit(CoreEvent::VolumeDisconnected(Volume {
+						name: mount_point.clone(),
+						mount_point: mount_point.clone(),
+						..Default::default()
+					}))
+					.await;
+			}
+			set_locations_online(&library_manager, &mount_point, false).await;
+		}
+
+		probes.retain(&observed);
+		known_shares = observed;
+		tokio::time::sleep(NETWORK_SHARE_POLL_INTERVAL).await;
+	}
+}
+
+async fn set_locations_online(library_manager: &LibraryManager, mount_point: &str, online: bool) {
+	for ctx in library_manager.get_all_libraries_ctx().await {
+		// a prefix of the path isn't enough, "/mnt/nas" would take in "/mnt/nas2" too
+		let locations = match ctx
+			.db
+			.location()
+			.find_many(vec![
+				location::node_id::equals(Some(ctx.node_local_id)),
+				location::local_path::starts_with(mount_point.to_string()),
+			])
+			.exec()
+			.await
+		{
+			Ok(locations) => locations
+				.into_iter()
+				.filter(|location| {
+					location.local_path.as_ref().map_or(false, |local_path| {
+						is_within_mount_point(local_path, mount_point)
+					})
+				})
+				.map(|location| location.id)
+				.collect::<Vec<_>>(),
+			Err(e) => {
+				error!("Failed to read locations on '{}': {:#?}", mount_point, e);
+				continue;
+			}
+		};
+
+		if let Err(e) = ctx
+			.db
+			.location()
+			.find_many(vec![location::id::in_vec(locations)])
+			.update(vec![location::is_online::set(online)])
+			.exec()
+			.await
+		{
+			error!("Failed to update locations on '{}': {:#?}", mount_point, e);
+			continue;
+		}
+
+		ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+			library_id: ctx.id,
+			query: LibraryQuery::GetLocations,
+		}))
+		.await;
+	}
+}
+
+// whether the path is the mount point or within it, comparing whole components of the paths
+fn is_within_mount_point(path: &str, mount_point: &str) -> bool {
+	Path::new(path).starts_with(mount_point)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn mount_point_matches_whole_components() {
+		assert!(is_within_mount_point("/mnt/nas", "/mnt/nas"));
+		assert!(is_within_mount_point("/mnt/nas/photos", "/mnt/nas"));
+		assert!(is_within_mount_point("/mnt/nas/photos", "/mnt/nas/"));
+		assert!(!is_within_mount_point("/mnt/nas2", "/mnt/nas"));
+		assert!(!is_within_mount_point("/mnt/nas2/photos", "/mnt/nas"));
+	}
+}
@@ -1,13 +1,14 @@
 // use crate::native;
 use crate::{library::LibraryContext, prisma::volume::*};
+use int_enum::IntEnum;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 // #[cfg(not(target_os = "macos"))]
-use std::process::Command;
+use std::{path::Path, process::Command};
 // #[cfg(not(target_os = "macos"))]
 use sysinfo::{DiskExt, System, SystemExt};
 
-use super::SysError;
+use super::{get_network_shares, NetworkProtocol, NetworkShare, SysError};
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone, TS)]
 #[repr(C)]
@@ -21,6 +22,55 @@ pub struct Volume {
 	pub disk_type: Option<String>,
 	pub file_system: Option<String>,
 	pub is_root_filesystem: bool,
+	pub network_protocol: Option<NetworkProtocol>,
+	pub remote_host: Option<String>,
+	pub capabilities: FileSystemCapabilities,
+}
+
+// file systems which can share the blocks of a file between copies of it
+const CLONING_FILE_SYSTEMS: [&str; 4] = ["apfs", "btrfs", "xfs", "bcachefs"];
+// file systems which store the holes of sparse files as such, rather than as zeroes
+const SPARSE_FILE_SYSTEMS: [&str; 11] = [
+	"apfs", "btrfs", "xfs", "bcachefs", "ext2", "ext3", "ext4", "zfs", "f2fs", "ntfs", "tmpfs",
+];
+
+// What copies to a volume can do beside writing the contents out, by its file system
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, TS, Eq, PartialEq)]
+#[ts(export)]
+pub struct FileSystemCapabilities {
+	// copies within the volume share the blocks of the original until either is changed, with
+	// reflinks or clonefile. xfs only supports them when formatted with reflinks
+	pub clone: bool,
+	pub sparse_files: bool,
+}
+
+impl FileSystemCapabilities {
+	pub fn of(file_system: &str) -> Self {
+		let file_system = file_system.to_lowercase();
+		Self {
+			clone: CLONING_FILE_SYSTEMS.contains(&file_system.as_str()),
+			sparse_files: SPARSE_FILE_SYSTEMS.contains(&file_system.as_str()),
+		}
+	}
+}
+
+impl From<NetworkShare> for Volume {
+	fn from(share: NetworkShare) -> Self {
+		Volume {
+			name: share.source,
+			mount_point: share.mount_point,
+			total_capacity: 0,
+			available_capacity: 0,
+			is_removable: false,
+			disk_type: Some("Network".to_string()),
+			file_system: Some(share.file_system),
+			is_root_filesystem: false,
+			network_protocol: Some(share.protocol),
+			remote_host: Some(share.remote_host),
+			// a network volume copies what it is sent
+			capabilities: FileSystemCapabilities::default(),
+		}
+	}
 }
 
 impl Volume {
@@ -46,6 +96,8 @@ impl Volume {
 							filesystem::set(volume.file_system.clone()),
 							total_bytes_capacity::set(volume.total_capacity.to_string()),
 							total_bytes_available::set(volume.available_capacity.to_string()),
+							network_protocol::set(volume.network_protocol.map(|p| p.int_value())),
+							remote_host::set(volume.remote_host.clone()),
 						],
 					),
 					vec![
@@ -53,6 +105,8 @@ impl Volume {
 						filesystem::set(volume.file_system),
 						total_bytes_capacity::set(volume.total_capacity.to_string()),
 						total_bytes_available::set(volume.available_capacity.to_string()),
+						network_protocol::set(volume.network_protocol.map(|p| p.int_value())),
+						remote_host::set(volume.remote_host),
 					],
 				)
 				.exec()
@@ -62,8 +116,17 @@ impl Volume {
 
 		Ok(())
 	}
+
+	// the volume a path is on, the one mounted deepest above it
+	pub fn of_path(path: &Path) -> Option<Volume> {
+		Self::get_volumes()
+			.unwrap_or_default()
+			.into_iter()
+			.filter(|volume| path.starts_with(&volume.mount_point))
+			.max_by_key(|volume| volume.mount_point.len())
+	}
 	pub fn get_volumes() -> Result<Vec<Volume>, SysError> {
-		Ok(System::new_all()
+		let mut volumes = System::new_all()
 			.disks()
 			.iter()
 			.map(|disk| {
@@ -115,12 +178,33 @@ impl Volume {
 					available_capacity: available_space,
 					is_removable,
 					disk_type: Some(disk_type),
+					capabilities: FileSystemCapabilities::of(&file_system),
 					file_system: Some(file_system),
 					is_root_filesystem: mount_point == "/",
+					network_protocol: None,
+					remote_host: None,
 				}
 			})
 			.filter(|volume| !volume.mount_point.starts_with("/System"))
-			.collect())
+			.collect::<Vec<_>>();
+
+		// sysinfo doesn't report every network mount, and doesn't tell them apart from local disks
+		for share in get_network_shares() {
+			match volumes
+				.iter_mut()
+				.find(|volume| volume.mount_point == share.mount_point)
+			{
+				Some(volume) => {
+					volume.disk_type = Some("Network".to_string());
+					volume.capabilities = FileSystemCapabilities::default();
+					volume.network_protocol = Some(share.protocol);
+					volume.remote_host = Some(share.remote_host);
+				}
+				None => volumes.push(share.into()),
+			}
+		}
+
+		Ok(volumes)
 	}
 }
 
@@ -0,0 +1,295 @@
+use crate::{
+	history::{self, Operation},
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::LibraryContext,
+	prisma::{self, file, tag, tag_on_file},
+	search, ClientQuery, CoreError, CoreEvent, CoreResponse, Job, LibraryQuery,
+};
+use log::info;
+use prisma_client_rust::{prisma_models::PrismaValue, raw::Raw};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use ts_rs::TS;
+
+use super::TagError;
+
+pub const BULK_TAG_JOB_NAME: &str = "bulk_tagger";
+// selections larger than a batch are applied by a job, so their progress is reported
+const BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum BulkTagAction {
+	Assign,
+	Remove,
+}
+
+// What a bulk tag operation would change. Applying the inverse action to `changed_file_ids`
+// afterwards undoes the operation exactly, without touching files which were already tagged
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BulkTagPreview {
+	pub tag_id: i32,
+	pub action: BulkTagAction,
+	pub changed_file_ids: Vec<i32>,
+	// selected files which already had, or didn't have, the tag
+	pub unchanged: usize,
+}
+
+pub async fn get_bulk_tag_preview(
+	ctx: &LibraryContext,
+	tag_id: i32,
+	file_ids: Vec<i32>,
+	action: BulkTagAction,
+) -> Result<BulkTagPreview, TagError> {
+	ctx.db
+		.tag()
+		.find_unique(tag::id::equals(tag_id))
+		.exec()
+		.await?
+		.ok_or(TagError::TagNotFound(tag_id))?;
+
+	let selected = file_ids.into_iter().collect::<HashSet<_>>();
+	let mut changed_file_ids = vec![];
+
+	for batch in selected
+		.iter()
+		.copied()
+		.collect::<Vec<_>>()
+		.chunks(BATCH_SIZE)
+	{
+		let existing = ctx
+			.db
+			.file()
+			.find_many(vec![file::id::in_vec(batch.to_vec())])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file| file.id);
+		let tagged = ctx
+			.db
+			.tag_on_file()
+			.find_many(vec![
+				tag_on_file::tag_id::equals(tag_id),
+				tag_on_file::file_id::in_vec(batch.to_vec()),
+			])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|tag_on_file| tag_on_file.file_id)
+			.collect::<HashSet<_>>();
+
+		changed_file_ids.extend(existing.filter(|id| match action {
+			BulkTagAction::Assign => !tagged.contains(id),
+			BulkTagAction::Remove => tagged.contains(id),
+		}));
+	}
+	changed_file_ids.sort_unstable();
+
+	Ok(BulkTagPreview {
+		tag_id,
+		action,
+		unchanged: selected.len() - changed_file_ids.len(),
+		changed_file_ids,
+	})
+}
+
+pub async fn bulk_tag(
+	ctx: LibraryContext,
+	tag_id: i32,
+	file_ids: Vec<i32>,
+	action: BulkTagAction,
+) -> Result<CoreResponse, CoreError> {
+	if file_ids.len() > BATCH_SIZE {
+		ctx.spawn_job(Job::new(
+			BulkTagJobInit {
+				tag_id,
+				file_ids,
+				action,
+			},
+			Box::new(BulkTagJob {}),
+		))
+		.await;
+
+		return Ok(CoreResponse::Success(()));
+	}
+
+	let preview = get_bulk_tag_preview(&ctx, tag_id, file_ids, action).await?;
+	apply(&ctx, tag_id, &preview.changed_file_ids, action).await?;
+	record(&ctx, tag_id, preview.changed_file_ids, action).await;
+
+	Ok(CoreResponse::Success(()))
+}
+
+// applies an operation to every file at once, without reporting progress
+pub(crate) async fn apply(
+	ctx: &LibraryContext,
+	tag_id: i32,
+	file_ids: &[i32],
+	action: BulkTagAction,
+) -> Result<(), prisma::QueryError> {
+	for batch in file_ids.chunks(BATCH_SIZE) {
+		apply_batch(ctx, tag_id, batch, action).await?;
+	}
+	finish(ctx, tag_id).await;
+
+	Ok(())
+}
+
+async fn record(ctx: &LibraryContext, tag_id: i32, file_ids: Vec<i32>, action: BulkTagAction) {
+	if !file_ids.is_empty() {
+		history::record(
+			ctx,
+			Operation::Tag {
+				tag_id,
+				file_ids,
+				action,
+			},
+		)
+		.await;
+	}
+}
+
+// each batch is a single statement, so it is either applied entirely or not at all
+async fn apply_batch(
+	ctx: &LibraryContext,
+	tag_id: i32,
+	file_ids: &[i32],
+	action: BulkTagAction,
+) -> Result<(), prisma::QueryError> {
+	if file_ids.is_empty() {
+		return Ok(());
+	}
+
+	match action {
+		BulkTagAction::Assign => {
+			let mut values = vec![PrismaValue::Int(tag_id as i64)];
+			values.extend(file_ids.iter().map(|id| PrismaValue::Int(*id as i64)));
+
+			ctx.db
+				._execute_raw(Raw::new(
+					&format!(
+						"INSERT OR IGNORE INTO tags_on_file (tag_id, file_id)
+						SELECT {{}}, id FROM files WHERE id IN ({})",
+						vec!["{}"; file_ids.len()].join(", ")
+					),
+					values,
+				))
+				.await?;
+		}
+		BulkTagAction::Remove => {
+			ctx.db
+				.tag_on_file()
+				.find_many(vec![
+					tag_on_file::tag_id::equals(tag_id),
+					tag_on_file::file_id::in_vec(file_ids.to_vec()),
+				])
+				.delete()
+				.exec()
+				.await?;
+		}
+	}
+
+	Ok(())
+}
+
+async fn finish(ctx: &LibraryContext, tag_id: i32) {
+	ctx.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+		library_id: ctx.id,
+		query: LibraryQuery::GetFilesTagged { tag_id },
+	}))
+	.await;
+
+	search::refresh_subscriptions(ctx).await;
+}
+
+pub struct BulkTagJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BulkTagJobInit {
+	pub tag_id: i32,
+	pub file_ids: Vec<i32>,
+	pub action: BulkTagAction,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for BulkTagJob {
+	type Init = BulkTagJobInit;
+	// the files changed by the job, recorded in the history once it completes
+	type Data = Vec<i32>;
+	type Step = Vec<i32>;
+
+	fn name(&self) -> &'static str {
+		BULK_TAG_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let preview = get_bulk_tag_preview(
+			&ctx.library_ctx(),
+			state.init.tag_id,
+			state.init.file_ids.clone(),
+			state.init.action,
+		)
+		.await?;
+
+		info!(
+			"Applying tag {} to {} files ({:?})",
+			state.init.tag_id,
+			preview.changed_file_ids.len(),
+			state.init.action
+		);
+
+		state.steps = preview
+			.changed_file_ids
+			.chunks(BATCH_SIZE)
+			.map(|batch| batch.to_vec())
+			.collect();
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+		state.data = Some(preview.changed_file_ids);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		apply_batch(
+			&ctx.library_ctx(),
+			state.init.tag_id,
+			&state.steps[0],
+			state.init.action,
+		)
+		.await?;
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> Result<(), JobError> {
+		let library_ctx = ctx.library_ctx();
+		finish(&library_ctx, state.init.tag_id).await;
+		record(
+			&library_ctx,
+			state.init.tag_id,
+			state.data.take().unwrap_or_default(),
+			state.init.action,
+		)
+		.await;
+
+		Ok(())
+	}
+}
@@ -0,0 +1,163 @@
+use super::{
+	bulk::{self, BulkTagAction},
+	find_or_create_tag,
+};
+use crate::{
+	file::{attributes::read_finder_tags, FileError},
+	job::{JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	prisma::file_path,
+	sys::get_location,
+	ClientQuery, CoreEvent, LibraryQuery,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
+use tokio::task::spawn_blocking;
+
+pub const FINDER_TAGS_JOB_NAME: &str = "finder_tags";
+const BATCH_SIZE: usize = 500;
+// the colors of Finder, by their index. Tags without one are gray
+const FINDER_COLORS: [&str; 8] = [
+	"#8E8E93", "#8E8E93", "#34C759", "#AF52DE", "#007AFF", "#FFCC00", "#FF3B30", "#FF9500",
+];
+
+// Tags the files of a location with the tags they have in Finder, once they are identified. Tags
+// are only ever added, and directories are left out as tags are on files
+pub struct FinderTagsJob {}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FinderTagsJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FinderTagsJobData {
+	location_path: PathBuf,
+	// the tag of every Finder tag met, by name
+	tags: HashMap<String, i32>,
+	checked: usize,
+	tagged: usize,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FinderTagsJob {
+	type Init = FinderTagsJobInit;
+	type Data = FinderTagsJobData;
+	// the materialized path and file of each entry
+	type Step = Vec<(String, i32)>;
+
+	fn name(&self) -> &'static str {
+		FINDER_TAGS_JOB_NAME
+	}
+
+	async fn init(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let location_path = get_location(&library_ctx, state.init.location_id)
+			.await?
+			.path
+			.ok_or(FileError::LocationUnavailable(state.init.location_id))?;
+
+		let file_paths = library_ctx
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(state.init.location_id)),
+				file_path::is_dir::equals(false),
+			])
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| Some((file_path.materialized_path, file_path.file_id?)))
+			.collect::<Vec<_>>();
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(file_paths.len())]);
+		state.steps = file_paths
+			.chunks(BATCH_SIZE)
+			.map(|batch| batch.to_vec())
+			.collect();
+		state.data = Some(FinderTagsJobData {
+			location_path,
+			tags: HashMap::new(),
+			checked: 0,
+			tagged: 0,
+		});
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let data = state.data.as_mut().unwrap();
+
+		let location_path = data.location_path.clone();
+		let batch = state.steps[0].clone();
+		let batch_len = batch.len();
+		let found = spawn_blocking(move || {
+			batch
+				.into_iter()
+				.map(|(path, file_id)| (file_id, read_finder_tags(&location_path.join(path))))
+				.filter(|(_, tags)| !tags.is_empty())
+				.collect::<Vec<_>>()
+		})
+		.await?;
+
+		let mut tagged: HashMap<i32, Vec<i32>> = HashMap::new();
+		for (file_id, tags) in &found {
+			for (name, color) in tags {
+				let tag_id = match data.tags.get(name) {
+					Some(tag_id) => *tag_id,
+					None => {
+						let color = color
+							.and_then(|color| FINDER_COLORS.get(color))
+							.unwrap_or(&FINDER_COLORS[0]);
+						let tag_id = find_or_create_tag(&library_ctx, name, color).await?;
+						data.tags.insert(name.clone(), tag_id);
+						tag_id
+					}
+				};
+				tagged.entry(tag_id).or_default().push(*file_id);
+			}
+		}
+		for (tag_id, file_ids) in tagged {
+			bulk::apply(&library_ctx, tag_id, &file_ids, BulkTagAction::Assign).await?;
+		}
+
+		data.checked += batch_len;
+		data.tagged += found.len();
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(data.checked)]);
+
+		Ok(())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self::Init, Self::Data, Self::Step>,
+	) -> JobResult {
+		let library_ctx = ctx.library_ctx();
+		let data = state.data.as_ref().unwrap();
+		info!(
+			"Found Finder tags on {} of {} files of location {}",
+			data.tagged, data.checked, state.init.location_id
+		);
+
+		if !data.tags.is_empty() {
+			library_ctx
+				.emit(CoreEvent::InvalidateQuery(ClientQuery::LibraryQuery {
+					library_id: library_ctx.id,
+					query: LibraryQuery::GetTags,
+				}))
+				.await;
+		}
+
+		Ok(())
+	}
+}
@@ -1,18 +1,22 @@
 use crate::{
 	file::File,
+	history,
 	library::LibraryContext,
 	prisma::{
 		self, file,
 		tag::{self},
 		tag_on_file,
 	},
-	ClientQuery, CoreError, CoreEvent, CoreResponse, LibraryQuery,
+	search, ClientQuery, CoreError, CoreEvent, CoreResponse, LibraryQuery,
 };
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use ts_rs::TS;
 use uuid::Uuid;
 
+pub mod bulk;
+pub mod finder;
+
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct Tag {
@@ -135,11 +139,26 @@ pub async fn tag_assign(
 	file_id: i32,
 	tag_id: i32,
 ) -> Result<CoreResponse, CoreError> {
-	ctx.db.tag_on_file().create(
-		tag_on_file::tag::link(tag::UniqueWhereParam::IdEquals(tag_id)),
-		tag_on_file::file::link(file::UniqueWhereParam::IdEquals(file_id)),
-		vec![],
-	);
+	ctx.db
+		.tag_on_file()
+		.create(
+			tag_on_file::tag::link(tag::UniqueWhereParam::IdEquals(tag_id)),
+			tag_on_file::file::link(file::UniqueWhereParam::IdEquals(file_id)),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	search::refresh_subscriptions(&ctx).await;
+	history::record(
+		&ctx,
+		history::Operation::Tag {
+			tag_id,
+			file_ids: vec![file_id],
+			action: bulk::BulkTagAction::Assign,
+		},
+	)
+	.await;
 
 	Ok(CoreResponse::Success(()))
 }
@@ -159,6 +178,8 @@ pub async fn tag_delete(ctx: LibraryContext, id: i32) -> Result<CoreResponse, Co
 	}))
 	.await;
 
+	search::refresh_subscriptions(&ctx).await;
+
 	Ok(CoreResponse::Success(()))
 }
 
@@ -187,3 +208,35 @@ pub async fn get_all_tags(ctx: LibraryContext) -> Result<CoreResponse, CoreError
 
 	Ok(CoreResponse::GetTags(tags))
 }
+
+// the tag named `name`, created with `color` unless there is one already
+pub(crate) async fn find_or_create_tag(
+	ctx: &LibraryContext,
+	name: &str,
+	color: &str,
+) -> Result<i32, prisma::QueryError> {
+	let existing = ctx
+		.db
+		.tag()
+		.find_first(vec![tag::name::equals(Some(name.to_string()))])
+		.exec()
+		.await?;
+	if let Some(tag) = existing {
+		return Ok(tag.id);
+	}
+
+	let tag = ctx
+		.db
+		.tag()
+		.create(
+			tag::pub_id::set(Uuid::new_v4().as_bytes().to_vec()),
+			vec![
+				tag::name::set(Some(name.to_string())),
+				tag::color::set(Some(color.to_string())),
+			],
+		)
+		.exec()
+		.await?;
+
+	Ok(tag.id)
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, iter::Peekable};
+use ts_rs::TS;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// How names are ordered, configured per library.
+// sqlite can only order names by their bytes, and the query engine doesn't expose its connection to
+// register a collation on it, so entries ordered by name are sorted once they are read
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, Eq, PartialEq)]
+#[ts(export)]
+pub enum Collation {
+	// byte order, as sqlite sorts
+	Binary,
+	// case insensitive, with runs of digits compared by their value so "file2" comes before "file10"
+	Natural,
+	// natural, also ignoring accents so "é" sorts with "e" as most latin locales expect
+	NaturalUnaccented,
+}
+
+impl Default for Collation {
+	fn default() -> Self {
+		Self::Natural
+	}
+}
+
+impl Collation {
+	pub fn compare(&self, a: &str, b: &str) -> Ordering {
+		match self {
+			Self::Binary => a.cmp(b),
+			// names only differing by case or leading zeros still get a stable order
+			Self::Natural => natural_cmp(fold(a), fold(b)).then_with(|| a.cmp(b)),
+			Self::NaturalUnaccented => natural_cmp(unaccent(a), unaccent(b))
+				.then_with(|| natural_cmp(fold(a), fold(b)))
+				.then_with(|| a.cmp(b)),
+		}
+	}
+}
+
+fn fold(s: &str) -> impl Iterator<Item = char> + '_ {
+	s.chars().flat_map(char::to_lowercase)
+}
+
+fn unaccent(s: &str) -> impl Iterator<Item = char> + '_ {
+	s.nfd()
+		.filter(|c| !is_combining_mark(*c))
+		.flat_map(char::to_lowercase)
+}
+
+fn natural_cmp(a: impl Iterator<Item = char>, b: impl Iterator<Item = char>) -> Ordering {
+	let (mut a, mut b) = (a.peekable(), b.peekable());
+	loop {
+		match (a.peek().copied(), b.peek().copied()) {
+			(None, None) => return Ordering::Equal,
+			(None, Some(_)) => return Ordering::Less,
+			(Some(_), None) => return Ordering::Greater,
+			(Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+				let ordering = number_cmp(&mut a, &mut b);
+				if ordering != Ordering::Equal {
+					return ordering;
+				}
+			}
+			(Some(x), Some(y)) => {
+				if x != y {
+					return x.cmp(&y);
+				}
+				a.next();
+				b.next();
+			}
+		}
+	}
+}
+
+// compares the runs of digits both iterators start with, without parsing them so any length works
+fn number_cmp<A, B>(a: &mut Peekable<A>, b: &mut Peekable<B>) -> Ordering
+where
+	A: Iterator<Item = char>,
+	B: Iterator<Item = char>,
+{
+	while a.next_if_eq(&'0').is_some() {}
+	while b.next_if_eq(&'0').is_some() {}
+
+	// the longer number is larger, the first differing digit decides between numbers of the same length
+	let mut ordering = Ordering::Equal;
+	loop {
+		match (
+			a.next_if(char::is_ascii_digit),
+			b.next_if(char::is_ascii_digit),
+		) {
+			(Some(x), Some(y)) => {
+				if ordering == Ordering::Equal {
+					ordering = x.cmp(&y);
+				}
+			}
+			(Some(_), None) => return Ordering::Greater,
+			(None, Some(_)) => return Ordering::Less,
+			(None, None) => return ordering,
+		}
+	}
+}
@@ -1 +1,2 @@
+pub mod collation;
 pub mod db;
@@ -0,0 +1,98 @@
+// Snapshots of what the API answers clients with, for changes to the responses to show up here
+// rather than as broken screens. Ids, dates and paths are redacted, see `TestNode::redact`
+mod common;
+
+use common::{assert_snapshot, TestNode};
+use sdcore::{ClientQuery, CoreResponse, LibraryCommand, LibraryQuery};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_and_libraries() {
+	let node = TestNode::start().await;
+
+	assert_snapshot("get_node", &node.query(ClientQuery::GetNode).await);
+	assert_snapshot(
+		"get_libraries",
+		&node.query(ClientQuery::GetLibraries).await,
+	);
+
+	node.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn location_indexing_and_explorer() {
+	let node = TestNode::start().await;
+	let path = node.fixture("documents");
+
+	let location = node
+		.library_command(LibraryCommand::LocCreate { path })
+		.await;
+	assert_snapshot("loc_create", &location);
+	node.wait_for_jobs().await;
+
+	assert_snapshot(
+		"get_locations",
+		&node.library_query(LibraryQuery::GetLocations).await,
+	);
+	let location_id = location_id(&node).await;
+	for (name, path, show_hidden) in [
+		("get_explorer_dir", "", None),
+		("get_explorer_dir_hidden", "", Some(true)),
+		("get_explorer_dir_nested", "archive", None),
+		("get_explorer_dir_missing", "missing", None),
+	] {
+		let dir = node
+			.library_query(LibraryQuery::GetExplorerDir {
+				location_id,
+				path: PathBuf::from(path),
+				limit: 100,
+				show_hidden,
+			})
+			.await;
+		assert_snapshot(name, &dir);
+	}
+
+	node.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tags() {
+	let node = TestNode::start().await;
+
+	let tag = node
+		.library_command(LibraryCommand::TagCreate {
+			name: "Important".to_string(),
+			color: "#ff4d4d".to_string(),
+		})
+		.await;
+	assert_snapshot("tag_create", &tag);
+	assert_snapshot("get_tags", &node.library_query(LibraryQuery::GetTags).await);
+
+	node.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unknown_library() {
+	let node = TestNode::start().await;
+
+	let res = node
+		.query(ClientQuery::LibraryQuery {
+			library_id: Uuid::new_v4(),
+			query: LibraryQuery::GetTags,
+		})
+		.await;
+	assert_snapshot("unknown_library", &res);
+
+	node.shutdown().await;
+}
+
+// ids are redacted from responses, so the location is found through the controller
+async fn location_id(node: &TestNode) -> i32 {
+	match node.library_response(LibraryQuery::GetLocations).await {
+		Ok(CoreResponse::GetLocations(locations)) => {
+			locations.first().expect("the location wasn't created").id
+		}
+		res => panic!("unexpected response to GetLocations: {:?}", res),
+	}
+}
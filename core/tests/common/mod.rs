@@ -0,0 +1,256 @@
+use sdcore::{
+	ClientCommand, ClientQuery, CoreError, CoreResponse, LibraryCommand, LibraryQuery, Node,
+	NodeController,
+};
+use serde_json::{json, Value};
+use std::{
+	env, fs,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+use tempfile::TempDir;
+use tokio::{sync::oneshot, time::sleep};
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+// set to write the snapshots again from what the API returns, after changing it on purpose
+const UPDATE_SNAPSHOTS_VAR: &str = "UPDATE_SNAPSHOTS";
+// how long the jobs started by a test get to finish
+const JOBS_TIMEOUT: Duration = Duration::from_secs(60);
+const JOBS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// values which change from one machine or run to the next without an id or a date to tell them
+const VOLATILE_KEYS: [&str; 5] = [
+	"total_capacity",
+	"available_capacity",
+	"platform",
+	"inode",
+	"device",
+];
+
+// A node kept in memory, with its default library, which tests query as clients do
+pub struct TestNode {
+	controller: NodeController,
+	shutdown_tx: oneshot::Sender<()>,
+	shutdown_completion_rx: oneshot::Receiver<()>,
+	pub library_id: Uuid,
+	// the fixtures copied for the node to index, removed once it is dropped
+	fixtures: TempDir,
+	// strings which differ between runs, with what they are replaced by in snapshots
+	redactions: Vec<(String, &'static str)>,
+	// named after the host it runs on
+	node_name: String,
+}
+
+impl TestNode {
+	pub async fn start() -> Self {
+		let (controller, mut events, node, shutdown_completion_rx) = Node::new_in_memory().await;
+		let (shutdown_tx, shutdown_rx) = oneshot::channel();
+		tokio::spawn(node.start(shutdown_rx));
+		// nothing listens to the events, they would fill the channel up and block the node
+		tokio::spawn(async move { while events.recv().await.is_some() {} });
+
+		let mut node = Self {
+			controller,
+			shutdown_tx,
+			shutdown_completion_rx,
+			library_id: Uuid::nil(),
+			fixtures: tempfile::tempdir().unwrap(),
+			redactions: vec![],
+			node_name: String::new(),
+		};
+
+		match node.controller.query(ClientQuery::GetNode).await {
+			Ok(CoreResponse::GetNode(state)) => {
+				node.redactions.push((state.data_path, "[data]"));
+				node.node_name = state.config.name;
+			}
+			res => panic!("unexpected response to GetNode: {:?}", res),
+		}
+		node.library_id = match node.controller.query(ClientQuery::GetLibraries).await {
+			Ok(CoreResponse::GetLibraries(libraries)) => {
+				libraries
+					.first()
+					.expect("the node has no default library")
+					.uuid
+			}
+			res => panic!("unexpected response to GetLibraries: {:?}", res),
+		};
+		node.redactions.push((
+			node.fixtures.path().to_string_lossy().to_string(),
+			"[fixtures]",
+		));
+
+		node
+	}
+
+	// copies a directory of tests/fixtures for the node to index, and returns where it is
+	pub fn fixture(&self, name: &str) -> PathBuf {
+		let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+			.join("tests")
+			.join("fixtures")
+			.join(name);
+		let target = self.fixtures.path().join(name);
+		for entry in WalkDir::new(&source).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+			let entry = entry.unwrap();
+			let path = target.join(entry.path().strip_prefix(&source).unwrap());
+			match entry.file_type().is_dir() {
+				true => fs::create_dir_all(&path).unwrap(),
+				false => {
+					fs::copy(entry.path(), &path).unwrap();
+				}
+			}
+		}
+		target
+	}
+
+	pub async fn query(&self, query: ClientQuery) -> Value {
+		self.redact(self.controller.query(query).await)
+	}
+
+	pub async fn command(&self, command: ClientCommand) -> Value {
+		self.redact(self.controller.command(command).await)
+	}
+
+	pub async fn library_query(&self, query: LibraryQuery) -> Value {
+		self.redact(self.library_response(query).await)
+	}
+
+	// the response to a query of the library before it is redacted, to read ids from
+	pub async fn library_response(&self, query: LibraryQuery) -> Result<CoreResponse, CoreError> {
+		self.controller
+			.query(ClientQuery::LibraryQuery {
+				library_id: self.library_id,
+				query,
+			})
+			.await
+	}
+
+	pub async fn library_command(&self, command: LibraryCommand) -> Value {
+		self.command(ClientCommand::LibraryCommand {
+			library_id: self.library_id,
+			command,
+		})
+		.await
+	}
+
+	// waits until no job is queued or running, as for what a command started to be done
+	pub async fn wait_for_jobs(&self) {
+		let started = tokio::time::Instant::now();
+		// a job can queue the next one as it finishes, so the node has to be idle a few times over
+		let mut idle = 0;
+		while idle < 3 {
+			assert!(
+				started.elapsed() < JOBS_TIMEOUT,
+				"jobs still running after {:?}",
+				JOBS_TIMEOUT
+			);
+			sleep(JOBS_POLL_INTERVAL).await;
+			idle = match self.controller.query(ClientQuery::GetMetrics).await {
+				Ok(CoreResponse::GetMetrics(metrics))
+					if metrics.queued_jobs == 0 && metrics.running_jobs == 0 =>
+				{
+					idle + 1
+				}
+				_ => 0,
+			};
+		}
+	}
+
+	pub async fn shutdown(self) {
+		self.shutdown_tx.send(()).unwrap();
+		self.shutdown_completion_rx.await.unwrap();
+	}
+
+	// the response as clients get it, with what differs between runs replaced by placeholders
+	fn redact(&self, res: Result<CoreResponse, CoreError>) -> Value {
+		let mut value = match res {
+			Ok(response) => serde_json::to_value(response).unwrap(),
+			Err(e) => json!({ "error": e.to_string() }),
+		};
+		self.redact_value(None, &mut value);
+		value
+	}
+
+	fn redact_value(&self, key: Option<&str>, value: &mut Value) {
+		match value {
+			Value::Object(object) => {
+				for (key, value) in object.iter_mut() {
+					self.redact_value(Some(key.as_str()), value);
+				}
+				return;
+			}
+			Value::Array(values) => {
+				for value in values {
+					self.redact_value(key, value);
+				}
+				return;
+			}
+			Value::Null => return,
+			_ => {}
+		}
+
+		let placeholder = match key {
+			Some(key) if key == "id" || key.ends_with("_id") => Some("[id]"),
+			Some(key) if key.starts_with("date_") || key == "last_seen" => Some("[timestamp]"),
+			Some(key) if VOLATILE_KEYS.contains(&key) => Some("[redacted]"),
+			_ => None,
+		};
+		if let Some(placeholder) = placeholder {
+			*value = json!(placeholder);
+			return;
+		}
+
+		if let Value::String(string) = value {
+			if *string == self.node_name {
+				*string = "[node]".to_string();
+			} else if string.len() == 36 && Uuid::parse_str(string).is_ok() {
+				*string = "[uuid]".to_string();
+			} else if chrono::DateTime::parse_from_rfc3339(string).is_ok() {
+				*string = "[timestamp]".to_string();
+			} else if let Some((redacted, placeholder)) = self
+				.redactions
+				.iter()
+				.find(|(redacted, _)| string.starts_with(redacted.as_str()))
+			{
+				// paths are compared with forward slashes whatever the system
+				*string = format!(
+					"{}{}",
+					placeholder,
+					string[redacted.len()..].replace('\\', "/")
+				);
+			}
+		}
+	}
+}
+
+// compares a response to the snapshot of that name in tests/snapshots. Snapshots are only written,
+// new or changed, when the tests are run with UPDATE_SNAPSHOTS set, to be reviewed and committed
+pub fn assert_snapshot(name: &str, value: &Value) {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests")
+		.join("snapshots")
+		.join(name)
+		.with_extension("json");
+	let actual = format!("{}\n", serde_json::to_string_pretty(value).unwrap());
+
+	if env::var_os(UPDATE_SNAPSHOTS_VAR).is_some() {
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(&path, actual).unwrap();
+		return;
+	}
+
+	let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+		panic!(
+			"there is no snapshot {:?}, run the tests with {}=1 to write it",
+			path, UPDATE_SNAPSHOTS_VAR
+		)
+	});
+	assert!(
+		expected == actual,
+		"the response doesn't match the snapshot {:?}, run the tests with {}=1 if it changed on purpose\n--- expected\n{}\n--- actual\n{}",
+		path,
+		UPDATE_SNAPSHOTS_VAR,
+		expected,
+		actual
+	);
+}
@@ -63,7 +63,8 @@ export const FileList: React.FC<{ location_id: number; path: string; limit: numb
 	const { data: currentDir } = useLibraryQuery('GetExplorerDir', {
 		location_id: props.location_id,
 		path,
-		limit: props.limit
+		limit: props.limit,
+		show_hidden: null
 	});
 
 	useEffect(() => {
@@ -10,7 +10,8 @@ export const DebugScreen: React.FC<{}> = (props) => {
 	const { data: nodeState } = useBridgeQuery('GetNode');
 	const { data: libraryState } = useBridgeQuery('GetLibraries');
 	const { data: jobs } = useLibraryQuery('GetRunningJobs');
-	const { data: jobHistory } = useLibraryQuery('GetJobHistory');
+	// the core invalidates the first page of the history with these params when a job stops
+	const { data: jobHistory } = useLibraryQuery('GetJobHistory', { cursor: null, limit: 100 });
 	// const { mutate: purgeDB } = useBridgeCommand('PurgeDatabase', {
 	//   onMutate: () => {
 	//     alert('Database purged');
@@ -24,7 +24,7 @@ export const ExplorerScreen: React.FC<{}> = () => {
 	// Current Directory
 	const { data: currentDir } = useLibraryQuery(
 		'GetExplorerDir',
-		{ location_id: location_id!, path, limit },
+		{ location_id: location_id!, path, limit, show_hidden: null },
 		{ enabled: !!location_id }
 	);
 
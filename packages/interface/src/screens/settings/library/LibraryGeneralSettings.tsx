@@ -31,7 +31,10 @@ export default function LibraryGeneralSettings() {
 				editLibrary({
 					id: currentLibraryUuid!,
 					name: nameDebounced,
-					description: descriptionDebounced
+					description: descriptionDebounced,
+					object_chunk_hashing: null,
+					secrets_scanning: null,
+					trash_retention_days: null
 				});
 			}
 		}